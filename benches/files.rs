@@ -0,0 +1,41 @@
+use assert_fs::TempDir;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use dotbak_core::files::Files;
+use std::{fs, path::PathBuf};
+
+/// The numbers of files to benchmark with, up to a whole `.config` folder's worth.
+const FILE_COUNTS: &[usize] = &[10, 100, 500];
+
+/// Benchmarks moving files into the repository and symlinking them back, and restoring them again.
+fn bench_move_and_restore(c: &mut Criterion) {
+    let mut group = c.benchmark_group("move_and_restore");
+
+    for &count in FILE_COUNTS {
+        let dir = TempDir::new().unwrap();
+        let (home, repo) = (dir.path().join("home"), dir.path().join("repo"));
+        let files = (0..count)
+            .map(|i| PathBuf::from(format!(".config/app-{}/config", i)))
+            .collect::<Vec<_>>();
+
+        for file in &files {
+            fs::create_dir_all(home.join(file).parent().unwrap()).unwrap();
+            fs::write(home.join(file), "contents").unwrap();
+        }
+
+        fs::create_dir_all(&repo).unwrap();
+
+        let dotfiles = Files::init(home, repo);
+
+        group.bench_with_input(BenchmarkId::from_parameter(count), &files, |b, files| {
+            b.iter(|| {
+                dotfiles.move_and_symlink(files).unwrap();
+                dotfiles.remove_and_restore(files).unwrap();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_move_and_restore);
+criterion_main!(benches);
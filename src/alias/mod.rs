@@ -0,0 +1,82 @@
+mod tests;
+
+use crate::errors::{config::ConfigError, Result};
+use itertools::Itertools;
+use std::collections::BTreeMap;
+
+/// Expands the alias in `args` (the command-line arguments, including the program name), if there is one. The alias
+/// is the first argument that isn't a flag or the value of one of the global flags in `value_flags` (e.g. `--home`),
+/// and is replaced with the arguments it maps to in `aliases`. If that starts with an alias as well, it's expanded
+/// again, like `git` does.
+///
+/// Aliases never shadow the built-in commands in `builtins`. Returns an error if an alias (indirectly) expands to
+/// itself.
+pub fn expand<S1, S2>(
+    args: Vec<String>,
+    aliases: &BTreeMap<String, Vec<String>>,
+    builtins: &[S1],
+    value_flags: &[S2],
+) -> Result<Vec<String>>
+where
+    S1: AsRef<str>,
+    S2: AsRef<str>,
+{
+    let mut args = args;
+    let mut chain: Vec<String> = vec![];
+
+    while let Some(position) = command_position(&args, value_flags) {
+        let name = &args[position];
+
+        let expansion = match aliases.get(name) {
+            Some(expansion) if !builtins.iter().any(|builtin| builtin.as_ref() == name) => {
+                expansion
+            }
+            _ => break,
+        };
+
+        if chain.contains(name) {
+            chain.push(name.clone());
+
+            return Err(ConfigError::RecursiveAlias {
+                chain: chain.join(" -> "),
+            }
+            .into());
+        }
+
+        chain.push(name.clone());
+        args.splice(position..=position, expansion.iter().cloned());
+    }
+
+    Ok(args)
+}
+
+/// Helper function to find the position of the command in `args`: the first argument that isn't a flag, skipping the
+/// values of the flags in `value_flags`. Values given as `--flag=value` are part of the flag's argument already.
+fn command_position<S>(args: &[String], value_flags: &[S]) -> Option<usize>
+where
+    S: AsRef<str>,
+{
+    let mut position = 1;
+
+    while let Some(arg) = args.get(position) {
+        if !arg.starts_with('-') {
+            return Some(position);
+        }
+
+        if value_flags.iter().any(|flag| flag.as_ref() == arg) {
+            position += 1;
+        }
+
+        position += 1;
+    }
+
+    None
+}
+
+/// Describes `aliases` for the help output, one per line.
+pub fn describe(aliases: &BTreeMap<String, Vec<String>>) -> String {
+    aliases
+        .iter()
+        .map(|(name, expansion)| format!("  {} = {}", name, expansion.join(" ")))
+        .join("\n")
+}
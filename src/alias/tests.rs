@@ -0,0 +1,169 @@
+#![cfg(test)]
+
+use super::*;
+use crate::errors::DotbakError;
+
+/// The built-in commands used in the tests.
+const BUILTINS: &[&str] = &["sync", "pull", "push", "add"];
+
+/// The global flags that take values used in the tests.
+const VALUE_FLAGS: &[&str] = &["--home", "--set"];
+
+/// Helper function to build an alias table.
+fn aliases(table: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+    table
+        .iter()
+        .map(|(name, expansion)| {
+            (
+                name.to_string(),
+                expansion.iter().map(|arg| arg.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+/// Helper function to turn a string into arguments.
+fn args(args: &str) -> Vec<String> {
+    args.split_whitespace().map(|arg| arg.to_string()).collect()
+}
+
+/// Test that aliases are expanded, keeping the arguments around them.
+#[test]
+fn test_expand() {
+    let aliases = aliases(&[("save", &["add", "--verbose"]), ("up", &["pull"])]);
+
+    assert_eq!(
+        expand(
+            args("dotbak save .zshrc .bashrc"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak add --verbose .zshrc .bashrc")
+    );
+    assert_eq!(
+        expand(
+            args("dotbak -v --low-bandwidth up"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak -v --low-bandwidth pull")
+    );
+}
+
+/// Test that the values of global flags are skipped when looking for the alias, whether they're given as separate
+/// arguments or with `=`, and that they're never expanded themselves.
+#[test]
+fn test_expand_flag_values() {
+    let aliases = aliases(&[("up", &["pull"]), ("ls2", &["list", "--long"])]);
+
+    assert_eq!(
+        expand(
+            args("dotbak --home /tmp/x ls2"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak --home /tmp/x list --long")
+    );
+    assert_eq!(
+        expand(
+            args("dotbak --home=/tmp/x -v ls2"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak --home=/tmp/x -v list --long")
+    );
+    assert_eq!(
+        expand(
+            args("dotbak --home up sync"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak --home up sync")
+    );
+    assert_eq!(
+        expand(
+            args("dotbak --set a=b --home up"),
+            &aliases,
+            BUILTINS,
+            VALUE_FLAGS
+        )
+        .unwrap(),
+        args("dotbak --set a=b --home up")
+    );
+}
+
+/// Test that arguments without aliases are left alone.
+#[test]
+fn test_expand_no_alias() {
+    let aliases = aliases(&[("up", &["pull"])]);
+
+    for unchanged in ["dotbak", "dotbak --help", "dotbak sync", "dotbak add up"] {
+        assert_eq!(
+            expand(args(unchanged), &aliases, BUILTINS, VALUE_FLAGS).unwrap(),
+            args(unchanged)
+        );
+    }
+}
+
+/// Test that aliases can't shadow built-in commands.
+#[test]
+fn test_expand_builtin() {
+    let aliases = aliases(&[("sync", &["push"])]);
+
+    assert_eq!(
+        expand(args("dotbak sync"), &aliases, BUILTINS, VALUE_FLAGS).unwrap(),
+        args("dotbak sync")
+    );
+}
+
+/// Test that aliases can expand to other aliases.
+#[test]
+fn test_expand_nested() {
+    let aliases = aliases(&[("s", &["save"]), ("save", &["add", "-v"])]);
+
+    assert_eq!(
+        expand(args("dotbak s .zshrc"), &aliases, BUILTINS, VALUE_FLAGS).unwrap(),
+        args("dotbak add -v .zshrc")
+    );
+}
+
+/// Test that recursive aliases are rejected, instead of expanding forever.
+#[test]
+fn test_expand_recursive() {
+    let aliases = aliases(&[
+        ("a", &["b"]),
+        ("b", &["c", "x"]),
+        ("c", &["a"]),
+        ("d", &["d"]),
+    ]);
+
+    match expand(args("dotbak a"), &aliases, BUILTINS, VALUE_FLAGS) {
+        Err(DotbakError::Config(ConfigError::RecursiveAlias { chain })) => {
+            assert_eq!(chain, "a -> b -> c -> a")
+        }
+        _ => panic!("expected a recursive alias error"),
+    }
+
+    assert!(matches!(
+        expand(args("dotbak d"), &aliases, BUILTINS, VALUE_FLAGS),
+        Err(DotbakError::Config(ConfigError::RecursiveAlias { .. }))
+    ));
+}
+
+/// Test the description of aliases in the help output.
+#[test]
+fn test_describe() {
+    let aliases = aliases(&[("up", &["pull"]), ("save", &["add", "-v"])]);
+
+    assert_eq!(describe(&aliases), "  save = add -v\n  up = pull");
+}
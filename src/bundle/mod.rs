@@ -0,0 +1,233 @@
+mod tests;
+
+use crate::{
+    clock,
+    errors::{bundle::BundleError, io::IoError, Result},
+    git::Repository,
+};
+use age::secrecy::SecretString;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Write},
+    iter,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The name of the git bundle of the repository inside an offline bundle.
+const REPO_ENTRY: &str = "repo.bundle";
+
+/// The name of the configuration file inside an offline bundle.
+const CONFIG_ENTRY: &str = "config.toml";
+
+/// The name of the metadata file inside an offline bundle.
+const METADATA_ENTRY: &str = "metadata.toml";
+
+/// Information about how and where an offline bundle was created.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The version of `dotbak` that created the bundle.
+    pub dotbak_version: String,
+
+    /// When the bundle was created, in seconds since the UNIX epoch.
+    pub created: u64,
+
+    /// The hostname of the machine that created the bundle, if it could be found.
+    pub hostname: Option<String>,
+}
+
+/// The decrypted contents of an offline bundle.
+pub struct Bundle {
+    /// The git bundle containing the whole repository.
+    pub repo: Vec<u8>,
+
+    /// The contents of the configuration file.
+    pub config: Vec<u8>,
+
+    /// Information about the bundle.
+    pub metadata: Metadata,
+}
+
+/// Creates an offline bundle at `path`, encrypted with `passphrase`. The bundle contains all branches of `repo`
+/// (as a git bundle), the configuration file at `config_path`, and some metadata, packed together as a tarball.
+pub fn create<P1, P2>(
+    repo: &mut Repository,
+    config_path: P1,
+    path: P2,
+    passphrase: SecretString,
+) -> Result<Metadata>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let write_err = |err| IoError::Write {
+        source: err,
+        path: path.to_path_buf(),
+    };
+
+    let repo_bundle = repo
+        .arbitrary_command(&["bundle", "create", "-", "--all"])?
+        .stdout;
+
+    let config = fs::read(config_path.as_ref()).map_err(|err| IoError::Read {
+        source: err,
+        path: config_path.as_ref().to_path_buf(),
+    })?;
+
+    let metadata = Metadata {
+        dotbak_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: clock::now(),
+        hostname: hostname(),
+    };
+    let metadata_str = toml::to_string(&metadata)?;
+
+    let file = fs::File::create(path).map_err(|err| IoError::Create {
+        source: err,
+        path: path.to_path_buf(),
+    })?;
+
+    let encrypted = age::Encryptor::with_user_passphrase(passphrase)
+        .wrap_output(file)
+        .map_err(write_err)?;
+
+    let mut archive = tar::Builder::new(encrypted);
+
+    for (name, data) in [
+        (METADATA_ENTRY, metadata_str.as_bytes()),
+        (CONFIG_ENTRY, &config),
+        (REPO_ENTRY, &repo_bundle),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(metadata.created);
+        header.set_cksum();
+
+        archive
+            .append_data(&mut header, name, data)
+            .map_err(write_err)?;
+    }
+
+    archive
+        .into_inner()
+        .and_then(|encrypted| encrypted.finish())
+        .and_then(|mut file| file.flush())
+        .map_err(write_err)?;
+
+    Ok(metadata)
+}
+
+/// Opens and decrypts the offline bundle at `path` with `passphrase`.
+pub fn open<P>(path: P, passphrase: SecretString) -> Result<Bundle>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let read_err = |err| IoError::Read {
+        source: err,
+        path: path.to_path_buf(),
+    };
+    let decrypt_err = |err| BundleError::Decrypt {
+        source: err,
+        path: path.to_path_buf(),
+    };
+
+    let file = fs::File::open(path).map_err(read_err)?;
+    let identity = age::scrypt::Identity::new(passphrase);
+    let decrypted = age::Decryptor::new(file)
+        .and_then(|decryptor| decryptor.decrypt(iter::once(&identity as &dyn age::Identity)))
+        .map_err(decrypt_err)?;
+
+    // Read all the entries into memory. Bundles of dotfiles are small, and this makes them easy to look up.
+    let mut entries = HashMap::new();
+    let mut archive = tar::Archive::new(decrypted);
+
+    for entry in archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+        let name = entry
+            .path()
+            .map_err(read_err)?
+            .to_string_lossy()
+            .to_string();
+        let mut data = vec![];
+
+        entry.read_to_end(&mut data).map_err(read_err)?;
+        entries.insert(name, data);
+    }
+
+    let mut take = |name: &str| {
+        entries.remove(name).ok_or_else(|| BundleError::Missing {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+        })
+    };
+
+    let (repo, config, metadata) = (
+        take(REPO_ENTRY)?,
+        take(CONFIG_ENTRY)?,
+        take(METADATA_ENTRY)?,
+    );
+
+    Ok(Bundle {
+        repo,
+        config,
+        metadata: toml::from_str(&String::from_utf8_lossy(&metadata))?,
+    })
+}
+
+impl Bundle {
+    /// Clones the repository in the bundle to `repo_dir`. Afterwards, the `origin` remote is set to
+    /// `remote_url`, or removed if there is none (instead of pointing to the bundle).
+    pub fn clone_repo<P>(&self, repo_dir: P, remote_url: Option<&str>) -> Result<Repository>
+    where
+        P: AsRef<Path>,
+    {
+        let repo_dir = repo_dir.as_ref();
+
+        // Git can only clone bundles from files, so temporarily write it next to where the repository goes.
+        let bundle_path = PathBuf::from(format!("{}.bundle", repo_dir.display()));
+
+        if let Some(parent) = bundle_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| IoError::Create {
+                source: err,
+                path: parent.to_path_buf(),
+            })?;
+        }
+
+        fs::write(&bundle_path, &self.repo).map_err(|err| IoError::Write {
+            source: err,
+            path: bundle_path.clone(),
+        })?;
+
+        let cloned = Repository::clone(repo_dir, bundle_path.to_string_lossy(), false);
+
+        fs::remove_file(&bundle_path).map_err(|err| IoError::Delete {
+            source: err,
+            path: bundle_path.clone(),
+        })?;
+
+        let mut repo = cloned?;
+
+        match remote_url {
+            Some(url) => {
+                repo.set_remote(url)?;
+            }
+            None => {
+                repo.arbitrary_command(&["remote", "remove", crate::git::REMOTE_NAME])?;
+            }
+        }
+
+        Ok(repo)
+    }
+}
+
+/// Gets the hostname of this machine, if possible.
+pub fn hostname() -> Option<String> {
+    let output = Command::new("hostname").output().ok()?;
+    let hostname = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    (output.status.success() && !hostname.is_empty()).then_some(hostname)
+}
@@ -0,0 +1,76 @@
+#![cfg(test)]
+
+use super::*;
+use crate::errors::DotbakError;
+use assert_fs::TempDir;
+
+/// Creates a repository with a single committed file and a configuration file in `dir`, and bundles them up with
+/// the passphrase "hunter2". Returns the path to the bundle.
+fn create_test_bundle(dir: &Path) -> PathBuf {
+    let repo_dir = dir.join("repo");
+    let config_file = dir.join("config.toml");
+    let bundle_file = dir.join("dotfiles.bundle");
+    let mut repo = Repository::init(&repo_dir, None).unwrap();
+
+    fs::write(repo_dir.join(".zshrc"), "export EDITOR=vim").unwrap();
+    repo.commit("Add zshrc").unwrap();
+    fs::write(&config_file, "[files]\ninclude = [\".zshrc\"]\n").unwrap();
+
+    create(
+        &mut repo,
+        &config_file,
+        &bundle_file,
+        SecretString::from("hunter2".to_string()),
+    )
+    .unwrap();
+
+    bundle_file
+}
+
+/// Test if a bundle can be created and opened again with the same passphrase.
+#[test]
+fn test_create_open_bundle() {
+    let dir = TempDir::new().unwrap();
+    let bundle_file = create_test_bundle(dir.path());
+    let bundle = open(&bundle_file, SecretString::from("hunter2".to_string())).unwrap();
+
+    assert_eq!(
+        bundle.config,
+        fs::read(dir.path().join("config.toml")).unwrap()
+    );
+    assert_eq!(bundle.metadata.dotbak_version, env!("CARGO_PKG_VERSION"));
+    assert!(!bundle.repo.is_empty());
+}
+
+/// Test if opening a bundle with the wrong passphrase fails.
+#[test]
+fn test_open_bundle_wrong_passphrase() {
+    let dir = TempDir::new().unwrap();
+    let bundle_file = create_test_bundle(dir.path());
+    let result = open(&bundle_file, SecretString::from("hunter3".to_string()));
+
+    assert!(matches!(
+        result,
+        Err(DotbakError::Bundle(BundleError::Decrypt { .. }))
+    ));
+}
+
+/// Test if the repository in a bundle can be cloned without a remote.
+#[test]
+fn test_bundle_clone_repo() {
+    let dir = TempDir::new().unwrap();
+    let bundle_file = create_test_bundle(dir.path());
+    let bundle = open(&bundle_file, SecretString::from("hunter2".to_string())).unwrap();
+    let restored_dir = dir.path().join("restored");
+
+    bundle.clone_repo(&restored_dir, None).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(restored_dir.join(".zshrc")).unwrap(),
+        "export EDITOR=vim"
+    );
+    assert!(!PathBuf::from(format!("{}.bundle", restored_dir.display())).exists());
+    assert!(!fs::read_to_string(restored_dir.join(".git/config"))
+        .unwrap()
+        .contains("[remote"));
+}
@@ -1,11 +1,25 @@
-use crate::{
-    dotbak::{daemon::Daemon, Dotbak},
-    errors::Result,
+use age::secrecy::SecretString;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use dotbak_core::{
+    alias,
+    config::overrides::{self, Layer, Overrides},
+    dotbak::{
+        builder::{DotbakBuilder, HOME_VAR},
+        daemon::Daemon,
+        list, Dotbak,
+    },
+    errors::bundle::BundleError,
+    errors::{io::IoError, Result},
+    files::{LinkState, PruneMode},
+    format_utc,
+    shell::{self, Shell},
+    ui::{self, Verbosity},
+    InstallStyle, LockMode, Scheduler, Severity,
 };
-use clap::Parser;
-use indicatif::HumanDuration;
+use indicatif::{HumanBytes, HumanDuration};
 use std::path::PathBuf;
-use std::time::Instant;
+use std::process::{ExitCode, ExitStatus};
+use std::time::{Instant, SystemTime};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -14,13 +28,102 @@ pub struct Cli {
     #[clap(subcommand)]
     pub action: Action,
 
-    /// Whether to be verbose with logging or not.
-    /// Ex: printing the output of git commands.
-    #[clap(short, long)]
-    pub verbose: bool,
+    /// How verbose to be with logging. `-v` shows what's being done, and `-vv` the output of git commands too.
+    /// Everything is always logged to `$XDG_STATE_HOME/dotbak/logs/dotbak.log` regardless.
+    #[clap(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Whether to only print errors and what commands print themselves, e.g. `dotbak status`.
+    #[clap(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Whether to save bandwidth, for metered connections. Clones are shallow, git-lfs content is skipped,
+    /// pushes are compressed, and pushes with large files are deferred while the connection is metered.
+    #[clap(long, global = true)]
+    pub low_bandwidth: bool,
+
+    /// Whether to run without asking anything, e.g. from provisioning scripts and cron jobs. Spinners are printed
+    /// as plain lines, and questions are answered with their default. This is on by default when stdout isn't a
+    /// terminal, or `CI=true` is set.
+    #[clap(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Whether to overwrite real files in your home directory that differ from the tracked version when syncing.
+    /// Otherwise, you're asked whether to back them up (the default), adopt them, or leave them alone for now. This
+    /// also adds files larger than `files.add_size_limit` without asking.
+    #[clap(long, global = true)]
+    pub force: bool,
+
+    /// Whether to delete files and folders permanently, instead of moving them to the system trash. Overrides
+    /// `files.permanent_delete` in the configuration, for this command.
+    #[clap(long, global = true)]
+    pub permanent: bool,
+
+    /// The home directory to manage the dotfiles of, instead of yours. The configuration file and the repository
+    /// are in `<home>/.config/dotbak` and `<home>/.local/share/dotbak` by default.
+    #[clap(long, global = true, env = HOME_VAR, value_name = "DIR")]
+    pub home: Option<PathBuf>,
+
+    /// Overrides `repository_url` in the configuration, for this command.
+    #[clap(long = "repository-url", global = true, value_name = "URL")]
+    pub repository_url: Option<String>,
+
+    /// Overrides `files.include` in the configuration, for this command. Can be given more than once.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub include: Vec<PathBuf>,
+
+    /// Overrides any setting in the configuration, for this command, e.g. `--set files.sparse=true`. The value is
+    /// read as TOML, or as a string if it isn't TOML. Can be given more than once.
+    #[clap(long, global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    pub set: Vec<(String, String)>,
 }
 
 impl Cli {
+    /// Parses the command-line arguments like `Cli::parse`, but expands the user-defined aliases from the
+    /// configuration first, and lists them in the help output.
+    pub fn parse_with_aliases() -> Result<Self> {
+        let args = std::env::args_os()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+
+        // The aliases are needed to parse the arguments, so `--home` has to be found without parsing them.
+        let builder = match home_arg(&args) {
+            Some(home) => DotbakBuilder::new().home_dir(home),
+            None => DotbakBuilder::new(),
+        };
+        let aliases = builder.aliases();
+        let mut command = Cli::command();
+
+        let builtins = command
+            .get_subcommands()
+            .flat_map(|sub| std::iter::once(sub.get_name()).chain(sub.get_all_aliases()))
+            .chain(["help"])
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        // The values of the global flags that take them (like `--home <DIR>`) aren't the command.
+        let value_flags = command
+            .get_arguments()
+            .filter(|arg| !arg.is_positional() && arg.get_action().takes_values())
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|long| format!("--{}", long));
+                let short = arg.get_short().map(|short| format!("-{}", short));
+
+                long.into_iter().chain(short)
+            })
+            .collect::<Vec<_>>();
+
+        let args = alias::expand(args, &aliases, &builtins, &value_flags)?;
+
+        if !aliases.is_empty() {
+            command = command.after_help(format!("Aliases:\n{}", alias::describe(&aliases)));
+        }
+
+        let matches = command.get_matches_from(args);
+
+        Ok(Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit()))
+    }
+
     /// Gets the action that's currently being performed, as a human-readable string.
     pub fn action(&self) -> String {
         match &self.action {
@@ -32,45 +135,699 @@ impl Cli {
                     String::new()
                 }
             ),
-            Action::Clone { repo_url } => format!("Cloning with url {}", repo_url).to_string(),
-            Action::Add { paths } => format!("Adding {} file(s)", paths.len()),
-            Action::Sync => "Synchronizing".to_string(),
-            Action::Remove { paths } => format!("Removing {} file(s)", paths.len()),
+            Action::Clone { repo_url, .. } => format!("Cloning with url {}", repo_url).to_string(),
+            Action::Bootstrap { repo_url, .. } => format!("Bootstrapping with url {}", repo_url),
+            Action::Add { paths, .. } => format!("Adding {} file(s)", paths.len()),
+            Action::Adopt { paths } => format!("Adopting {} symlink(s)", paths.len()),
+            Action::Sync { .. } => "Synchronizing".to_string(),
+            Action::Status { .. } => "Checking the status".to_string(),
+            Action::Prompt => "Summarizing the status".to_string(),
+            Action::Diff { .. } => "Showing pending changes".to_string(),
+            Action::Log { path: None, .. } => "Getting the history".to_string(),
+            Action::Log {
+                path: Some(path), ..
+            } => format!("Getting the history of '{}'", path.display()),
+            Action::History { .. } => "Getting the recent runs".to_string(),
+            Action::List => "Listing managed files".to_string(),
+            Action::Lint => "Checking for hard-coded paths".to_string(),
+            Action::Remove {
+                all_matching: Some(pattern),
+                ..
+            } => format!("Removing files matching '{}'", pattern),
+            Action::Remove { paths, .. } => format!("Removing {} file(s)", paths.len()),
+            Action::Undo {
+                commit: Some(commit),
+                ..
+            } => format!("Undoing commit '{}'", commit),
+            Action::Undo { count, .. } => format!("Undoing {} commit(s)", count),
+            Action::Pin {
+                commit: Some(commit),
+            } => format!("Pinning to '{}'", commit),
+            Action::Pin { commit: None } => "Pinning to the current commit".to_string(),
+            Action::Unpin => "Unpinning".to_string(),
+            Action::RelocateRepo { path } => {
+                format!("Moving the repository to '{}'", path.display())
+            }
             Action::Push => "Pushing".to_string(),
             Action::Pull => "Pulling".to_string(),
             Action::Git { args } => format!("Running 'git {}'", args.join(" ")),
             Action::Deinit => "Deinitializing".to_string(),
+            Action::Eject { .. } => "Ejecting".to_string(),
             Action::StartDaemon => "Starting daemon".to_string(),
             Action::StopDaemon => "Stopping daemon".to_string(),
+            Action::Daemon {
+                action: DaemonAction::Install,
+            } => "Installing the sync schedule".to_string(),
+            Action::Daemon {
+                action: DaemonAction::Uninstall,
+            } => "Uninstalling the sync schedule".to_string(),
+            Action::Absorb { source } => format!("Absorbing '{}'", source),
+            Action::Import {
+                source: ImportSource::Stow { dir, .. },
+            } => format!("Importing stow directory '{}'", dir.display()),
+            Action::Import {
+                source: ImportSource::Archive { file },
+            } => format!("Importing archive '{}'", file.display()),
+            Action::Export {
+                format: ExportFormat::Html { dir },
+            } => format!("Exporting HTML site to '{}'", dir.display()),
+            Action::Export {
+                format: ExportFormat::Archive { file, .. },
+            } => format!("Exporting archive to '{}'", file.display()),
+            Action::Bundle {
+                action: BundleAction::Create { file },
+            } => format!("Creating bundle '{}'", file.display()),
+            Action::Bundle {
+                action: BundleAction::Restore { file },
+            } => format!("Restoring bundle '{}'", file.display()),
+            Action::Repair { .. } => "Repairing symlinks".to_string(),
+            Action::Coverage { .. } => "Checking coverage".to_string(),
+            Action::Plugins {
+                action: PluginsAction::Status,
+            } => "Checking plugin lock files".to_string(),
+            Action::Packages {
+                action: PackagesAction::Apply,
+            } => "Installing packages".to_string(),
+            Action::ShellInit { shell, .. } => format!("Generating {:?} integration", shell),
+            Action::Promote { .. } => "Promoting staged changes".to_string(),
+            Action::Unshallow => "Fetching the full history".to_string(),
+            Action::Gc { .. } => "Cleaning up the repository".to_string(),
+            Action::Env => "Collecting the environment".to_string(),
+            Action::Doctor { .. } => "Checking the installation".to_string(),
+            Action::Config {
+                action: ConfigAction::Check,
+            } => "Checking the configuration".to_string(),
+            Action::Config {
+                action: ConfigAction::Get { key },
+            } => format!("Getting `{}`", key),
+            Action::Config {
+                action: ConfigAction::Set { key, .. },
+            } => format!("Setting `{}`", key),
+            Action::Config {
+                action: ConfigAction::Edit,
+            } => "Editing the configuration".to_string(),
+            Action::Prune {
+                restore: false,
+                delete: false,
+            } => "Listing orphaned files".to_string(),
+            Action::Prune { .. } => "Pruning orphaned files".to_string(),
+            Action::Quarantine {
+                action: QuarantineAction::Clear { paths },
+            } => format!("Clearing {} file(s) from the quarantine", paths.len()),
+            Action::Key {
+                action: KeyAction::Generate,
+            } => "Generating an encryption key".to_string(),
+            Action::Key {
+                action: KeyAction::Import { file },
+            } => format!("Importing the encryption key '{}'", file.display()),
+            Action::Hosts {
+                action: HostsAction::List,
+            } => "Listing machines".to_string(),
+            Action::Hosts {
+                action: HostsAction::Retire { host },
+            } => format!("Retiring the machine '{}'", host),
+            Action::Branch { name: None } => "Getting the branch".to_string(),
+            Action::Branch { name: Some(name) } => format!("Switching to branch '{}'", name),
+            Action::Remote {
+                action: RemoteAction::List,
+            } => "Listing remotes".to_string(),
+            Action::Remote {
+                action: RemoteAction::Add { name, .. },
+            } => format!("Adding the remote '{}'", name),
+            Action::Remote {
+                action: RemoteAction::Remove { name },
+            } => format!("Removing the remote '{}'", name),
+            Action::Module {
+                action: ModuleAction::Add { path, .. },
+            } => format!("Adding the module '{}'", path.display()),
+            Action::Ignore {
+                action: IgnoreAction::List,
+            } => "Listing ignored files".to_string(),
+            Action::Ignore {
+                action: IgnoreAction::Add { patterns },
+            } => format!("Ignoring {} pattern(s)", patterns.len()),
+            Action::Ignore {
+                action: IgnoreAction::Remove { patterns },
+            } => format!("Stopping to ignore {} pattern(s)", patterns.len()),
         }
     }
 
     /// Runs the command-line interface for `dotbak` based on the user's input.
-    pub fn run(&self) -> Result<()> {
+    pub fn run(&self) -> Result<ExitCode> {
+        // Some actions print output meant for other programs (or people), so they don't load `dotbak` or print
+        // progress.
+        if let Action::ShellInit {
+            shell,
+            binary,
+            sync_on_exit,
+        } = &self.action
+        {
+            let binary = match binary.clone().or(self.builder().shell_binary()?) {
+                Some(binary) => binary,
+                None => std::env::current_exe().map_err(|err| IoError::Read {
+                    source: err,
+                    path: PathBuf::from("dotbak"),
+                })?,
+            };
+
+            print!("{}", shell::init_script(*shell, binary, *sync_on_exit));
+
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if let Action::Env = &self.action {
+            print!("{}", self.builder().diagnostics()?);
+
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if let Action::Prompt = &self.action {
+            println!("{}", self.builder().prompt()?);
+
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if let Action::Doctor { offline } = &self.action {
+            let findings = self.builder().doctor(!offline)?;
+
+            for finding in &findings {
+                let icon = match finding.severity {
+                    Severity::Ok => "✅",
+                    Severity::Warning => "❗️",
+                    Severity::Error => "❌",
+                };
+
+                println!("{} {}: {}", icon, finding.check.name(), finding.message);
+
+                if let Some(fix) = &finding.fix {
+                    println!("   💡 {}", fix);
+                }
+            }
+
+            let problems = findings
+                .iter()
+                .filter(|finding| finding.severity != Severity::Ok)
+                .count();
+
+            // Scripts and CI jobs tell from the exit code whether something is wrong.
+            return Ok(match problems {
+                0 => {
+                    println!("🩺 No problems found");
+                    ExitCode::SUCCESS
+                }
+                _ => {
+                    println!("🩺 Found {} problem(s)", problems);
+                    ExitCode::FAILURE
+                }
+            });
+        }
+
+        // Wait for other `dotbak` processes changing the state (e.g. a daemon sync) to finish, unless this only
+        // reads it.
+        let _lock = self.builder().lock(self.action.lock_mode(), || {
+            println!("⏳ Waiting for another dotbak command (e.g. a daemon sync) to finish...")
+        })?;
+
+        // The configuration commands work on the configuration file, so they work even if `dotbak` can't be loaded.
+        if let Action::Config { action } = &self.action {
+            return self.config(action).map(|()| ExitCode::SUCCESS);
+        }
+
         // Get the dotbak instance.
         let mut dotbak = self.get_dotbak()?;
+        let _tracing = dotbak.logger().enter();
         let started = Instant::now();
 
-        println!("⏳ {}...", self.action());
+        if self.verbosity() > Verbosity::Quiet {
+            println!("⏳ {}...", self.action());
+        }
+
+        // Run the action, logging why it failed if it did.
+        dotbak.logger().info(self.action());
+
+        let code = match &self.action {
+            // Deinitialize `dotbak`.
+            Action::Deinit => {
+                dotbak.deinit()?;
+                ExitCode::SUCCESS
+            }
+
+            // Turn the repository into a conventional dotfiles repository.
+            Action::Eject { stow } => {
+                dotbak.eject(if *stow {
+                    InstallStyle::Stow
+                } else {
+                    InstallStyle::Ln
+                })?;
+                ExitCode::SUCCESS
+            }
+
+            _ => match self.run_action(&mut dotbak) {
+                Ok(code) => code,
+                Err(err) => {
+                    dotbak.logger().error(format!("Failed: {}", err));
+                    return Err(err);
+                }
+            },
+        };
+
+        // Commands passed through to git print their own output, and exit like it did.
+        if code != ExitCode::SUCCESS {
+            return Ok(code);
+        }
+
+        if self.verbosity() > Verbosity::Quiet {
+            println!(
+                "✨ Done! {}",
+                console::style(format!("[{}]", HumanDuration(started.elapsed())))
+                    .bold()
+                    .dim(),
+            );
+        }
+
+        Ok(code)
+    }
 
-        // Run the action.
+    /// Runs the action on `dotbak`.
+    fn run_action(&self, dotbak: &mut Dotbak) -> Result<ExitCode> {
         match &self.action {
             // Do nothing if we've already initialized.
             Action::Init { .. } | Action::Clone { .. } => (),
 
+            // Finish setting up the new machine, after cloning.
+            Action::Bootstrap { no_daemon, .. } => {
+                if let Some(branch) = dotbak.bootstrap()? {
+                    println!("🌿 Switched to this machine's branch '{}'", branch);
+                }
+
+                if !no_daemon {
+                    install_schedule(dotbak)?;
+                }
+            }
+
             // Add the files.
-            Action::Add { paths } => {
-                dotbak.add(paths)?;
+            Action::Add {
+                paths,
+                rewrite_home,
+                allow_secrets,
+            } => {
+                if *allow_secrets {
+                    dotbak.allow_secrets();
+                }
+
+                dotbak.add(paths, *rewrite_home)?;
+            }
+
+            // Take over symlinks installed by another dotfiles manager.
+            Action::Adopt { paths } => {
+                let skipped = dotbak.adopt(paths)?;
+
+                for path in skipped {
+                    println!(
+                        "⚠️  Skipped '{}', as it's already managed or isn't a symlink to a file outside of the repository",
+                        path.display()
+                    );
+                }
+            }
+
+            // Show the hard-coded paths to the home directory.
+            Action::Lint => {
+                let found = dotbak.lint()?;
+
+                if found.is_empty() {
+                    println!("✅ No hard-coded paths to your home directory");
+                }
+
+                for found in found {
+                    println!(
+                        "⚠️ {}:{}: {}",
+                        found.path.display(),
+                        found.line,
+                        found.text.trim()
+                    );
+                }
             }
 
             // Synchonize the files.
-            Action::Sync => {
+            Action::Sync {
+                allow_secrets,
+                offline,
+            } => {
+                if *allow_secrets {
+                    dotbak.allow_secrets();
+                }
+
+                if *offline {
+                    dotbak.offline();
+                }
+
                 dotbak.sync()?;
             }
 
+            // Show the state of the managed files and the repository.
+            Action::Status { porcelain: true } => print!("{}", dotbak.status()?.porcelain()),
+
+            Action::Status { porcelain: false } => {
+                let status = dotbak.status()?;
+
+                for (file, state) in &status.files {
+                    let icon = match state {
+                        LinkState::Intact => "🔗",
+                        _ => "❗️",
+                    };
+
+                    println!("{} {}: {}", icon, file.display(), state.describe());
+                }
+
+                for (file, drift) in &status.drifted {
+                    println!("🌀 {}: {}", file.display(), drift.describe());
+                }
+
+                match (status.uncommitted, status.unpushed) {
+                    (0, 0) => println!("📦 Everything is committed and pushed"),
+                    (uncommitted, unpushed) => println!(
+                        "📦 {} uncommitted change(s), {} unpushed commit(s)",
+                        uncommitted, unpushed
+                    ),
+                }
+
+                if status.behind > 0 {
+                    println!(
+                        "⬇️ {} commit(s) on the remote haven't been pulled yet, run `dotbak pull` to get them",
+                        status.behind
+                    );
+                }
+
+                if status.push_deferred {
+                    println!("⏸️ Pushing is deferred until you're on an unmetered connection");
+                }
+
+                if let Some(queued) = &status.queued_push {
+                    println!(
+                        "📤 Pushing is queued since {} ({}), the next sync that reaches the remote pushes {} commit(s)",
+                        format_utc(queued.since),
+                        queued.reason,
+                        status.unpushed
+                    );
+                }
+
+                if let Some(pin) = &status.pinned {
+                    println!(
+                        "📌 Pinned to {} since {}, run `dotbak unpin` to catch up with the remote again",
+                        &pin.commit[..7.min(pin.commit.len())],
+                        format_utc(pin.pinned)
+                    );
+                }
+
+                for entry in &status.quarantined {
+                    println!(
+                        "🚧 {}: quarantined after {} failed syncs, run `dotbak quarantine clear {}` once fixed: {}",
+                        entry.path.display(),
+                        entry.failures,
+                        entry.path.display(),
+                        entry.reason
+                    );
+                }
+
+                if status.sync_in_progress {
+                    println!("🔒 Another dotbak command (e.g. a daemon sync) is running, this is the state before it");
+                }
+
+                if !status.daemon_pending_restart.is_empty() {
+                    println!(
+                        "🔁 Restart the daemon to apply the changes to: {}",
+                        status.daemon_pending_restart.join(", ")
+                    );
+                }
+            }
+
+            // Show the pending changes.
+            Action::Diff { paths, staged } => {
+                let diff = dotbak.diff(paths, *staged)?;
+
+                match diff.is_empty() {
+                    true => println!("👍 Nothing changed"),
+                    false => print!("{}", diff),
+                }
+            }
+
+            // Show the history of the managed files.
+            Action::Log { path, limit } => {
+                let log = dotbak.log(path.as_ref(), *limit)?;
+
+                if log.is_empty() {
+                    println!("📭 Nothing was committed yet.");
+                }
+
+                for entry in log {
+                    println!(
+                        "🕓 {} {} {}",
+                        console::style(&entry.commit.hash[..7]).yellow(),
+                        console::style(&entry.commit.date).dim(),
+                        entry.commit.summary
+                    );
+
+                    if !entry.files.is_empty() {
+                        println!(
+                            "   {}",
+                            console::style(
+                                entry
+                                    .files
+                                    .iter()
+                                    .map(|file| file.display().to_string())
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                            .dim()
+                        );
+                    }
+                }
+            }
+
+            // Show the recent syncs, pushes and pulls as a table, newest first.
+            Action::History { limit } => {
+                let log = dotbak.run_log()?;
+
+                if log.runs().is_empty() {
+                    println!("📭 Nothing was synced yet.");
+                } else {
+                    println!(
+                        "{}",
+                        console::style(format!(
+                            "   {:<20}  {:<4}  {:>8}  {:>5}  RESULT",
+                            "STARTED", "RUN", "TOOK", "FILES"
+                        ))
+                        .dim()
+                    );
+                }
+
+                for run in log.runs().iter().rev().take(*limit) {
+                    let (icon, result) = match &run.error {
+                        None => ("✅", console::style("ok".to_string()).green()),
+                        Some(err) => ("❌", console::style(err.replace('\n', " ")).red()),
+                    };
+
+                    println!(
+                        "{} {:<20}  {:<4}  {:>8}  {:>5}  {}",
+                        icon,
+                        format_utc(run.started),
+                        run.kind.as_str(),
+                        format!("{:.1}s", run.duration as f64 / 1000.0),
+                        run.files,
+                        result
+                    );
+                }
+            }
+
+            // Show the managed files as a tree.
+            Action::List => {
+                let entries = dotbak.list()?;
+
+                if entries.is_empty() {
+                    println!("📭 No files are managed yet. Add some with `dotbak add`.");
+                } else {
+                    print!(
+                        "{}",
+                        list::render_tree(&entries, |entry| {
+                            let icon = match entry.state {
+                                LinkState::Intact => "🔗",
+                                _ => "❗️",
+                            };
+                            let last_commit = entry
+                                .last_commit
+                                .as_ref()
+                                .map(|commit| format!("last committed {}", commit.date))
+                                .unwrap_or_else(|| "never committed".to_string());
+                            let state = match entry.state {
+                                LinkState::Intact => String::new(),
+                                state => format!(", {}", state.describe()),
+                            };
+
+                            format!(
+                                "{} {}",
+                                icon,
+                                console::style(format!(
+                                    "({}, {}{})",
+                                    HumanBytes(entry.size),
+                                    last_commit,
+                                    state
+                                ))
+                                .dim()
+                            )
+                        })
+                    );
+                }
+            }
+
+            // Manage the encryption key.
+            Action::Key { action } => {
+                let recipient = match action {
+                    KeyAction::Generate => dotbak.generate_key()?,
+                    KeyAction::Import { file } => dotbak.import_key(file)?,
+                };
+
+                println!("🔑 Your public key is {}", recipient);
+            }
+
+            // List or retire the machines syncing to the repository.
+            Action::Hosts {
+                action: HostsAction::List,
+            } => {
+                let hosts = dotbak.hosts()?;
+
+                if hosts.is_empty() {
+                    println!("📭 No machine synced with a recorded hostname yet.");
+                }
+
+                for host in hosts {
+                    match host.retired {
+                        true => println!(
+                            "   {} {}",
+                            console::style(&host.name).dim(),
+                            console::style(format!("(retired, last synced {})", host.date)).dim()
+                        ),
+                        false => println!("🖥️ {} (last synced {})", host.name, host.date),
+                    }
+                }
+            }
+
+            Action::Hosts {
+                action: HostsAction::Retire { host },
+            } => {
+                dotbak.retire_host(host)?;
+            }
+
+            Action::Branch { name: None } => {
+                println!("🌿 On branch '{}'", dotbak.repository().branch());
+            }
+
+            Action::Branch { name: Some(name) } => {
+                dotbak.switch_branch(name)?;
+            }
+
+            Action::Remote {
+                action: RemoteAction::List,
+            } => {
+                let remotes = dotbak.remotes()?;
+
+                if remotes.is_empty() {
+                    println!("📭 No remotes yet, add one with `dotbak remote add`.");
+                }
+
+                for remote in remotes {
+                    match dotbak.config().git.pushes_to(&remote.name) {
+                        true => println!("🌐 {} {}", remote.name, remote.url),
+                        false => println!(
+                            "   {} {}",
+                            console::style(&remote.name).dim(),
+                            console::style(format!("{} (not pushed to)", remote.url)).dim()
+                        ),
+                    }
+                }
+            }
+
+            Action::Remote {
+                action: RemoteAction::Add { name, url },
+            } => {
+                dotbak.add_remote(name, url)?;
+            }
+
+            Action::Remote {
+                action: RemoteAction::Remove { name },
+            } => {
+                dotbak.remove_remote(name)?;
+            }
+
+            Action::Module {
+                action: ModuleAction::Add { url, path },
+            } => {
+                dotbak.add_module(url, path)?;
+            }
+
+            Action::Ignore {
+                action: IgnoreAction::List,
+            } => {
+                let patterns = &dotbak.config().ignore.patterns;
+
+                if patterns.is_empty() {
+                    println!("📭 Nothing is ignored yet, add patterns with `dotbak ignore add`.");
+                }
+
+                for pattern in patterns {
+                    println!("🙈 {}", pattern);
+                }
+            }
+
+            Action::Ignore {
+                action: IgnoreAction::Add { patterns },
+            } => {
+                dotbak.ignore(patterns)?;
+            }
+
+            Action::Ignore {
+                action: IgnoreAction::Remove { patterns },
+            } => {
+                dotbak.unignore(patterns)?;
+            }
+
+            // Let quarantined files be synced again.
+            Action::Quarantine {
+                action: QuarantineAction::Clear { paths },
+            } => {
+                dotbak.clear_quarantine(paths)?;
+            }
+
             // Remove the files.
-            Action::Remove { paths } => {
-                dotbak.remove(paths)?;
+            Action::Remove {
+                paths,
+                all_matching,
+                yes,
+            } => {
+                dotbak.remove_matching(paths, all_matching.as_deref(), *yes)?;
+            }
+
+            // Undo the newest commits, or a specific one.
+            Action::Undo { count, commit, yes } => {
+                dotbak.undo(*count, commit.as_deref(), *yes)?;
+            }
+
+            // Keep the repository at a commit on this machine, or stop doing so.
+            Action::Pin { commit } => {
+                let hash = dotbak.pin(commit.as_deref())?;
+
+                println!(
+                    "📌 Pinned to {}. Syncs won't pull or push until you run `dotbak unpin`.",
+                    &hash[..7.min(hash.len())]
+                );
+            }
+            Action::Unpin => match dotbak.unpin()? {
+                Some(_) => println!("📌 Unpinned. The next sync catches up with the remote again."),
+                None => println!("👍 This machine isn't pinned"),
+            },
+
+            // Move the repository.
+            Action::RelocateRepo { path } => {
+                dotbak.relocate_repo(path)?;
             }
 
             // Push changes to remote.
@@ -83,36 +840,241 @@ impl Cli {
                 dotbak.pull()?;
             }
 
-            // Run an arbitrary git command.
+            // Run an arbitrary git command, exiting like it did.
             Action::Git { args } => {
-                dotbak
+                let status = dotbak
                     .arbitrary_git_command(&args.iter().map(|s| s.as_str()).collect::<Vec<_>>())?;
+
+                return Ok(exit_code(status));
             }
 
-            // Deinitialize `dotbak`.
-            Action::Deinit => {
-                dotbak.deinit()?;
+            // Handled by `run`, as these consume `dotbak`.
+            Action::Deinit | Action::Eject { .. } => (),
+
+            // Restore the symlinks of replaced files.
+            Action::Repair { adopt_changes } => {
+                dotbak.repair(*adopt_changes)?;
+            }
+
+            // Create an offline bundle. Restoring is done when getting the dotbak instance.
+            Action::Bundle {
+                action: BundleAction::Create { file },
+            } => {
+                dotbak.create_bundle(file, self.bundle_passphrase(true)?)?;
+            }
+            Action::Bundle {
+                action: BundleAction::Restore { .. },
+            } => (),
+
+            // Export the repository.
+            Action::Export {
+                format: ExportFormat::Html { dir },
+            } => {
+                dotbak.export_html(dir)?;
+            }
+            Action::Export {
+                format: ExportFormat::Archive { file, paths },
+            } => {
+                let exported = dotbak.export_archive(file, paths)?;
+
+                println!("🗜️  Exported {} file(s) to '{}'", exported, file.display());
+            }
+
+            // Promote the staged syncs to the main branch.
+            Action::Promote { yes } => {
+                dotbak.promote(*yes)?;
+            }
+
+            // Fetch what a shallow or filtered clone left out.
+            Action::Unshallow => {
+                dotbak.unshallow()?;
+            }
+
+            // Clean up the repository, and maybe its history.
+            Action::Gc {
+                larger_than,
+                removed,
+                yes,
+            } => {
+                dotbak.gc(*larger_than, *removed, *yes)?;
+            }
+
+            // Absorb another repository's history.
+            Action::Absorb { source } => {
+                dotbak.absorb(source)?;
+            }
+
+            // Take over the files of another dotfiles manager.
+            Action::Import {
+                source: ImportSource::Stow { dir, packages },
+            } => {
+                let skipped = dotbak.import_stow(dir, packages)?;
+
+                for path in skipped {
+                    println!(
+                        "⚠️  Skipped '{}', as it's already managed or wasn't installed by stow",
+                        path.display()
+                    );
+                }
+            }
+            Action::Import {
+                source: ImportSource::Archive { file },
+            } => {
+                let skipped = dotbak.import_archive(file)?;
+
+                for path in skipped {
+                    println!(
+                        "⚠️  Skipped '{}', as a different version is already in your home directory",
+                        path.display()
+                    );
+                }
+            }
+
+            // List the orphaned files in the repository, or reconcile them.
+            Action::Prune {
+                restore: false,
+                delete: false,
+            } => {
+                let orphans = dotbak.orphans()?;
+
+                if orphans.is_empty() {
+                    println!("👍 Every file in the repository is managed");
+                }
+
+                for orphan in orphans {
+                    println!("👻 {}", orphan.display());
+                }
+            }
+
+            Action::Prune { restore, .. } => {
+                let mode = match restore {
+                    true => PruneMode::Restore,
+                    false => PruneMode::Delete,
+                };
+
+                let (pruned, skipped) = dotbak.prune(mode)?;
+
+                for path in pruned {
+                    match mode {
+                        PruneMode::Restore => println!("📤 Restored '{}'", path.display()),
+                        PruneMode::Delete => println!("🗑️ Deleted '{}'", path.display()),
+                    }
+                }
+
+                for path in skipped {
+                    println!(
+                        "⚠️  Skipped '{}', as something else is in its way in your home directory",
+                        path.display()
+                    );
+                }
+            }
+
+            // Show how much of the home directory's configuration is managed.
+            Action::Coverage { top } => {
+                let coverage = dotbak.coverage();
+
+                println!(
+                    "📊 {} of {} known configuration locations are managed ({:.0}%)",
+                    coverage.managed.len(),
+                    coverage.managed.len() + coverage.unmanaged.len(),
+                    coverage.fraction() * 100.0
+                );
+
+                for candidate in coverage.unmanaged.iter().take(*top) {
+                    let modified = candidate
+                        .modified
+                        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                        .map(|age| format!("modified {} ago", HumanDuration(age)))
+                        .unwrap_or_else(|| "modified at an unknown time".to_string());
+
+                    println!(
+                        "   {} {}",
+                        candidate.path.display(),
+                        console::style(format!("({})", modified)).dim()
+                    );
+                }
+            }
+
+            // Show how the plugin lock files compare across machines.
+            Action::Packages {
+                action: PackagesAction::Apply,
+            } => {
+                let applied = dotbak.apply_packages()?;
+
+                if applied.is_empty() {
+                    println!("📦 No package lists were exported for this operating system. Set `packages` to export them.");
+                }
+
+                for manager in applied {
+                    println!("📦 Installed the packages of {}", manager.name());
+                }
             }
 
+            Action::Plugins {
+                action: PluginsAction::Status,
+            } => {
+                let statuses = dotbak.plugin_status()?;
+
+                if statuses.is_empty() {
+                    println!("🔌 No plugin lock files are managed. Set `files.track_locks` to manage them.");
+                }
+
+                for status in statuses {
+                    let last_change = status
+                        .last_change
+                        .as_ref()
+                        .map(|commit| format!("last changed {} by {}", commit.date, commit.author))
+                        .unwrap_or_else(|| "never committed".to_string());
+
+                    println!(
+                        "🔌 {} {}: {} {}",
+                        status.lock.manager,
+                        console::style(status.lock.path).dim(),
+                        status.describe(),
+                        console::style(format!("({})", last_change)).dim()
+                    );
+                }
+            }
+
+            // Handled above.
+            Action::ShellInit { .. }
+            | Action::Env
+            | Action::Prompt
+            | Action::Doctor { .. }
+            | Action::Config { .. } => unreachable!(),
+
             // Run the daemon, don't use `dotbak` result.
             Action::StartDaemon => {
-                Daemon::new()?.run();
+                Daemon::new(self.builder())?.run();
             }
 
             // Stop the daemon, don't use `dotbak` result.
             Action::StopDaemon => {
                 Daemon::stop()?;
             }
-        }
 
-        println!(
-            "✨ Done! {}",
-            console::style(format!("[{}]", HumanDuration(started.elapsed())))
-                .bold()
-                .dim(),
-        );
+            Action::Daemon {
+                action: DaemonAction::Install,
+            } => {
+                install_schedule(dotbak)?;
+            }
 
-        Ok(())
+            Action::Daemon {
+                action: DaemonAction::Uninstall,
+            } => {
+                let removed = Scheduler::current_or_err()?.uninstall(dotbak.files().home_dir())?;
+
+                if removed.is_empty() {
+                    println!("📭 The sync schedule isn't installed.");
+                }
+
+                for path in removed {
+                    println!("🗑️ Removed '{}'", path.display());
+                }
+            }
+        }
+
+        Ok(ExitCode::SUCCESS)
     }
 }
 
@@ -122,48 +1084,398 @@ impl Cli {
         // Initialize the `Dotbak` instance depending on what the user wants.
         match &self.action {
             // If we are initializing, then just initialize.
-            Action::Init { repo_url: None } => Dotbak::init(self.verbose),
+            Action::Init { repo_url: None } => self.builder().init(),
 
             // If we're provided a repository URL, then clone it.
-            Action::Clone { repo_url }
+            Action::Clone { repo_url, .. }
+            | Action::Bootstrap { repo_url, .. }
             | Action::Init {
                 repo_url: Some(repo_url),
-            } => Dotbak::clone(repo_url, self.verbose),
+            } => self.builder().clone(repo_url),
+
+            // Restore from an offline bundle.
+            Action::Bundle {
+                action: BundleAction::Restore { file },
+            } => self
+                .builder()
+                .restore_bundle(file, self.bundle_passphrase(false)?),
+
+            // Repairing and checking the status need to see the files before they're synchronized, clearing the
+            // quarantine shouldn't fail because of the files that are still quarantined, the encryption key has
+            // to be set up before encrypted files can be synchronized, and the machines are only looked up in the
+            // history.
+            Action::Repair { .. }
+            | Action::Status { .. }
+            | Action::Quarantine { .. }
+            | Action::Key { .. }
+            | Action::Hosts { .. } => self.builder().load_without_sync(),
+
+            // Actions that only read the state don't hold the lock, so they mustn't change the files either. The
+            // daemon syncs right away when it starts.
+            action if action.lock_mode() == LockMode::Shared => self.builder().load_without_sync(),
 
             // Otherwise, we just load the instance.
-            _ => Dotbak::load(self.verbose),
+            _ => self.builder().load(),
         }
     }
+
+    /// Runs a configuration command, without loading `dotbak`.
+    fn config(&self, action: &ConfigAction) -> Result<()> {
+        match action {
+            ConfigAction::Check => {
+                let (path, problems) = self.builder().check_config()?;
+
+                for problem in &problems {
+                    let location = match problem.location {
+                        Some((line, column)) => format!("{}:{}:{}", path.display(), line, column),
+                        None => path.display().to_string(),
+                    };
+
+                    match problem.setting.is_empty() {
+                        true => println!("❌ {}: {}", location, problem.message),
+                        false => println!(
+                            "❌ {}: `{}`: {}",
+                            location, problem.setting, problem.message
+                        ),
+                    }
+                }
+
+                match problems.len() {
+                    0 => println!("✅ No problems found in '{}'", path.display()),
+                    count => println!("🩺 Found {} problem(s)", count),
+                }
+            }
+
+            // The value is printed on its own, for scripts.
+            ConfigAction::Get { key } => match self.builder().get_setting(key)? {
+                toml::Value::String(value) => println!("{}", value),
+                toml::Value::Table(table) => print!("{}", toml::to_string_pretty(&table)?),
+                value => println!("{}", value),
+            },
+
+            ConfigAction::Set { key, value } => {
+                self.builder().set_setting(key, value)?;
+                println!("✨ Set `{}`", key);
+            }
+
+            ConfigAction::Edit => match self.builder().edit_config()? {
+                true => println!("✨ Saved the configuration"),
+                false => println!("✨ The configuration wasn't changed"),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Gets the passphrase for an offline bundle, from `DOTBAK_BUNDLE_PASSPHRASE` or by asking the user (unless
+    /// running non-interactively). If `confirm` is set, the user has to enter the passphrase twice.
+    fn bundle_passphrase(&self, confirm: bool) -> Result<SecretString> {
+        if let Ok(passphrase) = std::env::var("DOTBAK_BUNDLE_PASSPHRASE") {
+            return Ok(SecretString::from(passphrase));
+        }
+
+        if self.non_interactive || !ui::is_interactive() {
+            return Err(IoError::NonInteractive {
+                prompt: "Bundle passphrase".to_string(),
+            }
+            .into());
+        }
+
+        let passphrase = ui::read_secret("Bundle passphrase:")?;
+
+        if confirm && ui::read_secret("Confirm passphrase:")? != passphrase {
+            return Err(BundleError::PassphraseMismatch.into());
+        }
+
+        Ok(SecretString::from(passphrase))
+    }
+
+    /// Gets the verbosity from the `--verbose` and `--quiet` flags.
+    fn verbosity(&self) -> Verbosity {
+        // Output meant for scripts isn't mixed with progress.
+        match self.action {
+            Action::Status { porcelain: true } => Verbosity::Quiet,
+            _ => Verbosity::from_flags(self.verbose, self.quiet),
+        }
+    }
+
+    /// Get the builder for the dotbak structure, with the directories and settings from the arguments.
+    fn builder(&self) -> DotbakBuilder {
+        let builder = DotbakBuilder::new()
+            .verbosity(self.verbosity())
+            .low_bandwidth(self.low_bandwidth)
+            .non_interactive(self.non_interactive)
+            .force(self.force)
+            .overrides(self.overrides());
+
+        match &self.home {
+            Some(home) => builder.home_dir(home),
+            None => builder,
+        }
+    }
+
+    /// Gets the settings overriding the configuration file, from the `DOTBAK_*` environment variables and the flags.
+    /// The flags take precedence.
+    fn overrides(&self) -> Overrides {
+        let mut overrides = Overrides::from_env();
+
+        if let Some(url) = &self.repository_url {
+            overrides.set(Layer::Flag, "repository_url", url.as_str().into());
+        }
+
+        if !self.include.is_empty() {
+            let include = self
+                .include
+                .iter()
+                .map(|path| path.to_string_lossy().to_string().into())
+                .collect::<Vec<toml::Value>>();
+
+            overrides.set(Layer::Flag, "files.include", include.into());
+        }
+
+        if self.permanent {
+            overrides.set(Layer::Flag, "files.permanent_delete", true.into());
+        }
+
+        if let Action::Clone { depth, filter, .. } = &self.action {
+            if let Some(depth) = depth {
+                overrides.set(Layer::Flag, "git.clone.depth", i64::from(*depth).into());
+            }
+
+            if let Some(filter) = filter {
+                overrides.set(Layer::Flag, "git.clone.filter", filter.as_str().into());
+            }
+        }
+
+        for (key, value) in &self.set {
+            overrides.set(Layer::Flag, key, overrides::parse_value(value));
+        }
+
+        overrides
+    }
+}
+
+/// Parses a `KEY=VALUE` argument of `--set`.
+fn parse_setting(arg: &str) -> std::result::Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.trim().to_string(), value.to_string())),
+        _ => Err(format!("'{}' isn't written as KEY=VALUE", arg)),
+    }
+}
+
+/// Installs the sync schedule for `dotbak` (see `Scheduler`), printing the files that were installed.
+fn install_schedule(dotbak: &Dotbak) -> Result<()> {
+    let binary = std::env::current_exe().map_err(|err| IoError::Read {
+        source: err,
+        path: PathBuf::from("dotbak"),
+    })?;
+    let installed = Scheduler::current_or_err()?.install(
+        binary,
+        dotbak.files().home_dir(),
+        dotbak.config().delay_between_sync,
+    )?;
+
+    for path in installed {
+        println!("📅 Installed '{}'", path.display());
+    }
+
+    Ok(())
+}
+
+/// Gets the exit code for `dotbak` to exit with after git exited with `status`. Exits by a signal are failures.
+fn exit_code(status: ExitStatus) -> ExitCode {
+    status
+        .code()
+        .and_then(|code| u8::try_from(code).ok())
+        .map_or(ExitCode::FAILURE, ExitCode::from)
+}
+
+/// Finds the value of `--home` in the arguments `args`, before they are parsed.
+fn home_arg(args: &[String]) -> Option<PathBuf> {
+    args.iter()
+        .enumerate()
+        .find_map(|(index, arg)| match arg.strip_prefix("--home") {
+            Some("") => args.get(index + 1).map(PathBuf::from),
+            Some(value) => value.strip_prefix('=').map(PathBuf::from),
+            None => None,
+        })
 }
 
 #[derive(Parser)]
 pub enum Action {
-    /// Initializes a new instance of `dotbak` in your home directory (at `~/.dotbak`).
+    /// Initializes a new instance of `dotbak` in your home directory (in the XDG base directories, e.g.
+    /// `~/.config/dotbak`).
     Init {
         /// The URL of the repository to clone. This is essentially the same as 'dotbak clone <REPO_URL>'.
         #[arg(short, long)]
         repo_url: Option<String>,
     },
 
-    /// Clones an instance of `dotbak` from the given URL to your home directory (at `~/.dotbak`).
+    /// Clones an instance of `dotbak` from the given URL to your home directory (in the XDG base directories, e.g.
+    /// `~/.local/share/dotbak`).
     Clone {
         /// The URL of the repository to clone.
         repo_url: String,
+
+        /// Only clone this many of the newest commits, to set up large repositories faster. `dotbak unshallow`
+        /// fetches the rest of the history later. Overrides `git.clone.depth`.
+        #[arg(long)]
+        depth: Option<u32>,
+
+        /// Clone with this git object filter, e.g. 'blob:none' to only download the contents of files when they're
+        /// checked out. Overrides `git.clone.filter`.
+        #[arg(long)]
+        filter: Option<String>,
+    },
+
+    /// Sets up a new machine in one go: clones an instance of `dotbak` from the given URL like `dotbak clone`,
+    /// switches to the machine's own branch (`hosts/<hostname>`) if there is one, installs the sync schedule like
+    /// `dotbak daemon install`, and runs the `bootstrap` hooks from the configuration.
+    Bootstrap {
+        /// The URL of the repository to clone.
+        repo_url: String,
+
+        /// Don't install the sync schedule.
+        #[arg(long)]
+        no_daemon: bool,
     },
 
     /// Adds files to the repository.
     Add {
         /// The paths to the files to add.
         paths: Vec<PathBuf>,
+
+        /// Rewrite hard-coded paths to your home directory in the files to `$HOME` (in shell configuration files),
+        /// `$env:USERPROFILE` (in PowerShell scripts), `%USERPROFILE%` (in batch files) or `~` (in all other files),
+        /// so that they work on machines with a different home directory.
+        #[arg(long)]
+        rewrite_home: bool,
+
+        /// Commit the files even if they look like they contain secrets (e.g. access keys or private keys).
+        #[arg(long)]
+        allow_secrets: bool,
+    },
+
+    /// Takes over symlinks in your home directory installed by another dotfiles manager (e.g. stow or chezmoi):
+    /// the files they point to are copied into the repository and added, and the symlinks are pointed at the
+    /// copies. The files they pointed to are left as they are.
+    Adopt {
+        /// The paths to the symlinks to adopt.
+        paths: Vec<PathBuf>,
+    },
+
+    /// Synchonizes the home directory with the repository. If the remote can't be reached, the changes are only
+    /// committed, and pushed by the next sync that reaches it.
+    Sync {
+        /// Commit the changed files even if they look like they contain secrets (e.g. access keys or private keys).
+        #[arg(long)]
+        allow_secrets: bool,
+
+        /// Don't pull or push: only commit the changes, and queue the push for the next sync that reaches the
+        /// remote.
+        #[arg(long)]
+        offline: bool,
+    },
+
+    /// Shows whether the symlink of each managed file is intact, broken, missing, or shadowed by a real file, and
+    /// whether the repository has uncommitted or unpushed changes.
+    Status {
+        /// Print the status in a stable format for scripts and shell prompts, one line per finding (see the README).
+        #[arg(long)]
+        porcelain: bool,
+    },
+
+    /// Prints a compact summary for shell prompts: the number of unpushed commits (e.g. `3↑`) and of broken
+    /// symlinks (e.g. `1✗`), or `✓`. It only reads what the last `dotbak` command recorded, so it doesn't run git.
+    Prompt,
+
+    /// Shows the pending changes to managed files as a unified diff: the uncommitted changes in the repository,
+    /// and the differences to the real files that replaced symlinks in your home directory.
+    Diff {
+        /// The paths to the files/folders to show the changes to. All managed files by default.
+        paths: Vec<PathBuf>,
+
+        /// Only show the changes that are staged for the next commit.
+        #[clap(long)]
+        staged: bool,
+    },
+
+    /// Shows the history of a managed file/folder (following renames), or of all managed files: the commits, when
+    /// they were made, and the files they touched.
+    Log {
+        /// The path to the file/folder to show the history of.
+        path: Option<PathBuf>,
+
+        /// Only show this many of the newest commits.
+        #[clap(short = 'n', long)]
+        limit: Option<usize>,
     },
 
-    /// Synchonizes the home directory with the repository.
-    Sync,
+    /// Shows the recent syncs, pushes and pulls on this machine (including the daemon's): when they ran, how long
+    /// they took, how many files they changed, and why they failed, if they did.
+    History {
+        /// Only show this many of the newest runs.
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Lists the managed files as a tree rooted at your home directory, with the state of their symlinks, their
+    /// sizes, and when they were last committed.
+    List,
+
+    /// Lists the lines in managed files with hard-coded paths to your home directory, which break on machines with a
+    /// different home directory (e.g. a different user name).
+    Lint,
 
     /// Removes files from the repository.
     Remove {
-        /// The paths to the files to remove.
+        /// The paths to the files to remove. These may also be glob patterns (e.g. '.config/app*', quoted so that
+        /// your shell doesn't expand them), which remove every managed path they match.
         paths: Vec<PathBuf>,
+
+        /// Also remove every managed path with a file or folder name matching this glob pattern, wherever it is
+        /// (e.g. 'slack*' matches both '.config/slack' and '.local/share/slack-desktop').
+        #[arg(long, value_name = "PATTERN")]
+        all_matching: Option<String>,
+
+        /// Remove the paths matching patterns without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Undoes the newest commits to the repository, after showing them. Commits that weren't pushed yet are removed
+    /// from the history, keeping their changes. Commits that were pushed already are reverted with new commits, so
+    /// that the remote's history isn't rewritten.
+    Undo {
+        /// How many of the newest commits to undo.
+        #[arg(default_value_t = 1)]
+        count: usize,
+
+        /// Undo this commit (e.g. its hash, from `dotbak log`) instead of the newest ones.
+        #[arg(long, value_name = "HASH", conflicts_with = "count")]
+        commit: Option<String>,
+
+        /// Undo without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Pins this machine to a commit, so that syncs and pulls don't move past it until it's unpinned, e.g. while a
+    /// change that broke something here gets fixed. Other machines keep syncing as usual.
+    Pin {
+        /// The commit to pin to (e.g. its hash, from `dotbak log`), moving the files back to it. Defaults to the
+        /// current commit.
+        #[arg(value_name = "HASH")]
+        commit: Option<String>,
+    },
+
+    /// Unpins this machine, so that the next sync catches up with the remote again.
+    Unpin,
+
+    /// Moves the repository to a new location, and updates all symlinks to point to it.
+    RelocateRepo {
+        /// The new location of the repository. Relative paths are relative to your home directory.
+        path: PathBuf,
     },
 
     /// Pushes the repository to the remote.
@@ -172,19 +1484,465 @@ pub enum Action {
     /// Pulls the repository from the remote.
     Pull,
 
-    /// Runs an arbitrary git command on the repository, as if you were in the repository directory.
-    /// TODO: this does not work with flags passed to git.
+    /// Runs an arbitrary git command on the repository, as if you were in the repository directory, e.g.
+    /// 'dotbak git log --oneline'. Everything after 'git' goes to git, including flags like '--help', and `dotbak`
+    /// exits like git did.
+    #[command(disable_help_flag = true)]
     Git {
         /// The arguments to pass to git.
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
     },
 
     /// Deinitializes an instance of `dotbak` in your home directory.
     Deinit,
 
+    /// Stops using `dotbak`, but keeps the repository as a conventional dotfiles repository: replaces the symlinks
+    /// in your home directory with real files, and commits an `install.sh` script that links the files in again.
+    Eject {
+        /// Make the install script use GNU stow instead of plain `ln` commands.
+        #[arg(long)]
+        stow: bool,
+    },
+
     /// Runs a daemon variant of `dotbak`.
     StartDaemon,
 
     /// Stops the daemon variant of `dotbak`.
     StopDaemon,
+
+    /// Runs `dotbak sync` on a schedule (every `delay_between_sync` seconds) with a systemd user timer on Linux, or a
+    /// launchd agent on macOS. Unlike the daemon, the schedule keeps running across reboots.
+    Daemon {
+        #[clap(subcommand)]
+        action: DaemonAction,
+    },
+
+    /// Repairs the symlinks in your home directory: restores the ones of managed files that were replaced by real
+    /// files (e.g. by an OS upgrade), backing up the replacements next to the original file as
+    /// `<file>.dotbak-backup`, and re-creates the ones that were deleted. For symlinks pointing to files missing from
+    /// the repository, or to files that aren't in `files.include` anymore, you choose whether to restore the files
+    /// (from the history, or by managing them again) or to delete the symlinks.
+    Repair {
+        /// Show the differences between the tracked version and the replacement of each file, and choose whether
+        /// to keep the tracked version, adopt the new one, or edit the tracked version to merge them.
+        #[arg(long)]
+        adopt_changes: bool,
+    },
+
+    /// Creates or restores an encrypted offline bundle of your dotfiles, for machines without network access.
+    Bundle {
+        #[clap(subcommand)]
+        action: BundleAction,
+    },
+
+    /// Shows the lock files of plugin managers that are managed (see `files.track_locks`).
+    Plugins {
+        #[clap(subcommand)]
+        action: PluginsAction,
+    },
+
+    /// Installs the packages exported to the repository (see `packages` in the configuration).
+    Packages {
+        #[clap(subcommand)]
+        action: PackagesAction,
+    },
+
+    /// Exports a read-only view of your dotfiles, e.g. to share your setup without sharing the repository.
+    Export {
+        #[clap(subcommand)]
+        format: ExportFormat,
+    },
+
+    /// Absorbs the history of another, independently initialized dotfiles repository, keeping both histories.
+    /// Include lists are combined, and you are asked which version to keep for files that conflict.
+    Absorb {
+        /// The URL of, or path to, the other repository.
+        source: String,
+    },
+
+    /// Imports the dotfiles of another dotfiles manager (replacing its symlinks with ones managed by dotbak), or an
+    /// exported archive.
+    Import {
+        #[clap(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Lists the files in the repository that aren't managed (e.g. after editing `files.include` by hand), or
+    /// reconciles them.
+    Prune {
+        /// Move the orphaned files back into your home directory.
+        #[clap(long, conflicts_with = "delete")]
+        restore: bool,
+
+        /// Delete the orphaned files.
+        #[clap(long)]
+        delete: bool,
+    },
+
+    /// Shows how many well-known configuration files/folders in your home directory are managed, and lists the
+    /// most recently modified ones that aren't.
+    Coverage {
+        /// How many unmanaged files/folders to list.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Prints shell code defining `dotbak` helpers, to be evaluated in your shell's configuration.
+    /// Ex: add `eval "$(dotbak shell-init bash)"` to your `~/.bashrc`.
+    ShellInit {
+        /// The shell to generate code for.
+        shell: Shell,
+
+        /// The path to the `dotbak` binary the helpers should call. Defaults to `shell.binary` in the configuration,
+        /// or the currently running binary.
+        #[arg(long, env = shell::BINARY_VAR)]
+        binary: Option<PathBuf>,
+
+        /// Whether to run `dotbak sync` in the background when the shell exits.
+        #[arg(long)]
+        sync_on_exit: bool,
+    },
+
+    /// Promotes the syncs that the daemon pushed to the `staging` branch (see `history.staging`) to the `main`
+    /// branch, after showing what changed.
+    Promote {
+        /// Promote without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Fetches the history and file contents left out when the repository was cloned with `--depth` or `--filter`
+    /// (see `git.clone`), like 'dotbak git fetch --unshallow' does for the history.
+    Unshallow,
+
+    /// Cleans up the repository with 'git gc', and shows how much space that reclaimed. With `--larger-than` or
+    /// `--removed`, the history of the current branch is rewritten first without the files that were ever that large,
+    /// or that were removed from the repository, e.g. large files committed by accident. Files that are still in the
+    /// repository are never dropped. The rewritten history replaces the remote's, so your other machines have to
+    /// clone the repository again.
+    Gc {
+        /// Drop the files that were ever larger than this many bytes from the history.
+        #[arg(long, value_name = "BYTES")]
+        larger_than: Option<u64>,
+
+        /// Drop the files that were removed from the repository from the history.
+        #[arg(long)]
+        removed: bool,
+
+        /// Rewrite the history without asking for confirmation.
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// Manages the files/folders that are skipped when syncing because they failed too often in a row (see
+    /// `files.quarantine_after`). `dotbak status` lists them.
+    Quarantine {
+        #[clap(subcommand)]
+        action: QuarantineAction,
+    },
+
+    /// Manages the key that the files in `encryption.files` are encrypted with. The key is never stored in the
+    /// repository, so copy it to your other machines yourself (e.g. with a password manager).
+    Key {
+        #[clap(subcommand)]
+        action: KeyAction,
+    },
+
+    /// Manages the machines (hosts) that sync to the repository. Every commit records the hostname of the machine
+    /// that made it.
+    Hosts {
+        #[clap(subcommand)]
+        action: HostsAction,
+    },
+
+    /// Switches to another branch (e.g. to keep a branch per machine), creating it if needed, and syncs its files.
+    /// Without a name, shows the branch that is synced.
+    Branch {
+        /// The name of the branch.
+        name: Option<String>,
+    },
+
+    /// Manages the remotes that are pushed to. Pushes go to `origin` and every remote added here (e.g. a backup
+    /// mirror), while pulls only come from `origin`.
+    Remote {
+        #[clap(subcommand)]
+        action: RemoteAction,
+    },
+
+    /// Manages the modules: other git repositories (e.g. a shared `nvim` configuration) that are kept in the
+    /// repository as submodules, and managed like any other path.
+    Module {
+        #[clap(subcommand)]
+        action: ModuleAction,
+    },
+
+    /// Manages the files in the repository that git ignores (`ignore.patterns`), like caches or swap files inside of
+    /// managed folders.
+    Ignore {
+        #[clap(subcommand)]
+        action: IgnoreAction,
+    },
+
+    /// Checks the installation for problems (git, the remote, the configuration, broken symlinks, files in the
+    /// repository that aren't managed, permissions and the daemon), and shows how to fix them. Exits with a failure
+    /// if it finds any problem.
+    Doctor {
+        /// Don't check if the remote can be reached.
+        #[clap(long)]
+        offline: bool,
+    },
+
+    /// Works with the configuration file.
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Prints the resolved runtime environment (configuration, directories, git, platform capabilities, daemon
+    /// and lock state) for pasting into bug reports. Secrets are redacted.
+    Env,
+}
+
+impl Action {
+    /// How the action accesses `dotbak`'s state. Actions that only read it never wait for other `dotbak` processes,
+    /// so that e.g. `dotbak status` doesn't block behind a slow daemon sync.
+    pub fn lock_mode(&self) -> LockMode {
+        match self {
+            Action::Status { .. }
+            | Action::Prompt
+            | Action::Diff { .. }
+            | Action::Log { .. }
+            | Action::History { .. }
+            | Action::List
+            | Action::Lint
+            | Action::Coverage { .. }
+            | Action::Env
+            | Action::Doctor { .. }
+            | Action::Config {
+                action: ConfigAction::Check | ConfigAction::Get { .. },
+            }
+            | Action::Prune {
+                restore: false,
+                delete: false,
+            }
+            | Action::ShellInit { .. }
+            | Action::Hosts {
+                action: HostsAction::List,
+            }
+            | Action::Branch { name: None }
+            | Action::Remote {
+                action: RemoteAction::List,
+            }
+            | Action::Ignore {
+                action: IgnoreAction::List,
+            }
+            // The daemon locks each of its syncs itself, instead of the whole time it runs.
+            | Action::StartDaemon
+            | Action::StopDaemon
+            // The schedule doesn't change the state, it only runs `dotbak sync`.
+            | Action::Daemon { .. } => LockMode::Shared,
+            _ => LockMode::Exclusive,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum BundleAction {
+    /// Creates an encrypted bundle containing the repository, its history, and your configuration. The passphrase
+    /// is read from `DOTBAK_BUNDLE_PASSPHRASE`, or asked for.
+    Create {
+        /// The path to write the bundle to.
+        file: PathBuf,
+    },
+
+    /// Sets up `dotbak` from a bundle created by `dotbak bundle create`, without any network access.
+    Restore {
+        /// The path to the bundle.
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportSource {
+    /// Imports a GNU stow directory: the files of its packages are copied into the repository and added, and
+    /// stow's symlinks are replaced. The stow directory itself is left as it is.
+    Stow {
+        /// The stow directory, containing one folder per package.
+        dir: PathBuf,
+
+        /// The packages to import. All of them are imported if none are given.
+        #[arg(short, long = "package")]
+        packages: Vec<String>,
+    },
+
+    /// Imports an archive exported by `dotbak export archive`: its files are unpacked into your home directory and
+    /// managed. Files that are already there are left alone.
+    Archive {
+        /// The archive, a `.tar`, `.tar.gz`, `.tgz` or `.zip` file.
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExportFormat {
+    /// Exports a static, browsable HTML site with the file tree, syntax highlighted file contents, and the history
+    /// of every file.
+    Html {
+        /// The directory to write the site to.
+        dir: PathBuf,
+    },
+
+    /// Exports the managed files with their contents (not as symlinks) into an archive, e.g. for sharing them or as
+    /// an offline backup. Encrypted files are exported decrypted.
+    Archive {
+        /// The archive to write, a `.tar`, `.tar.gz`, `.tgz` or `.zip` file.
+        file: PathBuf,
+
+        /// Only export this managed path (or path inside of a managed folder). Can be given more than once.
+        #[arg(long = "path", value_name = "PATH")]
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PluginsAction {
+    /// Shows whether each managed lock file changed on this machine, or differs from the remote because another
+    /// machine updated its plugins.
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum PackagesAction {
+    /// Installs the packages of this operating system's package managers from the package lists that syncs
+    /// exported to the repository, e.g. on a new machine. Packages that are installed already are left alone.
+    Apply,
+}
+
+#[derive(Subcommand)]
+pub enum QuarantineAction {
+    /// Lets quarantined files/folders be synced again, once you fixed what made them fail. They're synced right away.
+    Clear {
+        /// The paths to the files/folders to clear.
+        paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HostsAction {
+    /// Lists the machines that synced to the repository, and when they last synced.
+    List,
+
+    /// (Experimental) Retires a machine that isn't used anymore, archiving its `hosts/<host>` branch (if any) as
+    /// `archive/hosts/<host>`, locally and on the remote. Its history is kept.
+    Retire {
+        /// The hostname of the machine, as listed by `dotbak hosts list`.
+        host: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RemoteAction {
+    /// Lists the remotes, and whether they're pushed to.
+    List,
+
+    /// Adds a remote that every push goes to as well, or changes its URL if it already exists.
+    Add {
+        /// The name of the remote, e.g. `backup`.
+        name: String,
+
+        /// The URL of the remote.
+        url: String,
+    },
+
+    /// Removes a remote added with `dotbak remote add`.
+    Remove {
+        /// The name of the remote.
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ModuleAction {
+    /// Adds a git repository as a submodule at a path in the home directory, which must not exist yet, and symlinks
+    /// it there. It's cloned and updated along with the repository.
+    Add {
+        /// The URL of the repository.
+        url: String,
+
+        /// The path to put it at in the home directory, e.g. `.config/nvim`.
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Lists every problem with the configuration file, with the line and column it's on: syntax errors, unknown
+    /// settings, managed paths outside of your home directory or inside of other managed paths, duplicate entries,
+    /// and more.
+    Check,
+
+    /// Prints the value of a setting, e.g. `delay_between_sync` or `files.sparse`. Settings that aren't in the
+    /// configuration file have their default value.
+    Get {
+        /// The setting, with dots between the tables it's in.
+        key: String,
+    },
+
+    /// Changes a setting, keeping the comments and formatting of the configuration file. Nothing is changed if the
+    /// configuration would be invalid afterwards.
+    Set {
+        /// The setting, with dots between the tables it's in. Missing tables are created.
+        key: String,
+
+        /// The new value, as TOML (e.g. `600`, `true` or `[".zshrc"]`). Anything else is taken as a string, e.g. a
+        /// URL.
+        value: String,
+    },
+
+    /// Opens the configuration file in your editor (`$VISUAL` or `$EDITOR`). It's checked when you close the
+    /// editor, and if there are problems, you can edit it again or discard the changes.
+    Edit,
+}
+
+#[derive(Subcommand)]
+pub enum DaemonAction {
+    /// Installs the systemd user service and timer (on Linux) or the launchd agent (on macOS) running `dotbak sync`,
+    /// and starts it. If it's already installed, it's replaced, e.g. to pick up a new `delay_between_sync`.
+    Install,
+
+    /// Stops the schedule, and removes its systemd units or launchd agent.
+    Uninstall,
+}
+
+#[derive(Subcommand)]
+pub enum IgnoreAction {
+    /// Lists the patterns of the ignored files.
+    List,
+
+    /// Ignores the files matching the patterns, and removes the ones already in the repository from it (without
+    /// deleting them).
+    Add {
+        /// The patterns, in `.gitignore` syntax and relative to your home directory (e.g. `*.swp`).
+        patterns: Vec<String>,
+    },
+
+    /// Stops ignoring the files matching the patterns.
+    Remove {
+        /// The patterns, as they were added.
+        patterns: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum KeyAction {
+    /// Generates a new key, and encrypts the files in `encryption.files` with it.
+    Generate,
+
+    /// Imports an existing key (e.g. from your other machines, or created by `age-keygen`), and decrypts the files in
+    /// `encryption.files` with it.
+    Import {
+        /// The path to the key file.
+        file: PathBuf,
+    },
 }
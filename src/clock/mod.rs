@@ -0,0 +1,91 @@
+mod tests;
+
+#[cfg(test)]
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The environment variable that scripts the clock of `dotbak` (and of the commits it makes), in seconds since the
+/// UNIX epoch. This is only read with the `testing` feature, for end-to-end tests of the binary.
+#[cfg(feature = "testing")]
+pub const SCRIPTED_CLOCK_VAR: &str = "DOTBAK_TEST_NOW";
+
+#[cfg(test)]
+thread_local! {
+    /// The scripted time of the current (test) thread, if it's scripted. See `set`.
+    static SCRIPTED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Gets the current time, in seconds since the UNIX epoch. In tests, this is the time scripted with `set`, if it is
+/// scripted, so that tests don't depend on the real clock.
+pub fn now() -> u64 {
+    scripted().unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0)
+    })
+}
+
+/// Gets the scripted time, if the clock is scripted (see `now`). Commits made while the clock is scripted are
+/// dated with the scripted time.
+pub fn scripted() -> Option<u64> {
+    #[cfg(test)]
+    if let Some(now) = SCRIPTED.with(Cell::get) {
+        return Some(now);
+    }
+
+    #[cfg(feature = "testing")]
+    if let Some(now) = std::env::var(SCRIPTED_CLOCK_VAR)
+        .ok()
+        .and_then(|now| now.parse().ok())
+    {
+        return Some(now);
+    }
+
+    None
+}
+
+/// Formats `secs` (in seconds since the UNIX epoch) as a UTC timestamp in RFC 3339, e.g. `2024-03-01T12:30:00Z`.
+pub fn format_utc(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Converts the days since the epoch to a date in the proleptic Gregorian calendar, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Scripts the clock of the current thread to `now` (in seconds since the UNIX epoch), or goes back to the real
+/// clock if it's `None`.
+#[cfg(test)]
+pub fn set(now: Option<u64>) {
+    SCRIPTED.with(|scripted| scripted.set(now));
+}
+
+/// Advances the scripted clock of the current thread by `secs` seconds. Does nothing if it isn't scripted.
+#[cfg(test)]
+pub fn advance(secs: u64) {
+    SCRIPTED.with(|scripted| scripted.set(scripted.get().map(|now| now + secs)));
+}
@@ -0,0 +1,30 @@
+#![cfg(test)]
+
+use super::*;
+
+/// Test that the clock can be scripted, and goes back to the real time afterwards.
+#[test]
+fn test_scripted_clock() {
+    assert_eq!(scripted(), None);
+    assert!(now() > 1_700_000_000);
+
+    set(Some(1000));
+    assert_eq!(now(), 1000);
+
+    advance(60);
+    assert_eq!(now(), 1060);
+    assert_eq!(scripted(), Some(1060));
+
+    set(None);
+    assert_eq!(scripted(), None);
+    assert!(now() > 1_700_000_000);
+}
+
+/// Test that timestamps are formatted in RFC 3339, in UTC.
+#[test]
+fn test_format_utc() {
+    assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+    assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+    assert_eq!(format_utc(1_709_296_200), "2024-03-01T12:30:00Z");
+    assert_eq!(format_utc(1_735_689_599), "2024-12-31T23:59:59Z");
+}
@@ -0,0 +1,490 @@
+use super::{files::Root, git::Backend, migrations, Config};
+use crate::{
+    errors::{config::ConfigError, Result},
+    paths::{self, ManagedPath},
+};
+use globset::GlobBuilder;
+use itertools::Itertools;
+use regex::Regex;
+use std::path::{Component, Path, PathBuf};
+use toml_edit::{ImDocument, Item};
+
+/// A problem with the configuration file, found by `check` (or `Config::validate`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// The setting the problem is with, e.g. `files.include`. It's empty if the file can't be read as a whole.
+    pub setting: String,
+
+    /// The entry of the setting the problem is with, if the setting is a list.
+    pub index: Option<usize>,
+
+    /// What the problem is.
+    pub message: String,
+
+    /// Where the problem is in the configuration file, as the line and column (both starting at 1), if known.
+    pub location: Option<(usize, usize)>,
+}
+
+impl Problem {
+    /// Creates a problem with `setting` (or its entry `index`), which isn't located in the file yet.
+    fn new<S>(setting: &str, index: Option<usize>, message: S) -> Problem
+    where
+        S: ToString,
+    {
+        Problem {
+            setting: setting.to_string(),
+            index,
+            message: message.to_string(),
+            location: None,
+        }
+    }
+}
+
+/// Checks the contents `contents` of a configuration file for every problem that can be found: syntax errors,
+/// unknown settings and values of the wrong type (only the first of these, as nothing else can be checked then),
+/// and everything `Config::validate` finds for the home directory `home`. The problems are located in the file.
+pub fn check<P>(contents: &str, home: P) -> Vec<Problem>
+where
+    P: AsRef<Path>,
+{
+    let document = match ImDocument::parse(contents) {
+        Ok(document) => document,
+        Err(err) => {
+            return vec![Problem {
+                location: err.span().map(|span| line_column(contents, span.start)),
+                ..Problem::new("", None, err.message())
+            }]
+        }
+    };
+
+    let config = match toml::from_str::<Config>(contents) {
+        Ok(config) => config,
+        Err(err) => {
+            return vec![Problem {
+                location: err.span().map(|span| line_column(contents, span.start)),
+                ..Problem::new("", None, err.message())
+            }]
+        }
+    };
+
+    config
+        .validate(home)
+        .into_iter()
+        .map(|problem| Problem {
+            location: locate(&document, &problem.setting, problem.index)
+                .map(|offset| line_column(contents, offset)),
+            ..problem
+        })
+        .collect()
+}
+
+/// Public API for validating the configuration.
+impl Config {
+    /// Finds the problems with the settings that deserializing can't find, for the home directory `home`:
+    ///
+    /// - Managed, excluded or encrypted paths that are outside of the home directory (and of `files.roots`), or not
+    ///   written relative to it.
+    /// - Roots that aren't absolute, overlap the home directory or each other, or whose targets aren't relative.
+    /// - Paths and patterns that are listed more than once.
+    /// - Managed paths inside of other managed paths.
+    /// - Encrypted files that aren't managed.
+    /// - Excluded paths that aren't inside of a managed folder.
+    /// - Managed paths that `ignore.patterns` ignores as a whole, so they're never committed.
+    /// - Secret patterns that aren't valid regular expressions, and allowlist entries that aren't valid globs.
+    /// - A sparse checkout (`files.sparse`) with the libgit2 backend, which would commit the files outside of it as
+    ///   deleted.
+    /// - Files stored with git-lfs (`git.lfs.track`) with the libgit2 backend, which would commit them whole instead
+    ///   of as pointers.
+    /// - A `version` newer than this version of `dotbak` supports.
+    pub fn validate<P>(&self, home: P) -> Vec<Problem>
+    where
+        P: AsRef<Path>,
+    {
+        let home = home.as_ref();
+        let mut problems = vec![];
+
+        if self.version > migrations::CURRENT_VERSION {
+            problems.push(Problem::new(
+                "version",
+                None,
+                format!(
+                    "version {} of the format isn't supported, only up to version {}",
+                    self.version,
+                    migrations::CURRENT_VERSION
+                ),
+            ));
+        }
+
+        if self.files.sparse && self.git.backend == Backend::Libgit2 {
+            problems.push(Problem::new(
+                "files.sparse",
+                None,
+                "sparse checkouts aren't supported with `git.backend = \"libgit2\"`, which would commit the files \
+                 outside of them as deleted",
+            ));
+        }
+
+        if !self.git.lfs.track.is_empty() && self.git.backend == Backend::Libgit2 {
+            problems.push(Problem::new(
+                "git.lfs.track",
+                None,
+                "git-lfs isn't supported with `git.backend = \"libgit2\"`, which would commit the files whole \
+                 instead of as pointers",
+            ));
+        }
+
+        let roots = check_roots(&self.files.roots, home, &mut problems);
+        let include = check_paths(
+            &self.files.include,
+            "files.include",
+            home,
+            &roots,
+            &mut problems,
+        );
+
+        for (i, path) in self.files.include.iter().enumerate() {
+            if paths::is_pattern(path) {
+                if let Err(err) = paths::glob_set(&[path.to_string_lossy()]) {
+                    problems.push(Problem::new("files.include", Some(i), err));
+                }
+            }
+        }
+
+        for (i, path) in include.iter().enumerate() {
+            let Some(path) = path else {
+                continue;
+            };
+
+            if let Some(managed) = include
+                .iter()
+                .flatten()
+                .find(|managed| path.starts_with(managed) && path != *managed)
+            {
+                problems.push(Problem::new(
+                    "files.include",
+                    Some(i),
+                    format!(
+                        "'{}' is inside of '{}', which is managed already",
+                        path.display(),
+                        managed.display()
+                    ),
+                ));
+            }
+
+            if let Some(pattern) = self
+                .ignore
+                .patterns
+                .iter()
+                .find(|pattern| ignores(pattern, path))
+            {
+                problems.push(Problem::new(
+                    "files.include",
+                    Some(i),
+                    format!(
+                        "'{}' is ignored by '{}' in `ignore.patterns`, so it's never committed",
+                        path.display(),
+                        pattern
+                    ),
+                ));
+            }
+        }
+
+        let encrypted = check_paths(
+            &self.encryption.files,
+            "encryption.files",
+            home,
+            &roots,
+            &mut problems,
+        );
+
+        for (i, path) in encrypted.iter().enumerate() {
+            if let Some(path) = path
+                .as_ref()
+                .filter(|path| !include.contains(&Some(path.to_path_buf())))
+            {
+                problems.push(Problem::new(
+                    "encryption.files",
+                    Some(i),
+                    format!(
+                        "'{}' is encrypted, but isn't in `files.include`",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+
+        let exclude = check_paths(
+            &self.files.exclude,
+            "files.exclude",
+            home,
+            &roots,
+            &mut problems,
+        );
+
+        for (i, path) in exclude.iter().enumerate() {
+            if let Some(path) = path.as_ref().filter(|path| {
+                !include
+                    .iter()
+                    .flatten()
+                    .any(|managed| path.starts_with(managed) && *path != managed)
+            }) {
+                problems.push(Problem::new(
+                    "files.exclude",
+                    Some(i),
+                    format!(
+                        "'{}' isn't inside of a path in `files.include`, so excluding it does nothing",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+
+        check_duplicates(&self.ignore.patterns, "ignore.patterns", &mut problems);
+        check_duplicates(&self.secrets.allow, "secrets.allow", &mut problems);
+        check_duplicates(&self.git.lfs.track, "git.lfs.track", &mut problems);
+
+        for (name, pattern) in &self.secrets.patterns {
+            if let Err(err) = Regex::new(pattern) {
+                problems.push(Problem::new(
+                    &format!("secrets.patterns.{}", name),
+                    None,
+                    format!("'{}' is not a valid regular expression: {}", pattern, err),
+                ));
+            }
+        }
+
+        for (i, pattern) in self.secrets.allow.iter().enumerate() {
+            if let Err(err) = paths::glob_set(&[pattern]) {
+                problems.push(Problem::new("secrets.allow", Some(i), err));
+            }
+        }
+
+        problems
+    }
+
+    /// Like `validate`, but returns an error listing the problems if there are any.
+    pub fn check_valid<P>(&self, home: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let problems = self.validate(home);
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        Err(ConfigError::Invalid {
+            path: self.path.clone(),
+            count: problems.len(),
+            problems: describe(&problems),
+        }
+        .into())
+    }
+}
+
+/// Describes `problems`, one per line, for errors.
+pub fn describe(problems: &[Problem]) -> String {
+    problems
+        .iter()
+        .map(|problem| match problem.setting.is_empty() {
+            true => format!("  {}", problem.message),
+            false => format!("  {}: {}", problem.setting, problem.message),
+        })
+        .join("\n")
+}
+
+/// Helper function to check the paths `paths` of the setting `setting` for the home directory `home`, which must be
+/// written relative to it (or as normalized absolute paths inside of one of `roots`), and listed only once. Returns
+/// each path the way it's managed (see `paths::ManagedPath`), or `None` if it has a problem.
+fn check_paths(
+    paths: &[PathBuf],
+    setting: &str,
+    home: &Path,
+    roots: &[PathBuf],
+    problems: &mut Vec<Problem>,
+) -> Vec<Option<PathBuf>> {
+    let mut checked: Vec<Option<PathBuf>> = vec![];
+
+    for (i, path) in paths.iter().enumerate() {
+        let relative = match ManagedPath::new(path, home, roots).map(PathBuf::from) {
+            Err(_) => {
+                problems.push(Problem::new(
+                    setting,
+                    Some(i),
+                    format!("'{}' is outside of the home directory", path.display()),
+                ));
+                None
+            }
+            Ok(relative) if relative != *path && relative.is_absolute() => {
+                problems.push(Problem::new(
+                    setting,
+                    Some(i),
+                    format!(
+                        "'{}' should be written as '{}'",
+                        path.display(),
+                        relative.display()
+                    ),
+                ));
+                None
+            }
+            Ok(relative) if relative != *path => {
+                problems.push(Problem::new(
+                    setting,
+                    Some(i),
+                    format!(
+                        "'{}' should be written relative to the home directory, as '{}'",
+                        path.display(),
+                        relative.display()
+                    ),
+                ));
+                None
+            }
+            Ok(relative) if checked.contains(&Some(relative.clone())) => {
+                problems.push(Problem::new(
+                    setting,
+                    Some(i),
+                    format!("'{}' is listed more than once", path.display()),
+                ));
+                None
+            }
+            Ok(relative) => Some(relative),
+        };
+
+        checked.push(relative);
+    }
+
+    checked
+}
+
+/// Helper function to check the roots `roots` (see `FilesConfig::roots`) for the home directory `home`: they must be
+/// absolute, outside of the home directory without containing it, not inside of each other, and have distinct
+/// targets relative to the repository. Returns the paths of the roots without problems.
+fn check_roots(roots: &[Root], home: &Path, problems: &mut Vec<Problem>) -> Vec<PathBuf> {
+    let home = paths::normalize(home);
+    let mut checked = vec![];
+
+    for (i, root) in roots.iter().enumerate() {
+        let (path, target) = (paths::normalize(&root.path), root.target());
+        let mut problem = |message: String| {
+            problems.push(Problem::new("files.roots", Some(i), message));
+        };
+
+        if !root.path.is_absolute() {
+            problem(format!("'{}' isn't an absolute path", root.path.display()));
+        } else if path.starts_with(&home) || home.starts_with(&path) {
+            problem(format!(
+                "'{}' overlaps the home directory '{}'",
+                root.path.display(),
+                home.display()
+            ));
+        } else if let Some(other) = roots[..i].iter().find(|other| {
+            let other = paths::normalize(&other.path);
+            path.starts_with(&other) || other.starts_with(&path)
+        }) {
+            problem(format!(
+                "'{}' overlaps the root '{}'",
+                root.path.display(),
+                other.path.display()
+            ));
+        } else if target.as_os_str().is_empty()
+            || target
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            problem(format!(
+                "the target '{}' isn't a path inside of the repository",
+                target.display()
+            ));
+        } else if roots[..i].iter().any(|other| {
+            let other = other.target();
+            target.starts_with(&other) || other.starts_with(&target)
+        }) {
+            problem(format!(
+                "the target '{}' overlaps the one of another root",
+                target.display()
+            ));
+        } else {
+            checked.push(path);
+        }
+    }
+
+    checked
+}
+
+/// Helper function to check that the entries `entries` of the setting `setting` are listed only once.
+fn check_duplicates(entries: &[String], setting: &str, problems: &mut Vec<Problem>) {
+    for (i, entry) in entries.iter().enumerate() {
+        if entries[..i].contains(entry) {
+            problems.push(Problem::new(
+                setting,
+                Some(i),
+                format!("'{}' is listed more than once", entry),
+            ));
+        }
+    }
+}
+
+/// Helper function to check whether the `.gitignore` pattern `pattern` ignores the managed path `path` (or a folder
+/// it's in) as a whole. Negated patterns never do, and patterns only matching folders are taken to match `path`.
+fn ignores(pattern: &str, path: &Path) -> bool {
+    if pattern.is_empty() || pattern.starts_with(['!', '#']) {
+        return false;
+    }
+
+    let pattern = pattern.trim_end_matches('/');
+    let glob = match pattern.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if pattern.contains('/') => pattern.to_string(),
+        None => format!("**/{}", pattern),
+    };
+
+    let Ok(glob) = GlobBuilder::new(&glob).literal_separator(true).build() else {
+        return false;
+    };
+    let matcher = glob.compile_matcher();
+
+    path.ancestors()
+        .filter(|ancestor| !ancestor.as_os_str().is_empty())
+        .any(|ancestor| matcher.is_match(ancestor))
+}
+
+/// Helper function to find the byte offset of `setting` (e.g. `files.include`), or of its entry `index`, in
+/// `document`. Falls back to the setting itself if the entry can't be found, and to the closest table it's in if the
+/// setting isn't in the file (e.g. because it's the default).
+fn locate(document: &ImDocument<&str>, setting: &str, index: Option<usize>) -> Option<usize> {
+    let mut item: Option<&Item> = None;
+    let mut found = None;
+
+    for key in setting.split('.').filter(|key| !key.is_empty()) {
+        let table = match item {
+            None => document.as_table().get_key_value(key),
+            Some(item) => item
+                .as_table_like()
+                .and_then(|table| table.get_key_value(key)),
+        };
+
+        let Some((key, next)) = table else {
+            break;
+        };
+
+        found = key.span().or(next.span()).or(found);
+        item = Some(next);
+    }
+
+    let entry = item
+        .and_then(|item| item.as_array())
+        .zip(index)
+        .and_then(|(array, index)| array.get(index))
+        .and_then(|value| value.span());
+
+    entry.or(found).map(|span| span.start)
+}
+
+/// Helper function to turn the byte offset `offset` in `contents` into a line and column, both starting at 1.
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = contents.get(..offset).unwrap_or(contents);
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+
+    (line, column)
+}
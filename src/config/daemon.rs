@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration for when the daemon syncs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// What makes the daemon sync: a fixed timer (every `delay_between_sync` seconds), changes to the managed
+    /// files, or both. The default is the timer.
+    #[serde(default)]
+    pub mode: SyncMode,
+
+    /// How long in milliseconds the managed files have to stay unchanged before the daemon syncs them, so that a
+    /// burst of changes (e.g. an editor saving several files) is synced at once. The default is 2 seconds.
+    #[serde(default = "DaemonConfig::default_debounce")]
+    pub debounce: u64,
+
+    /// Whether the daemon amends its previous sync commit instead of making a new one, as long as that commit wasn't
+    /// pushed yet. The default is to not amend.
+    #[serde(default)]
+    pub amend: bool,
+
+    /// How many sync commits the daemon makes per day at most. Once there are as many from this machine, the changes
+    /// are squashed into the newest one, as long as it wasn't pushed yet. The default is 0, which doesn't limit them.
+    #[serde(default)]
+    pub commits_per_day: usize,
+}
+
+/// What makes the daemon sync.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncMode {
+    /// Sync every `delay_between_sync` seconds.
+    #[default]
+    Interval,
+
+    /// Sync whenever a managed file changes. Changes on the remote are only pulled with the next local change.
+    Watch,
+
+    /// Sync whenever a managed file changes, and at the latest every `delay_between_sync` seconds.
+    Both,
+}
+
+impl SyncMode {
+    /// Whether the managed files are watched for changes.
+    pub fn watches(&self) -> bool {
+        matches!(self, SyncMode::Watch | SyncMode::Both)
+    }
+
+    /// Whether the daemon syncs every `delay_between_sync` seconds.
+    pub fn has_interval(&self) -> bool {
+        matches!(self, SyncMode::Interval | SyncMode::Both)
+    }
+}
+
+impl Default for DaemonConfig {
+    /// The default configuration for Dotbak.
+    fn default() -> Self {
+        DaemonConfig {
+            mode: SyncMode::default(),
+            debounce: DaemonConfig::default_debounce(),
+            amend: false,
+            commits_per_day: 0,
+        }
+    }
+}
+
+/// Private API for the configuration.
+impl DaemonConfig {
+    /// Returns the default for `debounce`.
+    fn default_debounce() -> u64 {
+        2000
+    }
+}
@@ -0,0 +1,72 @@
+use super::Config;
+use crate::errors::{config::ConfigError, Result};
+use toml_edit::{DocumentMut, Item};
+
+/// Gets the value of the setting `key` (e.g. `files.sparse`, or `git.remotes.backup`) in `config`, including the
+/// defaults of the settings that aren't in the configuration file.
+pub fn get(config: &Config, key: &str) -> Result<toml::Value> {
+    let mut value = toml::Value::try_from(config)?;
+
+    for part in key.split('.') {
+        value = match value {
+            toml::Value::Table(mut table) => table.remove(part),
+            _ => None,
+        }
+        .ok_or_else(|| ConfigError::UnknownSetting {
+            key: key.to_string(),
+        })?;
+    }
+
+    Ok(value)
+}
+
+/// Sets the setting `key` (e.g. `delay_between_sync`) to `value` in the contents `contents` of a configuration file,
+/// returning the new contents. Everything else in the file, like comments and formatting, is kept as-is. `value` is
+/// read as a TOML value (e.g. `600`, `true` or `[".zshrc"]`), and taken as a string if it isn't one (e.g. a URL).
+/// Missing tables are created. The new contents aren't checked, see `check::check` for that.
+pub fn set(contents: &str, key: &str, value: &str) -> Result<String> {
+    let unknown = || ConfigError::UnknownSetting {
+        key: key.to_string(),
+    };
+    let mut document = contents
+        .parse::<DocumentMut>()
+        .map_err(|err| ConfigError::Syntax { source: err })?;
+    let value = value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| value.into());
+
+    let (parents, name) = match key.rsplit_once('.') {
+        Some((parents, name)) => (parents.split('.').collect::<Vec<_>>(), name),
+        None => (vec![], key),
+    };
+
+    if name.is_empty() || parents.iter().any(|part| part.is_empty()) {
+        return Err(unknown().into());
+    }
+
+    let mut item = document.as_item_mut();
+
+    for part in parents {
+        item = item
+            .as_table_like_mut()
+            .ok_or_else(unknown)?
+            .entry(part)
+            .or_insert(toml_edit::table());
+    }
+
+    let table = item.as_table_like_mut().ok_or_else(unknown)?;
+
+    // Replacing the value in place keeps the comments around it.
+    match table.get_mut(name).and_then(Item::as_value_mut) {
+        Some(old) => {
+            let decor = old.decor().clone();
+            *old = value;
+            *old.decor_mut() = decor;
+        }
+        None => {
+            table.insert(name, Item::Value(value));
+        }
+    }
+
+    Ok(document.to_string())
+}
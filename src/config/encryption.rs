@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The configuration for encrypting sensitive files.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptionConfig {
+    /// The managed files (i.e. paths in `files.include`) that are encrypted with age in the repository, e.g.
+    /// `.ssh/config` or `.netrc`. They're stored as `<file>.age` in the repository, and as real (decrypted) files
+    /// instead of symlinks in the home directory. Only files can be encrypted, not folders. The default is `[]`.
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+
+    /// Where the age key to encrypt the files with is. Relative paths (and paths starting with `~`) are relative to
+    /// the home directory. The key must never be managed by `dotbak` itself. The default is `key.txt` next to the
+    /// configuration file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<PathBuf>,
+}
@@ -1,15 +1,130 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Component, PathBuf};
 
 /// The configuration for the `Files` struct.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct FilesConfig {
     /// The inclusion patterns for files to backup. This is a list of glob patterns to match
     /// against the files in the home directory. These are all relative to the home directory.
     /// When both include and exclude patterns match a file, the exclude pattern takes precedence.
-    /// The default value is `[".dotbak/config.toml"]`, which is the configuration file itself.
+    /// The default value is `[".config/dotbak/config.toml"]`, which is the configuration file itself.
     #[serde(default = "FilesConfig::default_include")]
     pub include: Vec<PathBuf>,
+
+    /// The files/folders inside of included folders that aren't managed, relative to the home directory, e.g.
+    /// `.config/Slack` when `.config` is included. Included folders with excluded paths inside of them aren't linked
+    /// as a whole, but child by child. The default is to exclude nothing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<PathBuf>,
+
+    /// What to do with files that have other hard links to them (e.g. in maildir setups or some password stores).
+    /// Moving them into the repository would silently break the link between them. The default is to skip them.
+    #[serde(default)]
+    pub hardlinks: HardlinkPolicy,
+
+    /// Whether to automatically manage the lock files of well-known plugin managers (e.g. `lazy-lock.json`), but
+    /// not the plugins themselves, which the plugin managers can install again from the lock files. The default is
+    /// `false`.
+    #[serde(default)]
+    pub track_locks: bool,
+
+    /// How large (in bytes) a file may be to be diffed or exported with its contents. Larger files, and files that
+    /// look binary, are marked as binary for git and only compared by size and in chunks, so that `dotbak` stays
+    /// fast and within this much memory even with huge assets like wallpapers or fonts. The default is 16 MiB.
+    #[serde(default = "FilesConfig::default_diff_memory_limit")]
+    pub diff_memory_limit: u64,
+
+    /// How large (in bytes) the files/folders added at once may be in total before the user is asked to confirm
+    /// adding them, so that e.g. the caches in `.config` don't end up in the history by accident. `0` never asks.
+    /// The default is 50 MiB.
+    #[serde(default = "FilesConfig::default_add_size_limit")]
+    pub add_size_limit: u64,
+
+    /// After how many failed syncs in a row a path is quarantined, i.e. skipped when syncing until it's cleared with
+    /// `dotbak quarantine clear`. This keeps a single broken path from failing every sync. `0` never quarantines
+    /// anything. The default is 3.
+    #[serde(default = "FilesConfig::default_quarantine_after")]
+    pub quarantine_after: u32,
+
+    /// Whether to only check out the files in `include` from the repository (with git's sparse checkout), e.g. when
+    /// other machines keep large fonts or wallpapers in it that this one doesn't need. They stay in the repository,
+    /// but aren't in the working tree. This needs git 2.35 or newer. The default is `false`.
+    #[serde(default)]
+    pub sparse: bool,
+
+    /// Whether to record the extended attributes of the managed files in the metadata manifest, next to their
+    /// permissions, and restore them on other machines. The default is `false`.
+    #[serde(default)]
+    pub xattrs: bool,
+
+    /// Whether files and folders `dotbak` deletes (e.g. orphaned files, or the repository and the configuration file
+    /// when deinitializing) are deleted permanently, instead of being moved to the system trash. Symlinks are always
+    /// deleted, as nothing is lost with them. The default is `false`.
+    #[serde(default)]
+    pub permanent_delete: bool,
+
+    /// The folders outside of the home directory whose files/folders can be managed too, e.g. `/etc`. Their
+    /// files/folders are listed in `include` (and `exclude`) with their absolute paths, e.g. `/etc/hosts`. The
+    /// default is to manage nothing outside of the home directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<Root>,
+}
+
+/// A folder outside of the home directory whose files/folders can be managed too. See `FilesConfig::roots`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Root {
+    /// The absolute path to the folder, e.g. `/etc`.
+    pub path: PathBuf,
+
+    /// Where the files/folders of the root are kept in the repository, relative to it. The default is the path of
+    /// the root with `@` in front of it instead of `/`, e.g. `@etc` for `/etc` and `@etc/nixos` for `/etc/nixos`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<PathBuf>,
+
+    /// Whether the files of the root are copied there instead of symlinked, so that they keep their owner (usually
+    /// root), and programs that refuse symlinks (like `sudo` for `/etc/sudoers`) or that run before the home
+    /// directory is mounted can read them. Changes to the copies are copied back into the repository when syncing.
+    /// Folders are always linked. The default is `true`.
+    #[serde(default = "Root::default_copies")]
+    pub copies: bool,
+
+    /// How the operations on the files/folders of the root that fail for lack of permissions (e.g. on root-owned
+    /// files in `/etc`) are run again with more of them. The default is not to, so that they just fail.
+    #[serde(default)]
+    pub escalate: Escalation,
+}
+
+/// How operations on files/folders in a root that `dotbak` isn't allowed to do are run again with more permissions.
+/// See `Root::escalate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Escalation {
+    /// Don't run them again, and fail.
+    #[default]
+    Never,
+
+    /// Run them with `sudo`, which asks for your password in the terminal if it needs it.
+    Sudo,
+
+    /// Run them with `pkexec` (polkit), which asks for your password in a dialog of the desktop.
+    Pkexec,
+}
+
+/// What to do with files (or folders containing files) that have other hard links to them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HardlinkPolicy {
+    /// Leave the file where it is, and warn about it.
+    #[default]
+    Skip,
+
+    /// Copy the file into the repository (on every sync), and leave the original where it is.
+    Copy,
+
+    /// Move the file into the repository anyways, intentionally breaking the link to its other hard links.
+    BreakLinks,
 }
 
 impl Default for FilesConfig {
@@ -17,6 +132,16 @@ impl Default for FilesConfig {
     fn default() -> Self {
         FilesConfig {
             include: FilesConfig::default_include(),
+            exclude: vec![],
+            hardlinks: HardlinkPolicy::default(),
+            track_locks: false,
+            diff_memory_limit: FilesConfig::default_diff_memory_limit(),
+            add_size_limit: FilesConfig::default_add_size_limit(),
+            quarantine_after: FilesConfig::default_quarantine_after(),
+            sparse: false,
+            xattrs: false,
+            permanent_delete: false,
+            roots: vec![],
         }
     }
 }
@@ -25,6 +150,58 @@ impl Default for FilesConfig {
 impl FilesConfig {
     /// Returns the default for `include`.
     fn default_include() -> Vec<PathBuf> {
-        vec![".dotbak/config.toml".into()]
+        vec![".config/dotbak/config.toml".into()]
+    }
+
+    /// Returns the default for `diff_memory_limit`.
+    fn default_diff_memory_limit() -> u64 {
+        16 * 1024 * 1024
+    }
+
+    /// Returns the default for `add_size_limit`.
+    fn default_add_size_limit() -> u64 {
+        50 * 1024 * 1024
+    }
+
+    /// Returns the default for `quarantine_after`.
+    fn default_quarantine_after() -> u32 {
+        3
+    }
+}
+
+impl Root {
+    /// Gets where the files/folders of the root are kept in the repository, relative to it. See `target`.
+    pub fn target(&self) -> PathBuf {
+        match &self.target {
+            Some(target) => target.clone(),
+            None => {
+                let path = self
+                    .path
+                    .components()
+                    .filter_map(|component| match component {
+                        Component::Normal(name) => Some(name),
+                        _ => None,
+                    })
+                    .collect::<PathBuf>();
+
+                PathBuf::from(format!("@{}", path.display()))
+            }
+        }
+    }
+
+    /// Returns the default for `copies`.
+    fn default_copies() -> bool {
+        true
+    }
+}
+
+impl Escalation {
+    /// Gets the command (with its arguments) that operations are run again with, or `None` if they aren't.
+    pub fn command(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Escalation::Never => None,
+            Escalation::Sudo => Some(&["sudo", "--"]),
+            Escalation::Pkexec => Some(&["pkexec"]),
+        }
     }
 }
@@ -0,0 +1,286 @@
+use crate::{
+    errors::Result,
+    git::{auth::Credentials, Signing, MAIN_BRANCH_NAME, REMOTE_NAME},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The configuration for the git repository.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// The branch `dotbak` commits to, pulls and pushes, e.g. to keep a branch per machine. The default is
+    /// `main`, or the remote's default branch when cloning. Switch branches with `dotbak branch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+
+    /// The remotes every push goes to, by name, e.g. a backup mirror next to `origin`. The `origin` remote (set
+    /// from `repository_url` when cloning) is the one pulled from, and is always pushed to unless it's disabled
+    /// here. Remotes are added to the repository when pushing, if they're missing.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub remotes: BTreeMap<String, RemoteConfig>,
+
+    /// A shell command printing an access token for HTTPS remotes (e.g. `gh auth token`), used when the remote asks
+    /// for credentials. This keeps the token itself out of the configuration file, which is synced. The
+    /// `DOTBAK_GIT_TOKEN` environment variable takes precedence over it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_command: Option<String>,
+
+    /// The username sent with the access token. Hosts like GitHub ignore it, so the default is `x-access-token`.
+    /// The `DOTBAK_GIT_USERNAME` environment variable takes precedence over it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+
+    /// Whether to push right away when `add`, `remove` or `sync` leave commits that haven't been pushed yet,
+    /// instead of warning about them. The default is `false`.
+    #[serde(default)]
+    pub auto_push: bool,
+
+    /// How the local commits are reconciled with the remote's when pulling. The default is `merge`.
+    #[serde(default)]
+    pub pull_strategy: PullStrategy,
+
+    /// The configuration for storing large files with git-lfs.
+    #[serde(default)]
+    pub lfs: LfsConfig,
+
+    /// The name and email to make commits with.
+    #[serde(default)]
+    pub identity: IdentityConfig,
+
+    /// How `dotbak clone` clones the repository, e.g. without its full history to set up large repositories faster.
+    #[serde(default)]
+    pub clone: CloneConfig,
+
+    /// How long in seconds git commands may run before they're stopped, by subcommand (e.g. `pull`, `push`, `fetch`
+    /// or `clone`), so that a hung command (e.g. on a bad network, or waiting for an SSH passphrase) doesn't block
+    /// `dotbak` and the daemon forever. `default` applies to the subcommands that aren't listed, and `0` means no
+    /// timeout. Without either, commands that talk to the remote may run for 10 minutes, and the others as long as
+    /// they take.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub timeouts: BTreeMap<String, u64>,
+
+    /// How `dotbak` commits, pushes, pulls, clones and reads the status and history of the repository: by running
+    /// the `git` binary (the default), or with libgit2, which needs `dotbak` to be built with the `libgit2` feature.
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// Whether to sign the commits `dotbak` makes (including merges when pulling). The default is `false`.
+    #[serde(default)]
+    pub sign: bool,
+
+    /// The key to sign commits with: a GPG key ID, or for SSH signatures the path to a public key (or the key
+    /// itself, prefixed with `key::`). The default is git's `user.signingkey`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_key: Option<String>,
+
+    /// The format of the signatures. The default is `ssh` for keys that look like SSH keys, and git's `gpg.format`
+    /// otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signing_format: Option<SigningFormat>,
+
+    /// Whether to warn about pulled commits that aren't signed, or whose signature is bad. The default is `false`.
+    #[serde(default)]
+    pub require_signed: bool,
+}
+
+/// The configuration for storing large files with git-lfs, so that they don't bloat the repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LfsConfig {
+    /// The patterns (in `.gitattributes` syntax, relative to the home directory) of the files to store with git-lfs,
+    /// e.g. `*.ttf` or `wallpapers/**`. They're only used if git-lfs is installed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub track: Vec<String>,
+}
+
+/// The name and email to make commits with. They're written into the repository's git configuration when it's
+/// created or cloned (and when the configuration changes), so that committing works on fresh machines where git
+/// isn't configured yet.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdentityConfig {
+    /// The name to make commits with. The default is git's `user.name`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The email to make commits with. The default is git's `user.email`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// How `dotbak clone` clones the repository. Leaving out history or file contents speeds up setting up large
+/// repositories; `dotbak unshallow` fetches what was left out later.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CloneConfig {
+    /// How many of the newest commits to clone. The default is all of them (or only the newest one with
+    /// `network.low_bandwidth`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+
+    /// The git object filter to clone with, e.g. `blob:none` to only download the contents of files when they're
+    /// checked out. The default is to download everything.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<String>,
+}
+
+/// How the local commits are reconciled with the remote's when pulling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullStrategy {
+    /// Merge the remote's commits, with a merge commit if both sides have new commits.
+    #[default]
+    Merge,
+
+    /// Replay the local commits on top of the remote's, so that the history stays linear.
+    Rebase,
+
+    /// Only fast-forward to the remote's commits, and fail if there are new local commits as well.
+    FfOnly,
+}
+
+/// How git operations on the repository are run. See `git::backend::GitBackend`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Run the `git` binary.
+    #[default]
+    Git,
+
+    /// Use libgit2, so that `dotbak init`, `add`, `sync` and `clone` work without the `git` binary.
+    Libgit2,
+}
+
+/// The format of commit signatures, as in git's `gpg.format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SigningFormat {
+    /// OpenPGP signatures, made with `gpg`.
+    Openpgp,
+
+    /// SSH signatures, made with `ssh-keygen`.
+    Ssh,
+
+    /// X.509 signatures, made with `gpgsm`.
+    X509,
+}
+
+/// A remote in `git.remotes`: either just its URL, or a table with the URL and whether it's pushed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+pub enum RemoteConfig {
+    /// The URL of the remote, which is pushed to.
+    Url(String),
+
+    /// The URL of the remote, and whether it's pushed to.
+    Table {
+        /// The URL of the remote.
+        url: String,
+
+        /// Whether pushes go to the remote. The default is `true`.
+        #[serde(default = "RemoteConfig::default_enabled")]
+        enabled: bool,
+    },
+}
+
+impl GitConfig {
+    /// Gets the branch `dotbak` works on.
+    pub fn branch(&self) -> &str {
+        self.branch.as_deref().unwrap_or(MAIN_BRANCH_NAME)
+    }
+
+    /// Gets the credentials for HTTPS remotes from the environment (see `Credentials::from_env`), or from the output
+    /// of `token_command`. Returns `None` if neither has a token.
+    pub fn credentials(&self) -> Result<Option<Credentials>> {
+        if let Some(credentials) = Credentials::from_env(self.username.as_deref()) {
+            return Ok(Some(credentials));
+        }
+
+        self.token_command
+            .as_deref()
+            .map(|command| Credentials::from_command(command, self.username.as_deref()))
+            .transpose()
+    }
+
+    /// Gets how commits are signed, or `None` if they aren't (see `sign`). Without a `signing_format`, keys that
+    /// look like SSH keys (public key files, or keys prefixed with `key::` or `ssh-`) are SSH keys.
+    pub fn signing(&self) -> Option<Signing> {
+        if !self.sign {
+            return None;
+        }
+
+        let looks_like_ssh = |key: &str| {
+            key.ends_with(".pub") || key.starts_with("key::") || key.starts_with("ssh-")
+        };
+        let format = self.signing_format.or_else(|| {
+            self.signing_key
+                .as_deref()
+                .filter(|key| looks_like_ssh(key))
+                .map(|_| SigningFormat::Ssh)
+        });
+
+        Some(Signing {
+            format,
+            key: self.signing_key.clone(),
+        })
+    }
+
+    /// Gets the names of the remotes that pushes go to: `origin` unless it's disabled, and the enabled remotes in
+    /// `remotes`.
+    pub fn push_remotes(&self) -> Vec<&str> {
+        let origin = self.pushes_to(REMOTE_NAME).then_some(REMOTE_NAME);
+        let others = self
+            .remotes
+            .iter()
+            .filter(|(name, remote)| *name != REMOTE_NAME && remote.enabled())
+            .map(|(name, _)| name.as_str());
+
+        origin.into_iter().chain(others).collect()
+    }
+
+    /// Whether pushes go to the remote `name`. Remotes that aren't configured here are only pushed to if they're
+    /// `origin`.
+    pub fn pushes_to(&self, name: &str) -> bool {
+        match self.remotes.get(name) {
+            Some(remote) => remote.enabled(),
+            None => name == REMOTE_NAME,
+        }
+    }
+}
+
+impl RemoteConfig {
+    /// Gets the URL of the remote.
+    pub fn url(&self) -> &str {
+        match self {
+            RemoteConfig::Url(url) | RemoteConfig::Table { url, .. } => url,
+        }
+    }
+
+    /// Whether pushes go to the remote.
+    pub fn enabled(&self) -> bool {
+        match self {
+            RemoteConfig::Url(_) => true,
+            RemoteConfig::Table { enabled, .. } => *enabled,
+        }
+    }
+}
+
+impl SigningFormat {
+    /// Gets the name of the format for git's `gpg.format`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigningFormat::Openpgp => "openpgp",
+            SigningFormat::Ssh => "ssh",
+            SigningFormat::X509 => "x509",
+        }
+    }
+}
+
+/// Private API for the configuration.
+impl RemoteConfig {
+    /// Returns the default for `enabled`.
+    fn default_enabled() -> bool {
+        true
+    }
+}
@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration for how `dotbak` records history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// How far in seconds the newest known commit may be in the future before `dotbak` warns that this machine's
+    /// clock (or another machine's) is off. The default is 5 minutes.
+    #[serde(default = "HistoryConfig::default_max_clock_skew")]
+    pub max_clock_skew: u64,
+
+    /// Whether to add a `Dotbak-Sequence` trailer with a monotonically increasing number to every commit, so that
+    /// the order of commits doesn't depend on the (possibly wrong) clocks of the machines. The default is `false`.
+    #[serde(default)]
+    pub sequence_trailer: bool,
+
+    /// Whether the syncs of the daemon are pushed to the `staging` branch of the remote instead of `main`, so that
+    /// they can be reviewed and promoted to `main` with `dotbak promote`. The default is `false`.
+    #[serde(default)]
+    pub staging: bool,
+}
+
+impl Default for HistoryConfig {
+    /// The default configuration for Dotbak.
+    fn default() -> Self {
+        HistoryConfig {
+            max_clock_skew: HistoryConfig::default_max_clock_skew(),
+            sequence_trailer: false,
+            staging: false,
+        }
+    }
+}
+
+/// Private API for the configuration.
+impl HistoryConfig {
+    /// Returns the default for `max_clock_skew`.
+    fn default_max_clock_skew() -> u64 {
+        5 * 60
+    }
+}
@@ -0,0 +1,63 @@
+use crate::hooks::Hook;
+use serde::{Deserialize, Serialize};
+
+/// The configuration for the shell commands run before and after `dotbak`'s operations. Each hook is a list of
+/// commands, which are run one after another with `sh -c` in the home directory. If a command fails, the remaining
+/// commands and the operation itself (for `pre_*` hooks) are stopped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// The commands run before adding files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_add: Vec<String>,
+
+    /// The commands run after adding files.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_add: Vec<String>,
+
+    /// The commands run before syncing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_sync: Vec<String>,
+
+    /// The commands run after syncing.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_sync: Vec<String>,
+
+    /// The commands run before pulling, including the pull of a sync.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_pull: Vec<String>,
+
+    /// The commands run after pulling, including the pull of a sync.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_pull: Vec<String>,
+
+    /// The commands run before pushing, including the push of a sync.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_push: Vec<String>,
+
+    /// The commands run after pushing, including the push of a sync.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_push: Vec<String>,
+
+    /// The commands run once a new machine is set up with `dotbak bootstrap`, e.g. to install packages.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bootstrap: Vec<String>,
+}
+
+/// Public API for the configuration.
+impl HooksConfig {
+    /// Gets the commands of `hook`.
+    pub fn commands(&self, hook: Hook) -> &[String] {
+        match hook {
+            Hook::PreAdd => &self.pre_add,
+            Hook::PostAdd => &self.post_add,
+            Hook::PreSync => &self.pre_sync,
+            Hook::PostSync => &self.post_sync,
+            Hook::PrePull => &self.pre_pull,
+            Hook::PostPull => &self.post_pull,
+            Hook::PrePush => &self.pre_push,
+            Hook::PostPush => &self.post_push,
+            Hook::Bootstrap => &self.bootstrap,
+        }
+    }
+}
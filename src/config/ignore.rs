@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration for the files in the repository that git ignores, like caches or swap files inside of managed
+/// folders. `dotbak` keeps the repository's `.gitignore` in sync with it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IgnoreConfig {
+    /// The patterns of the files to ignore, in `.gitignore` syntax, relative to the home directory (e.g.
+    /// `.config/nvim/plugin/packer_compiled.lua` or `*.swp`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+}
@@ -0,0 +1,117 @@
+use crate::{
+    errors::{config::ConfigError, io::IoError, DotbakError, Result},
+    lock,
+};
+use std::{fs, path::Path};
+use toml_edit::{DocumentMut, Item};
+
+/// The version of the configuration format this version of `dotbak` writes. Configuration files without a `version`
+/// are from before it was added, so they're version 0.
+pub const CURRENT_VERSION: u32 = 1;
+
+/// The migrations upgrading the configuration format, in order: the migration at index `i` upgrades version `i` to
+/// version `i + 1`. They change the document in place, so that comments and formatting are kept.
+const MIGRATIONS: &[fn(&mut DocumentMut)] = &[move_include_to_files];
+
+/// Reads the configuration file at `path`, which must exist. If it's older than `CURRENT_VERSION`, it's upgraded
+/// (see `migrate`), and the upgraded file is written back. Returns the (upgraded) contents.
+pub fn upgrade_file<P>(path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|err| -> DotbakError {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => ConfigError::NotFound {
+                path: path.to_path_buf(),
+            }
+            .into(),
+            _ => IoError::Read {
+                source: err,
+                path: path.to_path_buf(),
+            }
+            .into(),
+        }
+    })?;
+
+    match migrate(&contents)? {
+        Some(upgraded) => {
+            lock::write_atomic(path, &upgraded)?;
+            Ok(upgraded)
+        }
+        None => Ok(contents),
+    }
+}
+
+/// Upgrades the contents `contents` of a configuration file to `CURRENT_VERSION`, running every migration from its
+/// version on. Returns `None` if it's up to date already, or isn't valid TOML (deserializing it reports where the
+/// syntax error is). Returns an error if it's from a newer version of `dotbak`.
+pub fn migrate(contents: &str) -> Result<Option<String>> {
+    let Ok(mut document) = contents.parse::<DocumentMut>() else {
+        return Ok(None);
+    };
+
+    let version = version(&document);
+
+    if version > CURRENT_VERSION {
+        return Err(ConfigError::UnsupportedVersion {
+            version,
+            supported: CURRENT_VERSION,
+        }
+        .into());
+    }
+
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut document);
+    }
+
+    document.insert("version", toml_edit::value(CURRENT_VERSION as i64));
+
+    Ok(Some(document.to_string()))
+}
+
+/// Gets the version of the configuration format `document` is in. Documents without a (valid) `version` are
+/// version 0.
+pub fn version(document: &DocumentMut) -> u32 {
+    document
+        .get("version")
+        .and_then(Item::as_integer)
+        .and_then(|version| u32::try_from(version).ok())
+        .unwrap_or(0)
+}
+
+/// Version 0 to 1: the managed paths were listed in a top-level `include`, which is now `files.include`. If both are
+/// set, the paths from `include` are added to `files.include`.
+fn move_include_to_files(document: &mut DocumentMut) {
+    let Some(include) = document.remove("include") else {
+        return;
+    };
+
+    let Some(files) = document
+        .entry("files")
+        .or_insert(toml_edit::table())
+        .as_table_like_mut()
+    else {
+        return;
+    };
+
+    if !files.contains_key("include") {
+        files.insert("include", include);
+        return;
+    }
+
+    if let (Some(existing), Some(include)) = (
+        files.get_mut("include").and_then(Item::as_array_mut),
+        include.as_array(),
+    ) {
+        for path in include {
+            if !existing.iter().any(|entry| entry.as_str() == path.as_str()) {
+                existing.push(path.clone());
+            }
+        }
+    }
+}
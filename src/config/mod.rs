@@ -1,35 +1,165 @@
+pub mod check;
+pub mod daemon;
+pub mod edit;
+pub mod encryption;
 pub mod files;
+pub mod git;
+pub mod history;
+pub mod hooks;
+pub mod ignore;
+pub mod migrations;
+pub mod network;
+pub mod notifications;
+pub mod overrides;
+pub mod packages;
+pub mod secrets;
+pub mod shell;
 mod tests;
 
-use self::files::FilesConfig;
-use crate::errors::{config::ConfigError, io::IoError, Result};
+use self::{
+    daemon::DaemonConfig, encryption::EncryptionConfig, files::FilesConfig, git::GitConfig,
+    history::HistoryConfig, hooks::HooksConfig, ignore::IgnoreConfig, network::NetworkConfig,
+    notifications::NotificationsConfig, overrides::Overrides, packages::PackagesConfig,
+    secrets::SecretsConfig, shell::ShellConfig,
+};
+use crate::{
+    errors::{config::ConfigError, io::IoError, DotbakError, Result},
+    lock, paths,
+    platform::{self, Trash},
+};
 use serde::{Deserialize, Serialize};
 use std::path::Path;
-use std::{fs, path::PathBuf};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// The settings that can't be changed while `dotbak` is running, as the repository was already opened with them.
+/// Changes to these are only applied the next time `dotbak` starts.
+pub const RESTART_SETTINGS: &[&str] = &["repository_url", "repo_path"];
+
+/// The top-level settings (and sections) of the configuration.
+pub const SETTINGS: &[&str] = &[
+    "repository_url",
+    "repo_path",
+    "delay_between_sync",
+    "daemon",
+    "files",
+    "network",
+    "git",
+    "history",
+    "encryption",
+    "secrets",
+    "ignore",
+    "hooks",
+    "packages",
+    "notifications",
+    "alias",
+];
+
+/// The settings that changed when reloading the configuration file. See `Config::reload`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Reload {
+    /// The changed settings that were applied right away.
+    pub applied: Vec<&'static str>,
+
+    /// The changed settings that are only applied the next time `dotbak` starts.
+    pub pending: Vec<&'static str>,
+}
+
+impl Reload {
+    /// Whether no settings changed.
+    pub fn is_empty(&self) -> bool {
+        self.applied.is_empty() && self.pending.is_empty()
+    }
+}
 
 /// The configuration that Dotbak uses to run.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// The location of the configuration file. This is a temporary value that will be overwritten
     /// later when loading in `Config::load_config`, so it is not serialized.
     #[serde(skip)]
     pub path: PathBuf,
 
+    /// The settings overridden by environment variables and flags, which the configuration was loaded with. They're
+    /// never saved to the configuration file, so they're not serialized either.
+    #[serde(skip)]
+    pub overrides: Overrides,
+
+    /// The version of the configuration format the file is in. Older files are upgraded when they're loaded, see
+    /// `migrations`.
+    #[serde(default)]
+    pub version: u32,
+
     /// The URL for the remote git repository. This is the URL that will be used to clone the
     /// repository if it doesn't exist, and to push and pull changes to and from the repository.
     /// Also, incase the local repository is deleted or corrupted, this URL will be used to clone
     /// the repository again.
     pub repository_url: Option<String>,
 
+    /// The location of the dotfiles repository. If this is not set, the repository lives in
+    /// `~/.local/share/dotbak/dotfiles`. Relative paths (and paths starting with `~`) are relative to the home
+    /// directory. This may not be inside of (or contain) any path in `files.include`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_path: Option<PathBuf>,
+
     /// The delay between syncs in seconds. This is the amount of time in SECONDS that Dotbak will wait in
     /// between synchronizing files and folders when run as a daemon.
     #[serde(default = "default_delay_time")]
     pub delay_between_sync: u64,
 
+    /// The configuration for when the daemon syncs.
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+
     /// The configuration for the `Files` struct. This is a list of files and folders that will be
     /// managed by Dotbak.
     #[serde(default)]
     pub files: FilesConfig,
+
+    /// The configuration for how `dotbak` uses the network.
+    #[serde(default)]
+    pub network: NetworkConfig,
+
+    /// The configuration for the git repository, like the remotes pushed to.
+    #[serde(default)]
+    pub git: GitConfig,
+
+    /// The configuration for how `dotbak` records history.
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    /// The configuration for encrypting sensitive files.
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+
+    /// The configuration for finding secrets in files before committing them.
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+
+    /// The configuration for the files in the repository that git ignores.
+    #[serde(default)]
+    pub ignore: IgnoreConfig,
+
+    /// The shell commands run before and after `dotbak`'s operations.
+    #[serde(default)]
+    pub hooks: HooksConfig,
+
+    /// The package managers whose installed packages are exported to the repository.
+    #[serde(default)]
+    pub packages: PackagesConfig,
+
+    /// How the user is notified when the daemon fails to sync.
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+
+    /// The configuration for the shell integration, see `dotbak shell-init`.
+    #[serde(default)]
+    pub shell: ShellConfig,
+
+    /// User-defined command shortcuts, mapping a name to the arguments it stands for. For example,
+    /// `up = ["pull"]` makes `dotbak up` run `dotbak pull`. Aliases can't shadow built-in commands.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub alias: BTreeMap<String, Vec<String>>,
 }
 
 impl Default for Config {
@@ -37,9 +167,24 @@ impl Default for Config {
     fn default() -> Self {
         Config {
             path: PathBuf::new(), // This is a temporary value that will be overwritten later.
-            repository_url: None, // No default value.
+            overrides: Overrides::default(),
+            version: migrations::CURRENT_VERSION,
+            repository_url: None,        // No default value.
+            repo_path: None,             // Defaults to `~/.local/share/dotbak/dotfiles`.
             delay_between_sync: 15 * 60, // 15 minutes
+            daemon: DaemonConfig::default(),
             files: FilesConfig::default(),
+            network: NetworkConfig::default(),
+            git: GitConfig::default(),
+            history: HistoryConfig::default(),
+            encryption: EncryptionConfig::default(),
+            secrets: SecretsConfig::default(),
+            ignore: IgnoreConfig::default(),
+            hooks: HooksConfig::default(),
+            packages: PackagesConfig::default(),
+            notifications: NotificationsConfig::default(),
+            shell: ShellConfig::default(),
+            alias: BTreeMap::new(),
         }
     }
 }
@@ -48,6 +193,16 @@ impl Default for Config {
 impl Config {
     /// Loads the config file from the given path. If the path doesn't exist, it will return an error.
     pub fn load_config<P>(path: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Config::load_layered(path, &Overrides::default())
+    }
+
+    /// Loads the config file from the given path, with the settings in `overrides` taking precedence over the ones
+    /// in the file. If the file is in an older format, it's upgraded first (see `migrations`). If the path doesn't
+    /// exist, it will return an error.
+    pub fn load_layered<P>(path: P, overrides: &Overrides) -> Result<Self>
     where
         P: AsRef<Path>,
     {
@@ -61,20 +216,27 @@ impl Config {
             .into());
         }
 
-        let config_str = fs::read_to_string(path).map_err(|err| IoError::Read {
-            source: err,
-            path: path.to_path_buf(),
-        })?;
+        let config_str = migrations::upgrade_file(path)?;
 
-        config = toml::from_str(&config_str)?;
+        // Deserializing the file directly keeps the locations of errors in it.
+        config = match overrides.is_empty() {
+            true => toml::from_str(&config_str)?,
+            false => {
+                let mut table: toml::Table = toml::from_str(&config_str)?;
+                overrides.apply(&mut table)?;
+                toml::Value::Table(table).try_into()?
+            }
+        };
 
         // IMPORTANT: This is the only place where the path is set.
         config.path = path.to_path_buf();
+        config.overrides = overrides.clone();
 
         Ok(config)
     }
 
-    /// Saves the config file to the given path. If the path doesn't exist, it will return an error.
+    /// Saves the config file to the given path. If the path doesn't exist, it will return an error. Overridden
+    /// settings keep their value from the file.
     pub fn save_config(&self) -> Result<()> {
         if !self.path.exists() {
             return Err(ConfigError::NotFound {
@@ -83,13 +245,25 @@ impl Config {
             .into());
         }
 
-        let config_str = toml::to_string_pretty(self)?;
-        fs::write(&self.path, config_str).map_err(|err| IoError::Write {
-            source: err,
-            path: self.path.to_path_buf(),
-        })?;
+        let config_str = match self.overrides.is_empty() {
+            true => toml::to_string_pretty(self)?,
+            false => {
+                let saved: toml::Table = fs::read_to_string(&self.path)
+                    .ok()
+                    .and_then(|contents| toml::from_str(&contents).ok())
+                    .unwrap_or_default();
+                let mut table = toml::Table::try_from(self)?;
 
-        Ok(())
+                for key in self.overrides.keys() {
+                    overrides::set(&mut table, key, overrides::get(&saved, key).cloned())?;
+                }
+
+                toml::to_string_pretty(&table)?
+            }
+        };
+
+        // Commands reading the configuration don't wait for the lock, so they must never see it half written.
+        lock::write_atomic(&self.path, config_str)
     }
 
     /// Creates a new config file at the given path. If the path already exists, it will return an error.
@@ -106,7 +280,11 @@ impl Config {
             .into());
         } else {
             // Create the file if it doesn't exist.
-            fs::create_dir_all(path.parent().unwrap()).map_err(|err| IoError::Create {
+            let dir = path.parent().ok_or_else(|| DotbakError::NoConfigDir {
+                path: path.to_path_buf(),
+            })?;
+
+            fs::create_dir_all(dir).map_err(|err| IoError::Create {
                 source: err,
                 path: path.to_path_buf(),
             })?;
@@ -132,13 +310,14 @@ impl Config {
         Ok(config)
     }
 
-    /// Deletes the config file at the given path. If the path doesn't exist, it will return an error.
-    pub fn delete_config(self) -> Result<()> {
+    /// Deletes the config file at the given path, moving it to `trash` if there is one, or permanently otherwise. If
+    /// the path doesn't exist, it will return an error.
+    pub fn delete_config(self, trash: Option<&dyn Trash>) -> Result<()> {
         if !self.path.exists() {
             return Err(ConfigError::NotFound { path: self.path }.into());
         }
 
-        fs::remove_file(&self.path).map_err(|err| IoError::Write {
+        platform::delete(&self.path, trash).map_err(|err| IoError::Write {
             source: err,
             path: self.path,
         })?;
@@ -147,6 +326,95 @@ impl Config {
     }
 }
 
+/// Public API for reloading the configuration while `dotbak` is running.
+impl Config {
+    /// Loads the configuration file again, returning the new configuration and the settings that changed. The
+    /// settings in `RESTART_SETTINGS` are reported as pending, as they only take effect the next time `dotbak`
+    /// starts; all other changed settings can be applied right away.
+    pub fn reload(&self) -> Result<(Self, Reload)> {
+        let config = Config::load_layered(&self.path, &self.overrides)?;
+        let (pending, applied) = self
+            .changed_settings(&config)
+            .into_iter()
+            .partition(|setting| RESTART_SETTINGS.contains(setting));
+
+        Ok((config, Reload { applied, pending }))
+    }
+
+    /// Lists the top-level settings (and sections) that differ between this configuration and `other`.
+    pub fn changed_settings(&self, other: &Config) -> Vec<&'static str> {
+        [
+            (
+                "repository_url",
+                self.repository_url != other.repository_url,
+            ),
+            ("repo_path", self.repo_path != other.repo_path),
+            (
+                "delay_between_sync",
+                self.delay_between_sync != other.delay_between_sync,
+            ),
+            ("daemon", self.daemon != other.daemon),
+            ("files", self.files != other.files),
+            ("network", self.network != other.network),
+            ("git", self.git != other.git),
+            ("history", self.history != other.history),
+            ("encryption", self.encryption != other.encryption),
+            ("secrets", self.secrets != other.secrets),
+            ("ignore", self.ignore != other.ignore),
+            ("hooks", self.hooks != other.hooks),
+            ("packages", self.packages != other.packages),
+            ("notifications", self.notifications != other.notifications),
+            ("alias", self.alias != other.alias),
+        ]
+        .into_iter()
+        .filter_map(|(setting, changed)| changed.then_some(setting))
+        .collect()
+    }
+}
+
+/// Public API for resolving paths from the configuration.
+impl Config {
+    /// Resolves `repo_path` against the home directory `home`. Returns `None` if `repo_path` is not set,
+    /// so the default location should be used. Returns an error if the path overlaps with any managed path.
+    pub fn repo_dir<P>(&self, home: P) -> Result<Option<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        match &self.repo_path {
+            Some(path) => {
+                let path = paths::resolve_home(path, home.as_ref());
+                self.check_repo_dir(&path, home)?;
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks that the (fully resolved) repository directory `repo` is not inside of a managed path, and that
+    /// no managed path is inside of it. Either would make `dotbak` symlink the repository into itself.
+    pub fn check_repo_dir<P1, P2>(&self, repo: P1, home: P2) -> Result<()>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let repo = repo.as_ref();
+
+        for managed in &self.files.include {
+            let managed_path = home.as_ref().join(managed);
+
+            if repo.starts_with(&managed_path) || managed_path.starts_with(repo) {
+                return Err(ConfigError::RepoPathManaged {
+                    repo_path: repo.to_path_buf(),
+                    managed: managed.to_path_buf(),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
 // The default delay time in seconds.
 fn default_delay_time() -> u64 {
     15 * 60
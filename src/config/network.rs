@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration for how `dotbak` uses the network.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Whether to save bandwidth, for metered connections. This makes clones shallow, skips downloading git-lfs
+    /// content, compresses pushes as much as possible, and defers pushing large files while on a metered
+    /// connection. This also applies to the daemon. The default is `false`.
+    #[serde(default)]
+    pub low_bandwidth: bool,
+
+    /// The size in bytes above which a file counts as "large" in low bandwidth mode. Pushes that contain
+    /// a file larger than this are deferred while on a metered connection. The default is 1 MiB.
+    #[serde(default = "NetworkConfig::default_large_file_size")]
+    pub large_file_size: u64,
+
+    /// How many times pushing, pulling and cloning are tried in total when the remote can't be reached (e.g. on
+    /// flaky Wi-Fi), before giving up. The default is 3.
+    #[serde(default = "NetworkConfig::default_retry_attempts")]
+    pub retry_attempts: u32,
+
+    /// How long in milliseconds to wait before trying again after failing to reach the remote. The wait doubles with
+    /// every retry. The default is 1 second.
+    #[serde(default = "NetworkConfig::default_retry_backoff")]
+    pub retry_backoff: u64,
+}
+
+impl Default for NetworkConfig {
+    /// The default configuration for Dotbak.
+    fn default() -> Self {
+        NetworkConfig {
+            low_bandwidth: false,
+            large_file_size: NetworkConfig::default_large_file_size(),
+            retry_attempts: NetworkConfig::default_retry_attempts(),
+            retry_backoff: NetworkConfig::default_retry_backoff(),
+        }
+    }
+}
+
+/// Private API for the configuration.
+impl NetworkConfig {
+    /// Returns the default for `large_file_size`.
+    fn default_large_file_size() -> u64 {
+        1024 * 1024
+    }
+
+    /// Returns the default for `retry_attempts`.
+    fn default_retry_attempts() -> u32 {
+        3
+    }
+
+    /// Returns the default for `retry_backoff`.
+    fn default_retry_backoff() -> u64 {
+        1000
+    }
+}
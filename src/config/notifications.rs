@@ -0,0 +1,67 @@
+use crate::notifications::Severity;
+use serde::{Deserialize, Serialize};
+
+/// The configuration for notifying the user when the daemon fails to sync, as nobody sees its output otherwise.
+/// Failures are shown as desktop notifications by default, and can also be posted to a webhook or emailed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationsConfig {
+    /// The failures shown as desktop notifications. The default is errors.
+    #[serde(default)]
+    pub desktop: Level,
+
+    /// The URL that failures are posted to as JSON (with `title`, `body` and `severity`), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<String>,
+
+    /// The failures posted to `webhook`. The default is errors.
+    #[serde(default)]
+    pub webhook_level: Level,
+
+    /// The email address that failures are sent to with `sendmail`, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+
+    /// The failures sent to `email`. The default is errors.
+    #[serde(default)]
+    pub email_level: Level,
+}
+
+/// Which failures a way of notifying the user is used for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    /// None of them.
+    Off,
+
+    /// Only errors, i.e. syncs that failed.
+    #[default]
+    Errors,
+
+    /// Errors and warnings, i.e. also syncs that only committed locally, as the remote couldn't be reached.
+    Warnings,
+}
+
+impl Level {
+    /// Whether failures of `severity` are notified about.
+    pub(crate) fn includes(&self, severity: Severity) -> bool {
+        match self {
+            Level::Off => false,
+            Level::Errors => severity == Severity::Error,
+            Level::Warnings => true,
+        }
+    }
+}
+
+impl Default for NotificationsConfig {
+    /// The default configuration for Dotbak.
+    fn default() -> Self {
+        NotificationsConfig {
+            desktop: Level::default(),
+            webhook: None,
+            webhook_level: Level::default(),
+            email: None,
+            email_level: Level::default(),
+        }
+    }
+}
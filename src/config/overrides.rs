@@ -0,0 +1,154 @@
+use super::SETTINGS;
+use crate::errors::{config::ConfigError, Result};
+
+/// The prefix of the environment variables overriding settings, e.g. `DOTBAK_DELAY_BETWEEN_SYNC`.
+pub const ENV_PREFIX: &str = "DOTBAK_";
+
+/// What separates the tables a setting is in from the setting in the names of environment variables, e.g.
+/// `DOTBAK_FILES__SPARSE` for `files.sparse`.
+pub const ENV_SEPARATOR: &str = "__";
+
+/// Where an override of a setting comes from. Later layers take precedence over earlier ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    /// A `DOTBAK_*` environment variable.
+    Env,
+
+    /// A command line flag.
+    Flag,
+}
+
+/// Settings overriding the ones in the configuration file, from environment variables and command line flags. The
+/// configuration is layered: flags take precedence over environment variables, which take precedence over the
+/// configuration file, which takes precedence over the defaults. Overridden settings are never saved to the
+/// configuration file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    /// The overridden settings (e.g. `files.include`), their values, and where they come from.
+    settings: Vec<(String, toml::Value, Layer)>,
+}
+
+impl Overrides {
+    /// Gets the overrides from the `DOTBAK_*` environment variables of this process, see `from_vars`.
+    pub fn from_env() -> Overrides {
+        Overrides::from_vars(std::env::vars())
+    }
+
+    /// Gets the overrides from the environment variables `vars`. Variables are named after the setting they
+    /// override, in capitals with `ENV_PREFIX` in front and `ENV_SEPARATOR` between tables, e.g.
+    /// `DOTBAK_REPOSITORY_URL` or `DOTBAK_GIT__BRANCH`. Other `DOTBAK_*` variables (e.g. `DOTBAK_HOME`) are
+    /// skipped, as they don't start with a setting. Values are read like `parse_value` does.
+    pub fn from_vars<I>(vars: I) -> Overrides
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut overrides = Overrides::default();
+
+        for (name, value) in vars {
+            let Some(name) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+
+            let key = name
+                .split(ENV_SEPARATOR)
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+
+            if !SETTINGS.contains(&key[0].as_str()) {
+                continue;
+            }
+
+            overrides.set(Layer::Env, key.join("."), parse_value(&value));
+        }
+
+        overrides
+    }
+
+    /// Overrides the setting `key` (e.g. `files.include`) with `value` in `layer`.
+    pub fn set<S>(&mut self, layer: Layer, key: S, value: toml::Value)
+    where
+        S: ToString,
+    {
+        self.settings.push((key.to_string(), value, layer));
+    }
+
+    /// Whether no settings are overridden.
+    pub fn is_empty(&self) -> bool {
+        self.settings.is_empty()
+    }
+
+    /// The overridden settings.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.settings.iter().map(|(key, _, _)| key.as_str())
+    }
+
+    /// Applies the overrides to `table`, the contents of a configuration file, in the order of their layers.
+    pub fn apply(&self, table: &mut toml::Table) -> Result<()> {
+        let mut settings = self.settings.iter().collect::<Vec<_>>();
+        settings.sort_by_key(|(_, _, layer)| *layer);
+
+        for (key, value, _) in settings {
+            set(table, key, Some(value.clone()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads `value` as a TOML value (e.g. `600`, `true` or `[".zshrc"]`), or as a string if it isn't one (e.g. a URL).
+pub fn parse_value(value: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", value))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| value.into())
+}
+
+/// Gets the setting `key` (e.g. `files.include`) from `table`, if it's set.
+pub fn get<'a>(table: &'a toml::Table, key: &str) -> Option<&'a toml::Value> {
+    let (parents, name) = split_key(key);
+    let mut table = table;
+
+    for part in parents {
+        table = table.get(part)?.as_table()?;
+    }
+
+    table.get(name)
+}
+
+/// Sets the setting `key` (e.g. `files.include`) in `table` to `value`, or removes it if `value` is `None`. Missing
+/// tables are created.
+pub fn set(table: &mut toml::Table, key: &str, value: Option<toml::Value>) -> Result<()> {
+    let unknown = || ConfigError::UnknownSetting {
+        key: key.to_string(),
+    };
+    let (parents, name) = split_key(key);
+
+    if name.is_empty() || parents.iter().any(|part| part.is_empty()) {
+        return Err(unknown().into());
+    }
+
+    let mut table = table;
+
+    for part in parents {
+        table = table
+            .entry(part)
+            .or_insert(toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(unknown)?;
+    }
+
+    match value {
+        Some(value) => table.insert(name.to_string(), value),
+        None => table.remove(name),
+    };
+
+    Ok(())
+}
+
+/// Helper function to split the setting `key` into the tables it's in and its name.
+fn split_key(key: &str) -> (Vec<&str>, &str) {
+    match key.rsplit_once('.') {
+        Some((parents, name)) => (parents.split('.').collect(), name),
+        None => (vec![], key),
+    }
+}
@@ -0,0 +1,29 @@
+use crate::packages::Manager;
+use serde::{Deserialize, Serialize};
+
+/// The configuration for exporting the installed packages to the repository on every sync, so that they can be
+/// installed again with `dotbak packages apply` on a new machine. The package managers are set per operating system,
+/// as each has its own. Nothing is exported by default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackagesConfig {
+    /// The package managers whose packages are exported on macOS, e.g. `["brew"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macos: Vec<Manager>,
+
+    /// The package managers whose packages are exported on Linux, e.g. `["pacman"]` or `["apt", "brew"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub linux: Vec<Manager>,
+}
+
+/// Public API for the configuration.
+impl PackagesConfig {
+    /// Gets the package managers for the operating system `os` (see `std::env::consts::OS`).
+    pub fn managers(&self, os: &str) -> &[Manager] {
+        match os {
+            "macos" => &self.macos,
+            "linux" => &self.linux,
+            _ => &[],
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The configuration for finding secrets (like access keys or private keys) in files before they're committed, so
+/// that they don't end up in a public repository by accident.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SecretsConfig {
+    /// Whether to look for secrets in the changed files before committing them in `dotbak add` and `dotbak sync`.
+    /// If any are found, nothing is committed. The default is `true`.
+    #[serde(default = "SecretsConfig::default_scan")]
+    pub scan: bool,
+
+    /// More regular expressions to find secrets with, by name (e.g. `company-token = "ct_[0-9a-f]{32}"`), next to
+    /// the built-in ones for AWS keys, tokens and private keys.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub patterns: BTreeMap<String, String>,
+
+    /// Glob patterns of the paths (relative to the home directory) that are never scanned, e.g. for false
+    /// positives. Folders allow everything inside of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+}
+
+impl Default for SecretsConfig {
+    /// The default configuration for Dotbak.
+    fn default() -> Self {
+        SecretsConfig {
+            scan: SecretsConfig::default_scan(),
+            patterns: BTreeMap::new(),
+            allow: vec![],
+        }
+    }
+}
+
+/// Private API for the configuration.
+impl SecretsConfig {
+    /// Returns the default for `scan`.
+    fn default_scan() -> bool {
+        true
+    }
+}
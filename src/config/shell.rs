@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The configuration for the shell integration that `dotbak shell-init` generates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShellConfig {
+    /// The path to the `dotbak` binary the shell helpers call, if not the one generating them. That one may be in a
+    /// folder that changes with every upgrade (e.g. the Nix store, or a build folder).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<PathBuf>,
+}
@@ -1,6 +1,15 @@
 #![cfg(test)]
 
 use super::*;
+use crate::{
+    config::{
+        daemon::SyncMode,
+        files::{Escalation, HardlinkPolicy, Root},
+        notifications::Level,
+    },
+    errors::DotbakError,
+    packages::Manager,
+};
 use assert_fs::{prelude::FileTouch, NamedTempFile, TempDir};
 
 /// Test if the default configuration can be loaded from a file that doesn't exist.
@@ -72,6 +81,34 @@ fn test_save_config_file_exists() {
             // The include and exclude fields are here to make sure we are not
             // loading an empty file down the line.
             include: vec!["test1".into(), "test2".into()],
+            exclude: vec!["test1/cache".into()],
+            hardlinks: HardlinkPolicy::BreakLinks,
+            track_locks: true,
+            diff_memory_limit: 1024,
+            add_size_limit: 0,
+            quarantine_after: 5,
+            sparse: true,
+            xattrs: true,
+            permanent_delete: true,
+            roots: vec![Root {
+                path: "/etc".into(),
+                target: None,
+                copies: false,
+                escalate: Escalation::Sudo,
+            }],
+        },
+        daemon: DaemonConfig {
+            mode: SyncMode::Both,
+            debounce: 500,
+            amend: true,
+            commits_per_day: 4,
+        },
+        notifications: NotificationsConfig {
+            desktop: Level::Warnings,
+            webhook: Some("https://example.com/hook".to_string()),
+            webhook_level: Level::Errors,
+            email: None,
+            email_level: Level::Off,
         },
         ..Default::default()
     };
@@ -140,4 +177,442 @@ fn test_create_config_file_exists() {
     Config::create_config(&config_path).unwrap();
 }
 
+/// Tests that `repo_path` is resolved relative to the home directory.
+#[test]
+fn test_repo_dir_resolution() {
+    let home = PathBuf::from("/home/user");
+    let mut config = Config::default();
+
+    assert_eq!(config.repo_dir(&home).unwrap(), None);
+
+    config.repo_path = Some("dotfiles".into());
+    assert_eq!(config.repo_dir(&home).unwrap(), Some(home.join("dotfiles")));
+
+    config.repo_path = Some("~/some/dotfiles".into());
+    assert_eq!(
+        config.repo_dir(&home).unwrap(),
+        Some(home.join("some/dotfiles"))
+    );
+
+    config.repo_path = Some("/opt/dotfiles".into());
+    assert_eq!(
+        config.repo_dir(&home).unwrap(),
+        Some(PathBuf::from("/opt/dotfiles"))
+    );
+}
+
+/// Tests that `repo_path` can't be inside of, or contain, a managed path.
+#[test]
+fn test_repo_dir_overlaps_managed() {
+    let home = PathBuf::from("/home/user");
+    let mut config = Config::default();
+    config.files.include = vec![".config".into(), "dotfiles/.zshrc".into()];
+
+    config.repo_path = Some(".config/dotfiles".into());
+    assert!(matches!(
+        config.repo_dir(&home),
+        Err(DotbakError::Config(ConfigError::RepoPathManaged { .. }))
+    ));
+
+    config.repo_path = Some("dotfiles".into());
+    assert!(matches!(
+        config.repo_dir(&home),
+        Err(DotbakError::Config(ConfigError::RepoPathManaged { .. }))
+    ));
+
+    config.repo_path = Some(".configs".into());
+    assert!(config.repo_dir(&home).is_ok());
+}
+
+/// Tests that reloading the configuration splits the changed settings into applied and pending ones.
+#[test]
+fn test_reload() {
+    let config_path = NamedTempFile::new("config.toml").unwrap();
+    FileTouch::touch(&config_path).unwrap();
+
+    let config = Config::load_config(&config_path).unwrap();
+    let (_, reload) = config.reload().unwrap();
+    assert!(reload.is_empty());
+
+    let mut changed = config.clone();
+    changed.files.include.push(".zshrc".into());
+    changed.delay_between_sync = 60;
+    changed.repo_path = Some("dotfiles".into());
+    changed.save_config().unwrap();
+
+    let (reloaded, reload) = config.reload().unwrap();
+    assert_eq!(reloaded, changed);
+    assert_eq!(reload.applied, vec!["delay_between_sync", "files"]);
+    assert_eq!(reload.pending, vec!["repo_path"]);
+}
+
+/// Tests that the package managers are set per operating system, and that unknown ones are rejected.
+#[test]
+fn test_packages() {
+    let packages: packages::PackagesConfig = toml::from_str(
+        r#"
+        macos = ["brew"]
+        linux = ["apt", "brew"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(packages.managers("macos"), [Manager::Brew]);
+    assert_eq!(packages.managers("linux"), [Manager::Apt, Manager::Brew]);
+    assert!(packages.managers("windows").is_empty());
+
+    assert!(toml::from_str::<packages::PackagesConfig>(r#"linux = ["yum"]"#).is_err());
+}
+
+/// Tests that remotes can be given as a URL or a table, and which of them are pushed to.
+#[test]
+fn test_git_remotes() {
+    let git: git::GitConfig = toml::from_str(
+        r#"
+        [remotes]
+        origin = { url = "https://example.com/dotfiles.git", enabled = false }
+        backup = "/mnt/backup/dotfiles.git"
+        old = { url = "https://example.com/old.git", enabled = false }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(git.remotes["backup"].url(), "/mnt/backup/dotfiles.git");
+    assert_eq!(git.push_remotes(), vec!["backup"]);
+    assert!(!git.pushes_to("origin"));
+    assert!(!git.pushes_to("upstream"));
+    assert!(git::GitConfig::default().pushes_to("origin"));
+}
+
+/// Tests that the credentials for HTTPS remotes come from `token_command`, with the configured username.
+#[test]
+fn test_git_credentials() {
+    let mut git = git::GitConfig::default();
+
+    assert!(git.credentials().unwrap().is_none());
+
+    git.token_command = Some("echo ' hunter2 '".to_string());
+    git.username = Some("me".to_string());
+
+    assert_eq!(git.credentials().unwrap().unwrap().username(), "me");
+
+    git.token_command = Some("exit 1".to_string());
+
+    assert!(git.credentials().is_err());
+}
+
+/// Tests that commits are only signed with `sign`, and that SSH keys are recognized without a format.
+#[test]
+fn test_git_signing() {
+    let mut git = git::GitConfig::default();
+
+    assert!(git.signing().is_none());
+
+    git.sign = true;
+    assert_eq!(git.signing().unwrap().format, None);
+
+    git.signing_key = Some("~/.ssh/id_ed25519.pub".to_string());
+    assert_eq!(git.signing().unwrap().format, Some(git::SigningFormat::Ssh));
+
+    git.signing_key = Some("ABCDEF0123456789".to_string());
+    assert_eq!(git.signing().unwrap().format, None);
+
+    git.signing_format = Some(git::SigningFormat::Openpgp);
+    assert_eq!(
+        git.signing().unwrap().format,
+        Some(git::SigningFormat::Openpgp)
+    );
+    assert_eq!(
+        git.signing().unwrap().key.as_deref(),
+        Some("ABCDEF0123456789")
+    );
+}
+
+/// Tests that unknown settings aren't silently ignored.
+#[test]
+fn test_unknown_settings() {
+    let err =
+        toml::from_str::<Config>("[files]\ninclude = []\nexcludes = [\".cache\"]\n").unwrap_err();
+
+    assert!(err.message().contains("unknown field `excludes`"));
+    assert!(
+        toml::from_str::<Config>("[git.remotes]\nbackup = { url = \"a\", enable = false }\n")
+            .is_err()
+    );
+}
+
+/// Tests that the problems deserializing can't find are found, for every entry they're with.
+#[test]
+fn test_validate() {
+    let home = PathBuf::from("/home/user");
+    let mut config = Config::default();
+    config.files.include = vec![
+        ".config/nvim".into(),
+        "/etc/hosts".into(),
+        ".config/nvim/init.lua".into(),
+        "~/.zshrc".into(),
+        ".config/nvim".into(),
+        ".cache/thumbnails".into(),
+    ];
+    config.files.exclude = vec![".config/nvim/plugin".into(), ".config".into()];
+    config.encryption.files = vec![".netrc".into()];
+    config.ignore.patterns = vec!["thumbnails/".to_string()];
+    config.files.roots = vec![
+        Root {
+            path: "/srv".into(),
+            target: None,
+            copies: true,
+            escalate: Escalation::Never,
+        },
+        Root {
+            path: "etc".into(),
+            target: None,
+            copies: true,
+            escalate: Escalation::Never,
+        },
+        Root {
+            path: "/srv/www".into(),
+            target: None,
+            copies: true,
+            escalate: Escalation::Never,
+        },
+        Root {
+            path: "/opt".into(),
+            target: Some("../opt".into()),
+            copies: true,
+            escalate: Escalation::Never,
+        },
+    ];
+    config.files.include.push("/srv/index.html".into());
+    config
+        .secrets
+        .patterns
+        .insert("broken".to_string(), "(".to_string());
+
+    let problems = config
+        .validate(&home)
+        .into_iter()
+        .map(|problem| (problem.setting, problem.index))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        problems,
+        vec![
+            ("files.roots".to_string(), Some(1)),
+            ("files.roots".to_string(), Some(2)),
+            ("files.roots".to_string(), Some(3)),
+            ("files.include".to_string(), Some(1)),
+            ("files.include".to_string(), Some(3)),
+            ("files.include".to_string(), Some(4)),
+            ("files.include".to_string(), Some(2)),
+            ("files.include".to_string(), Some(5)),
+            ("encryption.files".to_string(), Some(0)),
+            ("files.exclude".to_string(), Some(1)),
+            ("secrets.patterns.broken".to_string(), None),
+        ]
+    );
+    assert!(Config::default().validate(&home).is_empty());
+    assert!(matches!(
+        config.check_valid(&home),
+        Err(DotbakError::Config(ConfigError::Invalid { count: 11, .. }))
+    ));
+}
+
+/// Tests that sparse checkouts are rejected with the libgit2 backend, which would commit the files outside of them as
+/// deleted.
+#[test]
+fn test_validate_sparse_libgit2() {
+    let home = PathBuf::from("/home/user");
+    let mut config = Config::default();
+    config.files.sparse = true;
+    assert!(config.validate(&home).is_empty());
+
+    config.git.backend = git::Backend::Libgit2;
+    let problems = config.validate(&home);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].setting, "files.sparse");
+}
+
+/// Tests that git-lfs is rejected with the libgit2 backend, which would commit the files whole instead of as
+/// pointers.
+#[test]
+fn test_validate_lfs_libgit2() {
+    let home = PathBuf::from("/home/user");
+    let mut config = Config::default();
+    config.git.lfs.track = vec!["*.ttf".to_string()];
+    assert!(config.validate(&home).is_empty());
+
+    config.git.backend = git::Backend::Libgit2;
+    let problems = config.validate(&home);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].setting, "git.lfs.track");
+}
+
+/// Tests that the problems in a configuration file are found with the line and column they're on.
+#[test]
+fn test_check() {
+    let home = PathBuf::from("/home/user");
+    let contents = "repository_url = \"https://example.com/dotfiles.git\"\n\n\
+                    [files]\n\
+                    include = [\".zshrc\", \"../.bashrc\"]\n\n\
+                    [encryption]\n\
+                    files = [\".netrc\"]\n";
+
+    let problems = check::check(contents, &home);
+
+    assert_eq!(
+        problems
+            .iter()
+            .map(|problem| (problem.setting.as_str(), problem.location))
+            .collect::<Vec<_>>(),
+        vec![
+            ("files.include", Some((4, 22))),
+            ("encryption.files", Some((7, 10)))
+        ]
+    );
+
+    let problems = check::check("[files]\nincluded = []\n", &home);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].location, Some((2, 1)));
+    assert!(problems[0].message.contains("unknown field `included`"));
+
+    let problems = check::check("[files\n", &home);
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].location.map(|(line, _)| line), Some(1));
+}
+
+/// Tests that settings can be read (with their defaults) and changed, keeping the rest of the file as-is.
+#[test]
+fn test_get_set() {
+    let contents =
+        "# Sync often.\ndelay_between_sync = 60\n\n[files]\ninclude = [\".zshrc\"] # Just this.\n";
+    let config = Config {
+        files: FilesConfig {
+            include: vec![".zshrc".into()],
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert_eq!(
+        edit::get(&config, "files.include").unwrap(),
+        toml::Value::Array(vec![".zshrc".into()])
+    );
+    assert_eq!(
+        edit::get(&config, "files.sparse").unwrap(),
+        toml::Value::Boolean(false)
+    );
+    assert!(matches!(
+        edit::get(&config, "files.included"),
+        Err(DotbakError::Config(ConfigError::UnknownSetting { .. }))
+    ));
+
+    let contents = edit::set(contents, "delay_between_sync", "600").unwrap();
+    let contents = edit::set(
+        &contents,
+        "repository_url",
+        "git@example.com:me/dotfiles.git",
+    )
+    .unwrap();
+    let contents = edit::set(&contents, "git.username", "me").unwrap();
+
+    assert!(contents.contains("# Sync often.\ndelay_between_sync = 600\n"));
+    assert!(contents.contains("include = [\".zshrc\"] # Just this.\n"));
+
+    let config: Config = toml::from_str(&contents).unwrap();
+    assert_eq!(config.delay_between_sync, 600);
+    assert_eq!(
+        config.repository_url.as_deref(),
+        Some("git@example.com:me/dotfiles.git")
+    );
+    assert_eq!(config.git.username.as_deref(), Some("me"));
+
+    assert!(matches!(
+        edit::set(&contents, "delay_between_sync.seconds", "1"),
+        Err(DotbakError::Config(ConfigError::UnknownSetting { .. }))
+    ));
+}
+
+/// Tests that flags take precedence over environment variables, which take precedence over the file, and that
+/// overridden settings aren't saved.
+#[test]
+fn test_overrides() {
+    let config_path = NamedTempFile::new("config.toml").unwrap();
+    fs::write(
+        &config_path,
+        "delay_between_sync = 60\n\n[files]\ninclude = [\".zshrc\"]\n",
+    )
+    .unwrap();
+
+    let mut overrides = overrides::Overrides::from_vars([
+        ("DOTBAK_DELAY_BETWEEN_SYNC".to_string(), "120".to_string()),
+        ("DOTBAK_FILES__SPARSE".to_string(), "true".to_string()),
+        ("DOTBAK_GIT__BRANCH".to_string(), "laptop".to_string()),
+        ("DOTBAK_HOME".to_string(), "/home/user".to_string()),
+        ("DOTBAK_GIT_TOKEN".to_string(), "hunter2".to_string()),
+    ]);
+    overrides.set(overrides::Layer::Flag, "git.branch", "desktop".into());
+    assert_eq!(
+        overrides.keys().collect::<Vec<_>>(),
+        vec![
+            "delay_between_sync",
+            "files.sparse",
+            "git.branch",
+            "git.branch"
+        ]
+    );
+
+    let mut config = Config::load_layered(&config_path, &overrides).unwrap();
+    assert_eq!(config.delay_between_sync, 120);
+    assert!(config.files.sparse);
+    assert_eq!(config.files.include, vec![PathBuf::from(".zshrc")]);
+    assert_eq!(config.git.branch(), "desktop");
+
+    config.files.include.push(".bashrc".into());
+    config.save_config().unwrap();
+
+    let saved = Config::load_config(&config_path).unwrap();
+    assert_eq!(saved.delay_between_sync, 60);
+    assert!(!saved.files.sparse);
+    assert_eq!(saved.git.branch(), git::GitConfig::default().branch());
+    assert_eq!(
+        saved.files.include,
+        vec![PathBuf::from(".zshrc"), PathBuf::from(".bashrc")]
+    );
+}
+
+/// Tests that configuration files in older formats are upgraded when they're loaded, keeping their comments, and
+/// that ones from newer versions aren't loaded.
+#[test]
+fn test_migrate() {
+    let dir = TempDir::new().unwrap();
+    let config_path = dir.path().join("config.toml");
+    fs::write(
+        &config_path,
+        "# Sync often.\ndelay_between_sync = 60\ninclude = [\".zshrc\", \".vimrc\"]\n\n[files]\ninclude = [\".vimrc\"]\n",
+    )
+    .unwrap();
+
+    let config = Config::load_config(&config_path).unwrap();
+    assert_eq!(config.version, migrations::CURRENT_VERSION);
+    assert_eq!(
+        config.files.include,
+        vec![PathBuf::from(".vimrc"), PathBuf::from(".zshrc")]
+    );
+
+    let upgraded = fs::read_to_string(&config_path).unwrap();
+    assert!(upgraded.starts_with("# Sync often.\ndelay_between_sync = 60\n"));
+    assert!(upgraded.contains(&format!("version = {}", migrations::CURRENT_VERSION)));
+    assert_eq!(migrations::migrate(&upgraded).unwrap(), None);
+
+    fs::write(&config_path, "version = 1000\n").unwrap();
+    assert!(matches!(
+        Config::load_config(&config_path),
+        Err(DotbakError::Config(ConfigError::UnsupportedVersion {
+            version: 1000,
+            ..
+        }))
+    ));
+}
+
 // TODO: test loading config from a file that already exists.
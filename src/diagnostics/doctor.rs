@@ -0,0 +1,417 @@
+use super::Report;
+use crate::{
+    config::Config,
+    dotbak::{self, daemon::Daemon},
+    files::{self, Files, LinkState},
+    git::{self, Repository},
+    platform,
+};
+use miette::Diagnostic;
+use std::{fs, path::Path};
+
+/// The oldest git version `dotbak` works with, as `(major, minor)`. Passing configuration to git in the environment
+/// (which credentials are passed with) needs git 2.31.
+pub const MIN_GIT_VERSION: (u32, u32) = (2, 31);
+
+/// What a check of `dotbak doctor` is about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Git is installed, and is new enough.
+    Git,
+
+    /// The configuration file can be loaded.
+    Config,
+
+    /// The remote can be reached.
+    Remote,
+
+    /// The symlinks in the home directory are intact.
+    Symlinks,
+
+    /// Every file in the repository is managed.
+    Orphans,
+
+    /// The permissions of the configuration folder and the key are safe.
+    Permissions,
+
+    /// The daemon's PID file belongs to a running daemon.
+    Daemon,
+}
+
+/// How serious a finding of `dotbak doctor` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Everything is fine.
+    Ok,
+
+    /// Something is off, but `dotbak` still works.
+    Warning,
+
+    /// Something keeps `dotbak` from working.
+    Error,
+}
+
+/// A finding of `dotbak doctor`: what was checked, what was found, and how to fix it if it's a problem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    /// What was checked.
+    pub check: Check,
+
+    /// How serious the finding is.
+    pub severity: Severity,
+
+    /// What was found.
+    pub message: String,
+
+    /// How to fix the problem, if it is one.
+    pub fix: Option<String>,
+}
+
+impl Check {
+    /// The name of the check, for humans.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Git => "git",
+            Check::Config => "configuration",
+            Check::Remote => "remote",
+            Check::Symlinks => "symlinks",
+            Check::Orphans => "repository",
+            Check::Permissions => "permissions",
+            Check::Daemon => "daemon",
+        }
+    }
+}
+
+impl Finding {
+    /// Creates a finding that everything is fine.
+    fn ok<S>(check: Check, message: S) -> Finding
+    where
+        S: ToString,
+    {
+        Finding {
+            check,
+            severity: Severity::Ok,
+            message: message.to_string(),
+            fix: None,
+        }
+    }
+
+    /// Creates a finding of a problem with `severity`, which is fixed by `fix`.
+    fn problem<S1, S2>(check: Check, severity: Severity, message: S1, fix: S2) -> Finding
+    where
+        S1: ToString,
+        S2: ToString,
+    {
+        Finding {
+            check,
+            severity,
+            message: message.to_string(),
+            fix: Some(fix.to_string()),
+        }
+    }
+}
+
+/// Checks the installation of `dotbak` for the home directory `home`, configuration file `config_path` and default
+/// repository path `repo_path`, building on the `Report` of `dotbak env`. The remote is only checked if
+/// `check_remote` is set, as it needs the network. Like the report, this doesn't fail, as it should work especially
+/// when `dotbak` doesn't.
+pub fn examine<P1, P2, P3>(
+    home: P1,
+    config_path: P2,
+    repo_path: P3,
+    check_remote: bool,
+) -> Vec<Finding>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let (home, config_path) = (home.as_ref(), config_path.as_ref());
+    let report = Report::collect(home, config_path, repo_path);
+    let mut findings = vec![check_git(&report)];
+
+    let config = match Config::load_config(config_path) {
+        Ok(config) => {
+            findings.push(Finding::ok(
+                Check::Config,
+                format!("'{}' is valid", config_path.display()),
+            ));
+            Some(config)
+        }
+        Err(err) => {
+            findings.push(Finding::problem(
+                Check::Config,
+                Severity::Error,
+                format!("'{}' can't be loaded: {}", config_path.display(), err),
+                "Fix the configuration file, or run `dotbak init` to create one.",
+            ));
+            None
+        }
+    };
+
+    if let Some(config) = &config {
+        if check_remote {
+            findings.push(check_remote_reachable(&report, config));
+        }
+
+        findings.extend(check_symlinks(home, &report.repo_path, config));
+        findings.extend(check_orphans(&report.repo_path, config));
+        findings.extend(check_permissions(home, config_path, config));
+    }
+
+    findings.push(check_daemon(&report));
+
+    findings
+}
+
+/// Parses the output of `git --version` (e.g. `git version 2.39.5 (Apple Git-154)`) into `(major, minor)`.
+pub fn parse_git_version(version: &str) -> Option<(u32, u32)> {
+    let mut numbers = version
+        .strip_prefix("git version ")?
+        .split(|c: char| !c.is_ascii_digit());
+
+    Some((numbers.next()?.parse().ok()?, numbers.next()?.parse().ok()?))
+}
+
+/// Checks that git is installed and new enough.
+fn check_git(report: &Report) -> Finding {
+    let version = match &report.git_version {
+        Ok(version) => version,
+        Err(err) => {
+            return Finding::problem(
+                Check::Git,
+                Severity::Error,
+                format!("git can't be run: {}", err),
+                "Install git, and make sure it's on your PATH.",
+            )
+        }
+    };
+
+    match parse_git_version(version) {
+        Some(parsed) if parsed < MIN_GIT_VERSION => Finding::problem(
+            Check::Git,
+            Severity::Error,
+            format!("{} is too old", version),
+            format!(
+                "Update git to version {}.{} or newer.",
+                MIN_GIT_VERSION.0, MIN_GIT_VERSION.1
+            ),
+        ),
+        _ => Finding::ok(Check::Git, version),
+    }
+}
+
+/// Checks that the remote can be reached, with the configured credentials.
+fn check_remote_reachable(report: &Report, config: &Config) -> Finding {
+    let Some(url) = &report.remote_url else {
+        return Finding::ok(Check::Remote, "no remote is set");
+    };
+
+    let reached = Repository::load(&report.repo_path).and_then(|mut repo| {
+        repo.set_credentials(config.git.credentials().ok().flatten());
+        repo.arbitrary_command(&["ls-remote", "--heads", git::REMOTE_NAME])
+    });
+
+    match reached {
+        Ok(_) => Finding::ok(Check::Remote, format!("'{}' can be reached", url)),
+        Err(err) => {
+            let fix = err.help().map(|help| help.to_string()).unwrap_or_else(|| {
+                "Check your network connection, and that the remote's URL (`repository_url`) is right.".to_string()
+            });
+
+            Finding::problem(
+                Check::Remote,
+                Severity::Error,
+                format!("'{}' can't be reached", url),
+                fix,
+            )
+        }
+    }
+}
+
+/// Checks the symlinks of the entries of `files.include`, like `dotbak status` does.
+fn check_symlinks(home: &Path, repo_path: &Path, config: &Config) -> Vec<Finding> {
+    let mut files = Files::init(home.to_path_buf(), repo_path.to_path_buf());
+
+    if let Err(err) = dotbak::configure_files(&mut files, config) {
+        return vec![Finding::problem(
+            Check::Symlinks,
+            Severity::Error,
+            format!("the managed files can't be checked: {}", err),
+            "Make sure the encryption key (`encryption.key`) is a valid age key.",
+        )];
+    }
+
+    let states = match super::link_states(&files, &config.files.include) {
+        Ok(states) => states,
+        Err(err) => {
+            return vec![Finding::problem(
+                Check::Symlinks,
+                Severity::Error,
+                format!("the managed files can't be checked: {}", err),
+                "Fix the pattern in `files.include`, see `dotbak config check`.",
+            )]
+        }
+    };
+
+    let findings = states
+        .iter()
+        .filter_map(|(file, state)| {
+            let (severity, fix) = match state {
+                LinkState::Intact => return None,
+                LinkState::Broken => (
+                    Severity::Error,
+                    format!(
+                        "Restore it in the repository, or stop managing it with `dotbak remove {}`.",
+                        file.display()
+                    ),
+                ),
+                LinkState::Missing => (Severity::Warning, "Run `dotbak sync` to link it.".to_string()),
+                LinkState::Shadowed => (
+                    Severity::Warning,
+                    "Run `dotbak repair` to move the file out of the way and link it again.".to_string(),
+                ),
+            };
+
+            Some(Finding::problem(
+                Check::Symlinks,
+                severity,
+                format!("'{}' is {}", file.display(), state.describe()),
+                fix,
+            ))
+        })
+        .collect::<Vec<_>>();
+
+    match findings.is_empty() {
+        true => vec![Finding::ok(
+            Check::Symlinks,
+            format!("{} managed path(s) are linked", config.files.include.len()),
+        )],
+        false => findings,
+    }
+}
+
+/// Checks for files in the repository that no entry of `files.include` covers, e.g. left behind by editing the
+/// configuration file by hand. They're never linked into the home directory.
+fn check_orphans(repo_path: &Path, config: &Config) -> Vec<Finding> {
+    let Ok(tracked) = Repository::load(repo_path).and_then(|mut repo| repo.tracked_files()) else {
+        return vec![];
+    };
+
+    let findings = files::orphans(&tracked, &config.files.include, &config.files.roots)
+        .iter()
+        .map(|path| {
+            Finding::problem(
+                Check::Orphans,
+                Severity::Warning,
+                format!(
+                    "'{}' is in the repository, but isn't managed",
+                    path.display()
+                ),
+                "Move the orphaned files back into your home directory with `dotbak prune --restore`, or delete \
+                 them with `dotbak prune --delete`.",
+            )
+        })
+        .collect::<Vec<_>>();
+
+    match findings.is_empty() {
+        true => vec![Finding::ok(
+            Check::Orphans,
+            "every file in the repository is managed",
+        )],
+        false => findings,
+    }
+}
+
+/// Checks that other users can't change the configuration folder (and with it, what `dotbak` does), and can't read
+/// the encryption key.
+fn check_permissions(home: &Path, config_path: &Path, config: &Config) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    if let Some(dir) = config_path.parent() {
+        if let Some(mode) = permission_bits(dir) {
+            if mode & 0o022 != 0 {
+                findings.push(Finding::problem(
+                    Check::Permissions,
+                    Severity::Warning,
+                    format!("'{}' can be changed by other users", dir.display()),
+                    format!("Run `chmod go-w {}`.", dir.display()),
+                ));
+            }
+        }
+    }
+
+    let key = dotbak::key_path(config, home);
+
+    if let Some(mode) = permission_bits(&key) {
+        if mode & 0o077 != 0 {
+            findings.push(Finding::problem(
+                Check::Permissions,
+                Severity::Error,
+                format!(
+                    "the encryption key '{}' can be read by other users",
+                    key.display()
+                ),
+                format!("Run `chmod 600 {}`.", key.display()),
+            ));
+        }
+    }
+
+    match findings.is_empty() {
+        true => vec![Finding::ok(
+            Check::Permissions,
+            "only you can change the configuration",
+        )],
+        false => findings,
+    }
+}
+
+/// Checks that the daemon's PID file (if there is one) belongs to a running daemon. A stale PID file is left behind
+/// when the daemon is killed, e.g. by a crash.
+fn check_daemon(report: &Report) -> Finding {
+    let pid_file = Daemon::pid_file();
+    let Ok(pid) = fs::read_to_string(&pid_file) else {
+        return Finding::ok(Check::Daemon, "the daemon isn't running");
+    };
+
+    match (pid.trim().parse::<u32>(), report.daemon) {
+        (Ok(_), Some(pid)) => Finding::ok(
+            Check::Daemon,
+            format!("the daemon is running (pid {})", pid),
+        ),
+        (Ok(pid), None) => Finding::problem(
+            Check::Daemon,
+            Severity::Warning,
+            format!(
+                "the PID file '{}' is stale, pid {} isn't running",
+                pid_file.display(),
+                pid
+            ),
+            stale_pid_fix(&pid_file),
+        ),
+        (Err(_), _) => Finding::problem(
+            Check::Daemon,
+            Severity::Error,
+            format!(
+                "the PID file '{}' doesn't contain a PID",
+                pid_file.display()
+            ),
+            stale_pid_fix(&pid_file),
+        ),
+    }
+}
+
+/// Helper function to describe how to fix a PID file that doesn't belong to a running daemon.
+fn stale_pid_fix(pid_file: &Path) -> String {
+    format!(
+        "Delete '{}', and run `dotbak start-daemon` if you want the daemon to run.",
+        pid_file.display()
+    )
+}
+
+/// Helper function to get the Unix permission bits of `path`, or `None` if it doesn't exist (or on Windows).
+fn permission_bits(path: &Path) -> Option<u32> {
+    fs::metadata(path)
+        .ok()
+        .and_then(|meta| platform::permission_bits(&meta))
+}
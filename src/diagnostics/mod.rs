@@ -0,0 +1,293 @@
+pub mod doctor;
+mod tests;
+
+use crate::{
+    config::{git::RemoteConfig, Config},
+    dotbak::{daemon::Daemon, quarantine::Quarantine},
+    errors,
+    files::{Files, LinkState},
+    git::{self, Repository},
+    lock::Lock,
+    platform,
+    xdg::BaseDirs,
+};
+use std::{
+    env,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// What is put in place of secrets in the report.
+const REDACTED: &str = "<redacted>";
+
+/// The name of the extended attribute used to check if extended attributes are supported.
+const XATTR_PROBE: &str = "user.dotbak.probe";
+
+/// A dump of the runtime environment of `dotbak`, for pasting into bug reports. Secrets are redacted.
+#[derive(Debug)]
+pub struct Report {
+    /// The home directory.
+    pub home: PathBuf,
+
+    /// The path to the configuration file.
+    pub config_path: PathBuf,
+
+    /// The path to the repository, after applying `repo_path` from the configuration.
+    pub repo_path: PathBuf,
+
+    /// The effective configuration as TOML, or the error from loading it.
+    pub config: Result<String, String>,
+
+    /// The path to the git binary, if it's on the `PATH`.
+    pub git_path: Option<PathBuf>,
+
+    /// The output of `git --version`, or the error from running it.
+    pub git_version: Result<String, String>,
+
+    /// The URL of the repository's remote, if there is one.
+    pub remote_url: Option<String>,
+
+    /// Whether symlinks can be created next to the repository.
+    pub symlinks: bool,
+
+    /// Whether extended attributes can be set next to the repository.
+    pub xattrs: bool,
+
+    /// The PID of the running daemon, if there is one.
+    pub daemon: Option<u32>,
+
+    /// Whether the repository's index is locked by a git process.
+    pub repo_locked: bool,
+
+    /// Whether another `dotbak` process holds the lock on the state, i.e. is changing it right now.
+    pub state_locked: bool,
+
+    /// The managed paths that are quarantined, i.e. skipped when syncing.
+    pub quarantined: Vec<PathBuf>,
+}
+
+impl Report {
+    /// Collects the report for the home directory `home`, configuration file `config_path` and default
+    /// repository path `repo_path`. This doesn't fail, as it should work especially when `dotbak` doesn't.
+    pub fn collect<P1, P2, P3>(home: P1, config_path: P2, repo_path: P3) -> Self
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let home = home.as_ref().to_path_buf();
+        let config = Config::load_config(&config_path);
+        let repo_path = config
+            .as_ref()
+            .ok()
+            .and_then(|config| config.repo_dir(&home).ok().flatten())
+            .unwrap_or_else(|| repo_path.as_ref().to_path_buf());
+
+        let config = config.map_err(|err| err.to_string()).and_then(|config| {
+            toml::to_string_pretty(&redact_config(config)).map_err(|err| err.to_string())
+        });
+
+        let remote_url = Repository::load(&repo_path)
+            .and_then(|mut repo| repo.arbitrary_command(&["remote", "get-url", git::REMOTE_NAME]))
+            .ok()
+            .map(|output| redact_url(String::from_utf8_lossy(&output.stdout).trim()));
+
+        // Probe the filesystem the repository is on, as that's where the symlinks point to.
+        let probe_dir = repo_path
+            .ancestors()
+            .find(|dir| dir.is_dir())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(env::temp_dir);
+
+        let state_dir = BaseDirs::new(&home).state;
+        let quarantined = Quarantine::load(&state_dir)
+            .map(|quarantine| {
+                quarantine
+                    .quarantined()
+                    .map(|entry| entry.path.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            config_path: config_path.as_ref().to_path_buf(),
+            repo_locked: repo_path.join(".git/index.lock").exists(),
+            state_locked: Lock::is_held(&state_dir),
+            git_path: find_in_path("git"),
+            git_version: git::version().map_err(|err| err.to_string()),
+            symlinks: supports_symlinks(&probe_dir),
+            xattrs: supports_xattrs(&probe_dir),
+            daemon: Daemon::running(),
+            home,
+            repo_path,
+            config,
+            remote_url,
+            quarantined,
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let supported = |yes| if yes { "supported" } else { "unsupported" };
+
+        writeln!(f, "dotbak {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(f, "platform: {} {}", env::consts::OS, env::consts::ARCH)?;
+
+        writeln!(f, "\n[directories]")?;
+        writeln!(f, "home: {}", self.home.display())?;
+        writeln!(f, "config: {}", describe_path(&self.config_path))?;
+        writeln!(f, "repository: {}", describe_path(&self.repo_path))?;
+
+        writeln!(f, "\n[git]")?;
+        match &self.git_path {
+            Some(path) => writeln!(f, "binary: {}", path.display())?,
+            None => writeln!(f, "binary: not found on PATH")?,
+        }
+        match &self.git_version {
+            Ok(version) => writeln!(f, "version: {}", version)?,
+            Err(err) => writeln!(f, "version: unknown ({})", err)?,
+        }
+        writeln!(
+            f,
+            "remote: {}",
+            self.remote_url.as_deref().unwrap_or("none")
+        )?;
+
+        writeln!(f, "\n[capabilities]")?;
+        writeln!(f, "symlinks: {}", supported(self.symlinks))?;
+        writeln!(f, "extended attributes: {}", supported(self.xattrs))?;
+
+        writeln!(f, "\n[state]")?;
+        match self.daemon {
+            Some(pid) => writeln!(f, "daemon: running (pid {})", pid)?,
+            None => writeln!(f, "daemon: not running")?,
+        }
+        match self.repo_locked {
+            true => writeln!(f, "repository lock: held (.git/index.lock exists)")?,
+            false => writeln!(f, "repository lock: free")?,
+        }
+        match self.state_locked {
+            true => writeln!(f, "dotbak lock: held (another dotbak command is running)")?,
+            false => writeln!(f, "dotbak lock: free")?,
+        }
+        match self.quarantined.is_empty() {
+            true => writeln!(f, "quarantined: none")?,
+            false => writeln!(
+                f,
+                "quarantined: {}",
+                self.quarantined
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?,
+        }
+
+        writeln!(f, "\n[config]")?;
+        match &self.config {
+            Ok(config) => write!(f, "{}", config),
+            Err(err) => writeln!(f, "could not be loaded: {}", err),
+        }
+    }
+}
+
+/// Gets the state of the symlink in the home directory of each path managed for the entries `include` of
+/// `files.include`: glob patterns are resolved into the paths they match (see `Files::resolve_globs`), and folders
+/// with excluded paths inside of them are split into their children (see `Files::expand_excluded`). This is what
+/// both `dotbak status` and `dotbak doctor` show.
+pub fn link_states(
+    files: &Files,
+    include: &[PathBuf],
+) -> errors::Result<Vec<(PathBuf, LinkState)>> {
+    let include = files.resolve_globs(include)?;
+
+    Ok(files
+        .expand_excluded(&include)
+        .into_iter()
+        .map(|file| {
+            let state = files.link_state(&file);
+            (file, state)
+        })
+        .collect())
+}
+
+/// Removes the secrets from `config`: the credentials in the repository URL and the URLs of `git.remotes`, and the
+/// whole webhook URL, which is a secret itself.
+fn redact_config(mut config: Config) -> Config {
+    config.repository_url = config.repository_url.map(|url| redact_url(&url));
+    config.notifications.webhook = config.notifications.webhook.map(|_| REDACTED.to_string());
+
+    for remote in config.git.remotes.values_mut() {
+        match remote {
+            RemoteConfig::Url(url) | RemoteConfig::Table { url, .. } => *url = redact_url(url),
+        }
+    }
+
+    config
+}
+
+/// Replaces the credentials in `url` with `REDACTED`: anything before the `@` of HTTP(S) URLs (like `user:password@`
+/// or a token on its own), and `user:password@` of other URLs. URLs without credentials, or SSH URLs with just a
+/// user name (like `git@github.com:...`), are kept as-is.
+pub fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+
+    // The credentials end at the last `@` before the path. Over HTTP(S), a user name on its own is often a token.
+    let authority = rest.split('/').next().unwrap_or(rest);
+    let http = scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https");
+
+    match authority.rsplit_once('@') {
+        Some((credentials, _)) if http || credentials.contains(':') => format!(
+            "{}://{}@{}",
+            scheme,
+            REDACTED,
+            &rest[credentials.len() + 1..]
+        ),
+        _ => url.to_string(),
+    }
+}
+
+/// Describes `path`, and whether it exists.
+fn describe_path(path: &Path) -> String {
+    match path.exists() {
+        true => path.display().to_string(),
+        false => format!("{} (missing)", path.display()),
+    }
+}
+
+/// Finds the executable `name` on the `PATH`.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(format!("{}{}", name, env::consts::EXE_SUFFIX)))
+        .find(|path| {
+            fs::metadata(path)
+                .map(|meta| platform::is_executable(&meta))
+                .unwrap_or(false)
+        })
+}
+
+/// Checks if symlinks (to files, too) can be created in `dir`.
+fn supports_symlinks(dir: &Path) -> bool {
+    let link = dir.join(format!(".dotbak-probe-{}", std::process::id()));
+    let supported = platform::symlink(dir, &link).is_ok();
+
+    let _ = platform::remove_link(&link);
+
+    supported && platform::can_symlink_files(dir)
+}
+
+/// Checks if extended attributes can be set on files in `dir`.
+fn supports_xattrs(dir: &Path) -> bool {
+    let file = dir.join(format!(".dotbak-probe-{}", std::process::id()));
+    let supported = xattr::SUPPORTED_PLATFORM
+        && fs::write(&file, "").is_ok()
+        && xattr::set(&file, XATTR_PROBE, b"1").is_ok();
+
+    let _ = fs::remove_file(&file);
+
+    supported
+}
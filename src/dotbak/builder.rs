@@ -0,0 +1,571 @@
+use super::{
+    configure_files, managed::ManagedFiles, quarantine::QUARANTINE_FILE_NAME, Dotbak,
+    CONFIG_FILE_NAME, REPO_FOLDER_NAME,
+};
+use crate::{
+    config::{
+        check::{self, Problem},
+        edit, migrations,
+        overrides::Overrides,
+        Config,
+    },
+    diagnostics::{
+        doctor::{self, Finding},
+        Report,
+    },
+    errors::{config::ConfigError, io::IoError, DotbakError, Result},
+    files::{metadata, Files},
+    git::Repository,
+    lock::{self, Lock, LockMode},
+    ui::{messages::MAX_MSG_LEN, Interface, Verbosity},
+    xdg::{BaseDirs, LEGACY_FOLDER_NAME},
+};
+use age::secrecy::SecretString;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable overriding the home directory, for when there is none (e.g. in containers) or another
+/// one should be managed.
+pub const HOME_VAR: &str = "DOTBAK_HOME";
+
+/// The extension of the copy of the configuration file that `dotbak config edit` edits, e.g. `config.edit.toml`.
+const EDIT_EXTENSION: &str = "edit.toml";
+
+/// Sets up or loads `dotbak`, in the user's home directory or in custom directories. By default, the home directory
+/// is `DOTBAK_HOME` if it's set, or the user's otherwise, and the folders follow the XDG base directories (see
+/// `BaseDirs`): the configuration file is `~/.config/dotbak/config.toml`, and the repository is
+/// `~/.local/share/dotbak/dotfiles` (unless `repo_path` is set in the configuration).
+#[derive(Debug, Default)]
+pub struct DotbakBuilder {
+    /// The home directory, whose files are managed.
+    home_dir: Option<PathBuf>,
+
+    /// The path to the configuration file.
+    config_path: Option<PathBuf>,
+
+    /// The path to the repository.
+    repo_dir: Option<PathBuf>,
+
+    /// How much to tell about what's being done on the terminal.
+    verbosity: Verbosity,
+
+    /// Whether to save bandwidth, see `network.low_bandwidth` in the configuration.
+    low_bandwidth: bool,
+
+    /// Whether to run non-interactively, even if the terminal is interactive. See `Interface::set_non_interactive`.
+    non_interactive: bool,
+
+    /// Whether to overwrite real files in the home directory that differ from the tracked version. See
+    /// `Dotbak::force`.
+    force: bool,
+
+    /// The settings overriding the ones in the configuration file.
+    overrides: Overrides,
+}
+
+impl DotbakBuilder {
+    /// Creates a builder with the default directories.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the home directory, whose files are managed. The default configuration file and repository are inside
+    /// of it.
+    pub fn home_dir<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.home_dir = Some(path.into());
+        self
+    }
+
+    /// Sets the path to the configuration file.
+    pub fn config_path<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.config_path = Some(path.into());
+        self
+    }
+
+    /// Sets the path to the repository. `repo_path` in the configuration takes precedence over this.
+    pub fn repo_dir<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.repo_dir = Some(path.into());
+        self
+    }
+
+    /// Sets how much to tell about what's being done on the terminal, e.g. printing the output of git commands.
+    pub fn verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Turns on low bandwidth mode, even if it is not turned on in the configuration.
+    pub fn low_bandwidth(mut self, low_bandwidth: bool) -> Self {
+        self.low_bandwidth = low_bandwidth;
+        self
+    }
+
+    /// Runs non-interactively, even if stdout is a terminal and `CI` isn't set: prompts are answered with their
+    /// default, and spinners are printed as plain lines. See `Interface::set_non_interactive`.
+    pub fn non_interactive(mut self, non_interactive: bool) -> Self {
+        self.non_interactive = non_interactive;
+        self
+    }
+
+    /// Overwrites the real files in the home directory that differ from the tracked version when syncing, instead of
+    /// asking what to do with them. See `Dotbak::force`.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Sets the settings overriding the ones in the configuration file, e.g. from environment variables and flags.
+    pub fn overrides(mut self, overrides: Overrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Resolves the home directory, the path to the configuration file, and the path to the repository. Returns an
+    /// error if no home directory was set and the user's can't be found.
+    pub fn dirs(&self) -> Result<(PathBuf, PathBuf, PathBuf)> {
+        let home = self.home()?;
+        let base = BaseDirs::new(&home);
+
+        // Until the legacy folder is moved (see `migrate_legacy`), `dotbak` keeps working in it.
+        let (config_dir, repo_dir) = match base.legacy(&home, CONFIG_FILE_NAME) {
+            Some(legacy) if self.config_path.is_none() => {
+                (legacy.clone(), legacy.join(REPO_FOLDER_NAME))
+            }
+            _ => (base.config, base.data.join(REPO_FOLDER_NAME)),
+        };
+
+        let config = self
+            .config_path
+            .clone()
+            .unwrap_or_else(|| config_dir.join(CONFIG_FILE_NAME));
+        let repo = self.repo_dir.clone().unwrap_or(repo_dir);
+
+        Ok((home, config, repo))
+    }
+
+    /// Resolves the folder that `dotbak` keeps its state in, like the lock. See `BaseDirs::state`.
+    pub fn state_dir(&self) -> Result<PathBuf> {
+        Ok(BaseDirs::new(self.home()?).state)
+    }
+
+    /// Summarizes the unpushed commits and broken symlinks for a shell prompt (see `ManagedFiles::prompt`). This only
+    /// reads the state folder, so that it's fast enough to run every time the prompt is drawn.
+    pub fn prompt(&self) -> Result<String> {
+        Ok(ManagedFiles::load(self.state_dir()?)?.prompt(self.home()?))
+    }
+
+    /// Moves the files of a legacy setup in `~/.dotbak` (see `BaseDirs::legacy`) to the XDG base directories, unless
+    /// the configuration file was set. The repository is moved to the data folder (unless it was set, or
+    /// `repo_path` is) and the managed files are linked to it again, the state files are moved to the state folder,
+    /// and everything else (like the configuration file and the encryption key) to the configuration folder. If the
+    /// configuration file is managed, it's moved in the repository, too. Returns whether anything was moved.
+    pub fn migrate_legacy(&self) -> Result<bool> {
+        let home = self.home()?;
+        let base = BaseDirs::new(&home);
+        let legacy = match base.legacy(&home, CONFIG_FILE_NAME) {
+            Some(legacy) if self.config_path.is_none() => legacy,
+            _ => return Ok(false),
+        };
+
+        let mut config = Config::load_config(legacy.join(CONFIG_FILE_NAME))?;
+        let moves_repo = config.repo_path.is_none() && self.repo_dir.is_none();
+        let (old_repo, repo) = match moves_repo {
+            true => (
+                legacy.join(REPO_FOLDER_NAME),
+                base.data.join(REPO_FOLDER_NAME),
+            ),
+            false => {
+                let (_, _, repo) = self.dirs()?;
+                (repo.clone(), repo)
+            }
+        };
+
+        if moves_repo && old_repo.exists() {
+            move_path(&old_repo, &repo)?;
+        }
+
+        let mut files = Files::init(home.clone(), repo.clone());
+        configure_files(&mut files, &config)?;
+        files.relink(&config.files.include, &old_repo)?;
+
+        // The configuration file is managed by default, so it has to be moved in the repository as well.
+        let old_entry = Path::new(LEGACY_FOLDER_NAME).join(CONFIG_FILE_NAME);
+        let new_config = base.config.join(CONFIG_FILE_NAME);
+        let managed = config
+            .files
+            .include
+            .iter()
+            .position(|entry| *entry == old_entry);
+
+        if let (Some(i), Ok(new_entry)) = (managed, new_config.strip_prefix(&home)) {
+            move_path(&repo.join(&old_entry), &repo.join(new_entry))?;
+            files.relink_renamed(&old_entry, new_entry)?;
+
+            config.files.include[i] = new_entry.to_path_buf();
+            config.path = new_config.clone();
+            config.save_config()?;
+
+            Repository::load(&repo)?.commit(&format!(
+                "🚚 Moved '{}' to '{}'",
+                old_entry.display(),
+                new_entry.display()
+            ))?;
+        }
+
+        for entry in fs::read_dir(&legacy)
+            .map_err(|err| IoError::Read {
+                source: err,
+                path: legacy.clone(),
+            })?
+            .flatten()
+        {
+            let name = entry.file_name();
+            let state_files = [
+                lock::LOCK_FILE_NAME,
+                QUARANTINE_FILE_NAME,
+                metadata::KNOWN_FILE_NAME,
+            ];
+
+            match state_files.iter().any(|file| name == *file) {
+                true => move_path(&entry.path(), &base.state.join(&name))?,
+                false => move_path(&entry.path(), &base.config.join(&name))?,
+            }
+        }
+
+        // Anything left behind (e.g. an empty folder) is left alone.
+        let _ = fs::remove_dir(&legacy);
+
+        Ok(true)
+    }
+
+    /// Sets up a new instance of `dotbak`, creating the configuration file and the repository.
+    pub fn init(self) -> Result<Dotbak> {
+        self.migrate_legacy()?;
+
+        let (home, config, repo) = self.dirs()?;
+        let mut dotbak =
+            Dotbak::init_into_dirs(home, config, repo, self.verbosity, &self.overrides)?;
+
+        self.finish(&mut dotbak);
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+
+    /// Sets up an instance of `dotbak` by cloning the repository at `url`.
+    pub fn clone(self, url: &str) -> Result<Dotbak> {
+        self.migrate_legacy()?;
+
+        let (home, config, repo) = self.dirs()?;
+        let mut dotbak = Dotbak::clone_into_dirs(
+            home,
+            config,
+            repo,
+            url,
+            self.verbosity,
+            self.low_bandwidth,
+            &self.overrides,
+        )?;
+
+        self.finish(&mut dotbak);
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+
+    /// Sets up an instance of `dotbak` from the offline bundle at `path`, decrypting it with `passphrase`.
+    pub fn restore_bundle<P>(self, path: P, passphrase: SecretString) -> Result<Dotbak>
+    where
+        P: AsRef<Path>,
+    {
+        self.migrate_legacy()?;
+
+        let (home, config, repo) = self.dirs()?;
+        let mut dotbak = Dotbak::restore_bundle_into_dirs(
+            home,
+            config,
+            repo,
+            path,
+            passphrase,
+            self.verbosity,
+            &self.overrides,
+        )?;
+
+        self.finish(&mut dotbak);
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+
+    /// Loads an existing instance of `dotbak`, and synchronizes the managed files.
+    pub fn load(self) -> Result<Dotbak> {
+        let mut dotbak = self.load_without_sync()?;
+
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+
+    /// Like `load`, but does not synchronize the files afterwards, so the home directory is left as-is.
+    pub fn load_without_sync(self) -> Result<Dotbak> {
+        self.migrate_legacy()?;
+
+        let (home, config, repo) = self.dirs()?;
+        let mut dotbak =
+            Dotbak::load_into_dirs(home, config, repo, self.verbosity, &self.overrides)?;
+
+        self.finish(&mut dotbak);
+
+        Ok(dotbak)
+    }
+
+    /// Gets the path to the repository without loading `dotbak`, respecting `repo_path` in the configuration if
+    /// it exists.
+    pub fn resolve_repo_dir(&self) -> Result<PathBuf> {
+        let (home, config, repo) = self.dirs()?;
+
+        match Config::load_layered(config, &self.overrides) {
+            Ok(config) => Ok(config.repo_dir(&home)?.unwrap_or(repo)),
+            Err(DotbakError::Config(ConfigError::NotFound { .. })) => Ok(repo),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Gets `shell.binary` from the configuration without loading `dotbak`, or `None` if it isn't set or there is no
+    /// configuration file yet.
+    pub fn shell_binary(&self) -> Result<Option<PathBuf>> {
+        let (_, config, _) = self.dirs()?;
+
+        match Config::load_layered(config, &self.overrides) {
+            Ok(config) => Ok(config.shell.binary),
+            Err(DotbakError::Config(ConfigError::NotFound { .. })) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Collects a dump of the runtime environment for bug reports, without loading `dotbak` (so that it works even
+    /// if loading fails).
+    pub fn diagnostics(&self) -> Result<Report> {
+        let (home, config, repo) = self.dirs()?;
+
+        Ok(Report::collect(home, config, repo))
+    }
+
+    /// Checks the installation for problems, and how to fix them, without loading `dotbak` (so that it works even
+    /// if loading fails). The remote is only checked if `check_remote` is set. See `diagnostics::doctor`.
+    pub fn doctor(&self, check_remote: bool) -> Result<Vec<Finding>> {
+        let (home, config, repo) = self.dirs()?;
+
+        Ok(doctor::examine(home, config, repo, check_remote))
+    }
+
+    /// Checks the configuration file for every problem with it (see `config::check`), without loading `dotbak` (so
+    /// that it works even if loading fails). Returns the path to the configuration file, and the problems.
+    pub fn check_config(&self) -> Result<(PathBuf, Vec<Problem>)> {
+        let (home, config, _) = self.dirs()?;
+        let contents = migrations::upgrade_file(&config)?;
+        let problems = check::check(&contents, home);
+
+        Ok((config, problems))
+    }
+
+    /// Gets the value of the setting `key` (e.g. `delay_between_sync`, or `files.sparse`) without loading `dotbak`,
+    /// including the defaults of settings that aren't in the configuration file, and the overrides.
+    pub fn get_setting(&self, key: &str) -> Result<toml::Value> {
+        let (_, config, _) = self.dirs()?;
+
+        edit::get(&Config::load_layered(config, &self.overrides)?, key)
+    }
+
+    /// Sets the setting `key` to `value` (see `edit::set`) in the configuration file without loading `dotbak`,
+    /// keeping its comments and formatting. Nothing is changed if the configuration would be invalid afterwards.
+    pub fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        let (home, config, _) = self.dirs()?;
+        let contents = edit::set(&migrations::upgrade_file(&config)?, key, value)?;
+        let problems = check::check(&contents, home);
+
+        if !problems.is_empty() {
+            return Err(ConfigError::InvalidChange {
+                key: key.to_string(),
+                problems: check::describe(&problems),
+            }
+            .into());
+        }
+
+        lock::write_atomic(config, contents)
+    }
+
+    /// Opens the configuration file in the user's editor without loading `dotbak`. The changes are made to a copy
+    /// next to it, which is checked when the editor is closed. If there are problems, they're listed, and the user
+    /// can edit the copy again or discard the changes, which leaves the configuration file as it was. Returns
+    /// whether the configuration file was changed.
+    pub fn edit_config(&self) -> Result<bool> {
+        let (home, config, _) = self.dirs()?;
+        let contents = migrations::upgrade_file(&config)?;
+        let copy = config.with_extension(EDIT_EXTENSION);
+        let mut interface = Interface::new(MAX_MSG_LEN, self.verbosity);
+
+        if self.non_interactive {
+            interface.set_non_interactive();
+        }
+
+        lock::write_atomic(&copy, &contents)?;
+
+        let edited = loop {
+            let problems = interface
+                .open_editor(&copy)
+                .and_then(|_| read_config(&copy))
+                .map(|edited| (check::check(&edited, &home), edited));
+
+            let (problems, edited) = match problems {
+                Ok(checked) => checked,
+                Err(err) => {
+                    remove_copy(&copy)?;
+                    return Err(err);
+                }
+            };
+
+            if problems.is_empty() {
+                break Some(edited);
+            }
+
+            interface.warn(format!(
+                "The configuration has {} problem(s):\n{}",
+                problems.len(),
+                check::describe(&problems)
+            ));
+
+            match interface.choose(
+                "What should be done with the changes?",
+                &["Edit them again", "Discard them"],
+            ) {
+                Ok(0) => continue,
+                Ok(_) => break None,
+                Err(err) => {
+                    remove_copy(&copy)?;
+                    return Err(err);
+                }
+            }
+        };
+
+        remove_copy(&copy)?;
+
+        match edited {
+            Some(edited) if edited != contents => {
+                lock::write_atomic(config, edited)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Gets the user-defined aliases from the configuration without loading `dotbak`. If the configuration can't be
+    /// loaded, there are no aliases (the error will come up again when `dotbak` is loaded).
+    pub fn aliases(&self) -> BTreeMap<String, Vec<String>> {
+        self.dirs()
+            .and_then(|(_, config, _)| Config::load_layered(config, &self.overrides))
+            .map(|config| config.alias)
+            .unwrap_or_default()
+    }
+
+    /// Takes the lock on `dotbak`'s state for a command accessing it in `mode`, without loading `dotbak`. Shared
+    /// access doesn't lock anything, see `LockMode`. If another process holds the exclusive lock, `on_wait` is
+    /// called before waiting for it. The lock is held until the returned value is dropped.
+    pub fn lock<F>(&self, mode: LockMode, on_wait: F) -> Result<Option<Lock>>
+    where
+        F: FnOnce(),
+    {
+        match mode {
+            LockMode::Shared => Ok(None),
+            LockMode::Exclusive => Lock::exclusive(self.state_dir()?, on_wait).map(Some),
+        }
+    }
+
+    /// Resolves the home directory: the one that was set, or the default one (see `default_home_dir`).
+    fn home(&self) -> Result<PathBuf> {
+        match &self.home_dir {
+            Some(home) => Ok(home.clone()),
+            None => default_home_dir(),
+        }
+    }
+
+    /// Applies the settings that aren't needed to construct `dotbak` to `dotbak`.
+    fn finish(&self, dotbak: &mut Dotbak) {
+        if self.low_bandwidth {
+            dotbak.enable_low_bandwidth();
+        }
+
+        if self.non_interactive {
+            dotbak.interface.set_non_interactive();
+        }
+
+        if self.force {
+            dotbak.force();
+        }
+    }
+}
+
+/// Helper function to get the default home directory: `DOTBAK_HOME` if it's set, or the user's otherwise.
+fn default_home_dir() -> Result<PathBuf> {
+    match std::env::var_os(HOME_VAR) {
+        Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+        _ => dirs::home_dir().ok_or(DotbakError::NoHomeDir),
+    }
+}
+
+/// Helper function to move the file/folder at `from` to `to`, creating the folders it's moved into.
+fn move_path(from: &Path, to: &Path) -> Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent).map_err(|err| IoError::Create {
+            source: err,
+            path: parent.to_path_buf(),
+        })?;
+    }
+
+    fs::rename(from, to).map_err(|err| {
+        IoError::Move {
+            source: err,
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to read the configuration file at `path`, which must exist.
+fn read_config(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => ConfigError::NotFound {
+            path: path.to_path_buf(),
+        }
+        .into(),
+        _ => IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into(),
+    })
+}
+
+/// Helper function to remove the copy of the configuration file that `dotbak config edit` edits.
+fn remove_copy(path: &Path) -> Result<()> {
+    fs::remove_file(path).map_err(|err| {
+        IoError::Delete {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
@@ -1,91 +1,191 @@
-use super::Dotbak;
+use super::{builder::DotbakBuilder, Dotbak};
 use crate::errors::io::IoError;
 use crate::errors::Result;
+use crate::git;
+use crate::lock::{self, Lock};
+use crate::notifications::{self, Notification, Severity};
+use crate::platform;
+use crate::xdg::BaseDirs;
+#[cfg(unix)]
 use daemonize::Daemonize;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
 use std::fs::File;
-use std::path::PathBuf;
-use std::process::{Command, ExitStatus};
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
-const PID_FILE: &str = "/tmp/dotbak-daemon.pid";
+/// The name of the file with the PID of the running daemon, in `daemon_dir`.
+const PID_FILE_NAME: &str = "dotbak-daemon.pid";
 
-pub struct Daemon<'a> {
+/// The name of the file listing the changed settings the running daemon has to be restarted for, one per line, in
+/// `daemon_dir`.
+const PENDING_FILE_NAME: &str = "dotbak-daemon.pending";
+
+pub struct Daemon {
     /// The dotbak instance.
     pub dotbak: Dotbak,
 
-    /// The daemonize instance created by the daemon.
-    pub daemonize: Daemonize<&'a str>,
+    /// Where the output of the daemon goes once it's detached: stdout and stderr.
+    output: (File, File),
+
+    /// Watches the configuration file for changes.
+    config_watch: ConfigWatch,
+}
+
+/// Watches the configuration file while the daemon runs, so that changes to it are applied without a restart.
+struct ConfigWatch {
+    /// When the configuration file was last modified, as of the last time it was (re)loaded.
+    modified: Option<SystemTime>,
+
+    /// The changed settings that only take effect once the daemon is restarted.
+    pending: Vec<&'static str>,
 }
 
-impl Daemon<'_> {
-    /// Crate a new daemon instance.
-    pub fn new<'a>() -> Result<Daemon<'a>> {
-        let stdout = File::create("/tmp/dotbak-daemon.out").unwrap();
-        let stderr = File::create("/tmp/dotbak-daemon.err").unwrap();
-
-        let dotbak =
-            Dotbak::load_for_daemon(stdout.try_clone().unwrap(), stderr.try_clone().unwrap())?;
-
-        let daemonize = Daemonize::new()
-            .pid_file("/tmp/dotbak-daemon.pid") // Every method except `new` and `start`
-            .chown_pid_file(true) // is optional, see `Daemonize` documentation
-            .working_directory("/tmp") // for default behaviour.
-            .umask(0o777) // Set umask, `0o027` by default.
-            .stdout(stdout) // Redirect stdout to `/tmp/daemon.out`.
-            .stderr(stderr) // Redirect stderr to `/tmp/daemon.err`.
-            .privileged_action(|| "");
-
-        Ok(Daemon { dotbak, daemonize })
+impl Daemon {
+    /// Crate a new daemon instance, loading `dotbak` with `builder`.
+    pub fn new(builder: DotbakBuilder) -> Result<Daemon> {
+        let dir = daemon_dir();
+        std::fs::create_dir_all(&dir).map_err(|err| IoError::Create {
+            source: err,
+            path: dir.clone(),
+        })?;
+
+        let stdout = File::create(dir.join("dotbak-daemon.out")).unwrap();
+        let stderr = File::create(dir.join("dotbak-daemon.err")).unwrap();
+
+        let dotbak = Dotbak::load_for_daemon(
+            builder,
+            stdout.try_clone().unwrap(),
+            stderr.try_clone().unwrap(),
+        )?;
+
+        Ok(Daemon {
+            config_watch: ConfigWatch {
+                modified: config_modified(&dotbak),
+                pending: vec![],
+            },
+            dotbak,
+            output: (stdout, stderr),
+        })
     }
 
     /// Run dotbak daemon wrapper.
     /// TODO: Signal handling, so that the process stops gracefully.
-    pub fn run(mut self) {
-        self.dotbak.logger.info("Running dotbak daemon...");
+    pub fn run(self) {
+        let Daemon {
+            mut dotbak,
+            output,
+            mut config_watch,
+        } = self;
+
+        // Record the spans of the daemon's syncs with its own logger.
+        let _tracing = dotbak.logger.enter();
+        dotbak.logger.info("Running dotbak daemon...");
+
+        detach(output);
 
-        self.daemonize.start().unwrap();
+        // Don't report pending changes from a previous daemon.
+        let _ = std::fs::remove_file(pending_file());
 
-        let delay_between_sync = Duration::from_secs(self.dotbak.config.delay_between_sync);
+        // The failure the user was last notified of, so that they're only notified again once something changes.
+        let mut notified = None;
 
         // Run forever, until the user stops the daemon OR it panics OR the computer shuts down.
         loop {
-            self.dotbak.logger.info("Running sync command...");
-
-            // Run the sync command
-            self.dotbak
-                .sync()
+            // Wait for other `dotbak` commands changing the state to finish, and make them wait for this sync.
+            let lock = dotbak
+                .state_dir()
+                .and_then(|dir| {
+                    Lock::exclusive(dir, || {
+                        dotbak
+                            .logger
+                            .info("Waiting for another dotbak command to finish...")
+                    })
+                })
                 .expect("This should not error out when running on the daemon!");
 
-            thread::sleep(delay_between_sync);
+            config_watch.reload_config(&mut dotbak);
+
+            dotbak.logger.info("Running sync command...");
+
+            // Run the sync command. A failed sync is logged, and tried again next time.
+            let failure = match dotbak.sync() {
+                Ok(()) => offline_warning(&dotbak),
+                Err(err) => {
+                    dotbak.logger.error(format!("Sync failed: {}", err));
+
+                    Some(Notification {
+                        severity: Severity::Error,
+                        title: "dotbak couldn't sync".to_string(),
+                        body: err.to_string(),
+                    })
+                }
+            };
+
+            notify(&dotbak, &mut notified, failure);
+
+            drop(lock);
+
+            wait_for_sync(&dotbak);
+        }
+    }
+
+    /// Gets the PID of the running daemon, or `None` if it isn't running.
+    pub fn running() -> Option<u32> {
+        let pid = std::fs::read_to_string(pid_file())
+            .ok()?
+            .trim()
+            .parse::<u32>()
+            .ok()?;
+
+        platform::process_exists_command(pid)
+            .output()
+            .ok()?
+            .status
+            .success()
+            .then_some(pid)
+    }
+
+    /// Gets the path to the file with the PID of the running daemon.
+    pub fn pid_file() -> PathBuf {
+        pid_file()
+    }
+
+    /// Gets the changed settings the running daemon has to be restarted for, if there are any. See
+    /// `config::RESTART_SETTINGS`.
+    pub fn pending_restart() -> Vec<String> {
+        if Self::running().is_none() {
+            return vec![];
         }
+
+        std::fs::read_to_string(pending_file())
+            .map(|pending| pending.lines().map(str::to_string).collect())
+            .unwrap_or_default()
     }
 
     /// Stops the daemon.
     pub fn stop() -> Result<()> {
         // Get the PID
-        let pid = std::fs::read_to_string(PID_FILE).map_err(|err| IoError::Read {
+        let pid = std::fs::read_to_string(pid_file()).map_err(|err| IoError::Read {
             source: err,
-            path: PathBuf::from_str(PID_FILE).expect("The PID_FILE path should always exist!"),
+            path: pid_file(),
         })?;
 
         let pid = pid.trim();
 
         // Run the kill command
-        let output = Command::new("kill")
-            .arg(pid)
-            .output()
-            .map_err(|err| IoError::CommandIO {
-                command: "kill".to_string(),
-                args: vec![format!("{}", pid)],
-                source: err,
-            })?;
+        let mut command = platform::terminate_command(pid);
+        let output = command.output().map_err(|err| IoError::CommandIO {
+            command: command.get_program().to_string_lossy().to_string(),
+            args: vec![format!("{}", pid)],
+            source: err,
+        })?;
 
         // If the output isn't a success, then return an error.
         if !output.status.success() {
             return Err(IoError::CommandRun {
-                command: "kill".to_string(),
+                command: command.get_program().to_string_lossy().to_string(),
                 args: vec![format!("{}", pid)],
                 stdout: String::from_utf8_lossy(&output.stdout).to_string(),
                 stderr: String::from_utf8_lossy(&output.stderr).to_string(),
@@ -93,12 +193,261 @@ impl Daemon<'_> {
             .into());
         }
 
+        // The settings are applied when the daemon starts again.
+        let _ = std::fs::remove_file(pending_file());
+
         // Delete the PID file
-        std::fs::remove_file(PID_FILE).map_err(|err| IoError::Delete {
-            path: PathBuf::from_str(PID_FILE).expect("The PID_FILE path should always exist!"),
+        std::fs::remove_file(pid_file()).map_err(|err| IoError::Delete {
+            path: pid_file(),
             source: err,
         })?;
 
         Ok(())
     }
 }
+
+impl ConfigWatch {
+    /// Reload the configuration file if it was modified since it was last loaded. Invalid configurations are
+    /// logged and ignored, so that fixing them is picked up by the next reload.
+    fn reload_config(&mut self, dotbak: &mut Dotbak) {
+        let modified = config_modified(dotbak);
+
+        if modified == self.modified {
+            return;
+        }
+
+        self.modified = modified;
+
+        let reload = match dotbak.reload_config() {
+            // The file was saved without changing anything.
+            Ok(reload) if reload.is_empty() => return,
+            Ok(reload) => reload,
+            Err(err) => {
+                dotbak.logger.error(format!(
+                    "Couldn't reload the configuration, keeping the current one: {}",
+                    err
+                ));
+                return;
+            }
+        };
+
+        // `history.staging` is only used by the daemon, so it's applied here.
+        dotbak.repo.set_push_branch(
+            dotbak
+                .config
+                .history
+                .staging
+                .then_some(git::STAGING_BRANCH_NAME),
+        );
+
+        let new_pending = reload
+            .pending
+            .into_iter()
+            .filter(|setting| !self.pending.contains(setting))
+            .collect::<Vec<_>>();
+
+        if !new_pending.is_empty() {
+            self.pending.extend(new_pending);
+
+            if let Err(err) = lock::write_atomic(pending_file(), self.pending.join("\n")) {
+                dotbak.logger.error(format!(
+                    "Couldn't record the settings pending a restart: {}",
+                    err
+                ));
+            }
+        }
+    }
+}
+
+/// Helper function to get the warning about the last sync of `dotbak` only committing locally, as the remote couldn't
+/// be reached, if it did.
+fn offline_warning(dotbak: &Dotbak) -> Option<Notification> {
+    let queued = dotbak.queued_push().ok().flatten()?;
+
+    Some(Notification {
+        severity: Severity::Warning,
+        title: "dotbak synced offline".to_string(),
+        body: format!(
+            "The changes were only committed, as {}. They're pushed with the next sync that reaches the remote.",
+            queued.reason
+        ),
+    })
+}
+
+/// Helper function to notify the user of `failure` of the last sync (see `NotificationsConfig`), unless they were
+/// notified of it already (`notified`). Failing to notify them is only logged.
+fn notify(dotbak: &Dotbak, notified: &mut Option<Notification>, failure: Option<Notification>) {
+    match &failure {
+        Some(notification) if notified.as_ref() != Some(notification) => {
+            if let Err(err) = notifications::send(&dotbak.config.notifications, notification) {
+                dotbak
+                    .logger
+                    .error(format!("Couldn't notify of the failed sync: {}", err));
+            }
+        }
+        _ => (),
+    }
+
+    *notified = failure;
+}
+
+/// Helper function to get when the configuration file of `dotbak` was last modified, if it can be read.
+fn config_modified(dotbak: &Dotbak) -> Option<SystemTime> {
+    std::fs::metadata(&dotbak.config.path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Helper function to wait until the daemon should sync again, depending on `daemon.mode`: until
+/// `delay_between_sync` seconds passed, and/or until the managed files changed and then stayed unchanged for
+/// `daemon.debounce` milliseconds. If the files can't be watched, this falls back to the timer, which also runs while
+/// a push is queued (see `Dotbak::offline`).
+fn wait_for_sync(dotbak: &Dotbak) {
+    let interval = Duration::from_secs(dotbak.config.delay_between_sync);
+    let mode = dotbak.config.daemon.mode;
+
+    if !mode.watches() {
+        thread::sleep(interval);
+        return;
+    }
+
+    // The watcher is created anew for every wait, so that it picks up changes to `files.include` and files that
+    // appeared since (e.g. from a pull), and changes made while syncing aren't synced again.
+    let (sender, events) = mpsc::channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        if let Ok(event) = event {
+            let _ = sender.send(event);
+        }
+    });
+
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            dotbak.logger.error(format!(
+                "Couldn't watch the managed files, waiting {} seconds instead: {}",
+                dotbak.config.delay_between_sync, err
+            ));
+            thread::sleep(interval);
+            return;
+        }
+    };
+
+    for path in watched_paths(dotbak) {
+        if let Err(err) = watcher.watch(&path, RecursiveMode::Recursive) {
+            dotbak.logger.error(format!(
+                "Couldn't watch '{}' for changes: {}",
+                path.display(),
+                err
+            ));
+        }
+    }
+
+    // Queued pushes are tried again on the timer, even if the daemon only syncs on changes otherwise.
+    let queued = dotbak.queued_push().is_ok_and(|queued| queued.is_some());
+    let deadline = (mode.has_interval() || queued).then(|| Instant::now() + interval);
+    let is_change = |event: &Event| {
+        !matches!(event.kind, EventKind::Access(_))
+            && !event.paths.iter().all(|path| is_ignored(dotbak, path))
+    };
+
+    // Wait for the first change, or until the timer runs out.
+    loop {
+        let event = match deadline {
+            Some(deadline) => {
+                match events.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(event) => event,
+                    Err(_) => return,
+                }
+            }
+            None => match events.recv() {
+                Ok(event) => event,
+                Err(_) => return,
+            },
+        };
+
+        if is_change(&event) {
+            break;
+        }
+    }
+
+    // Then wait until the files stay unchanged, so that a burst of changes is synced at once.
+    let debounce = Duration::from_millis(dotbak.config.daemon.debounce);
+
+    while events.recv_timeout(debounce).is_ok() {}
+
+    dotbak.logger.info("Managed files changed");
+}
+
+/// Helper function to get the paths to watch for changes: the managed files/folders in the repository (which the
+/// symlinks in the home directory point to), or in the home directory if they aren't symlinked (e.g. encrypted
+/// files), and the configuration file. Paths that don't exist are skipped.
+fn watched_paths(dotbak: &Dotbak) -> Vec<PathBuf> {
+    let home = dotbak.dotfiles.home_dir();
+
+    dotbak
+        .config
+        .files
+        .include
+        .iter()
+        .map(|path| match home.join(path).is_symlink() {
+            true => dotbak.dotfiles.repo_path(path),
+            false => home.join(path),
+        })
+        .chain([dotbak.config.path.clone()])
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// Helper function to check if the changed file/folder at `path` (in the repository or the home directory) is ignored
+/// (see `Files::is_ignored`), so that changes to it, like a shell rewriting its history, don't cause a sync.
+fn is_ignored(dotbak: &Dotbak, path: &Path) -> bool {
+    let files = &dotbak.dotfiles;
+    let path = match path.strip_prefix(files.file_dir()) {
+        Ok(path) => files.managed_relative(path),
+        Err(_) => path
+            .strip_prefix(files.home_dir())
+            .unwrap_or(path)
+            .to_path_buf(),
+    };
+
+    files.is_ignored(path)
+}
+
+/// Gets the folder with the daemon's PID file and output: the user's state folder (see `BaseDirs::state`), so that
+/// every `dotbak` finds the daemon regardless of `--home`, or the temporary folder if there's no home directory.
+fn daemon_dir() -> PathBuf {
+    BaseDirs::user()
+        .map(|base| base.state)
+        .unwrap_or_else(platform::temp_dir)
+}
+
+/// Gets the path to the file with the PID of the running daemon.
+fn pid_file() -> PathBuf {
+    daemon_dir().join(PID_FILE_NAME)
+}
+
+/// Gets the path to the file listing the changed settings the running daemon has to be restarted for.
+fn pending_file() -> PathBuf {
+    daemon_dir().join(PENDING_FILE_NAME)
+}
+
+/// Detaches the daemon from the terminal, writing its PID to the PID file and its output to `output`.
+#[cfg(unix)]
+fn detach((stdout, stderr): (File, File)) {
+    Daemonize::new()
+        .pid_file(pid_file())
+        .chown_pid_file(true)
+        .working_directory(platform::temp_dir())
+        .umask(0o777) // Set umask, `0o027` by default.
+        .stdout(stdout)
+        .stderr(stderr)
+        .start()
+        .unwrap();
+}
+
+/// Windows processes can't detach themselves, so the daemon keeps running where it was started (e.g. as a hidden
+/// scheduled task). Its PID is still written to the PID file, so that `dotbak stop-daemon` finds it.
+#[cfg(windows)]
+fn detach(_output: (File, File)) {
+    let _ = std::fs::write(pid_file(), std::process::id().to_string());
+}
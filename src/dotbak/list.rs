@@ -0,0 +1,198 @@
+use crate::{
+    errors::{io::IoError, Result},
+    files::{encrypt, Files, LinkState},
+    git::{Commit, Repository},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A managed file or folder (or a file inside of a managed folder), as listed by `dotbak list`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListEntry {
+    /// The path, relative to the home directory.
+    pub path: PathBuf,
+
+    /// Whether this is a folder.
+    pub is_dir: bool,
+
+    /// The state of the symlink in the home directory. Files inside of managed folders have the state of the folder.
+    pub state: LinkState,
+
+    /// The size in bytes. For folders, this is the total size of the files inside.
+    pub size: u64,
+
+    /// The last commit changing the path, if it was committed yet.
+    pub last_commit: Option<Commit>,
+}
+
+/// Gets the last commit changing each file in the repository, and each folder leading to them, by their paths in the
+/// repository. This reads the whole history with a single `git log`, rather than one per listed path.
+pub(super) fn last_commits(repo: &mut Repository) -> Result<HashMap<PathBuf, Commit>> {
+    let mut last_commits = HashMap::new();
+
+    // The history is newest commit first, so the first commit seen changing a path is its last one.
+    for entry in repo.log(None, None)? {
+        for file in &entry.files {
+            for path in file.ancestors().filter(|path| !path.as_os_str().is_empty()) {
+                last_commits
+                    .entry(path.to_path_buf())
+                    .or_insert_with(|| entry.commit.clone());
+            }
+        }
+    }
+
+    Ok(last_commits)
+}
+
+/// Lists the managed path `file` (relative to the home directory), and everything inside of it if it's a folder.
+/// `last_commits` are the last commits changing the paths in the repository (see `last_commits`).
+pub(super) fn entries<P>(
+    last_commits: &HashMap<PathBuf, Commit>,
+    dotfiles: &Files,
+    file: P,
+) -> Result<Vec<ListEntry>>
+where
+    P: AsRef<Path>,
+{
+    let file = file.as_ref();
+    let state = dotfiles.link_state(file);
+
+    // Encrypted files are only stored encrypted in the repository, so their size is the one of the decrypted file.
+    if dotfiles.is_encrypted(file) {
+        let encrypted = encrypt::encrypted_path(file);
+
+        return Ok(vec![ListEntry {
+            path: file.to_path_buf(),
+            is_dir: false,
+            state,
+            size: fs::metadata(dotfiles.home_dir().join(file)).map_or(0, |meta| meta.len()),
+            last_commit: last_commits.get(&encrypted).cloned(),
+        }]);
+    }
+
+    // Files in roots outside of the home directory are listed by their absolute paths, rather than by their paths in
+    // the repository.
+    let mut found = vec![];
+    collect(
+        last_commits,
+        dotfiles.file_dir(),
+        &dotfiles.repo_relative(file),
+        state,
+        &mut found,
+    )?;
+
+    Ok(found
+        .into_iter()
+        .map(|entry| ListEntry {
+            path: dotfiles.managed_relative(&entry.path),
+            ..entry
+        })
+        .collect())
+}
+
+/// Renders `entries` as a tree rooted at the home directory (`~`), with `describe` giving the text after each entry.
+/// Folders leading to managed paths (like `.config`) are shown without a description.
+pub fn render_tree<F>(entries: &[ListEntry], describe: F) -> String
+where
+    F: Fn(&ListEntry) -> String,
+{
+    let mut root = Node::default();
+
+    for entry in entries {
+        let node = entry.path.iter().fold(&mut root, |node, name| {
+            node.children.entry(name.to_os_string()).or_default()
+        });
+
+        node.entry = Some(entry);
+    }
+
+    let mut rendered = "~\n".to_string();
+    root.render("", &describe, &mut rendered);
+
+    rendered
+}
+
+/// A folder or file in the tree rendered by `render_tree`.
+#[derive(Default)]
+struct Node<'a> {
+    /// The entry of the path, if it's listed.
+    entry: Option<&'a ListEntry>,
+
+    /// The files and folders inside of this one, by name.
+    children: BTreeMap<OsString, Node<'a>>,
+}
+
+impl Node<'_> {
+    /// Renders the children of this node into `rendered`, each line starting with `prefix`.
+    fn render<F>(&self, prefix: &str, describe: &F, rendered: &mut String)
+    where
+        F: Fn(&ListEntry) -> String,
+    {
+        for (index, (name, child)) in self.children.iter().enumerate() {
+            let last = index + 1 == self.children.len();
+            let (branch, indent) = match last {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+
+            rendered.push_str(&format!("{}{}{}", prefix, branch, name.to_string_lossy()));
+
+            if let Some(entry) = child.entry {
+                rendered.push_str(&format!(" {}", describe(entry)));
+            }
+
+            rendered.push('\n');
+            child.render(&format!("{}{}", prefix, indent), describe, rendered);
+        }
+    }
+}
+
+/// Helper function to list `path` in the repository `dir`, and everything inside of it if it's a folder, into
+/// `found`. Returns the size of `path`.
+fn collect(
+    last_commits: &HashMap<PathBuf, Commit>,
+    dir: &Path,
+    path: &Path,
+    state: LinkState,
+    found: &mut Vec<ListEntry>,
+) -> Result<u64> {
+    let full_path = dir.join(path);
+    let meta = fs::symlink_metadata(&full_path).ok();
+    let is_dir = meta.as_ref().is_some_and(|meta| meta.is_dir());
+    let index = found.len();
+
+    found.push(ListEntry {
+        path: path.to_path_buf(),
+        is_dir,
+        state,
+        size: meta.as_ref().map_or(0, |meta| meta.len()),
+        last_commit: last_commits.get(path).cloned(),
+    });
+
+    if is_dir {
+        let mut children = fs::read_dir(&full_path)
+            .map_err(|err| IoError::Read {
+                source: err,
+                path: full_path.clone(),
+            })?
+            .flatten()
+            .map(|entry| entry.file_name())
+            .filter(|name| name != ".git")
+            .collect::<Vec<_>>();
+        children.sort();
+
+        let mut size = 0;
+
+        for child in children {
+            size += collect(last_commits, dir, &path.join(child), state, found)?;
+        }
+
+        found[index].size = size;
+    }
+
+    Ok(found[index].size)
+}
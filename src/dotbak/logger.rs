@@ -1,39 +1,110 @@
+use crate::{clock, ui::Verbosity, xdg::BaseDirs};
 use itertools::Itertools;
-use std::{cell::RefCell, fmt::Display, io::Write, process::Output};
+use std::{
+    fmt::{self, Display},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Output,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tracing::{dispatcher::DefaultGuard, Dispatch, Event, Level, Metadata, Subscriber};
+use tracing_subscriber::{
+    field::RecordFields,
+    fmt::{
+        format::{DefaultFields, FmtSpan, Writer},
+        FmtContext, FormatEvent, FormatFields, FormattedFields, MakeWriter,
+    },
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    EnvFilter, Layer, Registry,
+};
 
 /// The padding used before logs (normally hidden). Normally PAD + "> "
 const LOG_PAD: &str = "   > ";
 
-/// Logger for the dotbak crate.
+/// The name of the folder the log files are in, in the state folder (see `BaseDirs::state`).
+pub const LOG_DIR_NAME: &str = "logs";
+
+/// The name of the current log file, in `LOG_DIR_NAME`. Older ones have `.1`, `.2`, etc. appended, see
+/// `MAX_LOG_FILES`.
+pub const LOG_FILE_NAME: &str = "dotbak.log";
+
+/// How large (in bytes) the current log file may get before it's rotated, i.e. renamed to `<LOG_FILE_NAME>.1`.
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// How many rotated log files are kept, besides the current one. Older ones are deleted.
+pub const MAX_LOG_FILES: usize = 5;
+
+/// The environment variable with the filter for what's logged (e.g. `debug`, or `dotbak_core::git=trace`), in the
+/// syntax of `tracing_subscriber::EnvFilter`. It replaces the verbosity on the terminal, and what's logged to the
+/// log file.
+pub const LOG_ENV_VAR: &str = "DOTBAK_LOG";
+
+/// The target of the records logged with `Logger`, so that `LOG_ENV_VAR` can filter them as `dotbak`.
+const TARGET: &str = "dotbak";
+
+/// Logger for the dotbak crate. Records are `tracing` events, within the spans of the operations they happened in
+/// (e.g. `run{kind=sync}`). They're shown on the terminal depending on the verbosity, and always written to the log
+/// file (with timestamps, and how long the spans took), so that what happened can be looked up afterwards, e.g. for
+/// daemon runs.
 pub struct Logger {
-    /// Whether to be verbose with logging or not.
-    /// Ex: printing the output of git commands.
-    verbose: bool,
+    /// How much is shown on the terminal.
+    verbosity: Verbosity,
+
+    /// The stdout and stderr to use for logging.
+    streams: Streams,
 
-    /// The stdout to use for logging.
-    stdout: RefCell<Box<dyn Write>>,
+    /// The current log file.
+    log_file: LogFile,
 
-    /// The stderr to use for logging.
-    stderr: RefCell<Box<dyn Write>>,
+    /// Whether records are written to `streams` as JSON, one object per line, instead of for people to read.
+    json: bool,
+
+    /// The subscriber writing the records, see `enter`.
+    dispatch: Dispatch,
 }
 
 impl Logger {
-    /// Creates a new logger.
-    pub fn new(verbose: bool) -> Self {
+    /// Creates a new logger for the home directory `home`, logging to the terminal (see `with_streams`), and to the
+    /// log files in `$XDG_STATE_HOME/dotbak/logs`.
+    pub fn new<P>(verbosity: Verbosity, home: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
         Self {
-            verbose,
-            stdout: RefCell::new(Box::new(std::io::stdout())),
-            stderr: RefCell::new(Box::new(std::io::stderr())),
+            verbosity,
+            streams: Streams::new(Box::new(io::stdout()), Box::new(io::stderr())),
+            log_file: LogFile::new(BaseDirs::new(home).state.join(LOG_DIR_NAME)),
+            json: false,
+            dispatch: Dispatch::none(),
         }
+        .subscribe()
     }
 
-    // Creates a new logger with custom stdout and stderr.
-    pub fn new_with_streams(verbose: bool, stdout: Box<dyn Write>, stderr: Box<dyn Write>) -> Self {
+    /// Logs to `stdout` and `stderr` instead of the terminal.
+    pub fn with_streams(
+        self,
+        stdout: Box<dyn Write + Send>,
+        stderr: Box<dyn Write + Send>,
+    ) -> Self {
         Self {
-            verbose,
-            stdout: RefCell::new(stdout),
-            stderr: RefCell::new(stderr),
+            streams: Streams::new(stdout, stderr),
+            ..self
         }
+        .subscribe()
+    }
+
+    /// Logs to the streams as JSON, one object per line with the record's fields and spans, instead of for people to
+    /// read. This is for the daemon, whose output nobody reads as it goes.
+    pub fn json(self) -> Self {
+        Self { json: true, ..self }.subscribe()
+    }
+
+    /// Makes this logger record the spans of the operations run on this thread (see `tracing::instrument`), until
+    /// the returned guard is dropped.
+    pub fn enter(&self) -> DefaultGuard {
+        tracing::dispatcher::set_default(&self.dispatch)
     }
 
     /// Log an output at INFO level.
@@ -41,16 +112,10 @@ impl Logger {
     where
         S: Display,
     {
-        if !self.verbose {
-            return;
-        }
-
-        writeln!(
-            self.stdout.borrow_mut(),
-            "{}",
-            console::style(pad_lines_from_start(message, LOG_PAD)).dim()
-        )
-        .unwrap(); // TODO: Handle error
+        tracing::dispatcher::with_default(
+            &self.dispatch,
+            || tracing::info!(target: TARGET, "{}", message),
+        );
     }
 
     /// Log an error.
@@ -58,57 +123,296 @@ impl Logger {
     where
         S: Display,
     {
-        if !self.verbose {
-            return;
+        tracing::dispatcher::with_default(
+            &self.dispatch,
+            || tracing::error!(target: TARGET, "{}", message),
+        );
+    }
+
+    /// Log an output from a command, at DEBUG level.
+    pub fn log_output(&self, output: Output) {
+        let stdout_untrimmed = String::from_utf8_lossy(&output.stdout);
+        let stderr_untrimmed = String::from_utf8_lossy(&output.stderr);
+
+        let stdout = stdout_untrimmed.trim();
+        let stderr = stderr_untrimmed.trim();
+
+        tracing::dispatcher::with_default(&self.dispatch, || {
+            if !stdout.is_empty() {
+                tracing::debug!(target: TARGET, "{}", stdout);
+            }
+
+            if !stderr.is_empty() {
+                tracing::debug!(target: TARGET, "{}", stderr);
+            }
+        });
+    }
+
+    // Log multiple outputs.
+    pub fn log_outputs<const N: usize>(&self, outputs: [Output; N]) {
+        for output in outputs {
+            self.log_output(output);
+        }
+    }
+
+    /// Helper function to set up the subscriber writing the records: to the streams (as far as the verbosity or
+    /// `LOG_ENV_VAR` says), and to the log file (everything but the most detailed spans, unless `LOG_ENV_VAR` says
+    /// otherwise).
+    fn subscribe(self) -> Self {
+        let env_filter = std::env::var(LOG_ENV_VAR)
+            .ok()
+            .map(|filter| EnvFilter::builder().parse_lossy(filter));
+
+        let terminal_filter = env_filter.clone().unwrap_or_else(|| {
+            EnvFilter::new(match self.verbosity {
+                Verbosity::Quiet | Verbosity::Normal => "off",
+                Verbosity::Verbose => "info",
+                Verbosity::Debug => "debug",
+            })
+        });
+        let file_filter = env_filter.unwrap_or_else(|| EnvFilter::new("debug"));
+
+        let terminal = tracing_subscriber::fmt::layer().with_writer(self.streams.clone());
+        let terminal = match self.json {
+            true => terminal
+                .json()
+                .with_span_list(true)
+                .with_span_events(FmtSpan::CLOSE)
+                .with_filter(terminal_filter)
+                .boxed(),
+            false => terminal
+                .event_format(TerminalFormat)
+                .with_filter(terminal_filter)
+                .boxed(),
+        };
+
+        let file = tracing_subscriber::fmt::layer()
+            .with_writer(self.log_file.clone())
+            .with_ansi(false)
+            .with_span_events(FmtSpan::CLOSE)
+            .fmt_fields(FileFields::default())
+            .event_format(FileFormat)
+            .with_filter(file_filter)
+            .boxed();
+
+        Self {
+            dispatch: Dispatch::new(Registry::default().with(vec![terminal, file])),
+            ..self
         }
+    }
+}
+
+/// The format of the records on the terminal: only their message, indented (see `LOG_PAD`) and dimmed, and red if
+/// they're errors.
+struct TerminalFormat;
+
+impl<S, N> FormatEvent<S, N> for TerminalFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut message = String::new();
+        ctx.format_fields(Writer::new(&mut message), event)?;
+
+        let padded = pad_lines_from_start(&message, LOG_PAD);
+        match *event.metadata().level() {
+            Level::ERROR => writeln!(writer, "{}", console::style(padded).red().dim()),
+            _ => writeln!(writer, "{}", console::style(padded).dim()),
+        }
+    }
+}
+
+/// The format of the records in the log file: a timestamp, the level, the spans the record happened in (with their
+/// fields), and the message. Lines after the first are indented, so each record starts with its timestamp.
+struct FileFormat;
+
+impl<S, N> FormatEvent<S, N> for FileFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut record = String::new();
+
+        for span in ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+        {
+            record.push_str(span.name());
+
+            if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                if !fields.is_empty() {
+                    record.push_str(&format!("{{{}}}", fields));
+                }
+            }
+
+            record.push_str(": ");
+        }
+
+        ctx.format_fields(Writer::new(&mut record), event)?;
 
         writeln!(
-            self.stderr.borrow_mut(),
-            "{}",
-            console::style(pad_lines_from_start(message, LOG_PAD))
-                .red()
-                .dim()
+            writer,
+            "{} {:<5} {}",
+            clock::format_utc(clock::now()),
+            event.metadata().level(),
+            record.lines().join("\n    ")
         )
-        .unwrap(); // TODO: Handle error
     }
+}
 
-    /// Log an output from a command.
-    pub fn log_output(&self, output: Output) {
-        if !self.verbose {
-            return;
+/// The fields of the records and spans in the log file. They're formatted like `DefaultFields` does, but kept
+/// apart from the ones formatted for the terminal, which may be colored.
+#[derive(Default)]
+struct FileFields(DefaultFields);
+
+impl<'writer> FormatFields<'writer> for FileFields {
+    fn format_fields<R>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result
+    where
+        R: RecordFields,
+    {
+        self.0.format_fields(writer, fields)
+    }
+}
+
+/// The streams records are written to: errors to stderr, and the rest to stdout.
+#[derive(Clone)]
+struct Streams {
+    /// The stdout.
+    stdout: Arc<Mutex<Box<dyn Write + Send>>>,
+
+    /// The stderr.
+    stderr: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+impl Streams {
+    /// Creates the streams writing to `stdout` and `stderr`.
+    fn new(stdout: Box<dyn Write + Send>, stderr: Box<dyn Write + Send>) -> Self {
+        Self {
+            stdout: Arc::new(Mutex::new(stdout)),
+            stderr: Arc::new(Mutex::new(stderr)),
         }
+    }
+}
 
-        let stdout_untrimmed = String::from_utf8_lossy(&output.stdout);
-        let stderr_untrimmed = String::from_utf8_lossy(&output.stderr);
+impl<'a> MakeWriter<'a> for Streams {
+    type Writer = Locked<'a, Box<dyn Write + Send>>;
 
-        let stdout = stdout_untrimmed.trim();
-        let stderr = stderr_untrimmed.trim();
+    fn make_writer(&'a self) -> Self::Writer {
+        Locked(self.stdout.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let stream = match *meta.level() {
+            Level::ERROR => &self.stderr,
+            _ => &self.stdout,
+        };
+
+        Locked(stream.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+}
 
-        if !stdout.is_empty() {
-            self.info(stdout);
+/// The current log file, which is opened by the first record, and rotated once it's too large (see
+/// `MAX_LOG_SIZE`).
+#[derive(Clone)]
+struct LogFile {
+    /// The folder with the log files.
+    dir: PathBuf,
+
+    /// The current log file, once it's opened.
+    file: Arc<Mutex<Option<File>>>,
+}
+
+impl LogFile {
+    /// Creates the log file in the folder `dir`, without opening it yet.
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            file: Arc::new(Mutex::new(None)),
         }
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = Locked<'a, Option<File>>;
 
-        if !stdout.is_empty() && !stderr.is_empty() {
-            println!();
+    /// Gets the log file to write a record to, rotating it first if it's too large. Logging never fails the
+    /// command, so if the log file can't be opened, the record is dropped.
+    fn make_writer(&'a self) -> Self::Writer {
+        let path = self.dir.join(LOG_FILE_NAME);
+        let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Rotating renames the open log file, so it's opened again afterwards.
+        if fs::metadata(&path).is_ok_and(|meta| meta.len() >= MAX_LOG_SIZE) {
+            *file = None;
+            rotate(&self.dir);
         }
 
-        if !stderr.is_empty() {
-            self.error(stderr);
+        if file.is_none() {
+            *file = fs::create_dir_all(&self.dir)
+                .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+                .ok();
         }
+
+        Locked(file)
     }
+}
 
-    // Log multiple outputs.
-    pub fn log_outputs<const N: usize>(&self, outputs: [Output; N]) {
-        if !self.verbose {
-            return;
+/// A locked stream, which records are written to while it's locked, so that they aren't interleaved.
+struct Locked<'a, W>(MutexGuard<'a, W>);
+
+impl Write for Locked<'_, Box<dyn Write + Send>> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Write for Locked<'_, Option<File>> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.as_mut() {
+            Some(file) => file.write(buf),
+            None => Ok(buf.len()),
         }
+    }
 
-        for output in outputs {
-            self.log_output(output);
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.as_mut() {
+            Some(file) => file.flush(),
+            None => Ok(()),
         }
     }
 }
 
+/// Helper function to rotate the log files in `dir`: `<LOG_FILE_NAME>.<n>` becomes `<LOG_FILE_NAME>.<n + 1>`, and the
+/// current log file becomes `<LOG_FILE_NAME>.1`. The oldest one is deleted, so that at most `MAX_LOG_FILES` are kept.
+fn rotate(dir: &Path) {
+    let rotated = |n: usize| dir.join(format!("{}.{}", LOG_FILE_NAME, n));
+
+    let _ = fs::remove_file(rotated(MAX_LOG_FILES));
+
+    for n in (1..MAX_LOG_FILES).rev() {
+        let _ = fs::rename(rotated(n), rotated(n + 1));
+    }
+
+    let _ = fs::rename(dir.join(LOG_FILE_NAME), rotated(1));
+}
+
 // Pad all lines in a string from the start with a given string.
 fn pad_lines_from_start<S1, S2>(input: S1, pad: S2) -> String
 where
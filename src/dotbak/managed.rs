@@ -0,0 +1,266 @@
+use crate::{
+    errors::{io::IoError, Result},
+    files::{compare, Files},
+    lock, paths, platform,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file the managed files are recorded in, in the state folder.
+pub const MANAGED_FILE_NAME: &str = "managed.toml";
+
+/// What `prompt` shows when nothing needs attention.
+pub const PROMPT_CLEAN: &str = "✓";
+
+/// What this machine knows about the files/folders it manages, as of the last time each of them was synced. This is
+/// kept apart from the configuration, so that drift (see `Drift`) can be told precisely, and so that files whose
+/// entries were taken out of `files.include` by hand are still known.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManagedFiles {
+    /// The location of the file. This is set when loading, so it is not serialized.
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// The number of commits that weren't pushed yet, as of the last `dotbak` command that committed, pushed or
+    /// pulled.
+    #[serde(default)]
+    unpushed: usize,
+
+    /// The managed files/folders.
+    #[serde(default, rename = "file")]
+    entries: Vec<ManagedEntry>,
+}
+
+/// A managed file/folder, as of the last time it was synced.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManagedEntry {
+    /// The path, relative to the home directory.
+    pub path: PathBuf,
+
+    /// The entry of `files.include` that covered the path, e.g. a glob pattern that matched it.
+    pub include: PathBuf,
+
+    /// The checksum of the contents (see `compare::checksum`), if they could be read.
+    pub checksum: Option<String>,
+
+    /// The permission bits of the contents, if the platform has them.
+    pub mode: Option<u32>,
+
+    /// Where the symlink in the home directory pointed to, if there was one.
+    pub target: Option<PathBuf>,
+
+    /// When the path was last synced, in seconds since the UNIX epoch.
+    pub synced: u64,
+}
+
+/// How a managed file/folder drifted since it was last synced. See `ManagedEntry::drift`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Drift {
+    /// No entry of `files.include` covers it anymore, e.g. because the configuration file was edited by hand.
+    Unlisted,
+
+    /// Its contents are gone from the repository.
+    Missing,
+
+    /// Its symlink in the home directory points somewhere else, or is gone.
+    Relinked,
+
+    /// Its contents changed.
+    Modified,
+
+    /// Its permissions changed.
+    Permissions,
+}
+
+impl Drift {
+    /// Describes the drift for humans.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Drift::Unlisted => "not in `files.include` anymore, run `dotbak prune` to clean it up",
+            Drift::Missing => "gone from the repository",
+            Drift::Relinked => "its symlink was changed",
+            Drift::Modified => "changed since the last sync",
+            Drift::Permissions => "its permissions changed since the last sync",
+        }
+    }
+}
+
+impl ManagedFiles {
+    /// Loads the managed files from the folder `dir` (the state folder). If there is no file yet, nothing is
+    /// managed.
+    pub fn load<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(MANAGED_FILE_NAME);
+
+        let mut managed: ManagedFiles = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => ManagedFiles::default(),
+            Err(err) => return Err(IoError::Read { source: err, path }.into()),
+        };
+
+        managed.path = path;
+
+        Ok(managed)
+    }
+
+    /// Saves the managed files to the file they were loaded from. The file is removed if nothing is managed (and
+    /// nothing is unpushed).
+    pub fn save(&self) -> Result<()> {
+        if self.entries.is_empty() && self.unpushed == 0 {
+            return match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(IoError::Delete {
+                    source: err,
+                    path: self.path.clone(),
+                }
+                .into()),
+                _ => Ok(()),
+            };
+        }
+
+        lock::write_atomic(&self.path, toml::to_string_pretty(self)?)
+    }
+
+    /// Records `entry`, replacing what was recorded for its path until now.
+    pub fn record(&mut self, entry: ManagedEntry) {
+        match self
+            .entries
+            .iter_mut()
+            .find(|other| other.path == entry.path)
+        {
+            Some(other) => *other = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Forgets `path`, and the paths inside of it. Returns whether anything was forgotten.
+    pub fn forget<P>(&mut self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let count = self.entries.len();
+
+        self.entries
+            .retain(|entry| !entry.path.starts_with(path.as_ref()));
+
+        self.entries.len() != count
+    }
+
+    /// Gets what's recorded for `path`, if it's managed.
+    pub fn get<P>(&self, path: P) -> Option<&ManagedEntry>
+    where
+        P: AsRef<Path>,
+    {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path.as_ref())
+    }
+
+    /// Gets the managed files/folders.
+    pub fn entries(&self) -> impl Iterator<Item = &ManagedEntry> {
+        self.entries.iter()
+    }
+
+    /// Gets the number of commits that weren't pushed yet, as recorded with `set_unpushed`.
+    pub fn unpushed(&self) -> usize {
+        self.unpushed
+    }
+
+    /// Records the number of commits that weren't pushed yet.
+    pub fn set_unpushed(&mut self, unpushed: usize) {
+        self.unpushed = unpushed;
+    }
+
+    /// Summarizes what needs attention for a shell prompt, without running git: the number of unpushed commits
+    /// (e.g. `3↑`) and the number of managed files/folders whose symlink in the home directory `home` is broken or
+    /// gone (e.g. `1✗`), or `PROMPT_CLEAN` if there are none.
+    pub fn prompt<P>(&self, home: P) -> String
+    where
+        P: AsRef<Path>,
+    {
+        let broken = self
+            .entries
+            .iter()
+            .filter(|entry| entry.target.is_some())
+            .filter(|entry| fs::metadata(home.as_ref().join(&entry.path)).is_err())
+            .count();
+
+        let mut parts = vec![];
+
+        if self.unpushed > 0 {
+            parts.push(format!("{}↑", self.unpushed));
+        }
+
+        if broken > 0 {
+            parts.push(format!("{}✗", broken));
+        }
+
+        match parts.is_empty() {
+            true => PROMPT_CLEAN.to_string(),
+            false => parts.join(" "),
+        }
+    }
+}
+
+impl ManagedEntry {
+    /// Records the file/folder `path` (relative to the home directory) as it is now, as managed by `dotfiles` because
+    /// of the entry `include` of `files.include`.
+    pub fn snapshot<P>(dotfiles: &Files, path: P, include: PathBuf, synced: u64) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let contents = dotfiles.contents_path(path);
+
+        Self {
+            path: path.to_path_buf(),
+            include,
+            checksum: compare::checksum(&contents).ok(),
+            mode: fs::metadata(&contents)
+                .ok()
+                .and_then(|meta| platform::permission_bits(&meta)),
+            target: fs::read_link(dotfiles.home_dir().join(path)).ok(),
+            synced,
+        }
+    }
+
+    /// Finds how the file/folder drifted since it was last synced, given the entries of `files.include` there are
+    /// now. Only the first drift is returned, in the order of `Drift`.
+    pub fn drift(&self, dotfiles: &Files, include: &[PathBuf]) -> Option<Drift> {
+        let now = ManagedEntry::snapshot(dotfiles, &self.path, self.include.clone(), self.synced);
+
+        if include_entry(include, &self.path).is_none() {
+            Some(Drift::Unlisted)
+        } else if fs::symlink_metadata(dotfiles.contents_path(&self.path)).is_err() {
+            Some(Drift::Missing)
+        } else if now.target != self.target {
+            Some(Drift::Relinked)
+        } else if now.checksum != self.checksum {
+            Some(Drift::Modified)
+        } else if now.mode != self.mode {
+            Some(Drift::Permissions)
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds the entry of `include` that covers `path` (relative to the home directory): the first one that is `path`
+/// or one of the folders containing it, or a glob pattern matching it (see `paths::is_pattern`).
+pub fn include_entry<P>(include: &[PathBuf], path: P) -> Option<&PathBuf>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    include.iter().find(|entry| {
+        path.starts_with(entry)
+            || (paths::is_pattern(entry)
+                && paths::glob_set(&[entry.to_string_lossy()])
+                    .is_ok_and(|matcher| matcher.is_match(path)))
+    })
+}
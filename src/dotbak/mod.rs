@@ -1,18 +1,68 @@
+pub mod builder;
 pub mod daemon;
-mod logger;
+pub mod list;
+pub mod logger;
+pub mod managed;
+pub mod pin;
+pub mod quarantine;
+pub mod queue;
+pub mod runs;
+pub mod status;
 mod tests;
 
+use self::builder::DotbakBuilder;
+use self::daemon::Daemon;
+use self::list::ListEntry;
 use self::logger::Logger;
-use crate::ui::{messages::*, Interface};
+use self::managed::{ManagedEntry, ManagedFiles};
+use self::pin::Pin;
+use self::quarantine::Quarantine;
+use self::queue::QueuedPush;
+use self::runs::{Run, RunKind, RunLog};
+use self::status::Status;
+#[cfg(any(test, feature = "testing"))]
+use crate::test_util::Machine;
+use crate::ui::{messages::*, FileProgress, Interface, Verbosity};
+use crate::xdg::BaseDirs;
 use crate::{
-    config::Config,
-    errors::{config::ConfigError, DotbakError, Result},
-    files::Files,
-    git::Repository,
+    bundle, clock,
+    config::{files::HardlinkPolicy, git::RemoteConfig, overrides::Overrides, Config, Reload},
+    diagnostics,
+    eject::{self, InstallStyle},
+    errors::{config::ConfigError, git::GitError, io::IoError, DotbakError, Result},
+    export::{self, Snapshot},
+    files::{
+        self, compare, encrypt,
+        metadata::{self, Manifest},
+        variant, Files, LinkState, PruneMode,
+    },
+    git::{
+        self,
+        auth::{AuthFailure, Credentials},
+        CloneOptions, LogEntry, MergeSide, ProgressHandler, Repository,
+    },
+    hooks::{self, Hook},
+    lock::Lock,
+    network::{self, Metered},
+    packages::{self, Manager},
+    paths,
+    platform::{self, SystemTrash, Trash},
+    plugins::{self, LockStatus},
+    rewrite::{self, HardcodedPath},
+    secrets::Scanner,
+    stow,
+    suggest::{self, Coverage},
 };
+use age::secrecy::SecretString;
+use indicatif::{HumanBytes, HumanDuration};
 use itertools::Itertools;
-use std::fs::File;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// The path to the configuration file, relative to `XDG_CONFIG_HOME`.
 pub(crate) const CONFIG_FILE_NAME: &str = "config.toml";
@@ -20,6 +70,16 @@ pub(crate) const CONFIG_FILE_NAME: &str = "config.toml";
 /// The path to the git repository folder, relative to `XDG_DATA_HOME`.
 pub(crate) const REPO_FOLDER_NAME: &str = "dotfiles";
 
+/// How many of the largest files/folders are shown when what's added is larger than `files.add_size_limit`.
+const LARGEST_SHOWN: usize = 5;
+
+/// The message of the commits of syncs.
+const SYNC_COMMIT_MSG: &str = "🔄 Sync files";
+
+/// The message of the commits of automated syncs (see `Dotbak::automated`), which tells them apart from the ones the
+/// user made, so that only they are compacted.
+const AUTOMATED_SYNC_COMMIT_MSG: &str = "🔄 Sync files automatically";
+
 /// The main structure to manage `dotbak`'s actions and such.
 pub struct Dotbak {
     /// The configuration for `dotbak`.
@@ -36,59 +96,177 @@ pub struct Dotbak {
 
     /// The interface for `dotbak`.
     interface: Interface,
+
+    /// Where deleted files/folders are moved to, unless `files.permanent_delete` is set. See `set_trash`.
+    trash: Arc<dyn Trash>,
+
+    /// Whether to commit files even if they look like they contain secrets. See `allow_secrets`.
+    allow_secrets: bool,
+
+    /// Whether to overwrite real files in the home directory that differ from the tracked version, and to add large
+    /// files without asking. See `force`.
+    force: bool,
+
+    /// Whether to sync without pulling or pushing, queueing the push instead. See `offline`.
+    offline: bool,
+
+    /// Whether syncs are automated, i.e. run by the daemon. Their commits are compacted according to the `daemon`
+    /// configuration (see `commit_sync`).
+    automated: bool,
 }
 
 /// Public API for `Dotbak`.
 impl Dotbak {
     /// Create a new instance of `dotbak`. If the configuration file does not exist, it will be created.
     /// If it does exist, it will be loaded.
-    pub fn init(verbose: bool) -> Result<Self> {
-        let (home, config, repo) = get_dotbak_dirs();
-        let mut dotbak = Self::init_into_dirs(home, config, repo, verbose)?;
-
-        dotbak.sync_all_files()?;
-
-        Ok(dotbak)
+    pub fn init(verbosity: Verbosity) -> Result<Self> {
+        Self::builder().verbosity(verbosity).init()
     }
 
     /// Clone a remote repository to the local repository. If the local repository already exists, it will be
     /// deleted and re-cloned.
-    pub fn clone(url: &str, verbose: bool) -> Result<Self> {
-        let (home, config, repo) = get_dotbak_dirs();
-        let mut dotbak = Self::clone_into_dirs(home, config, repo, url, verbose)?;
-
-        dotbak.sync_all_files()?;
+    ///
+    /// If `low_bandwidth` is set (or `network.low_bandwidth` is set in the configuration), the clone is shallow
+    /// and skips git-lfs content.
+    pub fn clone(url: &str, verbosity: Verbosity, low_bandwidth: bool) -> Result<Self> {
+        Self::builder()
+            .verbosity(verbosity)
+            .low_bandwidth(low_bandwidth)
+            .clone(url)
+    }
 
-        Ok(dotbak)
+    /// Restore an instance of `dotbak` from an offline bundle created by `create_bundle`, decrypting it with
+    /// `passphrase`. This needs no network access, and will return an error if the configuration file already
+    /// exists.
+    pub fn restore_bundle<P>(
+        path: P,
+        passphrase: SecretString,
+        verbosity: Verbosity,
+    ) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::builder()
+            .verbosity(verbosity)
+            .restore_bundle(path, passphrase)
     }
 
     /// Creates a new instance of `dotbak` from pre-defined configuration. If the configuration file does not exist,
     /// an error will be returned. If it does exist, it will be loaded.
-    pub fn load(verbose: bool) -> Result<Self> {
-        let (home, config, repo) = get_dotbak_dirs();
-        let mut dotbak = Self::load_into_dirs(home, config, repo, verbose)?;
+    pub fn load(verbosity: Verbosity) -> Result<Self> {
+        Self::builder().verbosity(verbosity).load()
+    }
 
-        dotbak.sync_all_files()?;
+    /// Turns on low bandwidth mode, even if it is not turned on in the configuration. See
+    /// `network.low_bandwidth` in the configuration for what this does.
+    pub fn enable_low_bandwidth(&mut self) {
+        self.repo.set_low_bandwidth(true);
+    }
 
-        Ok(dotbak)
+    /// Commits files even if they look like they contain secrets, for this instance only. See `secrets` in the
+    /// configuration for what is looked for.
+    pub fn allow_secrets(&mut self) {
+        self.allow_secrets = true;
+    }
+
+    /// Overwrites the real files in the home directory that replaced the symlinks of managed files with the tracked
+    /// version when syncing, even if they differ from it, for this instance only. Otherwise, the user chooses what
+    /// happens to them (see `protect_overwritten`). Files larger than `files.add_size_limit` are added without asking,
+    /// too (see `confirm_add_size`).
+    pub fn force(&mut self) {
+        self.force = true;
+    }
+
+    /// Syncs without pulling or pushing, for this instance only: the changes are committed locally, and the push is
+    /// queued for the next sync that reaches the remote (see `QueuedPush`). Syncs also go offline like this by
+    /// themselves when the remote can't be reached.
+    pub fn offline(&mut self) {
+        self.offline = true;
+    }
+
+    /// Sets where deleted files/folders (like orphaned files, or the repository in `deinit`) are moved to, which is
+    /// the system trash until then. They're deleted permanently instead if `files.permanent_delete` is set.
+    pub fn set_trash(&mut self, trash: Arc<dyn Trash>) {
+        self.dotfiles.set_trash(trash.clone());
+        self.trash = trash;
+    }
+
+    /// Like `load`, but does not synchronize the files afterwards. This is for commands that need to see the
+    /// home directory as-is, before `dotbak` replaces anything in it.
+    pub fn load_without_sync(verbosity: Verbosity) -> Result<Self> {
+        Self::builder().verbosity(verbosity).load_without_sync()
     }
 
-    /// Like `load`, but specifically for daemons: Will take two files as stdout and stderr, and
-    /// silence the interface.
-    pub fn load_for_daemon(stdout: File, stderr: File) -> Result<Self> {
-        let mut dotbak = Self::load(true)?;
+    /// Creates a builder to set up or load `dotbak` in custom directories, e.g. when embedding it in other tools.
+    pub fn builder() -> DotbakBuilder {
+        DotbakBuilder::new()
+    }
+
+    /// Gets the configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Gets the managed files.
+    pub fn files(&self) -> &Files {
+        &self.dotfiles
+    }
+
+    /// Gets the repository.
+    pub fn repository(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Gets the logger.
+    pub fn logger(&self) -> &Logger {
+        &self.logger
+    }
+
+    /// Like `load_without_sync`, but specifically for daemons: Will take two files as stdout and stderr, and
+    /// silence the interface. The daemon syncs right away once it holds the lock (see `LockMode`).
+    pub fn load_for_daemon(builder: DotbakBuilder, stdout: File, stderr: File) -> Result<Self> {
+        let mut dotbak = builder.verbosity(Verbosity::Debug).load_without_sync()?;
 
         dotbak.interface.silence();
+        dotbak.automated = true;
 
-        dotbak.logger = Logger::new_with_streams(true, Box::new(stdout), Box::new(stderr));
+        // Automated syncs wait on the staging branch until they're promoted, if enabled.
+        if dotbak.config.history.staging {
+            dotbak.repo.set_push_branch(Some(git::STAGING_BRANCH_NAME));
+        }
+
+        // Nobody reads the daemon's output as it goes, so it's logged as JSON for tools to pick up.
+        dotbak.logger = Logger::new(Verbosity::Debug, dotbak.dotfiles.home_dir())
+            .with_streams(Box::new(stdout), Box::new(stderr))
+            .json();
 
         Ok(dotbak)
     }
 
-    /// Sync the state. I.e., load all the files that are supposed to be loaded through `files.include`.
+    /// Sync the state. I.e., load all the files that are supposed to be loaded through `files.include`. The sync is
+    /// recorded in the history of runs (see `run_log`).
     pub fn sync(&mut self) -> Result<()> {
+        self.record_run(RunKind::Sync, Dotbak::run_sync)
+    }
+
+    /// Syncs the state, without recording the sync. See `sync`.
+    fn run_sync(&mut self) -> Result<()> {
+        self.run_hooks(Hook::PreSync)?;
+
+        // Warn about wrong clocks before making any commits with them.
+        self.check_clock_skew()?;
+
+        // Pick up lock files of plugin managers that were installed since the last sync.
+        if self.config.files.track_locks {
+            self.track_plugin_locks()?;
+        }
+
         // Make sure everything's up to date.
         self.sync_all_files()?;
+        self.mark_binaries()?;
+        self.update_lfs()?;
+        self.update_ignored()?;
+        self.export_packages();
 
         let (mut commit_spinner, mut pull_spinner, mut push_spinner, mut sync_spinner) = (
             self.interface.spawn_spinner(COMMIT_MSG, 0),
@@ -97,23 +275,106 @@ impl Dotbak {
             self.interface.spawn_spinner(SYNC_MSG, 0),
         );
 
-        // Commit to the repository.
+        // A pinned machine doesn't move past its pin, and keeps its own commits until it's unpinned.
+        let pin = Pin::load(self.state_dir()?)?;
+
+        if let Some(pin) = &pin {
+            self.warn_pinned(pin);
+        }
+
+        // Pull from the repository before committing, so that the local changes go on top of the remote's (see
+        // `git.pull_strategy`).
+        let mut offline = self.offline;
+        let pulled = match &pin {
+            Some(_) => Ok(vec![]),
+            None if offline => Ok(vec![]),
+            None => {
+                self.run_hooks(Hook::PrePull)?;
+                pull_spinner.start();
+                let before = self.repo.head();
+                let pulled = self.with_stash(|dotbak| {
+                    let output = dotbak.authenticated(Repository::pull)?;
+                    dotbak.logger.log_output(output);
+
+                    // When pushing to a branch other than the one worked on, its changes aren't on that branch yet.
+                    let branch = dotbak.repo.push_branch().to_string();
+
+                    if branch != dotbak.repo.branch() && dotbak.repo.has_remote_branch(&branch) {
+                        let output = dotbak.repo.pull_branch(&branch)?;
+                        dotbak.logger.log_output(output);
+                    }
+
+                    Ok(())
+                });
+                pull_spinner.close();
+
+                match pulled {
+                    Ok(()) => {
+                        self.pull_lfs()?;
+                        self.warn_unsigned(before.as_deref())?;
+                        let renamed = self.follow_upstream_renames(before)?;
+                        self.run_hooks(Hook::PostPull)?;
+
+                        Ok(renamed)
+                    }
+                    // Committing now would commit the conflicts.
+                    Err(err @ DotbakError::StashConflict { .. }) => return Err(err),
+                    // Without the remote, the changes are only committed, and pushed later.
+                    Err(DotbakError::Git(err)) if err.is_transient() => {
+                        self.logger
+                            .error(format!("Couldn't pull, going offline: {}", err));
+                        offline = true;
+
+                        Ok(vec![])
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        };
+
+        // Record the permissions git doesn't keep.
+        self.record_metadata()?;
+
+        // Make sure no secrets are committed by accident.
+        self.check_secrets()?;
+
+        // Commit to the repository. This happens even if pulling failed (e.g. when offline), so that the local
+        // changes are recorded.
         commit_spinner.start();
-        let outputs = self.repo.commit("🔄 Sync files")?;
+        let outputs = self.commit_sync()?;
         commit_spinner.close();
         self.logger.log_outputs(outputs);
+        let renamed = pulled?;
 
-        // Pull from the repository.
-        pull_spinner.start();
-        let output = self.repo.pull()?;
-        pull_spinner.close();
-        self.logger.log_output(output);
-
-        // Push to the repository.
+        // Push to the repository, unless large files should wait for an unmetered connection.
         push_spinner.start();
-        let output = self.repo.push()?;
-        push_spinner.close();
-        self.logger.log_output(output);
+        if pin.is_some() {
+            push_spinner.close();
+        } else if offline {
+            push_spinner.close();
+            self.queue_push("syncing offline")?;
+        } else if self.should_defer_push()? {
+            push_spinner.close();
+            self.interface
+                .warn("Deferred pushing large files until you're on an unmetered connection.");
+            self.logger
+                .info("Deferred push: low bandwidth mode is on and the connection is metered");
+        } else {
+            self.run_hooks(Hook::PrePush)?;
+
+            match self.push_remotes() {
+                Ok(()) => {
+                    push_spinner.close();
+                    self.run_hooks(Hook::PostPush)?;
+                }
+                Err(DotbakError::Git(err)) if err.is_transient() => {
+                    push_spinner.close();
+                    self.logger.error(format!("Couldn't push: {}", err));
+                    self.queue_push("the remote couldn't be reached")?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
 
         // Sync all files again.
         sync_spinner.start();
@@ -128,24 +389,44 @@ impl Dotbak {
                 .map(|f| f.display())
                 .join(", ")
         ));
+        self.report_renames(&renamed);
+        self.handle_unpushed()?;
+        self.run_hooks(Hook::PostSync)?;
 
         Ok(())
     }
 
     /// Add a set of files/folders to the repository. This will move the files/folders to the repository and
     /// symlink them to their original location. It also writes their paths to the configuration file in the `include`
-    /// list.
-    pub fn add<P>(&mut self, files: &[P]) -> Result<()>
+    /// list. If `rewrite_home` is set, hard-coded paths to the home directory in them are rewritten to `$HOME` or `~`,
+    /// otherwise the user is warned about them.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn add<P>(&mut self, files: &[P], rewrite_home: bool) -> Result<()>
     where
         P: AsRef<Path>,
     {
-        let (mut update_conf_spinner, mut sync_spinner, mut commit_spinner) = (
-            self.interface.spawn_spinner(UPDATE_CONF_MSG, 0),
-            self.interface.spawn_spinner(SYNC_MSG, 0),
-            self.interface.spawn_spinner(COMMIT_MSG, 0),
-        );
+        self.run_hooks(Hook::PreAdd)?;
+
+        let mut update_conf_spinner = self.interface.spawn_spinner(UPDATE_CONF_MSG, 0);
+
+        // Make the paths relative to the home directory, and make sure none of them would make dotbak manage itself.
+        let files = paths::preprocess(
+            files,
+            self.dotfiles.home_dir(),
+            &self.dotfiles.root_paths(),
+            self.dotfiles.file_dir(),
+            &self.config.path,
+            key_path(&self.config, self.dotfiles.home_dir()),
+        )?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
 
-        let files = preprocess_paths(files);
+        // Make sure nothing huge (like the caches in `.config`) ends up in the history by accident.
+        if !self.confirm_add_size(&files)? {
+            self.interface.warn("Nothing was added. Ignore the large files with `dotbak ignore add`, or pass `--force` to add them anyways.");
+            return Ok(());
+        }
 
         // Add the paths to the `include` list.
         update_conf_spinner.start();
@@ -161,24 +442,44 @@ impl Dotbak {
             files.iter().map(|p| p.display()).join(", ")
         ));
 
-        // Move the files/folders to the repository and symlink them to their original location.
-        sync_spinner.start();
-        self.sync_files(&files)?;
-        sync_spinner.close();
+        // Check out the versions of the files that other machines already committed, instead of replacing them.
+        self.update_sparse_checkout()?;
+
+        // Move the files/folders (the ones matching the patterns among them, that is) to the repository and symlink
+        // them to their original location.
+        let matched = self.dotfiles.resolve_globs(&files)?;
+        let progress = self.spawn_progress(SYNC_MSG, matched.len());
+        self.sync_files(&matched)?;
+        self.close_progress(progress);
         self.logger.info(format!(
             "Synced files: {}",
-            files.iter().map(|p| p.display()).join(", ")
+            matched.iter().map(|p| p.display()).join(", ")
         ));
 
+        // Deal with hard-coded paths to the home directory, which break on machines with a different home directory.
+        self.check_home_paths(&files, rewrite_home)?;
+
+        // Leave out the ignored files in the added folders.
+        self.update_ignored()?;
+
+        // Record the permissions git doesn't keep.
+        self.record_metadata()?;
+        self.record_managed(&matched)?;
+
+        // Make sure no secrets are committed by accident.
+        self.check_secrets()?;
+
         // Commit to the repository.
         // TODO: Make this message configurable.
-        commit_spinner.start();
-        let outputs = self.repo.commit(&format!(
+        let commit_spinner = self.interface.spawn_spinner(COMMIT_MSG, 0);
+        let outputs = self.commit(&format!(
             "📦 Added files: {}",
             files.iter().map(|p| p.display()).join(", ")
         ))?;
         commit_spinner.close();
         self.logger.log_outputs(outputs);
+        self.handle_unpushed()?;
+        self.run_hooks(Hook::PostAdd)?;
 
         Ok(())
     }
@@ -186,6 +487,7 @@ impl Dotbak {
     /// Remove a set of files/folders from the repository. This will remove the files/folders from the repository
     /// and restore them to their original location. It also removes their paths from the configuration file in the
     /// `include` list.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
     pub fn remove<P>(&mut self, files: &[P]) -> Result<()>
     where
         P: AsRef<Path>,
@@ -196,7 +498,45 @@ impl Dotbak {
             self.interface.spawn_spinner(COMMIT_MSG, 0),
         );
 
-        let files = preprocess_paths(files);
+        let files = files
+            .iter()
+            .map(|file| self.dotfiles.managed_path(file))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Files that are gone from the repository (e.g. deleted by hand along with their entry in `files.include`)
+        // can't be restored, but if this machine managed them, their symlinks are left over.
+        let mut managed = ManagedFiles::load(self.state_dir()?)?;
+        let (present, gone): (Vec<_>, Vec<_>) = files.iter().partition(|file| {
+            self.dotfiles.is_encrypted(file) || self.dotfiles.is_managed_in_repo(file)
+        });
+
+        if let Some(file) = gone.iter().find(|file| managed.get(file).is_none()) {
+            return Err(DotbakError::NotManaged {
+                path: file.to_path_buf(),
+            });
+        }
+
+        // Remove the files/folders from the repository and restore them to their original location. This happens
+        // first, so that the configuration is left alone if it fails.
+        rm_files_spinner.start();
+        self.dotfiles.remove_and_restore(&present)?;
+
+        for file in gone {
+            if self.dotfiles.is_managed_in_home(file) {
+                self.dotfiles.remove_link(file)?;
+            }
+        }
+
+        for file in &files {
+            managed.forget(file);
+        }
+
+        managed.save()?;
+        rm_files_spinner.close();
+        self.logger.info(format!(
+            "Restored files: {}",
+            files.iter().map(|p| p.display()).join(", ")
+        ));
 
         // Remove the paths from the `include` list.
         update_conf_spinner.start();
@@ -213,68 +553,328 @@ impl Dotbak {
             files.iter().map(|p| p.display()).join(", ")
         ));
 
-        // Remove the files/folders from the repository and restore them to their original location.
-        rm_files_spinner.start();
-        self.dotfiles.remove_and_restore(&files)?;
-        rm_files_spinner.close();
-        self.logger.info(format!(
-            "Restored files: {}",
-            files.iter().map(|p| p.display()).join(", ")
-        ));
-
         // Commit to the repository.
         // TODO: Make this message configurable.
         commit_spinner.start();
-        let outputs = self.repo.commit(&format!(
+        let outputs = self.commit(&format!(
             "❌ Removed files: {}",
             files.iter().map(|p| p.display()).join(", ")
         ))?;
         commit_spinner.close();
         self.logger.log_outputs(outputs);
+        self.handle_unpushed()?;
+
+        Ok(())
+    }
+
+    /// Adds the repository at `url` (e.g. a shared `nvim` configuration) as a git submodule of the repository at
+    /// `path`, and manages `path` like `add` does, so that it's symlinked into the home directory. Nothing may be at
+    /// `path` in the home directory yet. The submodule is cloned and updated along with the repository.
+    pub fn add_module<P>(&mut self, url: &str, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let (mut update_conf_spinner, mut module_spinner, mut sync_spinner, mut commit_spinner) = (
+            self.interface.spawn_spinner(UPDATE_CONF_MSG, 0),
+            self.interface.spawn_spinner(ADD_MODULE_MSG, 0),
+            self.interface.spawn_spinner(SYNC_MSG, 0),
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+        );
+
+        let path = paths::preprocess(
+            &[path],
+            self.dotfiles.home_dir(),
+            &self.dotfiles.root_paths(),
+            self.dotfiles.file_dir(),
+            &self.config.path,
+            key_path(&self.config, self.dotfiles.home_dir()),
+        )?
+        .remove(0)
+        .into_path_buf();
+
+        if fs::symlink_metadata(self.dotfiles.home_dir().join(&path)).is_ok() {
+            return Err(DotbakError::ModuleExists { path });
+        }
+
+        // Clone the module first, so that the configuration is left alone if it fails.
+        module_spinner.start();
+        let output = self.authenticated(|repo| repo.add_submodule(url, &path))?;
+        module_spinner.close();
+        self.logger.log_output(output);
+
+        update_conf_spinner.start();
+        self.config.files.include.push(path.clone());
+        self.config.save_config()?;
+        update_conf_spinner.close();
+        self.logger
+            .info(format!("Added module '{}' from '{}'", path.display(), url));
+
+        sync_spinner.start();
+        self.sync_files(&[&path])?;
+        sync_spinner.close();
+
+        // Commit to the repository.
+        commit_spinner.start();
+        let outputs =
+            self.repo
+                .commit(&format!("📦 Added module: {} ({})", path.display(), url))?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+        self.handle_unpushed()?;
 
         Ok(())
     }
 
-    /// Undo the last *local* commit to the repository and restore the files/folders that were changed in that commit.
-    /// This will not affect the remote repository.
-    pub fn undo(&mut self) -> Result<()> {
+    /// Ignores the files matching `patterns` (in `.gitignore` syntax, relative to the home directory) in the
+    /// repository, e.g. caches inside of managed folders, by adding them to `ignore.patterns`. Files matching them
+    /// that are already in the repository are removed from it, but not deleted.
+    pub fn ignore<S>(&mut self, patterns: &[S]) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let mut update_conf_spinner = self.interface.spawn_spinner(UPDATE_CONF_MSG, 0);
+
+        update_conf_spinner.start();
+        for pattern in patterns {
+            if !self
+                .config
+                .ignore
+                .patterns
+                .iter()
+                .any(|p| p == pattern.as_ref())
+            {
+                self.config
+                    .ignore
+                    .patterns
+                    .push(pattern.as_ref().to_string());
+            }
+        }
+
+        self.config.save_config()?;
+        update_conf_spinner.close();
+
+        self.commit_ignored(&format!(
+            "🙈 Ignored: {}",
+            patterns.iter().map(|p| p.as_ref()).join(", ")
+        ))
+    }
+
+    /// Stops ignoring the files matching `patterns` in the repository, by removing them from `ignore.patterns`. The
+    /// files are committed again with the next sync.
+    pub fn unignore<S>(&mut self, patterns: &[S]) -> Result<()>
+    where
+        S: AsRef<str>,
+    {
+        let mut update_conf_spinner = self.interface.spawn_spinner(UPDATE_CONF_MSG, 0);
+
+        for pattern in patterns {
+            if !self
+                .config
+                .ignore
+                .patterns
+                .iter()
+                .any(|p| p == pattern.as_ref())
+            {
+                self.interface.warn(format!(
+                    "'{}' isn't in `ignore.patterns`.",
+                    pattern.as_ref()
+                ));
+            }
+        }
+
+        update_conf_spinner.start();
+        self.config
+            .ignore
+            .patterns
+            .retain(|p| !patterns.iter().any(|p2| p == p2.as_ref()));
+        self.config.save_config()?;
+        update_conf_spinner.close();
+
+        self.commit_ignored(&format!(
+            "👀 Stopped ignoring: {}",
+            patterns.iter().map(|p| p.as_ref()).join(", ")
+        ))
+    }
+
+    /// Remove the managed files/folders that `paths` and `all_matching` select, in a single commit. `paths` may be
+    /// plain paths or glob patterns (see `paths::select`), and `all_matching` selects the entries with a file or
+    /// folder name matching it (see `paths::select_all_matching`). If anything is selected by a pattern, the
+    /// selected entries are shown, and the user has to confirm removing them (unless `yes` is set).
+    pub fn remove_matching<P>(
+        &mut self,
+        paths: &[P],
+        all_matching: Option<&str>,
+        yes: bool,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let include = &self.config.files.include;
+        let mut selected = paths::select(
+            paths,
+            include,
+            self.dotfiles.home_dir(),
+            &self.dotfiles.root_paths(),
+        )?;
+
+        if let Some(pattern) = all_matching {
+            for entry in paths::select_all_matching(pattern, include)? {
+                if !selected.contains(&entry) {
+                    selected.push(entry);
+                }
+            }
+        }
+
+        let matched = all_matching.is_some() || paths.iter().any(paths::is_pattern);
+
+        if selected.is_empty() {
+            self.interface
+                .warn("Nothing managed matches, so nothing was removed.");
+            return Ok(());
+        }
+
+        if matched && !yes {
+            self.interface.println(format!(
+                "This removes {} managed file(s)/folder(s):\n{}",
+                selected.len(),
+                selected
+                    .iter()
+                    .map(|entry| format!("   {}", entry.display()))
+                    .join("\n")
+            ));
+
+            if self
+                .interface
+                .choose("Remove them?", &["keep them", "remove them"])?
+                == 0
+            {
+                return Ok(());
+            }
+        }
+
+        self.remove(&selected)
+    }
+
+    /// Undoes the newest `count` commits to the repository, or the commit `commit` (e.g. its hash) if it's given,
+    /// after showing them and asking for confirmation (unless `yes` is set). Commits that weren't pushed yet are
+    /// removed from the history, keeping their changes staged. Commits that were pushed already, or that aren't the
+    /// newest ones, are reverted with new commits instead, so that the remote's history isn't rewritten. Only the
+    /// files/folders the commits touched are synced afterwards.
+    pub fn undo(&mut self, count: usize, commit: Option<&str>, yes: bool) -> Result<()> {
         let (mut undo_spinner, mut sync_spinner) = (
             self.interface.spawn_spinner(UNDO_MSG, 0),
             self.interface.spawn_spinner(SYNC_MSG, 0),
         );
 
+        let entries =
+            match commit {
+                Some(commit) => vec![self.repo.find_commit(commit)?.ok_or_else(|| {
+                    DotbakError::UnknownCommit {
+                        commit: commit.to_string(),
+                    }
+                })?],
+                None => self.repo.log(None, Some(count))?,
+            };
+
+        if entries.is_empty() {
+            self.interface
+                .println("📭 Nothing to undo, there are no commits yet.");
+
+            return Ok(());
+        }
+
+        if commit.is_none() && entries.len() < count {
+            return Err(DotbakError::TooFewCommits {
+                count,
+                available: entries.len(),
+            });
+        }
+
+        let newest = match commit {
+            Some(_) => self.repo.head().as_ref() == Some(&entries[0].commit.hash),
+            None => true,
+        };
+        let uncommits = newest
+            && self.repo.has_commit(&format!("HEAD~{}", entries.len()))
+            && !entries
+                .iter()
+                .any(|entry| self.repo.is_pushed(&entry.commit.hash));
+
+        self.interface
+            .println(format!("⏪ This undoes {} commit(s):", entries.len()));
+
+        for entry in &entries {
+            self.interface.println(format!(
+                "   {} {} {}",
+                &entry.commit.hash[..7.min(entry.commit.hash.len())],
+                entry.commit.summary,
+                console::style(format!("({}, {})", entry.commit.author, entry.commit.date)).dim()
+            ));
+        }
+
+        self.interface.println(match uncommits {
+            true => "They weren't pushed yet, so they're removed from the history, keeping their changes.",
+            false => "They're reverted with new commits, as they were pushed already or aren't the newest ones.",
+        });
+
+        if !yes
+            && self
+                .interface
+                .choose("Undo them?", &["keep them", "undo them"])?
+                == 0
+        {
+            return Ok(());
+        }
+
         undo_spinner.start();
-        let output = self.repo.arbitrary_command(&["reset", "--soft", "HEAD~"])?;
+        let output = match uncommits {
+            true => self.repo.uncommit(entries.len())?,
+            false => {
+                let hashes = entries
+                    .iter()
+                    .map(|entry| entry.commit.hash.as_str())
+                    .collect_vec();
+
+                self.repo.revert(&hashes)?
+            }
+        };
         undo_spinner.close();
         self.logger.log_output(output);
 
+        // Reverting might have changed the configuration file, e.g. if a commit added a file to `files.include`.
+        if !uncommits && self.config_path_in_repo().is_some() {
+            self.reload_config()?;
+        }
+
+        let changed = entries
+            .iter()
+            .flat_map(|entry| entry.files.iter().cloned())
+            .collect_vec();
+        let affected = files::covering(
+            &changed,
+            &self.config.files.include,
+            &self.config.files.roots,
+        );
+
         sync_spinner.start();
-        self.sync_all_files()?;
+        self.sync_files(&affected)?;
         sync_spinner.close();
         self.logger.info(format!(
             "Synced files: {}",
-            self.config
-                .files
-                .include
-                .iter()
-                .map(|f| f.display())
-                .join(", ")
+            affected.iter().map(|f| f.display()).join(", ")
         ));
 
         Ok(())
     }
 
-    /// Push the repository to the remote.
-    /// TODO: Logging/tracing and such.
+    /// Push the repository to the remote. The push is recorded in the history of runs (see `run_log`).
     pub fn push(&mut self) -> Result<()> {
-        let (mut sync_spinner, mut push_spinner) = (
-            self.interface.spawn_spinner(SYNC_MSG, 0),
-            self.interface.spawn_spinner(PUSH_MSG, 0),
-        );
+        self.record_run(RunKind::Push, Dotbak::run_push)
+    }
 
-        sync_spinner.start();
+    /// Pushes the repository, without recording the push. See `push`.
+    fn run_push(&mut self) -> Result<()> {
         self.sync_all_files()?;
-        sync_spinner.close();
         self.logger.info(format!(
             "Synced files: {}",
             self.config
@@ -285,30 +885,125 @@ impl Dotbak {
                 .join(", ")
         ));
 
-        push_spinner.start();
-        let output = self.repo.push()?;
+        self.run_hooks(Hook::PrePush)?;
+        let push_spinner = self.interface.spawn_spinner(PUSH_MSG, 0);
+        self.push_remotes()?;
         push_spinner.close();
-        self.logger.log_output(output);
+        self.record_unpushed()?;
+        self.run_hooks(Hook::PostPush)?;
 
         Ok(())
     }
 
-    /// Pull changes from the remote.
-    /// TODO: Logging/tracing and such.
+    /// Pins this machine to the commit `commit` (or the current one, if it's `None`), so that `sync` and `pull` don't
+    /// move the repository past it until `unpin` is run, e.g. while a change that broke something on this machine
+    /// gets fixed. Pinning to an older commit moves the repository (and so the managed files) back to it. Commits
+    /// made while pinned stay on this machine, and are pushed once it's unpinned.
+    ///
+    /// Returns the hash of the pinned commit.
+    pub fn pin(&mut self, commit: Option<&str>) -> Result<String> {
+        let rev = commit.unwrap_or("HEAD");
+        let hash = self
+            .repo
+            .find_commit(rev)?
+            .ok_or_else(|| DotbakError::UnknownCommit {
+                commit: rev.to_string(),
+            })?
+            .commit
+            .hash;
+
+        if !self.repo.is_ancestor(&hash, "HEAD") {
+            return Err(DotbakError::PinNotInHistory {
+                commit: rev.to_string(),
+            });
+        }
+
+        // The commits after the pinned one are only kept on the remote until unpinning.
+        if self.repo.head().as_deref() != Some(hash.as_str()) {
+            if !self.repo.is_pushed("HEAD") {
+                return Err(DotbakError::PinUnpushed {
+                    commit: rev.to_string(),
+                });
+            }
+
+            let mut pin_spinner = self.interface.spawn_spinner(PIN_MSG, 0);
+            pin_spinner.start();
+            let output = self.repo.reset_to(&hash)?;
+            pin_spinner.close();
+            self.logger.log_output(output);
+
+            self.sync_all_files()?;
+        }
+
+        Pin {
+            commit: hash.clone(),
+            pinned: clock::now(),
+        }
+        .save(self.state_dir()?)?;
+        self.logger.info(format!("Pinned to {}", hash));
+
+        Ok(hash)
+    }
+
+    /// Unpins this machine (see `pin`), so that the next `sync` or `pull` catches up with the remote again. Returns
+    /// the pin, or `None` if the machine wasn't pinned.
+    pub fn unpin(&mut self) -> Result<Option<Pin>> {
+        let dir = self.state_dir()?;
+        let pin = Pin::load(&dir)?;
+
+        if Pin::remove(&dir)? {
+            self.logger.info("Unpinned");
+        }
+
+        Ok(pin)
+    }
+
+    /// Gets the commit this machine is pinned to, if it is (see `pin`).
+    pub fn pinned(&self) -> Result<Option<Pin>> {
+        Pin::load(self.state_dir()?)
+    }
+
+    /// Gets the push that's queued until the remote can be reached, if there is one (see `offline`).
+    pub fn queued_push(&self) -> Result<Option<QueuedPush>> {
+        QueuedPush::load(self.state_dir()?)
+    }
+
+    /// Gets the history of the recent syncs, pushes and pulls on this machine (see `dotbak history`).
+    pub fn run_log(&self) -> Result<RunLog> {
+        RunLog::load(self.state_dir()?)
+    }
+
+    /// Pull changes from the remote. The pull is recorded in the history of runs (see `run_log`).
     pub fn pull(&mut self) -> Result<()> {
-        let (mut pull_spinner, mut sync_spinner) = (
-            self.interface.spawn_spinner(PULL_MSG, 0),
-            self.interface.spawn_spinner(SYNC_MSG, 0),
-        );
+        self.record_run(RunKind::Pull, Dotbak::run_pull)
+    }
+
+    /// Pulls changes, without recording the pull. See `pull`.
+    fn run_pull(&mut self) -> Result<()> {
+        if let Some(pin) = Pin::load(self.state_dir()?)? {
+            self.warn_pinned(&pin);
+
+            return Ok(());
+        }
+
+        self.run_hooks(Hook::PrePull)?;
+
+        let mut pull_spinner = self.interface.spawn_spinner(PULL_MSG, 0);
 
         pull_spinner.start();
-        let output = self.repo.pull()?;
+        let before = self.repo.head();
+        self.with_stash(|dotbak| {
+            let output = dotbak.authenticated(Repository::pull)?;
+            dotbak.logger.log_output(output);
+
+            Ok(())
+        })?;
+        self.pull_lfs()?;
         pull_spinner.close();
-        self.logger.log_output(output);
+        self.warn_unsigned(before.as_deref())?;
+        let renamed = self.follow_upstream_renames(before)?;
 
-        sync_spinner.start();
         self.sync_all_files()?;
-        sync_spinner.close();
         self.logger.info(format!(
             "Synced files: {}",
             self.config
@@ -318,25 +1013,27 @@ impl Dotbak {
                 .map(|f| f.display())
                 .join(", ")
         ));
+        self.report_renames(&renamed);
+        self.record_unpushed()?;
+        self.run_hooks(Hook::PostPull)?;
 
         Ok(())
     }
 
-    /// Run an arbitrary git command on the repository.
-    pub fn arbitrary_git_command(&mut self, args: &[&str]) -> Result<()> {
-        let (mut arbitrary_command_spinner, mut sync_spinner) = (
-            self.interface.spawn_spinner(ARBITRARY_GIT_CMD_MSG, 0),
-            self.interface.spawn_spinner(SYNC_MSG, 0),
-        );
+    /// Run an arbitrary git command on the repository, with its output going straight to the terminal (see
+    /// `Repository::passthrough`). The files are synchronized afterwards if it succeeded.
+    ///
+    /// Returns how git exited.
+    pub fn arbitrary_git_command(&mut self, args: &[&str]) -> Result<ExitStatus> {
+        let status = self.interface.suspend(|| self.repo.passthrough(args))?;
+        self.logger
+            .info(format!("Ran 'git {}': {}", args.join(" "), status));
 
-        arbitrary_command_spinner.start();
-        let output = self.repo.arbitrary_command(args)?;
-        arbitrary_command_spinner.close();
-        self.logger.log_output(output);
+        if !status.success() {
+            return Ok(status);
+        }
 
-        sync_spinner.start();
         self.sync_all_files()?;
-        sync_spinner.close();
         self.logger.info(format!(
             "Synced files: {}",
             self.config
@@ -347,25 +1044,216 @@ impl Dotbak {
                 .join(", ")
         ));
 
-        Ok(())
+        Ok(status)
     }
 
-    // Deinitializes `dotbak`, removing the configuration file and the repository. This also restores all files
-    // that were managed by `dotbak` to their original location.
-    pub fn deinit(mut self) -> Result<()> {
-        let (mut restore_files_spinner, mut rm_config_spinner, mut rm_repo_spinner) = (
-            self.interface.spawn_spinner(RESTORE_FILES_MSG, 0),
-            self.interface.spawn_spinner(RM_CONFG_MSG, 0),
-            self.interface.spawn_spinner(RM_REPO_MSG, 0),
+    /// Move the repository to `path`, and rewrite all the symlinks in the home directory to point to the new
+    /// location. `path` is resolved like `repo_path` in the configuration, and is saved there afterwards. If the
+    /// symlinks or the configuration can't be updated, the repository is moved back.
+    pub fn relocate_repo<P>(&mut self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let (
+            mut move_repo_spinner,
+            mut relink_spinner,
+            mut update_conf_spinner,
+            mut commit_spinner,
+        ) = (
+            self.interface.spawn_spinner(MOVE_REPO_MSG, 0),
+            self.interface.spawn_spinner(RELINK_MSG, 0),
+            self.interface.spawn_spinner(UPDATE_CONF_MSG, 0),
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
         );
 
-        // Restore all files that were managed by `dotbak` to their original location.
-        restore_files_spinner.start();
-        self.dotfiles
-            .remove_and_restore(&self.config.files.include)?;
-        restore_files_spinner.close();
+        let home = self.dotfiles.home_dir().to_path_buf();
+        let old_repo = self.dotfiles.file_dir().to_path_buf();
+        let new_repo = paths::resolve_home(path, &home);
+
+        self.config.check_repo_dir(&new_repo, &home)?;
+
+        if new_repo.exists() {
+            return Err(IoError::AlreadyExists { path: new_repo }.into());
+        }
+
+        // Move the repository itself.
+        move_repo_spinner.start();
+        if let Some(parent) = new_repo.parent() {
+            fs::create_dir_all(parent).map_err(|err| IoError::Create {
+                source: err,
+                path: parent.to_path_buf(),
+            })?;
+        }
+
+        move_dir(&old_repo, &new_repo)?;
+        move_repo_spinner.close();
         self.logger.info(format!(
-            "Restored files: {}",
+            "Moved repository from '{}' to '{}'",
+            old_repo.display(),
+            new_repo.display()
+        ));
+
+        if old_repo.exists() {
+            self.interface.warn(format!(
+                "'{}' couldn't be deleted entirely after copying it, delete what's left of it yourself.",
+                old_repo.display()
+            ));
+        }
+
+        // Point all the symlinks at the new location. This has to happen before saving the configuration, as
+        // the configuration file itself is normally symlinked into the repository.
+        relink_spinner.start();
+        let old_repo_path = self.config.repo_path.clone();
+        let switched = self
+            .switch_repo(&home, &old_repo, &new_repo)
+            .and_then(|skipped| {
+                relink_spinner.close();
+
+                for file in skipped {
+                    self.interface.warn(format!(
+                        "Did not relink '{}', as it is not a symlink into the old repository.",
+                        file.display()
+                    ));
+                }
+
+                // Save the new location. Keep it relative to the home directory if possible, so that the
+                // configuration works across machines.
+                update_conf_spinner.start();
+                self.config.repo_path = Some(
+                    new_repo
+                        .strip_prefix(&home)
+                        .unwrap_or(&new_repo)
+                        .to_path_buf(),
+                );
+                self.config.save_config()?;
+                update_conf_spinner.close();
+
+                Ok(())
+            });
+
+        // Move the repository back, so that it's where the configuration (which wasn't saved) says it is.
+        if let Err(err) = switched {
+            self.interface.warn(format!(
+                "Moving the repository failed, moving it back to '{}'",
+                old_repo.display()
+            ));
+            self.config.repo_path = old_repo_path;
+            move_dir(&new_repo, &old_repo)?;
+            self.switch_repo(&home, &new_repo, &old_repo)?;
+
+            return Err(err);
+        }
+
+        commit_spinner.start();
+        let outputs = self
+            .repo
+            .commit(&format!("🚚 Moved repository to '{}'", new_repo.display()))?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        self.interface.println(format!(
+            "📁 The repository is now at '{}'",
+            new_repo.display()
+        ));
+
+        Ok(())
+    }
+
+    /// Switches to the repository that was moved from `from` to `to`, and points the symlinks in the home directory
+    /// `home` at it. Returns the managed paths that weren't relinked, as they aren't symlinks into `from`.
+    fn switch_repo(&mut self, home: &Path, from: &Path, to: &Path) -> Result<Vec<PathBuf>> {
+        self.repo = Repository::load(to)?;
+        self.dotfiles = Files::init(home.to_path_buf(), to.to_path_buf());
+        self.dotfiles.set_trash(self.trash.clone());
+        configure_files(&mut self.dotfiles, &self.config)?;
+
+        self.dotfiles.relink(&self.config.files.include, from)
+    }
+
+    /// Absorb the history of another, independently initialized dotfiles repository. `source` is either a remote
+    /// URL or a path to the other repository. Both histories are kept and joined with a merge commit, the include
+    /// lists are combined, and the user is asked which version to keep for every conflicting file.
+    pub fn absorb(&mut self, source: &str) -> Result<()> {
+        let (mut commit_spinner, mut fetch_spinner, mut merge_spinner, mut update_conf_spinner) = (
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(FETCH_MSG, 0),
+            self.interface.spawn_spinner(MERGE_MSG, 0),
+            self.interface.spawn_spinner(UPDATE_CONF_MSG, 0),
+        );
+
+        // Git resolves local paths relative to the repository, so make them absolute first.
+        let source = fs::canonicalize(source)
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|_| source.to_string());
+
+        // Commit any pending changes first, so that the merge doesn't trip over them.
+        commit_spinner.start();
+        let outputs = self.commit(SYNC_COMMIT_MSG)?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        fetch_spinner.start();
+        let output = self.repo.fetch_head(&source)?;
+        fetch_spinner.close();
+        self.logger.log_output(output);
+
+        // Read the other include list before merging, as the configuration file itself will most likely conflict.
+        let config_in_repo = self.config_path_in_repo();
+        let other_include = match &config_in_repo {
+            Some(path) => self
+                .repo
+                .show_file("FETCH_HEAD", path)?
+                .and_then(|config| toml::from_str::<Config>(&config).ok())
+                .map(|config| config.files.include)
+                .unwrap_or_default(),
+            None => vec![],
+        };
+
+        merge_spinner.start();
+        let conflicts = self
+            .repo
+            .merge_unrelated("FETCH_HEAD", &format!("🔀 Absorbed '{}'", source))?;
+        merge_spinner.close();
+
+        if !conflicts.is_empty() {
+            for conflict in conflicts {
+                // Keep our configuration file. The include lists are combined below.
+                let side = if Some(&conflict) == config_in_repo.as_ref() {
+                    MergeSide::Ours
+                } else {
+                    match self.interface.choose(
+                        format!(
+                            "'{}' differs between the two repositories. Which version should be kept?",
+                            conflict.display()
+                        ),
+                        &["this one", "the absorbed one"],
+                    )? {
+                        0 => MergeSide::Ours,
+                        _ => MergeSide::Theirs,
+                    }
+                };
+
+                self.repo.resolve_conflict(&conflict, side)?;
+            }
+
+            let output = self.repo.commit_merge()?;
+            self.logger.log_output(output);
+        }
+
+        // Combine the include lists.
+        update_conf_spinner.start();
+        for path in other_include {
+            if !self.config.files.include.contains(&path) {
+                self.config.files.include.push(path);
+            }
+        }
+
+        self.config.save_config()?;
+        update_conf_spinner.close();
+
+        self.sync_all_files()?;
+        self.logger.info(format!(
+            "Synced files: {}",
             self.config
                 .files
                 .include
@@ -374,183 +1262,3280 @@ impl Dotbak {
                 .join(", ")
         ));
 
-        // Remove the configuration file.
-        rm_config_spinner.start();
-        self.config.delete_config()?;
-        rm_config_spinner.close();
-
-        // Remove the repository.
-        rm_repo_spinner.start();
-        self.repo.delete()?;
-        rm_repo_spinner.close();
+        let final_commit_spinner = self.interface.spawn_spinner(COMMIT_MSG, 0);
+        let outputs = self.commit("🔀 Combined include lists")?;
+        final_commit_spinner.close();
+        self.logger.log_outputs(outputs);
 
         Ok(())
     }
-}
 
-/// Private API for `Dotbak`. These are mainly used for testing.
-impl Dotbak {
-    /// Initialize a new instance of `dotbak`, loading the configuration file from `<dotbak>/config.toml` and the
-    /// repository from `<dotbak>/dotfiles`. The user's home directory is assumed to be `<home>`.
-    fn init_into_dirs<P1, P2, P3>(home: P1, config: P2, repo: P3, verbose: bool) -> Result<Self>
+    /// Import the stow directory `dir`, replacing the symlinks stow installed into the home directory with ones
+    /// managed by `dotbak`. Only `packages` are imported, or all of them if it's empty. Paths which are already
+    /// managed, or which are in the way of a package in the home directory (i.e. stow didn't install them), are
+    /// skipped and returned.
+    pub fn import_stow<P>(&mut self, dir: P, packages: &[String]) -> Result<Vec<PathBuf>>
     where
-        P1: AsRef<Path>,
-        P2: AsRef<Path>,
-        P3: AsRef<Path>,
+        P: AsRef<Path>,
     {
-        let config_path = config.as_ref().to_path_buf();
-        let repo_path = repo.as_ref().to_path_buf();
-        let home_path = home.as_ref().to_path_buf();
+        let import_spinner = self.interface.spawn_spinner(IMPORT_MSG, 0);
 
-        // Try to load the configuration file.
-        let config = match Config::load_config(&config_path) {
-            // If the configuration file exists, load it.
-            // TODO: log that the configuration file was loaded, not created.
-            Ok(config) => config,
+        let dir = fs::canonicalize(dir.as_ref()).map_err(|err| IoError::Read {
+            source: err,
+            path: dir.as_ref().to_path_buf(),
+        })?;
+        let packages = match packages.is_empty() {
+            true => stow::packages(&dir)?,
+            false => packages.to_vec(),
+        };
+        let home = self.dotfiles.home_dir().to_path_buf();
 
-            // If the configuration file does not exist, create it.
-            // TODO: log that the configuration file was created, not loaded.
-            Err(DotbakError::Config(ConfigError::NotFound { .. })) => {
-                Config::create_config(config_path)?
+        let (mut imported, mut skipped) = (vec![], vec![]);
+
+        for stowed in stow::paths(&dir, &packages, &home)? {
+            let target = home.join(&stowed.path);
+            let installed_by_stow = match fs::symlink_metadata(&target) {
+                Ok(meta) if meta.is_symlink() => {
+                    fs::canonicalize(&target).is_ok_and(|path| path.starts_with(&dir))
+                }
+                Ok(_) => false,
+                Err(_) => true,
+            };
+
+            if !installed_by_stow || self.config.files.include.contains(&stowed.path) {
+                skipped.push(stowed.path);
+                continue;
             }
 
-            // If the error is not a `ConfigNotFound` error, return it.
-            Err(err) => return Err(err),
-        };
+            if fs::symlink_metadata(&target).is_ok() {
+                platform::remove_link(&target).map_err(|err| IoError::Delete {
+                    source: err,
+                    path: target.clone(),
+                })?;
+            }
 
-        // Try to load the repository.
-        let repo = Repository::init(&repo_path, None)?;
+            stow::copy(&stowed.source, &target)?;
+            imported.push(stowed.path);
+        }
 
-        Ok(Dotbak {
-            dotfiles: Files::init(home_path, repo_path),
-            config,
-            repo,
-            logger: Logger::new(verbose),
-            interface: Interface::new(MAX_MSG_LEN),
-        })
+        import_spinner.close();
+        self.logger.info(format!(
+            "Imported files from stow: {}",
+            imported.iter().map(|p| p.display()).join(", ")
+        ));
+
+        // The copies in the home directory are added like any other files.
+        if !imported.is_empty() {
+            self.add(&imported, false)?;
+        }
+
+        Ok(skipped)
     }
 
-    /// Clone an instance of `dotbak`, cloning the repository from the given URL to `<dotbak>/dotfiles`.
-    /// The user's home directory is assumed to be `<home>`.
-    fn clone_into_dirs<P1, P2, P3>(
-        home: P1,
-        config: P2,
-        repo: P3,
-        url: &str,
-        verbose: bool,
-    ) -> Result<Self>
+    /// Takes over the symlinks at `files` in the home directory that another dotfiles manager (e.g. stow or
+    /// chezmoi) installed: the files/folders they point to are copied into the repository and added, and the
+    /// symlinks are replaced with ones into the repository. What they pointed to is left as it is. Paths which are
+    /// already managed, or which aren't symlinks pointing outside of the repository, are skipped and returned.
+    pub fn adopt<P>(&mut self, files: &[P]) -> Result<Vec<PathBuf>>
     where
-        P1: AsRef<Path>,
-        P2: AsRef<Path>,
-        P3: AsRef<Path>,
+        P: AsRef<Path>,
     {
-        let config_path = config.as_ref().to_path_buf();
-        let repo_path = repo.as_ref().to_path_buf();
-        let home_path = home.as_ref().to_path_buf();
+        let adopt_spinner = self.interface.spawn_spinner(ADOPT_MSG, 0);
 
-        // Try to load the configuration file.
-        let config = match Config::load_config(&config_path) {
-            // If the configuration file exists, load it.
-            // TODO: log that the configuration file was loaded, not created.
-            Ok(config) => config,
+        let files = paths::preprocess(
+            files,
+            self.dotfiles.home_dir(),
+            &self.dotfiles.root_paths(),
+            self.dotfiles.file_dir(),
+            &self.config.path,
+            key_path(&self.config, self.dotfiles.home_dir()),
+        )?
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
 
-            // If the configuration file does not exist, create it.
-            // TODO: log that the configuration file was created, not loaded.
-            Err(DotbakError::Config(ConfigError::NotFound { .. })) => {
-                Config::create_config(config_path)?
+        let (mut adopted, mut skipped) = (vec![], vec![]);
+
+        for file in files {
+            if !self.config.files.include.contains(&file)
+                && self.dotfiles.copy_link_target(&file)?
+            {
+                adopted.push(file);
+            } else {
+                skipped.push(file);
             }
+        }
 
-            // If the error is not a `ConfigNotFound` error, return it.
-            Err(err) => return Err(err),
+        adopt_spinner.close();
+        self.logger.info(format!(
+            "Adopted symlinks: {}",
+            adopted.iter().map(|p| p.display()).join(", ")
+        ));
+
+        // The copies in the home directory are added like any other files.
+        if !adopted.is_empty() {
+            self.add(&adopted, false)?;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Lists the files in the repository that no entry of `files.include` covers (e.g. because the configuration
+    /// file was edited by hand), so they aren't linked into the home directory. See `files::orphans`.
+    pub fn orphans(&mut self) -> Result<Vec<PathBuf>> {
+        Ok(files::orphans(
+            &self.repo.tracked_files()?,
+            &self.config.files.include,
+            &self.config.files.roots,
+        ))
+    }
+
+    /// Reconciles the orphaned files in the repository (see `orphans`): they're moved back into the home directory
+    /// or deleted, depending on `mode`, and the result is committed. Returns the orphans that were pruned, and the
+    /// ones that were skipped because something else is in their way in the home directory.
+    pub fn prune(&mut self, mode: PruneMode) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let (mut commit_spinner, mut prune_spinner) = (
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(PRUNE_MSG, 0),
+        );
+
+        // Commit any pending changes first, so that the orphans are the ones in the latest commit.
+        commit_spinner.start();
+        let outputs = self.commit(SYNC_COMMIT_MSG)?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        prune_spinner.start();
+        let orphans = self.orphans()?;
+        let skipped = match mode {
+            PruneMode::Restore => self.dotfiles.restore_orphans(&orphans)?,
+            PruneMode::Delete => {
+                self.dotfiles.delete_orphans(&orphans)?;
+                vec![]
+            }
         };
+        let pruned = orphans
+            .into_iter()
+            .filter(|orphan| !skipped.contains(orphan))
+            .collect::<Vec<_>>();
+        self.forget_unlisted()?;
+        prune_spinner.close();
+        self.logger.info(format!(
+            "Pruned orphaned files: {}",
+            pruned.iter().map(|p| p.display()).join(", ")
+        ));
 
-        // Try to load the repository.
-        let repo = Repository::clone(&repo_path, url)?;
+        if !pruned.is_empty() {
+            let outputs = self.commit("🧹 Pruned orphaned files")?;
+            self.logger.log_outputs(outputs);
+        }
 
-        Ok(Dotbak {
-            dotfiles: Files::init(home_path, repo_path),
-            config,
-            repo,
-            logger: Logger::new(verbose),
-            interface: Interface::new(MAX_MSG_LEN),
-        })
+        Ok((pruned, skipped))
     }
 
-    /// Load an instance of `dotbak`, loading the configuration file from `<dotbak>/config.toml` and the
-    /// repository from `<dotbak>/dotfiles`.
-    fn load_into_dirs<P1, P2, P3>(home: P1, config: P2, repo: P3, verbose: bool) -> Result<Self>
+    /// Create an offline bundle at `path`, encrypted with `passphrase`. The bundle contains the whole repository
+    /// history, the configuration file, and some metadata, and can be restored with `restore_bundle` on a machine
+    /// without network access.
+    pub fn create_bundle<P>(&mut self, path: P, passphrase: SecretString) -> Result<()>
     where
-        P1: AsRef<Path>,
-        P2: AsRef<Path>,
-        P3: AsRef<Path>,
+        P: AsRef<Path>,
     {
-        let config_path = config.as_ref().to_path_buf();
-        let repo_path = repo.as_ref().to_path_buf();
-        let home_path = home.as_ref().to_path_buf();
+        let (mut commit_spinner, mut bundle_spinner) = (
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(CREATE_BUNDLE_MSG, 0),
+        );
 
-        // Load the configuration file and the repository.
-        let config = Config::load_config(config_path)?;
-        let repo = Repository::load(&repo_path)?;
+        // Make sure the bundle has the latest changes.
+        commit_spinner.start();
+        let outputs = self.commit(SYNC_COMMIT_MSG)?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
 
-        Ok(Dotbak {
-            dotfiles: Files::init(home_path, repo_path),
-            config,
-            repo,
+        bundle_spinner.start();
+        let metadata = bundle::create(&mut self.repo, &self.config.path, &path, passphrase)?;
+        bundle_spinner.close();
+        self.logger.info(format!(
+            "Created bundle '{}' (dotbak {}, host {})",
+            path.as_ref().display(),
+            metadata.dotbak_version,
+            metadata.hostname.as_deref().unwrap_or("unknown"),
+        ));
 
-            // TODO: Make this output to log file when in daemon mode.
-            logger: Logger::new_with_streams(
-                verbose,
-                Box::new(std::io::stdout()),
-                Box::new(std::io::stderr()),
-            ),
+        Ok(())
+    }
 
-            interface: Interface::new(MAX_MSG_LEN),
-        })
+    /// Export the repository as a static, browsable HTML site into `dir`: a file tree, the (syntax highlighted)
+    /// contents of every file, and the history of every file and who changed it. Only committed changes are
+    /// exported.
+    pub fn export_html<P>(&mut self, dir: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut export_spinner = self.interface.spawn_spinner(EXPORT_MSG, 0);
+
+        export_spinner.start();
+        let snapshot = Snapshot::take(&mut self.repo, self.config.files.diff_memory_limit)?;
+        let pages = export::html::export(&snapshot, &dir)?;
+        export_spinner.close();
+        self.logger.info(format!(
+            "Exported {} pages to '{}'",
+            pages,
+            dir.as_ref().display()
+        ));
+
+        Ok(())
     }
 
-    /// Synchronize all files that are supposed to be synchronized.
-    fn sync_all_files(&mut self) -> Result<()> {
-        let files = self.config.files.include.clone(); // TODO: Get rid of this clone!
+    /// Exports the managed files (or only the ones in `only`, if it isn't empty) with their contents, not as
+    /// symlinks, into an archive at `path`: a tarball (`.tar`, `.tar.gz` or `.tgz`) or a zip archive (`.zip`), e.g.
+    /// for sharing them or as an offline backup. Encrypted files are exported decrypted, as they are in the home
+    /// directory.
+    ///
+    /// Returns the number of files exported.
+    pub fn export_archive<P>(&mut self, path: P, only: &[PathBuf]) -> Result<usize>
+    where
+        P: AsRef<Path>,
+    {
+        let mut export_spinner = self.interface.spawn_spinner(EXPORT_ARCHIVE_MSG, 0);
+        let home = self.dotfiles.home_dir().to_path_buf();
+        let only = only
+            .iter()
+            .map(|path| self.dotfiles.managed_path(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Paths inside of managed folders export only that part of the folder.
+        let managed = self
+            .dotfiles
+            .expand_excluded(&self.dotfiles.resolve_globs(&self.config.files.include)?);
+        let roots = match only.is_empty() {
+            true => managed,
+            false => managed
+                .iter()
+                .flat_map(|managed| {
+                    only.iter().filter_map(move |only| {
+                        match (managed.starts_with(only), only.starts_with(managed)) {
+                            (true, _) => Some(managed.clone()),
+                            (_, true) => Some(only.clone()),
+                            _ => None,
+                        }
+                    })
+                })
+                .unique()
+                .collect(),
+        };
+
+        export_spinner.start();
+        let mut files = vec![];
+
+        for root in &roots {
+            let full_path = home.join(root);
+
+            if full_path.is_file() {
+                files.push(root.clone());
+            } else if full_path.is_dir() {
+                let config = files::WalkConfig {
+                    follow_links: true,
+                    ..Default::default()
+                };
+
+                files.extend(
+                    Files::walk_dir(&full_path, &config)?
+                        .into_iter()
+                        .map(|file| root.join(file))
+                        .filter(|file| {
+                            home.join(file).is_file() && !self.dotfiles.is_excluded(file)
+                        }),
+                );
+            }
+        }
+
+        export::archive::export(&home, &roots, &files, &path)?;
+        export_spinner.close();
+        self.logger.info(format!(
+            "Exported {} files to '{}'",
+            files.len(),
+            path.as_ref().display()
+        ));
 
-        self.sync_files(&files)
+        Ok(files.len())
     }
 
-    /// Synchronize a select set of files.
-    fn sync_files<P>(&mut self, files: &[P]) -> Result<()>
+    /// Imports the files in the archive at `path` (e.g. from `export_archive`) into the home directory, and manages
+    /// the paths they belong to, like `add` does. Files that are in the home directory already are left alone: the
+    /// ones with other contents are returned, so nothing is overwritten.
+    pub fn import_archive<P>(&mut self, path: P) -> Result<Vec<PathBuf>>
     where
         P: AsRef<Path>,
     {
-        // Move the files/folders to the repository and symlink them to their original location.
-        self.dotfiles.move_and_symlink(files)?;
+        let mut import_spinner = self.interface.spawn_spinner(IMPORT_ARCHIVE_MSG, 0);
+        let home = self.dotfiles.home_dir().to_path_buf();
+
+        import_spinner.start();
+        let archive = export::archive::read(&path)?;
+        let (mut imported, mut skipped) = (vec![], vec![]);
+
+        for file in archive.files {
+            let target = home.join(&file.path);
+
+            if fs::symlink_metadata(&target).is_ok() {
+                if fs::read(&target).map_or(true, |contents| contents != file.contents) {
+                    skipped.push(file.path);
+                }
+
+                continue;
+            }
+
+            let parent = target.parent().unwrap();
+            fs::create_dir_all(parent).map_err(|err| IoError::Create {
+                source: err,
+                path: parent.to_path_buf(),
+            })?;
+            fs::write(&target, &file.contents).map_err(|err| IoError::Write {
+                source: err,
+                path: target.clone(),
+            })?;
+
+            if let Some(mode) = file.mode {
+                platform::set_permission_bits(&target, mode).map_err(|err| IoError::Write {
+                    source: err,
+                    path: target.clone(),
+                })?;
+            }
+
+            imported.push(file.path);
+        }
+
+        import_spinner.close();
+        self.logger.info(format!(
+            "Imported files from '{}': {}",
+            path.as_ref().display(),
+            imported.iter().map(|p| p.display()).join(", ")
+        ));
+
+        // Paths that are managed already (e.g. files imported into a managed folder) only need to be committed.
+        let managed = self.dotfiles.resolve_globs(&self.config.files.include)?;
+        let to_add = archive
+            .paths
+            .into_iter()
+            .filter(|path| !managed.iter().any(|managed| path.starts_with(managed)))
+            .filter(|path| imported.iter().any(|file| file.starts_with(path)))
+            .collect_vec();
+
+        if !to_add.is_empty() {
+            self.add(&to_add, false)?;
+        } else if !imported.is_empty() {
+            let commit_spinner = self.interface.spawn_spinner(COMMIT_MSG, 0);
+            let outputs = self.commit(&format!(
+                "📥 Imported files from '{}'",
+                path.as_ref().display()
+            ))?;
+            commit_spinner.close();
+            self.logger.log_outputs(outputs);
+            self.handle_unpushed()?;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Shows the pending changes to `files` (or to all managed files, if it's empty) as a unified diff: the
+    /// uncommitted changes in the repository (only the staged ones if `staged` is set), followed by the differences
+    /// between the tracked versions and the real files that replaced their symlinks (see `repair`), unless `staged`
+    /// is set. Returns an empty string if nothing changed.
+    pub fn diff<P>(&mut self, files: &[P], staged: bool) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let files = files
+            .iter()
+            .map(|file| self.dotfiles.managed_path(file))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut diff = self.repo.diff(&files, staged)?;
+
+        if staged {
+            return Ok(diff);
+        }
+
+        // Diff the replaced files (or the given files inside of replaced folders) themselves.
+        let replaced = self.dotfiles.replaced(&self.config.files.include);
+        let targets = match files.is_empty() {
+            true => replaced,
+            false => files
+                .iter()
+                .flat_map(|file| {
+                    replaced.iter().filter_map(move |replaced| {
+                        match (file.starts_with(replaced), replaced.starts_with(file)) {
+                            (true, _) => Some(file.clone()),
+                            (_, true) => Some(replaced.clone()),
+                            _ => None,
+                        }
+                    })
+                })
+                .unique()
+                .collect(),
+        };
+
+        for file in targets {
+            let home_path = self.dotfiles.home_dir().join(&file);
+            let repo_path = self.dotfiles.repo_path(&file);
+
+            if !home_path.exists() || compare::same_contents(&repo_path, &home_path)? {
+                continue;
+            }
+
+            diff.push_str(&format!(
+                "🔍 '{}' was replaced in your home directory. Changes from the tracked version:\n",
+                file.display()
+            ));
+            diff.push_str(&self.diff_replaced(&repo_path, &home_path)?);
 
-        // Synchronize the files/folders.
-        self.dotfiles.symlink_back_home(files)?;
+            if !diff.ends_with('\n') {
+                diff.push('\n');
+            }
+        }
+
+        Ok(diff)
+    }
+
+    /// Gets the history of the file/folder `file` (following renames), or of all managed files if it's `None`,
+    /// along with the files each commit touched, newest commit first. Only the newest `limit` commits are returned,
+    /// if it's given.
+    pub fn log<P>(&mut self, file: Option<P>, limit: Option<usize>) -> Result<Vec<LogEntry>>
+    where
+        P: AsRef<Path>,
+    {
+        let file = file
+            .map(|file| self.dotfiles.managed_path(file))
+            .transpose()?;
+
+        self.repo.log(file.as_deref(), limit)
+    }
+
+    /// Repair the symlinks of managed files, and the symlinks into the repository that are left over in the home
+    /// directory:
+    ///
+    /// - Symlinks that were replaced by real files (e.g. by an OS or application upgrade writing a fresh default
+    ///   configuration) are restored. The replacements are backed up next to the original file as
+    ///   `<file>.dotbak-backup`.
+    /// - Symlinks of managed files that were deleted are re-created.
+    /// - For symlinks pointing to files that are missing from the repository, the user can choose to restore the
+    ///   files from the history, or to delete the symlinks.
+    /// - For symlinks pointing to files that aren't in `files.include` anymore, the user can choose to manage the
+    ///   files again, or to delete the symlinks.
+    ///
+    /// If `adopt_changes` is set, the user is shown the differences between the tracked version and the
+    /// replacement for each replaced file, and can choose to keep the tracked version, adopt the replacement, or
+    /// edit the tracked version to merge in the parts they want.
+    pub fn repair(&mut self, adopt_changes: bool) -> Result<()> {
+        let mut repaired = self.repair_replaced(adopt_changes)?;
+        repaired.extend(self.repair_missing()?);
+        repaired.extend(self.repair_stray_links()?);
+
+        if repaired.is_empty() {
+            self.logger.info("No files to repair found");
+            return Ok(());
+        }
+
+        let mut commit_spinner = self.interface.spawn_spinner(COMMIT_MSG, 0);
+
+        commit_spinner.start();
+        let outputs = self.commit(&format!(
+            "🩹 Repaired files: {}",
+            repaired.iter().map(|p| p.display()).join(", ")
+        ))?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
 
         Ok(())
     }
-}
 
-/// Get the directories that `dotbak` uses. In order, it returns the `<home>`, `<config>`, and `<repo>` dirs.
-fn get_dotbak_dirs() -> (PathBuf, PathBuf, PathBuf) {
-    let home_dir = dirs::home_dir().expect("You should have a home directory!");
-    let dotbak_dir = home_dir.join(".dotbak");
+    /// Check how many of the well-known configuration files/folders in the home directory are managed.
+    pub fn coverage(&self) -> Coverage {
+        Coverage::new(
+            suggest::scan(self.dotfiles.home_dir()),
+            &self.config.files.include,
+        )
+    }
 
-    (
-        home_dir,
-        dotbak_dir.join(CONFIG_FILE_NAME),
-        dotbak_dir.join(REPO_FOLDER_NAME),
-    )
-}
+    /// Reload the configuration file, e.g. after it was edited while the daemon is running. Changed settings take
+    /// effect right away (new `files.include` entries are picked up by the next sync), except for the ones in
+    /// `config::RESTART_SETTINGS`, which only take effect the next time `dotbak` starts. If the new configuration
+    /// is invalid, the current one is kept.
+    pub fn reload_config(&mut self) -> Result<Reload> {
+        let (config, reload) = self.config.reload()?;
 
-// Convert to pathbufs and strip the $HOME prefix.
-fn preprocess_paths<P: AsRef<Path>>(paths: &[P]) -> Vec<PathBuf> {
-    paths
-        .iter()
-        .map(|p| {
-            p.as_ref()
-                .strip_prefix(dirs::home_dir().expect("You should have a home directory!"))
-                .unwrap_or(p.as_ref()) // Default to syncing the file: assumes all files w/o $HOME prefix are in $HOME. TODO: Is this a good idea?
-                .to_path_buf()
-        })
-        .collect()
+        // The repository stays where it is until the next start, so check the new configuration against it.
+        config.check_repo_dir(self.dotfiles.file_dir(), self.dotfiles.home_dir())?;
+
+        self.repo.set_low_bandwidth(config.network.low_bandwidth);
+        self.repo
+            .set_sequence_trailer(config.history.sequence_trailer);
+        self.repo.set_pull_strategy(config.git.pull_strategy);
+        self.repo.set_timeouts(config.git.timeouts.clone());
+        self.repo.set_backend(config.git.backend)?;
+        self.repo.set_signing(config.git.signing());
+        self.repo.set_identity(
+            config.git.identity.name.as_deref(),
+            config.git.identity.email.as_deref(),
+        )?;
+        configure_files(&mut self.dotfiles, &config)?;
+        self.config = config;
+
+        if !reload.applied.is_empty() {
+            self.logger.info(format!(
+                "Reloaded the configuration, applied changes to: {}",
+                reload.applied.join(", ")
+            ));
+        }
+
+        if !reload.pending.is_empty() {
+            self.logger.info(format!(
+                "Changes to {} will be applied the next time dotbak starts",
+                reload.pending.join(", ")
+            ));
+        }
+
+        Ok(reload)
+    }
+
+    /// Generate a new key to encrypt the files in `encryption.files` with, and encrypt them. Returns the public key.
+    pub fn generate_key(&mut self) -> Result<String> {
+        let recipient = encrypt::generate_key(key_path(&self.config, self.dotfiles.home_dir()))?;
+
+        self.use_new_key()?;
+
+        Ok(recipient.to_string())
+    }
+
+    /// Import an existing key (e.g. from another machine) from `path` to encrypt the files in `encryption.files`
+    /// with, and decrypt them. Returns the public key.
+    pub fn import_key<P>(&mut self, path: P) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let recipient =
+            encrypt::import_key(path, key_path(&self.config, self.dotfiles.home_dir()))?;
+
+        self.use_new_key()?;
+
+        Ok(recipient.to_string())
+    }
+
+    /// Find the hard-coded paths to the home directory in the managed files, which break on machines with a
+    /// different home directory.
+    pub fn lint(&self) -> Result<Vec<HardcodedPath>> {
+        rewrite::scan(
+            &self.repo_include(),
+            self.dotfiles.file_dir(),
+            self.dotfiles.home_dir(),
+            self.config.files.diff_memory_limit,
+        )
+    }
+
+    /// List the managed files and folders, and the files inside of the managed folders, sorted by path. Each entry
+    /// has the state of its symlink in the home directory, its size, and the last commit changing it. This doesn't
+    /// change anything, so it should be used on an instance loaded with `load_without_sync`.
+    pub fn list(&mut self) -> Result<Vec<ListEntry>> {
+        let mut entries = vec![];
+        let last_commits = list::last_commits(&mut self.repo)?;
+
+        for file in self.managed_paths()? {
+            entries.extend(list::entries(&last_commits, &self.dotfiles, &file)?);
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(entries)
+    }
+
+    /// Get the status of the managed files and the repository: the state of each file's symlink in the home
+    /// directory, and whether there are uncommitted or unpushed changes. This doesn't change anything, so it should
+    /// be used on an instance loaded with `load_without_sync`.
+    pub fn status(&mut self) -> Result<Status> {
+        let files = diagnostics::link_states(&self.dotfiles, &self.config.files.include)?;
+        let (unpushed, behind) = self.repo.ahead_behind()?;
+        let drifted = ManagedFiles::load(self.state_dir()?)?
+            .entries()
+            .filter_map(|entry| {
+                entry
+                    .drift(&self.dotfiles, &self.config.files.include)
+                    .map(|drift| (entry.path.clone(), drift))
+            })
+            .collect();
+
+        Ok(Status {
+            files,
+            drifted,
+            uncommitted: self.repo.uncommitted_changes()?,
+            unpushed,
+            behind,
+            push_deferred: unpushed > 0 && self.should_defer_push()?,
+            daemon_pending_restart: Daemon::pending_restart(),
+            quarantined: Quarantine::load(self.state_dir()?)?
+                .quarantined()
+                .cloned()
+                .collect(),
+            pinned: self.pinned()?,
+            queued_push: self.queued_push()?,
+            sync_in_progress: Lock::is_held(self.state_dir()?),
+        })
+    }
+
+    /// Let quarantined files/folders be synced again, after the cause of their failures was fixed. They are synced
+    /// right away, so if they still fail, the error is shown (and they're quarantined again after enough failures).
+    pub fn clear_quarantine<P>(&mut self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let mut quarantine = Quarantine::load(self.state_dir()?)?;
+
+        for file in files {
+            let file = self.dotfiles.managed_path(file)?;
+
+            match quarantine.clear(&file) {
+                true => self
+                    .logger
+                    .info(format!("Cleared '{}' from the quarantine", file.display())),
+                false => self
+                    .interface
+                    .warn(format!("'{}' isn't quarantined", file.display())),
+            }
+        }
+
+        quarantine.save()?;
+
+        self.sync_all_files()
+    }
+
+    /// Get the status of the managed lock files of plugin managers: whether they changed on this machine, and
+    /// whether they differ from the remote (i.e. another machine updated its plugins). This fetches the remote
+    /// first, if there is one.
+    pub fn plugin_status(&mut self) -> Result<Vec<LockStatus>> {
+        let mut fetch_spinner = self.interface.spawn_spinner(FETCH_REMOTE_MSG, 0);
+
+        fetch_spinner.start();
+        let fetched = self.authenticated(Repository::fetch);
+        fetch_spinner.close();
+
+        match fetched {
+            Ok(output) => self.logger.log_output(output),
+            Err(err) => self.interface.warn(format!(
+                "Couldn't fetch the remote, comparing with what was last fetched instead: {}",
+                err
+            )),
+        }
+
+        let remote = format!("{}/{}", git::REMOTE_NAME, self.repo.branch());
+        let mut statuses = vec![];
+
+        for lock in plugins::REGISTRY {
+            if !self.dotfiles.is_managed_in_repo(&lock.path) {
+                continue;
+            }
+
+            statuses.push(LockStatus {
+                lock: *lock,
+                changed_locally: self.repo.path_differs("HEAD", None, lock.path),
+                differs_from_remote: self.repo.path_differs("HEAD", Some(&remote), lock.path),
+                last_change: self
+                    .repo
+                    .history(Some(Path::new(lock.path)))?
+                    .into_iter()
+                    .next(),
+            });
+        }
+
+        Ok(statuses)
+    }
+
+    /// Installs the packages exported for the package managers of this operating system (see `packages` in the
+    /// configuration), e.g. on a new machine. Returns the package managers whose packages were installed, leaving
+    /// out the ones without an exported package list.
+    pub fn apply_packages(&mut self) -> Result<Vec<Manager>> {
+        let managers = self.config.packages.managers(std::env::consts::OS).to_vec();
+        let dir = self.dotfiles.file_dir().join(packages::PACKAGES_DIR);
+        let mut applied = vec![];
+
+        let apply_spinner = self.interface.spawn_spinner(APPLY_PACKAGES_MSG, 0);
+
+        for manager in managers {
+            // Package managers may ask for a password (through `sudo`), so the spinners are hidden meanwhile.
+            let output = self.interface.suspend(|| packages::apply(manager, &dir))?;
+
+            if let Some(output) = output {
+                self.logger
+                    .info(format!("Installed the packages of {}", manager.name()));
+                self.logger.log_output(output);
+                applied.push(manager);
+            }
+        }
+
+        apply_spinner.close();
+
+        Ok(applied)
+    }
+
+    /// Fetches the history and file contents left out when the repository was cloned shallow or filtered (see
+    /// `git.clone`), so that the repository is complete.
+    pub fn unshallow(&mut self) -> Result<()> {
+        let mut unshallow_spinner = self.interface.spawn_spinner(UNSHALLOW_MSG, 0);
+
+        unshallow_spinner.start();
+        let output = self.authenticated(Repository::unshallow)?;
+        unshallow_spinner.close();
+
+        match output {
+            Some(output) => {
+                self.logger.log_output(output);
+                self.interface
+                    .println("📚 Fetched the full history of the repository.");
+            }
+            None => self
+                .interface
+                .println("📚 The repository already has its full history."),
+        }
+
+        Ok(())
+    }
+
+    /// Cleans up the repository with `git gc`, and shows how much space that reclaimed. If `larger_than` (in bytes)
+    /// is given, the history of the current branch is rewritten first without the files that were ever larger than
+    /// it, and if `removed` is set, without the files that were removed from the repository. Files that are still in
+    /// the repository are never dropped. The files to drop are shown first, and the user is asked to confirm, unless
+    /// `yes` is set. The rewritten history replaces the remote's.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn gc(&mut self, larger_than: Option<u64>, removed: bool, yes: bool) -> Result<()> {
+        let (mut rewrite_spinner, mut gc_spinner) = (
+            self.interface.spawn_spinner(REWRITE_HISTORY_MSG, 0),
+            self.interface.spawn_spinner(GC_MSG, 0),
+        );
+        let before = self.repo.objects_size()?;
+
+        // Files that are still in the repository are kept, so that they aren't lost.
+        let tracked = self.repo.tracked_files()?;
+        let (kept, dropped): (Vec<_>, Vec<_>) = match larger_than.is_some() || removed {
+            true => droppable_files(self.repo.history_files()?, &tracked, larger_than, removed)
+                .into_iter()
+                .partition(|(path, _)| tracked.contains(path)),
+            false => (vec![], vec![]),
+        };
+
+        for (path, size) in &kept {
+            self.interface.warn(format!(
+                "Kept '{}' ({}), as it's still in the repository. Remove it with `dotbak remove` first.",
+                path.display(),
+                HumanBytes(*size)
+            ));
+        }
+
+        let rewrite = !dropped.is_empty() && (yes || self.confirm_rewrite(&dropped)?);
+
+        if rewrite {
+            let count = self.repo.uncommitted_changes()?;
+
+            if count > 0 {
+                return Err(DotbakError::UncommittedChanges { count });
+            }
+
+            // The remote's history is replaced, so it must not have commits this machine doesn't have.
+            let has_remote = self
+                .repo
+                .list_remotes()?
+                .iter()
+                .any(|remote| remote.name == git::REMOTE_NAME);
+
+            if has_remote {
+                let output = self.authenticated(Repository::pull)?;
+                self.logger.log_output(output);
+            }
+
+            rewrite_spinner.start();
+            let paths = dropped.iter().map(|(path, _)| path.clone()).collect_vec();
+            let output = self.repo.drop_from_history(&paths)?;
+            rewrite_spinner.close();
+            self.logger.log_output(output);
+
+            if has_remote {
+                match self.authenticated(|repo| repo.force_push_to(git::REMOTE_NAME)) {
+                    Ok(output) => self.logger.log_output(output),
+                    Err(err) => {
+                        self.interface.warn(format!(
+                            "Couldn't push the rewritten history. Push it with `dotbak git push --force {} {}` \
+                             before syncing again, or the old history comes back.",
+                            git::REMOTE_NAME,
+                            self.repo.branch()
+                        ));
+
+                        return Err(err);
+                    }
+                }
+            }
+        }
+
+        gc_spinner.start();
+        let output = self.repo.gc(rewrite)?;
+        gc_spinner.close();
+        self.logger.log_output(output);
+
+        let after = self.repo.objects_size()?;
+
+        self.interface.println(format!(
+            "🧹 Reclaimed {} ({} → {}).",
+            HumanBytes(before.saturating_sub(after)),
+            HumanBytes(before),
+            HumanBytes(after)
+        ));
+
+        if rewrite {
+            self.interface.println(format!(
+                "✂️ Dropped {} file(s) from the history. Clone the repository again on your other machines \
+                 (`dotbak clone`), so that they don't bring the old history back.",
+                dropped.len()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Promotes the syncs waiting on the remote's staging branch (see `history.staging`) to the main branch. The
+    /// staged commits and their changes are shown first, and the user is asked to confirm, unless `yes` is set.
+    /// If the main branch moved on in the meantime, the staged changes are merged into it.
+    pub fn promote(&mut self, yes: bool) -> Result<()> {
+        let (mut fetch_spinner, mut promote_spinner) = (
+            self.interface.spawn_spinner(FETCH_REMOTE_MSG, 0),
+            self.interface.spawn_spinner(PROMOTE_MSG, 0),
+        );
+        let branch = self.repo.branch().to_string();
+        let main = format!("{}/{}", git::REMOTE_NAME, branch);
+        let staging = format!("{}/{}", git::REMOTE_NAME, git::STAGING_BRANCH_NAME);
+
+        fetch_spinner.start();
+        let output = self.authenticated(Repository::fetch)?;
+        self.logger.log_output(output);
+
+        if !self.repo.has_remote_branch(git::STAGING_BRANCH_NAME) {
+            fetch_spinner.close();
+            self.interface
+                .println("📭 Nothing is staged, the remote has no staging branch.");
+
+            return Ok(());
+        }
+
+        let output = self.authenticated(|repo| repo.fetch_branch(git::STAGING_BRANCH_NAME))?;
+        fetch_spinner.close();
+        self.logger.log_output(output);
+
+        let commits = self.repo.commits_between(&main, &staging)?;
+
+        if commits.is_empty() {
+            self.interface
+                .println("📭 Nothing is staged, the main branch is up to date.");
+
+            return Ok(());
+        }
+
+        self.interface
+            .println(format!("📋 {} staged commit(s):", commits.len()));
+
+        for commit in &commits {
+            self.interface.println(format!(
+                "   {} {} {}",
+                &commit.hash[..7.min(commit.hash.len())],
+                commit.summary,
+                console::style(format!("({}, {})", commit.author, commit.date)).dim()
+            ));
+        }
+
+        self.interface
+            .println(self.repo.diff_revs(&main, &staging)?);
+
+        if !yes
+            && self.interface.choose(
+                "Promote these changes to the main branch?",
+                &["keep them staged", "promote them"],
+            )? == 0
+        {
+            return Ok(());
+        }
+
+        promote_spinner.start();
+        if self.repo.is_ancestor(&main, &staging) {
+            let output = self.authenticated(|repo| repo.push_rev(&staging, &branch))?;
+            promote_spinner.close();
+            self.logger.log_output(output);
+        } else {
+            // The main branch moved on, so merge the staged changes into it here (with the uncommitted changes stashed,
+            // like when syncing), and catch the staging branch up.
+            self.with_stash(|dotbak| {
+                let outputs = [
+                    dotbak.authenticated(|repo| repo.pull_branch(&branch))?,
+                    dotbak.authenticated(|repo| repo.merge_branch(git::STAGING_BRANCH_NAME))?,
+                ];
+                dotbak.logger.log_outputs(outputs);
+
+                Ok(())
+            })?;
+            let outputs = [
+                self.authenticated(|repo| repo.push_rev(&branch, &branch))?,
+                self.authenticated(|repo| repo.push_rev(&branch, git::STAGING_BRANCH_NAME))?,
+            ];
+            self.sync_all_files()?;
+            promote_spinner.close();
+            self.logger.log_outputs(outputs);
+        }
+
+        self.interface.println(format!(
+            "🚀 Promoted {} commit(s) to the main branch",
+            commits.len()
+        ));
+
+        Ok(())
+    }
+
+    /// Switches to the branch `name` (e.g. to keep a branch per machine), creating it if it doesn't exist yet, and
+    /// makes it the branch that is committed to, pulled and pushed. Pending changes are committed to the current
+    /// branch first, and the files on the new branch are synced afterwards. New branches are pushed right away, so
+    /// that they can be pulled from with the next sync.
+    pub fn switch_branch(&mut self, name: &str) -> Result<()> {
+        let (mut commit_spinner, mut switch_spinner) = (
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(SWITCH_BRANCH_MSG, 0),
+        );
+
+        commit_spinner.start();
+        let outputs = self.commit(SYNC_COMMIT_MSG)?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        switch_spinner.start();
+        let output = self.repo.switch_branch(name)?;
+        self.logger.log_output(output);
+
+        // The configuration file may be different on the new branch, if it's managed.
+        self.reload_config()?;
+        self.config.git.branch = (name != git::MAIN_BRANCH_NAME).then(|| name.to_string());
+        self.config.save_config()?;
+        switch_spinner.close();
+        self.logger.info(format!("Switched to branch '{}'", name));
+
+        self.sync_all_files()?;
+
+        let (mut final_commit_spinner, mut push_spinner) = (
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(PUSH_MSG, 0),
+        );
+
+        final_commit_spinner.start();
+        let outputs = self
+            .repo
+            .commit(&format!("🌿 Switched to branch '{}'", name))?;
+        final_commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        let has_remote = self
+            .repo
+            .list_remotes()?
+            .iter()
+            .any(|remote| remote.name == git::REMOTE_NAME);
+
+        push_spinner.start();
+        if has_remote && !self.repo.has_remote_branch(name) {
+            self.push_remotes()?;
+        }
+        push_spinner.close();
+
+        Ok(())
+    }
+
+    /// Finishes setting up a new machine after cloning the repository, for `dotbak bootstrap`: switches to the
+    /// machine's own branch (`hosts/<hostname>`) if the remote has one, and runs the `bootstrap` hooks. Returns the
+    /// branch that was switched to, if any.
+    pub fn bootstrap(&mut self) -> Result<Option<String>> {
+        let host_branch = self
+            .repo
+            .host()
+            .map(|host| format!("{}{}", git::HOST_BRANCH_PREFIX, host))
+            .filter(|branch| branch != self.repo.branch());
+
+        let switched = match host_branch {
+            Some(branch) if self.repo.has_remote_branch(&branch) => {
+                self.switch_branch(&branch)?;
+                Some(branch)
+            }
+            _ => None,
+        };
+
+        self.run_hooks(Hook::Bootstrap)?;
+
+        Ok(switched)
+    }
+
+    /// Gets the remotes of the repository, including the ones in `git.remotes` which weren't added to it yet.
+    pub fn remotes(&mut self) -> Result<Vec<git::Remote>> {
+        let mut remotes = self.repo.list_remotes()?;
+
+        for (name, remote) in &self.config.git.remotes {
+            if !remotes.iter().any(|existing| &existing.name == name) {
+                remotes.push(git::Remote {
+                    name: name.clone(),
+                    url: remote.url().to_string(),
+                });
+            }
+        }
+
+        remotes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(remotes)
+    }
+
+    /// Adds the remote `name` with the URL `url` to `git.remotes` and the repository, so that pushes go to it as
+    /// well. If it already exists, its URL is changed instead.
+    pub fn add_remote(&mut self, name: &str, url: &str) -> Result<()> {
+        let mut update_conf_spinner = self.interface.spawn_spinner(UPDATE_CONF_MSG, 0);
+
+        update_conf_spinner.start();
+        match self.config.git.remotes.get_mut(name) {
+            Some(RemoteConfig::Table { url: existing, .. }) => *existing = url.to_string(),
+            _ => {
+                self.config
+                    .git
+                    .remotes
+                    .insert(name.to_string(), RemoteConfig::Url(url.to_string()));
+            }
+        }
+
+        self.config.save_config()?;
+        let output = self.repo.add_remote(name, url)?;
+        update_conf_spinner.close();
+        self.logger.log_output(output);
+        self.logger.info(format!(
+            "Added remote '{}' with URL '{}'",
+            name,
+            diagnostics::redact_url(url)
+        ));
+
+        Ok(())
+    }
+
+    /// Removes the remote `name` from `git.remotes` and the repository. `origin` can't be removed, as it's the
+    /// remote pulled from.
+    pub fn remove_remote(&mut self, name: &str) -> Result<()> {
+        if name == git::REMOTE_NAME {
+            self.interface.println(format!(
+                "⛔ '{}' is pulled from, so it can't be removed. Disable pushing to it in `git.remotes` instead.",
+                name
+            ));
+
+            return Ok(());
+        }
+
+        let mut update_conf_spinner = self.interface.spawn_spinner(UPDATE_CONF_MSG, 0);
+
+        update_conf_spinner.start();
+        self.config.git.remotes.remove(name);
+        self.config.save_config()?;
+
+        if self
+            .repo
+            .list_remotes()?
+            .iter()
+            .any(|remote| remote.name == name)
+        {
+            let output = self.repo.remove_remote(name)?;
+            self.logger.log_output(output);
+        }
+        update_conf_spinner.close();
+        self.logger.info(format!("Removed remote '{}'", name));
+
+        Ok(())
+    }
+
+    /// Gets the machines (hosts) that synced to the repository, and when they last synced, as recorded in the
+    /// `Dotbak-Host` trailers of their commits. If there is a remote, the branches of the hosts are fetched first.
+    pub fn hosts(&mut self) -> Result<Vec<git::Host>> {
+        if self.config.repository_url.is_some() {
+            let mut fetch_spinner = self.interface.spawn_spinner(FETCH_REMOTE_MSG, 0);
+
+            fetch_spinner.start();
+            let output = self.authenticated(Repository::fetch_hosts)?;
+            fetch_spinner.close();
+            self.logger.log_output(output);
+        }
+
+        self.repo.hosts()
+    }
+
+    /// Retires the machine (host) `name`, which isn't used anymore: its branch is archived as
+    /// `archive/hosts/<name>` (locally, and on the remote if there is one), and it isn't listed as an active host
+    /// anymore. Its history is kept in the archive branch. This machine itself can't be retired, as its next sync
+    /// would bring it back.
+    pub fn retire_host(&mut self, name: &str) -> Result<()> {
+        let Some(host) = self.hosts()?.into_iter().find(|host| host.name == name) else {
+            self.interface.println(format!(
+                "❓ No machine named '{}' ever synced, see `dotbak hosts list`.",
+                name
+            ));
+
+            return Ok(());
+        };
+
+        if host.retired {
+            self.interface
+                .println(format!("📭 '{}' is already retired.", name));
+
+            return Ok(());
+        }
+
+        if bundle::hostname().as_deref() == Some(name) {
+            self.interface.println(format!(
+                "⛔ '{}' is this machine, retire it from another machine.",
+                name
+            ));
+
+            return Ok(());
+        }
+
+        let mut archive_spinner = self.interface.spawn_spinner(ARCHIVE_HOST_MSG, 0);
+
+        archive_spinner.start();
+        let archive = self.repo.archive_host(&host)?;
+
+        if self.config.repository_url.is_some() {
+            let branch = format!("{}{}", git::HOST_BRANCH_PREFIX, name);
+            let output = self.repo.push_rev(&archive, &archive)?;
+            self.logger.log_output(output);
+
+            if self.repo.has_remote_branch(&branch) {
+                let output = self.repo.delete_remote_branch(&branch)?;
+                self.logger.log_output(output);
+            }
+        }
+        archive_spinner.close();
+
+        self.logger.info(format!(
+            "Retired host '{}', archived as '{}'",
+            name, archive
+        ));
+        self.interface.println(format!(
+            "🗄️ Retired '{}', its history is kept in the '{}' branch",
+            name, archive
+        ));
+
+        Ok(())
+    }
+
+    /// Ejects from `dotbak`, turning the repository into a conventional dotfiles repository: the symlinks in the
+    /// home directory are replaced with copies of the files, and an install script that links the files in `style`
+    /// is committed to the repository. The history is kept, and so is the repository (which is where it was). The
+    /// configuration file is removed, so `dotbak` doesn't manage anything afterwards.
+    pub fn eject(mut self, style: InstallStyle) -> Result<()> {
+        let (mut eject_spinner, mut script_spinner, mut commit_spinner, mut rm_config_spinner) = (
+            self.interface.spawn_spinner(EJECT_MSG, 0),
+            self.interface.spawn_spinner(INSTALL_SCRIPT_MSG, 0),
+            self.interface.spawn_spinner(COMMIT_MSG, 0),
+            self.interface.spawn_spinner(RM_CONFG_MSG, 0),
+        );
+
+        // `dotbak`'s own configuration has no place in a conventional repository.
+        let config_in_repo = self.config_path_in_repo();
+        let files = self
+            .config
+            .files
+            .include
+            .iter()
+            .filter(|file| Some(*file) != config_in_repo.as_ref())
+            .cloned()
+            .collect_vec();
+
+        eject_spinner.start();
+        self.dotfiles.copy_back_home(&files)?;
+        eject_spinner.close();
+        self.logger.info(format!(
+            "Replaced symlinks with files: {}",
+            files.iter().map(|f| f.display()).join(", ")
+        ));
+
+        script_spinner.start();
+        let script_path = self.dotfiles.file_dir().join(eject::INSTALL_SCRIPT);
+        fs::write(&script_path, eject::install_script(&files, style)).map_err(|err| {
+            IoError::Write {
+                source: err,
+                path: script_path.clone(),
+            }
+        })?;
+        platform::set_executable(&script_path).map_err(|err| IoError::Write {
+            source: err,
+            path: script_path.clone(),
+        })?;
+        script_spinner.close();
+
+        // The managed configuration file is a symlink into the repository, so remove both.
+        rm_config_spinner.start();
+        if let Some(config_in_repo) = &config_in_repo {
+            let repo_config = self.dotfiles.file_dir().join(config_in_repo);
+
+            fs::remove_file(&self.config.path).map_err(|err| IoError::Delete {
+                source: err,
+                path: self.config.path.clone(),
+            })?;
+            fs::remove_file(&repo_config).map_err(|err| IoError::Delete {
+                source: err,
+                path: repo_config,
+            })?;
+        } else {
+            let trash = (!self.config.files.permanent_delete).then_some(self.trash.as_ref());
+            self.config.delete_config(trash)?;
+        }
+        rm_config_spinner.close();
+
+        commit_spinner.start();
+        let outputs = self.repo.commit("⏏️ Ejected from dotbak")?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        self.interface.println(format!(
+            "⏏️ Your dotfiles are a plain git repository at '{}' now. Run '{}' in it to install them elsewhere.",
+            self.dotfiles.file_dir().display(),
+            eject::INSTALL_SCRIPT
+        ));
+
+        Ok(())
+    }
+
+    // Deinitializes `dotbak`, removing the configuration file and the repository. This also restores all files
+    // that were managed by `dotbak` to their original location.
+    pub fn deinit(mut self) -> Result<()> {
+        let (mut restore_files_spinner, mut rm_config_spinner, mut rm_repo_spinner) = (
+            self.interface.spawn_spinner(RESTORE_FILES_MSG, 0),
+            self.interface.spawn_spinner(RM_CONFG_MSG, 0),
+            self.interface.spawn_spinner(RM_REPO_MSG, 0),
+        );
+
+        // Restore all files that were managed by `dotbak` to their original location.
+        restore_files_spinner.start();
+        self.dotfiles
+            .remove_and_restore(&self.config.files.include)?;
+        restore_files_spinner.close();
+        self.logger.info(format!(
+            "Restored files: {}",
+            self.config
+                .files
+                .include
+                .iter()
+                .map(|f| f.display())
+                .join(", ")
+        ));
+
+        // Remove the configuration file.
+        let trash = (!self.config.files.permanent_delete).then_some(self.trash.as_ref());
+        rm_config_spinner.start();
+        self.config.delete_config(trash)?;
+        rm_config_spinner.close();
+
+        // Remove the repository.
+        rm_repo_spinner.start();
+        self.repo.delete(trash)?;
+        rm_repo_spinner.close();
+
+        Ok(())
+    }
+}
+
+/// These are helper functions for end-to-end tests on `Dotbak`, see `TestEnv`.
+#[cfg(any(test, feature = "testing"))]
+impl Dotbak {
+    /// Sets up `dotbak` on the test machine `machine` by cloning the repository at `url`, like `dotbak clone`.
+    pub fn clone_machine(machine: &Machine, url: &str) -> Result<Self> {
+        let mut dotbak = Self::clone_into_dirs(
+            &machine.home,
+            &machine.config,
+            &machine.repo,
+            url,
+            Verbosity::Debug,
+            false,
+            &Overrides::default(),
+        )?;
+
+        dotbak.repo.set_host(Some(machine.name.clone()));
+        dotbak.set_trash(machine.trash.clone());
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+
+    /// Loads `dotbak` on the test machine `machine`, like any command after it was set up.
+    pub fn load_machine(machine: &Machine) -> Result<Self> {
+        let mut dotbak = Self::load_into_dirs(
+            &machine.home,
+            &machine.config,
+            &machine.repo,
+            Verbosity::Debug,
+            &Overrides::default(),
+        )?;
+
+        dotbak.repo.set_host(Some(machine.name.clone()));
+        dotbak.set_trash(machine.trash.clone());
+        dotbak.sync_all_files()?;
+
+        Ok(dotbak)
+    }
+}
+
+/// Private API for `Dotbak`. These are mainly used for testing.
+impl Dotbak {
+    /// Initialize a new instance of `dotbak`, loading the configuration file from `<dotbak>/config.toml` and the
+    /// repository from `<dotbak>/dotfiles`. The user's home directory is assumed to be `<home>`.
+    fn init_into_dirs<P1, P2, P3>(
+        home: P1,
+        config: P2,
+        repo: P3,
+        verbosity: Verbosity,
+        overrides: &Overrides,
+    ) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let config_path = config.as_ref().to_path_buf();
+        let repo_path = repo.as_ref().to_path_buf();
+        let home_path = home.as_ref().to_path_buf();
+
+        // Try to load the configuration file.
+        let config = match Config::load_layered(&config_path, overrides) {
+            // If the configuration file exists, load it.
+            // TODO: log that the configuration file was loaded, not created.
+            Ok(config) => config,
+
+            // If the configuration file does not exist, create it.
+            // TODO: log that the configuration file was created, not loaded.
+            Err(DotbakError::Config(ConfigError::NotFound { .. })) => {
+                Config::create_config(&config_path)?;
+                Config::load_layered(&config_path, overrides)?
+            }
+
+            // If the error is not a `ConfigNotFound` error, return it.
+            Err(err) => return Err(err),
+        };
+
+        // Use the configured repository location, if there is one.
+        let repo_path = config.repo_dir(&home_path)?.unwrap_or(repo_path);
+        config.check_valid(&home_path)?;
+
+        // Try to load the repository.
+        let mut repo = Repository::init_with_backend(&repo_path, None, config.git.backend)?;
+        repo.set_low_bandwidth(config.network.low_bandwidth);
+
+        if repo.current_branch().as_deref() != Some(config.git.branch()) {
+            repo.switch_branch(config.git.branch())?;
+        }
+
+        repo.set_sequence_trailer(config.history.sequence_trailer);
+        repo.set_pull_strategy(config.git.pull_strategy);
+        repo.set_timeouts(config.git.timeouts.clone());
+        repo.set_signing(config.git.signing());
+        repo.set_host(bundle::hostname());
+        repo.set_identity(
+            config.git.identity.name.as_deref(),
+            config.git.identity.email.as_deref(),
+        )?;
+
+        let interface = Interface::new(MAX_MSG_LEN, verbosity);
+        repo.set_progress(Some(git_progress(&interface)));
+
+        let logger = Logger::new(verbosity, &home_path);
+        let mut dotfiles = Files::init(home_path, repo_path);
+        configure_files(&mut dotfiles, &config)?;
+
+        Ok(Dotbak {
+            dotfiles,
+            config,
+            repo,
+            logger,
+            interface,
+            trash: Arc::new(SystemTrash),
+            allow_secrets: false,
+            force: false,
+            offline: false,
+            automated: false,
+        })
+    }
+
+    /// Clone an instance of `dotbak`, cloning the repository from the given URL to `<dotbak>/dotfiles`.
+    /// The user's home directory is assumed to be `<home>`.
+    fn clone_into_dirs<P1, P2, P3>(
+        home: P1,
+        config: P2,
+        repo: P3,
+        url: &str,
+        verbosity: Verbosity,
+        low_bandwidth: bool,
+        overrides: &Overrides,
+    ) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let config_path = config.as_ref().to_path_buf();
+        let repo_path = repo.as_ref().to_path_buf();
+        let home_path = home.as_ref().to_path_buf();
+
+        // Try to load the configuration file.
+        let mut config = match Config::load_layered(&config_path, overrides) {
+            // If the configuration file exists, load it.
+            // TODO: log that the configuration file was loaded, not created.
+            Ok(config) => config,
+
+            // If the configuration file does not exist, create it.
+            // TODO: log that the configuration file was created, not loaded.
+            Err(DotbakError::Config(ConfigError::NotFound { .. })) => {
+                Config::create_config(&config_path)?;
+                Config::load_layered(&config_path, overrides)?
+            }
+
+            // If the error is not a `ConfigNotFound` error, return it.
+            Err(err) => return Err(err),
+        };
+
+        // Use the configured repository location, if there is one.
+        let repo_path = config.repo_dir(&home_path)?.unwrap_or(repo_path);
+        config.check_valid(&home_path)?;
+
+        // Try to load the repository.
+        let low_bandwidth = low_bandwidth || config.network.low_bandwidth;
+        let mut interface = Interface::new(MAX_MSG_LEN, verbosity);
+        let clone_spinner = interface.spawn_spinner(CLONE_MSG, 0);
+        let mut repo = clone_authenticated(&repo_path, url, low_bandwidth, &config, &interface)?;
+        clone_spinner.close();
+        configure_cloned_branch(&mut repo, &mut config)?;
+        repo.set_sequence_trailer(config.history.sequence_trailer);
+        repo.set_pull_strategy(config.git.pull_strategy);
+        repo.set_timeouts(config.git.timeouts.clone());
+        repo.set_backend(config.git.backend)?;
+        repo.set_signing(config.git.signing());
+        repo.set_host(bundle::hostname());
+        repo.set_identity(
+            config.git.identity.name.as_deref(),
+            config.git.identity.email.as_deref(),
+        )?;
+
+        let logger = Logger::new(verbosity, &home_path);
+        let mut dotfiles = Files::init(home_path, repo_path);
+        configure_files(&mut dotfiles, &config)?;
+
+        let mut dotbak = Dotbak {
+            dotfiles,
+            config,
+            repo,
+            logger,
+            interface,
+            trash: Arc::new(SystemTrash),
+            allow_secrets: false,
+            force: false,
+            offline: false,
+            automated: false,
+        };
+
+        // Check out the managed files of this machine, if only those should be.
+        dotbak.update_sparse_checkout()?;
+
+        // The clone checked out git-lfs pointers if git-lfs wasn't set up for the repository yet.
+        dotbak.pull_lfs()?;
+
+        Ok(dotbak)
+    }
+
+    /// Restore an instance of `dotbak` from the offline bundle at `path`, writing the configuration file to
+    /// `<config>` and cloning the repository to `<repo>` (or `repo_path` from the bundled configuration).
+    /// The user's home directory is assumed to be `<home>`.
+    fn restore_bundle_into_dirs<P1, P2, P3, P4>(
+        home: P1,
+        config: P2,
+        repo: P3,
+        path: P4,
+        passphrase: SecretString,
+        verbosity: Verbosity,
+        overrides: &Overrides,
+    ) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+        P4: AsRef<Path>,
+    {
+        let config_path = config.as_ref().to_path_buf();
+        let repo_path = repo.as_ref().to_path_buf();
+        let home_path = home.as_ref().to_path_buf();
+        let mut interface = Interface::new(MAX_MSG_LEN, verbosity);
+        let logger = Logger::new(verbosity, &home_path);
+        let mut restore_spinner = interface.spawn_spinner(RESTORE_BUNDLE_MSG, 0);
+
+        restore_spinner.start();
+        let bundle = bundle::open(path, passphrase)?;
+        logger.info(format!(
+            "Restoring bundle from dotbak {}, host {}",
+            bundle.metadata.dotbak_version,
+            bundle.metadata.hostname.as_deref().unwrap_or("unknown"),
+        ));
+
+        // Write the bundled configuration file, unless there already is one.
+        if config_path.exists() {
+            return Err(ConfigError::AlreadyExists { path: config_path }.into());
+        }
+
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent).map_err(|err| IoError::Create {
+                source: err,
+                path: parent.to_path_buf(),
+            })?;
+        }
+
+        fs::write(&config_path, &bundle.config).map_err(|err| IoError::Write {
+            source: err,
+            path: config_path.clone(),
+        })?;
+
+        let mut config = Config::load_layered(&config_path, overrides)?;
+        let repo_path = config.repo_dir(&home_path)?.unwrap_or(repo_path);
+        let mut repo = bundle.clone_repo(&repo_path, config.repository_url.as_deref())?;
+        configure_cloned_branch(&mut repo, &mut config)?;
+        repo.set_low_bandwidth(config.network.low_bandwidth);
+        repo.set_sequence_trailer(config.history.sequence_trailer);
+        repo.set_pull_strategy(config.git.pull_strategy);
+        repo.set_timeouts(config.git.timeouts.clone());
+        repo.set_backend(config.git.backend)?;
+        repo.set_signing(config.git.signing());
+        repo.set_host(bundle::hostname());
+        repo.set_identity(
+            config.git.identity.name.as_deref(),
+            config.git.identity.email.as_deref(),
+        )?;
+        repo.set_progress(Some(git_progress(&interface)));
+        restore_spinner.close();
+
+        let mut dotfiles = Files::init(home_path, repo_path);
+        configure_files(&mut dotfiles, &config)?;
+
+        Ok(Dotbak {
+            dotfiles,
+            config,
+            repo,
+            logger,
+            interface,
+            trash: Arc::new(SystemTrash),
+            allow_secrets: false,
+            force: false,
+            offline: false,
+            automated: false,
+        })
+    }
+
+    /// Load an instance of `dotbak`, loading the configuration file from `<dotbak>/config.toml` and the
+    /// repository from `<dotbak>/dotfiles`.
+    fn load_into_dirs<P1, P2, P3>(
+        home: P1,
+        config: P2,
+        repo: P3,
+        verbosity: Verbosity,
+        overrides: &Overrides,
+    ) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+        P3: AsRef<Path>,
+    {
+        let config_path = config.as_ref().to_path_buf();
+        let repo_path = repo.as_ref().to_path_buf();
+        let home_path = home.as_ref().to_path_buf();
+
+        // Load the configuration file and the repository.
+        let config = Config::load_layered(config_path, overrides)?;
+        let repo_path = config.repo_dir(&home_path)?.unwrap_or(repo_path);
+        config.check_valid(&home_path)?;
+        let mut repo = Repository::load(&repo_path)?;
+        repo.set_branch(config.git.branch());
+        repo.set_low_bandwidth(config.network.low_bandwidth);
+        repo.set_sequence_trailer(config.history.sequence_trailer);
+        repo.set_pull_strategy(config.git.pull_strategy);
+        repo.set_timeouts(config.git.timeouts.clone());
+        repo.set_backend(config.git.backend)?;
+        repo.set_signing(config.git.signing());
+        repo.set_host(bundle::hostname());
+
+        let interface = Interface::new(MAX_MSG_LEN, verbosity);
+        repo.set_progress(Some(git_progress(&interface)));
+
+        let logger = Logger::new(verbosity, &home_path);
+        let mut dotfiles = Files::init(home_path, repo_path);
+        configure_files(&mut dotfiles, &config)?;
+
+        Ok(Dotbak {
+            dotfiles,
+            config,
+            repo,
+            logger,
+            interface,
+            trash: Arc::new(SystemTrash),
+            allow_secrets: false,
+            force: false,
+            offline: false,
+            automated: false,
+        })
+    }
+
+    /// Whether pushing should be deferred. This is the case in low bandwidth mode, when there are unpushed large
+    /// files and the connection is known to be metered.
+    fn should_defer_push(&mut self) -> Result<bool> {
+        if !self.repo.low_bandwidth() {
+            return Ok(false);
+        }
+
+        Ok(
+            self.repo.largest_unpushed_file()? > self.config.network.large_file_size
+                && network::detect_metered() == Metered::Yes,
+        )
+    }
+
+    /// The path of the configuration file inside the repository, if the configuration file is managed.
+    fn config_path_in_repo(&self) -> Option<PathBuf> {
+        let path = self
+            .config
+            .path
+            .strip_prefix(self.dotfiles.home_dir())
+            .ok()?;
+
+        self.config
+            .files
+            .include
+            .iter()
+            .any(|include| path.starts_with(include))
+            .then(|| path.to_path_buf())
+    }
+
+    /// Adds the lock files of plugin managers that exist in the home directory, but aren't managed yet, to the
+    /// `include` list.
+    fn track_plugin_locks(&mut self) -> Result<()> {
+        let untracked = plugins::untracked(
+            &plugins::scan(self.dotfiles.home_dir()),
+            &self.config.files.include,
+        );
+
+        if untracked.is_empty() {
+            return Ok(());
+        }
+
+        let mut track_spinner = self.interface.spawn_spinner(TRACK_LOCKS_MSG, 0);
+
+        track_spinner.start();
+        self.config
+            .files
+            .include
+            .extend(untracked.iter().map(|lock| PathBuf::from(lock.path)));
+        self.config.save_config()?;
+        track_spinner.close();
+        self.logger.info(format!(
+            "Tracking lock files: {}",
+            untracked
+                .iter()
+                .map(|lock| format!("{} ({})", lock.path, lock.manager))
+                .join(", ")
+        ));
+
+        Ok(())
+    }
+
+    /// Exports the installed packages of the package managers of this operating system (see `packages` in the
+    /// configuration) to the repository, so that they're committed. Package managers that fail (e.g. because they
+    /// aren't installed) are warned about, without stopping the sync.
+    fn export_packages(&mut self) {
+        let managers = self.config.packages.managers(std::env::consts::OS).to_vec();
+
+        if managers.is_empty() {
+            return;
+        }
+
+        let dir = self.dotfiles.file_dir().join(packages::PACKAGES_DIR);
+        let export_spinner = self.interface.spawn_spinner(EXPORT_PACKAGES_MSG, 0);
+
+        for manager in managers {
+            match packages::export(manager, &dir) {
+                Ok(true) => self
+                    .logger
+                    .info(format!("Exported the packages of {}", manager.name())),
+                Ok(false) => (),
+                Err(err) => self.interface.warn(format!(
+                    "Couldn't export the packages of {}: {}",
+                    manager.name(),
+                    err
+                )),
+            }
+        }
+
+        export_spinner.close();
+    }
+
+    /// Runs `run` on the repository, running it again with credentials if it fails to authenticate with an HTTPS
+    /// remote (see `next_credentials` for where they come from), and after a while if it can't reach the remote (see
+    /// `network::with_retries`).
+    fn authenticated<T, F>(&mut self, mut run: F) -> Result<T>
+    where
+        F: FnMut(&mut Repository) -> Result<T>,
+    {
+        let mut prompted = false;
+
+        loop {
+            let (network, interface) = (&self.config.network, &self.interface);
+            let result = network::with_retries(
+                network.retry_attempts,
+                network.retry_backoff,
+                thread::sleep,
+                || run(&mut self.repo),
+                |err, delay| warn_retry(interface, err, delay),
+            );
+
+            let err = match result {
+                Err(
+                    err @ DotbakError::Git(GitError::AuthFailed {
+                        transport: AuthFailure::Https,
+                        ..
+                    }),
+                ) => err,
+                result => return result,
+            };
+
+            match next_credentials(
+                &self.config,
+                &self.interface,
+                self.repo.has_credentials(),
+                &mut prompted,
+            )? {
+                Some(credentials) => self.repo.set_credentials(Some(credentials)),
+                None => return Err(err),
+            }
+        }
+    }
+
+    /// Follows the renames of included files and folders pulled in since the commit `before` (e.g. because another
+    /// machine moved them): updates the `include` list and moves their symlinks in the home directory.
+    ///
+    /// Returns the renames that were applied, as `(old, new)` pairs.
+    fn follow_upstream_renames(
+        &mut self,
+        before: Option<String>,
+    ) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let (Some(before), Some(after)) = (before, self.repo.head()) else {
+            return Ok(vec![]);
+        };
+
+        if before == after {
+            return Ok(vec![]);
+        }
+
+        let renames = self.repo.renames(&before, &after)?;
+        let renamed = paths::follow_renames(&self.config.files.include, &renames)
+            .into_iter()
+            // Folders which only had some of their files moved out of them are still there.
+            .filter(|(old, new)| {
+                !self.dotfiles.is_managed_in_repo(old) && self.dotfiles.is_managed_in_repo(new)
+            })
+            .collect_vec();
+
+        if renamed.is_empty() {
+            return Ok(vec![]);
+        }
+
+        for (old, new) in &renamed {
+            self.dotfiles.relink_renamed(old, new)?;
+
+            let include = &mut self.config.files.include;
+
+            if include.contains(new) {
+                include.retain(|entry| entry != old);
+            } else if let Some(entry) = include.iter_mut().find(|entry| *entry == old) {
+                *entry = new.clone();
+            }
+        }
+
+        self.config.save_config()?;
+
+        Ok(renamed)
+    }
+
+    /// Starts using a newly written key, encrypting and decrypting the files in `encryption.files` with it.
+    fn use_new_key(&mut self) -> Result<()> {
+        configure_files(&mut self.dotfiles, &self.config)?;
+        self.logger.info(format!(
+            "Using the encryption key at '{}'",
+            key_path(&self.config, self.dotfiles.home_dir()).display()
+        ));
+
+        self.sync_all_files()
+    }
+
+    /// Checks how much adding `files` (relative to the home directory, or glob patterns) would add to the repository.
+    /// If it's more than `files.add_size_limit`, the largest of the files/folders are shown, and the user has to
+    /// confirm adding them, unless `force` is set. Returns whether to add them.
+    fn confirm_add_size(&self, files: &[PathBuf]) -> Result<bool> {
+        let limit = self.config.files.add_size_limit;
+
+        if self.force || limit == 0 {
+            return Ok(true);
+        }
+
+        let sizes = self.dotfiles.sizes(&self.dotfiles.resolve_globs(files)?)?;
+        let total = sizes.iter().map(|(_, size)| size).sum::<u64>();
+
+        if total <= limit {
+            return Ok(true);
+        }
+
+        self.interface.println(format!(
+            "This adds {} to the repository, more than `files.add_size_limit` ({}). The largest are:\n{}",
+            HumanBytes(total),
+            HumanBytes(limit),
+            sizes
+                .iter()
+                .take(LARGEST_SHOWN)
+                .map(|(path, size)| format!("   {:>10}  {}", HumanBytes(*size), path.display()))
+                .join("\n")
+        ));
+
+        Ok(self
+            .interface
+            .choose("Add them anyways?", &["don't add them", "add them"])?
+            == 1)
+    }
+
+    /// Rewrites the hard-coded paths to the home directory in the managed `files` if `rewrite_home` is set, or warns
+    /// about them otherwise.
+    fn check_home_paths(&mut self, files: &[PathBuf], rewrite_home: bool) -> Result<()> {
+        let files = files
+            .iter()
+            .map(|file| self.dotfiles.repo_relative(file))
+            .collect_vec();
+        let (home, repo, limit) = (
+            self.dotfiles.home_dir(),
+            self.dotfiles.file_dir(),
+            self.config.files.diff_memory_limit,
+        );
+
+        if rewrite_home {
+            for (file, count) in rewrite::rewrite_files(&files, repo, home, limit)? {
+                self.logger.info(format!(
+                    "Rewrote {} hard-coded path(s) to your home directory in '{}'",
+                    count,
+                    file.display()
+                ));
+            }
+
+            return Ok(());
+        }
+
+        let found = rewrite::scan(&files, repo, home, limit)?;
+
+        for (file, lines) in &found.iter().group_by(|found| &found.path) {
+            self.interface.warn(format!(
+                "'{}' contains {} hard-coded path(s) to your home directory, which break on machines with a different home directory. Add it with `--rewrite-home` to rewrite them, or see `dotbak lint`.",
+                file.display(),
+                lines.count()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Tells the user about the renames applied by `follow_upstream_renames`.
+    fn report_renames(&self, renamed: &[(PathBuf, PathBuf)]) {
+        if renamed.is_empty() {
+            return;
+        }
+
+        for (old, new) in renamed {
+            self.interface.println(format!(
+                "🚚 Followed the rename of '{}' to '{}'",
+                old.display(),
+                new.display()
+            ));
+        }
+
+        self.logger.info(format!(
+            "Followed renames: {}",
+            renamed
+                .iter()
+                .map(|(old, new)| format!("{} -> {}", old.display(), new.display()))
+                .join(", ")
+        ));
+    }
+
+    /// Runs `pull` with the uncommitted changes in the repository (e.g. files edited in it directly) stashed, so that
+    /// pulling can't fail because of them. They're reapplied afterwards, even if `pull` failed. If they conflict with
+    /// what was pulled, the conflicts are left in the files to resolve, and an error listing them is returned.
+    fn with_stash<F>(&mut self, pull: F) -> Result<()>
+    where
+        F: FnOnce(&mut Dotbak) -> Result<()>,
+    {
+        let stashed = self.repo.stash_push()?;
+
+        if stashed > 0 {
+            self.logger.info(format!(
+                "Stashed {} uncommitted change(s) in the repository before pulling",
+                stashed
+            ));
+        }
+
+        let result = pull(self);
+
+        if stashed == 0 {
+            return result;
+        }
+
+        let conflicts = self.repo.stash_pop()?;
+
+        if !conflicts.is_empty() {
+            self.logger.error(format!(
+                "Stashed changes conflict with the pulled ones in: {}",
+                conflicts.iter().map(|path| path.display()).join(", ")
+            ));
+
+            return Err(DotbakError::StashConflict {
+                paths: conflicts
+                    .iter()
+                    .map(|path| format!("  {}", path.display()))
+                    .join("\n"),
+            });
+        }
+
+        self.logger
+            .info("Reapplied the stashed changes in the repository");
+
+        result
+    }
+
+    /// Warns about the pulled commits that aren't signed (or whose signature is bad), if `git.require_signed` is set.
+    /// `before` is the commit the branch was at before pulling.
+    fn warn_unsigned(&mut self, before: Option<&str>) -> Result<()> {
+        if !self.config.git.require_signed {
+            return Ok(());
+        }
+
+        let unsigned = self.repo.unsigned_commits(before)?;
+
+        if unsigned.is_empty() {
+            return Ok(());
+        }
+
+        self.interface.warn(format!(
+            "Pulled {} commit(s) that aren't signed, or whose signature is bad:\n{}",
+            unsigned.len(),
+            unsigned
+                .iter()
+                .map(|commit| format!(
+                    "  {} {} ({})",
+                    &commit.hash[..7.min(commit.hash.len())],
+                    commit.summary,
+                    commit.author
+                ))
+                .join("\n")
+        ));
+        self.logger.info(format!(
+            "Pulled unsigned commits: {}",
+            unsigned.iter().map(|commit| &commit.hash).join(", ")
+        ));
+
+        Ok(())
+    }
+
+    /// Warns that nothing is pulled or pushed, as this machine is pinned to `pin`.
+    fn warn_pinned(&self, pin: &Pin) {
+        self.interface.warn(format!(
+            "Pinned to {}, so nothing is pulled or pushed. Run `dotbak unpin` to catch up again.",
+            &pin.commit[..7.min(pin.commit.len())]
+        ));
+        self.logger.info(format!(
+            "Skipped pulling and pushing: pinned to {}",
+            pin.commit
+        ));
+    }
+
+    /// Warns about the commits that haven't been pushed to the remote yet, or pushes them right away if
+    /// `git.auto_push` is set (unless pushing is deferred, see `should_defer_push`). Nothing happens without a
+    /// remote, or when syncs are pushed to another branch for review (see `history.staging`). Either way, the number
+    /// of commits left unpushed is recorded for `dotbak prompt` (see `record_unpushed`).
+    fn handle_unpushed(&mut self) -> Result<()> {
+        self.push_unpushed()?;
+        self.record_unpushed()
+    }
+
+    /// Records the number of commits that haven't been pushed to the remote yet, as of the last fetch, in the
+    /// managed files of this machine (see `ManagedFiles::unpushed`), so that `dotbak prompt` can show it without
+    /// running git.
+    fn record_unpushed(&mut self) -> Result<()> {
+        let mut managed = ManagedFiles::load(self.state_dir()?)?;
+        let (unpushed, _) = self.repo.ahead_behind()?;
+
+        if managed.unpushed() != unpushed {
+            managed.set_unpushed(unpushed);
+            managed.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Does the work of `handle_unpushed`, except for recording the commits left unpushed.
+    fn push_unpushed(&mut self) -> Result<()> {
+        // Pinned machines keep their commits until they're unpinned (see `pin`), and queued pushes wait for the next
+        // sync that reaches the remote.
+        if self.repo.push_branch() != self.repo.branch()
+            || self.pinned()?.is_some()
+            || self.queued_push()?.is_some()
+            || !self
+                .repo
+                .list_remotes()?
+                .iter()
+                .any(|remote| remote.name == git::REMOTE_NAME)
+        {
+            return Ok(());
+        }
+
+        let (unpushed, _) = self.repo.ahead_behind()?;
+
+        if unpushed == 0 {
+            return Ok(());
+        }
+
+        if self.config.git.auto_push && !self.should_defer_push()? {
+            let mut push_spinner = self.interface.spawn_spinner(PUSH_MSG, 0);
+
+            self.run_hooks(Hook::PrePush)?;
+            push_spinner.start();
+            self.push_remotes()?;
+            push_spinner.close();
+            self.run_hooks(Hook::PostPush)?;
+
+            return Ok(());
+        }
+
+        self.interface.warn(format!(
+            "{} commit(s) haven't been pushed yet. Run `dotbak push` to push them, or set `git.auto_push` to push them right away.",
+            unpushed
+        ));
+
+        Ok(())
+    }
+
+    /// Pushes to every remote in `git.push_remotes`, adding the configured remotes to the repository first if
+    /// they're missing. Failing to push to `origin` is an error, but failing to push to another remote (e.g. a
+    /// backup mirror that's offline) is only a warning.
+    fn push_remotes(&mut self) -> Result<()> {
+        let existing = self.repo.list_remotes()?;
+
+        for (name, remote) in &self.config.git.remotes {
+            if !existing
+                .iter()
+                .any(|other| &other.name == name && other.url == remote.url())
+            {
+                let output = self.repo.add_remote(name, remote.url())?;
+                self.logger.log_output(output);
+            }
+        }
+
+        let names = self
+            .config
+            .git
+            .push_remotes()
+            .into_iter()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        for name in names {
+            match self.authenticated(|repo| repo.push_to(&name)) {
+                Ok(output) => self.logger.log_output(output),
+                Err(err) if name != git::REMOTE_NAME => {
+                    self.interface
+                        .warn(format!("Couldn't push to the remote '{}'.", name));
+                    self.logger
+                        .error(format!("Failed to push to '{}': {:?}", name, err));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        if QueuedPush::remove(self.state_dir()?)? {
+            self.logger.info("Pushed the queued commits");
+        }
+
+        Ok(())
+    }
+
+    /// Queues pushing the commits until the next sync that reaches the remote (see `QueuedPush`), because of
+    /// `reason`, and tells the user about it.
+    fn queue_push(&mut self, reason: &str) -> Result<()> {
+        let dir = self.state_dir()?;
+        let since = QueuedPush::load(&dir)?.map_or_else(clock::now, |queued| queued.since);
+
+        QueuedPush {
+            since,
+            reason: reason.to_string(),
+        }
+        .save(&dir)?;
+
+        self.interface.warn(format!(
+            "Committed locally without pushing ({}). The push is queued until the next sync that reaches the remote.",
+            reason
+        ));
+        self.logger.info(format!("Queued the push: {}", reason));
+
+        Ok(())
+    }
+
+    /// Runs the commands of `hook` (see `HooksConfig`) in the home directory, and logs their output.
+    fn run_hooks(&mut self, hook: Hook) -> Result<()> {
+        let commands = self.config.hooks.commands(hook).to_vec();
+
+        if commands.is_empty() {
+            return Ok(());
+        }
+
+        let mut hooks_spinner = self.interface.spawn_spinner(HOOKS_MSG, 0);
+        hooks_spinner.start();
+        let outputs = hooks::run(
+            hook,
+            &commands,
+            self.dotfiles.home_dir(),
+            self.dotfiles.file_dir(),
+        )?;
+        hooks_spinner.close();
+        self.logger.info(format!("Ran {} hooks", hook.name()));
+
+        for output in outputs {
+            self.logger.log_output(output);
+        }
+
+        Ok(())
+    }
+
+    /// Warns if the newest known commit (on any local or remote-tracking branch) is further in the future than
+    /// `history.max_clock_skew` allows, as this means that the clock of this machine (or of another one) is off.
+    fn check_clock_skew(&mut self) -> Result<()> {
+        let now = clock::now() as i64;
+
+        if let Some(skew) = self
+            .repo
+            .newest_commit_time()?
+            .and_then(|newest| clock_skew(newest, now, self.config.history.max_clock_skew))
+        {
+            self.interface.warn(format!(
+                "The newest commit is {} in the future. Check the clocks of this and your other machines!",
+                HumanDuration(Duration::from_secs(skew))
+            ));
+            self.logger
+                .info(format!("Detected clock skew of {} seconds", skew));
+        }
+
+        Ok(())
+    }
+
+    /// Keeps the part of the repository's `.gitattributes` that stores files with git-lfs in sync with
+    /// `git.lfs.track`, setting up git-lfs for the repository first. Files that were committed before they matched
+    /// are stored with git-lfs from the next commit on. If git-lfs isn't installed, `.gitattributes` is left alone.
+    fn update_lfs(&mut self) -> Result<()> {
+        let track = self.config.git.lfs.track.clone();
+
+        if !track.is_empty() {
+            if !git::lfs_available() {
+                self.interface.warn(
+                    "git-lfs isn't installed, so the files in `git.lfs.track` are committed as usual.",
+                );
+
+                return Ok(());
+            }
+
+            let output = self.repo.lfs_install()?;
+            self.logger.log_output(output);
+        }
+
+        if self.repo.set_lfs_tracked(&track)? {
+            self.logger.info(format!(
+                "Updated the files stored with git-lfs: {}",
+                track.join(", ")
+            ));
+
+            if !track.is_empty() {
+                let output = self.repo.renormalize()?;
+                self.logger.log_output(output);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Downloads the git-lfs content of the checked out files if files are stored with git-lfs (see
+    /// `git.lfs.track`), so that they aren't left as pointers. Nothing is downloaded in low bandwidth mode, or if
+    /// git-lfs isn't installed.
+    fn pull_lfs(&mut self) -> Result<()> {
+        if self.config.git.lfs.track.is_empty()
+            || self.repo.low_bandwidth()
+            || !git::lfs_available()
+        {
+            return Ok(());
+        }
+
+        let output = self.repo.lfs_install()?;
+        self.logger.log_output(output);
+        let output = self.authenticated(Repository::lfs_pull)?;
+        self.logger.log_output(output);
+
+        Ok(())
+    }
+
+    /// Keeps the repository's `.gitignore` in sync with `ignore.patterns`, and removes the files that are ignored
+    /// from the repository (without deleting them).
+    fn update_ignored(&mut self) -> Result<()> {
+        self.dotfiles.set_ignored(&self.config.ignore.patterns)?;
+
+        if self.repo.set_ignored(&self.config.ignore.patterns)? {
+            self.logger.info(format!(
+                "Updated .gitignore: {}",
+                self.config.ignore.patterns.join(", ")
+            ));
+        }
+
+        let untracked = self.repo.untrack_ignored()?;
+
+        if !untracked.is_empty() {
+            self.logger.info(format!(
+                "Removed ignored files from the repository: {}",
+                untracked.iter().map(|f| f.display()).join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Commits all changes in the repository with `message`. If git doesn't know who to make the commit as, the user
+    /// is asked for their name and email (when they can be), which are saved in `git.identity` and the commit is
+    /// tried again.
+    fn commit(&mut self, message: &str) -> Result<[Output; 2]> {
+        match self.repo.commit(message) {
+            Err(DotbakError::Git(GitError::MissingIdentity { .. }))
+                if io::stdin().is_terminal() && self.interface.is_interactive() =>
+            {
+                self.ask_identity()?;
+                self.repo.commit(message)
+            }
+            result => result,
+        }
+    }
+
+    /// Commits all changes of a sync. The commits of automated syncs (see `automated`) are squashed into the previous
+    /// one instead if `daemon.amend` is set, or if this machine already made `daemon.commits_per_day` of them today,
+    /// as long as the previous commit is one of them too and wasn't pushed anywhere yet.
+    fn commit_sync(&mut self) -> Result<[Output; 2]> {
+        if !self.automated {
+            return self.commit(SYNC_COMMIT_MSG);
+        }
+
+        let daemon = &self.config.daemon;
+        let (amend, limit) = (daemon.amend, daemon.commits_per_day);
+
+        let compact = (amend || limit > 0)
+            && self.repo.uncommitted_changes()? > 0
+            && self.repo.has_commit("HEAD~1")
+            && self.repo.is_own_commit("HEAD", AUTOMATED_SYNC_COMMIT_MSG)?
+            && !self.repo.is_on_remote("HEAD")
+            && (amend
+                || self
+                    .repo
+                    .count_own_commits_since(AUTOMATED_SYNC_COMMIT_MSG, "midnight")?
+                    >= limit);
+
+        if compact {
+            self.logger
+                .info("Squashing the changes into the previous automated sync commit");
+            let output = self.repo.uncommit(1)?;
+            self.logger.log_output(output);
+        }
+
+        self.commit(AUTOMATED_SYNC_COMMIT_MSG)
+    }
+
+    /// Shows the files `dropped` (with their sizes) that rewriting the history drops, largest first, and asks the
+    /// user whether to rewrite it.
+    fn confirm_rewrite(&mut self, dropped: &[(PathBuf, u64)]) -> Result<bool> {
+        self.interface.println(format!(
+            "✂️ This rewrites the history to drop {} file(s), which replaces the history of the remote too:",
+            dropped.len()
+        ));
+
+        for (path, size) in dropped
+            .iter()
+            .sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)))
+            .take(LARGEST_SHOWN)
+        {
+            self.interface
+                .println(format!("   {} ({})", path.display(), HumanBytes(*size)));
+        }
+
+        if dropped.len() > LARGEST_SHOWN {
+            self.interface
+                .println(format!("   ...and {} more", dropped.len() - LARGEST_SHOWN));
+        }
+
+        Ok(self
+            .interface
+            .choose("Rewrite the history?", &["keep it", "rewrite it"])?
+            == 1)
+    }
+
+    /// Asks the user for the name and email to make commits as, and saves them in `git.identity` and the
+    /// repository's git config.
+    fn ask_identity(&mut self) -> Result<()> {
+        self.interface.warn(
+            "Git doesn't know who to make commits as. Enter the name and email to make them as (they're saved in \
+             `git.identity`).",
+        );
+        let name = self.interface.ask("Name:")?;
+        let email = self.interface.ask("Email:")?;
+
+        self.config.git.identity.name = Some(name).filter(|name| !name.is_empty());
+        self.config.git.identity.email = Some(email).filter(|email| !email.is_empty());
+        self.config.save_config()?;
+
+        self.repo.set_identity(
+            self.config.git.identity.name.as_deref(),
+            self.config.git.identity.email.as_deref(),
+        )
+    }
+
+    /// Updates `.gitignore` (see `update_ignored`) and commits it with `message`.
+    fn commit_ignored(&mut self, message: &str) -> Result<()> {
+        let mut commit_spinner = self.interface.spawn_spinner(COMMIT_MSG, 0);
+
+        self.update_ignored()?;
+
+        commit_spinner.start();
+        let outputs = self.commit(message)?;
+        commit_spinner.close();
+        self.logger.log_outputs(outputs);
+
+        Ok(())
+    }
+
+    /// Marks the managed files which are too large or binary to be diffed (see `files.diff_memory_limit`) as binary
+    /// in the repository's `.gitattributes`, so that git doesn't diff or merge them line by line.
+    fn mark_binaries(&mut self) -> Result<()> {
+        let binaries = compare::large_or_binary(
+            &self.repo_include(),
+            self.dotfiles.file_dir(),
+            self.config.files.diff_memory_limit,
+        )?;
+
+        if self.repo.mark_binary(&binaries)? {
+            self.logger.info(format!(
+                "Marked as binary: {}",
+                binaries.iter().map(|f| f.display()).join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Shows the differences between the tracked version of a file/folder at `repo_path` and its replacement at
+    /// `home_path`. Files which are too large or binary to be diffed are only compared by size.
+    fn diff_replaced(&self, repo_path: &Path, home_path: &Path) -> Result<String> {
+        let limit = self.config.files.diff_memory_limit;
+
+        if !compare::is_large_or_binary(repo_path, limit)?
+            && !compare::is_large_or_binary(home_path, limit)?
+        {
+            return git::diff_paths(repo_path, home_path);
+        }
+
+        let size = |path: &Path| fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+
+        Ok(format!(
+            "Binary or large files differ ({} bytes tracked, {} bytes now)",
+            size(repo_path),
+            size(home_path)
+        ))
+    }
+
+    /// Restores the symlinks of managed files that were replaced by real files, see `repair`. Returns the repaired
+    /// files.
+    fn repair_replaced(&mut self, adopt_changes: bool) -> Result<Vec<PathBuf>> {
+        let replaced = self.dotfiles.replaced(&self.config.files.include);
+
+        for file in &replaced {
+            let home_path = self.dotfiles.home_dir().join(file);
+            let repo_path = self.dotfiles.repo_path(file);
+
+            // A replacement with the same contents doesn't need a decision.
+            let choice = if compare::same_contents(&repo_path, &home_path)? {
+                0
+            } else if adopt_changes {
+                self.interface.println(format!(
+                    "🔍 '{}' was replaced. Changes from the tracked version:",
+                    file.display()
+                ));
+                self.interface
+                    .println(self.diff_replaced(&repo_path, &home_path)?);
+
+                self.interface.choose(
+                    format!("What should be done with '{}'?", file.display()),
+                    &[
+                        "keep the tracked version",
+                        "adopt the new version",
+                        "edit the tracked version",
+                    ],
+                )?
+            } else {
+                0
+            };
+
+            match choice {
+                // Adopt the new version, replacing the tracked one.
+                1 => {
+                    self.dotfiles.adopt_from_home(file)?;
+                    self.logger
+                        .info(format!("Adopted the new version of '{}'", file.display()));
+                }
+
+                // Keep the tracked version (possibly after editing it), backing up the new one.
+                _ => {
+                    if choice == 2 {
+                        self.interface.open_editor(&repo_path)?;
+                    }
+
+                    let backup = self.dotfiles.backup_in_home(file)?;
+                    self.dotfiles.symlink_back_home(&[file])?;
+                    self.logger.info(format!(
+                        "Restored '{}', and backed up the replacement to '{}'",
+                        file.display(),
+                        backup.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(replaced)
+    }
+
+    /// Re-creates the symlinks of managed files that were deleted from the home directory, see `repair`. Returns
+    /// the repaired files.
+    fn repair_missing(&mut self) -> Result<Vec<PathBuf>> {
+        let missing = self
+            .managed_paths()?
+            .into_iter()
+            .filter(|path| {
+                self.dotfiles.link_state(path) == LinkState::Missing
+                    && (self.dotfiles.is_managed_in_repo(path)
+                        || self
+                            .dotfiles
+                            .file_dir()
+                            .join(encrypt::encrypted_path(path))
+                            .exists())
+            })
+            .collect::<Vec<_>>();
+
+        for file in &missing {
+            self.dotfiles.symlink_back_home(&[file])?;
+            self.logger
+                .info(format!("Re-created the symlink of '{}'", file.display()));
+        }
+
+        Ok(missing)
+    }
+
+    /// Asks the user what to do with the symlinks into the repository that point to missing files, or to files
+    /// that aren't managed anymore, see `repair`. Returns the repaired files.
+    fn repair_stray_links(&mut self) -> Result<Vec<PathBuf>> {
+        let managed = self.managed_paths()?;
+        let mut repaired = vec![];
+
+        for (link, target) in self.dotfiles.links_into_repo()? {
+            let is_managed = managed.iter().any(|path| link.starts_with(path));
+            let exists = || fs::symlink_metadata(self.dotfiles.file_dir().join(&target)).is_ok();
+
+            if !exists() {
+                let choice = self.interface.choose(
+                    format!(
+                        "'{}' points to '{}', which is missing from the repository. What should be done?",
+                        link.display(),
+                        target.display()
+                    ),
+                    &[
+                        "restore it from the history",
+                        "delete the symlink",
+                        "leave it alone",
+                    ],
+                )?;
+
+                match choice {
+                    0 if self.repo.restore_from_history(&target)? => {
+                        self.logger
+                            .info(format!("Restored '{}' from the history", target.display()));
+                        repaired.push(target.clone());
+                    }
+                    0 => {
+                        self.interface.warn(format!(
+                            "'{}' isn't in the history, so it can't be restored",
+                            target.display()
+                        ));
+                        continue;
+                    }
+                    1 => {
+                        self.dotfiles.remove_link(&link)?;
+                        self.logger
+                            .info(format!("Deleted the symlink '{}'", link.display()));
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+
+            if is_managed {
+                continue;
+            }
+
+            // Files can only be managed again from where their symlinks are.
+            let choices: &[&str] = match link == target {
+                true => &["manage it again", "delete the symlink", "leave it alone"],
+                false => &["delete the symlink", "leave it alone"],
+            };
+            let choice = self.interface.choose(
+                format!(
+                    "'{}' points to '{}', which isn't in `files.include`. What should be done?",
+                    link.display(),
+                    target.display()
+                ),
+                choices,
+            )?;
+
+            match choices[choice] {
+                "manage it again" => {
+                    self.config.files.include.push(link.clone());
+                    self.config.save_config()?;
+                    self.sync_files(&[&link])?;
+                    self.logger
+                        .info(format!("Managing '{}' again", link.display()));
+
+                    if !repaired.contains(&link) {
+                        repaired.push(link);
+                    }
+                }
+                "delete the symlink" => {
+                    self.dotfiles.remove_link(&link)?;
+                    self.logger
+                        .info(format!("Deleted the symlink '{}'", link.display()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(repaired)
+    }
+
+    /// Gets the files/folders that are linked for `files.include`: glob patterns are resolved into the paths they
+    /// match (see `Files::resolve_globs`), and folders with excluded paths inside of them are split into their
+    /// children (see `Files::expand_excluded`).
+    fn managed_paths(&self) -> Result<Vec<PathBuf>> {
+        let include = self.dotfiles.resolve_globs(&self.config.files.include)?;
+
+        Ok(self.dotfiles.expand_excluded(&include))
+    }
+
+    /// Gets the entries of `files.include` as paths in the repository (see `Files::repo_relative`), for what looks at
+    /// the managed files there rather than in the home directory.
+    fn repo_include(&self) -> Vec<PathBuf> {
+        self.config
+            .files
+            .include
+            .iter()
+            .map(|path| self.dotfiles.repo_relative(path))
+            .collect()
+    }
+
+    /// Synchronize all files that are supposed to be synchronized.
+    ///
+    /// Paths that fail to sync are retried on every sync, but the first error is only returned after all other paths
+    /// were synced. Paths that failed `files.quarantine_after` times in a row are quarantined and skipped instead.
+    fn sync_all_files(&mut self) -> Result<()> {
+        // Files that were added to `include` since the last sync need to be checked out first.
+        self.update_sparse_checkout()?;
+
+        // Folders with excluded paths inside of them are split by syncing them, so only the patterns are resolved.
+        let mut files = self.dotfiles.resolve_globs(&self.config.files.include)?;
+        let mut quarantine = Quarantine::load(self.state_dir()?)?;
+        let mut first_err = None;
+
+        files.retain(|file| {
+            let quarantined = quarantine.is_quarantined(file);
+
+            if quarantined {
+                self.logger
+                    .info(format!("Skipped quarantined file '{}'", file.display()));
+            }
+
+            !quarantined
+        });
+
+        // Don't overwrite real files that differ from the tracked version without asking.
+        let left_alone = self.protect_overwritten(&files)?;
+        files.retain(|file| !left_alone.iter().any(|left| left.starts_with(file)));
+
+        let progress = self.spawn_progress(SYNC_MSG, files.len());
+        let mut synced = vec![];
+
+        for file in files {
+            match self.sync_files(&[&file]) {
+                Ok(()) => {
+                    quarantine.record_success(&file);
+                    synced.push(file);
+                }
+                Err(err) => {
+                    let threshold = self.config.files.quarantine_after;
+
+                    if quarantine.record_failure(&file, &err, threshold) {
+                        self.interface.warn(format!(
+                            "Quarantined '{}' after {} failed syncs in a row, it's skipped until you fix it and run `dotbak quarantine clear {}`: {}",
+                            file.display(),
+                            threshold,
+                            file.display(),
+                            err
+                        ));
+                    } else if first_err.is_none() {
+                        first_err = Some(err);
+                    }
+                }
+            }
+        }
+
+        self.close_progress(progress);
+        quarantine.save()?;
+        self.restore_metadata()?;
+        self.record_managed(&synced)?;
+
+        match first_err {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps syncing `files` from overwriting the real files in the home directory that replaced their symlinks,
+    /// if they differ from the tracked version (see `Files::overwritten`), e.g. a fresh default configuration file
+    /// on a new machine, or changes made while the repository was stale. Unless `force` is set, edits that don't clash
+    /// with the tracked version are merged into it (see `Files::merge_from_home`). For the rest, the user sees the
+    /// differences and chooses to back them up (the default, also when running non-interactively), to adopt them, or
+    /// to leave them alone for now. Returns the files to leave alone, which mustn't be synced.
+    fn protect_overwritten(&mut self, files: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let overwritten = self.dotfiles.overwritten(files)?;
+        let mut left_alone = vec![];
+
+        if self.force {
+            for file in &overwritten {
+                self.logger.info(format!(
+                    "Overwrote '{}' with the tracked version",
+                    file.display()
+                ));
+            }
+
+            return Ok(left_alone);
+        }
+
+        for file in overwritten {
+            // Edits that don't clash with the tracked version are imported without asking.
+            if self
+                .dotfiles
+                .merge_from_home(&file, self.config.files.diff_memory_limit)?
+            {
+                self.logger.info(format!(
+                    "Merged the edits to '{}' into the tracked version",
+                    file.display()
+                ));
+                continue;
+            }
+
+            let home_path = self.dotfiles.home_dir().join(&file);
+            let repo_path = self.dotfiles.repo_path(&file);
+            self.interface.println(format!(
+                "🔍 '{}' replaced its symlink. Changes from the tracked version:",
+                file.display()
+            ));
+            self.interface
+                .println(self.diff_replaced(&repo_path, &home_path)?);
+
+            let choice = self.interface.choose(
+                format!(
+                    "'{}' differs from the tracked version, and would be overwritten by it. What should be done with it?",
+                    file.display()
+                ),
+                &[
+                    "back it up",
+                    "adopt it as the tracked version",
+                    "leave it alone for now",
+                ],
+            )?;
+
+            match choice {
+                0 => {
+                    let backup = self.dotfiles.backup_in_home(&file)?;
+                    self.interface.warn(format!(
+                        "'{}' differed from the tracked version, so it was backed up to '{}'",
+                        file.display(),
+                        backup.display()
+                    ));
+                }
+                1 => {
+                    self.dotfiles.adopt_from_home(&file)?;
+                    self.logger
+                        .info(format!("Adopted the new version of '{}'", file.display()));
+                }
+                _ => {
+                    self.logger.info(format!(
+                        "Left '{}' alone, as it differs from the tracked version",
+                        file.display()
+                    ));
+                    left_alone.push(file);
+                }
+            }
+        }
+
+        Ok(left_alone)
+    }
+
+    /// Looks for secrets in the files about to be committed (see `secrets` in the configuration), and fails without
+    /// committing anything if there are any. The secrets themselves aren't shown, only where they are.
+    fn check_secrets(&mut self) -> Result<()> {
+        if !self.config.secrets.scan || self.allow_secrets {
+            return Ok(());
+        }
+
+        let scanner = Scanner::new(&self.config.secrets)?;
+        let changed = self.repo.changed_files()?;
+        let found = scanner.scan(
+            &changed,
+            self.dotfiles.file_dir(),
+            self.config.files.diff_memory_limit,
+        )?;
+
+        if found.is_empty() {
+            return Ok(());
+        }
+
+        Err(DotbakError::SecretsFound {
+            count: found.len(),
+            found: found
+                .iter()
+                .map(|secret| {
+                    format!(
+                        "  {}:{}: {}",
+                        secret.path.display(),
+                        secret.line,
+                        secret.kind
+                    )
+                })
+                .join("\n"),
+        })
+    }
+
+    /// Records the permissions (and extended attributes, if `files.xattrs` is set) of the managed files in the
+    /// metadata manifest, as git doesn't keep them. The user is warned about the ones git can't represent at all,
+    /// when they're first recorded. See `metadata::merge` for how changes from other machines are kept.
+    fn record_metadata(&mut self) -> Result<()> {
+        let manifest_path = self.dotfiles.file_dir().join(metadata::MANIFEST_FILE);
+        let known_path = self.state_dir()?.join(metadata::KNOWN_FILE_NAME);
+        let manifest = Manifest::load(&manifest_path)?;
+        let current = self.current_metadata();
+        let merged = metadata::merge(&manifest, &Manifest::load(&known_path)?, &current);
+
+        let unrepresentable = merged
+            .iter()
+            .filter(|(path, recorded)| {
+                !recorded.git_keeps() && manifest.get(path) != Some(*recorded)
+            })
+            .map(|(path, recorded)| format!("'{}' ({:o})", path.display(), recorded.mode))
+            .collect_vec();
+
+        if !unrepresentable.is_empty() {
+            self.interface.warn(format!(
+                "Git can't keep the permissions of {}, so they're recorded in `{}` and restored from there on your other machines.",
+                unrepresentable.join(", "),
+                metadata::MANIFEST_FILE
+            ));
+        }
+
+        if merged != manifest {
+            merged.save(&manifest_path)?;
+        }
+
+        current.save(known_path)
+    }
+
+    /// Restores the permissions (and extended attributes) of the managed files from the metadata manifest, where
+    /// they didn't change on this machine since they were last recorded or restored. See `metadata::to_restore`.
+    fn restore_metadata(&mut self) -> Result<()> {
+        let known_path = self.state_dir()?.join(metadata::KNOWN_FILE_NAME);
+        let manifest = Manifest::load(self.dotfiles.file_dir().join(metadata::MANIFEST_FILE))?;
+        let known = Manifest::load(&known_path)?;
+        let restore = metadata::to_restore(&manifest, &known, &self.current_metadata());
+
+        for (path, recorded) in &restore {
+            recorded.apply(self.dotfiles.contents_path(path))?;
+        }
+
+        let restore = restore.into_iter().map(|(path, _)| path).collect_vec();
+
+        if !restore.is_empty() {
+            self.logger.info(format!(
+                "Restored the permissions of: {}",
+                restore.iter().map(|path| path.display()).join(", ")
+            ));
+        }
+
+        let updated = metadata::update_known(&known, &self.current_metadata(), &restore);
+
+        if updated != known {
+            updated.save(known_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records `files` (relative to the home directory) as they are now in the managed files of this machine (see
+    /// `ManagedFiles`), along with the entries of `files.include` covering them.
+    fn record_managed(&self, files: &[PathBuf]) -> Result<()> {
+        let mut managed = ManagedFiles::load(self.state_dir()?)?;
+        let now = clock::now();
+
+        for file in files {
+            let include = managed::include_entry(&self.config.files.include, file)
+                .unwrap_or(file)
+                .clone();
+
+            managed.record(ManagedEntry::snapshot(&self.dotfiles, file, include, now));
+        }
+
+        managed.save()
+    }
+
+    /// Forgets the managed files of this machine that no entry of `files.include` covers anymore (see
+    /// `ManagedFiles`), e.g. because the configuration file was edited by hand. Their symlinks in the home directory
+    /// are removed if what they pointed to is gone from the repository.
+    fn forget_unlisted(&self) -> Result<()> {
+        let mut managed = ManagedFiles::load(self.state_dir()?)?;
+        let unlisted = managed
+            .entries()
+            .filter(|entry| {
+                managed::include_entry(&self.config.files.include, &entry.path).is_none()
+            })
+            .map(|entry| entry.path.clone())
+            .collect_vec();
+
+        for file in unlisted {
+            if self.dotfiles.is_managed_in_home(&file) && !self.dotfiles.is_managed_in_repo(&file) {
+                self.dotfiles.remove_link(&file)?;
+                self.logger
+                    .info(format!("Removed the stale symlink '{}'", file.display()));
+            }
+
+            managed.forget(&file);
+        }
+
+        managed.save()
+    }
+
+    /// Gets the metadata of the managed files (and the files in managed folders that aren't ignored) on this machine.
+    fn current_metadata(&self) -> Manifest {
+        let mut current = Manifest::default();
+
+        for file in &self.config.files.include {
+            current.scan(
+                file,
+                self.dotfiles.contents_path(file),
+                self.config.files.xattrs,
+            );
+        }
+
+        // Ignored files are never committed, so their permissions aren't recorded either.
+        current.retain(|path| !self.dotfiles.is_ignored(path));
+
+        current
+    }
+
+    /// Checks out only the managed files (and the encrypted versions of encrypted ones, the metadata manifest and the
+    /// exported package lists) if `files.sparse` is set, or every file again if it was turned off.
+    fn update_sparse_checkout(&mut self) -> Result<()> {
+        if !self.config.files.sparse {
+            if self.repo.is_sparse() {
+                let output = self.repo.sparse_checkout(None::<&[PathBuf]>)?;
+                self.logger.log_output(output);
+            }
+
+            return Ok(());
+        }
+
+        let paths = self
+            .config
+            .files
+            .include
+            .iter()
+            .flat_map(|file| {
+                let path = self.dotfiles.repo_relative(file);
+
+                match self.dotfiles.is_encrypted(file) {
+                    true => vec![encrypt::encrypted_path(&path), path],
+                    false => vec![path],
+                }
+            })
+            .chain([
+                PathBuf::from(metadata::MANIFEST_FILE),
+                PathBuf::from(packages::PACKAGES_DIR),
+            ])
+            .collect_vec();
+
+        let output = self.repo.sparse_checkout(Some(&paths))?;
+        self.logger.log_output(output);
+
+        Ok(())
+    }
+
+    /// Runs `run` (a sync, push or pull, as `kind` says) and records how it went in the history of runs. Failing to
+    /// record it is only logged, so that it doesn't fail the run itself.
+    fn record_run<F>(&mut self, kind: RunKind, run: F) -> Result<()>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        // Pushes change the remote's branch, and syncs and pulls the local one.
+        let rev = match kind {
+            RunKind::Push => format!("{}/{}", git::REMOTE_NAME, self.repo.push_branch()),
+            RunKind::Sync | RunKind::Pull => "HEAD".to_string(),
+        };
+
+        let _tracing = self.logger.enter();
+        let span = tracing::info_span!("run", kind = kind.as_str(), files = tracing::field::Empty);
+        let _span = span.enter();
+
+        let started = clock::now();
+        let timer = Instant::now();
+        let before = self.repo.resolve(&rev);
+        let result = run(self);
+        let after = self.repo.resolve(&rev);
+
+        let files = match &after {
+            Some(after) if before.as_ref() != Some(after) => self
+                .repo
+                .count_changed_files(before.as_deref(), after)
+                .unwrap_or(0),
+            _ => 0,
+        };
+        span.record("files", files);
+
+        let recorded = self.run_log().and_then(|mut log| {
+            log.record(Run {
+                kind,
+                started,
+                duration: timer.elapsed().as_millis() as u64,
+                files,
+                error: result.as_ref().err().map(|err| err.to_string()),
+            });
+            log.save()
+        });
+
+        if let Err(err) = recorded {
+            self.logger
+                .error(format!("Couldn't record the {}: {}", kind.as_str(), err));
+        }
+
+        result
+    }
+
+    /// Get the folder that `dotbak` keeps its state in (see `BaseDirs::state`), creating it if it doesn't exist yet.
+    fn state_dir(&self) -> Result<PathBuf> {
+        let dir = BaseDirs::new(self.dotfiles.home_dir()).state;
+
+        fs::create_dir_all(&dir).map_err(|err| IoError::Create {
+            source: err,
+            path: dir.clone(),
+        })?;
+
+        Ok(dir)
+    }
+
+    /// Spawns a progress bar with `message` for syncing `total` files/folders, which `Files::sync` reports to until
+    /// it's closed with `close_progress`.
+    fn spawn_progress(&mut self, message: &str, total: usize) -> FileProgress {
+        let progress = self.interface.spawn_progress(message, 0, total);
+        self.dotfiles.set_progress(Some(Box::new(progress.clone())));
+
+        progress
+    }
+
+    /// Closes the progress bar `progress` spawned by `spawn_progress`.
+    fn close_progress(&mut self, progress: FileProgress) {
+        self.dotfiles.set_progress(None);
+        progress.close();
+    }
+
+    /// Synchronize a select set of files.
+    fn sync_files<P>(&mut self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        // Move the files/folders to the repository and symlink them to their original location.
+        let hardlinked = self.dotfiles.sync(files)?;
+
+        // Tell the user what happened to the files with other hard links to them.
+        for file in hardlinked {
+            match self.config.files.hardlinks {
+                HardlinkPolicy::Skip => self.interface.warn(format!(
+                    "Skipped '{}', as it has other hard links to it. Set `files.hardlinks` to change this.",
+                    file.display()
+                )),
+                HardlinkPolicy::Copy => self.logger.info(format!(
+                    "Copied '{}' into the repository, as it has other hard links to it",
+                    file.display()
+                )),
+                HardlinkPolicy::BreakLinks => self.interface.warn(format!(
+                    "Broke the hard links to '{}' by moving it into the repository",
+                    file.display()
+                )),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Clones the repository at `url` into `path` (see `Repository::clone`), cloning it again with credentials if it
+/// fails to authenticate with an HTTPS remote (see `next_credentials` for where they come from), and after a while
+/// if it can't reach the remote (see `network::with_retries`).
+fn clone_authenticated(
+    path: &Path,
+    url: &str,
+    low_bandwidth: bool,
+    config: &Config,
+    interface: &Interface,
+) -> Result<Repository> {
+    let (mut credentials, mut prompted) = (None, false);
+    let options = CloneOptions {
+        sparse: config.files.sparse,
+        depth: config.git.clone.depth,
+        filter: config.git.clone.filter.clone(),
+        progress: Some(git_progress(interface)),
+    };
+
+    loop {
+        let result = network::with_retries(
+            config.network.retry_attempts,
+            config.network.retry_backoff,
+            thread::sleep,
+            || {
+                Repository::clone_with_credentials(
+                    path,
+                    url,
+                    low_bandwidth,
+                    &options,
+                    credentials.clone(),
+                    config.git.timeouts.clone(),
+                    config.git.backend,
+                )
+            },
+            |err, delay| warn_retry(interface, err, delay),
+        );
+
+        let err = match result {
+            Err(
+                err @ DotbakError::Git(GitError::AuthFailed {
+                    transport: AuthFailure::Https,
+                    ..
+                }),
+            ) => err,
+            result => return result,
+        };
+
+        credentials =
+            match next_credentials(config, interface, credentials.is_some(), &mut prompted)? {
+                Some(credentials) => Some(credentials),
+                None => return Err(err),
+            };
+    }
+}
+
+/// Warns that the remote couldn't be reached (`err`), and is tried again after `delay`.
+fn warn_retry(interface: &Interface, err: &DotbakError, delay: Duration) {
+    interface.warn(format!(
+        "Couldn't reach the remote, trying again in {}: {}",
+        HumanDuration(delay),
+        err
+    ));
+}
+
+/// Gets the credentials to try after failing to authenticate with an HTTPS remote. These are the configured ones
+/// (see `GitConfig::credentials`) if none were used yet, and otherwise the token the user is asked for, once. Returns
+/// `None` if there are no more credentials to try, e.g. when there is no terminal to ask on.
+fn next_credentials(
+    config: &Config,
+    interface: &Interface,
+    used_credentials: bool,
+    prompted: &mut bool,
+) -> Result<Option<Credentials>> {
+    if !used_credentials {
+        if let Some(credentials) = config.git.credentials()? {
+            return Ok(Some(credentials));
+        }
+    }
+
+    if *prompted || !io::stdin().is_terminal() || !interface.is_interactive() {
+        return Ok(None);
+    }
+
+    *prompted = true;
+    interface.warn(
+        "The remote needs credentials. Enter an access token (or password), or press enter to give up and use an \
+         SSH URL instead (after adding your SSH key to the remote's host, e.g. from `ssh-keygen -t ed25519`).",
+    );
+    let token = interface.ask_secret("Access token:")?;
+
+    if token.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Credentials::new(
+        config.git.username.as_deref(),
+        SecretString::from(token),
+    )))
+}
+
+/// Gets the handler showing the progress git reports while cloning, pushing and pulling next to the spinner of the
+/// step on `interface` (see `Repository::set_progress`).
+fn git_progress(interface: &Interface) -> ProgressHandler {
+    let interface = interface.clone();
+
+    ProgressHandler::new(move |line| interface.show_detail(line))
+}
+
+/// Makes the freshly cloned `repo` work on the branch in `config`, or records the branch the clone checked out (the
+/// remote's default branch) in `config` if none is configured there.
+fn configure_cloned_branch(repo: &mut Repository, config: &mut Config) -> Result<()> {
+    match &config.git.branch {
+        Some(branch) if branch != repo.branch() => {
+            repo.switch_branch(branch)?;
+        }
+        None if repo.branch() != git::MAIN_BRANCH_NAME => {
+            config.git.branch = Some(repo.branch().to_string());
+            config.save_config()?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Applies the settings in `config` that `dotfiles` uses: the hard link policy, and which files are encrypted with
+/// which key. Variants of files are picked for this machine. The key is only loaded if it exists, as it's only needed once files are encrypted.
+pub(crate) fn configure_files(dotfiles: &mut Files, config: &Config) -> Result<()> {
+    let key_path = key_path(config, dotfiles.home_dir());
+    let key = match key_path.exists() {
+        true => Some(encrypt::load_key(&key_path)?),
+        false => None,
+    };
+
+    dotfiles.set_hardlink_policy(config.files.hardlinks);
+    dotfiles.set_excluded(config.files.exclude.clone());
+    dotfiles.set_permanent_delete(config.files.permanent_delete);
+    dotfiles.set_encryption(config.encryption.files.clone(), key, key_path);
+    dotfiles.set_machine(variant::Machine::current());
+    dotfiles.set_roots(config.files.roots.clone());
+    dotfiles.set_ignored(&config.ignore.patterns)?;
+
+    // Without the rights to create symlinks to files (on Windows), managed files are copied instead.
+    let copies = !platform::can_symlink_files(dotfiles.home_dir());
+    dotfiles.set_copies(copies);
+
+    Ok(())
+}
+
+/// Gets the path to the key to encrypt files with: `encryption.key` resolved against the home directory `home`, or
+/// `key.txt` next to the configuration file.
+pub(crate) fn key_path(config: &Config, home: &Path) -> PathBuf {
+    match &config.encryption.key {
+        Some(key) => paths::resolve_home(key, home),
+        None => config
+            .path
+            .parent()
+            .unwrap_or(home)
+            .join(encrypt::KEY_FILE_NAME),
+    }
+}
+
+/// Helper function to move the folder `from` to `to`. Folders can't be renamed onto another filesystem, so they're
+/// copied and deleted instead. If the copy fails, what was copied is deleted again. Once it's copied, the move went
+/// through even if `from` can't be deleted entirely.
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    let fail = |err| IoError::Move {
+        source: err,
+        from: from.to_path_buf(),
+        to: to.to_path_buf(),
+    };
+
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            if let Err(err) = copy_dir(from, to) {
+                let _ = fs::remove_dir_all(to);
+                return Err(fail(err).into());
+            }
+
+            let _ = fs::remove_dir_all(from);
+
+            Ok(())
+        }
+        moved => Ok(moved.map_err(fail)?),
+    }
+}
+
+/// Helper function to copy the folder `from` to `to`, with everything inside of it. Symlinks are copied as symlinks,
+/// and permissions are kept.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir(to)?;
+    fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (from, to) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            platform::symlink(fs::read_link(&from)?, &to)?;
+        } else if file_type.is_dir() {
+            copy_dir(&from, &to)?;
+        } else {
+            fs::copy(&from, &to)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Picks the files of `history` (see `Repository::history_files`) to drop from it: the ones that were ever larger than
+/// `larger_than`, and if `removed` is set, the ones that aren't in `tracked` (the files in the repository) anymore.
+fn droppable_files(
+    history: Vec<(PathBuf, u64)>,
+    tracked: &[PathBuf],
+    larger_than: Option<u64>,
+    removed: bool,
+) -> Vec<(PathBuf, u64)> {
+    history
+        .into_iter()
+        .filter(|(path, size)| {
+            larger_than.is_some_and(|limit| *size > limit) || (removed && !tracked.contains(path))
+        })
+        .collect()
+}
+
+/// Gets how many seconds the commit time `newest` is ahead of `now`, if that is more than `max_skew` seconds.
+fn clock_skew(newest: i64, now: i64, max_skew: u64) -> Option<u64> {
+    let skew = newest.saturating_sub(now);
+
+    (skew > 0 && skew as u64 > max_skew).then_some(skew as u64)
 }
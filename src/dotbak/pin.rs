@@ -0,0 +1,62 @@
+use crate::{
+    errors::{io::IoError, Result},
+    lock,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// The name of the file the pin is stored in, in the state folder.
+pub const PIN_FILE_NAME: &str = "pin.toml";
+
+/// The commit this machine is pinned to: `dotbak sync` and `dotbak pull` don't move the repository past it until
+/// it's unpinned, e.g. while a change that broke something on this machine gets fixed. It's only stored on this
+/// machine, so the others keep syncing as usual.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    /// The hash of the pinned commit.
+    pub commit: String,
+
+    /// When the machine was pinned, in seconds since the UNIX epoch.
+    pub pinned: u64,
+}
+
+impl Pin {
+    /// Loads the pin from the folder `dir` (the state folder), or `None` if the machine isn't pinned.
+    pub fn load<P>(dir: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(PIN_FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(toml::from_str(&contents)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(IoError::Read { source: err, path }.into()),
+        }
+    }
+
+    /// Saves the pin to the folder `dir` (the state folder).
+    pub fn save<P>(&self, dir: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        lock::write_atomic(
+            dir.as_ref().join(PIN_FILE_NAME),
+            toml::to_string_pretty(self)?,
+        )
+    }
+
+    /// Removes the pin from the folder `dir` (the state folder). Returns whether the machine was pinned.
+    pub fn remove<P>(dir: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(PIN_FILE_NAME);
+
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(IoError::Delete { source: err, path }.into()),
+        }
+    }
+}
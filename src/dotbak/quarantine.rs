@@ -0,0 +1,153 @@
+use crate::{
+    errors::{io::IoError, Result},
+    lock,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file the quarantine is stored in, next to the configuration file.
+pub const QUARANTINE_FILE_NAME: &str = "quarantine.toml";
+
+/// The managed paths that failed to sync, and the ones that failed so often in a row that they're skipped until the
+/// user fixes the cause and clears them. This keeps a single broken path (e.g. one with wrong permissions) from
+/// failing every sync.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quarantine {
+    /// The location of the quarantine file. This is set when loading, so it is not serialized.
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// The paths that failed to sync on their last sync.
+    #[serde(default, rename = "entry")]
+    entries: Vec<QuarantineEntry>,
+}
+
+/// A managed path that failed to sync on its last sync.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    /// The path, relative to the home directory (as in `files.include`).
+    pub path: PathBuf,
+
+    /// How many syncs in a row failed for this path.
+    pub failures: u32,
+
+    /// The error from the last failed sync.
+    pub reason: String,
+
+    /// Whether the path is skipped when syncing.
+    pub quarantined: bool,
+}
+
+impl Quarantine {
+    /// Loads the quarantine from the folder `dir` (the folder containing the configuration file). If there is no
+    /// quarantine file yet, the quarantine is empty.
+    pub fn load<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(QUARANTINE_FILE_NAME);
+
+        let mut quarantine: Quarantine = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Quarantine::default(),
+            Err(err) => return Err(IoError::Read { source: err, path }.into()),
+        };
+
+        quarantine.path = path;
+
+        Ok(quarantine)
+    }
+
+    /// Saves the quarantine to the file it was loaded from. The file is removed if nothing failed.
+    pub fn save(&self) -> Result<()> {
+        if self.entries.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(IoError::Delete {
+                    source: err,
+                    path: self.path.clone(),
+                }
+                .into()),
+                _ => Ok(()),
+            };
+        }
+
+        lock::write_atomic(&self.path, toml::to_string_pretty(self)?)
+    }
+
+    /// Checks if `path` is skipped when syncing.
+    pub fn is_quarantined<P>(&self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        self.entry(path).is_some_and(|entry| entry.quarantined)
+    }
+
+    /// Gets the paths that are skipped when syncing.
+    pub fn quarantined(&self) -> impl Iterator<Item = &QuarantineEntry> {
+        self.entries.iter().filter(|entry| entry.quarantined)
+    }
+
+    /// Records that syncing `path` failed because of `reason`. Once it failed `threshold` times in a row, it is
+    /// quarantined; a `threshold` of 0 never quarantines anything. Returns whether `path` was quarantined just now.
+    pub fn record_failure<P, S>(&mut self, path: P, reason: S, threshold: u32) -> bool
+    where
+        P: AsRef<Path>,
+        S: ToString,
+    {
+        let path = path.as_ref();
+        let index = match self.entries.iter().position(|entry| entry.path == path) {
+            Some(index) => index,
+            None => {
+                self.entries.push(QuarantineEntry {
+                    path: path.to_path_buf(),
+                    failures: 0,
+                    reason: String::new(),
+                    quarantined: false,
+                });
+                self.entries.len() - 1
+            }
+        };
+
+        let entry = &mut self.entries[index];
+        let was_quarantined = entry.quarantined;
+
+        entry.failures += 1;
+        entry.reason = reason.to_string();
+        entry.quarantined = threshold > 0 && entry.failures >= threshold;
+
+        entry.quarantined && !was_quarantined
+    }
+
+    /// Records that syncing `path` succeeded, which resets its failures.
+    pub fn record_success<P>(&mut self, path: P)
+    where
+        P: AsRef<Path>,
+    {
+        self.entries.retain(|entry| entry.path != path.as_ref());
+    }
+
+    /// Lets `path` be synced again. Returns whether it was quarantined.
+    pub fn clear<P>(&mut self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let was_quarantined = self.is_quarantined(&path);
+
+        self.record_success(path);
+
+        was_quarantined
+    }
+
+    /// Helper function to get the entry for `path`, if it failed to sync.
+    fn entry<P>(&self, path: P) -> Option<&QuarantineEntry>
+    where
+        P: AsRef<Path>,
+    {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path.as_ref())
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{
+    errors::{io::IoError, Result},
+    lock,
+};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// The name of the file the queued push is stored in, in the state folder.
+pub const QUEUE_FILE_NAME: &str = "push-queue.toml";
+
+/// A push that's queued because the remote couldn't be reached (or `dotbak sync --offline` was run): the commits are
+/// kept locally, and pushed by the next sync (e.g. by the daemon) that reaches the remote.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedPush {
+    /// When the first push that's still queued was queued, in seconds since the UNIX epoch.
+    pub since: u64,
+
+    /// Why the latest push was queued.
+    pub reason: String,
+}
+
+impl QueuedPush {
+    /// Loads the queued push from the folder `dir` (the state folder), or `None` if no push is queued.
+    pub fn load<P>(dir: P) -> Result<Option<Self>>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(QUEUE_FILE_NAME);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Ok(Some(toml::from_str(&contents)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(IoError::Read { source: err, path }.into()),
+        }
+    }
+
+    /// Saves the queued push to the folder `dir` (the state folder).
+    pub fn save<P>(&self, dir: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        lock::write_atomic(
+            dir.as_ref().join(QUEUE_FILE_NAME),
+            toml::to_string_pretty(self)?,
+        )
+    }
+
+    /// Removes the queued push from the folder `dir` (the state folder), once it's pushed. Returns whether a push
+    /// was queued.
+    pub fn remove<P>(dir: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(QUEUE_FILE_NAME);
+
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(IoError::Delete { source: err, path }.into()),
+        }
+    }
+}
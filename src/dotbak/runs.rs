@@ -0,0 +1,112 @@
+use crate::{
+    errors::{io::IoError, Result},
+    lock,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the file the history of runs is stored in, in the state folder.
+pub const RUNS_FILE_NAME: &str = "runs.toml";
+
+/// How many runs are kept. Older ones are dropped, so that the file stays small.
+const MAX_RUNS: usize = 100;
+
+/// The history of the recent syncs, pushes and pulls on this machine, including the daemon's, so that what it has
+/// been doing can be looked back on with `dotbak history`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunLog {
+    /// The location of the history file. This is set when loading, so it is not serialized.
+    #[serde(skip)]
+    path: PathBuf,
+
+    /// The runs, oldest first.
+    #[serde(default, rename = "run")]
+    runs: Vec<Run>,
+}
+
+/// A sync, push or pull, and how it went.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Run {
+    /// What was run.
+    pub kind: RunKind,
+
+    /// When the run started, in seconds since the UNIX epoch.
+    pub started: u64,
+
+    /// How long the run took, in milliseconds.
+    pub duration: u64,
+
+    /// How many files the run changed: committed or pulled for syncs and pulls, and pushed for pushes.
+    pub files: usize,
+
+    /// Why the run failed, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a run did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunKind {
+    /// `dotbak sync`, or a sync by the daemon.
+    Sync,
+
+    /// `dotbak push`.
+    Push,
+
+    /// `dotbak pull`.
+    Pull,
+}
+
+impl RunKind {
+    /// Gets the name of the run, as it's shown by `dotbak history`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RunKind::Sync => "sync",
+            RunKind::Push => "push",
+            RunKind::Pull => "pull",
+        }
+    }
+}
+
+impl RunLog {
+    /// Loads the history from the folder `dir` (the state folder). If there is no history file yet, the history is
+    /// empty.
+    pub fn load<P>(dir: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = dir.as_ref().join(RUNS_FILE_NAME);
+
+        let mut log: RunLog = match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => RunLog::default(),
+            Err(err) => return Err(IoError::Read { source: err, path }.into()),
+        };
+
+        log.path = path;
+
+        Ok(log)
+    }
+
+    /// Saves the history to the file it was loaded from.
+    pub fn save(&self) -> Result<()> {
+        lock::write_atomic(&self.path, toml::to_string_pretty(self)?)
+    }
+
+    /// Adds `run` to the history, dropping the oldest runs past `MAX_RUNS`.
+    pub fn record(&mut self, run: Run) {
+        self.runs.push(run);
+
+        let excess = self.runs.len().saturating_sub(MAX_RUNS);
+        self.runs.drain(..excess);
+    }
+
+    /// Gets the runs, oldest first.
+    pub fn runs(&self) -> &[Run] {
+        &self.runs
+    }
+}
@@ -0,0 +1,120 @@
+use super::{managed::Drift, pin::Pin, quarantine::QuarantineEntry, queue::QueuedPush};
+use crate::files::LinkState;
+use std::path::PathBuf;
+
+/// The state of the managed files and of the repository, as shown by `dotbak status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    /// The state of the symlink in the home directory for each entry of `files.include`.
+    pub files: Vec<(PathBuf, LinkState)>,
+
+    /// How the files/folders this machine manages drifted since they were last synced, for the ones that did. See
+    /// `ManagedFiles`.
+    pub drifted: Vec<(PathBuf, Drift)>,
+
+    /// The number of files with uncommitted changes in the repository.
+    pub uncommitted: usize,
+
+    /// The number of commits that haven't been pushed to the remote yet, as of the last fetch.
+    pub unpushed: usize,
+
+    /// The number of commits on the remote that haven't been pulled yet, as of the last fetch.
+    pub behind: usize,
+
+    /// Whether pushing is deferred until the connection is unmetered. See `network.low_bandwidth`.
+    pub push_deferred: bool,
+
+    /// The changed settings the running daemon has to be restarted for. See `config::RESTART_SETTINGS`.
+    pub daemon_pending_restart: Vec<String>,
+
+    /// The managed paths that are skipped when syncing, because they failed too often in a row.
+    pub quarantined: Vec<QuarantineEntry>,
+
+    /// The commit this machine is pinned to, if it is. See `Dotbak::pin`.
+    pub pinned: Option<Pin>,
+
+    /// The push that's queued until the remote can be reached, if there is one. See `Dotbak::offline`.
+    pub queued_push: Option<QueuedPush>,
+
+    /// Whether another `dotbak` process (e.g. a daemon sync) is changing the state right now, so that this status
+    /// is as of before its changes.
+    pub sync_in_progress: bool,
+}
+
+impl Status {
+    /// Formats the status for scripts and shell prompts, like `git status --porcelain` does. The format is stable:
+    /// each line is a keyword followed by its values, separated by spaces, with the path (which may contain spaces)
+    /// last. Lines are left out when there's nothing to report, except for the counts. The lines are:
+    ///
+    /// - `file <state> <path>` for each entry of `files.include`, where the state is `intact`, `broken`, `missing`
+    ///   or `shadowed` (see `LinkState`).
+    /// - `drift <kind> <path>` for each drifted file/folder, where the kind is `unlisted`, `missing`, `relinked`,
+    ///   `modified` or `permissions` (see `Drift`).
+    /// - `quarantined <failures> <path>` for each quarantined path.
+    /// - `uncommitted <count>`, `unpushed <count>` and `behind <count>`.
+    /// - `push-deferred`, if pushing is deferred.
+    /// - `queued-push <since>`, if a push is queued, with the time it was queued in seconds since the UNIX epoch.
+    /// - `pinned <commit> <since>`, if the machine is pinned.
+    /// - `sync-in-progress`, if another `dotbak` process is changing the state.
+    /// - `restart <setting>` for each setting the daemon has to be restarted for.
+    pub fn porcelain(&self) -> String {
+        let mut lines = vec![];
+
+        for (file, state) in &self.files {
+            let state = match state {
+                LinkState::Intact => "intact",
+                LinkState::Broken => "broken",
+                LinkState::Missing => "missing",
+                LinkState::Shadowed => "shadowed",
+            };
+
+            lines.push(format!("file {} {}", state, file.display()));
+        }
+
+        for (file, drift) in &self.drifted {
+            let drift = match drift {
+                Drift::Unlisted => "unlisted",
+                Drift::Missing => "missing",
+                Drift::Relinked => "relinked",
+                Drift::Modified => "modified",
+                Drift::Permissions => "permissions",
+            };
+
+            lines.push(format!("drift {} {}", drift, file.display()));
+        }
+
+        for entry in &self.quarantined {
+            lines.push(format!(
+                "quarantined {} {}",
+                entry.failures,
+                entry.path.display()
+            ));
+        }
+
+        lines.push(format!("uncommitted {}", self.uncommitted));
+        lines.push(format!("unpushed {}", self.unpushed));
+        lines.push(format!("behind {}", self.behind));
+
+        if self.push_deferred {
+            lines.push("push-deferred".to_string());
+        }
+
+        if let Some(queued) = &self.queued_push {
+            lines.push(format!("queued-push {}", queued.since));
+        }
+
+        if let Some(pin) = &self.pinned {
+            lines.push(format!("pinned {} {}", pin.commit, pin.pinned));
+        }
+
+        if self.sync_in_progress {
+            lines.push("sync-in-progress".to_string());
+        }
+
+        for setting in &self.daemon_pending_restart {
+            lines.push(format!("restart {}", setting));
+        }
+
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+}
@@ -0,0 +1,67 @@
+mod tests;
+
+use crate::{
+    files::metadata::MANIFEST_FILE,
+    shell::{self, Shell},
+};
+use itertools::Itertools;
+use std::path::PathBuf;
+
+/// The name of the install script written into the repository when ejecting.
+pub const INSTALL_SCRIPT: &str = "install.sh";
+
+/// The files at the top of the repository that aren't dotfiles, which the stow install script leaves out. Stow's
+/// own defaults already leave out `.git`, `.gitignore`, `README.*` and the like.
+const STOW_IGNORED: [&str; 3] = [INSTALL_SCRIPT, ".gitattributes", MANIFEST_FILE];
+
+/// How the install script links the files into the home directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstallStyle {
+    /// Plain `ln -s` commands, one per file/folder, reproducing the current layout exactly.
+    Ln,
+
+    /// A single GNU stow invocation, treating the repository as a stow package. Stow decides on its own which
+    /// folders it links as a whole, so the layout may differ slightly.
+    Stow,
+}
+
+/// Generates a POSIX shell script that links `files` (relative to both the repository and the home directory) from
+/// the repository the script is in into the home directory, so that the repository can be installed without
+/// `dotbak`.
+pub fn install_script(files: &[PathBuf], style: InstallStyle) -> String {
+    let mut script = String::from(
+        r#"#!/bin/sh
+# Links these dotfiles into your home directory. Generated by `dotbak eject`.
+set -e
+
+DOTFILES=$(cd "$(dirname "$0")" && pwd)
+"#,
+    );
+
+    match style {
+        InstallStyle::Ln => {
+            for file in files {
+                let file = shell::quote(Shell::Bash, &file.to_string_lossy());
+
+                script += &format!(
+                    "\nmkdir -p \"$(dirname \"$HOME\"/{file})\"\nln -sfn \"$DOTFILES\"/{file} \"$HOME\"/{file}\n"
+                );
+            }
+        }
+
+        InstallStyle::Stow => {
+            // Stow matches `--ignore` against the paths relative to the package, which have no leading `/`.
+            let ignored = STOW_IGNORED
+                .iter()
+                .map(|file| format!("--ignore='^{}$'", file.replace('.', "\\.")))
+                .join(" ");
+
+            script += &format!(
+                "\nstow --dir=\"$(dirname \"$DOTFILES\")\" --target=\"$HOME\" {} \"$(basename \"$DOTFILES\")\"\n",
+                ignored
+            );
+        }
+    }
+
+    script
+}
@@ -0,0 +1,46 @@
+#![cfg(test)]
+
+use super::*;
+use std::{fs, os::unix::fs::PermissionsExt, process::Command};
+
+/// Test that the `ln` install script reproduces the layout, even for paths with spaces and quotes.
+#[test]
+fn test_install_script_ln() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let repo = dir.path().join("dotfiles");
+    let home = dir.path().join("home");
+    let files = [".zshrc", ".config/nvim", ".config/it's a dir"].map(PathBuf::from);
+
+    for file in &files {
+        fs::create_dir_all(repo.join(file).parent().unwrap()).unwrap();
+        fs::write(repo.join(file), "contents").unwrap();
+    }
+    fs::create_dir_all(&home).unwrap();
+
+    let script = repo.join(INSTALL_SCRIPT);
+    fs::write(&script, install_script(&files, InstallStyle::Ln)).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    // Running it twice shouldn't fail or nest links.
+    for _ in 0..2 {
+        let status = Command::new(&script).env("HOME", &home).status().unwrap();
+        assert!(status.success());
+    }
+
+    for file in &files {
+        assert_eq!(fs::read_link(home.join(file)).unwrap(), repo.join(file));
+    }
+}
+
+/// Test that the stow install script treats the repository as a package, leaving out the files that aren't
+/// dotfiles. Running it is tested by `dotbak::tests::test_eject_stow`.
+#[test]
+fn test_install_script_stow() {
+    let script = install_script(&[PathBuf::from(".zshrc")], InstallStyle::Stow);
+
+    assert!(script.contains("stow --dir="));
+    assert!(script.contains(r"--ignore='^install\.sh$'"));
+    assert!(script.contains(r"--ignore='^\.gitattributes$'"));
+    assert!(script.contains(r"--ignore='^\.dotbak-metadata\.toml$'"));
+    assert!(!script.contains("ln -s"));
+}
@@ -0,0 +1,36 @@
+use miette::Diagnostic;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum BundleError {
+    /// The bundle could not be decrypted.
+    #[error("Could not decrypt the bundle '{path}': {source}")]
+    #[diagnostic(
+        code(dotbak::error::bundle::decrypt),
+        help("Make sure the passphrase is correct, and that the file is a bundle created by `dotbak bundle create`.")
+    )]
+    Decrypt {
+        /// The path to the bundle.
+        path: PathBuf,
+
+        /// The source decryption error.
+        source: age::DecryptError,
+    },
+
+    /// The bundle is missing one of its parts.
+    #[error("The bundle '{path}' does not contain '{name}'")]
+    #[diagnostic(code(dotbak::error::bundle::missing))]
+    Missing {
+        /// The path to the bundle.
+        path: PathBuf,
+
+        /// The name of the missing part.
+        name: String,
+    },
+
+    /// The passphrase and its confirmation did not match.
+    #[error("The passphrases do not match")]
+    #[diagnostic(code(dotbak::error::bundle::passphrase_mismatch))]
+    PassphraseMismatch,
+}
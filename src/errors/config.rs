@@ -8,14 +8,33 @@ use super::DotbakError;
 pub enum ConfigError {
     /// A configuration parsing/deserialization error occured.
     #[error(transparent)]
-    #[diagnostic(code(dotbak::error::config::deserialize))]
+    #[diagnostic(
+        code(dotbak::error::config::deserialize),
+        help("Check the spelling and type of the setting, or run `dotbak config check` to list every problem with the configuration file.")
+    )]
     Deserialize { source: toml::de::Error },
 
+    /// The configuration file isn't valid TOML, so it can't be changed.
+    #[error(transparent)]
+    #[diagnostic(
+        code(dotbak::error::config::syntax),
+        help("Fix the configuration file with `dotbak config edit`.")
+    )]
+    Syntax { source: toml_edit::TomlError },
+
     /// A configuration serialization error occured.
     #[error(transparent)]
     #[diagnostic(code(dotbak::error::config::serialize))]
     Serialize { source: toml::ser::Error },
 
+    /// The configuration file is from a newer version of `dotbak`, whose format this one can't read.
+    #[error("The configuration file is in version {version} of the format, but this version of dotbak only supports up to version {supported}!")]
+    #[diagnostic(
+        code(dotbak::error::config::unsupported_version),
+        help("Update dotbak to the version the configuration file was written with (or a newer one).")
+    )]
+    UnsupportedVersion { version: u32, supported: u32 },
+
     /// Configuration file not found.
     #[error("The configuration file '{path}' does not exist!")]
     #[diagnostic(code(dotbak::error::config::not_found))]
@@ -25,6 +44,114 @@ pub enum ConfigError {
     #[error("The configuration file '{path}' already exists!")]
     #[diagnostic(code(dotbak::error::config::already_exists))]
     AlreadyExists { path: PathBuf },
+
+    /// The configuration file has problems that deserializing it didn't find. See `Config::validate`.
+    #[error("The configuration file '{path}' has {count} problem(s):\n{problems}")]
+    #[diagnostic(
+        code(dotbak::error::config::invalid),
+        help("Fix the settings, and run `dotbak config check` to see where in the file they are.")
+    )]
+    Invalid {
+        path: PathBuf,
+        count: usize,
+        problems: String,
+    },
+
+    /// A setting that is changed would make the configuration invalid.
+    #[error(
+        "Setting '{key}' would make the configuration invalid, so it wasn't changed:\n{problems}"
+    )]
+    #[diagnostic(
+        code(dotbak::error::config::invalid_change),
+        help("Check the value. It's read as TOML (e.g. `600`, `true` or `[\".zshrc\"]`), or as a string if it isn't TOML.")
+    )]
+    InvalidChange { key: String, problems: String },
+
+    /// A setting doesn't exist, or isn't set.
+    #[error("The setting '{key}' doesn't exist, or isn't set!")]
+    #[diagnostic(
+        code(dotbak::error::config::unknown_setting),
+        help("Settings are written with dots, like `files.sparse`. See the configuration section of the README for all of them.")
+    )]
+    UnknownSetting { key: String },
+
+    /// The repository path overlaps with a path managed by `dotbak`.
+    #[error("The repository path '{repo_path}' overlaps with the managed path '{managed}'!")]
+    #[diagnostic(
+        code(dotbak::error::config::repo_path_managed),
+        help("Choose a `repo_path` that is neither inside of nor contains a path in `files.include`.")
+    )]
+    RepoPathManaged {
+        repo_path: PathBuf,
+        managed: PathBuf,
+    },
+
+    /// An alias expands to itself, directly or through other aliases.
+    #[error("The alias '{chain}' is recursive!")]
+    #[diagnostic(
+        code(dotbak::error::config::recursive_alias),
+        help("Change the `[alias]` table in the configuration file so that no alias expands to itself.")
+    )]
+    RecursiveAlias { chain: String },
+
+    /// A path to manage is not inside of the home directory.
+    #[error("'{path}' is not inside of the home directory '{home}'!")]
+    #[diagnostic(
+        code(dotbak::error::config::outside_home),
+        help("dotbak can only manage files and folders inside of your home directory, or inside of the folders in `files.roots`.")
+    )]
+    OutsideHome { path: PathBuf, home: PathBuf },
+
+    /// A path to manage is (inside of) the repository.
+    #[error("'{path}' is (inside of) the repository '{repo_path}', and can't be managed!")]
+    #[diagnostic(
+        code(dotbak::error::config::inside_repo),
+        help("Everything in the repository is already backed up by dotbak.")
+    )]
+    InsideRepo { path: PathBuf, repo_path: PathBuf },
+
+    /// A path to manage contains the repository or configuration file.
+    #[error("'{path}' contains '{dotbak_path}', which dotbak uses itself, and can't be managed!")]
+    #[diagnostic(
+        code(dotbak::error::config::contains_dotbak_path),
+        help("Managing it would move dotbak's own files into the repository. Add the files inside of it one by one instead.")
+    )]
+    ContainsDotbakPath { path: PathBuf, dotbak_path: PathBuf },
+
+    /// A path to manage is (or contains) the key that encrypted files are encrypted with.
+    #[error("'{path}' is (or contains) the encryption key '{key_path}', and can't be managed!")]
+    #[diagnostic(
+        code(dotbak::error::config::contains_key),
+        help("The key must never be committed, as it decrypts the encrypted files. Copy it to your other machines by hand, or add the files next to it one by one.")
+    )]
+    ContainsKey { path: PathBuf, key_path: PathBuf },
+
+    /// A pattern to match managed paths against is not a valid glob pattern.
+    #[error("'{pattern}' is not a valid pattern!")]
+    #[diagnostic(
+        code(dotbak::error::config::invalid_pattern),
+        help("Patterns are glob patterns, like `.config/app*` or `**/*.conf`.")
+    )]
+    InvalidPattern {
+        pattern: String,
+        source: globset::Error,
+    },
+
+    /// A pattern of files to skip when walking a folder is not a valid `.gitignore` pattern.
+    #[error("'{pattern}' is not a valid .gitignore pattern: {reason}")]
+    #[diagnostic(
+        code(dotbak::error::config::invalid_ignore_pattern),
+        help("Patterns use `.gitignore` syntax, like `*.swp` or `cache/`.")
+    )]
+    InvalidIgnorePattern { pattern: String, reason: String },
+
+    /// A pattern to find secrets with is not a valid regular expression.
+    #[error("The secret pattern '{name}' is not a valid regular expression!")]
+    #[diagnostic(
+        code(dotbak::error::config::invalid_secret_pattern),
+        help("Fix `secrets.patterns.{name}`. See https://docs.rs/regex/latest/regex/#syntax for the syntax.")
+    )]
+    InvalidSecretPattern { name: String, source: regex::Error },
 }
 
 /* Convenience implementations for converting toml ser/de errors into dotbak errors. */
@@ -0,0 +1,75 @@
+use miette::Diagnostic;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum EncryptionError {
+    /// There is no key to encrypt or decrypt files with.
+    #[error("There is no encryption key at '{path}'!")]
+    #[diagnostic(
+        code(dotbak::error::encryption::no_key),
+        help("Run `dotbak key generate` to create a key, or `dotbak key import <file>` to use the key of your other machines.")
+    )]
+    NoKey {
+        /// The path the key should be at.
+        path: PathBuf,
+    },
+
+    /// The key file doesn't contain a valid age identity.
+    #[error("'{path}' is not a valid age key: {reason}")]
+    #[diagnostic(code(dotbak::error::encryption::invalid_key))]
+    InvalidKey {
+        /// The path to the key file.
+        path: PathBuf,
+
+        /// Why the key is invalid.
+        reason: String,
+    },
+
+    /// A key already exists, and would be overwritten.
+    #[error("There already is an encryption key at '{path}'!")]
+    #[diagnostic(
+        code(dotbak::error::encryption::key_exists),
+        help("Files encrypted with it can't be decrypted without it. Move it somewhere else first if you really want to replace it.")
+    )]
+    KeyExists {
+        /// The path to the key.
+        path: PathBuf,
+    },
+
+    /// Only files can be encrypted, not folders.
+    #[error("'{path}' is a folder, but only files can be encrypted!")]
+    #[diagnostic(
+        code(dotbak::error::encryption::not_a_file),
+        help("List the files inside of it in `encryption.files` instead.")
+    )]
+    NotAFile {
+        /// The path to the folder.
+        path: PathBuf,
+    },
+
+    /// A file could not be encrypted.
+    #[error("Could not encrypt '{path}': {source}")]
+    #[diagnostic(code(dotbak::error::encryption::encrypt))]
+    Encrypt {
+        /// The path to the file.
+        path: PathBuf,
+
+        /// The source encryption error.
+        source: Box<age::EncryptError>,
+    },
+
+    /// A file could not be decrypted.
+    #[error("Could not decrypt '{path}': {source}")]
+    #[diagnostic(
+        code(dotbak::error::encryption::decrypt),
+        help("Make sure your key is the same as on the machine that encrypted the file.")
+    )]
+    Decrypt {
+        /// The path to the encrypted file.
+        path: PathBuf,
+
+        /// The source decryption error.
+        source: Box<age::DecryptError>,
+    },
+}
@@ -0,0 +1,53 @@
+use miette::Diagnostic;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ExportError {
+    /// A template could not be rendered.
+    #[error("Could not render the template '{template}': {source}")]
+    #[diagnostic(code(dotbak::error::export::render))]
+    Render {
+        /// The name of the template.
+        template: String,
+
+        /// The source template error.
+        source: minijinja::Error,
+    },
+
+    /// A file could not be syntax highlighted.
+    #[error("Could not highlight '{file}': {source}")]
+    #[diagnostic(code(dotbak::error::export::highlight))]
+    Highlight {
+        /// The name of the file.
+        file: String,
+
+        /// The source highlighting error.
+        source: syntect::Error,
+    },
+
+    /// The archive to export to or import from has an unknown extension.
+    #[error("Don't know what kind of archive '{path}' is")]
+    #[diagnostic(
+        code(dotbak::error::export::unknown_archive_format),
+        help("Archives have to end in `.tar`, `.tar.gz`, `.tgz` or `.zip`.")
+    )]
+    UnknownArchiveFormat {
+        /// The path to the archive.
+        path: PathBuf,
+    },
+
+    /// An entry of an archive would be unpacked outside of the home directory.
+    #[error("The archive '{path}' contains '{entry}', which leads outside of the home directory")]
+    #[diagnostic(
+        code(dotbak::error::export::unsafe_archive_path),
+        help("Only import archives created by `dotbak export archive`, or others you trust.")
+    )]
+    UnsafeArchivePath {
+        /// The path to the archive.
+        path: PathBuf,
+
+        /// The path of the entry in the archive.
+        entry: PathBuf,
+    },
+}
@@ -0,0 +1,196 @@
+use crate::git::auth::AuthFailure;
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// The ways a git command can fail, told apart by its output (see `git::run_arbitrary_git_command`), so they can
+/// be handled or come with help on fixing them. Failures that aren't any of the known ones are `Command` errors.
+#[derive(Debug, Error, Diagnostic)]
+pub enum GitError {
+    /// Git could not authenticate with the remote.
+    #[error("Authentication with the remote failed running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(code(dotbak::error::git::auth_failed))]
+    AuthFailed {
+        /// Whether the remote is accessed over HTTPS or SSH.
+        transport: AuthFailure,
+
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+
+        /// How to set up authentication for `transport`.
+        #[help]
+        help: &'static str,
+    },
+
+    /// A merge, rebase or stash couldn't be applied because of conflicting changes.
+    #[error("Conflicting changes running 'git {}':\n{stdout}{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::merge_conflict),
+        help("Resolve the conflicts in the repository (`dotbak git status` lists them), then commit the result with `dotbak git commit`, or give up with `dotbak git merge --abort`.")
+    )]
+    MergeConflict {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stdout from git.
+        stdout: String,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// The branch that is checked out has no branch on the remote to pull from or push to.
+    #[error("The branch has no upstream branch running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::no_upstream),
+        help("Check that the remote is set up with `dotbak remote list`, and push the branch once with `dotbak git push -u origin HEAD`.")
+    )]
+    NoUpstream {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// No branch is checked out, e.g. after checking out a single commit.
+    #[error("No branch is checked out running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::detached_head),
+        help("Check out a branch again with `dotbak git switch <branch>` (`dotbak git branch` lists them).")
+    )]
+    DetachedHead {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// Git doesn't know the name and email to make commits with.
+    #[error("Git doesn't know who to make commits as running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::missing_identity),
+        help("Set the name and email for your commits with `dotbak config set git.identity.name <name>` and `dotbak config set git.identity.email <email>`.")
+    )]
+    MissingIdentity {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// There were no changes to commit.
+    #[error("Nothing to commit running 'git {}':\n{stdout}", args.join(" "))]
+    #[diagnostic(code(dotbak::error::git::nothing_to_commit))]
+    NothingToCommit {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stdout from git.
+        stdout: String,
+    },
+
+    /// The remote couldn't be reached, e.g. because the machine is offline or the URL is wrong.
+    #[error("Could not reach the remote running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::remote_unreachable),
+        help("Check your network connection, and the URL of the remote with `dotbak remote list`. Your changes are kept locally until the remote can be reached.")
+    )]
+    RemoteUnreachable {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// Uncommitted changes in the repository would be overwritten.
+    #[error("Uncommitted changes in the repository would be overwritten running 'git {}':\n{stderr}", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::dirty_worktree),
+        help("Commit the changes with `dotbak sync`, or stash them with `dotbak git stash` and try again.")
+    )]
+    DirtyWorktree {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+
+    /// A git command ran for longer than it may, and was killed.
+    #[error("'git {}' didn't finish within {timeout} seconds, so it was stopped", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::command_timeout),
+        help("Check your network connection, and that git doesn't wait for input (e.g. an SSH passphrase). The timeouts can be raised with `git.timeouts` in the configuration.")
+    )]
+    CommandTimeout {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// How long the command may run, in seconds.
+        timeout: u64,
+    },
+
+    /// The `git` binary isn't installed, or isn't on the `PATH`.
+    #[error("Running 'git {}' needs git, which isn't installed", args.join(" "))]
+    #[diagnostic(
+        code(dotbak::error::git::not_installed),
+        help("Install git. With `git.backend = \"libgit2\"`, initializing, adding files and syncing work without it, but the other commands (e.g. `dotbak undo`) still need it.")
+    )]
+    NotInstalled {
+        /// The arguments to git.
+        args: Vec<String>,
+    },
+
+    /// The git backend set with `git.backend` isn't built into this `dotbak`.
+    #[error("The '{backend}' git backend isn't available in this build of dotbak")]
+    #[diagnostic(
+        code(dotbak::error::git::backend_unavailable),
+        help("Install dotbak with the backend's feature (e.g. `cargo install dotbak --features libgit2`), or set `git.backend` to `git` in the configuration.")
+    )]
+    BackendUnavailable {
+        /// The name of the backend.
+        backend: &'static str,
+    },
+
+    /// The git backend can't do what was asked, e.g. a sparse clone with libgit2.
+    #[error("The '{backend}' git backend doesn't support {operation}")]
+    #[diagnostic(
+        code(dotbak::error::git::unsupported),
+        help("Set `git.backend` to `git` in the configuration to use the git binary instead.")
+    )]
+    Unsupported {
+        /// The name of the backend.
+        backend: &'static str,
+
+        /// What isn't supported.
+        operation: &'static str,
+    },
+
+    /// Git failed in some other way.
+    #[error("Error running command 'git {}':\n{stdout}{stderr}", args.join(" "))]
+    #[diagnostic(code(dotbak::error::git::command))]
+    Command {
+        /// The arguments to git.
+        args: Vec<String>,
+
+        /// The stdout from git.
+        stdout: String,
+
+        /// The stderr from git.
+        stderr: String,
+    },
+}
+
+impl GitError {
+    /// Whether trying again later may succeed, i.e. the remote couldn't be reached. Other failures (e.g. failing to
+    /// authenticate or conflicts) need something to be fixed first.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, GitError::RemoteUnreachable { .. })
+    }
+}
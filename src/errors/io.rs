@@ -12,6 +12,14 @@ pub enum IoError {
         path: PathBuf,
     },
 
+    /// A file or folder already exists where it shouldn't.
+    #[error("File or folder '{path}' already exists")]
+    #[diagnostic(code(dotbak::error::io::already_exists))]
+    AlreadyExists {
+        /// The path to the file/folder that already exists.
+        path: PathBuf,
+    },
+
     /// A reading error: `std::io::Error`.
     #[error("Error reading from file or folder '{path}': {source}")]
     #[diagnostic(code(dotbak::error::io::read))]
@@ -33,6 +41,17 @@ pub enum IoError {
         source: io::Error,
     },
 
+    /// An error locking a file occured.
+    #[error("Error locking file '{path}': {source}")]
+    #[diagnostic(code(dotbak::error::io::lock))]
+    Lock {
+        /// The path to the file being locked.
+        path: PathBuf,
+
+        /// The source io error.
+        source: io::Error,
+    },
+
     /// A file/folder creation error occured.
     #[error("Error creating file or folder '{path}': {source}")]
     #[diagnostic(code(dotbak::error::io::create))]
@@ -58,6 +77,20 @@ pub enum IoError {
         source: io::Error,
     },
 
+    /// An error copying a file occured.
+    #[error("Error copying file '{from}' to '{to}': {source}")]
+    #[diagnostic(code(dotbak::error::io::copy))]
+    Copy {
+        /// The path to the file being copied.
+        from: PathBuf,
+
+        /// The path to the copy.
+        to: PathBuf,
+
+        /// The source io error.
+        source: io::Error,
+    },
+
     /// A symlink creation error occured.
     #[error("Error creating symlink from '{from}' to '{to}': {source}")]
     #[diagnostic(code(dotbak::error::io::symlink))]
@@ -83,6 +116,76 @@ pub enum IoError {
         source: io::Error,
     },
 
+    /// An operation on a file/folder in a root (see `config::files::Root`) wasn't allowed, and the root doesn't
+    /// escalate (see `config::files::Escalation`).
+    #[error("Not allowed to change '{path}': {source}")]
+    #[diagnostic(
+        code(dotbak::error::io::needs_permissions),
+        help("The file or folder needs more permissions than dotbak has. Set `escalate` of its root in `files.roots` to `sudo` or `pkexec` to have dotbak ask for them, or run dotbak itself with `sudo`.")
+    )]
+    NeedsPermissions {
+        /// The path to the file/folder.
+        path: PathBuf,
+
+        /// The path to the root it's in.
+        root: PathBuf,
+
+        /// The source io error.
+        source: io::Error,
+    },
+
+    /// An operation on a file/folder in a root was run again with more permissions (see
+    /// `config::files::Escalation`), but that was declined or failed.
+    #[error("Running `{command}` to change '{path}' was declined or failed: {reason}")]
+    #[diagnostic(
+        code(dotbak::error::io::escalation),
+        help("The file or folder needs more permissions than dotbak has. Allow `{command}` when it asks for them and run the command again, or run dotbak itself with `sudo`.")
+    )]
+    Escalation {
+        /// The path to the file/folder.
+        path: PathBuf,
+
+        /// The command that was run to escalate, e.g. `sudo`.
+        command: String,
+
+        /// Why it failed, e.g. what the command printed.
+        reason: String,
+    },
+
+    /// The user couldn't be notified of a failure (see `notifications::send`).
+    #[error("Couldn't send the notification {channel}: {reason}")]
+    #[diagnostic(
+        code(dotbak::error::io::notification),
+        help("Check the `notifications` section of the configuration, and that the command it needs is installed.")
+    )]
+    Notification {
+        /// How it was sent, e.g. `to the desktop`.
+        channel: String,
+
+        /// Why it failed, e.g. what the command printed.
+        reason: String,
+    },
+
+    /// The user could not be prompted for input.
+    #[error("Error reading your answer from the terminal: {source}")]
+    #[diagnostic(code(dotbak::error::io::prompt))]
+    Prompt {
+        /// The source io error.
+        source: io::Error,
+    },
+
+    /// The user would have to be asked for something, but `dotbak` runs non-interactively (see
+    /// `ui::Interface::is_interactive`).
+    #[error("Can't ask '{prompt}', as dotbak isn't running interactively")]
+    #[diagnostic(
+        code(dotbak::error::io::non_interactive),
+        help("Run dotbak in a terminal, without `--non-interactive` and without `CI=true` set, or pass what would be asked with a flag or environment variable.")
+    )]
+    NonInteractive {
+        /// What would have been asked.
+        prompt: String,
+    },
+
     /// An arbitrary command could not be run.
     #[error("Error running command '{command} {}': {source}", args.join(" "))]
     #[diagnostic(code(dotbak::error::git::arbitrary_command))]
@@ -113,4 +216,14 @@ pub enum IoError {
         /// The stderr from the command.
         stderr: String,
     },
+
+    /// Several files/folders failed at once, e.g. when moving or symlinking many files in parallel. Every file is
+    /// still tried, so these are all the files that failed.
+    #[error("Error with {} files or folders", errors.len())]
+    #[diagnostic(code(dotbak::error::io::many))]
+    Many {
+        /// The errors of the files/folders that failed.
+        #[related]
+        errors: Vec<IoError>,
+    },
 }
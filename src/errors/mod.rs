@@ -1,8 +1,16 @@
+pub mod bundle;
 pub mod config;
+pub mod encryption;
+pub mod export;
+pub mod git;
 pub mod io;
 
-use self::{config::ConfigError, io::IoError};
+use self::{
+    bundle::BundleError, config::ConfigError, encryption::EncryptionError, export::ExportError,
+    git::GitError, io::IoError,
+};
 use miette::Diagnostic;
+use std::path::PathBuf;
 use thiserror::Error;
 
 /// A helper return type for functions that return `Result<T, DotbakError>`.
@@ -14,11 +22,132 @@ pub type Result<T> = std::result::Result<T, DotbakError>;
 pub enum DotbakError {
     /// An IO operations error occured.
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Io(#[from] IoError),
 
+    /// A git command failed.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Git(#[from] GitError),
+
     /// A configuration error occured.
     #[error(transparent)]
+    #[diagnostic(transparent)]
     Config(#[from] ConfigError),
+
+    /// An offline bundle error occured.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Bundle(#[from] BundleError),
+
+    /// An export error occured.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Export(#[from] ExportError),
+
+    /// An encryption error occured.
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Encryption(#[from] EncryptionError),
+
+    /// Files about to be committed contain what looks like secrets.
+    #[error("Found {count} possible secret(s) in the files to commit, so nothing was committed:\n{found}")]
+    #[diagnostic(
+        code(dotbak::error::secrets_found),
+        help("Encrypt the files with `encryption.files`, allow them with `secrets.allow` if these aren't secrets, or pass `--allow-secrets` to commit them anyways.")
+    )]
+    SecretsFound { count: usize, found: String },
+
+    /// The uncommitted changes in the repository that were stashed while pulling conflict with what was pulled.
+    #[error(
+        "The uncommitted changes in the repository conflict with the pulled ones, in:\n{paths}"
+    )]
+    #[diagnostic(
+        code(dotbak::error::stash_conflict),
+        help("Resolve the conflicts in these files in the repository, then run `dotbak git stash drop`. Your changes are kept in the stash until then.")
+    )]
+    StashConflict { paths: String },
+
+    /// A path to remove isn't managed, neither in the repository nor as far as this machine knows.
+    #[error("'{path}' isn't managed!")]
+    #[diagnostic(
+        code(dotbak::error::not_managed),
+        help("Run `dotbak list` to list the managed files.")
+    )]
+    NotManaged { path: PathBuf },
+
+    /// A module would be added at a path that already exists in the home directory.
+    #[error("'{path}' already exists in the home directory!")]
+    #[diagnostic(
+        code(dotbak::error::module_exists),
+        help("Move it out of the way first, or add the module at another path. The module is symlinked there.")
+    )]
+    ModuleExists { path: PathBuf },
+
+    /// A commit to undo doesn't exist in the repository.
+    #[error("The commit '{commit}' doesn't exist!")]
+    #[diagnostic(
+        code(dotbak::error::unknown_commit),
+        help("Run `dotbak log` to list the commits, with their hashes.")
+    )]
+    UnknownCommit { commit: String },
+
+    /// More commits are to be undone than the repository has.
+    #[error("Can't undo {count} commit(s), as there are only {available}!")]
+    #[diagnostic(
+        code(dotbak::error::too_few_commits),
+        help("Run `dotbak log` to list the commits.")
+    )]
+    TooFewCommits { count: usize, available: usize },
+
+    /// There is no service to run `dotbak sync` on a schedule on this platform.
+    #[error("Scheduling syncs isn't supported on {os}!")]
+    #[diagnostic(
+        code(dotbak::error::scheduler_unsupported),
+        help("Run `dotbak start-daemon` instead, e.g. when you log in.")
+    )]
+    SchedulerUnsupported { os: String },
+
+    /// The home directory couldn't be found.
+    #[error("Could not find your home directory!")]
+    #[diagnostic(
+        code(dotbak::error::no_home_dir),
+        help("Pass the home directory to manage with `--home`, or set `DOTBAK_HOME` to it.")
+    )]
+    NoHomeDir,
+
+    /// The configuration file isn't inside of a folder, which holds the lock and the other state files.
+    #[error("The configuration file '{path}' is not inside of a folder!")]
+    #[diagnostic(
+        code(dotbak::error::no_config_dir),
+        help("Put the configuration file inside of a folder, e.g. `~/.config/dotbak`.")
+    )]
+    NoConfigDir { path: PathBuf },
+
+    /// The commit to pin to isn't in the history of the current branch, so the files would have to move to another
+    /// line of history.
+    #[error("The commit '{commit}' isn't in the history of the current branch!")]
+    #[diagnostic(
+        code(dotbak::error::pin_not_in_history),
+        help("Run `dotbak log` to list the commits of the current branch, with their hashes.")
+    )]
+    PinNotInHistory { commit: String },
+
+    /// Pinning to an older commit would drop commits that were never pushed.
+    #[error("Pinning to '{commit}' would drop commits that weren't pushed yet!")]
+    #[diagnostic(
+        code(dotbak::error::pin_unpushed),
+        help("Run `dotbak push` first, so that they're kept on the remote until you unpin.")
+    )]
+    PinUnpushed { commit: String },
+
+    /// The history can't be rewritten while there are uncommitted changes, as they would be lost.
+    #[error("Can't rewrite the history, as there are {count} uncommitted change(s)!")]
+    #[diagnostic(
+        code(dotbak::error::uncommitted_changes),
+        help("Run `dotbak sync` first, so that they're committed.")
+    )]
+    UncommittedChanges { count: usize },
 }
 
 // /* Convenience implementations for converting boxed errors into dotbak errors. */
@@ -0,0 +1,330 @@
+use crate::{
+    errors::{export::ExportError, io::IoError, Result},
+    platform,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+use zip::{write::SimpleFileOptions, ZipArchive, ZipWriter};
+
+/// The name of the entry in an archive listing the managed paths the files in it belong to, one per line. These are
+/// what `dotbak import archive` manages again.
+pub const PATHS_ENTRY: &str = ".dotbak-paths";
+
+/// The kind of archive dotfiles are exported to, see `export`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// A plain tarball, `.tar`.
+    Tar,
+
+    /// A gzipped tarball, `.tar.gz` or `.tgz`.
+    TarGz,
+
+    /// A zip archive, `.zip`.
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Gets the format of the archive at `path` from its extension, or `None` if it isn't a known one.
+    pub fn from_path<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let name = path.as_ref().file_name()?.to_string_lossy().to_lowercase();
+
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// The contents of an archive, see `read`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Archive {
+    /// The managed paths the files belong to, from `PATHS_ENTRY`. Archives without it (i.e. ones not created by
+    /// `dotbak`) have the paths of their files here instead.
+    pub paths: Vec<PathBuf>,
+
+    /// The files in the archive.
+    pub files: Vec<ArchivedFile>,
+}
+
+/// A file read from an archive, see `read`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchivedFile {
+    /// The path to the file, relative to the folder it was exported from.
+    pub path: PathBuf,
+
+    /// The contents of the file.
+    pub contents: Vec<u8>,
+
+    /// The Unix permission bits of the file (e.g. `0o644`), if the archive recorded them.
+    pub mode: Option<u32>,
+}
+
+/// Packs the `files` in the folder `dir` into an archive at `path`, in the format its extension stands for (see
+/// `ArchiveFormat::from_path`), along with the list of managed `paths` they belong to (see `PATHS_ENTRY`). Symlinks
+/// are followed, so the archive contains the contents of the files, not links into the repository. The archive is
+/// only readable by the user, since it contains the decrypted encrypted files.
+///
+/// `paths` and `files` are paths in `dir`. These paths must be relative to `dir`.
+pub fn export<P1, P2>(dir: P1, paths: &[PathBuf], files: &[PathBuf], path: P2) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let (dir, path) = (dir.as_ref(), path.as_ref());
+    let format = format_of(path)?;
+    let write_err = |err| IoError::Write {
+        source: err,
+        path: path.to_path_buf(),
+    };
+    let zip_write_err = |err| write_err(io::Error::other(err));
+    let paths_list = paths
+        .iter()
+        .map(|path| entry_name(path) + "\n")
+        .collect::<String>();
+
+    // An existing archive keeps its permissions when it's overwritten, so they're set again.
+    let file = platform::private(OpenOptions::new().write(true).create(true).truncate(true))
+        .open(path)
+        .and_then(|file| platform::set_permission_bits(path, 0o600).map(|_| file))
+        .map_err(|err| IoError::Create {
+            source: err,
+            path: path.to_path_buf(),
+        })?;
+
+    match format {
+        ArchiveFormat::Tar => {
+            tar_files(tar::Builder::new(file), dir, &paths_list, files)
+                .and_then(|archive| archive.into_inner())
+                .and_then(|mut file| file.flush())
+                .map_err(write_err)?;
+        }
+        ArchiveFormat::TarGz => {
+            let encoder = GzEncoder::new(file, Compression::default());
+
+            tar_files(tar::Builder::new(encoder), dir, &paths_list, files)
+                .and_then(|archive| archive.into_inner())
+                .and_then(|encoder| encoder.finish())
+                .and_then(|mut file| file.flush())
+                .map_err(write_err)?;
+        }
+        ArchiveFormat::Zip => {
+            let mut archive = ZipWriter::new(file);
+
+            archive
+                .start_file(PATHS_ENTRY, SimpleFileOptions::default())
+                .map_err(zip_write_err)?;
+            archive
+                .write_all(paths_list.as_bytes())
+                .map_err(write_err)?;
+
+            for file in files {
+                let full_path = dir.join(file);
+                let contents = fs::read(&full_path).map_err(|err| IoError::Read {
+                    source: err,
+                    path: full_path.clone(),
+                })?;
+                let mode = fs::metadata(&full_path)
+                    .ok()
+                    .and_then(|meta| platform::permission_bits(&meta))
+                    .unwrap_or(0o644);
+                let options = SimpleFileOptions::default().unix_permissions(mode);
+
+                archive
+                    .start_file(entry_name(file), options)
+                    .map_err(zip_write_err)?;
+                archive.write_all(&contents).map_err(write_err)?;
+            }
+
+            archive
+                .finish()
+                .map_err(zip_write_err)?
+                .flush()
+                .map_err(write_err)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the files in the archive at `path` (see `ArchiveFormat::from_path`). Folders and links are left out, and
+/// entries with paths leading outside of the folder they'd be unpacked into (e.g. `../.bashrc` or `/etc/passwd`) are
+/// refused.
+pub fn read<P>(path: P) -> Result<Archive>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let format = format_of(path)?;
+    let read_err = |err| IoError::Read {
+        source: err,
+        path: path.to_path_buf(),
+    };
+
+    let file = fs::File::open(path).map_err(read_err)?;
+
+    let files = match format {
+        ArchiveFormat::Tar => untar_files(tar::Archive::new(file), path)?,
+        ArchiveFormat::TarGz => untar_files(tar::Archive::new(GzDecoder::new(file)), path)?,
+        ArchiveFormat::Zip => {
+            let mut archive = ZipArchive::new(file).map_err(|err| zip_err(path, err))?;
+            let mut files = vec![];
+
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i).map_err(|err| zip_err(path, err))?;
+
+                if !entry.is_file() {
+                    continue;
+                }
+
+                let entry_path = safe_path(path, Path::new(entry.name()))?;
+                let mut contents = vec![];
+                entry.read_to_end(&mut contents).map_err(read_err)?;
+
+                files.push(ArchivedFile {
+                    path: entry_path,
+                    contents,
+                    mode: entry.unix_mode().map(|mode| mode & 0o777),
+                });
+            }
+
+            files
+        }
+    };
+
+    // The list of managed paths isn't one of the files.
+    let (paths_entry, files): (Vec<_>, Vec<_>) = files
+        .into_iter()
+        .partition(|file| file.path == Path::new(PATHS_ENTRY));
+
+    let paths = match paths_entry.first() {
+        Some(entry) => String::from_utf8_lossy(&entry.contents)
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| safe_path(path, Path::new(line)))
+            .collect::<Result<Vec<_>>>()?,
+        None => files.iter().map(|file| file.path.clone()).collect(),
+    };
+
+    Ok(Archive { paths, files })
+}
+
+/// Helper function to get the format of the archive at `path`, or an error if its extension isn't a known one.
+fn format_of(path: &Path) -> Result<ArchiveFormat> {
+    ArchiveFormat::from_path(path).ok_or_else(|| {
+        ExportError::UnknownArchiveFormat {
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to append `paths_list` (as `PATHS_ENTRY`) and the `files` in `dir` to the tarball `archive`,
+/// following symlinks.
+fn tar_files<W>(
+    mut archive: tar::Builder<W>,
+    dir: &Path,
+    paths_list: &str,
+    files: &[PathBuf],
+) -> io::Result<tar::Builder<W>>
+where
+    W: Write,
+{
+    let mut header = tar::Header::new_gnu();
+    header.set_size(paths_list.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+
+    archive.append_data(&mut header, PATHS_ENTRY, paths_list.as_bytes())?;
+    archive.follow_symlinks(true);
+
+    for file in files {
+        archive.append_path_with_name(dir.join(file), entry_name(file))?;
+    }
+
+    Ok(archive)
+}
+
+/// Helper function to read the regular files in the tarball `archive` at `path`.
+fn untar_files<R>(mut archive: tar::Archive<R>, path: &Path) -> Result<Vec<ArchivedFile>>
+where
+    R: Read,
+{
+    let read_err = |err| IoError::Read {
+        source: err,
+        path: path.to_path_buf(),
+    };
+    let mut files = vec![];
+
+    for entry in archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let entry_path = safe_path(path, &entry.path().map_err(read_err)?)?;
+        let mode = entry.header().mode().ok().map(|mode| mode & 0o777);
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents).map_err(read_err)?;
+
+        files.push(ArchivedFile {
+            path: entry_path,
+            contents,
+            mode,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Helper function to get the name of `file` in an archive, which always uses `/` between its components.
+fn entry_name(file: &Path) -> String {
+    file.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Helper function to check that the path `entry` of an entry in the archive at `path` stays inside of the folder
+/// it's unpacked into, i.e. is relative and has no `..` in it.
+fn safe_path(path: &Path, entry: &Path) -> Result<PathBuf> {
+    let safe = entry
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    match safe
+        && entry
+            .components()
+            .any(|c| matches!(c, Component::Normal(_)))
+    {
+        true => Ok(entry
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect()),
+        false => Err(ExportError::UnsafeArchivePath {
+            path: path.to_path_buf(),
+            entry: entry.to_path_buf(),
+        }
+        .into()),
+    }
+}
+
+/// Helper function to turn an error of the zip archive at `path` into an IO error.
+fn zip_err(path: &Path, err: zip::result::ZipError) -> IoError {
+    IoError::Read {
+        source: io::Error::other(err),
+        path: path.to_path_buf(),
+    }
+}
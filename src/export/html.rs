@@ -0,0 +1,190 @@
+use super::{write_file, Snapshot};
+use crate::errors::{export::ExportError, DotbakError, Result};
+use minijinja::{context, Environment, Value};
+use serde::Serialize;
+use std::path::{Component, Path, PathBuf};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::highlighted_html_for_string,
+    parsing::{SyntaxReference, SyntaxSet},
+};
+
+/// The templates the site is rendered from, by name.
+const TEMPLATES: &[(&str, &str)] = &[
+    ("layout.html", include_str!("templates/layout.html")),
+    ("history.html", include_str!("templates/history.html")),
+    ("index.html", include_str!("templates/index.html")),
+    ("file.html", include_str!("templates/file.html")),
+];
+
+/// The stylesheet for the site.
+const STYLESHEET: &str = include_str!("templates/style.css");
+
+/// The syntax highlighting theme, which has to be one of `syntect`'s default themes.
+const THEME: &str = "InspiredGitHub";
+
+/// An entry in the file tree on the index page.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct TreeEntry {
+    /// The name of the file/folder.
+    pub name: String,
+
+    /// How deeply the file/folder is nested.
+    pub depth: usize,
+
+    /// The link to the file's page, or `None` if this is a folder.
+    pub link: Option<String>,
+}
+
+/// Renders `snapshot` as a static, browsable site into `dir`: an index page with the file tree and the history of
+/// the repository, and a page for every file with its (syntax highlighted) contents and history.
+///
+/// Returns the number of pages rendered.
+pub fn export<P>(snapshot: &Snapshot, dir: P) -> Result<usize>
+where
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    let mut env = Environment::new();
+
+    for (name, source) in TEMPLATES {
+        env.add_template(name, source)
+            .map_err(|err| render_err(name, err))?;
+    }
+
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let themes = ThemeSet::load_defaults();
+    let theme = &themes.themes[THEME];
+
+    let render = |name: &str, context: Value| {
+        env.get_template(name)
+            .and_then(|template| template.render(context))
+            .map_err(|err| render_err(name, err))
+    };
+
+    // Render the index page.
+    let index = render(
+        "index.html",
+        context! {
+            root => "",
+            version => env!("CARGO_PKG_VERSION"),
+            tree => tree(snapshot),
+            history => snapshot.history,
+        },
+    )?;
+    write_file(dir.join("index.html"), &index)?;
+    write_file(dir.join("style.css"), STYLESHEET)?;
+
+    // Render a page for every file.
+    for file in &snapshot.files {
+        let highlighted = file
+            .contents
+            .as_deref()
+            .map(|contents| highlight(&syntaxes, theme, &file.path, contents))
+            .transpose()?
+            .map(Value::from_safe_string);
+
+        let page = render(
+            "file.html",
+            context! {
+                root => "../".repeat(file.path.components().count()),
+                version => env!("CARGO_PKG_VERSION"),
+                path => file.path.display().to_string(),
+                highlighted,
+                history => file.history,
+            },
+        )?;
+        write_file(dir.join(page_path(&file.path)), &page)?;
+    }
+
+    Ok(snapshot.files.len() + 1)
+}
+
+/// Builds the file tree for the index page. This relies on the files in `snapshot` being sorted by path, so that
+/// the files in a folder always come right after the folder.
+pub fn tree(snapshot: &Snapshot) -> Vec<TreeEntry> {
+    let mut entries = vec![];
+    let mut previous: Vec<Component> = vec![];
+
+    for file in &snapshot.files {
+        let components = file.path.components().collect::<Vec<_>>();
+        let (name, folders) = components.split_last().expect("files have a name");
+
+        // Only add the folders that the previous file wasn't in.
+        let shared = folders
+            .iter()
+            .zip(&previous)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        for (depth, folder) in folders.iter().enumerate().skip(shared) {
+            entries.push(TreeEntry {
+                name: folder.as_os_str().to_string_lossy().to_string(),
+                depth,
+                link: None,
+            });
+        }
+
+        entries.push(TreeEntry {
+            name: name.as_os_str().to_string_lossy().to_string(),
+            depth: folders.len(),
+            link: Some(page_path(&file.path).display().to_string()),
+        });
+
+        previous = folders.to_vec();
+    }
+
+    entries
+}
+
+/// Gets the path of the page for the file at `path`, relative to the site.
+fn page_path<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    PathBuf::from(format!("files/{}.html", path.as_ref().display()))
+}
+
+/// Highlights `contents` of the file at `path` as HTML.
+fn highlight(syntaxes: &SyntaxSet, theme: &Theme, path: &Path, contents: &str) -> Result<String> {
+    highlighted_html_for_string(
+        contents,
+        syntaxes,
+        find_syntax(syntaxes, path, contents),
+        theme,
+    )
+    .map_err(|err| {
+        ExportError::Highlight {
+            file: path.display().to_string(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// Finds the syntax for the file at `path`. Dotfiles rarely have extensions, so this tries the extension, then the
+/// name without the leading dot (e.g. `zshrc`), then the first line (e.g. a shebang), and falls back to plain text.
+fn find_syntax<'a>(syntaxes: &'a SyntaxSet, path: &Path, contents: &str) -> &'a SyntaxReference {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string());
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().trim_start_matches('.').to_string());
+
+    extension
+        .into_iter()
+        .chain(name)
+        .find_map(|ext| syntaxes.find_syntax_by_extension(&ext))
+        .or_else(|| syntaxes.find_syntax_by_first_line(contents))
+        .unwrap_or_else(|| syntaxes.find_syntax_plain_text())
+}
+
+/// Helper function to convert a template error into a `DotbakError`.
+fn render_err(template: &str, err: minijinja::Error) -> DotbakError {
+    ExportError::Render {
+        template: template.to_string(),
+        source: err,
+    }
+    .into()
+}
@@ -0,0 +1,92 @@
+pub mod archive;
+pub mod html;
+mod tests;
+
+use crate::{
+    errors::{io::IoError, Result},
+    git::{Commit, Repository},
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A file in the repository, as it is exported.
+pub struct ExportedFile {
+    /// The path to the file, relative to the repository.
+    pub path: PathBuf,
+
+    /// The contents of the file in the current commit, or `None` if it is binary or too large.
+    pub contents: Option<String>,
+
+    /// The commits that changed the file, newest first.
+    pub history: Vec<Commit>,
+}
+
+/// A read-only snapshot of the repository, with everything needed to export it.
+pub struct Snapshot {
+    /// All the files in the current commit, sorted by path.
+    pub files: Vec<ExportedFile>,
+
+    /// The history of the whole repository, newest commit first.
+    pub history: Vec<Commit>,
+}
+
+/// Public API for `Snapshot`.
+impl Snapshot {
+    /// Takes a snapshot of the current commit of `repo`. Uncommitted changes are not included, and neither are the
+    /// contents of files larger than `max_size` bytes, which aren't read at all.
+    pub fn take(repo: &mut Repository, max_size: u64) -> Result<Self> {
+        let mut files = vec![];
+
+        for path in repo.tracked_files()? {
+            let contents = match repo.file_size("HEAD", &path)? {
+                Some(size) if size <= max_size => repo
+                    .show_file("HEAD", &path)?
+                    .filter(|contents| !is_binary(contents)),
+                _ => None,
+            };
+            let history = repo.history(Some(&path))?;
+
+            files.push(ExportedFile {
+                path,
+                contents,
+                history,
+            });
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(Self {
+            files,
+            history: repo.history(None)?,
+        })
+    }
+}
+
+/// Checks if the contents of a file look binary, i.e. contain NUL bytes or weren't valid UTF-8.
+fn is_binary(contents: &str) -> bool {
+    contents.contains(['\0', char::REPLACEMENT_CHARACTER])
+}
+
+/// Helper function to write `contents` to `path`, creating any parent directories.
+fn write_file<P>(path: P, contents: &str) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| IoError::Create {
+            source: err,
+            path: parent.to_path_buf(),
+        })?;
+    }
+
+    fs::write(path, contents).map_err(|err| IoError::Write {
+        source: err,
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+#![cfg(test)]
+
+use super::{archive, html, Snapshot};
+use crate::{git::Repository, platform};
+use assert_fs::{prelude::*, TempDir};
+use std::{fs, path::PathBuf};
+
+/// Creates a repository in `dir` with a few commits, and takes a snapshot of it.
+fn test_snapshot(dir: &TempDir) -> Snapshot {
+    let repo_dir = dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir.child(".zshrc").write_str("echo hi").unwrap();
+    repo.commit("Add zshrc").unwrap();
+
+    repo_dir
+        .child(".config/nvim/init.lua")
+        .write_str("vim.o.number = true -- <b>numbers</b>")
+        .unwrap();
+    repo_dir
+        .child(".config/nvim/spell.bin")
+        .write_binary(&[0, 159, 146, 150])
+        .unwrap();
+    repo_dir.child(".zshrc").write_str("echo hello").unwrap();
+    repo.commit("Add nvim, say hello").unwrap();
+
+    // Uncommitted changes should not be exported.
+    repo_dir.child(".zshrc").write_str("echo secret").unwrap();
+
+    Snapshot::take(&mut repo, u64::MAX).unwrap()
+}
+
+/// Reads a page of the exported site. `minijinja` escapes slashes, so they are unescaped to make the tests more
+/// readable.
+fn read_page<P: AsRef<std::path::Path>>(path: P) -> String {
+    fs::read_to_string(path).unwrap().replace("&#x2f;", "/")
+}
+
+/// Test that a snapshot contains the committed files and their histories.
+#[test]
+fn test_snapshot_take() {
+    let dir = TempDir::new().unwrap();
+    let snapshot = test_snapshot(&dir);
+
+    assert_eq!(
+        snapshot
+            .files
+            .iter()
+            .map(|f| f.path.clone())
+            .collect::<Vec<_>>(),
+        vec![
+            PathBuf::from(".config/nvim/init.lua"),
+            PathBuf::from(".config/nvim/spell.bin"),
+            PathBuf::from(".zshrc"),
+        ]
+    );
+    assert_eq!(snapshot.files[1].contents, None);
+    assert_eq!(snapshot.files[2].contents.as_deref(), Some("echo hello"));
+    assert_eq!(
+        snapshot.files[2]
+            .history
+            .iter()
+            .map(|c| c.summary.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Add nvim, say hello", "Add zshrc"]
+    );
+    assert_eq!(snapshot.history.len(), 2);
+}
+
+/// Test that the file tree lists every folder once, before the files in it.
+#[test]
+fn test_html_tree() {
+    let dir = TempDir::new().unwrap();
+    let tree = html::tree(&test_snapshot(&dir));
+
+    assert_eq!(
+        tree.iter()
+            .map(|e| (e.name.as_str(), e.depth, e.link.is_some()))
+            .collect::<Vec<_>>(),
+        vec![
+            (".config", 0, false),
+            ("nvim", 1, false),
+            ("init.lua", 2, true),
+            ("spell.bin", 2, true),
+            (".zshrc", 0, true),
+        ]
+    );
+}
+
+/// Test that the site is exported with escaped contents and history.
+#[test]
+fn test_html_export() {
+    let dir = TempDir::new().unwrap();
+    let snapshot = test_snapshot(&dir);
+    let site = dir.child("site");
+
+    assert_eq!(html::export(&snapshot, site.path()).unwrap(), 4);
+
+    let index = read_page(site.child("index.html"));
+    assert!(index.contains(r#"href="files/.config/nvim/init.lua.html""#));
+    assert!(index.contains("Add nvim, say hello"));
+    assert!(site.child("style.css").exists());
+
+    let init = read_page(site.child("files/.config/nvim/init.lua.html"));
+    assert!(init.contains(r#"href="../../../style.css""#));
+    assert!(init.contains("&lt;b&gt;numbers&lt;/b&gt;"));
+    assert!(!init.contains("<b>numbers</b>"));
+
+    let spell = read_page(site.child("files/.config/nvim/spell.bin.html"));
+    assert!(spell.contains("Binary file, not shown."));
+
+    let zshrc = read_page(site.child("files/.zshrc.html"));
+    assert!(zshrc.contains("hello"));
+    assert!(!zshrc.contains("secret"));
+    assert!(zshrc.contains("Add zshrc"));
+}
+
+/// Test that files are exported with their contents (not as symlinks), and read back the same, in every format.
+#[test]
+fn test_archive_round_trip() {
+    let dir = TempDir::new().unwrap();
+    let home = dir.child("home");
+
+    dir.child("repo/init.lua")
+        .write_str("vim.o.number = true")
+        .unwrap();
+    home.child(".zshrc").write_str("echo hi").unwrap();
+    home.child(".config").create_dir_all().unwrap();
+    platform::symlink(dir.child("repo"), home.child(".config/nvim")).unwrap();
+
+    let paths = vec![PathBuf::from(".zshrc"), PathBuf::from(".config/nvim")];
+    let files = vec![
+        PathBuf::from(".zshrc"),
+        PathBuf::from(".config/nvim/init.lua"),
+    ];
+
+    // The archive written over is readable by everyone, but the exported one shouldn't be.
+    dir.child("dotfiles.zip").write_str("old").unwrap();
+    platform::set_permission_bits(dir.child("dotfiles.zip"), 0o644).unwrap();
+
+    for name in ["dotfiles.tar", "dotfiles.tar.gz", "dotfiles.zip"] {
+        let path = dir.child(name);
+        archive::export(home.path(), &paths, &files, path.path()).unwrap();
+
+        #[cfg(unix)]
+        assert_eq!(
+            platform::permission_bits(&fs::metadata(path.path()).unwrap()),
+            Some(0o600)
+        );
+
+        let archive = archive::read(path.path()).unwrap();
+        assert_eq!(archive.paths, paths);
+        assert_eq!(
+            archive
+                .files
+                .iter()
+                .map(|file| (file.path.clone(), file.contents.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (files[0].clone(), b"echo hi".to_vec()),
+                (files[1].clone(), b"vim.o.number = true".to_vec()),
+            ]
+        );
+    }
+
+    assert!(archive::export(
+        home.path(),
+        &paths,
+        &files,
+        dir.child("dotfiles.rar").path()
+    )
+    .is_err());
+}
+
+/// Test that entries leading outside of the folder they're unpacked into are refused.
+#[test]
+fn test_archive_unsafe_path() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.child("evil.tar");
+    let mut builder = tar::Builder::new(fs::File::create(path.path()).unwrap());
+    let mut header = tar::Header::new_gnu();
+    let paths = b"../.bashrc\n";
+
+    header.set_size(paths.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, archive::PATHS_ENTRY, paths.as_slice())
+        .unwrap();
+    builder.into_inner().unwrap();
+
+    assert!(archive::read(path.path()).is_err());
+}
@@ -0,0 +1,294 @@
+use crate::{
+    errors::{io::IoError, Result},
+    platform,
+};
+use similar::{DiffOp, TextDiff};
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+/// The size of the chunks files are compared in. Comparing files never needs more memory than two of these.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How much of a file is checked for NUL bytes to decide if it's binary. This is the same as git's heuristic.
+const BINARY_CHECK_SIZE: usize = 8000;
+
+/// The offset basis of the 64-bit FNV-1a hash, which `checksum` uses.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// The prime of the 64-bit FNV-1a hash, which `checksum` uses.
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Checks if the files/folders at `a` and `b` have the same contents. Files with different sizes are different
+/// without reading them, and all other files are streamed in chunks, so that this needs a bounded amount of memory
+/// even for huge files. Folders are the same if they contain the same files with the same contents.
+pub fn same_contents<P1, P2>(a: P1, b: P2) -> Result<bool>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let (meta_a, meta_b) = (metadata(a)?, metadata(b)?);
+
+    if meta_a.is_dir() || meta_b.is_dir() {
+        if !(meta_a.is_dir() && meta_b.is_dir()) {
+            return Ok(false);
+        }
+
+        let (entries_a, entries_b) = (entry_names(a)?, entry_names(b)?);
+
+        if entries_a != entries_b {
+            return Ok(false);
+        }
+
+        for name in entries_a {
+            if !same_contents(a.join(&name), b.join(&name))? {
+                return Ok(false);
+            }
+        }
+
+        return Ok(true);
+    }
+
+    if meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+
+    let (mut reader_a, mut reader_b) = (open(a)?, open(b)?);
+    let (mut chunk_a, mut chunk_b) = (vec![0; CHUNK_SIZE], vec![0; CHUNK_SIZE]);
+
+    loop {
+        let read = read_chunk(&mut reader_a, &mut chunk_a, a)?;
+
+        if read != read_chunk(&mut reader_b, &mut chunk_b, b)? || chunk_a[..read] != chunk_b[..read]
+        {
+            return Ok(false);
+        }
+
+        if read == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Gets a checksum of the contents of the file/folder at `path`, to notice when they change. It's not meant to be
+/// secure, only fast. Files are streamed in chunks like in `same_contents`, folders are checksummed by the names and
+/// contents of their entries, and symlinks by where they point to.
+pub fn checksum<P>(path: P) -> Result<String>
+where
+    P: AsRef<Path>,
+{
+    let mut hash = FNV_OFFSET_BASIS;
+
+    add_to_checksum(&mut hash, path.as_ref())?;
+
+    Ok(format!("{:016x}", hash))
+}
+
+/// Checks if the file at `path` is too large to be diffed within `limit` bytes of memory, or looks binary (i.e.
+/// has a NUL byte near its start), so that its contents shouldn't be diffed. Folders never are.
+pub fn is_large_or_binary<P>(path: P, limit: u64) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let meta = metadata(path)?;
+
+    if !meta.is_file() {
+        return Ok(false);
+    }
+
+    if meta.len() > limit {
+        return Ok(true);
+    }
+
+    let mut start = Vec::with_capacity(BINARY_CHECK_SIZE);
+
+    open(path)?
+        .take(BINARY_CHECK_SIZE as u64)
+        .read_to_end(&mut start)
+        .map_err(|err| IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        })?;
+
+    Ok(start.contains(&0))
+}
+
+/// Merges the lines of the two versions `tracked` and `home` of a file, without knowing which version they both
+/// started from: lines that are only in one of them (e.g. added on this machine, or pulled from another one) are
+/// kept. Returns `None` if both changed the same lines, as it's unclear which change to keep.
+pub fn merge_lines(tracked: &str, home: &str) -> Option<String> {
+    // A missing newline at the end would make the last line differ from the same line followed by others.
+    let with_newline = |text: &str| match text.is_empty() || text.ends_with('\n') {
+        true => text.to_string(),
+        false => format!("{}\n", text),
+    };
+    let (tracked, home) = (with_newline(tracked), with_newline(home));
+
+    let diff = TextDiff::from_lines(&tracked, &home);
+    let (old, new) = (diff.old_slices(), diff.new_slices());
+    let mut merged = String::with_capacity(tracked.len().max(home.len()));
+
+    for op in diff.ops() {
+        match *op {
+            DiffOp::Equal { old_index, len, .. }
+            | DiffOp::Delete {
+                old_index,
+                old_len: len,
+                ..
+            } => merged.extend(old[old_index..old_index + len].iter().copied()),
+            DiffOp::Insert {
+                new_index, new_len, ..
+            } => merged.extend(new[new_index..new_index + new_len].iter().copied()),
+            DiffOp::Replace { .. } => return None,
+        }
+    }
+
+    Some(merged)
+}
+
+/// Finds the files which shouldn't be diffed (see `is_large_or_binary`) in `files`, looking inside of folders.
+///
+/// `files` are the paths to the files/folders in `dir`. These paths must be relative to `dir`, and so are the
+/// returned paths.
+pub fn large_or_binary<P1, P2>(files: &[P1], dir: P2, limit: u64) -> Result<Vec<PathBuf>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut found = vec![];
+
+    for file in files {
+        let path = dir.as_ref().join(file);
+
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => {
+                let entries = entry_names(&path)?
+                    .into_iter()
+                    .map(|name| file.as_ref().join(name))
+                    .collect::<Vec<_>>();
+
+                found.extend(large_or_binary(&entries, dir.as_ref(), limit)?);
+            }
+            Ok(meta) if meta.is_file() && is_large_or_binary(&path, limit)? => {
+                found.push(file.as_ref().to_path_buf());
+            }
+            _ => (),
+        }
+    }
+
+    Ok(found)
+}
+
+/// Helper function to add the contents of the file/folder at `path` to the checksum `hash`. See `checksum`.
+fn add_to_checksum(hash: &mut u64, path: &Path) -> Result<()> {
+    let meta = metadata(path)?;
+
+    if meta.is_symlink() {
+        let target = fs::read_link(path).map_err(|err| IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        })?;
+
+        fnv_update(hash, b"l");
+        fnv_update(hash, &platform::os_str_bytes(target.as_os_str()));
+    } else if meta.is_dir() {
+        fnv_update(hash, b"d");
+
+        for name in entry_names(path)? {
+            fnv_update(hash, &platform::os_str_bytes(name.as_os_str()));
+            fnv_update(hash, &[0]);
+            add_to_checksum(hash, &path.join(name))?;
+        }
+    } else {
+        let (mut reader, mut chunk) = (open(path)?, vec![0; CHUNK_SIZE]);
+
+        fnv_update(hash, b"f");
+
+        loop {
+            let read = read_chunk(&mut reader, &mut chunk, path)?;
+
+            if read == 0 {
+                break;
+            }
+
+            fnv_update(hash, &chunk[..read]);
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function to add `bytes` to the FNV-1a hash `hash`.
+fn fnv_update(hash: &mut u64, bytes: &[u8]) {
+    for byte in bytes {
+        *hash ^= u64::from(*byte);
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+/// Helper function to get the metadata of `path`, without following symlinks.
+fn metadata(path: &Path) -> Result<fs::Metadata> {
+    fs::symlink_metadata(path).map_err(|err| {
+        IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to get the sorted names of the entries of the folder at `path`.
+fn entry_names(path: &Path) -> Result<Vec<PathBuf>> {
+    let read_err = |err| IoError::Read {
+        source: err,
+        path: path.to_path_buf(),
+    };
+
+    let mut names = fs::read_dir(path)
+        .map_err(read_err)?
+        .map(|entry| entry.map(|entry| PathBuf::from(entry.file_name())))
+        .collect::<std::io::Result<Vec<_>>>()
+        .map_err(read_err)?;
+
+    names.sort();
+
+    Ok(names)
+}
+
+/// Helper function to open the file at `path` for buffered reading.
+fn open(path: &Path) -> Result<BufReader<File>> {
+    File::open(path).map(BufReader::new).map_err(|err| {
+        IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to fill `chunk` from `reader` as far as possible. Returns how many bytes were read, which is
+/// less than the size of `chunk` only at the end of the file.
+fn read_chunk(reader: &mut impl Read, chunk: &mut [u8], path: &Path) -> Result<usize> {
+    let mut read = 0;
+
+    while read < chunk.len() {
+        match reader.read(&mut chunk[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+            Err(err) => {
+                return Err(IoError::Read {
+                    source: err,
+                    path: path.to_path_buf(),
+                }
+                .into())
+            }
+        }
+    }
+
+    Ok(read)
+}
@@ -0,0 +1,241 @@
+use crate::{
+    errors::{encryption::EncryptionError, io::IoError, DotbakError, Result},
+    platform,
+};
+use age::{
+    secrecy::ExposeSecret,
+    x25519::{Identity, Recipient},
+};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+/// The name of the key file, next to the configuration file, unless `encryption.key` is set.
+pub const KEY_FILE_NAME: &str = "key.txt";
+
+/// The extension of encrypted files in the repository.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+/// Gets the path of the encrypted version of `file`, i.e. `<file>.age`.
+pub fn encrypted_path<P>(file: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut path = file.as_ref().as_os_str().to_os_string();
+    path.push(".");
+    path.push(ENCRYPTED_EXTENSION);
+
+    PathBuf::from(path)
+}
+
+/// Generates a new key, and writes it to `path` in the format of `age-keygen`, readable only by the user. Returns
+/// an error if there already is a key at `path`.
+pub fn generate_key<P>(path: P) -> Result<Recipient>
+where
+    P: AsRef<Path>,
+{
+    let identity = Identity::generate();
+
+    write_key(path, &identity)?;
+
+    Ok(identity.to_public())
+}
+
+/// Copies the key at `from` (e.g. created by `age-keygen`, or on another machine) to `to`, readable only by the
+/// user. Returns an error if `from` isn't a valid key, or if there already is a key at `to`.
+pub fn import_key<P1, P2>(from: P1, to: P2) -> Result<Recipient>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let identity = load_key(from)?;
+
+    write_key(to, &identity)?;
+
+    Ok(identity.to_public())
+}
+
+/// Loads the key at `path`. Comments (lines starting with `#`) and empty lines are skipped, and the first other line
+/// must be the key itself.
+pub fn load_key<P>(path: P) -> Result<Identity>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => EncryptionError::NoKey {
+            path: path.to_path_buf(),
+        }
+        .into(),
+        _ => DotbakError::from(IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }),
+    })?;
+
+    let key = contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .unwrap_or_default();
+
+    Identity::from_str(key).map_err(|reason| {
+        EncryptionError::InvalidKey {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Encrypts the file at `from` into `to` with `key`, unless `to` is newer and already has the same contents. So
+/// files are only encrypted again when they changed, and changes to `to` (e.g. from a pull) are kept.
+pub fn encrypt_file<P1, P2>(from: P1, to: P2, key: &Identity) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let (from, to) = (from.as_ref(), to.as_ref());
+
+    if fs::symlink_metadata(from).is_ok_and(|meta| meta.is_dir()) {
+        return Err(EncryptionError::NotAFile {
+            path: from.to_path_buf(),
+        }
+        .into());
+    }
+
+    if modified(to) >= modified(from) {
+        return Ok(());
+    }
+
+    let plaintext = read(from)?;
+
+    if to.exists() && decrypt(to, key)? == plaintext {
+        return Ok(());
+    }
+
+    let ciphertext =
+        age::encrypt(&key.to_public(), &plaintext).map_err(|err| EncryptionError::Encrypt {
+            source: Box::new(err),
+            path: from.to_path_buf(),
+        })?;
+
+    create_parent(to)?;
+    fs::write(to, ciphertext).map_err(|err| {
+        IoError::Write {
+            source: err,
+            path: to.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Decrypts the file at `from` into `to` with `key`, unless `to` already has the same contents. If `to` doesn't exist
+/// yet, it's created readable only by the user, otherwise its permissions are kept.
+pub fn decrypt_file<P1, P2>(from: P1, to: P2, key: &Identity) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let (from, to) = (from.as_ref(), to.as_ref());
+    let plaintext = decrypt(from, key)?;
+
+    if to.is_file() && read(to)? == plaintext {
+        return Ok(());
+    }
+
+    // Don't write through symlinks, e.g. the one to the unencrypted file in the repository.
+    if fs::symlink_metadata(to).is_ok_and(|meta| meta.is_symlink()) {
+        fs::remove_file(to).map_err(|err| IoError::Delete {
+            source: err,
+            path: to.to_path_buf(),
+        })?;
+    }
+
+    create_parent(to)?;
+    write_private(to, &plaintext)
+}
+
+/// Helper function to decrypt the file at `path` with `key`.
+fn decrypt(path: &Path, key: &Identity) -> Result<Vec<u8>> {
+    age::decrypt(key, &read(path)?).map_err(|err| {
+        EncryptionError::Decrypt {
+            source: Box::new(err),
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to write `key` to `path`, readable only by the user. Returns an error if `path` already exists.
+fn write_key<P>(path: P, key: &Identity) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if path.exists() {
+        return Err(EncryptionError::KeyExists {
+            path: path.to_path_buf(),
+        }
+        .into());
+    }
+
+    let contents = format!(
+        "# public key: {}\n{}\n",
+        key.to_public(),
+        key.to_string().expose_secret()
+    );
+
+    create_parent(path)?;
+    write_private(path, contents.as_bytes())
+}
+
+/// Helper function to write `contents` to the file at `path`. If it doesn't exist yet, it's created readable only by
+/// the user.
+fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    platform::private(OpenOptions::new().write(true).create(true).truncate(true))
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|err| {
+            IoError::Write {
+                source: err,
+                path: path.to_path_buf(),
+            }
+            .into()
+        })
+}
+
+/// Helper function to read the file at `path`.
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|err| {
+        IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to create the parent folders of `path`.
+fn create_parent(path: &Path) -> Result<()> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(|err| {
+            IoError::Create {
+                source: err,
+                path: parent.to_path_buf(),
+            }
+            .into()
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Helper function to get when the file at `path` was last modified, if it exists.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -0,0 +1,162 @@
+use crate::{config::files::Root, errors::io::IoError, platform};
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{Mutex, PoisonError},
+};
+
+/// The script escalated operations are run with. It runs the command of the operation, and then gives what it wrote
+/// back to the owner of the repository, if it wrote into the repository. Its arguments are the owner (as
+/// `<uid>:<gid>`, or empty if nothing is given back), the path to give back, and the command.
+const SCRIPT: &str =
+    r#"owner="$1" path="$2"; shift 2; "$@" && { [ -z "$owner" ] || chown -R "$owner" "$path"; }"#;
+
+/// An operation on a file/folder that `Escalator` can run again with more permissions. Each one is run with the
+/// standard command for it, as that is what `sudo` and `pkexec` can run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation<'a> {
+    /// Creating the folder at the path, and the folders containing it.
+    CreateDir(&'a Path),
+
+    /// Copying the file at `from` to `to`, replacing what's at `to`.
+    Copy { from: &'a Path, to: &'a Path },
+
+    /// Moving the file/folder at `from` to `to`.
+    Move { from: &'a Path, to: &'a Path },
+
+    /// Symlinking the file/folder at `from` to `to`, replacing what's at `to`.
+    Symlink { from: &'a Path, to: &'a Path },
+
+    /// Deleting the file/folder at the path, permanently.
+    Delete(&'a Path),
+}
+
+impl<'a> Operation<'a> {
+    /// Gets the command (with its arguments) that runs the operation.
+    pub fn command(&self) -> Vec<OsString> {
+        let (command, paths): (&[&str], &[&Path]) = match self {
+            Operation::CreateDir(path) => (&["mkdir", "-p", "--"], &[path]),
+            Operation::Copy { from, to } => (&["cp", "--"], &[from, to]),
+            Operation::Move { from, to } => (&["mv", "-f", "--"], &[from, to]),
+            Operation::Symlink { from, to } => (&["ln", "-sfn", "--"], &[from, to]),
+            Operation::Delete(path) => (&["rm", "-rf", "--"], &[path]),
+        };
+
+        command
+            .iter()
+            .map(OsString::from)
+            .chain(paths.iter().map(|path| path.as_os_str().to_os_string()))
+            .collect()
+    }
+
+    /// Gets the paths the operation touches: where it writes to first, and then where it reads from, if anywhere.
+    fn paths(self) -> [Option<&'a Path>; 2] {
+        match self {
+            Operation::CreateDir(path) | Operation::Delete(path) => [Some(path), None],
+            Operation::Copy { from, to }
+            | Operation::Move { from, to }
+            | Operation::Symlink { from, to } => [Some(to), Some(from)],
+        }
+    }
+}
+
+/// Runs the operations on files/folders in roots that failed for lack of permissions again, with the escalation of
+/// their root (see `Root::escalate`).
+#[derive(Debug, Default)]
+pub struct Escalator {
+    /// The roots.
+    roots: Vec<Root>,
+
+    /// The repository. What escalated operations write into it is given back to `owner`.
+    file_dir: PathBuf,
+
+    /// The user and group ID of the owner of `file_dir`, if they're known.
+    owner: Option<(u32, u32)>,
+
+    /// Escalated operations are run one at a time, so that their prompts for passwords don't pile up.
+    lock: Mutex<()>,
+}
+
+impl Escalator {
+    /// Creates an escalator for the files/folders in `roots`, which are kept in the repository `file_dir`.
+    pub fn new(roots: Vec<Root>, file_dir: PathBuf) -> Self {
+        Self {
+            owner: platform::owner(&file_dir),
+            roots,
+            file_dir,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Runs `operation` again after it failed with `err`, with the escalation of the root of the files/folders it
+    /// touches. If it failed for another reason than a lack of permissions, or doesn't touch a root, it isn't run
+    /// again, and `fail` turns `err` into the error to return. If the root doesn't escalate, or escalating is
+    /// declined, the error says so.
+    pub fn retry<F>(&self, operation: Operation<'_>, err: io::Error, fail: F) -> Result<(), IoError>
+    where
+        F: FnOnce(io::Error) -> IoError,
+    {
+        let found = (err.kind() == io::ErrorKind::PermissionDenied)
+            .then(|| self.root_of(operation))
+            .flatten();
+
+        let Some((path, root)) = found else {
+            return Err(fail(err));
+        };
+
+        let Some(command) = root.escalate.command() else {
+            return Err(IoError::NeedsPermissions {
+                path: path.to_path_buf(),
+                root: root.path.clone(),
+                source: err,
+            });
+        };
+
+        // What's written into the repository is given back to its owner, so that it isn't owned by root.
+        let target = operation.paths()[0].unwrap_or(path);
+        let owner = self
+            .owner
+            .filter(|_| target.starts_with(&self.file_dir))
+            .map(|(uid, gid)| format!("{}:{}", uid, gid))
+            .unwrap_or_default();
+
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let output = Command::new(command[0])
+            .args(&command[1..])
+            .args(["/bin/sh", "-c", SCRIPT, "sh", &owner])
+            .arg(target)
+            .args(operation.command())
+            .stdin(Stdio::inherit())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output();
+
+        let reason = match output {
+            Ok(output) if output.status.success() => return Ok(()),
+            Ok(output) => match String::from_utf8_lossy(&output.stderr).trim() {
+                "" => output.status.to_string(),
+                stderr => stderr.to_string(),
+            },
+            Err(err) => err.to_string(),
+        };
+
+        Err(IoError::Escalation {
+            path: path.to_path_buf(),
+            command: command[0].to_string(),
+            reason,
+        })
+    }
+
+    /// Finds the first path `operation` touches that is in a root, along with the root.
+    fn root_of<'a>(&self, operation: Operation<'a>) -> Option<(&'a Path, &Root)> {
+        operation.paths().into_iter().flatten().find_map(|path| {
+            self.roots
+                .iter()
+                .find(|root| path.starts_with(&root.path))
+                .map(|root| (path, root))
+        })
+    }
+}
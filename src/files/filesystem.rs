@@ -0,0 +1,633 @@
+use crate::platform::{self, Trash};
+use std::{
+    ffi::OsString,
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[cfg(any(test, feature = "testing"))]
+use std::{
+    collections::BTreeMap,
+    path::Component,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+/// What is at a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// A file (or anything else that isn't a folder or a symlink).
+    File,
+
+    /// A folder.
+    Dir,
+
+    /// A symlink.
+    Symlink,
+}
+
+impl FileKind {
+    /// Gets the kind of what has the metadata `meta`.
+    fn of(meta: &Metadata) -> Self {
+        if meta.is_symlink() {
+            FileKind::Symlink
+        } else if meta.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        }
+    }
+}
+
+/// The operations `Files` links, moves and deletes files/folders with. `RealFileSystem` runs them on the disk, and
+/// `MemoryFileSystem` (with the `testing` feature) on files/folders kept in memory, so that what `Files` does can be
+/// tested without creating any. Every path is absolute.
+pub trait FileSystem: Send + Sync {
+    /// Gets what is at `path` without following it if it's a symlink, or `None` if there is nothing.
+    fn kind(&self, path: &Path) -> Option<FileKind>;
+
+    /// Gets what is at `path`, following symlinks, or `None` if there is nothing (or a broken symlink).
+    fn stat(&self, path: &Path) -> Option<FileKind>;
+
+    /// Gets the path the symlink at `path` points to, as it was written.
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Gets the names of the files/folders in the folder at `path`. Their order is unspecified.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>>;
+
+    /// Reads the contents of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Writes `contents` to the file at `path`, replacing it if it exists.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Gets when the file at `path` was last modified, if it exists.
+    fn modified(&self, path: &Path) -> Option<SystemTime>;
+
+    /// Gets the number of hard links to the file at `path`, or 0 if there is nothing. Symlinks aren't followed.
+    fn hard_links(&self, path: &Path) -> u64;
+
+    /// Creates the folder at `path`, and the folders containing it.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes the empty folder at `path`.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes the folder at `path`, and everything in it.
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Deletes the file (or symlink) at `path`.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Copies the file at `from` to `to`, replacing what's at `to`.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Moves the file/folder at `from` to `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Creates a symlink at `link` pointing to `target`. See `platform::symlink`.
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()>;
+
+    /// Removes the symlink at `link`, leaving what it points to alone. See `platform::remove_link`.
+    fn remove_link(&self, link: &Path) -> io::Result<()>;
+
+    /// Deletes the file/folder at `path`, moving it to `trash` if there is one, or permanently otherwise. See
+    /// `platform::delete`.
+    fn delete(&self, path: &Path, trash: Option<&dyn Trash>) -> io::Result<()>;
+
+    /// Checks if there is something at `path`, following symlinks.
+    fn exists(&self, path: &Path) -> bool {
+        self.stat(path).is_some()
+    }
+
+    /// Checks if there is a folder at `path`, following symlinks.
+    fn is_dir(&self, path: &Path) -> bool {
+        self.stat(path) == Some(FileKind::Dir)
+    }
+
+    /// Checks if there is a file at `path`, following symlinks.
+    fn is_file(&self, path: &Path) -> bool {
+        self.stat(path) == Some(FileKind::File)
+    }
+
+    /// Checks if there is a symlink at `path`.
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.kind(path) == Some(FileKind::Symlink)
+    }
+}
+
+/// The filesystem on the disk. This is what `Files` uses unless it's given another one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn kind(&self, path: &Path) -> Option<FileKind> {
+        fs::symlink_metadata(path)
+            .ok()
+            .map(|meta| FileKind::of(&meta))
+    }
+
+    fn stat(&self, path: &Path) -> Option<FileKind> {
+        fs::metadata(path).ok().map(|meta| FileKind::of(&meta))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        Ok(fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.file_name())
+            .collect())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        fs::write(path, contents)
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        fs::metadata(path).and_then(|meta| meta.modified()).ok()
+    }
+
+    fn hard_links(&self, path: &Path) -> u64 {
+        fs::symlink_metadata(path)
+            .map(|meta| platform::hard_links(&meta))
+            .unwrap_or(0)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::copy(from, to).map(|_| ())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        platform::symlink(target, link)
+    }
+
+    fn remove_link(&self, link: &Path) -> io::Result<()> {
+        platform::remove_link(link)
+    }
+
+    fn delete(&self, path: &Path, trash: Option<&dyn Trash>) -> io::Result<()> {
+        platform::delete(path, trash)
+    }
+}
+
+/// How many symlinks are followed when resolving a path in `MemoryFileSystem`, before it's given up on as a loop.
+#[cfg(any(test, feature = "testing"))]
+const MAX_HOPS: usize = 40;
+
+/// A filesystem kept in memory, for testing `Files` without touching the disk. It starts out with nothing but the
+/// root folder `/`. Deleting never moves anything to the trash, and files have one hard link. Writes to paths given
+/// to `deny` fail as if they weren't allowed, so that failures can be tested too.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    /// The files/folders, and the paths that can't be written to.
+    state: Mutex<Memory>,
+}
+
+/// The contents of a `MemoryFileSystem`.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+struct Memory {
+    /// The files/folders by their paths, which are normalized and have no symlinks in their parents. The root folder
+    /// isn't stored.
+    nodes: BTreeMap<PathBuf, Node>,
+
+    /// The paths that writing to (or inside of) fails. See `MemoryFileSystem::deny`.
+    denied: Vec<PathBuf>,
+
+    /// Counts the writes, so that every file has a later modification time than the ones written before it.
+    clock: u64,
+}
+
+/// A file/folder in a `MemoryFileSystem`.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    /// A file with its contents, and the tick of the clock it was last written at.
+    File { contents: Vec<u8>, modified: u64 },
+
+    /// A folder. Its children are the nodes inside of its path.
+    Dir,
+
+    /// A symlink to the path.
+    Symlink(PathBuf),
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Node {
+    /// Gets what kind of file/folder the node is.
+    fn kind(&self) -> FileKind {
+        match self {
+            Node::File { .. } => FileKind::File,
+            Node::Dir => FileKind::Dir,
+            Node::Symlink(_) => FileKind::Symlink,
+        }
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl MemoryFileSystem {
+    /// Creates a filesystem with nothing in it but the root folder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the file at `path` with `contents`, and the folders containing it.
+    ///
+    /// Panics if it can't be created.
+    pub fn add_file<P>(&self, path: P, contents: &str)
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        self.create_dir_all(path.parent().unwrap()).unwrap();
+        self.write(path, contents.as_bytes()).unwrap();
+    }
+
+    /// Makes writing to `path`, or to anything inside of it, fail with `io::ErrorKind::PermissionDenied`.
+    pub fn deny<P>(&self, path: P)
+    where
+        P: AsRef<Path>,
+    {
+        self.lock().denied.push(normalize(path.as_ref()));
+    }
+
+    /// Gets all the files/folders, and what they are, sorted by their paths.
+    pub fn tree(&self) -> BTreeMap<PathBuf, FileKind> {
+        self.lock()
+            .nodes
+            .iter()
+            .map(|(path, node)| (path.clone(), node.kind()))
+            .collect()
+    }
+
+    /// Locks the contents. A panic while they were locked doesn't leave them half-changed, so poisoning is ignored.
+    fn lock(&self) -> std::sync::MutexGuard<'_, Memory> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Memory {
+    /// Gets the path that `path` points to, following the symlinks in it (and at it, if `follow` is set).
+    fn resolve(&self, path: &Path, follow: bool) -> PathBuf {
+        self.resolve_with(path, follow, &mut 0)
+    }
+
+    /// Like `resolve`, counting the symlinks followed in `hops`.
+    fn resolve_with(&self, path: &Path, follow: bool, hops: &mut usize) -> PathBuf {
+        let components = path.components().collect::<Vec<_>>();
+        let mut resolved = PathBuf::from("/");
+
+        for (i, component) in components.iter().enumerate() {
+            match component {
+                Component::ParentDir => {
+                    resolved.pop();
+                    continue;
+                }
+                Component::Normal(name) => resolved.push(name),
+                _ => continue,
+            }
+
+            if i + 1 == components.len() && !follow {
+                break;
+            }
+
+            while let Some(Node::Symlink(target)) = self.nodes.get(&resolved) {
+                if *hops == MAX_HOPS {
+                    break;
+                }
+
+                *hops += 1;
+                let target = resolved.parent().unwrap().join(target);
+                resolved = self.resolve_with(&target, true, hops);
+            }
+        }
+
+        resolved
+    }
+
+    /// Gets the node at `path` (which must be resolved), treating the root as a folder.
+    fn node(&self, path: &Path) -> Option<&Node> {
+        match path.parent() {
+            None => Some(&Node::Dir),
+            Some(_) => self.nodes.get(path),
+        }
+    }
+
+    /// Checks that `path` (which must be resolved) can be written to: it isn't denied, and its parent is a folder.
+    fn writable(&self, path: &Path) -> io::Result<()> {
+        if self.denied.iter().any(|denied| path.starts_with(denied)) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+
+        match path.parent().map(|parent| self.node(parent)) {
+            Some(Some(Node::Dir)) | None => Ok(()),
+            Some(Some(_)) => Err(io::ErrorKind::NotADirectory.into()),
+            Some(None) => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Checks if the folder at `path` (which must be resolved) has nothing in it.
+    fn is_empty(&self, path: &Path) -> bool {
+        !self.nodes.keys().any(|key| key.parent() == Some(path))
+    }
+
+    /// Writes the file at `path` (which must be resolved and writable).
+    fn put_file(&mut self, path: PathBuf, contents: Vec<u8>) {
+        self.clock += 1;
+
+        let modified = self.clock;
+        self.nodes.insert(path, Node::File { contents, modified });
+    }
+
+    /// Deletes the file/folder at `path` (which must be resolved), and everything inside of it.
+    fn remove_all(&mut self, path: &Path) -> io::Result<()> {
+        self.writable(path)?;
+
+        if self.nodes.remove(path).is_none() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        self.nodes.retain(|key, _| !key.starts_with(path));
+
+        Ok(())
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl FileSystem for MemoryFileSystem {
+    fn kind(&self, path: &Path) -> Option<FileKind> {
+        let memory = self.lock();
+
+        memory.node(&memory.resolve(path, false)).map(Node::kind)
+    }
+
+    fn stat(&self, path: &Path) -> Option<FileKind> {
+        let memory = self.lock();
+
+        memory
+            .node(&memory.resolve(path, true))
+            .map(Node::kind)
+            .filter(|kind| *kind != FileKind::Symlink)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let memory = self.lock();
+
+        match memory.node(&memory.resolve(path, false)) {
+            Some(Node::Symlink(target)) => Ok(target.clone()),
+            Some(_) => Err(io::ErrorKind::InvalidInput.into()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<OsString>> {
+        let memory = self.lock();
+        let path = memory.resolve(path, true);
+
+        match memory.node(&path) {
+            Some(Node::Dir) => Ok(memory
+                .nodes
+                .keys()
+                .filter(|key| key.parent() == Some(&path))
+                .filter_map(|key| key.file_name().map(OsString::from))
+                .collect()),
+            Some(_) => Err(io::ErrorKind::NotADirectory.into()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let memory = self.lock();
+
+        match memory.node(&memory.resolve(path, true)) {
+            Some(Node::File { contents, .. }) => Ok(contents.clone()),
+            Some(_) => Err(io::ErrorKind::IsADirectory.into()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, true);
+
+        memory.writable(&path)?;
+
+        match memory.node(&path) {
+            Some(Node::Dir) => Err(io::ErrorKind::IsADirectory.into()),
+            _ => {
+                memory.put_file(path, contents.to_vec());
+                Ok(())
+            }
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Option<SystemTime> {
+        let memory = self.lock();
+
+        match memory.node(&memory.resolve(path, true)) {
+            Some(Node::File { modified, .. }) => {
+                Some(SystemTime::UNIX_EPOCH + Duration::from_secs(*modified))
+            }
+            _ => None,
+        }
+    }
+
+    fn hard_links(&self, path: &Path) -> u64 {
+        self.kind(path).map_or(0, |_| 1)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, true);
+
+        for dir in path.ancestors().collect::<Vec<_>>().into_iter().rev() {
+            match memory.node(dir) {
+                Some(Node::Dir) => continue,
+                Some(_) => return Err(io::ErrorKind::AlreadyExists.into()),
+                None => {
+                    memory.writable(dir)?;
+                    memory.nodes.insert(dir.to_path_buf(), Node::Dir);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, false);
+
+        match memory.node(&path) {
+            Some(Node::Dir) if !memory.is_empty(&path) => {
+                Err(io::ErrorKind::DirectoryNotEmpty.into())
+            }
+            Some(Node::Dir) => memory.remove_all(&path),
+            Some(_) => Err(io::ErrorKind::NotADirectory.into()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn remove_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, false);
+
+        match memory.node(&path) {
+            Some(Node::Dir) => memory.remove_all(&path),
+            Some(_) => Err(io::ErrorKind::NotADirectory.into()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, false);
+
+        match memory.node(&path) {
+            Some(Node::Dir) => Err(io::ErrorKind::IsADirectory.into()),
+            _ => memory.remove_all(&path),
+        }
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let (from, to) = (memory.resolve(from, true), memory.resolve(to, true));
+
+        let contents = match memory.node(&from) {
+            Some(Node::File { contents, .. }) => contents.clone(),
+            Some(_) => return Err(io::ErrorKind::IsADirectory.into()),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+
+        memory.writable(&to)?;
+
+        match memory.node(&to) {
+            Some(Node::Dir) => Err(io::ErrorKind::IsADirectory.into()),
+            _ => {
+                memory.put_file(to, contents);
+                Ok(())
+            }
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let (from, to) = (memory.resolve(from, false), memory.resolve(to, false));
+
+        memory.writable(&from)?;
+        memory.writable(&to)?;
+
+        let moved = match memory.node(&from) {
+            Some(node) => node.clone(),
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+
+        match (&moved, memory.node(&to)) {
+            _ if from == to => return Ok(()),
+            _ if to.starts_with(&from) => return Err(io::ErrorKind::InvalidInput.into()),
+            (Node::Dir, Some(Node::Dir)) if !memory.is_empty(&to) => {
+                return Err(io::ErrorKind::DirectoryNotEmpty.into())
+            }
+            (Node::Dir, Some(Node::Dir)) | (_, None) => {}
+            (Node::Dir, Some(_)) => return Err(io::ErrorKind::NotADirectory.into()),
+            (_, Some(Node::Dir)) => return Err(io::ErrorKind::IsADirectory.into()),
+            (_, Some(_)) => {}
+        }
+
+        let inside = memory
+            .nodes
+            .keys()
+            .filter(|key| key.starts_with(&from))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        memory.nodes.remove(&to);
+
+        for path in inside {
+            let node = memory.nodes.remove(&path).unwrap();
+            let rest = path.strip_prefix(&from).unwrap();
+
+            memory.nodes.insert(to.join(rest), node);
+        }
+
+        Ok(())
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> io::Result<()> {
+        let mut memory = self.lock();
+        let link = memory.resolve(link, false);
+
+        memory.writable(&link)?;
+
+        match memory.node(&link) {
+            Some(_) => Err(io::ErrorKind::AlreadyExists.into()),
+            None => {
+                memory
+                    .nodes
+                    .insert(link, Node::Symlink(target.to_path_buf()));
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_link(&self, link: &Path) -> io::Result<()> {
+        self.remove_file(link)
+    }
+
+    fn delete(&self, path: &Path, _trash: Option<&dyn Trash>) -> io::Result<()> {
+        let mut memory = self.lock();
+        let path = memory.resolve(path, false);
+
+        memory.remove_all(&path)
+    }
+}
+
+/// Helper function to normalize `path` without following any symlinks, i.e. to drop `.` and resolve `..`.
+#[cfg(any(test, feature = "testing"))]
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::from("/");
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(name) => normalized.push(name),
+            _ => {}
+        }
+    }
+
+    normalized
+}
@@ -0,0 +1,296 @@
+use crate::{
+    errors::{io::IoError, Result},
+    lock, platform,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The name of the manifest in the repository, which records the metadata of the managed files that git doesn't
+/// keep.
+pub const MANIFEST_FILE: &str = ".dotbak-metadata.toml";
+
+/// The name of the file the metadata of the managed files on this machine is stored in, as of the last time it was
+/// recorded or restored, next to the configuration file. See `merge` and `to_restore` for what it's for.
+pub const KNOWN_FILE_NAME: &str = "metadata.toml";
+
+/// Extended attributes in these namespaces need privileges to be set, so they're never recorded.
+const PRIVILEGED_XATTRS: &[&str] = &["security.", "system.", "trusted."];
+
+/// The metadata of managed files (and the files in managed folders), by their path relative to the home directory.
+/// Git only keeps whether a file is executable, so the rest (e.g. `600` on `.ssh` files) is recorded here.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Manifest {
+    /// The metadata of the files/folders.
+    files: BTreeMap<PathBuf, Metadata>,
+}
+
+/// The metadata of a file/folder.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// The permission bits, e.g. `0o600`. They're written in octal.
+    #[serde(with = "octal")]
+    pub mode: u32,
+
+    /// The extended attributes, with their values in hex. Only recorded if `files.xattrs` is set.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Loads the manifest from the file at `path`. If it doesn't exist, the manifest is empty.
+    pub fn load<P>(path: P) -> Result<Manifest>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(toml::from_str(&contents)?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(err) => Err(IoError::Read {
+                source: err,
+                path: path.to_path_buf(),
+            }
+            .into()),
+        }
+    }
+
+    /// Saves the manifest to the file at `path`. The file is removed if the manifest is empty.
+    pub fn save<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        if self.files.is_empty() {
+            return match fs::remove_file(path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(IoError::Delete {
+                    source: err,
+                    path: path.to_path_buf(),
+                }
+                .into()),
+                _ => Ok(()),
+            };
+        }
+
+        lock::write_atomic(path, toml::to_string_pretty(self)?)
+    }
+
+    /// Gets the metadata of the file/folder at `path`.
+    pub fn get<P>(&self, path: P) -> Option<&Metadata>
+    where
+        P: AsRef<Path>,
+    {
+        self.files.get(path.as_ref())
+    }
+
+    /// Forgets the files/folders for which `keep` returns `false`.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Path) -> bool,
+    {
+        self.files.retain(|path, _| keep(path));
+    }
+
+    /// Iterates over the files/folders and their metadata, sorted by path.
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &Metadata)> {
+        self.files.iter()
+    }
+
+    /// Records the metadata of the file/folder `path` (relative to the home directory), whose contents are at
+    /// `full_path`, and of every file/folder in it. Extended attributes are only recorded if `xattrs` is set.
+    /// Symlinks and paths that aren't UTF-8 are skipped, as is everything on Windows, which has no permission bits.
+    pub fn scan<P1, P2>(&mut self, path: P1, full_path: P2, xattrs: bool)
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let (path, full_path) = (path.as_ref(), full_path.as_ref());
+        let Ok(meta) = fs::symlink_metadata(full_path) else {
+            return;
+        };
+
+        if meta.is_symlink() || path.to_str().is_none() {
+            return;
+        }
+
+        let Some(mode) = platform::permission_bits(&meta) else {
+            return;
+        };
+
+        let metadata = Metadata {
+            mode,
+            xattrs: match xattrs {
+                true => read_xattrs(full_path),
+                false => BTreeMap::new(),
+            },
+        };
+
+        self.files.insert(path.to_path_buf(), metadata);
+
+        if meta.is_dir() {
+            for entry in fs::read_dir(full_path).into_iter().flatten().flatten() {
+                self.scan(path.join(entry.file_name()), entry.path(), xattrs);
+            }
+        }
+    }
+}
+
+impl Metadata {
+    /// Whether git keeps this metadata by itself, i.e. the mode is `644` or `755` and there are no extended
+    /// attributes.
+    pub fn git_keeps(&self) -> bool {
+        matches!(self.mode, 0o644 | 0o755) && self.xattrs.is_empty()
+    }
+
+    /// Applies the metadata to the file/folder at `path`. Extended attributes the file/folder has, but that aren't
+    /// recorded, are left alone.
+    pub fn apply<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        platform::set_permission_bits(path, self.mode).map_err(|err| IoError::Write {
+            source: err,
+            path: path.to_path_buf(),
+        })?;
+
+        for (name, value) in &self.xattrs {
+            let Some(value) = decode_hex(value) else {
+                continue;
+            };
+
+            xattr::set(path, name, &value).map_err(|err| IoError::Write {
+                source: err,
+                path: path.to_path_buf(),
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Merges the metadata `current` of the managed files on this machine into `manifest`, the one from the repository.
+/// `known` is the metadata on this machine as of the last time it was recorded or restored. Metadata that changed
+/// on this machine since then is taken from `current`, and everything else is kept from `manifest`, as it might
+/// have been changed by other machines (and is restored by `to_restore`). Paths that aren't in `current` anymore
+/// are dropped.
+pub fn merge(manifest: &Manifest, known: &Manifest, current: &Manifest) -> Manifest {
+    let files = current
+        .iter()
+        .map(|(path, metadata)| {
+            let unchanged = known.get(path).is_none_or(|known| known == metadata);
+
+            match manifest.get(path) {
+                Some(recorded) if unchanged => (path.clone(), recorded.clone()),
+                _ => (path.clone(), metadata.clone()),
+            }
+        })
+        .collect();
+
+    Manifest { files }
+}
+
+/// Finds the paths whose metadata in `manifest` (the one from the repository) should be restored, i.e. which differs
+/// from the metadata `current` on this machine, and didn't change on this machine since `known` (see `merge`).
+/// Paths that were never recorded or restored on this machine (e.g. right after cloning) are always restored.
+pub fn to_restore<'a>(
+    manifest: &'a Manifest,
+    known: &Manifest,
+    current: &Manifest,
+) -> Vec<(&'a PathBuf, &'a Metadata)> {
+    manifest
+        .iter()
+        .filter(|(path, recorded)| match current.get(path) {
+            Some(metadata) => {
+                metadata != *recorded && known.get(path).is_none_or(|known| known == metadata)
+            }
+            None => false,
+        })
+        .collect()
+}
+
+/// Updates `known` (see `merge`) after restoring the paths `restored`, with `current` being the metadata on this
+/// machine afterwards. Changes on this machine that weren't recorded yet are kept as changes, so that they're still
+/// recorded with the next commit.
+pub fn update_known(known: &Manifest, current: &Manifest, restored: &[&PathBuf]) -> Manifest {
+    let files = current
+        .iter()
+        .map(|(path, metadata)| match known.get(path) {
+            Some(known) if !restored.contains(&path) => (path.clone(), known.clone()),
+            _ => (path.clone(), metadata.clone()),
+        })
+        .collect();
+
+    Manifest { files }
+}
+
+/// Helper function to read the extended attributes of the file/folder at `path`, with their values in hex. Ones that
+/// can't be read, or need privileges to be set, are skipped.
+fn read_xattrs(path: &Path) -> BTreeMap<String, String> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return BTreeMap::new();
+    }
+
+    xattr::list(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|name| name.into_string().ok())
+        .filter(|name| {
+            !PRIVILEGED_XATTRS
+                .iter()
+                .any(|prefix| name.starts_with(prefix))
+        })
+        .filter_map(|name| {
+            let value = xattr::get(path, &name).ok()??;
+
+            Some((name, encode_hex(&value)))
+        })
+        .collect()
+}
+
+/// Helper function to encode `bytes` in hex.
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Helper function to decode the hex string `hex`, or `None` if it isn't valid hex.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// (De)serializes permission bits as octal strings, e.g. `"600"`, like `chmod` takes them.
+mod octal {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    /// Serializes `mode` as an octal string.
+    pub fn serialize<S>(mode: &u32, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{:o}", mode))
+    }
+
+    /// Deserializes an octal string into permission bits.
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<u32, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mode = String::deserialize(deserializer)?;
+
+        u32::from_str_radix(&mode, 8).map_err(D::Error::custom)
+    }
+}
@@ -1,12 +1,34 @@
+pub mod compare;
+pub mod encrypt;
+pub mod escalate;
+pub mod filesystem;
+pub mod metadata;
 mod tests;
+pub mod variant;
 
-use crate::errors::{io::IoError, Result};
+use crate::{
+    config::files::{HardlinkPolicy, Root},
+    errors::{config::ConfigError, encryption::EncryptionError, io::IoError, Result},
+    packages,
+    paths::{self, ManagedPath},
+    platform::{self, SystemTrash, Trash},
+};
+use age::x25519::Identity;
+use escalate::{Escalator, Operation};
+use filesystem::{FileKind, FileSystem, RealFileSystem};
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder},
+    WalkBuilder,
+};
 use itertools::Itertools;
+use rayon::prelude::*;
 use std::{
+    collections::HashMap,
     fs,
-    os::unix::fs as unix_fs,
     path::{Path, PathBuf},
+    sync::Arc,
 };
+use variant::Machine;
 
 /// This structure is used to manage the files/folders that `dotbak` is tracking. This does NOT manage the git repository,
 /// but instead is responsible for organizing, maintaining, and updating the files/folders and their symlinks.
@@ -18,13 +40,563 @@ pub struct Files {
     /// The path to the directory that contains the files/folders. This is where all the symlinks to the files/folders
     /// in `home_dir` originate from.
     file_dir: PathBuf,
+
+    /// What to do with files/folders in `home_dir` that have other hard links to them.
+    hardlinks: HardlinkPolicy,
+
+    /// The files that are stored encrypted in `file_dir` (as `<file>.age`), and as real files in `home_dir`.
+    encrypted: Vec<PathBuf>,
+
+    /// The key to encrypt and decrypt `encrypted` with, if there is one.
+    key: Option<Identity>,
+
+    /// Where the key should be, for telling the user when it's missing.
+    key_path: PathBuf,
+
+    /// Whether files are copied into `home_dir` instead of symlinked, because symlinks to files can't be created
+    /// (see `platform::can_symlink_files`). Changes to the copies are copied back into `file_dir` when syncing, and
+    /// the other way around. Folders are always linked.
+    copies: bool,
+
+    /// Whether files/folders are deleted permanently, instead of being moved to `trash`. See `platform::delete`.
+    permanent: bool,
+
+    /// Where deleted files/folders are moved to, unless they're deleted permanently. See `set_trash`.
+    trash: Arc<dyn Trash>,
+
+    /// The files/folders inside of managed folders that aren't managed. Folders with excluded paths inside of them
+    /// aren't linked as a whole, but each of their children is. See `expand_excluded`.
+    excluded: Vec<PathBuf>,
+
+    /// Where the progress of `sync` is reported to, if anywhere.
+    progress: Option<Box<dyn Progress>>,
+
+    /// The machine that the variants of files/folders in `file_dir` are picked for. See `variant`.
+    machine: Machine,
+
+    /// The folders outside of `home_dir` whose files/folders are managed too. Their files/folders are given by their
+    /// absolute paths, which `home_dir.join` keeps as they are, and are kept in their root's target folder in
+    /// `file_dir`. See `repo_path`.
+    roots: Vec<Root>,
+
+    /// What runs the operations on files/folders in `roots` that fail for lack of permissions again, with more of
+    /// them. See `Root::escalate`.
+    escalator: Escalator,
+
+    /// The filesystem that files/folders are linked, moved and deleted on. See `set_file_system`.
+    filesystem: Arc<dyn FileSystem>,
+
+    /// The files/folders git ignores in `file_dir` (see `set_ignored`), like caches inside of managed folders.
+    ignored: Gitignore,
+}
+
+/// Receives the progress of `Files::sync`, e.g. to show it to the user. See `Files::set_progress`.
+pub trait Progress {
+    /// Called when starting to sync the file/folder `file` (relative to `home_dir`).
+    fn start(&self, file: &Path);
+
+    /// Called when done syncing `file`, whether it succeeded or not.
+    fn finish(&self, file: &Path);
+}
+
+/// The state of the symlink in `home_dir` for a file/folder in `file_dir`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    /// The symlink points to the file/folder in `file_dir`, which exists.
+    Intact,
+
+    /// The symlink points into `file_dir`, but the file/folder isn't there.
+    Broken,
+
+    /// There is nothing at the path in `home_dir`.
+    Missing,
+
+    /// There is a real file/folder (or a symlink to somewhere else) at the path in `home_dir`.
+    Shadowed,
+}
+
+impl LinkState {
+    /// Describes the state for humans.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            LinkState::Intact => "intact",
+            LinkState::Broken => "broken, the file is missing from the repository",
+            LinkState::Missing => "missing from the home directory",
+            LinkState::Shadowed => "shadowed by a file that isn't managed",
+        }
+    }
+}
+
+/// How `Files::walk_dir` walks a folder.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WalkConfig {
+    /// How deep to walk: `Some(1)` only yields the children of the folder. `None` walks everything.
+    pub max_depth: Option<usize>,
+
+    /// Whether to walk into the folders that symlinks point to. The symlinks themselves are yielded either way.
+    pub follow_links: bool,
+
+    /// Whether to skip the files/folders that `.gitignore` files in the walked folders ignore.
+    pub gitignore: bool,
+
+    /// The files/folders to skip, in `.gitignore` syntax (relative to the walked folder), e.g. `*.swp` or `cache/`.
+    pub ignore: Vec<String>,
+}
+
+/// What to do with the orphaned files in `file_dir`. See `orphans`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PruneMode {
+    /// Move them back into `home_dir` (decrypting encrypted ones), like removing a managed file does.
+    Restore,
+
+    /// Delete them.
+    Delete,
 }
 
 /// Public API for `Files`.
 impl Files {
     /// Create a new instance of `Files`.
     pub fn init(home_dir: PathBuf, file_dir: PathBuf) -> Self {
-        Self { home_dir, file_dir }
+        Self {
+            home_dir,
+            file_dir,
+            hardlinks: HardlinkPolicy::default(),
+            encrypted: vec![],
+            key: None,
+            key_path: PathBuf::new(),
+            copies: false,
+            permanent: false,
+            trash: Arc::new(SystemTrash),
+            excluded: vec![],
+            progress: None,
+            machine: Machine::default(),
+            roots: vec![],
+            escalator: Escalator::default(),
+            filesystem: Arc::new(RealFileSystem),
+            ignored: Gitignore::empty(),
+        }
+    }
+
+    /// Sets what to do with files/folders in `home_dir` that have other hard links to them.
+    pub fn set_hardlink_policy(&mut self, hardlinks: HardlinkPolicy) {
+        self.hardlinks = hardlinks;
+    }
+
+    /// Sets the files that are stored encrypted, and the key (which should be at `key_path`) to encrypt them with.
+    pub fn set_encryption(
+        &mut self,
+        encrypted: Vec<PathBuf>,
+        key: Option<Identity>,
+        key_path: PathBuf,
+    ) {
+        self.encrypted = encrypted;
+        self.key = key;
+        self.key_path = key_path;
+    }
+
+    /// Sets whether files are copied into `home_dir` instead of symlinked.
+    pub fn set_copies(&mut self, copies: bool) {
+        self.copies = copies;
+    }
+
+    /// Sets whether files/folders are deleted permanently, instead of being moved to the trash.
+    pub fn set_permanent_delete(&mut self, permanent: bool) {
+        self.permanent = permanent;
+    }
+
+    /// Sets where deleted files/folders are moved to, which is the system trash until then.
+    pub fn set_trash(&mut self, trash: Arc<dyn Trash>) {
+        self.trash = trash;
+    }
+
+    /// Sets where the progress of `sync` is reported to, or stops reporting it if `progress` is `None`.
+    pub fn set_progress(&mut self, progress: Option<Box<dyn Progress>>) {
+        self.progress = progress;
+    }
+
+    /// Sets the machine that the variants of files/folders are picked for. Until it's set, no variant is picked.
+    pub fn set_machine(&mut self, machine: Machine) {
+        self.machine = machine;
+    }
+
+    /// Sets the folders outside of `home_dir` whose files/folders are managed too.
+    pub fn set_roots(&mut self, roots: Vec<Root>) {
+        self.escalator = Escalator::new(roots.clone(), self.file_dir.clone());
+        self.roots = roots;
+    }
+
+    /// Sets the filesystem that files/folders are linked, moved and deleted on, which is the one on the disk until
+    /// then. Encrypting, comparing, walking and globbing files/folders always happens on the disk.
+    pub fn set_file_system(&mut self, filesystem: Arc<dyn FileSystem>) {
+        self.filesystem = filesystem;
+    }
+
+    /// Sets the patterns of the files/folders that git ignores (see `ignore.patterns`), in `.gitignore` syntax relative
+    /// to `file_dir`. Walking managed folders skips them, see `is_ignored`.
+    pub fn set_ignored(&mut self, patterns: &[String]) -> Result<()> {
+        self.ignored = ignore_matcher(&self.file_dir, patterns)?;
+
+        Ok(())
+    }
+
+    /// Sets the files/folders inside of managed folders that aren't managed.
+    pub fn set_excluded(&mut self, excluded: Vec<PathBuf>) {
+        self.excluded = excluded;
+    }
+
+    /// Checks if `file` is excluded, or inside of an excluded folder.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn is_excluded<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        self.excluded
+            .iter()
+            .any(|excluded| file.as_ref().starts_with(excluded))
+    }
+
+    /// Checks if `file` is ignored (see `set_ignored`), or inside of an ignored folder. Such files/folders are never
+    /// committed, so they aren't picked up when walking managed folders.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn is_ignored<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let file = self.repo_relative(&file);
+        let is_dir = self.filesystem.is_dir(&self.file_dir.join(&file));
+
+        !file.as_os_str().is_empty()
+            && file.is_relative()
+            && self
+                .ignored
+                .matched_path_or_any_parents(&file, is_dir)
+                .is_ignore()
+    }
+
+    /// Splits the folders in `files` with excluded paths inside of them into their children (in `home_dir` and in
+    /// `file_dir`), recursively, leaving out the excluded ones. These are the files/folders that are linked. Other
+    /// files/folders are kept as they are.
+    ///
+    /// `files` are the paths to the file in `home_dir`. These paths must be relative to `home_dir`.
+    pub fn expand_excluded<P>(&self, files: &[P]) -> Vec<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        files
+            .iter()
+            .flat_map(|file| {
+                let file = file.as_ref();
+
+                if self.is_excluded(file) {
+                    vec![]
+                } else if self.has_excluded_inside(file) {
+                    self.expand_excluded(&self.children(file))
+                } else {
+                    vec![file.to_path_buf()]
+                }
+            })
+            .collect()
+    }
+
+    /// Walks the folder `root` as configured by `config`, and returns the paths of the files/folders in it (but not
+    /// `root` itself), relative to `root` and sorted. git's own `.git` folders are never walked. Files/folders that
+    /// can't be read are skipped.
+    pub fn walk_dir<P>(root: P, config: &WalkConfig) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let root = root.as_ref();
+        let ignored = ignore_matcher(root, &config.ignore)?;
+
+        let walk = WalkBuilder::new(root)
+            .standard_filters(false)
+            .git_ignore(config.gitignore)
+            .require_git(false)
+            .follow_links(config.follow_links)
+            .max_depth(config.max_depth)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+
+                entry.file_name() != ".git"
+                    && !ignored
+                        .matched_path_or_any_parents(entry.path(), is_dir)
+                        .is_ignore()
+            })
+            .build();
+
+        Ok(walk
+            .flatten()
+            .filter_map(|entry| {
+                entry
+                    .path()
+                    .strip_prefix(root)
+                    .ok()
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .map(Path::to_path_buf)
+            })
+            .sorted()
+            .collect())
+    }
+
+    /// Measures how much moving `files` from `home_dir` into `file_dir` would add to it: the sizes of the files, and
+    /// of the children of the folders (leaving out excluded and ignored files), largest first. Symlinks aren't
+    /// followed, and files/folders that add nothing (e.g. because they're managed already) are left out.
+    ///
+    /// `files` are the paths to the files in `home_dir`. These paths must be relative to `home_dir`.
+    pub fn sizes<P>(&self, files: &[P]) -> Result<Vec<(PathBuf, u64)>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut sizes = HashMap::<PathBuf, u64>::new();
+        let size_of = |path: &Path| {
+            fs::symlink_metadata(path)
+                .ok()
+                .filter(|meta| meta.is_file())
+                .map_or(0, |meta| meta.len())
+        };
+
+        for file in files.iter().map(AsRef::as_ref) {
+            let full_path = self.home_dir.join(file);
+
+            if self.is_managed_in_home(file) {
+                continue;
+            } else if !fs::symlink_metadata(&full_path).is_ok_and(|meta| meta.is_dir()) {
+                sizes.insert(file.to_path_buf(), size_of(&full_path));
+                continue;
+            }
+
+            for path in Files::walk_dir(&full_path, &WalkConfig::default())? {
+                let (child, path) = (file.join(path.iter().next().unwrap()), file.join(&path));
+
+                if !self.is_excluded(&path) && !self.is_ignored(&path) {
+                    *sizes.entry(child).or_default() += size_of(&self.home_dir.join(&path));
+                }
+            }
+        }
+
+        Ok(sizes
+            .into_iter()
+            .filter(|(_, size)| *size > 0)
+            .sorted_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)))
+            .collect())
+    }
+
+    /// Resolves the glob patterns among `include` (see `paths::is_pattern`), e.g. `.config/*.toml`, into the
+    /// files/folders they match in `home_dir` and in `file_dir`, where `*` doesn't match `/` (but `**` does). Matches
+    /// inside of other matches are left out, as they're managed with them, and so are ignored ones (see
+    /// `is_ignored`). Plain paths are kept as they are.
+    ///
+    /// `include` are the paths to the files in `home_dir`. These paths must be relative to `home_dir`.
+    pub fn resolve_globs<P>(&self, include: &[P]) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut resolved: Vec<PathBuf> = vec![];
+
+        for entry in include {
+            let entry = entry.as_ref();
+
+            if !paths::is_pattern(entry) {
+                resolved.push(entry.to_path_buf());
+                continue;
+            }
+
+            let matcher = paths::glob_set(&[entry.to_string_lossy()])?;
+
+            // Only the folder before the first component with a pattern in it needs to be walked, and only as deep as
+            // the pattern goes unless it has `**` in it.
+            let prefix = entry
+                .components()
+                .take_while(|component| !paths::is_pattern(component))
+                .collect::<PathBuf>();
+            let config = WalkConfig {
+                max_depth: match entry.to_string_lossy().contains("**") {
+                    true => None,
+                    false => Some(entry.components().count() - prefix.components().count()),
+                },
+                ..WalkConfig::default()
+            };
+
+            let mut matches = vec![];
+
+            for (root, in_home) in [
+                (self.home_dir.join(&prefix), true),
+                (self.repo_path(&prefix), false),
+            ] {
+                if !root.is_dir() {
+                    continue;
+                }
+
+                // Variants (see `variant`) are managed under the name of the file/folder they're a variant of.
+                matches.extend(
+                    Files::walk_dir(&root, &config)?
+                        .into_iter()
+                        .map(|path| variant::base(prefix.join(path)))
+                        .filter(|path| matcher.is_match(path) && !self.is_ignored(path))
+                        .filter(|path| in_home || !is_repo_file(path)),
+                );
+            }
+
+            resolved.extend(matches);
+        }
+
+        let resolved = resolved.into_iter().unique().collect_vec();
+
+        Ok(resolved
+            .iter()
+            .filter(|path| {
+                !resolved
+                    .iter()
+                    .any(|other| path.starts_with(other) && path != &other)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// Checks if `file` is stored encrypted.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn is_encrypted<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        self.encrypted
+            .iter()
+            .any(|encrypted| encrypted == file.as_ref())
+    }
+
+    /// Gets the full path to the contents of `file`: the decrypted file in `home_dir` if it's encrypted, and the file
+    /// in `file_dir` otherwise (which `home_dir` links to).
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn contents_path<P>(&self, file: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        match self.is_encrypted(&file) {
+            true => self.home_dir.join(file),
+            false => self.file_dir.join(self.resolve_variant(file)),
+        }
+    }
+
+    /// Finds the variant of `file` in `file_dir` for this machine (see `set_machine`): the file/folder next to it
+    /// named `<name>##<conditions>`, e.g. `.gitconfig##hostname.work-laptop` or `.zshrc##os.Darwin`, with the most
+    /// specific conditions that the machine matches (see `Machine::specificity`). If several are just as specific,
+    /// the first one by name wins.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    ///
+    /// Returns the path to the variant, relative to `file_dir`, or `None` if there is none for this machine.
+    pub fn variant<P>(&self, file: P) -> Option<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let file = self.repo_relative(file);
+        let name = file.file_name()?.to_str()?;
+        let parent = file.parent().unwrap_or(Path::new(""));
+
+        self.filesystem
+            .read_dir(&self.file_dir.join(parent))
+            .ok()?
+            .into_iter()
+            .filter_map(|entry| {
+                let variant = entry.into_string().ok()?;
+                let (base, conditions) = variant::split(&variant)?;
+                let specificity = match base == name {
+                    true => self.machine.specificity(conditions)?,
+                    false => return None,
+                };
+
+                Some((specificity, variant))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0).then_with(|| b.1.cmp(&a.1)))
+            .map(|(_, variant)| parent.join(variant))
+    }
+
+    /// Gets the path to the file/folder in `file_dir` that is linked to `file` in `home_dir`, relative to `file_dir`:
+    /// its variant for this machine if it has one (see `variant`), and `file` itself (see `repo_relative`)
+    /// otherwise.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn resolve_variant<P>(&self, file: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.variant(&file)
+            .unwrap_or_else(|| self.repo_relative(file))
+    }
+
+    /// The directory where all the files/folders are symlinked to (the user's home directory).
+    pub fn home_dir(&self) -> &Path {
+        &self.home_dir
+    }
+
+    /// The folders outside of `home_dir` whose files/folders are managed too.
+    pub fn roots(&self) -> &[Root] {
+        &self.roots
+    }
+
+    /// The absolute paths of the folders outside of `home_dir` whose files/folders are managed too.
+    pub fn root_paths(&self) -> Vec<PathBuf> {
+        self.roots.iter().map(|root| root.path.clone()).collect()
+    }
+
+    /// Gets the path of `file` in `file_dir`, relative to it: `file` itself if it's in `home_dir`, and the path
+    /// inside of its root's target folder if it's in one of the roots (see `set_roots`), e.g. `@etc/hosts` for
+    /// `/etc/hosts`. Variants aren't picked (see `resolve_variant`).
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`, or absolute if it's in
+    /// one of the roots.
+    pub fn repo_relative<P>(&self, file: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        repo_relative(&self.roots, file.as_ref())
+    }
+
+    /// Undoes `repo_relative`: gets the path that the file at `path` in `file_dir` (relative to it) is managed as,
+    /// i.e. its absolute path if it's in one of the roots' target folders, and `path` itself otherwise.
+    pub fn managed_relative<P>(&self, path: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+
+        self.roots
+            .iter()
+            .find_map(|root| {
+                path.strip_prefix(root.target())
+                    .ok()
+                    .map(|rest| root.path.join(rest))
+            })
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// Gets the full path to `file` in `file_dir` (see `repo_relative`).
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`, or absolute if it's in
+    /// one of the roots.
+    pub fn repo_path<P>(&self, file: P) -> PathBuf
+    where
+        P: AsRef<Path>,
+    {
+        self.file_dir.join(self.repo_relative(file))
+    }
+
+    /// Gets `path` (given by the user, e.g. on the command line) the way it's stored in `files.include`, see
+    /// `paths::ManagedPath`.
+    pub fn managed_path<P>(&self, path: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        ManagedPath::new(path, &self.home_dir, &self.root_paths()).map(PathBuf::from)
+    }
+
+    /// The directory that contains the files/folders (the repository).
+    pub fn file_dir(&self) -> &Path {
+        &self.file_dir
     }
 
     /// Check if a file is managed by `dotbak` in the home directory. This will check if the file is a symlink and if
@@ -37,39 +609,65 @@ impl Files {
     where
         P: AsRef<Path>,
     {
+        if self.is_copy_in_home(&file) {
+            return true;
+        }
+
         // Get the full paths to the file in `home_dir`.
         let home_path = self.home_dir.join(file);
 
-        // Check if the file in `home_dir` is a symlink.
-        fs::symlink_metadata(&home_path)
-            .and_then(|meta| match meta.file_type().is_symlink() {
-                // If it's a symlink, then check if it's symlinked to `file_dir`.
-                true => {
-                    // Get the path that the symlink points to.
-                    let symlink_path = fs::read_link(&home_path)?;
+        // Check if the file in `home_dir` is a symlink, and if it points to `file_dir`. If it's not a symlink, then we
+        // need to move the file.
+        self.filesystem
+            .read_link(&home_path)
+            .is_ok_and(|symlink_path| symlink_path.starts_with(&self.file_dir))
+    }
+
+    /// Gets the state of the symlink in `home_dir` for `file`, without changing anything.
+    ///
+    /// `file` is the path to the file in `file_dir`. This path must be relative to `file_dir`.
+    pub fn link_state<P>(&self, file: P) -> LinkState
+    where
+        P: AsRef<Path>,
+    {
+        let home_path = self.home_dir.join(&file);
 
-                    // Check if the symlink points to `file_dir`.
-                    Ok(symlink_path.starts_with(&self.file_dir))
+        if self.is_encrypted(&file) {
+            return match self.filesystem.kind(&home_path) == Some(FileKind::File) {
+                false if self.filesystem.kind(&home_path).is_none() => LinkState::Missing,
+                false => LinkState::Shadowed,
+                true if self
+                    .filesystem
+                    .exists(&self.repo_path(encrypt::encrypted_path(&file))) =>
+                {
+                    LinkState::Intact
                 }
+                true => LinkState::Broken,
+            };
+        }
 
-                // If it's not a symlink, then we need to move the file.
-                false => Ok(false),
-            })
-            // If it's not a symlink, then we need to move the file.
-            .unwrap_or(false)
+        if self.filesystem.kind(&home_path).is_none() {
+            LinkState::Missing
+        } else if !self.is_managed_in_home(&file) {
+            LinkState::Shadowed
+        } else if self.is_managed_in_repo(&file) {
+            LinkState::Intact
+        } else {
+            LinkState::Broken
+        }
     }
 
-    /// Check if a file is managed by `dotbak` and is in the `file_dir`. This will NOT check if the file is a symlink and
-    /// if it's symlinked to `file_dir`.
+    /// Check if a file is managed by `dotbak` and is in the `file_dir`, or has a variant for this machine there (see
+    /// `variant`). This will NOT check if the file is a symlink and if it's symlinked to `file_dir`.
     pub fn is_managed_in_repo<P>(&self, file: &P) -> bool
     where
         P: AsRef<Path>,
     {
         // Get the full paths to the file in `file_dir`.
-        let repo_path = self.file_dir.join(file);
+        let repo_path = self.file_dir.join(self.resolve_variant(file));
 
         // Check if the file in `file_dir` exists.
-        repo_path.exists()
+        self.filesystem.exists(&repo_path)
     }
 
     /// Move a file/folder from `home_dir` to `file_dir` and symlink it back to `home_dir`. If the file is already
@@ -78,194 +676,1429 @@ impl Files {
     /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
     ///
     /// Note that this creates the exact same file structure in `file_dir` as in `home_dir`. So if `file` is
-    /// `[/home/user/.config/foo/bar]`, then the file will be moved to `/home/user/.dotbak/dotfiles/config/foo/bar`
-    /// and symlinked back to `/home/user/.config/foo/bar`, regardless if `file` is a file or a folder. Of course,
-    /// this assumes that `file_dir` is `/home/user/.dotbak/dotfiles`.
+    /// `[/home/user/.config/foo/bar]`, then the file will be moved to
+    /// `/home/user/.local/share/dotbak/dotfiles/config/foo/bar` and symlinked back to `/home/user/.config/foo/bar`,
+    /// regardless if `file` is a file or a folder. Of course, this assumes that `file_dir` is
+    /// `/home/user/.local/share/dotbak/dotfiles`.
     ///
-    /// Returns either an error or `Ok(())`.
-    pub fn move_and_symlink<P>(&self, files: &[P]) -> Result<()>
+    /// Files/folders with other hard links to them are skipped, copied, or moved anyways, depending on the hard
+    /// link policy.
+    ///
+    /// Returns either an error or the files/folders that have other hard links to them.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn move_and_symlink<P>(&self, files: &[P]) -> Result<Vec<PathBuf>>
     where
         P: AsRef<Path>,
     {
-        // Filter out all the files which are already symlinked to `file_dir`.
+        // Folders with excluded paths inside of them are linked child by child.
+        self.split_folders(files)?;
+
+        // Encrypted files aren't moved, but encrypted into `file_dir`.
+        let (encrypted, files): (Vec<_>, Vec<_>) = self
+            .expand_excluded(files)
+            .into_iter()
+            .partition(|file| self.is_encrypted(file));
+
+        for file in &encrypted {
+            self.encrypt_into_repo(file)?;
+        }
+
+        // Changes to copies of files in `home_dir` are copied into `file_dir`.
+        self.refresh_copies(&files, under(&self.home_dir), self.in_repo())?;
+
+        let hardlinked = self.hardlinked_in_home(&files);
+
+        // Copied files are refreshed every time, as the original is where changes happen.
+        if self.hardlinks == HardlinkPolicy::Copy {
+            copy_files(
+                &*self.filesystem,
+                &hardlinked,
+                under(&self.home_dir),
+                self.in_repo(),
+                &self.escalator,
+            )?;
+        }
+
+        // Filter out all the files which are already symlinked to `file_dir`, or which are hardlinked and shouldn't
+        // be moved.
         let files = files
             .iter()
+            .map(|file| AsRef::<Path>::as_ref(file).to_path_buf())
             .filter(|file| !self.is_managed_in_home(file) && !self.is_managed_in_repo(file))
+            .filter(|file| {
+                self.hardlinks == HardlinkPolicy::BreakLinks || !hardlinked.contains(file)
+            })
             .collect_vec();
 
-        // Move the file from `home_dir` to `file_dir`.
-        move_files(&files, &self.home_dir, &self.file_dir)?;
+        // Files in roots that are copied (see `Root::copies`) stay where they are, and are copied into `file_dir`.
+        // Everything else is moved from `home_dir` to `file_dir`.
+        let (copied, files): (Vec<_>, Vec<_>) = files
+            .into_iter()
+            .partition(|file| self.root_of(file).is_some() && self.is_copied(file));
+
+        copy_files(
+            &*self.filesystem,
+            &copied,
+            under(&self.home_dir),
+            self.in_repo(),
+            &self.escalator,
+        )?;
+        move_files(
+            &*self.filesystem,
+            &files,
+            under(&self.home_dir),
+            self.in_repo(),
+            &self.escalator,
+        )?;
+        self.give_back_roots(copied.iter().chain(&files))?;
 
         // Now symlink them back to `home_dir`.
         self.symlink_back_home(&files)?;
 
-        Ok(())
+        Ok(hardlinked)
+    }
+
+    /// Syncs `files` one by one: each file/folder is moved to `file_dir` and symlinked back (see
+    /// `move_and_symlink`), and then symlinked back to `home_dir` if it's in `file_dir` already (see
+    /// `symlink_back_home`). The progress is reported to the `Progress` set with `set_progress`. Stops at the first
+    /// file/folder that fails to sync.
+    ///
+    /// `files` are the paths to the files in `home_dir`. These paths must be relative to `home_dir`.
+    ///
+    /// Returns either an error or the files/folders that have other hard links to them.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn sync<P>(&self, files: &[P]) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut hardlinked = vec![];
+
+        for file in files {
+            let file = file.as_ref();
+
+            if let Some(progress) = &self.progress {
+                progress.start(file);
+            }
+
+            let result = self
+                .move_and_symlink(&[file])
+                .and_then(|linked| self.symlink_back_home(&[file]).map(|_| linked));
+
+            if let Some(progress) = &self.progress {
+                progress.finish(file);
+            }
+
+            hardlinked.extend(result?);
+        }
+
+        Ok(hardlinked)
     }
 
     /// Symlinks the files back to `home_dir`. This will symlink the files from `file_dir` to `home_dir`.
-    /// If the file is already symlinked into `home_dir`, then this will do nothing.
+    /// If the file is already symlinked into `home_dir`, then this will do nothing. Unless hard links are to be
+    /// broken, this also leaves files/folders in `home_dir` that have other hard links to them alone.
     ///
     /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
     ///
     /// Returns either an error or `Ok(())`.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
     pub fn symlink_back_home<P>(&self, files: &[P]) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        // Folders with excluded paths inside of them are linked child by child.
+        self.split_folders(files)?;
+
+        // Encrypted files aren't symlinked, but decrypted into `home_dir`.
+        let (encrypted, files): (Vec<_>, Vec<_>) = self
+            .expand_excluded(files)
+            .into_iter()
+            .partition(|file| self.is_encrypted(file));
+
+        for file in &encrypted {
+            let repo_path = self.repo_path(encrypt::encrypted_path(file));
+
+            if self.filesystem.exists(&repo_path) {
+                encrypt::decrypt_file(&repo_path, self.home_dir.join(file), self.key()?)?;
+            }
+        }
+
+        // Changes to files in `file_dir` (e.g. from a pull) are copied into their copies in `home_dir`.
+        self.refresh_copies(&files, self.in_repo(), under(&self.home_dir))?;
+
+        let hardlinked = match self.hardlinks {
+            HardlinkPolicy::BreakLinks => vec![],
+            _ => self.hardlinked_in_home(&files),
+        };
+
+        // Symlinks to another version of a file than the one picked for this machine (e.g. because a variant for it
+        // was just pulled) are replaced.
+        let stale = files
+            .iter()
+            .filter(|file| self.links_other_variant(file))
+            .collect_vec();
+        delete_files(
+            &*self.filesystem,
+            &stale,
+            under(&self.home_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
+
         // Filter out all the files which are already symlinked to `file_dir`.
         let files = files
             .iter()
+            .map(|file| AsRef::<Path>::as_ref(file).to_path_buf())
             .filter(|file| !self.is_managed_in_home(file) && self.is_managed_in_repo(file))
+            .filter(|file| !hardlinked.contains(file))
             .collect_vec();
 
         // Symlink the files from `file_dir` to `home_dir`.
-        symlink_files(&files, &self.file_dir, &self.home_dir)?;
+        self.link_into_home(&files)?;
 
         Ok(())
     }
 
-    /// Basically undoes `move_and_symlink`. This will move the files/folders from `file_dir` to `home_dir` and
-    /// delete the symlinks in `home_dir`.
+    /// Rewrites the symlinks in `home_dir` that point into `old_file_dir` so that they point into `file_dir`
+    /// instead. This is used after the repository has been moved to a new location.
     ///
     /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
     ///
-    /// Returns either an error or `Ok(())`.
+    /// Returns the files that were not rewritten because their path in `home_dir` is not a symlink into
+    /// `old_file_dir` (e.g. it was replaced by a real file). These are left untouched.
+    pub fn relink<P1, P2>(&self, files: &[P1], old_file_dir: P2) -> Result<Vec<PathBuf>>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let (to_relink, skipped): (Vec<_>, Vec<_>) = files
+            .iter()
+            .filter(|file| !self.is_managed_in_home(file))
+            .partition(|file| {
+                self.filesystem
+                    .read_link(&self.home_dir.join(file))
+                    .is_ok_and(|target| target.starts_with(old_file_dir.as_ref()))
+            });
+
+        // Remove the old symlinks, and then link the files back in from their new location.
+        delete_files(
+            &*self.filesystem,
+            &to_relink,
+            under(&self.home_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
+        self.link_into_home(&to_relink)?;
+
+        Ok(skipped
+            .into_iter()
+            .map(|file| file.as_ref().to_path_buf())
+            .collect())
+    }
+
+    /// Moves the symlink in `home_dir` from `old` to `new`, after the file was renamed from `old` to `new` in
+    /// `file_dir` (e.g. by a pull). The symlink at `old` is only removed if it points into `file_dir`, and the one at
+    /// `new` is only created if nothing is there yet.
+    ///
+    /// `old` and `new` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    pub fn relink_renamed<P1, P2>(&self, old: P1, new: P2) -> Result<()>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        if self.is_managed_in_home(&old) {
+            delete_files(
+                &*self.filesystem,
+                &[&old],
+                under(&self.home_dir),
+                self.trash(),
+                &self.escalator,
+            )?;
+        }
+
+        let home_path = self.home_dir.join(&new);
+
+        if self.filesystem.kind(&home_path).is_none() {
+            let parent = home_path.parent().unwrap();
+
+            self.filesystem
+                .create_dir_all(parent)
+                .map_err(|err| IoError::Create {
+                    source: err,
+                    path: parent.to_path_buf(),
+                })?;
+            self.link_into_home(&[&new])?;
+        }
+
+        Ok(())
+    }
+
+    /// Finds the files which are in `file_dir`, but whose path in `home_dir` has been replaced by something that
+    /// isn't a symlink into `file_dir` (e.g. an OS upgrade wrote a fresh default configuration file there).
+    ///
+    /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    pub fn replaced<P>(&self, files: &[P]) -> Vec<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        self.expand_excluded(files)
+            .into_iter()
+            .filter(|file| {
+                self.filesystem.kind(&self.home_dir.join(file)).is_some()
+                    && !self.is_managed_in_home(file)
+                    && self.is_managed_in_repo(file)
+            })
+            .collect()
+    }
+
+    /// Finds the files which `symlink_back_home` would overwrite with a symlink to a different version: the ones
+    /// that were replaced (see `replaced`) by something with other contents than in `file_dir`. Files with other hard
+    /// links to them are left out, unless hard links are to be broken, as they aren't symlinked.
+    ///
+    /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    pub fn overwritten<P>(&self, files: &[P]) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let replaced = self
+            .replaced(files)
+            .into_iter()
+            .filter(|file| !self.is_encrypted(file))
+            .collect_vec();
+
+        let hardlinked = match self.hardlinks {
+            HardlinkPolicy::BreakLinks => vec![],
+            _ => self.hardlinked_in_home(&replaced),
+        };
+
+        let mut overwritten = vec![];
+
+        for file in replaced {
+            if !hardlinked.contains(&file)
+                && !compare::same_contents(
+                    self.file_dir.join(self.resolve_variant(&file)),
+                    self.home_dir.join(&file),
+                )?
+            {
+                overwritten.push(file);
+            }
+        }
+
+        Ok(overwritten)
+    }
+
+    /// Finds the symlinks in `home_dir` that point into `file_dir`, along with the path in `file_dir` each of them
+    /// points to. Both paths are relative to their folder. Neither `file_dir` nor the folders the symlinks point to
+    /// are walked.
+    pub fn links_into_repo(&self) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut config = WalkConfig::default();
+
+        if let Ok(file_dir) = self.file_dir.strip_prefix(&self.home_dir) {
+            config.ignore.push(format!("/{}/", file_dir.display()));
+        }
+
+        Ok(Files::walk_dir(&self.home_dir, &config)?
+            .into_iter()
+            .filter_map(|path| {
+                let home_path = self.home_dir.join(&path);
+                let target = home_path
+                    .parent()?
+                    .join(self.filesystem.read_link(&home_path).ok()?);
+                let target = target.strip_prefix(&self.file_dir).ok()?.to_path_buf();
+
+                Some((path, target))
+            })
+            .collect())
+    }
+
+    /// Deletes the symlink at `file` in `home_dir`. `file` must be relative to `home_dir`.
+    pub fn remove_link<P>(&self, file: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.home_dir.join(file);
+
+        self.filesystem
+            .remove_link(&path)
+            .map_err(|err| IoError::Delete { source: err, path }.into())
+    }
+
+    /// Moves the file/folder at `file` in `home_dir` out of the way, to `<file>.dotbak-backup` (or
+    /// `<file>.dotbak-backup.<n>` if that already exists).
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    ///
+    /// Returns the full path to the backup.
+    pub fn backup_in_home<P>(&self, file: P) -> Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.home_dir.join(file);
+        let mut backup = PathBuf::from(format!("{}.dotbak-backup", path.display()));
+        let mut n = 1;
+
+        while self.filesystem.kind(&backup).is_some() {
+            backup = PathBuf::from(format!("{}.dotbak-backup.{}", path.display(), n));
+            n += 1;
+        }
+
+        self.filesystem
+            .rename(&path, &backup)
+            .map_err(|err| IoError::Move {
+                source: err,
+                from: path,
+                to: backup.clone(),
+            })?;
+
+        Ok(backup)
+    }
+
+    /// Replaces the copy of `file` in `file_dir` (or its variant for this machine, see `variant`) with the one in
+    /// `home_dir`, and symlinks it back to `home_dir`. This is used to adopt changes made to a file whose symlink was
+    /// replaced by a real file.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn adopt_from_home<P>(&self, file: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let picked = self.resolve_variant(&file);
+        let (home_path, repo_path) = (self.home_dir.join(&file), self.file_dir.join(&picked));
+
+        delete_files(
+            &*self.filesystem,
+            &[&picked],
+            under(&self.file_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
+        self.filesystem
+            .rename(&home_path, &repo_path)
+            .map_err(|err| IoError::Move {
+                source: err,
+                from: home_path,
+                to: repo_path,
+            })?;
+        self.link_into_home(&[&file])?;
+
+        Ok(())
+    }
+
+    /// Merges the edits made to a real file that replaced the symlink of `file` into the copy in `file_dir` (see
+    /// `compare::merge_lines`), and symlinks it back to `home_dir`. Returns whether they could be merged: folders,
+    /// files which are larger than `limit` bytes or binary, and files whose versions both changed the same lines are
+    /// left untouched.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn merge_from_home<P>(&self, file: P, limit: u64) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let file = file.as_ref();
+        let repo_path = self.file_dir.join(self.resolve_variant(file));
+        let home_path = self.home_dir.join(file);
+
+        if !fs::symlink_metadata(&home_path).is_ok_and(|meta| meta.is_file())
+            || !repo_path.is_file()
+            || compare::is_large_or_binary(&repo_path, limit)?
+            || compare::is_large_or_binary(&home_path, limit)?
+        {
+            return Ok(false);
+        }
+
+        let read = |path: &Path| {
+            fs::read_to_string(path).map_err(|err| IoError::Read {
+                source: err,
+                path: path.to_path_buf(),
+            })
+        };
+
+        let Some(merged) = compare::merge_lines(&read(&repo_path)?, &read(&home_path)?) else {
+            return Ok(false);
+        };
+
+        fs::write(&repo_path, merged).map_err(|err| IoError::Write {
+            source: err,
+            path: repo_path.clone(),
+        })?;
+        delete_files(
+            &*self.filesystem,
+            &[file],
+            under(&self.home_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
+        self.link_into_home(&[file])?;
+
+        Ok(true)
+    }
+
+    /// Replaces the symlink at `file` in `home_dir` that points outside of `file_dir` (e.g. one installed by another
+    /// dotfiles manager) with a copy of the file/folder it points to, so it can be added. What it points to is left
+    /// as it is. Returns whether `file` was such a symlink: real files, symlinks into `file_dir` and broken symlinks
+    /// are left untouched.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    pub fn copy_link_target<P>(&self, file: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.home_dir.join(file);
+
+        if !fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_symlink()) {
+            return Ok(false);
+        }
+
+        let Ok(target) = fs::canonicalize(&path) else {
+            return Ok(false);
+        };
+
+        let file_dir = fs::canonicalize(&self.file_dir).unwrap_or(self.file_dir.clone());
+
+        if target.starts_with(&file_dir) {
+            return Ok(false);
+        }
+
+        platform::remove_link(&path).map_err(|err| IoError::Delete {
+            source: err,
+            path: path.clone(),
+        })?;
+
+        if target.is_dir() {
+            let entries = fs::read_dir(&target)
+                .map_err(|err| IoError::Read {
+                    source: err,
+                    path: target.clone(),
+                })?
+                .flatten()
+                .map(|entry| PathBuf::from(entry.file_name()))
+                .collect_vec();
+
+            fs::create_dir_all(&path).map_err(|err| IoError::Create {
+                source: err,
+                path: path.clone(),
+            })?;
+
+            copy_files(
+                &*self.filesystem,
+                &entries,
+                under(&target),
+                under(&path),
+                &self.escalator,
+            )?;
+        } else {
+            fs::copy(&target, &path).map_err(|err| IoError::Copy {
+                source: err,
+                from: target.clone(),
+                to: path.clone(),
+            })?;
+        }
+
+        Ok(true)
+    }
+
+    /// Replaces the symlinks in `home_dir` with copies of the files/folders in `file_dir`, leaving `file_dir` as
+    /// it is. Files whose path in `home_dir` isn't a symlink into `file_dir` are left untouched.
+    ///
+    /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn copy_back_home<P>(&self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let files = files
+            .iter()
+            .filter(|file| self.is_managed_in_home(file))
+            .collect_vec();
+
+        delete_files(
+            &*self.filesystem,
+            &files,
+            under(&self.home_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
+        copy_files(
+            &*self.filesystem,
+            &files,
+            self.in_repo(),
+            under(&self.home_dir),
+            &self.escalator,
+        )?;
+
+        Ok(())
+    }
+
+    /// Moves the orphaned `files` (see `orphans`) from `file_dir` back to `home_dir`, decrypting the encrypted ones,
+    /// and deletes their symlinks in `home_dir` that are left over from when they were managed. Files which have
+    /// something else in the way in `home_dir` are left alone, and returned.
+    ///
+    /// `files` are the paths to the files in `file_dir`. These paths must be relative to `file_dir`.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn restore_orphans<P>(&self, files: &[P]) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut skipped = vec![];
+
+        for file in files {
+            let file = file.as_ref();
+            let target = self.managed_relative(orphan_target(file));
+            self.unlink_orphan(&target)?;
+
+            // Copies in `home_dir` (see `copies`) already are what would be restored.
+            if self.is_copy_in_home(&target) {
+                delete_files(
+                    &*self.filesystem,
+                    &[file],
+                    under(&self.file_dir),
+                    self.trash(),
+                    &self.escalator,
+                )?;
+            } else if self.filesystem.kind(&self.home_dir.join(&target)).is_some() {
+                skipped.push(file.to_path_buf());
+                continue;
+            } else if orphan_target(file) != file {
+                let home_path = self.home_dir.join(&target);
+
+                if let Some(parent) = home_path.parent() {
+                    self.filesystem
+                        .create_dir_all(parent)
+                        .map_err(|err| IoError::Create {
+                            source: err,
+                            path: parent.to_path_buf(),
+                        })?;
+                }
+
+                encrypt::decrypt_file(self.file_dir.join(file), home_path, self.key()?)?;
+                delete_files(
+                    &*self.filesystem,
+                    &[file],
+                    under(&self.file_dir),
+                    self.trash(),
+                    &self.escalator,
+                )?;
+            } else {
+                move_files(
+                    &*self.filesystem,
+                    &[&target],
+                    self.in_repo(),
+                    under(&self.home_dir),
+                    &self.escalator,
+                )?;
+            }
+
+            remove_empty_parents(&*self.filesystem, file, &self.file_dir);
+        }
+
+        Ok(skipped)
+    }
+
+    /// Deletes the orphaned `files` (see `orphans`) from `file_dir`, along with their symlinks in `home_dir` that
+    /// are left over from when they were managed.
+    ///
+    /// `files` are the paths to the files in `file_dir`. These paths must be relative to `file_dir`.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
+    pub fn delete_orphans<P>(&self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        for file in files {
+            self.unlink_orphan(&self.managed_relative(orphan_target(file.as_ref())))?;
+            delete_files(
+                &*self.filesystem,
+                &[file],
+                under(&self.file_dir),
+                self.trash(),
+                &self.escalator,
+            )?;
+            remove_empty_parents(&*self.filesystem, file.as_ref(), &self.file_dir);
+        }
+
+        Ok(())
+    }
+
+    /// Basically undoes `move_and_symlink`. This will move the files/folders from `file_dir` to `home_dir` and
+    /// delete the symlinks in `home_dir`.
+    ///
+    /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    ///
+    /// Returns either an error or `Ok(())`.
+    #[tracing::instrument(level = "debug", skip_all, fields(files = files.len()))]
     pub fn remove_and_restore<P>(&self, files: &[P]) -> Result<()>
     where
         P: AsRef<Path>,
     {
+        // The decrypted versions of encrypted files already are in `home_dir`. Folders with excluded paths inside of
+        // them are restored child by child, as they're linked.
+        let (encrypted, files): (Vec<_>, Vec<_>) = self
+            .expand_excluded(files)
+            .into_iter()
+            .partition(|file| self.is_encrypted(file));
+        let encrypted = encrypted.iter().map(encrypt::encrypted_path).collect_vec();
+
+        delete_files(
+            &*self.filesystem,
+            &encrypted,
+            self.in_repo(),
+            self.trash(),
+            &self.escalator,
+        )?;
+
         // First, delete all the symlinks in `home_dir`.
-        delete_files(files, &self.home_dir)?;
+        delete_files(
+            &*self.filesystem,
+            &files,
+            under(&self.home_dir),
+            self.trash(),
+            &self.escalator,
+        )?;
 
         // Next, move the files/folders from `file_dir` to `home_dir`.
-        move_files(files, &self.file_dir, &self.home_dir)?;
+        move_files(
+            &*self.filesystem,
+            &files,
+            self.in_repo(),
+            under(&self.home_dir),
+            &self.escalator,
+        )?;
 
         Ok(())
     }
 }
 
-/// Helper function to delete files in `dir`.
-///
-/// `files` contains the files with a path relative to `dir`.
+/// Private API for `Files`.
+impl Files {
+    /// Gets where deleted files/folders are moved to, or `None` if they're deleted permanently.
+    fn trash(&self) -> Option<&dyn Trash> {
+        (!self.permanent).then_some(self.trash.as_ref())
+    }
+
+    /// Encrypts `file` from `home_dir` into `file_dir` (see `encrypt::encrypt_file`). If `file` was managed
+    /// unencrypted until now, its symlink in `home_dir` is replaced by the file itself first, and the unencrypted
+    /// file is removed from `file_dir`.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    fn encrypt_into_repo(&self, file: &Path) -> Result<()> {
+        let (home_path, repo_path) = (self.home_dir.join(file), self.repo_path(file));
+
+        if self.is_managed_in_home(file) && self.is_managed_in_repo(&file) {
+            delete_files(
+                &*self.filesystem,
+                &[file],
+                under(&self.home_dir),
+                self.trash(),
+                &self.escalator,
+            )?;
+            move_files(
+                &*self.filesystem,
+                &[file],
+                self.in_repo(),
+                under(&self.home_dir),
+                &self.escalator,
+            )?;
+        } else if self.filesystem.is_file(&repo_path)
+            && self.filesystem.kind(&home_path) == Some(FileKind::File)
+        {
+            delete_files(
+                &*self.filesystem,
+                &[file],
+                self.in_repo(),
+                self.trash(),
+                &self.escalator,
+            )?;
+        }
+
+        if self.filesystem.exists(&home_path) {
+            encrypt::encrypt_file(
+                &home_path,
+                self.repo_path(encrypt::encrypted_path(file)),
+                self.key()?,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Symlinks `files` from `file_dir` to `home_dir`, or copies them if they're copied (see `is_copied`, folders
+    /// are always symlinked). Files with a variant for this machine are linked to it instead (see
+    /// `resolve_variant`).
+    ///
+    /// `files` are the paths to the file in `file_dir`. These paths must be relative to `file_dir`.
+    fn link_into_home<P>(&self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let picked = files
+            .iter()
+            .map(|file| {
+                let file = file.as_ref();
+                (
+                    file.to_path_buf(),
+                    (self.resolve_variant(file), self.is_copied(file)),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let (file_dir, home_dir, escalator) = (&self.file_dir, &self.home_dir, &self.escalator);
+        let filesystem = &*self.filesystem;
+
+        for_each_file(files, |file| {
+            let (picked, copies) = &picked[file];
+            link_file(
+                filesystem,
+                &file_dir.join(picked),
+                &home_dir.join(file),
+                *copies,
+                escalator,
+            )
+        })
+    }
+
+    /// Checks if `file` in `home_dir` is a symlink to another version of it in `file_dir` (the file itself, or one of
+    /// its variants) than the one picked for this machine (see `resolve_variant`).
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    fn links_other_variant<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let file = file.as_ref();
+
+        self.filesystem
+            .read_link(&self.home_dir.join(file))
+            .is_ok_and(|target| {
+                target != self.file_dir.join(self.resolve_variant(file))
+                    && target
+                        .strip_prefix(&self.file_dir)
+                        .is_ok_and(|target| variant::base(target) == self.repo_relative(file))
+            })
+    }
+
+    /// Checks if there are excluded paths inside of (but not at) `file`.
+    fn has_excluded_inside(&self, file: &Path) -> bool {
+        self.excluded
+            .iter()
+            .any(|excluded| excluded.starts_with(file) && excluded != file)
+    }
+
+    /// Gets the paths of the children of the folder `file` in `home_dir` and in `file_dir`, sorted and without
+    /// duplicates. Returns no children if it isn't a folder in either.
+    ///
+    /// `file` is the path to the folder in `home_dir`. This path must be relative to `home_dir`.
+    fn children(&self, file: &Path) -> Vec<PathBuf> {
+        [self.home_dir.join(file), self.repo_path(file)]
+            .iter()
+            .flat_map(|dir| self.filesystem.read_dir(dir).into_iter().flatten())
+            .map(|name| file.join(name))
+            .sorted()
+            .dedup()
+            .collect()
+    }
+
+    /// Prepares the folders in `files` with excluded paths inside of them to be linked child by child (see
+    /// `expand_excluded`): a symlink linking such a folder as a whole is replaced by a real folder, and the excluded
+    /// paths that are in `file_dir` (because they were managed until now) are moved back into `home_dir`.
+    ///
+    /// `files` are the paths to the file in `home_dir`. These paths must be relative to `home_dir`.
+    fn split_folders<P>(&self, files: &[P]) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        for file in files {
+            let file = file.as_ref();
+
+            if self.is_excluded(file) || !self.has_excluded_inside(file) {
+                continue;
+            }
+
+            let home_path = self.home_dir.join(file);
+
+            if self.filesystem.is_symlink(&home_path) && self.is_managed_in_home(file) {
+                delete_files(
+                    &*self.filesystem,
+                    &[file],
+                    under(&self.home_dir),
+                    self.trash(),
+                    &self.escalator,
+                )?;
+                self.filesystem
+                    .create_dir_all(&home_path)
+                    .map_err(|err| IoError::Create {
+                        source: err,
+                        path: home_path.clone(),
+                    })?;
+            }
+
+            let children = self.children(file);
+            let restored = children
+                .iter()
+                .filter(|child| {
+                    self.is_excluded(child)
+                        && self.filesystem.kind(&self.repo_path(child)).is_some()
+                        && self.filesystem.kind(&self.home_dir.join(child)).is_none()
+                })
+                .collect_vec();
+
+            move_files(
+                &*self.filesystem,
+                &restored,
+                self.in_repo(),
+                under(&self.home_dir),
+                &self.escalator,
+            )?;
+            self.split_folders(&children)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if `file` in `home_dir` is a copy of the one in `file_dir`, i.e. it's copied instead of symlinked (see
+    /// `is_copied`) and both are regular files.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    fn is_copy_in_home<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let is_file = |path: PathBuf| self.filesystem.kind(&path) == Some(FileKind::File);
+
+        self.is_copied(&file)
+            && is_file(self.home_dir.join(&file))
+            && is_file(self.repo_path(&file))
+    }
+
+    /// Checks if `file` is copied into `home_dir` instead of symlinked, because `copies` is set or its root copies
+    /// its files (see `Root::copies`).
+    fn is_copied<P>(&self, file: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        self.copies
+            || self.root_of(&file).is_some_and(|root| {
+                root.copies && !self.filesystem.is_dir(&self.home_dir.join(&file))
+            })
+    }
+
+    /// Gets the root (see `set_roots`) that `file` is in, if it's in one.
+    fn root_of<P>(&self, file: P) -> Option<&Root>
+    where
+        P: AsRef<Path>,
+    {
+        self.roots
+            .iter()
+            .find(|root| file.as_ref().is_absolute() && file.as_ref().starts_with(&root.path))
+    }
+
+    /// Gives the target folders in `file_dir` of the roots that `files` are in back to the user running `dotbak`
+    /// with `sudo` (see `platform::give_back_to_sudo_user`), so that the repository stays theirs.
+    ///
+    /// `files` are the paths to the files in `home_dir`, or absolute if they're in one of the roots.
+    fn give_back_roots<'a, I>(&self, files: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        for root in files
+            .into_iter()
+            .filter_map(|file| self.root_of(file))
+            .unique_by(|root| &root.path)
+        {
+            let path = self.file_dir.join(root.target());
+
+            platform::give_back_to_sudo_user(&path)
+                .map_err(|err| IoError::Write { source: err, path })?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets where files/folders are in `file_dir`, given their paths in `home_dir` (see `repo_path`), for the helper
+    /// functions below.
+    fn in_repo(&self) -> impl Fn(&Path) -> PathBuf + Sync + '_ {
+        let (file_dir, roots) = (&self.file_dir, &self.roots);
+
+        move |file: &Path| file_dir.join(repo_relative(roots, file))
+    }
+
+    /// Copies the copied files among `files` from `from` to `to` (either `home_dir` to `file_dir` or the other way
+    /// around), if the one in `from` changed more recently and differs.
+    ///
+    /// `files` are the paths to the files in `home_dir`, and `from` and `to` get their full paths (see `under` and
+    /// `in_repo`).
+    fn refresh_copies<P, F1, F2>(&self, files: &[P], from: F1, to: F2) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F1: Fn(&Path) -> PathBuf + Sync,
+        F2: Fn(&Path) -> PathBuf + Sync,
+    {
+        for file in files.iter().filter(|file| self.is_copy_in_home(file)) {
+            let (from_path, to_path) = (from(file.as_ref()), to(file.as_ref()));
+
+            if self.filesystem.modified(&to_path) >= self.filesystem.modified(&from_path)
+                || self.filesystem.read(&from_path).ok() == self.filesystem.read(&to_path).ok()
+            {
+                continue;
+            }
+
+            copy_files(&*self.filesystem, &[file], &from, &to, &self.escalator)?;
+        }
+
+        Ok(())
+    }
+
+    /// Gets the key to encrypt and decrypt files with, or an error if there is none.
+    fn key(&self) -> Result<&Identity> {
+        self.key.as_ref().ok_or_else(|| {
+            EncryptionError::NoKey {
+                path: self.key_path.clone(),
+            }
+            .into()
+        })
+    }
+
+    /// Deletes the symlink in `home_dir` pointing into `file_dir` at `file` or one of the folders containing it, if
+    /// there is one. Such symlinks are left over when a managed file is removed from the `include` list by hand.
+    ///
+    /// `file` is the path to the file in `home_dir`. This path must be relative to `home_dir`.
+    fn unlink_orphan(&self, file: &Path) -> Result<()> {
+        let stale = file
+            .ancestors()
+            .filter(|path| !path.as_os_str().is_empty())
+            .map(|path| self.home_dir.join(path))
+            .find(|path| {
+                self.filesystem
+                    .read_link(path)
+                    .is_ok_and(|target| target.starts_with(&self.file_dir))
+            });
+
+        match stale {
+            Some(path) => self
+                .filesystem
+                .remove_link(&path)
+                .map_err(|err| IoError::Delete { source: err, path }.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Finds the files/folders in `home_dir` which have other hard links to them (or, for folders, contain files
+    /// which do). Symlinks are never counted, as moving them doesn't break anything.
+    ///
+    /// `files` are the paths to the file in `home_dir`. These paths must be relative to `home_dir`.
+    fn hardlinked_in_home<P>(&self, files: &[P]) -> Vec<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        files
+            .iter()
+            .filter(|file| has_hardlinks(&*self.filesystem, &self.home_dir.join(file)))
+            .map(|file| file.as_ref().to_path_buf())
+            .collect()
+    }
+}
+
+/// Helper function to check if the file at `path`, or any file inside of the folder at `path`, has other hard links
+/// to it.
+fn has_hardlinks(filesystem: &dyn FileSystem, path: &Path) -> bool {
+    match filesystem.kind(path) {
+        Some(FileKind::File) => filesystem.hard_links(path) > 1,
+        Some(FileKind::Dir) => filesystem.read_dir(path).is_ok_and(|names| {
+            names
+                .iter()
+                .any(|name| has_hardlinks(filesystem, &path.join(name)))
+        }),
+        _ => false,
+    }
+}
+
+/// Helper function to build a matcher for the `.gitignore` patterns `patterns`, relative to the folder `root`.
+fn ignore_matcher(root: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let mut ignored = GitignoreBuilder::new(root);
+
+    for pattern in patterns {
+        ignored
+            .add_line(None, pattern)
+            .map_err(|err| ConfigError::InvalidIgnorePattern {
+                pattern: pattern.clone(),
+                reason: err.to_string(),
+            })?;
+    }
+
+    Ok(ignored
+        .build()
+        .map_err(|err| ConfigError::InvalidIgnorePattern {
+            pattern: patterns.join(", "),
+            reason: err.to_string(),
+        })?)
+}
+
+/// Helper function to get the full paths of files in the folder `dir`, given their paths relative to it, for the
+/// helper functions below. Absolute paths (of files in roots, see `Files::set_roots`) are kept as they are.
+fn under(dir: &Path) -> impl Fn(&Path) -> PathBuf + Sync + '_ {
+    move |file: &Path| dir.join(file)
+}
+
+/// Helper function to get the path of `file` in the repository, relative to it. See `Files::repo_relative`.
+fn repo_relative(roots: &[Root], file: &Path) -> PathBuf {
+    roots
+        .iter()
+        .filter(|_| file.is_absolute())
+        .find_map(|root| {
+            file.strip_prefix(&root.path)
+                .ok()
+                .map(|rest| root.target().join(rest))
+        })
+        .unwrap_or_else(|| file.to_path_buf())
+}
+
+/// Helper function to copy files from `from` to `to`, replacing whatever is at `to`. Folders are copied
+/// recursively. What isn't allowed in roots is retried by `escalator`.
 ///
-/// `dir` is the full path to the directory.
+/// `files` contains the files, and `from` and `to` get their full paths (see `under`).
 ///
 /// Returns either an error or `Ok(())`.
-fn delete_files<P1, P2>(files: &[P1], dir: P2) -> Result<()>
+fn copy_files<P, F1, F2>(
+    filesystem: &dyn FileSystem,
+    files: &[P],
+    from: F1,
+    to: F2,
+    escalator: &Escalator,
+) -> Result<()>
 where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
+    P: AsRef<Path>,
+    F1: Fn(&Path) -> PathBuf,
+    F2: Fn(&Path) -> PathBuf,
 {
-    // Append all the paths to `dir` to get the full path to the file/folder.
-    let paths = files.iter().map(|file| dir.as_ref().join(file));
+    for file in files {
+        let (from_path, to_path) = (from(file.as_ref()), to(file.as_ref()));
+
+        // Create any and all parent directories.
+        create_dir_all(filesystem, to_path.parent().unwrap(), escalator)?;
 
-    for path in paths {
-        // Delete the file.
-        fs::remove_file(&path).map_err(|err| IoError::Delete { source: err, path })?;
+        if filesystem.is_dir(&from_path) {
+            // Remove the old copy, so that deleted files don't linger around.
+            if filesystem.exists(&to_path) {
+                filesystem.remove_dir_all(&to_path).or_else(|err| {
+                    escalator.retry(Operation::Delete(&to_path), err, |source| IoError::Delete {
+                        source,
+                        path: to_path.clone(),
+                    })
+                })?;
+            }
+
+            let entries = filesystem
+                .read_dir(&from_path)
+                .map_err(|err| IoError::Read {
+                    source: err,
+                    path: from_path.clone(),
+                })?
+                .into_iter()
+                .map(PathBuf::from)
+                .collect_vec();
+
+            create_dir_all(filesystem, &to_path, escalator)?;
+
+            copy_files(
+                filesystem,
+                &entries,
+                under(&from_path),
+                under(&to_path),
+                escalator,
+            )?;
+        } else {
+            let operation = Operation::Copy {
+                from: &from_path,
+                to: &to_path,
+            };
+
+            filesystem.copy(&from_path, &to_path).or_else(|err| {
+                escalator.retry(operation, err, |source| IoError::Copy {
+                    source,
+                    from: from_path.clone(),
+                    to: to_path.clone(),
+                })
+            })?;
+        }
     }
 
     Ok(())
 }
 
-/// Helper function to symlink files from `from` to `to`.
-///
-/// `file` contains the file with a path relative to `from`.
+/// Helper function to create the folder at `path`, and the folders containing it. What isn't allowed in roots is
+/// retried by `escalator`.
+fn create_dir_all(
+    filesystem: &dyn FileSystem,
+    path: &Path,
+    escalator: &Escalator,
+) -> std::result::Result<(), IoError> {
+    filesystem.create_dir_all(path).or_else(|err| {
+        escalator.retry(Operation::CreateDir(path), err, |source| IoError::Create {
+            source,
+            path: path.to_path_buf(),
+        })
+    })
+}
+
+/// Finds the files in `tracked` (paths in the repository, e.g. from `Repository::tracked_files`) that no entry of
+/// `include` covers, e.g. because the configuration file was edited by hand. These orphans are never linked into the
+/// home directory. Encrypted files are covered by the entry of their decrypted path, variants by the entry of the
+/// file/folder they're a variant of (see `variant::base`), and the files of the repository itself (like
+/// `.gitattributes`, the metadata manifest and the exported package lists) are never orphans. The entries for files
+/// in `roots` cover their paths in the roots' target folders (see `Files::repo_relative`).
+pub fn orphans(tracked: &[PathBuf], include: &[PathBuf], roots: &[Root]) -> Vec<PathBuf> {
+    let include = include
+        .iter()
+        .map(|include| repo_relative(roots, include))
+        .collect_vec();
+
+    tracked
+        .iter()
+        .filter(|path| !is_repo_file(path))
+        .filter(|path| {
+            let (target, base) = (orphan_target(path), variant::base(path));
+
+            !include.iter().any(|include| {
+                path.starts_with(include)
+                    || target.starts_with(include)
+                    || base.starts_with(include)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Finds the entries of `include` covering any of the files `changed` (paths in the repository, e.g. the files a
+/// commit touched), i.e. the managed paths whose contents changed. Encrypted files are covered by the entry of their
+/// decrypted path, variants by the entry of the file/folder they're a variant of, and files in the target folders of
+/// `roots` by the entries of the roots, like in `orphans`.
+pub fn covering(changed: &[PathBuf], include: &[PathBuf], roots: &[Root]) -> Vec<PathBuf> {
+    include
+        .iter()
+        .filter(|include| {
+            let include = repo_relative(roots, include);
+
+            changed.iter().any(|path| {
+                path.starts_with(&include)
+                    || orphan_target(path).starts_with(&include)
+                    || variant::base(path).starts_with(&include)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Helper function to get the path of the orphaned `file` in the home directory: its decrypted path if it's
+/// encrypted, and `file` itself otherwise.
+fn orphan_target(file: &Path) -> PathBuf {
+    match file
+        .extension()
+        .is_some_and(|ext| ext == encrypt::ENCRYPTED_EXTENSION)
+    {
+        true => file.with_extension(""),
+        false => file.to_path_buf(),
+    }
+}
+
+/// Helper function to delete the folders containing `file` in `dir` that are empty, up to `dir` itself. Folders that
+/// can't be deleted are left alone, as they're harmless.
+fn remove_empty_parents(filesystem: &dyn FileSystem, file: &Path, dir: &Path) {
+    for parent in file
+        .ancestors()
+        .skip(1)
+        .filter(|path| !path.as_os_str().is_empty())
+    {
+        if filesystem.remove_dir(&dir.join(parent)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Helper function to delete files. Symlinks are removed, but real files/folders are moved to `trash`, or deleted
+/// permanently if there is none (see `platform::delete`). What isn't allowed in roots is retried by `escalator`,
+/// which deletes permanently.
 ///
-/// `from` and `to` are the full paths to the directories.
+/// `files` contains the files, and `at` gets their full paths (see `under`).
 ///
 /// Returns either an error or `Ok(())`.
-fn symlink_files<P1, P2, P3>(files: &[P1], from: P2, to: P3) -> Result<()>
+fn delete_files<P, F>(
+    filesystem: &dyn FileSystem,
+    files: &[P],
+    at: F,
+    trash: Option<&dyn Trash>,
+    escalator: &Escalator,
+) -> Result<()>
 where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
-    P3: AsRef<Path>,
+    P: AsRef<Path>,
+    F: Fn(&Path) -> PathBuf + Sync,
 {
-    // Append all the paths to `from` to get the full path to the file/folder.
-    let from_paths = files.iter().map(|file| from.as_ref().join(file));
+    for_each_file(files, |file| {
+        let path = at(file);
 
-    let to_paths = files.iter().map(|file| to.as_ref().join(file));
+        // Delete the file. Links to folders are deleted like folders on some platforms.
+        let deleted = match filesystem.is_symlink(&path) {
+            true => filesystem.remove_link(&path),
+            false => filesystem.delete(&path, trash),
+        };
 
-    for (from_path, to_path) in from_paths.zip(to_paths) {
-        // Create the symlink.
-        match unix_fs::symlink(&from_path, &to_path) {
-            // If ok, just return.
-            Ok(_) => {}
+        deleted.or_else(|err| {
+            escalator.retry(Operation::Delete(&path), err, |source| IoError::Delete {
+                source,
+                path: path.clone(),
+            })
+        })
+    })
+}
 
-            // If the error says that the file exists, then delete the file and try again.
-            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
-                fs::remove_file(&to_path).map_err(|err| IoError::Delete {
-                    source: err,
-                    path: to_path.clone(),
-                })?;
+/// Helper function to symlink the file/folder at `from_path` to `to_path`, replacing the file at `to_path` if there
+/// is one. If `copies` is set, files (but not folders) are copied instead. What isn't allowed in roots is retried by
+/// `escalator`.
+///
+/// `from_path` and `to_path` are the full paths to the files.
+///
+/// Returns either an error or `Ok(())`.
+fn link_file(
+    filesystem: &dyn FileSystem,
+    from_path: &Path,
+    to_path: &Path,
+    copies: bool,
+    escalator: &Escalator,
+) -> std::result::Result<(), IoError> {
+    let copies = copies && !filesystem.is_dir(from_path);
+    let link = || match copies {
+        true => filesystem.copy(from_path, to_path),
+        false => filesystem.symlink(from_path, to_path),
+    };
+    let retry = |err| {
+        let operation = match copies {
+            true => Operation::Copy {
+                from: from_path,
+                to: to_path,
+            },
+            false => Operation::Symlink {
+                from: from_path,
+                to: to_path,
+            },
+        };
 
-                unix_fs::symlink(&from_path, &to_path).map_err(|err| IoError::Symlink {
-                    source: err,
-                    to: to_path,
-                    from: from_path,
-                })?;
-            }
+        escalator.retry(operation, err, |source| IoError::Symlink {
+            from: from_path.to_path_buf(),
+            to: to_path.to_path_buf(),
+            source,
+        })
+    };
 
-            // If it's any other error, then return it.
-            Err(err) => {
-                return Err(IoError::Symlink {
-                    from: from_path,
-                    to: to_path,
-                    source: err,
-                }
-                .into())
-            }
-        }
+    // Create any and all parent directories, e.g. on a new machine.
+    create_dir_all(filesystem, to_path.parent().unwrap(), escalator)?;
 
-        // // If the error says that the file exists, then delete the file and try again.
-        // .map_err(|err| {
-        //     if err.kind() == std::io::ErrorKind::AlreadyExists {
-        //         fs::remove_file(&to_path).context(DeleteSnafu { path: to_path })?;
+    // Create the symlink.
+    match link() {
+        // If ok, just return.
+        Ok(_) => Ok(()),
 
-        //         unix_fs::symlink(&from_path, &to_path).context(SymlinkSnafu {
-        //             from: from_path,
-        //             to: to_path,
-        //         })
-        //     } else {
-        //         Err(err)
-        //     }
-        // })
-    }
+        // If the error says that the file exists, then delete the file and try again.
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+            filesystem.remove_file(to_path).or_else(|err| {
+                escalator.retry(Operation::Delete(to_path), err, |source| IoError::Delete {
+                    source,
+                    path: to_path.to_path_buf(),
+                })
+            })?;
 
-    Ok(())
+            link().or_else(retry)
+        }
+
+        // If it's any other error, then try it again with more permissions if it's in a root.
+        Err(err) => retry(err),
+    }
 }
 
-/// Helper function to move files from `from` to `to`.
-///
-/// `file` contains the file with a path relative to `from`.
+/// Helper function to move files from `from` to `to`. Files in roots (see `Files::set_roots`) are often on another
+/// filesystem than the repository, so they're copied and deleted instead if they can't be renamed. What isn't
+/// allowed in roots is retried by `escalator`.
 ///
-/// `from` and `to` are the full paths to the directories.
+/// `files` contains the files, and `from` and `to` get their full paths (see `under`).
 ///
 /// Returns either an error or `Ok(())`.
-fn move_files<P1, P2, P3>(files: &[P1], from: P2, to: P3) -> Result<()>
+fn move_files<P, F1, F2>(
+    filesystem: &dyn FileSystem,
+    files: &[P],
+    from: F1,
+    to: F2,
+    escalator: &Escalator,
+) -> Result<()>
 where
-    P1: AsRef<Path>,
-    P2: AsRef<Path>,
-    P3: AsRef<Path>,
+    P: AsRef<Path>,
+    F1: Fn(&Path) -> PathBuf + Sync,
+    F2: Fn(&Path) -> PathBuf + Sync,
 {
-    // Append all the paths to `from` to get the full path to the file/folder.
-    let from_paths = files.iter().map(|file| from.as_ref().join(file));
-
-    let to_paths = files.iter().map(|file| to.as_ref().join(file));
+    for_each_file(files, |file| {
+        let (from_path, to_path) = (from(file), to(file));
 
-    for (from_path, to_path) in from_paths.zip(to_paths) {
         // Create any and all parent directories.
-        fs::create_dir_all(to_path.parent().unwrap()).map_err(|err| IoError::Create {
-            source: err,
-            path: to_path.parent().unwrap().to_path_buf(),
-        })?;
+        create_dir_all(filesystem, to_path.parent().unwrap(), escalator)?;
 
-        // Move the file.
-        fs::rename(&from_path, &to_path).map_err(|err| IoError::Move {
-            source: err,
+        let fail = |source| IoError::Move {
+            source,
             from: from_path.clone(),
             to: to_path.clone(),
-        })?;
-    }
+        };
 
-    Ok(())
+        // Move the file. Once it's copied, only deleting the original is left to retry.
+        match filesystem.rename(&from_path, &to_path) {
+            Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+                copy_files(
+                    filesystem,
+                    &[&from_path],
+                    |_: &Path| from_path.clone(),
+                    |_: &Path| to_path.clone(),
+                    escalator,
+                )
+                .map_err(|_| fail(err))?;
+
+                filesystem
+                    .delete(&from_path, None)
+                    .or_else(|err| escalator.retry(Operation::Delete(&from_path), err, fail))
+            }
+            moved => moved.or_else(|err| {
+                let operation = Operation::Move {
+                    from: &from_path,
+                    to: &to_path,
+                };
+
+                escalator.retry(operation, err, fail)
+            }),
+        }
+    })
+}
+
+/// Helper function to check if `path` (relative to the repository) is one of the files at the top of the repository
+/// that git or `dotbak` keep there for themselves, like `.gitignore` or the exported package lists, rather than a
+/// managed file.
+fn is_repo_file(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+
+    (path.components().count() == 1
+        && (name.starts_with(".git") || name == metadata::MANIFEST_FILE))
+        || path.starts_with(packages::PACKAGES_DIR)
+}
+
+/// Helper function to run `operation` on every file of `files` in parallel, as syncing a whole folder like
+/// `.config` can mean hundreds of them. Every file is tried even if others fail, and the errors are collected into
+/// one (see `IoError::Many`).
+fn for_each_file<P, F>(files: &[P], operation: F) -> Result<()>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path) -> std::result::Result<(), IoError> + Sync,
+{
+    let mut errors = files
+        .iter()
+        .map(AsRef::as_ref)
+        .collect_vec()
+        .into_par_iter()
+        .filter_map(|file| operation(file).err())
+        .collect::<Vec<_>>();
+
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0).into()),
+        _ => Err(IoError::Many { errors }.into()),
+    }
 }
@@ -1,8 +1,25 @@
 #![cfg(test)]
 
-use super::Files;
+use super::{
+    covering,
+    escalate::{Escalator, Operation},
+    filesystem::{FileKind, FileSystem, MemoryFileSystem, RealFileSystem},
+    move_files, orphans, under,
+    variant::{self, Machine},
+    Files, LinkState, Progress, WalkConfig,
+};
+use crate::{
+    config::files::{Escalation, HardlinkPolicy, Root},
+    errors::{io::IoError, DotbakError},
+    platform::RecordingTrash,
+};
 use assert_fs::prelude::*;
 use itertools::Itertools;
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
 
 /// Test if we can move items from `home_dir` to `file_dir`.
 #[test]
@@ -68,6 +85,64 @@ fn test_move_and_symlink() {
     }
 }
 
+/// Test that excluded paths inside of a managed folder are left in `home_dir`, also when the folder was linked as a
+/// whole until now.
+#[test]
+fn test_exclude() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    home_dir
+        .child(".config/nvim/init.lua")
+        .write_str("init")
+        .unwrap();
+    home_dir
+        .child(".config/Slack/cache")
+        .write_str("cache")
+        .unwrap();
+    home_dir
+        .child(".config/git/config")
+        .write_str("git")
+        .unwrap();
+    home_dir
+        .child(".config/git/secret")
+        .write_str("secret")
+        .unwrap();
+    file_manager.move_and_symlink(&[".config"]).unwrap();
+    assert!(home_dir.child(".config").is_symlink());
+
+    file_manager.set_excluded(vec![".config/Slack".into(), ".config/git/secret".into()]);
+    assert_eq!(
+        file_manager.expand_excluded(&[".config", ".zshrc"]),
+        vec![
+            PathBuf::from(".config/git/config"),
+            PathBuf::from(".config/nvim"),
+            PathBuf::from(".zshrc")
+        ]
+    );
+
+    // Like syncing does, as the children that are in `file_dir` already aren't moved.
+    file_manager.move_and_symlink(&[".config"]).unwrap();
+    file_manager.symlink_back_home(&[".config"]).unwrap();
+
+    assert!(!home_dir.child(".config").is_symlink());
+    assert!(!home_dir.child(".config/Slack").is_symlink());
+    assert!(!home_dir.child(".config/git").is_symlink());
+    assert!(home_dir.child(".config/nvim").is_symlink());
+    assert!(home_dir.child(".config/git/config").is_symlink());
+    home_dir.child(".config/Slack/cache").assert("cache");
+    home_dir.child(".config/git/secret").assert("secret");
+    assert!(!file_dir.child(".config/Slack").exists());
+    assert!(!file_dir.child(".config/git/secret").exists());
+    file_dir.child(".config/nvim/init.lua").assert("init");
+    assert_eq!(
+        file_manager.link_state(".config/git/config"),
+        LinkState::Intact
+    );
+}
+
 /// Test the undoing of `move_and_symlink`.
 #[test]
 fn test_remove_and_restore() {
@@ -133,3 +208,1149 @@ fn test_remove_and_restore() {
         assert!(file.exists());
     }
 }
+
+/// Test that many files are moved at once, and that every file is tried even if some fail, with all of their errors
+/// collected.
+#[test]
+fn test_move_many() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let (home_dir, file_dir) = (temp.child("home"), temp.child("files"));
+    let files = (0..200)
+        .map(|i| PathBuf::from(format!(".config/app-{}/config", i)))
+        .collect_vec();
+
+    for file in &files {
+        home_dir.child(file).touch().unwrap();
+    }
+
+    move_files(
+        &RealFileSystem,
+        &files,
+        under(home_dir.path()),
+        under(file_dir.path()),
+        &Escalator::default(),
+    )
+    .unwrap();
+
+    for file in &files {
+        assert!(!home_dir.child(file).exists());
+        assert!(file_dir.child(file).exists());
+    }
+
+    home_dir.child("present").touch().unwrap();
+
+    let err = move_files(
+        &RealFileSystem,
+        &["missing", "present", "also-missing"],
+        under(home_dir.path()),
+        under(file_dir.path()),
+        &Escalator::default(),
+    )
+    .unwrap_err();
+
+    match err {
+        DotbakError::Io(IoError::Many { errors }) => assert_eq!(errors.len(), 2),
+        err => panic!("expected several errors, got {:?}", err),
+    }
+
+    assert!(file_dir.child("present").exists());
+}
+
+/// Creates `Files` for the home directory `/home/user` on a filesystem kept in memory.
+fn in_memory() -> (Files, Arc<MemoryFileSystem>) {
+    let filesystem = Arc::new(MemoryFileSystem::new());
+    let mut files = Files::init(
+        PathBuf::from("/home/user"),
+        PathBuf::from("/home/user/.local/share/dotbak/dotfiles"),
+    );
+
+    files.set_file_system(filesystem.clone());
+
+    (files, filesystem)
+}
+
+/// Test that files/folders are moved into `file_dir` and symlinked back, and restored again, without touching the
+/// disk.
+#[test]
+fn test_move_and_symlink_in_memory() {
+    let (files, filesystem) = in_memory();
+    let (home_dir, file_dir) = (
+        files.home_dir().to_path_buf(),
+        files.file_dir().to_path_buf(),
+    );
+
+    filesystem.add_file(home_dir.join(".bashrc"), "export EDITOR=vi");
+    filesystem.add_file(home_dir.join(".config/app/config"), "theme = dark");
+
+    files.move_and_symlink(&[".bashrc", ".config/app"]).unwrap();
+
+    let tree = filesystem.tree();
+    assert_eq!(tree[&home_dir.join(".bashrc")], FileKind::Symlink);
+    assert_eq!(tree[&home_dir.join(".config/app")], FileKind::Symlink);
+    assert_eq!(tree[&file_dir.join(".bashrc")], FileKind::File);
+    assert_eq!(tree[&file_dir.join(".config/app/config")], FileKind::File);
+    assert_eq!(files.link_state(".bashrc"), LinkState::Intact);
+    assert_eq!(
+        filesystem
+            .read(&home_dir.join(".config/app/config"))
+            .unwrap(),
+        b"theme = dark"
+    );
+
+    // Moving them again does nothing, as they're managed already.
+    files.move_and_symlink(&[".bashrc", ".config/app"]).unwrap();
+    assert_eq!(filesystem.tree(), tree);
+
+    files
+        .remove_and_restore(&[".bashrc", ".config/app"])
+        .unwrap();
+
+    let tree = filesystem.tree();
+    assert_eq!(tree[&home_dir.join(".bashrc")], FileKind::File);
+    assert_eq!(tree[&home_dir.join(".config/app/config")], FileKind::File);
+    assert!(!tree.contains_key(&file_dir.join(".bashrc")));
+    assert_eq!(files.link_state(".bashrc"), LinkState::Shadowed);
+}
+
+/// Test that folders with excluded paths inside of them are linked child by child, and that the excluded paths are
+/// left in the home directory, without touching the disk.
+#[test]
+fn test_excluded_in_memory() {
+    let (mut files, filesystem) = in_memory();
+    let (home_dir, file_dir) = (
+        files.home_dir().to_path_buf(),
+        files.file_dir().to_path_buf(),
+    );
+
+    files.set_excluded(vec![PathBuf::from(".config/app/cache")]);
+    filesystem.add_file(home_dir.join(".config/app/config"), "theme = dark");
+    filesystem.add_file(home_dir.join(".config/app/cache/index"), "");
+
+    files.move_and_symlink(&[".config/app"]).unwrap();
+
+    let tree = filesystem.tree();
+    assert_eq!(tree[&home_dir.join(".config/app")], FileKind::Dir);
+    assert_eq!(
+        tree[&home_dir.join(".config/app/config")],
+        FileKind::Symlink
+    );
+    assert_eq!(
+        tree[&home_dir.join(".config/app/cache/index")],
+        FileKind::File
+    );
+    assert!(!tree.contains_key(&file_dir.join(".config/app/cache")));
+}
+
+/// Test that every file is still moved and symlinked when some of them can't be, and that the errors of the others
+/// are collected, without touching the disk.
+#[test]
+fn test_move_denied_in_memory() {
+    let (files, filesystem) = in_memory();
+    let (home_dir, file_dir) = (
+        files.home_dir().to_path_buf(),
+        files.file_dir().to_path_buf(),
+    );
+    let names = ["a", "b", "c", "d"];
+
+    for name in names {
+        filesystem.add_file(home_dir.join(name), name);
+    }
+
+    filesystem.create_dir_all(&file_dir).unwrap();
+    filesystem.deny(file_dir.join("b"));
+    filesystem.deny(file_dir.join("d"));
+
+    match files.move_and_symlink(&names).unwrap_err() {
+        DotbakError::Io(IoError::Many { errors }) => assert_eq!(errors.len(), 2),
+        err => panic!("expected several errors, got {:?}", err),
+    }
+
+    let tree = filesystem.tree();
+
+    for name in ["a", "c"] {
+        assert_eq!(tree[&file_dir.join(name)], FileKind::File);
+    }
+
+    for name in ["b", "d"] {
+        assert_eq!(tree[&home_dir.join(name)], FileKind::File);
+        assert!(!tree.contains_key(&file_dir.join(name)));
+    }
+}
+
+/// Test that symlinks are rewritten after the file directory moves, and that real files are left alone.
+#[test]
+fn test_relink() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let old_file_dir = temp.child("files");
+    let new_file_dir = temp.child("moved");
+    let file_manager = Files::init(home_dir.path().to_owned(), old_file_dir.path().to_owned());
+
+    home_dir.create_dir_all().unwrap();
+    old_file_dir.create_dir_all().unwrap();
+    home_dir.child("foo").touch().unwrap();
+    home_dir.child("bar").touch().unwrap();
+
+    file_manager.move_and_symlink(&["foo", "bar"]).unwrap();
+
+    // Move the file directory, and replace one of the symlinks with a real file.
+    std::fs::rename(old_file_dir.path(), new_file_dir.path()).unwrap();
+    std::fs::remove_file(home_dir.child("bar").path()).unwrap();
+    home_dir.child("bar").write_str("real").unwrap();
+
+    let file_manager = Files::init(home_dir.path().to_owned(), new_file_dir.path().to_owned());
+    let skipped = file_manager
+        .relink(&["foo", "bar"], old_file_dir.path())
+        .unwrap();
+
+    assert_eq!(skipped, vec![PathBuf::from("bar")]);
+    assert_eq!(
+        home_dir.child("foo").read_link().unwrap(),
+        new_file_dir.child("foo").path()
+    );
+    assert!(home_dir.child("bar").read_link().is_err());
+}
+
+/// Test that replaced symlinks are detected, backed up, and adopted.
+#[test]
+fn test_replaced_backup_and_adopt() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    file_manager.set_trash(Arc::new(RecordingTrash::new()));
+
+    home_dir.create_dir_all().unwrap();
+    file_dir.create_dir_all().unwrap();
+    home_dir.child("foo").write_str("tracked foo").unwrap();
+    home_dir.child("bar").write_str("tracked bar").unwrap();
+    home_dir.child("baz").touch().unwrap();
+
+    file_manager
+        .move_and_symlink(&["foo", "bar", "baz"])
+        .unwrap();
+    assert!(file_manager.replaced(&["foo", "bar", "baz"]).is_empty());
+
+    // Replace two of the symlinks with real files.
+    for (name, content) in [("foo", "new foo"), ("bar", "new bar")] {
+        std::fs::remove_file(home_dir.child(name).path()).unwrap();
+        home_dir.child(name).write_str(content).unwrap();
+    }
+
+    assert_eq!(
+        file_manager.replaced(&["foo", "bar", "baz"]),
+        vec![PathBuf::from("foo"), PathBuf::from("bar")]
+    );
+
+    // Back up `foo` and restore its symlink.
+    let backup = file_manager.backup_in_home("foo").unwrap();
+    file_manager.symlink_back_home(&["foo"]).unwrap();
+
+    assert_eq!(backup, home_dir.child("foo.dotbak-backup").path());
+    home_dir.child("foo.dotbak-backup").assert("new foo");
+    home_dir.child("foo").assert("tracked foo");
+
+    // Adopt the new `bar`.
+    file_manager.adopt_from_home("bar").unwrap();
+
+    file_dir.child("bar").assert("new bar");
+    assert_eq!(
+        home_dir.child("bar").read_link().unwrap(),
+        file_dir.child("bar").path()
+    );
+    assert!(file_manager.replaced(&["foo", "bar", "baz"]).is_empty());
+}
+
+/// Test that only replacements with other contents than the tracked version would be overwritten.
+#[test]
+fn test_overwritten() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    home_dir.create_dir_all().unwrap();
+    file_dir.create_dir_all().unwrap();
+    file_dir.child("foo").write_str("tracked foo").unwrap();
+    file_dir.child("bar").write_str("tracked bar").unwrap();
+    file_dir.child("baz").write_str("tracked baz").unwrap();
+    home_dir.child("foo").write_str("new foo").unwrap();
+    home_dir.child("bar").write_str("tracked bar").unwrap();
+
+    assert_eq!(
+        file_manager.overwritten(&["foo", "bar", "baz"]).unwrap(),
+        vec![PathBuf::from("foo")]
+    );
+}
+
+/// Test merging two versions of a file line by line, and merging edits to a real file that replaced its symlink.
+#[test]
+fn test_merge_from_home() {
+    use super::compare::merge_lines;
+
+    assert_eq!(
+        merge_lines("a\nb\n", "a\nb\nc\n"),
+        Some("a\nb\nc\n".to_string())
+    );
+    assert_eq!(
+        merge_lines("a\nb\nc", "a\nc\n"),
+        Some("a\nb\nc\n".to_string())
+    );
+    assert_eq!(merge_lines("a\nb\n", "a\nB\n"), None);
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    file_manager.set_trash(Arc::new(RecordingTrash::new()));
+
+    home_dir.create_dir_all().unwrap();
+    file_dir.child("foo").write_str("a\nb\n").unwrap();
+    file_dir.child("bar").write_str("a\nb\n").unwrap();
+    home_dir.child("foo").write_str("a\nb\nc\n").unwrap();
+    home_dir.child("bar").write_str("a\nB\n").unwrap();
+
+    assert!(file_manager.merge_from_home("foo", 1024).unwrap());
+    assert!(file_manager.is_managed_in_home("foo"));
+    file_dir.child("foo").assert("a\nb\nc\n");
+
+    assert!(!file_manager.merge_from_home("bar", 1024).unwrap());
+    assert!(!file_manager.is_managed_in_home("bar"));
+    file_dir.child("bar").assert("a\nb\n");
+    home_dir.child("bar").assert("a\nB\n");
+}
+
+/// Test that symlinks pointing outside of `file_dir` are replaced with copies of what they point to.
+#[test]
+fn test_copy_link_target() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let other_dir = temp.child("other");
+    let file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    home_dir.create_dir_all().unwrap();
+    other_dir.child("foo").write_str("foo").unwrap();
+    other_dir.child("bar/baz").write_str("baz").unwrap();
+    file_dir.child("qux").write_str("qux").unwrap();
+    home_dir.child("real").write_str("real").unwrap();
+    home_dir
+        .child("foo")
+        .symlink_to_file(other_dir.child("foo"))
+        .unwrap();
+    home_dir
+        .child("bar")
+        .symlink_to_dir(other_dir.child("bar"))
+        .unwrap();
+    home_dir
+        .child("qux")
+        .symlink_to_file(file_dir.child("qux"))
+        .unwrap();
+    home_dir
+        .child("broken")
+        .symlink_to_file(other_dir.child("missing"))
+        .unwrap();
+
+    assert!(file_manager.copy_link_target("foo").unwrap());
+    assert!(file_manager.copy_link_target("bar").unwrap());
+    assert!(!file_manager.copy_link_target("qux").unwrap());
+    assert!(!file_manager.copy_link_target("broken").unwrap());
+    assert!(!file_manager.copy_link_target("real").unwrap());
+
+    assert!(!home_dir.child("foo").path().is_symlink());
+    home_dir.child("foo").assert("foo");
+    home_dir.child("bar/baz").assert("baz");
+    other_dir.child("foo").assert("foo");
+    assert!(home_dir.child("qux").path().is_symlink());
+}
+
+/// Test that backups don't overwrite each other.
+#[test]
+fn test_backup_in_home_numbered() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_manager = Files::init(
+        home_dir.path().to_owned(),
+        temp.child("files").to_path_buf(),
+    );
+
+    home_dir.child("foo").write_str("first").unwrap();
+    file_manager.backup_in_home("foo").unwrap();
+    home_dir.child("foo").write_str("second").unwrap();
+    let backup = file_manager.backup_in_home("foo").unwrap();
+
+    assert_eq!(backup, home_dir.child("foo.dotbak-backup.1").path());
+    home_dir.child("foo.dotbak-backup").assert("first");
+    home_dir.child("foo.dotbak-backup.1").assert("second");
+}
+
+/// Test that hardlinked files are skipped, copied, or moved according to the hard link policy.
+#[test]
+fn test_hardlink_policies() {
+    for policy in [
+        HardlinkPolicy::Skip,
+        HardlinkPolicy::Copy,
+        HardlinkPolicy::BreakLinks,
+    ] {
+        let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+        let home_dir = temp.child("home");
+        let file_dir = temp.child("files");
+        let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+        file_manager.set_hardlink_policy(policy);
+
+        home_dir.child("mail/cur").create_dir_all().unwrap();
+        home_dir.child("mail/cur/1").write_str("hello").unwrap();
+        home_dir.child("foo").write_str("foo").unwrap();
+        std::fs::hard_link(home_dir.child("mail/cur/1"), temp.child("elsewhere")).unwrap();
+
+        let hardlinked = file_manager.move_and_symlink(&["mail", "foo"]).unwrap();
+
+        assert_eq!(hardlinked, vec![PathBuf::from("mail")]);
+        assert!(home_dir.child("foo").is_symlink());
+
+        match policy {
+            HardlinkPolicy::Skip => {
+                assert!(!home_dir.child("mail").is_symlink());
+                assert!(!file_dir.child("mail").exists());
+            }
+            HardlinkPolicy::Copy => {
+                assert!(!home_dir.child("mail").is_symlink());
+                file_dir.child("mail/cur/1").assert("hello");
+
+                // The copy is refreshed when syncing again.
+                std::fs::write(temp.child("elsewhere"), "bye").unwrap();
+                file_manager.move_and_symlink(&["mail", "foo"]).unwrap();
+                file_manager.symlink_back_home(&["mail", "foo"]).unwrap();
+
+                assert!(!home_dir.child("mail").is_symlink());
+                file_dir.child("mail/cur/1").assert("bye");
+            }
+            HardlinkPolicy::BreakLinks => {
+                assert!(home_dir.child("mail").is_symlink());
+                file_dir.child("mail/cur/1").assert("hello");
+            }
+        }
+    }
+}
+
+/// Test that files are copied instead of symlinked when symlinks to files can't be created, and that changes are
+/// copied in whichever direction is newer.
+#[test]
+fn test_copies() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    file_manager.set_copies(true);
+
+    home_dir.child("foo").write_str("foo").unwrap();
+    home_dir.child("folder/bar").write_str("bar").unwrap();
+
+    file_manager.move_and_symlink(&["foo", "folder"]).unwrap();
+
+    // Files are copied, folders are still linked.
+    assert!(!home_dir.child("foo").is_symlink());
+    assert!(home_dir.child("folder").is_symlink());
+    home_dir.child("foo").assert("foo");
+    file_dir.child("foo").assert("foo");
+    assert!(file_manager.is_managed_in_home("foo"));
+    assert_eq!(file_manager.link_state("foo"), LinkState::Intact);
+
+    // Changes in `home_dir` are copied into `file_dir` when syncing.
+    let later = |path: &std::path::Path, secs| {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(secs))
+            .unwrap();
+    };
+
+    home_dir.child("foo").write_str("changed at home").unwrap();
+    later(home_dir.child("foo").path(), 10);
+    file_manager.move_and_symlink(&["foo", "folder"]).unwrap();
+    file_dir.child("foo").assert("changed at home");
+
+    // And changes in `file_dir` (e.g. from a pull) are copied into `home_dir`.
+    file_dir.child("foo").write_str("pulled").unwrap();
+    later(file_dir.child("foo").path(), 20);
+    file_manager.symlink_back_home(&["foo", "folder"]).unwrap();
+    home_dir.child("foo").assert("pulled");
+}
+
+/// Test that files in roots outside of `home_dir` are kept in the roots' target folders in `file_dir`, and are
+/// symlinked or copied back into the roots.
+#[test]
+fn test_roots() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let (home_dir, file_dir) = (temp.child("home"), temp.child("files"));
+    let (etc, srv) = (temp.child("etc"), temp.child("srv"));
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    file_manager.set_trash(Arc::new(RecordingTrash::new()));
+    file_manager.set_roots(vec![
+        Root {
+            path: etc.to_path_buf(),
+            target: Some("@etc".into()),
+            copies: false,
+            escalate: Escalation::Never,
+        },
+        Root {
+            path: srv.to_path_buf(),
+            target: Some("@srv".into()),
+            copies: true,
+            escalate: Escalation::Never,
+        },
+    ]);
+
+    let (hosts, index) = (etc.child("hosts"), srv.child("www/index.html"));
+    hosts.write_str("127.0.0.1 localhost").unwrap();
+    index.write_str("<html>").unwrap();
+    home_dir.child(".zshrc").write_str("zsh").unwrap();
+
+    assert_eq!(
+        file_manager.repo_relative(hosts.path()),
+        PathBuf::from("@etc/hosts")
+    );
+    assert_eq!(
+        file_manager.managed_relative("@srv/www/index.html"),
+        index.to_path_buf()
+    );
+    assert_eq!(
+        file_manager.repo_relative(".zshrc"),
+        PathBuf::from(".zshrc")
+    );
+
+    file_manager
+        .move_and_symlink(&[hosts.path(), index.path(), Path::new(".zshrc")])
+        .unwrap();
+
+    // Files in roots that copy their files stay where they are.
+    assert!(hosts.is_symlink());
+    file_dir.child("@etc/hosts").assert("127.0.0.1 localhost");
+    assert!(!index.is_symlink());
+    file_dir.child("@srv/www/index.html").assert("<html>");
+    file_dir.child(".zshrc").assert("zsh");
+    assert_eq!(file_manager.link_state(hosts.path()), LinkState::Intact);
+    assert_eq!(file_manager.link_state(index.path()), LinkState::Intact);
+
+    // The files in the target folders are covered by the entries of the roots, not orphaned.
+    let include = [hosts.to_path_buf(), PathBuf::from(".zshrc")];
+    let tracked = ["@etc/hosts", "@srv/www/index.html", ".zshrc"].map(PathBuf::from);
+
+    assert_eq!(
+        orphans(&tracked, &include, file_manager.roots()),
+        vec![PathBuf::from("@srv/www/index.html")]
+    );
+    assert_eq!(
+        covering(&tracked, &include, file_manager.roots()),
+        include.to_vec()
+    );
+
+    // Removing them restores them into their roots.
+    file_manager
+        .remove_and_restore(&[hosts.path(), index.path()])
+        .unwrap();
+
+    assert!(!hosts.is_symlink());
+    hosts.assert("127.0.0.1 localhost");
+    index.assert("<html>");
+    assert!(!file_dir.child("@etc/hosts").exists());
+}
+
+/// Test getting the states of the symlinks in `home_dir`.
+#[test]
+fn test_link_state() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    for file in ["intact", "broken", "missing", "shadowed"] {
+        home_dir.child(file).write_str(file).unwrap();
+    }
+
+    file_manager
+        .move_and_symlink(&["intact", "broken", "missing", "shadowed"])
+        .unwrap();
+
+    std::fs::remove_file(file_dir.child("broken")).unwrap();
+    std::fs::remove_file(home_dir.child("missing")).unwrap();
+    std::fs::remove_file(home_dir.child("shadowed")).unwrap();
+    home_dir.child("shadowed").write_str("replaced").unwrap();
+
+    assert_eq!(file_manager.link_state("intact"), LinkState::Intact);
+    assert_eq!(file_manager.link_state("broken"), LinkState::Broken);
+    assert_eq!(file_manager.link_state("missing"), LinkState::Missing);
+    assert_eq!(file_manager.link_state("shadowed"), LinkState::Shadowed);
+}
+
+/// Test that the most specific variant of a file for the machine is linked in place of the file, and that the link
+/// follows when a more specific variant is added.
+#[test]
+fn test_variants() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    file_manager.set_trash(Arc::new(RecordingTrash::new()));
+    let machine = Machine {
+        hostname: Some("work-laptop".to_string()),
+        os: Some("Darwin".to_string()),
+    };
+
+    assert_eq!(machine.specificity("os.darwin"), Some(1));
+    assert_eq!(machine.specificity("hostname.work-laptop"), Some(2));
+    assert_eq!(
+        machine.specificity("os.Darwin,hostname.work-laptop"),
+        Some(3)
+    );
+    assert_eq!(machine.specificity("os.Linux,hostname.work-laptop"), None);
+    assert_eq!(machine.specificity("class.work"), None);
+    assert_eq!(
+        variant::base(".config/nvim##os.Darwin/init.lua"),
+        PathBuf::from(".config/nvim/init.lua")
+    );
+
+    file_dir.child(".gitconfig").write_str("base").unwrap();
+    file_dir
+        .child(".gitconfig##os.Linux")
+        .write_str("linux")
+        .unwrap();
+    file_dir
+        .child(".gitconfig##os.Darwin")
+        .write_str("darwin")
+        .unwrap();
+    file_dir
+        .child(".zshrc##os.Darwin")
+        .write_str("zsh")
+        .unwrap();
+
+    // Without a machine, no variant is picked.
+    assert_eq!(file_manager.variant(".gitconfig"), None);
+    file_manager.set_machine(machine);
+    assert_eq!(
+        file_manager.variant(".gitconfig"),
+        Some(PathBuf::from(".gitconfig##os.Darwin"))
+    );
+
+    // Files that only exist as variants are linked too.
+    file_manager.sync(&[".gitconfig", ".zshrc"]).unwrap();
+    home_dir.child(".gitconfig").assert("darwin");
+    home_dir.child(".zshrc").assert("zsh");
+    assert_eq!(file_manager.link_state(".zshrc"), LinkState::Intact);
+
+    // The variant for the machine itself wins over the one for its OS.
+    file_dir
+        .child(".gitconfig##hostname.work-laptop")
+        .write_str("laptop")
+        .unwrap();
+    file_manager.sync(&[".gitconfig"]).unwrap();
+    home_dir.child(".gitconfig").assert("laptop");
+
+    // Adopting a replaced file replaces the variant.
+    std::fs::remove_file(home_dir.child(".gitconfig")).unwrap();
+    home_dir.child(".gitconfig").write_str("adopted").unwrap();
+    file_manager.adopt_from_home(".gitconfig").unwrap();
+    file_dir
+        .child(".gitconfig##hostname.work-laptop")
+        .assert("adopted");
+    file_dir.child(".gitconfig").assert("base");
+
+    // Variants are managed under the name of the file they're a variant of.
+    assert_eq!(
+        file_manager.resolve_globs(&[".zsh*"]).unwrap(),
+        vec![PathBuf::from(".zshrc")]
+    );
+}
+
+/// Test comparing files and folders by their contents, including files larger than one chunk.
+#[test]
+fn test_same_contents() {
+    use super::compare::same_contents;
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let large = vec![7u8; 200 * 1024];
+    let mut large_changed = large.clone();
+    *large_changed.last_mut().unwrap() = 8;
+
+    temp.child("a/large").write_binary(&large).unwrap();
+    temp.child("b/large").write_binary(&large).unwrap();
+    temp.child("c/large").write_binary(&large_changed).unwrap();
+    temp.child("a/small").write_str("small").unwrap();
+    temp.child("b/small").write_str("small").unwrap();
+    temp.child("c/small").write_str("small").unwrap();
+    temp.child("short").write_str("smal").unwrap();
+
+    assert!(same_contents(temp.child("a/large"), temp.child("b/large")).unwrap());
+    assert!(!same_contents(temp.child("a/large"), temp.child("c/large")).unwrap());
+    assert!(!same_contents(temp.child("a/small"), temp.child("short")).unwrap());
+    assert!(same_contents(temp.child("a"), temp.child("b")).unwrap());
+    assert!(!same_contents(temp.child("a"), temp.child("c")).unwrap());
+    assert!(!same_contents(temp.child("a"), temp.child("a/small")).unwrap());
+}
+
+/// Test that checksums of files and folders change exactly when their contents do.
+#[test]
+fn test_checksum() {
+    use super::compare::checksum;
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+
+    temp.child("a/file").write_str("file").unwrap();
+    temp.child("b/file").write_str("file").unwrap();
+    temp.child("c/other").write_str("file").unwrap();
+
+    let sum = checksum(temp.child("a")).unwrap();
+    assert_eq!(sum.len(), 16);
+    assert_eq!(sum, checksum(temp.child("b")).unwrap());
+    assert_ne!(sum, checksum(temp.child("c")).unwrap());
+    assert_ne!(sum, checksum(temp.child("a/file")).unwrap());
+
+    temp.child("b/file").write_str("changed").unwrap();
+    assert_ne!(sum, checksum(temp.child("b")).unwrap());
+    assert!(checksum(temp.child("missing")).is_err());
+}
+
+/// Test finding the files which are too large or binary to be diffed.
+#[test]
+fn test_large_or_binary() {
+    use super::compare::large_or_binary;
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+
+    temp.child(".zshrc").write_str("echo hi").unwrap();
+    temp.child("fonts/font.ttf")
+        .write_binary(&[0, 1, 0, 0])
+        .unwrap();
+    temp.child("fonts/README").write_str("fonts").unwrap();
+    temp.child("wallpaper.txt")
+        .write_str(&"x".repeat(100))
+        .unwrap();
+
+    let mut found =
+        large_or_binary(&[".zshrc", "fonts", "wallpaper.txt"], temp.path(), 50).unwrap();
+    found.sort();
+
+    assert_eq!(
+        found,
+        vec![
+            PathBuf::from("fonts/font.ttf"),
+            PathBuf::from("wallpaper.txt")
+        ]
+    );
+}
+
+/// Test that the metadata of folders and the files in them is scanned and applied, and that changes on this machine
+/// and from the repository are merged and restored.
+#[test]
+fn test_metadata() {
+    use super::metadata::{self, Manifest};
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let ssh = temp.child(".ssh");
+
+    ssh.child("config").write_str("Host *").unwrap();
+    fs::set_permissions(ssh.path(), fs::Permissions::from_mode(0o700)).unwrap();
+    fs::set_permissions(
+        ssh.child("config").path(),
+        fs::Permissions::from_mode(0o600),
+    )
+    .unwrap();
+
+    let mut current = Manifest::default();
+    current.scan(".ssh", ssh.path(), false);
+    let config = current.get(".ssh/config").unwrap();
+
+    assert_eq!(current.get(".ssh").unwrap().mode, 0o700);
+    assert_eq!(config.mode, 0o600);
+    assert!(!config.git_keeps());
+
+    // Nothing was recorded yet, so this machine's metadata is taken as-is.
+    let manifest = metadata::merge(&Manifest::default(), &Manifest::default(), &current);
+    assert_eq!(manifest, current);
+    assert!(toml::to_string(&manifest)
+        .unwrap()
+        .contains("[\".ssh/config\"]\nmode = \"600\""));
+
+    // Another machine made the file readable for the group, which is restored here as this machine didn't change
+    // it, and kept when recording.
+    let known = current.clone();
+    let manifest: Manifest = toml::from_str(
+        "[\".ssh\"]\nmode = \"700\"\n[\".ssh/config\"]\nmode = \"640\"\n[\".ssh/gone\"]\nmode = \"600\"",
+    )
+    .unwrap();
+
+    assert_eq!(
+        metadata::merge(&manifest, &known, &current)
+            .get(".ssh/config")
+            .unwrap()
+            .mode,
+        0o640
+    );
+    assert!(metadata::merge(&manifest, &known, &current)
+        .get(".ssh/gone")
+        .is_none());
+
+    let restore = metadata::to_restore(&manifest, &known, &current);
+    assert_eq!(restore.len(), 1);
+    restore[0].1.apply(ssh.child("config").path()).unwrap();
+
+    let mut restored = Manifest::default();
+    restored.scan(".ssh", ssh.path(), false);
+    assert_eq!(restored.get(".ssh/config").unwrap().mode, 0o640);
+
+    // Changes on this machine win over the manifest, and aren't undone.
+    let changed: Manifest = toml::from_str("[\".ssh/config\"]\nmode = \"644\"").unwrap();
+
+    assert_eq!(
+        metadata::merge(&manifest, &known, &changed)
+            .get(".ssh/config")
+            .unwrap()
+            .mode,
+        0o644
+    );
+    assert!(metadata::to_restore(&manifest, &known, &changed).is_empty());
+}
+
+/// Test that encrypted files are only stored encrypted in the repository, and round-trip in both directions.
+#[test]
+fn test_encrypted_round_trip() {
+    use super::encrypt::{self, encrypted_path};
+    use std::{fs, os::unix::fs::PermissionsExt};
+
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let (home_dir, file_dir) = (temp.child("home"), temp.child("repo"));
+    let key_path = temp.child("key.txt");
+    let netrc = PathBuf::from(".netrc");
+
+    home_dir
+        .child(".netrc")
+        .write_str("password hunter2")
+        .unwrap();
+    file_dir.create_dir_all().unwrap();
+
+    encrypt::generate_key(&key_path).unwrap();
+    assert!(encrypt::generate_key(&key_path).is_err());
+
+    let mut files = Files::init(home_dir.to_path_buf(), file_dir.to_path_buf());
+    files.set_trash(Arc::new(RecordingTrash::new()));
+    files.set_encryption(
+        vec![netrc.clone()],
+        Some(encrypt::load_key(&key_path).unwrap()),
+        key_path.to_path_buf(),
+    );
+
+    files.move_and_symlink(&[&netrc]).unwrap();
+    files.symlink_back_home(&[&netrc]).unwrap();
+
+    let encrypted = file_dir.child(encrypted_path(&netrc));
+    assert!(!file_dir.child(".netrc").exists());
+    assert!(!fs::read(&encrypted)
+        .unwrap()
+        .windows(7)
+        .any(|w| w == b"hunter2"));
+    assert!(!home_dir.child(".netrc").is_symlink());
+    assert_eq!(files.link_state(&netrc), LinkState::Intact);
+
+    // Changes from the repository (e.g. a pull) are decrypted into the home directory...
+    let other = temp.child("other");
+    other.write_str("password correcthorse").unwrap();
+    fs::remove_file(&encrypted).unwrap();
+    encrypt::encrypt_file(&other, &encrypted, &encrypt::load_key(&key_path).unwrap()).unwrap();
+
+    files.move_and_symlink(&[&netrc]).unwrap();
+    files.symlink_back_home(&[&netrc]).unwrap();
+
+    home_dir.child(".netrc").assert("password correcthorse");
+
+    // ... and restored readable only by the user, if they're missing.
+    fs::remove_file(home_dir.child(".netrc")).unwrap();
+    files.symlink_back_home(&[&netrc]).unwrap();
+
+    home_dir.child(".netrc").assert("password correcthorse");
+    assert_eq!(
+        fs::metadata(home_dir.child(".netrc"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777,
+        0o600
+    );
+
+    // Removing the file leaves the decrypted version.
+    files.remove_and_restore(&[&netrc]).unwrap();
+
+    assert!(!encrypted.exists());
+    home_dir.child(".netrc").assert("password correcthorse");
+
+    // Without a key, encrypted files can't be synced.
+    files.set_encryption(vec![netrc.clone()], None, key_path.to_path_buf());
+    assert!(files.move_and_symlink(&[&netrc]).is_err());
+}
+
+/// Test that tracked files which no `include` entry covers are orphans, but not the repository's own files.
+#[test]
+fn test_orphans() {
+    let tracked = [
+        ".gitattributes",
+        ".config/app/config",
+        ".config/nvim/init.lua",
+        ".ssh/config.age",
+        ".gitconfig##hostname.laptop",
+        ".zshrc",
+    ]
+    .map(PathBuf::from);
+    let include = [".config/nvim", ".ssh/config", ".gitconfig"].map(PathBuf::from);
+
+    assert_eq!(
+        orphans(&tracked, &include, &[]),
+        vec![PathBuf::from(".config/app/config"), PathBuf::from(".zshrc")]
+    );
+}
+
+/// Test that the managed paths covering changed files in the repository are found, including encrypted ones.
+#[test]
+fn test_covering() {
+    let changed = [".config/nvim/init.lua", ".ssh/config.age", ".gitattributes"].map(PathBuf::from);
+    let include = [".config/nvim", ".ssh/config", ".zshrc"].map(PathBuf::from);
+
+    assert_eq!(
+        covering(&changed, &include, &[]),
+        vec![PathBuf::from(".config/nvim"), PathBuf::from(".ssh/config")]
+    );
+}
+
+/// Test walking folders with a maximum depth, symlinks, and ignored files.
+#[test]
+fn test_walk_dir() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let root = temp.child("root");
+    let other = temp.child("other");
+
+    root.child(".gitignore").write_str("*.log\n").unwrap();
+    root.child("a/b/c.txt").write_str("c").unwrap();
+    root.child("a/debug.log").write_str("log").unwrap();
+    root.child("a/.git/HEAD").write_str("ref").unwrap();
+    root.child("cache/file").write_str("cache").unwrap();
+    other.child("linked.txt").write_str("linked").unwrap();
+    root.child("link").symlink_to_dir(other.path()).unwrap();
+
+    let walked = |config: WalkConfig| {
+        Files::walk_dir(root.path(), &config)
+            .unwrap()
+            .iter()
+            .map(|path| path.to_string_lossy().replace('\\', "/"))
+            .collect_vec()
+    };
+
+    assert_eq!(
+        walked(WalkConfig::default()),
+        vec![
+            ".gitignore",
+            "a",
+            "a/b",
+            "a/b/c.txt",
+            "a/debug.log",
+            "cache",
+            "cache/file",
+            "link"
+        ]
+    );
+    assert_eq!(
+        walked(WalkConfig {
+            max_depth: Some(1),
+            ..WalkConfig::default()
+        }),
+        vec![".gitignore", "a", "cache", "link"]
+    );
+    assert_eq!(
+        walked(WalkConfig {
+            max_depth: Some(2),
+            follow_links: true,
+            gitignore: true,
+            ignore: vec!["cache/".to_string()],
+        }),
+        vec![".gitignore", "a", "a/b", "link", "link/linked.txt"]
+    );
+    assert!(Files::walk_dir(
+        root.path(),
+        &WalkConfig {
+            ignore: vec!["a/**/[".to_string()],
+            ..WalkConfig::default()
+        }
+    )
+    .is_err());
+}
+
+/// Test resolving glob patterns in the managed paths against both `home_dir` and `file_dir`.
+#[test]
+fn test_resolve_globs() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    home_dir.child(".config/a.toml").write_str("a").unwrap();
+    home_dir.child(".config/app/b.toml").write_str("b").unwrap();
+    home_dir.child(".config/c.json").write_str("c").unwrap();
+    file_dir.child(".config/d.toml").write_str("d").unwrap();
+    file_dir.child(".gitignore").write_str("").unwrap();
+
+    assert_eq!(
+        file_manager
+            .resolve_globs(&[".zshrc", ".config/*.toml", ".config/**", ".*"])
+            .unwrap(),
+        vec![PathBuf::from(".zshrc"), PathBuf::from(".config"),]
+    );
+    assert_eq!(
+        file_manager
+            .resolve_globs(&[".config/*.toml", ".config/**/*.toml"])
+            .unwrap(),
+        vec![
+            PathBuf::from(".config/a.toml"),
+            PathBuf::from(".config/d.toml"),
+            PathBuf::from(".config/app/b.toml"),
+        ]
+    );
+}
+
+/// Records the calls to `Progress`, for `test_sync_progress`.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Progress for Recorder {
+    fn start(&self, file: &Path) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("start {}", file.display()));
+    }
+
+    fn finish(&self, file: &Path) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("finish {}", file.display()));
+    }
+}
+
+/// Test if `sync` syncs the files one by one, and reports its progress.
+#[test]
+fn test_sync_progress() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let home_dir = temp.child("home");
+    let file_dir = temp.child("files");
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+    let recorder = Recorder::default();
+
+    file_dir.create_dir_all().unwrap();
+    home_dir.child("foo").touch().unwrap();
+    home_dir.child("bar/baz").touch().unwrap();
+    file_manager.set_progress(Some(Box::new(recorder.clone())));
+
+    file_manager.sync(&["foo", "bar"]).unwrap();
+
+    assert_eq!(
+        *recorder.0.lock().unwrap(),
+        ["start foo", "finish foo", "start bar", "finish bar"]
+    );
+    assert_eq!(file_manager.link_state("foo"), LinkState::Intact);
+    assert_eq!(file_manager.link_state("bar"), LinkState::Intact);
+}
+
+/// Test that operations in roots that aren't allowed are only run again with more permissions if their root
+/// escalates, and that everything else fails like it did.
+#[test]
+fn test_escalation() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let (etc, file_dir) = (temp.child("etc"), temp.child("files"));
+    let (hosts, copy) = (etc.child("hosts"), file_dir.child("@etc/hosts"));
+    let escalator = Escalator::new(
+        vec![Root {
+            path: etc.to_path_buf(),
+            target: None,
+            copies: true,
+            escalate: Escalation::Never,
+        }],
+        file_dir.to_path_buf(),
+    );
+    let operation = Operation::Move {
+        from: hosts.path(),
+        to: copy.path(),
+    };
+    let denied = || std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+    let fail = |source| IoError::Delete {
+        source,
+        path: PathBuf::new(),
+    };
+
+    assert_eq!(
+        operation.command(),
+        [
+            OsStr::new("mv"),
+            "-f".as_ref(),
+            "--".as_ref(),
+            hosts.as_os_str(),
+            copy.as_os_str()
+        ]
+    );
+
+    // The root doesn't escalate, which the error says.
+    match escalator.retry(operation, denied(), fail) {
+        Err(IoError::NeedsPermissions { path, root, .. }) => {
+            assert_eq!(path, hosts.path());
+            assert_eq!(root, etc.path());
+        }
+        err => panic!("expected the root to need permissions, got {:?}", err),
+    }
+
+    // Other errors, and operations outside of roots, aren't run again.
+    assert!(matches!(
+        escalator.retry(
+            operation,
+            std::io::Error::from(std::io::ErrorKind::NotFound),
+            fail
+        ),
+        Err(IoError::Delete { .. })
+    ));
+    assert!(matches!(
+        escalator.retry(Operation::Delete(file_dir.path()), denied(), fail),
+        Err(IoError::Delete { .. })
+    ));
+}
+
+/// Test that ignored files/folders are told apart, and that resolving glob patterns skips them.
+#[test]
+fn test_ignored() {
+    let temp: assert_fs::TempDir = assert_fs::TempDir::new().unwrap();
+    let (home_dir, file_dir) = (temp.child("home"), temp.child("files"));
+    let mut file_manager = Files::init(home_dir.path().to_owned(), file_dir.path().to_owned());
+
+    file_manager
+        .set_ignored(&[
+            "*.swp".to_string(),
+            ".config/fish/fish_variables".to_string(),
+            "cache/".to_string(),
+        ])
+        .unwrap();
+
+    home_dir.child(".config/fish/config.fish").touch().unwrap();
+    home_dir
+        .child(".config/fish/fish_variables")
+        .touch()
+        .unwrap();
+    home_dir
+        .child(".config/fish/.config.fish.swp")
+        .touch()
+        .unwrap();
+    home_dir.child(".config/nvim/cache/lazy").touch().unwrap();
+
+    assert!(file_manager.is_ignored(".config/fish/fish_variables"));
+    assert!(file_manager.is_ignored(".config/nvim/cache/lazy/state"));
+    assert!(!file_manager.is_ignored(".config/fish/config.fish"));
+    assert!(!file_manager.is_ignored(".config/fish"));
+
+    assert_eq!(
+        file_manager.resolve_globs(&[".config/fish/*"]).unwrap(),
+        [PathBuf::from(".config/fish/config.fish")]
+    );
+    assert!(file_manager.set_ignored(&["[z-a".to_string()]).is_err());
+}
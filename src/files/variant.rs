@@ -0,0 +1,97 @@
+use crate::bundle;
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+};
+
+/// What separates the name of a file/folder in the repository from the conditions of the machines it's for, e.g.
+/// `.gitconfig##hostname.work-laptop` or `.zshrc##os.Darwin`.
+pub const SEPARATOR: &str = "##";
+
+/// What separates the conditions of a variant that has more than one, e.g. `.zshrc##os.Linux,hostname.server`.
+const CONDITION_SEPARATOR: char = ',';
+
+/// The machine that variants are picked for (see `Files::variant`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Machine {
+    /// The hostname of the machine, if it's known.
+    pub hostname: Option<String>,
+
+    /// The name of the operating system of the machine, like `uname` prints it (see `os_name`), if it's known.
+    pub os: Option<String>,
+}
+
+impl Machine {
+    /// Gets the machine `dotbak` runs on.
+    pub fn current() -> Self {
+        Self {
+            hostname: bundle::hostname(),
+            os: Some(os_name().to_string()),
+        }
+    }
+
+    /// Gets how specific a variant with `conditions` (the part of its name after `SEPARATOR`) is for this machine,
+    /// or `None` if it isn't for this machine. Each condition is `<kind>.<value>`, where the kind is `hostname` or
+    /// `os`, and all of them have to match (ignoring case). Hostnames count for more than operating systems, so the
+    /// variant for the machine itself wins over the one for its operating system. Unknown kinds never match.
+    pub fn specificity(&self, conditions: &str) -> Option<u32> {
+        conditions
+            .split(CONDITION_SEPARATOR)
+            .map(|condition| {
+                let (kind, value) = condition.split_once('.')?;
+
+                match kind {
+                    "hostname" => self
+                        .hostname
+                        .as_ref()
+                        .filter(|hostname| hostname.eq_ignore_ascii_case(value))
+                        .map(|_| 2),
+                    "os" => self
+                        .os
+                        .as_ref()
+                        .filter(|os| os.eq_ignore_ascii_case(value))
+                        .map(|_| 1),
+                    _ => None,
+                }
+            })
+            .sum()
+    }
+}
+
+/// Gets the name of the operating system `dotbak` runs on, like `uname` prints it, e.g. `Linux` or `Darwin`.
+pub fn os_name() -> &'static str {
+    match std::env::consts::OS {
+        "linux" | "android" => "Linux",
+        "macos" | "ios" => "Darwin",
+        "windows" => "Windows",
+        "freebsd" => "FreeBSD",
+        "openbsd" => "OpenBSD",
+        "netbsd" => "NetBSD",
+        other => other,
+    }
+}
+
+/// Splits the name of the variant `name` into the name of the file/folder it's a variant of and its conditions, or
+/// returns `None` if it isn't a variant.
+pub fn split(name: &str) -> Option<(&str, &str)> {
+    name.split_once(SEPARATOR)
+        .filter(|(base, conditions)| !base.is_empty() && !conditions.is_empty())
+}
+
+/// Gets the path of the file/folder that `path` (relative to the repository) is or is inside of a variant of, i.e.
+/// `path` without the conditions in its components. Other paths are returned as they are.
+pub fn base<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    path.as_ref()
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => match name.to_str().and_then(split) {
+                Some((base, _)) => OsString::from(base),
+                None => name.to_os_string(),
+            },
+            other => other.as_os_str().to_os_string(),
+        })
+        .collect()
+}
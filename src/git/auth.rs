@@ -0,0 +1,156 @@
+use crate::errors::{io::IoError, Result};
+use age::secrecy::{ExposeSecret, SecretString};
+use std::process::Command;
+
+/// The environment variable with an access token for HTTPS remotes.
+pub const TOKEN_VAR: &str = "DOTBAK_GIT_TOKEN";
+
+/// The environment variable with the username for the access token. See `DEFAULT_USERNAME`.
+pub const USERNAME_VAR: &str = "DOTBAK_GIT_USERNAME";
+
+/// The username sent with access tokens if there is none configured. Hosts like GitHub ignore the username when
+/// authenticating with a token, but git needs one.
+pub const DEFAULT_USERNAME: &str = "x-access-token";
+
+/// The credential helper that answers git's requests for credentials with `USERNAME_VAR` and `TOKEN_VAR`. It's passed
+/// in the environment instead of being written to a file, so the token never ends up on disk.
+const CREDENTIAL_HELPER: &str = "!f() { test \"$1\" = get && printf 'username=%s\\npassword=%s\\n' \"$DOTBAK_GIT_USERNAME\" \"$DOTBAK_GIT_TOKEN\"; }; f";
+
+/// What git's error output says about failing to authenticate with the remote.
+const HTTPS_FAILURES: &[&str] = &[
+    "Authentication failed for",
+    "could not read Username",
+    "could not read Password",
+    "terminal prompts disabled",
+    "The requested URL returned error: 401",
+    "The requested URL returned error: 403",
+];
+
+/// What ssh's error output says about failing to authenticate with the remote.
+const SSH_FAILURES: &[&str] = &[
+    "Permission denied (publickey",
+    "Host key verification failed",
+];
+
+/// The credentials for HTTPS remotes: a username and an access token (or password).
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    /// The username.
+    username: String,
+
+    /// The access token.
+    token: SecretString,
+}
+
+/// How authenticating with the remote failed. See `AuthFailure::detect`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthFailure {
+    /// The remote is accessed over HTTPS, and needs (other) credentials.
+    Https,
+
+    /// The remote is accessed over SSH, and didn't accept the key (or the host isn't known).
+    Ssh,
+}
+
+impl Credentials {
+    /// Creates the credentials for `token`, sent with `username` (or `DEFAULT_USERNAME`).
+    pub fn new(username: Option<&str>, token: SecretString) -> Credentials {
+        Credentials {
+            username: username.unwrap_or(DEFAULT_USERNAME).to_string(),
+            token,
+        }
+    }
+
+    /// Gets the credentials from the `DOTBAK_GIT_TOKEN` and `DOTBAK_GIT_USERNAME` environment variables, falling back
+    /// to `username` for the latter. Returns `None` if there is no token.
+    pub fn from_env(username: Option<&str>) -> Option<Credentials> {
+        let token = std::env::var(TOKEN_VAR)
+            .ok()
+            .filter(|token| !token.is_empty())?;
+        let env_username = std::env::var(USERNAME_VAR)
+            .ok()
+            .filter(|name| !name.is_empty());
+
+        Some(Credentials::new(
+            env_username.as_deref().or(username),
+            SecretString::from(token),
+        ))
+    }
+
+    /// Gets the token from the output of the shell command `command` (e.g. `gh auth token`, or `pass show github`),
+    /// sent with `username`. Surrounding whitespace is trimmed from the output.
+    pub fn from_command(command: &str, username: Option<&str>) -> Result<Credentials> {
+        let args = vec!["-c".to_string(), command.to_string()];
+        let output = Command::new("sh")
+            .args(&args)
+            .output()
+            .map_err(|err| IoError::CommandIO {
+                source: err,
+                command: "sh".to_string(),
+                args: args.clone(),
+            })?;
+
+        // The token isn't part of the error, as it shouldn't end up in logs.
+        if !output.status.success() {
+            return Err(IoError::CommandRun {
+                command: "sh".to_string(),
+                args,
+                stdout: String::new(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            }
+            .into());
+        }
+
+        let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        Ok(Credentials::new(username, SecretString::from(token)))
+    }
+
+    /// Gets the username sent with the token.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Gets the access token, for authenticating without the `git` binary.
+    #[cfg(feature = "libgit2")]
+    pub(super) fn token(&self) -> &str {
+        self.token.expose_secret()
+    }
+
+    /// Makes the git command `command` authenticate with these credentials, replacing any credential helpers that
+    /// are configured.
+    pub(super) fn apply(&self, command: &mut Command) {
+        command
+            .env("GIT_CONFIG_COUNT", "2")
+            .env("GIT_CONFIG_KEY_0", "credential.helper")
+            .env("GIT_CONFIG_VALUE_0", "")
+            .env("GIT_CONFIG_KEY_1", "credential.helper")
+            .env("GIT_CONFIG_VALUE_1", CREDENTIAL_HELPER)
+            .env(USERNAME_VAR, &self.username)
+            .env(TOKEN_VAR, self.token.expose_secret());
+    }
+}
+
+impl AuthFailure {
+    /// Checks the error output `stderr` of a git command for a failure to authenticate with the remote.
+    pub fn detect(stderr: &str) -> Option<AuthFailure> {
+        if HTTPS_FAILURES
+            .iter()
+            .any(|failure| stderr.contains(failure))
+        {
+            Some(AuthFailure::Https)
+        } else if SSH_FAILURES.iter().any(|failure| stderr.contains(failure)) {
+            Some(AuthFailure::Ssh)
+        } else {
+            None
+        }
+    }
+
+    /// Gets help on setting up authentication for the remote.
+    pub fn help(&self) -> &'static str {
+        match self {
+            AuthFailure::Https => "Set `DOTBAK_GIT_TOKEN` to an access token, set `git.token_command` to a command printing one, or use an SSH URL for the remote.",
+            AuthFailure::Ssh => "Make sure your SSH key (e.g. from `ssh-keygen -t ed25519`) is added to your account on the remote's host, and that `ssh -T git@<host>` works.",
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use super::{Context, GitBackend};
+use crate::{
+    config::git::PullStrategy,
+    errors::Result,
+    git::{
+        parse_log, run_arbitrary_git_command, run_streamed_git_command, split_paths, CloneOptions,
+        LogEntry, Remote, Signing, COMPRESS_OPTIONS, HOST_TRAILER, LOG_FORMAT, SEQUENCE_TRAILER,
+        SKIP_LFS_OPTIONS, SUBMODULES_FILE,
+    },
+    platform,
+};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+/// The backend that runs the `git` binary. This is the default, and what all the operations that aren't part of
+/// `GitBackend` use.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CliBackend;
+
+impl GitBackend for CliBackend {
+    fn commit(
+        &self,
+        context: &Context,
+        message: &str,
+        sequence: bool,
+        host: Option<&str>,
+    ) -> Result<[Output; 2]> {
+        // Run the add command.
+        let add = run(context, &["add", "."])?;
+
+        // If nothing is staged, there is nothing to commit. `git diff --quiet` exits with 1 (which is an error
+        // here) if there are differences.
+        if let Ok(unchanged) = run(context, &["diff", "--cached", "--quiet"]) {
+            return Ok([add, unchanged]);
+        }
+
+        // Run the commit command, signed and with the next sequence number and the host if needed.
+        let mut args = signing_options(context);
+        args.extend(["commit".to_string(), "-am".to_string(), message.to_string()]);
+
+        if context.signing.is_some() {
+            args.push("--gpg-sign".to_string());
+        }
+
+        if sequence {
+            args.push("--trailer".to_string());
+            args.push(format!("{}: {}", SEQUENCE_TRAILER, next_sequence(context)?));
+        }
+
+        if let Some(host) = host {
+            args.push("--trailer".to_string());
+            args.push(format!("{}: {}", HOST_TRAILER, host));
+        }
+
+        let commit = run(context, &args)?;
+
+        Ok([add, commit])
+    }
+
+    fn push(&self, context: &Context, remote: &str, refspec: &str) -> Result<Output> {
+        if context.low_bandwidth {
+            let args = [COMPRESS_OPTIONS, &["push", remote, refspec]].concat();
+            run_with_progress(context, &args)
+        } else {
+            run_with_progress(context, &["push", remote, refspec])
+        }
+    }
+
+    fn pull(
+        &self,
+        context: &Context,
+        remote: &str,
+        branch: &str,
+        strategy: PullStrategy,
+    ) -> Result<Output> {
+        let strategy = match strategy {
+            PullStrategy::Merge => "--no-rebase",
+            PullStrategy::Rebase => "--rebase",
+            PullStrategy::FfOnly => "--ff-only",
+        };
+
+        // Merges (and rebased commits) are signed like the commits made with `commit`.
+        let mut args = signing_options(context);
+
+        if context.low_bandwidth {
+            args.extend(SKIP_LFS_OPTIONS.iter().map(|arg| arg.to_string()));
+        }
+
+        args.extend(["pull", strategy].map(str::to_string));
+
+        if context.signing.is_some() {
+            args.push("--gpg-sign".to_string());
+        }
+
+        if context.low_bandwidth {
+            args.push("--no-tags".to_string());
+        }
+
+        args.extend(["--recurse-submodules", remote, branch].map(str::to_string));
+
+        let output = run_with_progress(context, &args)?;
+
+        // Pulling only updates the submodules that are checked out already, not the ones that were just added.
+        if context.path.join(SUBMODULES_FILE).exists() {
+            run(context, &["submodule", "update", "--init", "--recursive"])?;
+        }
+
+        Ok(output)
+    }
+
+    fn clone(&self, context: &Context, url: &str, options: &CloneOptions) -> Result<Output> {
+        // Low bandwidth clones are shallow unless a depth is given.
+        let depth = options
+            .depth
+            .or(context.low_bandwidth.then_some(1))
+            .map(|depth| depth.to_string());
+        let filter = options
+            .filter
+            .as_ref()
+            .map(|filter| format!("--filter={}", filter));
+
+        let mut args = match context.low_bandwidth {
+            true => [SKIP_LFS_OPTIONS, &["clone", "--no-tags"]].concat(),
+            false => vec!["clone"],
+        };
+        args.push("--recurse-submodules");
+
+        if let Some(depth) = &depth {
+            args.extend(["--depth", depth, "--shallow-submodules"]);
+        }
+
+        if let Some(filter) = &filter {
+            args.push(filter);
+        }
+
+        if options.sparse {
+            args.push("--sparse");
+        }
+
+        args.extend([url, "."]);
+
+        run_with_progress(context, &args)
+    }
+
+    fn status(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let output = run(
+            context,
+            &[
+                "--no-optional-locks",
+                "status",
+                "--porcelain",
+                "-z",
+                "--untracked-files=all",
+            ],
+        )?;
+
+        // Renames are followed by their original path, which isn't a change of its own.
+        let mut entries = output.stdout.split(|b| *b == 0).filter(|e| e.len() > 3);
+        let mut changed = vec![];
+
+        while let Some(entry) = entries.next() {
+            if entry.starts_with(b"R") || entry.starts_with(b"C") {
+                entries.next();
+            }
+
+            changed.push(PathBuf::from(platform::os_string_from_bytes(&entry[3..])));
+        }
+
+        Ok(changed)
+    }
+
+    fn log(
+        &self,
+        context: &Context,
+        path: Option<&Path>,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogEntry>> {
+        if !has_commits(context) {
+            return Ok(vec![]);
+        }
+
+        let limit = limit.map(|limit| format!("--max-count={}", limit));
+        let mut args = [
+            "log",
+            "--date=format:%Y-%m-%d %H:%M",
+            "--name-only",
+            "-z",
+            LOG_FORMAT,
+        ]
+        .map(OsStr::new)
+        .to_vec();
+
+        if let Some(limit) = &limit {
+            args.push(OsStr::new(limit));
+        }
+
+        if let Some(path) = path {
+            args.extend([OsStr::new("--follow"), OsStr::new("--"), path.as_os_str()]);
+        }
+
+        let output = run(context, &args)?;
+
+        Ok(parse_log(&output.stdout))
+    }
+
+    fn init(&self, context: &Context, branch: &str) -> Result<Output> {
+        run(context, &["init", "--initial-branch", branch, "."])
+    }
+
+    fn resolve(&self, context: &Context, rev: &str) -> Option<String> {
+        run(context, &["rev-parse", "--verify", "--quiet", rev])
+            .ok()
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn current_branch(&self, context: &Context) -> Option<String> {
+        let output = run(context, &["symbolic-ref", "--quiet", "--short", "HEAD"]).ok()?;
+
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn ahead_behind(
+        &self,
+        context: &Context,
+        rev: &str,
+        upstream: Option<&str>,
+    ) -> Result<(usize, usize)> {
+        let Some(upstream) = upstream else {
+            let output = run(context, &["rev-list", "--count", rev])?;
+            let ahead = String::from_utf8_lossy(&output.stdout).trim().parse();
+
+            return Ok((ahead.unwrap_or(0), 0));
+        };
+
+        // The output is the number of commits only in `rev`, and the number only in `upstream`.
+        let range = format!("{}...{}", rev, upstream);
+        let output = run(context, &["rev-list", "--left-right", "--count", &range])?;
+        let output = String::from_utf8_lossy(&output.stdout);
+        let mut counts = output
+            .split_whitespace()
+            .map(|count| count.parse().unwrap_or(0));
+
+        Ok((counts.next().unwrap_or(0), counts.next().unwrap_or(0)))
+    }
+
+    fn uncommitted_changes(&self, context: &Context) -> Result<usize> {
+        // Don't refresh the index, which would conflict with other processes writing to the repository (see
+        // `LockMode::Shared`).
+        let output = run(
+            context,
+            &["--no-optional-locks", "status", "--porcelain", "-z"],
+        )?;
+
+        // Renames are followed by their original path, which isn't a change of its own.
+        let mut entries = output.stdout.split(|b| *b == 0).filter(|e| !e.is_empty());
+        let mut count = 0;
+
+        while let Some(entry) = entries.next() {
+            if entry.starts_with(b"R") || entry.starts_with(b"C") {
+                entries.next();
+            }
+
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    fn count_changed_files(
+        &self,
+        context: &Context,
+        from: Option<&str>,
+        to: &str,
+    ) -> Result<usize> {
+        let output = match from {
+            Some(from) => run(context, &["diff", "--name-only", "-z", from, to])?,
+            None => run(context, &["ls-tree", "-r", "-z", "--name-only", to])?,
+        };
+
+        Ok(split_paths(&output.stdout).len())
+    }
+
+    fn renames(&self, context: &Context, from: &str, to: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let output = run(
+            context,
+            &["diff", "--name-status", "--find-renames", "-z", from, to],
+        )?;
+
+        // Each entry is the status followed by its path, or by the old and the new path for renames and copies.
+        let mut fields = split_paths(&output.stdout).into_iter();
+        let mut renames = vec![];
+
+        while let Some(status) = fields.next() {
+            let status = platform::os_str_bytes(status.as_os_str());
+
+            if status.starts_with(b"R") {
+                if let Some((old, new)) = fields.next().zip(fields.next()) {
+                    renames.push((old, new));
+                }
+            } else if status.starts_with(b"C") {
+                fields.nth(1);
+            } else {
+                fields.next();
+            }
+        }
+
+        Ok(renames)
+    }
+
+    fn newest_commit_time(&self, context: &Context) -> Result<Option<i64>> {
+        let output = run(
+            context,
+            &[
+                "for-each-ref",
+                "--sort=-committerdate",
+                "--count=1",
+                "--format=%(committerdate:unix)",
+                "refs/heads",
+                "refs/remotes",
+            ],
+        )?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+    }
+
+    fn stash_push(&self, context: &Context) -> Result<Output> {
+        run(context, &["stash", "push", "--include-untracked"])
+    }
+
+    fn stash_pop(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let Err(err) = run(context, &["stash", "pop"]) else {
+            return Ok(vec![]);
+        };
+
+        let conflicts = run(context, &["diff", "--name-only", "--diff-filter=U", "-z"])?;
+
+        match split_paths(&conflicts.stdout) {
+            conflicts if conflicts.is_empty() => Err(err),
+            conflicts => Ok(conflicts),
+        }
+    }
+
+    fn untrack_ignored(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let output = run(
+            context,
+            &[
+                "ls-files",
+                "-z",
+                "--cached",
+                "--ignored",
+                "--exclude-standard",
+            ],
+        )?;
+        let ignored = split_paths(&output.stdout);
+
+        if !ignored.is_empty() {
+            let mut args = ["rm", "-r", "--cached", "--quiet", "--"]
+                .map(OsStr::new)
+                .to_vec();
+            args.extend(ignored.iter().map(|path| path.as_os_str()));
+            run(context, &args)?;
+        }
+
+        Ok(ignored)
+    }
+
+    fn remotes(&self, context: &Context) -> Result<Vec<Remote>> {
+        let output = run(context, &["remote", "-v"])?;
+
+        // Every remote is listed twice, as `<name>\t<url> (fetch)` and `<name>\t<url> (push)`.
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_suffix(" (fetch)"))
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, url)| Remote {
+                name: name.to_string(),
+                url: url.to_string(),
+            })
+            .collect())
+    }
+
+    fn set_remote(&self, context: &Context, name: &str, url: &str) -> Result<Output> {
+        // `git remote` lists one remote name per line, so this doesn't depend on git's (localized) messages.
+        let remotes = run(context, &["remote"])?;
+        let exists = remotes
+            .stdout
+            .split(|b| *b == b'\n')
+            .any(|remote| remote == name.as_bytes());
+
+        // If the remote could not be found, create it.
+        if !exists {
+            return run(context, &["remote", "add", name, url]);
+        }
+
+        run(context, &["remote", "set-url", name, url])
+    }
+
+    fn has_remote_branch(&self, context: &Context, remote: &str, branch: &str) -> bool {
+        // `--exit-code` makes `ls-remote` exit with 2 (which is an error here) if nothing matches.
+        run(
+            context,
+            &["ls-remote", "--exit-code", "--heads", remote, branch],
+        )
+        .is_ok()
+    }
+
+    fn config(&self, context: &Context, key: &str) -> Option<String> {
+        let output = run(context, &["config", "--get", key]).ok()?;
+
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    fn set_config(&self, context: &Context, key: &str, value: &str) -> Result<Output> {
+        run(context, &["config", "--local", key, value])
+    }
+}
+
+/// Runs git with `args` in the repository.
+fn run<S>(context: &Context, args: &[S]) -> Result<Output>
+where
+    S: AsRef<OsStr>,
+{
+    run_arbitrary_git_command(context.path, args, context.credentials, context.timeouts)
+}
+
+/// Runs git with `args` (for a command talking to the remote, like `push`) in the repository, reporting its progress
+/// to `context.progress` while it runs if it's set.
+fn run_with_progress<S>(context: &Context, args: &[S]) -> Result<Output>
+where
+    S: AsRef<OsStr>,
+{
+    let Some(progress) = context.progress else {
+        return run(context, args);
+    };
+
+    // Git only reports its progress to terminals, unless it's asked to. The flag goes right after the subcommand.
+    let mut args = args.iter().map(|arg| arg.as_ref()).collect::<Vec<_>>();
+    let subcommand = args
+        .iter()
+        .position(|arg| ["push", "pull", "clone"].map(OsStr::new).contains(arg))
+        .map_or(0, |index| index + 1);
+    args.insert(subcommand, OsStr::new("--progress"));
+
+    run_streamed_git_command(
+        context.path,
+        &args,
+        context.credentials,
+        context.timeouts,
+        Some(progress),
+    )
+}
+
+/// Gets the options for git to sign commits with the format and key of `context.signing`, if they're set (see
+/// `Signing::options`). Signing itself is turned on with `--gpg-sign`.
+fn signing_options(context: &Context) -> Vec<String> {
+    context.signing.map(Signing::options).unwrap_or_default()
+}
+
+/// Checks if the current branch has any commits yet.
+fn has_commits(context: &Context) -> bool {
+    run(context, &["rev-parse", "--verify", "--quiet", "HEAD"]).is_ok()
+}
+
+/// Gets the sequence number for the next commit: one more than the highest sequence number in the history of the
+/// current branch, or 1 if there is none.
+fn next_sequence(context: &Context) -> Result<u64> {
+    if !has_commits(context) {
+        return Ok(1);
+    }
+
+    let format = format!(
+        "--format=%(trailers:key={},valueonly,separator=%x0A)",
+        SEQUENCE_TRAILER
+    );
+    let output = run(context, &["log", &format])?;
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse::<u64>().ok())
+        .max()
+        .unwrap_or(0)
+        + 1)
+}
@@ -0,0 +1,1124 @@
+use super::{Context, GitBackend};
+use crate::{
+    clock,
+    config::git::PullStrategy,
+    errors::{git::GitError, DotbakError, Result},
+    git::{
+        auth::AuthFailure, timeout_for, CloneOptions, Commit, LogEntry, Remote, HOST_TRAILER,
+        SEQUENCE_TRAILER, SUBMODULES_FILE,
+    },
+    platform,
+};
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    AnnotatedCommit, AttrCheckFlags, AutotagOption, ConfigLevel, Cred, CredentialType, Direction,
+    ErrorClass, ErrorCode, FetchOptions, IndexAddOption, PushOptions, RebaseOptions,
+    RemoteCallbacks, Repository, RepositoryInitOptions, Signature, Sort, StashApplyOptions,
+    StashFlags, StatusOptions, SubmoduleUpdateOptions, Time, TreeWalkMode, TreeWalkResult,
+};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+    time::Instant,
+};
+
+/// The name of the backend, for errors.
+const NAME: &str = "libgit2";
+
+/// The username for SSH remotes that don't have one in their URL.
+const DEFAULT_SSH_USERNAME: &str = "git";
+
+/// The SSH keys tried (in `~/.ssh`) if the SSH agent doesn't have a key the remote accepts, in order.
+const SSH_KEYS: &[&str] = &["id_ed25519", "id_ecdsa", "id_rsa"];
+
+/// The result of calls to libgit2.
+type Git2Result<T> = std::result::Result<T, git2::Error>;
+
+/// The backend that uses libgit2 (through the `git2` crate), so that the `GitBackend` operations don't need the
+/// `git` binary. Repositories stay compatible with `git`, so the other operations can still run it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Libgit2Backend;
+
+impl GitBackend for Libgit2Backend {
+    fn commit(
+        &self,
+        context: &Context,
+        message: &str,
+        sequence: bool,
+        host: Option<&str>,
+    ) -> Result<[Output; 2]> {
+        let args = ["commit", "-am", message];
+        let repo = open(context, &args)?;
+
+        // libgit2 doesn't know about sparse checkouts, so it would stage the files outside of them as deleted.
+        if is_sparse(&repo) {
+            return Err(GitError::Unsupported {
+                backend: NAME,
+                operation: "sparse checkouts",
+            }
+            .into());
+        }
+
+        // Stage everything, including deleted files. git-lfs' filters only run in `git`, so the files it tracks
+        // would be committed whole instead of as pointers: staging stops at the first one.
+        let mut lfs_tracked = false;
+        let staged = (|| -> Git2Result<_> {
+            let mut index = repo.index()?;
+            index.add_all(
+                ["*"],
+                IndexAddOption::DEFAULT,
+                Some(&mut |path: &Path, _: &[u8]| match repo.get_attr(
+                    path,
+                    "filter",
+                    AttrCheckFlags::FILE_THEN_INDEX,
+                ) {
+                    Ok(Some("lfs")) => {
+                        lfs_tracked = true;
+                        -1
+                    }
+                    _ => 0,
+                }),
+            )?;
+            index.update_all(["*"], None)?;
+            index.write()?;
+
+            let tree = index.write_tree()?;
+            let head = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+            let unchanged = match &head {
+                Some(head) => head.tree_id() == tree,
+                None => index.is_empty(),
+            };
+
+            Ok((tree, head, unchanged))
+        })();
+
+        if lfs_tracked {
+            return Err(GitError::Unsupported {
+                backend: NAME,
+                operation: "git-lfs",
+            }
+            .into());
+        }
+
+        let (tree, head, unchanged) = staged.map_err(|err| failure(&["add", "."], None, err))?;
+
+        if unchanged {
+            return Ok([output(""), output("")]);
+        }
+
+        if context.signing.is_some() {
+            return Err(unsupported_signing());
+        }
+
+        // Commit, with the next sequence number and the host if needed.
+        let commit = (|| -> Git2Result<_> {
+            let mut trailers = vec![];
+
+            if sequence {
+                trailers.push(format!("{}: {}", SEQUENCE_TRAILER, next_sequence(&repo)?));
+            }
+
+            if let Some(host) = host {
+                trailers.push(format!("{}: {}", HOST_TRAILER, host));
+            }
+
+            let mut message = format!("{}\n", message.trim_end());
+
+            if !trailers.is_empty() {
+                message.push('\n');
+                trailers.iter().for_each(|trailer| {
+                    message.push_str(trailer);
+                    message.push('\n');
+                });
+            }
+
+            let signature = signature(&repo)?;
+            let tree = repo.find_tree(tree)?;
+            let parents = head.iter().collect::<Vec<_>>();
+
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                &message,
+                &tree,
+                &parents,
+            )
+        })()
+        .map_err(|err| failure(&args, None, err))?;
+
+        Ok([
+            output(""),
+            output(&format!("[{}] {}\n", commit, message.trim_end())),
+        ])
+    }
+
+    fn push(&self, context: &Context, remote: &str, refspec: &str) -> Result<Output> {
+        let args = ["push", remote, refspec];
+        let timeout = timeout_for(&args, context.timeouts);
+        let repo = open(context, &args)?;
+
+        // Pushes that the remote rejects (e.g. because they aren't fast-forwards) don't fail by themselves.
+        let rejected = RefCell::new(vec![]);
+
+        (|| -> Git2Result<_> {
+            let mut remote = repo.find_remote(remote)?;
+            let mut callbacks = callbacks(context, timeout.map(|timeout| Instant::now() + timeout));
+            callbacks.push_update_reference(|refname, status| {
+                if let Some(status) = status {
+                    rejected
+                        .borrow_mut()
+                        .push(format!(" ! [rejected] {} ({})", refname, status));
+                }
+
+                Ok(())
+            });
+
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+
+            remote.push(&[full_refspec(refspec)], Some(&mut options))
+        })()
+        .map_err(|err| failure(&args, timeout.map(|timeout| timeout.as_secs()), err))?;
+
+        let rejected = rejected.into_inner();
+
+        if !rejected.is_empty() {
+            return Err(GitError::Command {
+                args: args.map(str::to_string).to_vec(),
+                stdout: String::new(),
+                stderr: rejected.join("\n"),
+            }
+            .into());
+        }
+
+        Ok(output(""))
+    }
+
+    fn pull(
+        &self,
+        context: &Context,
+        remote: &str,
+        branch: &str,
+        strategy: PullStrategy,
+    ) -> Result<Output> {
+        let flag = match strategy {
+            PullStrategy::Merge => "--no-rebase",
+            PullStrategy::Rebase => "--rebase",
+            PullStrategy::FfOnly => "--ff-only",
+        };
+        let args = ["pull", flag, remote, branch];
+        let timeout = timeout_for(&args, context.timeouts);
+        let fail = |err| failure(&args, timeout.map(|timeout| timeout.as_secs()), err);
+        let repo = open(context, &args)?;
+
+        // Fetch the branch into `<remote>/<branch>`, like `git pull` does.
+        let (fetched, url) = (|| -> Git2Result<_> {
+            let mut remote = repo.find_remote(remote)?;
+            let mut options = FetchOptions::new();
+            options.remote_callbacks(callbacks(
+                context,
+                timeout.map(|timeout| Instant::now() + timeout),
+            ));
+
+            if context.low_bandwidth {
+                options.download_tags(AutotagOption::None);
+            }
+
+            let tracking = format!(
+                "refs/remotes/{}/{}",
+                remote.name().unwrap_or_default(),
+                branch
+            );
+            let refspec = format!("+refs/heads/{}:{}", branch, tracking);
+            remote.fetch(&[&refspec], Some(&mut options), None)?;
+
+            let fetched = repo.find_reference(&tracking)?;
+            let url = remote.url().unwrap_or_default().to_string();
+
+            Ok((repo.reference_to_annotated_commit(&fetched)?, url))
+        })()
+        .map_err(fail)?;
+
+        let (analysis, _) = repo.merge_analysis(&[&fetched]).map_err(fail)?;
+
+        let summary = if analysis.is_up_to_date() {
+            "Already up to date.".to_string()
+        } else if analysis.is_unborn() || analysis.is_fast_forward() {
+            fast_forward(&repo, &fetched).map_err(fail)?;
+            format!("Fast-forwarded to {}.", fetched.id())
+        } else if context.signing.is_some() {
+            return Err(unsupported_signing());
+        } else {
+            match strategy {
+                PullStrategy::FfOnly => {
+                    return Err(GitError::Command {
+                        args: args.map(str::to_string).to_vec(),
+                        stdout: String::new(),
+                        stderr: "fatal: Not possible to fast-forward, aborting.".to_string(),
+                    }
+                    .into())
+                }
+                PullStrategy::Merge => {
+                    let message = format!("Merge branch '{}' of {}", branch, url);
+                    merge(&repo, &fetched, &message).map_err(fail)?
+                }
+                PullStrategy::Rebase => rebase(&repo, &fetched).map_err(fail)?,
+            }
+            .map_err(|conflicts| GitError::MergeConflict {
+                args: args.map(str::to_string).to_vec(),
+                stdout: conflicts
+                    .iter()
+                    .map(|path| {
+                        format!("CONFLICT (content): Merge conflict in {}\n", path.display())
+                    })
+                    .collect(),
+                stderr: String::new(),
+            })?
+        };
+
+        // Check out the submodules the new commit records, including the ones that were just added.
+        if context.path.join(SUBMODULES_FILE).exists() {
+            update_submodules(context, &repo).map_err(fail)?;
+        }
+
+        Ok(output(&format!("{}\n", summary)))
+    }
+
+    fn clone(&self, context: &Context, url: &str, options: &CloneOptions) -> Result<Output> {
+        let args = ["clone", url, "."];
+
+        if options.sparse {
+            return Err(GitError::Unsupported {
+                backend: NAME,
+                operation: "sparse checkouts",
+            }
+            .into());
+        }
+
+        if options.filter.is_some() {
+            return Err(GitError::Unsupported {
+                backend: NAME,
+                operation: "filtered clones",
+            }
+            .into());
+        }
+
+        let timeout = timeout_for(&args, context.timeouts);
+        let fail = |err| failure(&args, timeout.map(|timeout| timeout.as_secs()), err);
+
+        let mut fetch_options = FetchOptions::new();
+        fetch_options.remote_callbacks(callbacks(
+            context,
+            timeout.map(|timeout| Instant::now() + timeout),
+        ));
+
+        if context.low_bandwidth {
+            fetch_options.depth(1).download_tags(AutotagOption::None);
+        }
+
+        if let Some(depth) = options.depth {
+            fetch_options.depth(depth.try_into().unwrap_or(i32::MAX));
+        }
+
+        let repo = RepoBuilder::new()
+            .fetch_options(fetch_options)
+            .clone(url, context.path)
+            .map_err(fail)?;
+
+        update_submodules(context, &repo).map_err(fail)?;
+
+        Ok(output(&format!(
+            "Cloned '{}' into '{}'.\n",
+            url,
+            context.path.display()
+        )))
+    }
+
+    fn status(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let args = ["status", "--porcelain", "--untracked-files=all"];
+        let repo = open(context, &args)?;
+
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(true)
+            .renames_head_to_index(true);
+
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .map_err(|err| failure(&args, None, err))?;
+
+        // Staged changes (including renames) come first, as their new path is the one that's committed.
+        Ok(statuses
+            .iter()
+            .filter_map(|entry| {
+                let delta = entry.head_to_index().or(entry.index_to_workdir())?;
+                let path = delta.new_file().path_bytes()?;
+
+                Some(PathBuf::from(platform::os_string_from_bytes(path)))
+            })
+            .collect())
+    }
+
+    fn log(
+        &self,
+        context: &Context,
+        path: Option<&Path>,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogEntry>> {
+        let args = ["log", "--name-only"];
+        let repo = open(context, &args)?;
+
+        if repo.head().is_err() {
+            return Ok(vec![]);
+        }
+
+        log(&repo, path, limit).map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn init(&self, context: &Context, branch: &str) -> Result<Output> {
+        let args = ["init", "--initial-branch", branch, "."];
+
+        if Repository::open(context.path).is_ok() {
+            return Ok(output(&format!(
+                "Reinitialized existing Git repository in {}\n",
+                context.path.display()
+            )));
+        }
+
+        Repository::init_opts(
+            context.path,
+            RepositoryInitOptions::new().initial_head(branch),
+        )
+        .map_err(|err| failure(&args, None, err))?;
+
+        Ok(output(&format!(
+            "Initialized empty Git repository in {}\n",
+            context.path.display()
+        )))
+    }
+
+    fn resolve(&self, context: &Context, rev: &str) -> Option<String> {
+        let repo = Repository::open(context.path).ok()?;
+        let object = repo.revparse_single(rev).ok()?;
+
+        Some(object.id().to_string())
+    }
+
+    fn current_branch(&self, context: &Context) -> Option<String> {
+        let repo = Repository::open(context.path).ok()?;
+
+        // Unlike `Repository::head`, this works on branches without commits too.
+        let head = repo.find_reference("HEAD").ok()?;
+        let branch = head.symbolic_target()?.strip_prefix("refs/heads/")?;
+
+        Some(branch.to_string())
+    }
+
+    fn ahead_behind(
+        &self,
+        context: &Context,
+        rev: &str,
+        upstream: Option<&str>,
+    ) -> Result<(usize, usize)> {
+        let args = ["rev-list", "--left-right", "--count", rev];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let local = repo.revparse_single(rev)?.peel_to_commit()?.id();
+
+            let Some(upstream) = upstream else {
+                let mut walk = repo.revwalk()?;
+                walk.push(local)?;
+
+                return Ok((walk.count(), 0));
+            };
+
+            let upstream = repo.revparse_single(upstream)?.peel_to_commit()?.id();
+
+            repo.graph_ahead_behind(local, upstream)
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn uncommitted_changes(&self, context: &Context) -> Result<usize> {
+        let args = ["status", "--porcelain"];
+        let repo = open(context, &args)?;
+
+        // Like `git status`, untracked folders count as a single change.
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .recurse_untracked_dirs(false)
+            .renames_head_to_index(true);
+
+        let statuses = repo
+            .statuses(Some(&mut options))
+            .map_err(|err| failure(&args, None, err))?;
+
+        Ok(statuses.len())
+    }
+
+    fn count_changed_files(
+        &self,
+        context: &Context,
+        from: Option<&str>,
+        to: &str,
+    ) -> Result<usize> {
+        let args = ["diff", "--name-only", to];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let to = repo.revparse_single(to)?.peel_to_tree()?;
+
+            let Some(from) = from else {
+                let mut count = 0;
+                to.walk(TreeWalkMode::PreOrder, |_, entry| {
+                    if entry.kind() != Some(git2::ObjectType::Tree) {
+                        count += 1;
+                    }
+
+                    TreeWalkResult::Ok
+                })?;
+
+                return Ok(count);
+            };
+
+            // Like `git diff`, renamed files count once.
+            let from = repo.revparse_single(from)?.peel_to_tree()?;
+            let mut diff = repo.diff_tree_to_tree(Some(&from), Some(&to), None)?;
+            diff.find_similar(None)?;
+
+            Ok(diff.deltas().len())
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn renames(&self, context: &Context, from: &str, to: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let args = ["diff", "--name-status", "--find-renames", from, to];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let from = repo.revparse_single(from)?.peel_to_tree()?;
+            let to = repo.revparse_single(to)?.peel_to_tree()?;
+            let mut diff = repo.diff_tree_to_tree(Some(&from), Some(&to), None)?;
+            diff.find_similar(None)?;
+
+            Ok(diff
+                .deltas()
+                .filter(|delta| delta.status() == git2::Delta::Renamed)
+                .filter_map(|delta| {
+                    let old = delta.old_file().path()?.to_path_buf();
+                    let new = delta.new_file().path()?.to_path_buf();
+
+                    Some((old, new))
+                })
+                .collect())
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn newest_commit_time(&self, context: &Context) -> Result<Option<i64>> {
+        let args = ["for-each-ref", "--sort=-committerdate", "--count=1"];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let mut newest = None;
+
+            for reference in repo.references()? {
+                let reference = reference?;
+                let name = reference.name().unwrap_or_default();
+
+                if !name.starts_with("refs/heads/") && !name.starts_with("refs/remotes/") {
+                    continue;
+                }
+
+                if let Ok(commit) = reference.peel_to_commit() {
+                    newest = newest.max(Some(commit.time().seconds()));
+                }
+            }
+
+            Ok(newest)
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn stash_push(&self, context: &Context) -> Result<Output> {
+        let args = ["stash", "push", "--include-untracked"];
+        let mut repo = open(context, &args)?;
+
+        let stash = (|| -> Git2Result<_> {
+            let signature = signature(&repo)?;
+
+            repo.stash_save2(&signature, None, Some(StashFlags::INCLUDE_UNTRACKED))
+        })()
+        .map_err(|err| failure(&args, None, err))?;
+
+        Ok(output(&format!("Saved working directory as {}\n", stash)))
+    }
+
+    fn stash_pop(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let args = ["stash", "pop"];
+        let mut repo = open(context, &args)?;
+
+        let mut options = StashApplyOptions::new();
+        options.checkout_options({
+            let mut checkout = CheckoutBuilder::new();
+            checkout.safe();
+            checkout
+        });
+
+        // The stash is only dropped if it was reapplied without conflicts.
+        let popped = repo.stash_pop(0, Some(&mut options));
+        let conflicts = repo
+            .index()
+            .and_then(|index| conflicts(&index))
+            .map_err(|err| failure(&args, None, err))?;
+
+        match popped {
+            Err(err) if conflicts.is_empty() => Err(failure(&args, None, err).into()),
+            _ => Ok(conflicts),
+        }
+    }
+
+    fn untrack_ignored(&self, context: &Context) -> Result<Vec<PathBuf>> {
+        let args = ["rm", "-r", "--cached", "--quiet"];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let mut index = repo.index()?;
+            let mut ignored = vec![];
+
+            for entry in index.iter() {
+                let path = PathBuf::from(platform::os_string_from_bytes(&entry.path));
+
+                if repo.is_path_ignored(&path)? {
+                    ignored.push(path);
+                }
+            }
+
+            if !ignored.is_empty() {
+                for path in &ignored {
+                    index.remove_path(path)?;
+                }
+
+                index.write()?;
+            }
+
+            Ok(ignored)
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn remotes(&self, context: &Context) -> Result<Vec<Remote>> {
+        let args = ["remote", "-v"];
+        let repo = open(context, &args)?;
+
+        (|| -> Git2Result<_> {
+            let mut remotes = vec![];
+
+            for name in repo.remotes()?.iter().flatten() {
+                let remote = repo.find_remote(name)?;
+
+                remotes.push(Remote {
+                    name: name.to_string(),
+                    url: remote.url().unwrap_or_default().to_string(),
+                });
+            }
+
+            Ok(remotes)
+        })()
+        .map_err(|err| failure(&args, None, err).into())
+    }
+
+    fn set_remote(&self, context: &Context, name: &str, url: &str) -> Result<Output> {
+        let args = ["remote", "set-url", name, url];
+        let repo = open(context, &args)?;
+
+        match repo.find_remote(name) {
+            Ok(_) => repo.remote_set_url(name, url),
+            Err(_) => repo.remote(name, url).map(|_| ()),
+        }
+        .map_err(|err| failure(&args, None, err))?;
+
+        Ok(output(""))
+    }
+
+    fn has_remote_branch(&self, context: &Context, remote: &str, branch: &str) -> bool {
+        let args = ["ls-remote", "--exit-code", "--heads", remote, branch];
+        let timeout = timeout_for(&args, context.timeouts);
+        let Ok(repo) = open(context, &args) else {
+            return false;
+        };
+
+        let listed = (|| -> Git2Result<_> {
+            let mut remote = repo.find_remote(remote)?;
+            let callbacks = callbacks(context, timeout.map(|timeout| Instant::now() + timeout));
+            let connection = remote.connect_auth(Direction::Fetch, Some(callbacks), None)?;
+            let head = format!("refs/heads/{}", branch);
+
+            Ok(connection
+                .list()?
+                .iter()
+                .any(|listed| listed.name() == head))
+        })();
+
+        listed.unwrap_or(false)
+    }
+
+    fn config(&self, context: &Context, key: &str) -> Option<String> {
+        let repo = Repository::open(context.path).ok()?;
+        let config = repo.config().ok()?.snapshot().ok()?;
+
+        config.get_string(key).ok()
+    }
+
+    fn set_config(&self, context: &Context, key: &str, value: &str) -> Result<Output> {
+        let args = ["config", "--local", key, value];
+        let repo = open(context, &args)?;
+
+        repo.config()
+            .and_then(|config| config.open_level(ConfigLevel::Local))
+            .and_then(|mut config| config.set_str(key, value))
+            .map_err(|err| failure(&args, None, err))?;
+
+        Ok(output(""))
+    }
+}
+
+/// Creates the error for signing commits, which needs `gpg` or `ssh-keygen` (and so the `git` binary).
+fn unsupported_signing() -> DotbakError {
+    GitError::Unsupported {
+        backend: NAME,
+        operation: "signed commits",
+    }
+    .into()
+}
+
+/// Opens the repository, failing like the git command run with `args` would.
+fn open(context: &Context, args: &[&str]) -> Result<Repository> {
+    Ok(Repository::open(context.path).map_err(|err| failure(args, None, err))?)
+}
+
+/// Checks if `repo` is a sparse checkout (see `Repository::is_sparse`).
+fn is_sparse(repo: &Repository) -> bool {
+    repo.config()
+        .and_then(|config| config.get_bool("core.sparseCheckout"))
+        .unwrap_or(false)
+}
+
+/// Tells what kind of failure libgit2's error `err` running the equivalent of `git <args>` is, like
+/// `git::classify_failure` does with git's output. `timeout` is how long the operation could run, in seconds.
+fn failure(args: &[&str], timeout: Option<u64>, err: git2::Error) -> GitError {
+    let args = args.iter().map(|arg| arg.to_string()).collect();
+    let stderr = err.message().to_string();
+
+    match (err.code(), err.class()) {
+        (ErrorCode::NotFound, ErrorClass::Config)
+            if stderr.contains("user.name") || stderr.contains("user.email") =>
+        {
+            GitError::MissingIdentity { args, stderr }
+        }
+        (ErrorCode::Auth, class) => {
+            let transport = match class {
+                ErrorClass::Ssh => AuthFailure::Ssh,
+                _ => AuthFailure::Https,
+            };
+
+            GitError::AuthFailed {
+                transport,
+                args,
+                stderr,
+                help: transport.help(),
+            }
+        }
+        (ErrorCode::User | ErrorCode::Timeout, _) if timeout.is_some() => {
+            GitError::CommandTimeout {
+                args,
+                timeout: timeout.unwrap_or_default(),
+            }
+        }
+        (ErrorCode::MergeConflict | ErrorCode::Unmerged, _) => GitError::MergeConflict {
+            args,
+            stdout: String::new(),
+            stderr,
+        },
+        (ErrorCode::Conflict | ErrorCode::Uncommitted | ErrorCode::IndexDirty, _) => {
+            GitError::DirtyWorktree { args, stderr }
+        }
+        (ErrorCode::UnbornBranch, _)
+        | (_, ErrorClass::Net | ErrorClass::Http | ErrorClass::Ssh) => {
+            GitError::RemoteUnreachable { args, stderr }
+        }
+        _ => GitError::Command {
+            args,
+            stdout: String::new(),
+            stderr,
+        },
+    }
+}
+
+/// Creates the callbacks for talking to the remote: they authenticate with it (see `credentials`), and stop the
+/// transfer once it's `deadline`.
+fn callbacks<'a>(context: &Context<'a>, deadline: Option<Instant>) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let credentials = context.credentials;
+    let (mut ssh_attempts, mut https_attempts) = (0, 0);
+
+    callbacks.credentials(move |url, username, allowed| {
+        let username = username.unwrap_or(DEFAULT_SSH_USERNAME);
+
+        if allowed.contains(CredentialType::USERNAME) {
+            Cred::username(username)
+        } else if allowed.contains(CredentialType::SSH_KEY) {
+            // Try the SSH agent first, then the default keys that exist.
+            ssh_attempts += 1;
+
+            match ssh_attempts {
+                1 => Cred::ssh_key_from_agent(username),
+                attempt => ssh_keys()
+                    .get(attempt - 2)
+                    .map(|key| Cred::ssh_key(username, None, key, None))
+                    .unwrap_or_else(|| Err(auth_error(ErrorClass::Ssh, url))),
+            }
+        } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) && https_attempts == 0 {
+            // Use the credentials if there are any, or the credential helpers configured for git otherwise.
+            https_attempts += 1;
+
+            match credentials {
+                Some(credentials) => {
+                    Cred::userpass_plaintext(credentials.username(), credentials.token())
+                }
+                None => git2::Config::open_default()
+                    .and_then(|config| Cred::credential_helper(&config, url, None))
+                    .map_err(|_| auth_error(ErrorClass::Http, url)),
+            }
+        } else {
+            Err(auth_error(ErrorClass::Http, url))
+        }
+    });
+
+    // Report the progress like git does, see `Repository::set_progress`.
+    let (progress, in_time) = (context.progress, move || {
+        deadline.is_none_or(|deadline| Instant::now() < deadline)
+    });
+    let report = move |line: &str| {
+        if let (Some(progress), false) = (progress, line.trim().is_empty()) {
+            progress.report(line.trim());
+        }
+    };
+
+    callbacks.transfer_progress(move |stats| {
+        report(&format!(
+            "Receiving objects: {:>3}% ({}/{})",
+            stats.received_objects() * 100 / stats.total_objects().max(1),
+            stats.received_objects(),
+            stats.total_objects()
+        ));
+        in_time()
+    });
+    callbacks.push_transfer_progress(move |current, total, _| {
+        report(&format!(
+            "Writing objects: {:>3}% ({}/{})",
+            current * 100 / total.max(1),
+            current,
+            total
+        ))
+    });
+    callbacks.sideband_progress(move |data| {
+        String::from_utf8_lossy(data)
+            .split(['\r', '\n'])
+            .filter(|line| !line.trim().is_empty())
+            .for_each(|line| report(&format!("remote: {}", line.trim())));
+        in_time()
+    });
+
+    callbacks
+}
+
+/// Creates the error for failing to authenticate with the remote at `url`. `class` is `Ssh` for SSH remotes.
+fn auth_error(class: ErrorClass, url: &str) -> git2::Error {
+    git2::Error::new(
+        ErrorCode::Auth,
+        class,
+        format!("Authentication failed for '{}'", url),
+    )
+}
+
+/// Gets the default SSH keys (see `SSH_KEYS`) that exist.
+fn ssh_keys() -> Vec<PathBuf> {
+    let Some(ssh_dir) = dirs::home_dir().map(|home| home.join(".ssh")) else {
+        return vec![];
+    };
+
+    SSH_KEYS
+        .iter()
+        .map(|key| ssh_dir.join(key))
+        .filter(|key| key.exists())
+        .collect()
+}
+
+/// Turns a refspec like `main:main` into one with full reference names, which libgit2 needs for pushes.
+fn full_refspec(refspec: &str) -> String {
+    let full = |name: &str| match name.starts_with("refs/") {
+        true => name.to_string(),
+        false => format!("refs/heads/{}", name),
+    };
+
+    match refspec.split_once(':') {
+        Some((src, dst)) => format!("{}:{}", full(src), full(dst)),
+        None => format!("{0}:{0}", full(refspec)),
+    }
+}
+
+/// Gets the signature for commits from git's configuration, dated with the scripted time if there is one (like
+/// `git::git_command` does).
+fn signature(repo: &Repository) -> Git2Result<Signature<'static>> {
+    let signature = repo.signature()?;
+
+    match clock::scripted() {
+        Some(now) => Signature::new(
+            signature.name().unwrap_or_default(),
+            signature.email().unwrap_or_default(),
+            &Time::new(now as i64, 0),
+        ),
+        None => Ok(signature.to_owned()),
+    }
+}
+
+/// Gets the sequence number for the next commit: one more than the highest sequence number in the history of the
+/// current branch, or 1 if there is none.
+fn next_sequence(repo: &Repository) -> Git2Result<u64> {
+    if repo.head().is_err() {
+        return Ok(1);
+    }
+
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+
+    let mut highest = 0;
+
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let trailers = git2::message_trailers_strs(commit.message().unwrap_or_default())?;
+
+        highest = trailers
+            .iter()
+            .filter(|(key, _)| *key == SEQUENCE_TRAILER)
+            .filter_map(|(_, value)| value.trim().parse::<u64>().ok())
+            .fold(highest, u64::max);
+    }
+
+    Ok(highest + 1)
+}
+
+/// Fast-forwards the current branch to `target`, or creates it there if it has no commits yet. The new commit is
+/// checked out first, so nothing moves if uncommitted changes are in the way.
+fn fast_forward(repo: &Repository, target: &AnnotatedCommit) -> Git2Result<()> {
+    let commit = repo.find_commit(target.id())?;
+    repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+
+    let message = format!("pull: Fast-forward to {}", target.id());
+
+    match repo.head() {
+        Ok(mut head) => head.set_target(target.id(), &message).map(|_| ()),
+        Err(_) => {
+            let head = repo.find_reference("HEAD")?;
+            let branch = head.symbolic_target().unwrap_or("refs/heads/main");
+
+            repo.reference(branch, target.id(), true, &message)
+                .map(|_| ())
+        }
+    }
+}
+
+/// Merges `theirs` into the current branch, and commits the merge with `message`. If there are conflicts, the merge
+/// is left in progress (like `git merge` does), and the conflicting paths are returned as the error.
+fn merge(
+    repo: &Repository,
+    theirs: &AnnotatedCommit,
+    message: &str,
+) -> Git2Result<std::result::Result<String, Vec<PathBuf>>> {
+    repo.merge(&[theirs], None, Some(CheckoutBuilder::new().safe()))?;
+
+    let mut index = repo.index()?;
+
+    if index.has_conflicts() {
+        return Ok(Err(conflicts(&index)?));
+    }
+
+    let signature = signature(repo)?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let head = repo.head()?.peel_to_commit()?;
+    let theirs = repo.find_commit(theirs.id())?;
+
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&head, &theirs],
+    )?;
+    repo.cleanup_state()?;
+
+    Ok(Ok(format!("Merged {}.", theirs.id())))
+}
+
+/// Replays the commits of the current branch on top of `upstream`. If there are conflicts, the rebase is left in
+/// progress (like `git rebase` does), and the conflicting paths are returned as the error.
+fn rebase(
+    repo: &Repository,
+    upstream: &AnnotatedCommit,
+) -> Git2Result<std::result::Result<String, Vec<PathBuf>>> {
+    let signature = signature(repo)?;
+    let head = repo.reference_to_annotated_commit(&repo.head()?)?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+
+    let mut options = RebaseOptions::new();
+    options.checkout_options(checkout);
+
+    let mut rebase = repo.rebase(Some(&head), Some(upstream), None, Some(&mut options))?;
+
+    while let Some(operation) = rebase.next() {
+        operation?;
+
+        let index = repo.index()?;
+
+        if index.has_conflicts() {
+            return Ok(Err(conflicts(&index)?));
+        }
+
+        // Commits whose changes are upstream already are left out.
+        match rebase.commit(None, &signature, None) {
+            Err(err) if err.code() != ErrorCode::Applied => return Err(err),
+            _ => (),
+        }
+    }
+
+    rebase.finish(Some(&signature))?;
+
+    Ok(Ok(format!("Rebased onto {}.", upstream.id())))
+}
+
+/// Gets the paths that conflict in `index`.
+fn conflicts(index: &git2::Index) -> Git2Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+
+        if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+            paths.push(PathBuf::from(platform::os_string_from_bytes(&entry.path)));
+        }
+    }
+
+    Ok(paths)
+}
+
+/// Checks out the commits of the submodules of `repo` (recursively), cloning the ones that aren't checked out yet.
+fn update_submodules(context: &Context, repo: &Repository) -> Git2Result<()> {
+    for mut submodule in repo.submodules()? {
+        let mut fetch = FetchOptions::new();
+        fetch.remote_callbacks(callbacks(context, None));
+
+        let mut options = SubmoduleUpdateOptions::new();
+        options.fetch(fetch);
+
+        submodule.update(true, Some(&mut options))?;
+
+        if let Ok(nested) = submodule.open() {
+            update_submodules(context, &nested)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the history of `repo`, newest commit first, like `git log --follow --name-only` does. See
+/// `GitBackend::log`.
+fn log(repo: &Repository, path: Option<&Path>, limit: Option<usize>) -> Git2Result<Vec<LogEntry>> {
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.set_sorting(Sort::TIME)?;
+
+    // The path that's followed, which changes to the old path at renames.
+    let mut path = path.map(Path::to_path_buf);
+    let mut entries = vec![];
+
+    for oid in walk {
+        if limit.is_some_and(|limit| entries.len() >= limit) {
+            break;
+        }
+
+        let commit = repo.find_commit(oid?)?;
+
+        // Like `git log`, merges aren't listed with the files they touched.
+        let files = match commit.parent_count() {
+            0 | 1 => {
+                let old = match commit.parent_count() {
+                    0 => None,
+                    _ => Some(commit.parent(0)?.tree()?),
+                };
+                let mut diff = repo.diff_tree_to_tree(old.as_ref(), Some(&commit.tree()?), None)?;
+                diff.find_similar(None)?;
+
+                diff.deltas()
+                    .filter_map(|delta| {
+                        let new = delta.new_file().path().map(Path::to_path_buf)?;
+                        let old = delta.old_file().path().map(Path::to_path_buf);
+
+                        Some((new, old))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            _ => vec![],
+        };
+
+        let files = match &mut path {
+            Some(followed) => {
+                let Some((new, old)) = files.into_iter().find(|(new, _)| new == followed) else {
+                    continue;
+                };
+
+                if let Some(old) = old {
+                    *followed = old;
+                }
+
+                vec![new]
+            }
+            None => files.into_iter().map(|(new, _)| new).collect(),
+        };
+
+        let author = commit.author();
+        let when = author.when();
+        let date =
+            clock::format_utc((when.seconds() + i64::from(when.offset_minutes()) * 60) as u64);
+
+        entries.push(LogEntry {
+            commit: Commit {
+                hash: commit.id().to_string(),
+                author: author.name().unwrap_or_default().to_string(),
+                email: author.email().unwrap_or_default().to_string(),
+                date: format!("{} {}", &date[..10], &date[11..16]),
+                summary: commit.summary().unwrap_or_default().to_string(),
+            },
+            files,
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Creates the output of an operation that succeeded, printing `stdout`.
+fn output(stdout: &str) -> Output {
+    Output {
+        status: ExitStatus::default(),
+        stdout: stdout.as_bytes().to_vec(),
+        stderr: vec![],
+    }
+}
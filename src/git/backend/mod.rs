@@ -0,0 +1,169 @@
+pub mod cli;
+#[cfg(feature = "libgit2")]
+pub mod libgit2;
+mod tests;
+
+use super::{auth::Credentials, CloneOptions, LogEntry, ProgressHandler, Remote, Signing};
+#[cfg(not(feature = "libgit2"))]
+use crate::errors::git::GitError;
+use crate::{
+    config::git::{Backend, PullStrategy},
+    errors::Result,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+/// The operations `dotbak` does on the repository all the time: creating or cloning it, committing, pushing, pulling
+/// (with the uncommitted changes stashed), setting up remotes, and reading its status and history. `Repository` runs
+/// them with the backend set with `Repository::set_backend`, so that initializing, adding files and syncing can be
+/// done without the `git` binary (see `Backend`). The other operations (e.g. undoing commits or switching hosts)
+/// always run `git`.
+///
+/// The outputs of the operations are what `git` prints, or a summary of what was done for backends that don't run
+/// `git`.
+pub trait GitBackend: Debug + Send + Sync {
+    /// Commits all changes in the repository with `message`. If `sequence` is set, the commit gets a
+    /// `Dotbak-Sequence` trailer with the next sequence number, and if `host` is given, a `Dotbak-Host` trailer with
+    /// it (see `Repository::set_sequence_trailer` and `Repository::set_host`). Nothing is committed if nothing
+    /// changed.
+    ///
+    /// Returns the outputs of staging the changes and of committing them.
+    fn commit(
+        &self,
+        context: &Context,
+        message: &str,
+        sequence: bool,
+        host: Option<&str>,
+    ) -> Result<[Output; 2]>;
+
+    /// Pushes `refspec` (e.g. `main:main`) to the remote `remote`.
+    fn push(&self, context: &Context, remote: &str, refspec: &str) -> Result<Output>;
+
+    /// Pulls the branch `branch` of the remote `remote` into the current branch, reconciling the commits according
+    /// to `strategy`, and checks out the submodules the new commit records.
+    fn pull(
+        &self,
+        context: &Context,
+        remote: &str,
+        branch: &str,
+        strategy: PullStrategy,
+    ) -> Result<Output>;
+
+    /// Clones the repository at `url` into the (empty) repository folder, with its submodules, leaving out what
+    /// `options` say to.
+    fn clone(&self, context: &Context, url: &str, options: &CloneOptions) -> Result<Output>;
+
+    /// Lists the files that changed since the last commit, including untracked and deleted ones. Renamed files are
+    /// listed by their new path. See `Repository::changed_files`.
+    fn status(&self, context: &Context) -> Result<Vec<PathBuf>>;
+
+    /// Gets the history of the repository, newest commit first, with the files each commit touched. See
+    /// `Repository::log`.
+    fn log(
+        &self,
+        context: &Context,
+        path: Option<&Path>,
+        limit: Option<usize>,
+    ) -> Result<Vec<LogEntry>>;
+
+    /// Creates an empty repository in the repository folder, on the branch `branch`. An existing repository is left
+    /// as it is.
+    fn init(&self, context: &Context, branch: &str) -> Result<Output>;
+
+    /// Gets the hash of the object the revision `rev` (e.g. `HEAD`, `origin/main` or `HEAD^{commit}`) names, or
+    /// `None` if there is no such object.
+    fn resolve(&self, context: &Context, rev: &str) -> Option<String>;
+
+    /// Gets the branch that is checked out, or `None` if HEAD is detached.
+    fn current_branch(&self, context: &Context) -> Option<String>;
+
+    /// Counts the commits only in the history of `rev`, and the ones only in the history of `upstream`. Without
+    /// `upstream`, all the commits of `rev` are counted.
+    fn ahead_behind(
+        &self,
+        context: &Context,
+        rev: &str,
+        upstream: Option<&str>,
+    ) -> Result<(usize, usize)>;
+
+    /// Counts the files with uncommitted changes in the working tree, including untracked files. See
+    /// `Repository::uncommitted_changes`.
+    fn uncommitted_changes(&self, context: &Context) -> Result<usize>;
+
+    /// Counts the files that differ between the commits `from` and `to`, or all the files in `to` if there's no
+    /// `from`.
+    fn count_changed_files(&self, context: &Context, from: Option<&str>, to: &str)
+        -> Result<usize>;
+
+    /// Lists the files renamed between the revisions `from` and `to`, as `(old, new)` pairs of paths relative to
+    /// the repository.
+    fn renames(&self, context: &Context, from: &str, to: &str) -> Result<Vec<(PathBuf, PathBuf)>>;
+
+    /// Gets the commit time (in seconds since the UNIX epoch) of the newest commit on any local or remote-tracking
+    /// branch, or `None` if there are no commits.
+    fn newest_commit_time(&self, context: &Context) -> Result<Option<i64>>;
+
+    /// Stashes the uncommitted changes in the repository, including new files.
+    fn stash_push(&self, context: &Context) -> Result<Output>;
+
+    /// Reapplies the newest stash, and drops it. If it conflicts with the commits made since, the conflicting paths
+    /// are returned, and the stash is kept. See `Repository::stash_pop`.
+    fn stash_pop(&self, context: &Context) -> Result<Vec<PathBuf>>;
+
+    /// Stops tracking the files that are ignored, without deleting them. Returns the files (relative to the
+    /// repository).
+    fn untrack_ignored(&self, context: &Context) -> Result<Vec<PathBuf>>;
+
+    /// Lists the remotes of the repository, in no particular order.
+    fn remotes(&self, context: &Context) -> Result<Vec<Remote>>;
+
+    /// Adds the remote `name` with the URL `url`, or changes its URL if it already exists.
+    fn set_remote(&self, context: &Context, name: &str, url: &str) -> Result<Output>;
+
+    /// Checks if the remote `remote` has the branch `branch`. Returns `false` if it can't be reached.
+    fn has_remote_branch(&self, context: &Context, remote: &str, branch: &str) -> bool;
+
+    /// Gets the value of `key` (e.g. `user.name`) as git sees it in the repository, whether it's set in the
+    /// repository's own configuration or a global one, or `None` if it isn't set.
+    fn config(&self, context: &Context, key: &str) -> Option<String>;
+
+    /// Sets `key` to `value` in the repository's own git configuration.
+    fn set_config(&self, context: &Context, key: &str, value: &str) -> Result<Output>;
+}
+
+/// What the backends need to know about the repository to run an operation on it.
+#[derive(Clone, Copy, Debug)]
+pub struct Context<'a> {
+    /// The repository folder.
+    pub path: &'a Path,
+
+    /// Whether to save bandwidth when talking to the remote. See `Repository::set_low_bandwidth`.
+    pub low_bandwidth: bool,
+
+    /// The credentials for HTTPS remotes, if there are any. See `Repository::set_credentials`.
+    pub credentials: Option<&'a Credentials>,
+
+    /// How long in seconds operations may run, by git subcommand. See `Repository::set_timeouts`.
+    pub timeouts: &'a BTreeMap<String, u64>,
+
+    /// How commits are signed, if they are. See `Repository::set_signing`.
+    pub signing: Option<&'a Signing>,
+
+    /// What the progress of clones, pushes and pulls is reported to, if anything. See `Repository::set_progress`.
+    pub progress: Option<&'a ProgressHandler>,
+}
+
+/// Creates the backend `backend`. Returns an error if it isn't built into this `dotbak`.
+pub fn new(backend: Backend) -> Result<Box<dyn GitBackend>> {
+    match backend {
+        Backend::Git => Ok(Box::new(cli::CliBackend)),
+        #[cfg(feature = "libgit2")]
+        Backend::Libgit2 => Ok(Box::new(libgit2::Libgit2Backend)),
+        #[cfg(not(feature = "libgit2"))]
+        Backend::Libgit2 => Err(GitError::BackendUnavailable { backend: "libgit2" }.into()),
+    }
+}
@@ -0,0 +1,172 @@
+#![cfg(test)]
+
+use crate::config::git::Backend;
+#[cfg(not(feature = "libgit2"))]
+use crate::errors::{git::GitError, DotbakError};
+
+/// Test that the backends are only available if they're built in.
+#[test]
+fn test_new() {
+    assert!(super::new(Backend::Git).is_ok());
+
+    #[cfg(feature = "libgit2")]
+    assert!(super::new(Backend::Libgit2).is_ok());
+
+    #[cfg(not(feature = "libgit2"))]
+    assert!(matches!(
+        super::new(Backend::Libgit2),
+        Err(DotbakError::Git(GitError::BackendUnavailable {
+            backend: "libgit2"
+        }))
+    ));
+}
+
+/// Test that the libgit2 backend clones, commits, pushes, pulls, and reads the status and history like the `git`
+/// binary does, so that repositories can be shared between them.
+#[cfg(feature = "libgit2")]
+#[test]
+fn test_libgit2() {
+    use crate::{
+        config::git::PullStrategy,
+        git::{CloneOptions, Repository, SEQUENCE_TRAILER},
+    };
+    use assert_fs::{prelude::*, TempDir};
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+    let other_dir = tmp_dir.child("other");
+    let url = remote_dir.path().to_string_lossy().to_string();
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+
+    // Commits from `git` are cloned and pulled by libgit2, and the other way around.
+    let mut other = Repository::init(other_dir.path(), Some(url.clone())).unwrap();
+    other_dir.child("file").write_str("first").unwrap();
+    other.commit("First commit").unwrap();
+    other.push().unwrap();
+
+    let mut repo = Repository::clone_with_credentials(
+        repo_dir.path(),
+        &url,
+        false,
+        &CloneOptions::default(),
+        None,
+        BTreeMap::new(),
+        Backend::Libgit2,
+    )
+    .unwrap();
+    repo_dir.child("file").assert("first");
+    assert!(repo.changed_files().unwrap().is_empty());
+
+    repo.set_sequence_trailer(true);
+    repo.set_host(Some("laptop".to_string()));
+    repo_dir.child("new").write_str("new").unwrap();
+    repo_dir.child("file").write_str("second").unwrap();
+    assert_eq!(
+        repo.changed_files().unwrap(),
+        [PathBuf::from("file"), PathBuf::from("new")]
+    );
+
+    repo.commit("Second commit").unwrap();
+    assert!(repo.changed_files().unwrap().is_empty());
+    repo.push().unwrap();
+
+    // Nothing is committed if nothing changed.
+    repo.commit("Empty commit").unwrap();
+    assert_eq!(repo.log(None, None).unwrap().len(), 2);
+
+    other.pull().unwrap();
+    other_dir.child("new").assert("new");
+    let trailers = other
+        .arbitrary_command(&["log", "-1", "--format=%(trailers:only)"])
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&trailers.stdout).trim(),
+        format!("{}: 1\nDotbak-Host: laptop", SEQUENCE_TRAILER)
+    );
+
+    // The history reads the same with both backends.
+    let log = repo.log(Some("file".as_ref()), None).unwrap();
+    assert_eq!(log, other.log(Some("file".as_ref()), None).unwrap());
+    assert_eq!(log[0].commit.summary, "Second commit");
+    assert_eq!(log[0].files, [PathBuf::from("file")]);
+    assert_eq!(
+        repo.log(None, Some(1)).unwrap(),
+        other.log(None, Some(1)).unwrap()
+    );
+
+    // Diverged commits are merged, or rebased.
+    other_dir.child("other").write_str("other").unwrap();
+    other.commit("Other commit").unwrap();
+    other.push().unwrap();
+    repo_dir.child("local").write_str("local").unwrap();
+    repo.commit("Local commit").unwrap();
+
+    repo.set_pull_strategy(PullStrategy::FfOnly);
+    assert!(repo.pull().is_err());
+
+    repo.set_pull_strategy(PullStrategy::Rebase);
+    repo.pull().unwrap();
+    repo_dir.child("other").assert("other");
+    assert_eq!(
+        repo.log(None, None).unwrap()[0].commit.summary,
+        "Local commit"
+    );
+    assert_eq!(repo.log(None, None).unwrap().len(), 4);
+    repo.push().unwrap();
+
+    other.pull().unwrap();
+    other_dir.child("more").write_str("more").unwrap();
+    other.commit("More commit").unwrap();
+    other.push().unwrap();
+    repo_dir.child("local").write_str("changed").unwrap();
+    repo.commit("Changed commit").unwrap();
+
+    repo.set_pull_strategy(PullStrategy::Merge);
+    repo.pull().unwrap();
+    repo_dir.child("more").assert("more");
+    let merges = repo
+        .arbitrary_command(&["rev-list", "--merges", "--count", "HEAD"])
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&merges.stdout).trim(), "1");
+    repo.push().unwrap();
+}
+
+/// Test that the libgit2 backend refuses to commit in a sparse checkout, instead of staging the files outside of it
+/// as deleted.
+#[cfg(feature = "libgit2")]
+#[test]
+fn test_libgit2_sparse() {
+    use crate::{errors::git::GitError, errors::DotbakError, git::Repository};
+    use assert_fs::{prelude::*, TempDir};
+    use std::path::PathBuf;
+
+    let repo_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo_dir.child("a/x").write_str("x").unwrap();
+    repo_dir.child("b/y").write_str("y").unwrap();
+    repo.commit("First commit").unwrap();
+
+    repo.sparse_checkout(Some(&[PathBuf::from("a")])).unwrap();
+    repo.set_backend(Backend::Libgit2).unwrap();
+    repo_dir.child("a/x").write_str("changed").unwrap();
+
+    assert!(matches!(
+        repo.commit("Second commit"),
+        Err(DotbakError::Git(GitError::Unsupported {
+            operation: "sparse checkouts",
+            ..
+        }))
+    ));
+
+    let status = repo.arbitrary_command(&["status", "--porcelain"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&status.stdout).trim(), "M a/x");
+}
@@ -1,28 +1,357 @@
+pub mod auth;
+pub mod backend;
 mod tests;
 
-use crate::errors::{io::IoError, DotbakError, Result};
+use self::{
+    auth::{AuthFailure, Credentials},
+    backend::{Context, GitBackend},
+};
+use crate::{
+    clock,
+    config::git::{Backend, PullStrategy, SigningFormat},
+    diagnostics,
+    errors::{git::GitError, io::IoError, DotbakError, Result},
+    platform::{self, Trash},
+};
 use itertools::Itertools;
+use serde::Serialize;
 use std::{
-    fs,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    fmt, fs,
+    io::Read,
     path::{Path, PathBuf},
-    process::Output,
+    process::{Command, ExitStatus, Output, Stdio},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
 };
 
 /// The default remote name.
 pub const REMOTE_NAME: &str = "origin";
 
-/// The default main branch name.
+/// The default name of the branch `dotbak` works on. See `Repository::set_branch`.
 pub const MAIN_BRANCH_NAME: &str = "main";
 
-/// A git repository. This is essentially a wrapper structure around git commands performed on the repository,
-/// and is not a wrapper around the git2 library. This is because when I tried to work with `git2`, I ran into
-/// issues pulling and pushing to the remote repository. I'm not sure if this is a bug with `git2` or if I'm just
-/// using it wrong, but I decided to just use the raw `git` command instead. This is much easier and simpler.
+/// The name of the branch that automated syncs are pushed to for review, if enabled. See
+/// `Repository::set_push_branch`.
+pub const STAGING_BRANCH_NAME: &str = "staging";
+
+/// Options for git to compress pushes as much as possible, for low bandwidth mode.
+const COMPRESS_OPTIONS: &[&str] = &["-c", "core.compression=9", "-c", "pack.compression=9"];
+
+/// Options for git to skip downloading git-lfs content, for low bandwidth mode.
+const SKIP_LFS_OPTIONS: &[&str] = &[
+    "-c",
+    "filter.lfs.smudge=git-lfs smudge --skip -- %f",
+    "-c",
+    "filter.lfs.process=git-lfs filter-process --skip",
+];
+
+/// The file in the repository that marks files as binary. See `Repository::mark_binary`.
+const ATTRIBUTES_FILE: &str = ".gitattributes";
+
+/// The file in the repository that lists the files git ignores. See `Repository::set_ignored`.
+const IGNORE_FILE: &str = ".gitignore";
+
+/// The file in the repository that lists its submodules. See `Repository::add_submodule`.
+const SUBMODULES_FILE: &str = ".gitmodules";
+
+/// The lines around the part of `.gitignore` that `dotbak` keeps in sync with `ignore.patterns`. Everything outside
+/// of them is left alone.
+const IGNORE_BEGIN: &str =
+    "# BEGIN dotbak: generated from `ignore.patterns` in the configuration, don't edit";
+const IGNORE_END: &str = "# END dotbak";
+
+/// The lines around the part of `.gitattributes` that `dotbak` keeps in sync with `git.lfs.track`. Everything
+/// outside of them is left alone.
+const LFS_BEGIN: &str =
+    "# BEGIN dotbak: generated from `git.lfs.track` in the configuration, don't edit";
+const LFS_END: &str = "# END dotbak";
+
+/// The git commands that talk to the remote, which are killed after `REMOTE_TIMEOUT` seconds unless configured
+/// otherwise (see `Repository::set_timeouts`). Other commands have no timeout by default.
+const REMOTE_COMMANDS: &[&str] = &[
+    "clone",
+    "fetch",
+    "pull",
+    "push",
+    "ls-remote",
+    "submodule",
+    "lfs",
+];
+
+/// How long in seconds the commands in `REMOTE_COMMANDS` may run by default.
+const REMOTE_TIMEOUT: u64 = 10 * 60;
+
+/// The key in the timeouts (see `Repository::set_timeouts`) for the commands that aren't listed by themselves.
+const DEFAULT_TIMEOUT_KEY: &str = "default";
+
+/// What git's output says about conflicting changes in a merge, rebase or stash.
+const CONFLICT_FAILURES: &[&str] = &[
+    "CONFLICT (",
+    "Automatic merge failed",
+    "you have unmerged files",
+    "needs merge",
+    "could not apply",
+];
+
+/// What git's output says about uncommitted changes that would be overwritten.
+const DIRTY_FAILURES: &[&str] = &[
+    "would be overwritten by",
+    "Please commit your changes or stash them",
+    "You have unstaged changes",
+    "Your index contains uncommitted changes",
+];
+
+/// What git's output says about not being on a branch.
+const DETACHED_FAILURES: &[&str] = &["You are not currently on a branch", "HEAD detached"];
+
+/// What git's output says about the branch having no upstream branch.
+const NO_UPSTREAM_FAILURES: &[&str] = &[
+    "has no upstream branch",
+    "no tracking information",
+    "couldn't find remote ref",
+];
+
+/// What git's output says about failing to reach the remote.
+const UNREACHABLE_FAILURES: &[&str] = &[
+    "Could not resolve host",
+    "Could not resolve hostname",
+    "Connection refused",
+    "Connection timed out",
+    "Network is unreachable",
+    "Could not read from remote repository",
+    "does not appear to be a git repository",
+];
+
+/// What git's output says about not knowing the name and email to commit with.
+const IDENTITY_FAILURES: &[&str] = &[
+    "Please tell me who you are",
+    "Author identity unknown",
+    "Committer identity unknown",
+    "unable to auto-detect email address",
+    "empty ident name",
+];
+
+/// What git's output says about there being nothing to commit.
+const NOTHING_TO_COMMIT_FAILURES: &[&str] = &[
+    "nothing to commit",
+    "nothing added to commit",
+    "no changes added to commit",
+];
+
+/// The file in the git folder listing the paths to drop from the history (separated by NUL), while
+/// `Repository::drop_from_history` runs.
+const DROPPED_PATHS_FILE: &str = "dotbak-dropped-paths";
+
+/// The attributes that make git store files with git-lfs, as `git lfs track` writes them.
+const LFS_ATTRIBUTES: &str = "filter=lfs diff=lfs merge=lfs -text";
+
+/// The key of the trailer recording the sequence number of a commit. See `Repository::set_sequence_trailer`.
+pub const SEQUENCE_TRAILER: &str = "Dotbak-Sequence";
+
+/// The key of the trailer recording the machine that made a commit. See `Repository::set_host`.
+pub const HOST_TRAILER: &str = "Dotbak-Host";
+
+/// The prefix of the branches of single machines (hosts).
+pub const HOST_BRANCH_PREFIX: &str = "hosts/";
+
+/// The prefix of the branches that retired hosts are archived in. See `Repository::archive_host`.
+pub const ARCHIVED_HOST_BRANCH_PREFIX: &str = "archive/hosts/";
+
+/// The `git log` format for `Commit`s: the fields, separated by ASCII unit separators.
+const COMMIT_FORMAT: &str = "--format=%H%x1f%an%x1f%ae%x1f%ad%x1f%s";
+
+/// The `git log` format for `LogEntry`s: `COMMIT_FORMAT`, starting with an ASCII record separator, so that the
+/// commits can be told apart from the files they touched.
+const LOG_FORMAT: &str = "--format=%x1e%H%x1f%an%x1f%ae%x1f%ad%x1f%s";
+
+/// A commit in the repository's history.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Commit {
+    /// The full hash of the commit.
+    pub hash: String,
+
+    /// The name of the commit's author.
+    pub author: String,
+
+    /// The email of the commit's author.
+    pub email: String,
+
+    /// When the commit was authored, as `YYYY-MM-DD HH:MM`.
+    pub date: String,
+
+    /// The first line of the commit message.
+    pub summary: String,
+}
+
+/// A commit in the repository's history, with the files it touched. See `Repository::log`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LogEntry {
+    /// The commit.
+    pub commit: Commit,
+
+    /// The paths of the files the commit touched, relative to the repository.
+    pub files: Vec<PathBuf>,
+}
+
+/// A machine (host) that synced to the repository, as recorded in the `Dotbak-Host` trailers of its commits.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Host {
+    /// The hostname of the machine.
+    pub name: String,
+
+    /// The full hash of the newest commit made by the machine.
+    pub commit: String,
+
+    /// When the machine last synced (committed), in seconds since the UNIX epoch.
+    pub last_sync: i64,
+
+    /// When the machine last synced, as `YYYY-MM-DD HH:MM`.
+    pub date: String,
+
+    /// Whether the host was retired, i.e. it has an archive branch.
+    pub retired: bool,
+}
+
+/// A remote of the repository.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Remote {
+    /// The name of the remote, like `origin`.
+    pub name: String,
+
+    /// The URL of the remote.
+    pub url: String,
+}
+
+/// How commits are signed. See `Repository::set_signing`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signing {
+    /// The format of the signatures, or `None` for git's `gpg.format`.
+    pub format: Option<SigningFormat>,
+
+    /// The key to sign with, or `None` for git's `user.signingkey`.
+    pub key: Option<String>,
+}
+
+impl Signing {
+    /// Gets the options for git to sign commits with the format and key, if they're set. Signing itself is turned on
+    /// with `--gpg-sign`, or with `commit.gpgsign` for the commands that don't take it (see
+    /// `Repository::signed_command`).
+    pub fn options(&self) -> Vec<String> {
+        let mut options = vec![];
+
+        if let Some(format) = self.format {
+            options.extend(["-c".to_string(), format!("gpg.format={}", format.as_str())]);
+        }
+
+        if let Some(key) = &self.key {
+            options.extend(["-c".to_string(), format!("user.signingkey={}", key)]);
+        }
+
+        options
+    }
+}
+
+/// Receives the progress git reports while cloning, pushing and pulling (e.g. `Receiving objects:  42% (21/50)`),
+/// line by line while it's reported. See `Repository::set_progress`.
+#[derive(Clone)]
+pub struct ProgressHandler(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl ProgressHandler {
+    /// Creates a handler calling `report` with every line of progress.
+    pub fn new<F>(report: F) -> ProgressHandler
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        ProgressHandler(Arc::new(report))
+    }
+
+    /// Reports the line of progress `line`.
+    pub fn report(&self, line: &str) {
+        (self.0)(line)
+    }
+}
+
+impl fmt::Debug for ProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressHandler")
+    }
+}
+
+/// How to clone a repository, and what to leave out. See `Repository::clone_with_credentials`.
+#[derive(Clone, Debug, Default)]
+pub struct CloneOptions {
+    /// Whether to only check out the files at the top of the repository, until the files to check out are set with
+    /// `Repository::sparse_checkout`.
+    pub sparse: bool,
+
+    /// How many of the newest commits to clone, or `None` for all of them (or only the newest one in low bandwidth
+    /// mode).
+    pub depth: Option<u32>,
+
+    /// The git object filter to clone with (e.g. `blob:none`), or `None` to download everything.
+    pub filter: Option<String>,
+
+    /// What to report the progress of the clone to, which the returned repository keeps using. See
+    /// `Repository::set_progress`.
+    pub progress: Option<ProgressHandler>,
+}
+
+/// Which side of a merge to keep when resolving a conflict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeSide {
+    /// Keep the version from the current branch.
+    Ours,
+
+    /// Keep the version from the branch being merged in.
+    Theirs,
+}
+
+/// A git repository. This is essentially a wrapper structure around git commands performed on the repository.
+/// What initializing, adding files and syncing need (creating or cloning the repository, committing, pushing,
+/// pulling, stashing, remotes, and reading the status and history) goes through a `GitBackend`, which can use
+/// libgit2 instead of the `git` binary (see `set_backend`). Everything else runs the `git` binary, as pulling and
+/// pushing with the `git2` library used to be troublesome, and the raw `git` command is much easier and simpler.
 #[derive(Debug)]
 pub struct Repository {
     /// The repository path for `dotbak`. Note that this is not the `.git` directory, but the directory
     /// containing the `.git` directory.
     path: PathBuf,
+
+    /// Whether to save bandwidth when talking to the remote. See `set_low_bandwidth`.
+    low_bandwidth: bool,
+
+    /// Whether to add sequence number trailers to commits. See `set_sequence_trailer`.
+    sequence_trailer: bool,
+
+    /// The branch `dotbak` works on, locally and on the remote. See `set_branch`.
+    branch: String,
+
+    /// The remote branch that `push` pushes to, if it's not `branch`. See `set_push_branch`.
+    push_branch: Option<&'static str>,
+
+    /// The hostname recorded in the trailers of commits. See `set_host`.
+    host: Option<String>,
+
+    /// The credentials for HTTPS remotes, if there are any. See `set_credentials`.
+    credentials: Option<Credentials>,
+
+    /// How pulling reconciles local commits with the remote's. See `set_pull_strategy`.
+    pull_strategy: PullStrategy,
+
+    /// How long in seconds git commands may run, by subcommand. See `set_timeouts`.
+    timeouts: BTreeMap<String, u64>,
+
+    /// What commits, pushes, pulls, clones and reads the status and history. See `set_backend`.
+    backend: Box<dyn GitBackend>,
+
+    /// How commits are signed, if they are. See `set_signing`.
+    signing: Option<Signing>,
+
+    /// What the progress of clones, pushes and pulls is reported to, if anything. See `set_progress`.
+    progress: Option<ProgressHandler>,
 }
 
 /// Public git API for `Repository`.
@@ -35,6 +364,19 @@ impl Repository {
     ///
     /// `remote_url` is the URL to the remote repository. This will be set to the `origin` remote.
     pub fn init<P>(path: P, remote_url: Option<String>) -> Result<Repository>
+    where
+        P: AsRef<Path>,
+    {
+        Repository::init_with_backend(path, remote_url, Backend::Git)
+    }
+
+    /// Initializes a new git repository like `init`, with `backend`, which the returned repository keeps using. See
+    /// `set_backend`.
+    pub fn init_with_backend<P>(
+        path: P,
+        remote_url: Option<String>,
+        backend: Backend,
+    ) -> Result<Repository>
     where
         P: AsRef<Path>,
     {
@@ -46,17 +388,25 @@ impl Repository {
             })?;
         }
 
-        // Run the init command.
-        run_arbitrary_git_command(
-            path.as_ref(),
-            &["init", "--initial-branch", MAIN_BRANCH_NAME, "."],
-        )?;
-
         // Create the repository.
         let mut repo = Repository {
             path: path.as_ref().to_path_buf(),
+            low_bandwidth: false,
+            sequence_trailer: false,
+            branch: MAIN_BRANCH_NAME.to_string(),
+            push_branch: None,
+            host: None,
+            credentials: None,
+            pull_strategy: PullStrategy::default(),
+            timeouts: BTreeMap::new(),
+            backend: backend::new(backend)?,
+            signing: None,
+            progress: None,
         };
 
+        // Run the init command.
+        repo.backend.init(&repo.context(), MAIN_BRANCH_NAME)?;
+
         // If we want to set the remote, we set it here.
         if let Some(url) = remote_url {
             repo.set_remote(url)?;
@@ -94,6 +444,17 @@ impl Repository {
         // Return the repository.
         Ok(Repository {
             path: path.as_ref().to_path_buf(),
+            low_bandwidth: false,
+            sequence_trailer: false,
+            branch: MAIN_BRANCH_NAME.to_string(),
+            push_branch: None,
+            host: None,
+            credentials: None,
+            pull_strategy: PullStrategy::default(),
+            timeouts: BTreeMap::new(),
+            backend: Box::new(backend::cli::CliBackend),
+            signing: None,
+            progress: None,
         })
     }
 
@@ -104,8 +465,44 @@ impl Repository {
     /// directory does not exist, it will be created.
     ///
     /// `url` is the URL to the remote repository.
+    ///
+    /// If `low_bandwidth` is set, the clone is shallow and skips git-lfs content, and the returned repository is
+    /// in low bandwidth mode.
     /// TODO: implement logging and such.
-    pub fn clone<P, S>(path: P, url: S) -> Result<Repository>
+    pub fn clone<P, S>(path: P, url: S, low_bandwidth: bool) -> Result<Repository>
+    where
+        P: AsRef<Path>,
+        S: ToString,
+    {
+        Repository::clone_with_credentials(
+            path,
+            url,
+            low_bandwidth,
+            &CloneOptions::default(),
+            None,
+            BTreeMap::new(),
+            Backend::Git,
+        )
+    }
+
+    /// Clones a pre-existing repository like `clone`, authenticating with the remote using `credentials` if it's
+    /// accessed over HTTPS. The returned repository keeps using them, see `set_credentials`.
+    ///
+    /// `options` are what to leave out of the clone: the files below the top of the repository, the older commits,
+    /// or the objects not matching a filter. See `unshallow` for getting them later.
+    ///
+    /// `timeouts` are how long git commands may run, including the clone itself. See `set_timeouts`.
+    ///
+    /// `backend` is what clones the repository, and what the returned repository uses. See `set_backend`.
+    pub fn clone_with_credentials<P, S>(
+        path: P,
+        url: S,
+        low_bandwidth: bool,
+        options: &CloneOptions,
+        credentials: Option<Credentials>,
+        timeouts: BTreeMap<String, u64>,
+        backend: Backend,
+    ) -> Result<Repository>
     where
         P: AsRef<Path>,
         S: ToString,
@@ -121,23 +518,227 @@ impl Repository {
             })?;
         }
 
-        // Run the clone command.
-        run_arbitrary_git_command(path, &["clone", &url, "."])?;
-
-        // Create the repository.
-        let repo = Repository {
+        // Create the repository, on the remote's default branch (which the clone checks out).
+        let mut repo = Repository {
             path: path.to_path_buf(),
+            low_bandwidth,
+            sequence_trailer: false,
+            branch: MAIN_BRANCH_NAME.to_string(),
+            push_branch: None,
+            host: None,
+            credentials,
+            pull_strategy: PullStrategy::default(),
+            timeouts,
+            backend: backend::new(backend)?,
+            signing: None,
+            progress: options.progress.clone(),
         };
 
+        // Run the clone.
+        repo.backend.clone(&repo.context(), &url, options)?;
+
+        if let Some(branch) = repo.current_branch() {
+            repo.branch = branch;
+        }
+
         Ok(repo)
     }
 
+    /// Sets whether to save bandwidth when talking to the remote. In low bandwidth mode, pulls skip git-lfs
+    /// content and tags, and pushes are compressed as much as possible.
+    pub fn set_low_bandwidth(&mut self, low_bandwidth: bool) {
+        self.low_bandwidth = low_bandwidth;
+    }
+
+    /// Whether the repository is in low bandwidth mode.
+    pub fn low_bandwidth(&self) -> bool {
+        self.low_bandwidth
+    }
+
+    /// Sets whether to add a `Dotbak-Sequence` trailer with a monotonically increasing number to every commit made
+    /// with `commit`, so that the order of commits doesn't depend on the clocks of the machines making them.
+    pub fn set_sequence_trailer(&mut self, sequence_trailer: bool) {
+        self.sequence_trailer = sequence_trailer;
+    }
+
+    /// Sets the hostname recorded in a `Dotbak-Host` trailer on every commit made with `commit`, so that `hosts` can
+    /// tell when each machine last synced. Commits don't get the trailer if this is `None`.
+    pub fn set_host(&mut self, host: Option<String>) {
+        self.host = host;
+    }
+
+    /// The hostname recorded in the trailers of commits. See `set_host`.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    /// Sets the branch `dotbak` works on: it's committed to, pulled from the remote, and pushed to it (unless
+    /// `set_push_branch` says otherwise). This is `MAIN_BRANCH_NAME` by default, or the remote's default branch for
+    /// cloned repositories. This doesn't check the branch out, see `switch_branch`.
+    pub fn set_branch<S>(&mut self, branch: S)
+    where
+        S: ToString,
+    {
+        self.branch = branch.to_string();
+    }
+
+    /// The branch `dotbak` works on. See `set_branch`.
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Sets the remote branch that `push` pushes the local branch to, instead of the branch itself. This is
+    /// `STAGING_BRANCH_NAME` for syncs that should be reviewed before they're promoted to the main branch.
+    pub fn set_push_branch(&mut self, branch: Option<&'static str>) {
+        self.push_branch = branch;
+    }
+
+    /// The remote branch that `push` pushes to.
+    pub fn push_branch(&self) -> &str {
+        self.push_branch.unwrap_or(&self.branch)
+    }
+
+    /// Sets the credentials to authenticate with HTTPS remotes, instead of the credential helpers configured for
+    /// git. Without them, git fails instead of asking for a username and password on the terminal, so that the
+    /// failure can be detected (see `auth::AuthFailure`) rather than hanging behind a spinner.
+    pub fn set_credentials(&mut self, credentials: Option<Credentials>) {
+        self.credentials = credentials;
+    }
+
+    /// Sets how pulling reconciles the local commits with the remote's: by merging them (the default), rebasing the
+    /// local commits on top of the remote's, or only fast-forwarding.
+    pub fn set_pull_strategy(&mut self, strategy: PullStrategy) {
+        self.pull_strategy = strategy;
+    }
+
+    /// Sets how long in seconds git commands may run before they're killed, by subcommand (e.g. `pull`, `push` or
+    /// `fetch`), so that a hung command doesn't block forever. The `default` entry applies to the subcommands that
+    /// aren't listed, and `0` means no timeout. Without either, the commands that talk to the remote may run for
+    /// `REMOTE_TIMEOUT` seconds, and the others have no timeout.
+    pub fn set_timeouts(&mut self, timeouts: BTreeMap<String, u64>) {
+        self.timeouts = timeouts;
+    }
+
+    /// Sets what commits, pushes, pulls, clones and reads the status and history of the repository: the `git`
+    /// binary (the default), or libgit2. It will return an error if the backend isn't built into `dotbak`.
+    pub fn set_backend(&mut self, backend: Backend) -> Result<()> {
+        self.backend = backend::new(backend)?;
+
+        Ok(())
+    }
+
+    /// Sets how the commits made with `commit` (and the merges made when pulling) are signed, or `None` to not sign
+    /// them.
+    pub fn set_signing(&mut self, signing: Option<Signing>) {
+        self.signing = signing;
+    }
+
+    /// Sets what the progress of clones, pushes and pulls is reported to while they run, or `None` to not report it.
+    pub fn set_progress(&mut self, progress: Option<ProgressHandler>) {
+        self.progress = progress;
+    }
+
+    /// Sets the name and email commits are made with in the repository's own git configuration, so that committing
+    /// works even if they aren't configured for git globally. The ones that are `None` are left as they are.
+    pub fn set_identity(&mut self, name: Option<&str>, email: Option<&str>) -> Result<()> {
+        for (key, value) in [("user.name", name), ("user.email", email)] {
+            let Some(value) = value else {
+                continue;
+            };
+
+            if self.backend.config(&self.context(), key).as_deref() != Some(value) {
+                self.backend.set_config(&self.context(), key, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the repository has credentials for HTTPS remotes. See `set_credentials`.
+    pub fn has_credentials(&self) -> bool {
+        self.credentials.is_some()
+    }
+
+    /// Gets the branch that is checked out, or `None` if HEAD is detached.
+    pub fn current_branch(&mut self) -> Option<String> {
+        self.backend.current_branch(&self.context())
+    }
+
+    /// Checks out the branch `branch`, and makes it the branch `dotbak` works on (see `set_branch`). If it doesn't
+    /// exist locally, it's created from the remote's branch if there is one, or from the current commit otherwise.
+    /// It will return an error if there are uncommitted changes in the way.
+    pub fn switch_branch(&mut self, branch: &str) -> Result<Output> {
+        let local = format!("refs/heads/{}", branch);
+        let output = if self.has_rev(&local) {
+            self.arbitrary_command(&["checkout", branch])?
+        } else if self.has_remote_branch(branch) {
+            self.fetch_branch(branch)?;
+            let remote = format!("{}/{}", REMOTE_NAME, branch);
+            self.arbitrary_command(&["checkout", "-b", branch, "--track", &remote])?
+        } else {
+            self.arbitrary_command(&["checkout", "-b", branch])?
+        };
+
+        self.set_branch(branch);
+
+        Ok(output)
+    }
+
     /// Runs an arbitrary `git` command. It will return an error if the repository is not initialized.
     ///
     /// `args` is a vector of arguments to pass to `git`.
-    pub fn arbitrary_command(&mut self, args: &[&str]) -> Result<Output> {
+    pub fn arbitrary_command<S>(&mut self, args: &[S]) -> Result<Output>
+    where
+        S: AsRef<OsStr>,
+    {
         // Run the command.
-        run_arbitrary_git_command(&self.path, args)
+        run_arbitrary_git_command(&self.path, args, self.credentials.as_ref(), &self.timeouts)
+    }
+
+    /// Runs `git` with `args` like `arbitrary_command`, but signs the commits it makes (e.g. reverts and merges) like
+    /// `commit` does, if commits are signed (see `set_signing`).
+    fn signed_command<S>(&mut self, args: &[S]) -> Result<Output>
+    where
+        S: AsRef<OsStr>,
+    {
+        let mut signed: Vec<OsString> = match &self.signing {
+            Some(signing) => ["-c".to_string(), "commit.gpgsign=true".to_string()]
+                .into_iter()
+                .chain(signing.options())
+                .map(OsString::from)
+                .collect(),
+            None => vec![],
+        };
+        signed.extend(args.iter().map(|arg| arg.as_ref().to_os_string()));
+
+        self.arbitrary_command(&signed)
+    }
+
+    /// Runs `git` with `args` in the repository like it runs in a terminal: its output goes straight to the
+    /// terminal, it may ask for input (e.g. open an editor or a pager), and it doesn't time out. Unlike
+    /// `arbitrary_command`, git failing isn't an error: the returned status says how it exited.
+    pub fn passthrough<S>(&mut self, args: &[S]) -> Result<ExitStatus>
+    where
+        S: AsRef<OsStr>,
+    {
+        let mut command = Command::new("git");
+
+        if let Some(credentials) = &self.credentials {
+            credentials.apply(&mut command);
+        }
+
+        command
+            .args(args)
+            .current_dir(&self.path)
+            .status()
+            .map_err(|err| {
+                IoError::CommandIO {
+                    source: err,
+                    command: "git".to_string(),
+                    args: display_args(args),
+                }
+                .into()
+            })
     }
 
     /// Set the remote for the repository. It will return an error if the repository is not
@@ -148,27 +749,34 @@ impl Repository {
     where
         S: ToString,
     {
-        let url = url.to_string();
-
-        // Run the remote command.
-        let result = self.arbitrary_command(&["remote", "set-url", REMOTE_NAME, &url]);
+        self.add_remote(REMOTE_NAME, url)
+    }
 
-        match result {
-            // If the command succeeded, return.
-            Ok(output) => Ok(output),
+    /// Adds the remote `name` with the URL `url`, or changes its URL if it already exists. It will return an error
+    /// if the repository is not initialized.
+    pub fn add_remote<S>(&mut self, name: &str, url: S) -> Result<Output>
+    where
+        S: ToString,
+    {
+        self.backend
+            .set_remote(&self.context(), name, &url.to_string())
+    }
 
-            // If the remote could not be found, create it.
-            Err(DotbakError::Io(IoError::CommandRun { stderr, .. }))
-                if stderr == *"error: No such remote 'origin'\n" =>
-            {
-                // Run the remote command.
-                self.arbitrary_command(&["remote", "add", REMOTE_NAME, &url])?;
-                self.arbitrary_command(&["remote", "set-url", REMOTE_NAME, &url])
-            }
+    /// Removes the remote `name`, along with its remote-tracking branches. It will return an error if the
+    /// repository is not initialized or the remote doesn't exist.
+    pub fn remove_remote(&mut self, name: &str) -> Result<Output> {
+        self.arbitrary_command(&["remote", "remove", name])
+    }
 
-            // If the command failed, return an error.
-            Err(e) => Err(e),
-        }
+    /// Lists the remotes of the repository, sorted by name. It will return an error if the repository is not
+    /// initialized.
+    pub fn list_remotes(&mut self) -> Result<Vec<Remote>> {
+        Ok(self
+            .backend
+            .remotes(&self.context())?
+            .into_iter()
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect())
     }
 
     /// Commits all changed files to the repository. It will return an error if the repository is not initialized.
@@ -176,95 +784,1831 @@ impl Repository {
     /// `message` is the commit message.
     ///
     /// Returns the commit's OID -- this is the commit's hash.
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn commit(&mut self, message: &str) -> Result<[Output; 2]> {
-        Ok([
-            // Run the add command.
-            self.arbitrary_command(&["add", "."])?,
-            // Run the commit command.
-            self.arbitrary_command(&["commit", "-am", message])?,
-        ])
+        self.backend.commit(
+            &self.context(),
+            message,
+            self.sequence_trailer,
+            self.host.as_deref(),
+        )
     }
 
-    /// Pushes all commits to the remote repository. It will return an error if the repository is not
-    /// initialized.
+    /// Gets the commit time (in seconds since the UNIX epoch) of the newest commit on any local or
+    /// remote-tracking branch, or `None` if there are no commits. This doesn't fetch anything.
+    pub fn newest_commit_time(&mut self) -> Result<Option<i64>> {
+        self.backend.newest_commit_time(&self.context())
+    }
+
+    /// Pushes all commits to the remote repository, on the branch set with `set_push_branch`. It will return an
+    /// error if the repository is not initialized.
     pub fn push(&mut self) -> Result<Output> {
-        self.arbitrary_command(&["push", REMOTE_NAME, MAIN_BRANCH_NAME])
+        self.push_to(REMOTE_NAME)
+    }
+
+    /// Like `push`, but pushes to the remote `remote` instead of the default one.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn push_to(&mut self, remote: &str) -> Result<Output> {
+        let refspec = format!("{}:{}", self.branch, self.push_branch());
+
+        self.backend.push(&self.context(), remote, &refspec)
+    }
+
+    /// Like `push_to`, but replaces the remote's branch even if the commits on it aren't in the local history (e.g.
+    /// after `drop_from_history`). The commits only on the remote are lost.
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn force_push_to(&mut self, remote: &str) -> Result<Output> {
+        let refspec = format!("+{}:{}", self.branch, self.push_branch());
+
+        self.backend.push(&self.context(), remote, &refspec)
     }
 
     /// Pulls all commits from the remote repository. It will return an error if the repository is not
     /// initialized.
     pub fn pull(&mut self) -> Result<Output> {
-        self.arbitrary_command(&["pull", REMOTE_NAME, MAIN_BRANCH_NAME])
+        let branch = self.branch.clone();
+
+        self.pull_branch(&branch)
     }
 
-    /// Deletes the git repository. It will return an error if the repository is not initialized or is not
-    /// there. Will not return an error if the repository is not empty.
-    /// TODO: implement logging and such.
-    /// TODO: Move symlinked files to their original location.
-    pub fn delete(self) -> Result<()> {
-        // Delete the repository using `fs::remove_dir_all`.
-        fs::remove_dir_all(&self.path).map_err(|err| IoError::Delete {
-            source: err,
-            path: self.path,
-        })?;
+    /// Pulls all commits from the branch `branch` of the remote repository into the current branch, reconciling them
+    /// with the local commits according to the pull strategy. It will return an error if the repository is not
+    /// initialized.
+    pub fn pull_branch(&mut self, branch: &str) -> Result<Output> {
+        let strategy = self.pull_strategy;
 
-        Ok(())
+        self.pull_with(branch, strategy)
     }
-}
 
-/// These are helper functions for tests on `Repository`.
-#[cfg(test)]
-impl Repository {
-    /// Get the path to the repository.
-    pub fn path(&self) -> &Path {
-        &self.path
+    /// Like `pull_branch`, but always merges, whatever the pull strategy is.
+    pub fn merge_branch(&mut self, branch: &str) -> Result<Output> {
+        self.pull_with(branch, PullStrategy::Merge)
     }
-}
 
-/// Run a command in the repository.
-///
-/// `path` is the path to the repository.
-///
-/// `args` is the arguments to pass to the command.
-///
-/// Returns the output of the command.
-fn run_arbitrary_git_command<P>(path: P, args: &[&str]) -> Result<Output>
-where
-    P: AsRef<Path>,
-{
-    // Run the command.
-    let output = std::process::Command::new("git")
-        .args(args)
-        .current_dir(path)
-        .output()
-        .map_err(|err| IoError::CommandIO {
-            source: err,
-            command: "git".to_string(),
-            args: args.iter().map(|s| s.to_string()).collect_vec(),
-        })?;
+    /// Stashes the uncommitted changes in the repository (including new files), so that pulling can't conflict with
+    /// them. Returns the number of stashed changes, which `stash_pop` should reapply afterwards.
+    pub fn stash_push(&mut self) -> Result<usize> {
+        if !self.has_commits() {
+            return Ok(0);
+        }
 
-    // If the command succeeded, return.
-    if output.status.success() {
-        return Ok(output);
+        let changes = self.uncommitted_changes()?;
+
+        if changes > 0 {
+            self.backend.stash_push(&self.context())?;
+        }
+
+        Ok(changes)
     }
 
-    let string_stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let string_stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    /// Reapplies the changes that `stash_push` stashed. Returns the paths (relative to the repository) that conflict
+    /// with what was pulled in the meantime, if there are any. Then the conflicts are left in the files, and the
+    /// changes are kept in the stash.
+    pub fn stash_pop(&mut self) -> Result<Vec<PathBuf>> {
+        self.backend.stash_pop(&self.context())
+    }
 
-    // Make sure that the error is not something benign like "nothing to commit".
+    /// Adds the repository at `url` as a submodule at `path` (relative to the repository), and stages it. It's
+    /// committed with the next commit.
+    pub fn add_submodule<S, P>(&mut self, url: S, path: P) -> Result<Output>
+    where
+        S: AsRef<OsStr>,
+        P: AsRef<Path>,
+    {
+        self.arbitrary_command(&[
+            OsStr::new("submodule"),
+            OsStr::new("add"),
+            OsStr::new("--"),
+            url.as_ref(),
+            path.as_ref().as_os_str(),
+        ])
+    }
 
-    match string_stdout {
-        // HACK: If it's an error, but the error is "nothing to commit", then return an empty output.
-        // TODO: This is a hack. Fix this.
-        _ if string_stdout.contains("nothing to commit") => Ok(output),
+    /// Checks out the commits of the submodules that the current commit records, cloning the ones that aren't
+    /// checked out yet (e.g. because another machine just added them).
+    pub fn update_submodules(&mut self) -> Result<Output> {
+        self.arbitrary_command(&["submodule", "update", "--init", "--recursive"])
+    }
 
-        // Otherwise, return the error.
-        _ => Err(IoError::CommandRun {
-            command: "git".to_string(),
-            args: args.iter().map(|s| s.to_string()).collect_vec(),
-            stdout: string_stdout,
-            stderr: string_stderr,
-        }
-        .into()),
+    /// Checks if the repository has any submodules.
+    pub fn has_submodules(&self) -> bool {
+        self.path.join(SUBMODULES_FILE).exists()
+    }
+
+    /// Fetches the branch `dotbak` works on from the remote repository, without merging it. It will return an
+    /// error if there is no remote.
+    pub fn fetch(&mut self) -> Result<Output> {
+        let branch = self.branch.clone();
+
+        self.fetch_branch(&branch)
+    }
+
+    /// Fetches the branch `branch` from the remote repository into `origin/<branch>`, without merging it. It will
+    /// return an error if there is no remote, or the remote doesn't have the branch. A shallow clone stays shallow
+    /// (see `shallow_options`).
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn fetch_branch(&mut self, branch: &str) -> Result<Output> {
+        let mut args = vec!["fetch".to_string(), "--no-tags".to_string()];
+        args.extend(self.shallow_options(branch));
+        args.extend([REMOTE_NAME.to_string(), branch.to_string()]);
+
+        self.arbitrary_command(&args)
     }
+
+    /// Gets the options keeping a fetch of the branch `branch` from downloading the history a shallow clone left
+    /// out. Fetching the branch `dotbak` works on (and pulling it) only downloads the commits that are new since the
+    /// last fetch anyway, but other branches aren't limited by that, so they're fetched without the commits on the
+    /// remote's branch `dotbak` works on. Returns nothing if the repository isn't shallow.
+    ///
+    /// Their depth isn't limited instead, since that cuts the history off at the fetched commits, and git then
+    /// refuses to merge the local commits with them.
+    fn shallow_options(&mut self, branch: &str) -> Vec<String> {
+        let tracking = format!("refs/remotes/{}/{}", REMOTE_NAME, self.branch);
+        let fetched = self.has_rev(&tracking);
+
+        match branch != self.branch && fetched && self.is_shallow() {
+            true => vec![format!("--shallow-exclude={}", self.branch)],
+            false => vec![],
+        }
+    }
+
+    /// Checks if the repository was cloned without all of its history, see `clone_with_credentials`.
+    pub fn is_shallow(&mut self) -> bool {
+        self.arbitrary_command(&["rev-parse", "--is-shallow-repository"])
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "true")
+    }
+
+    /// Checks if the repository was cloned with an object filter, so that some objects are only downloaded when
+    /// they're needed. See `clone_with_credentials`.
+    pub fn is_partial(&mut self) -> bool {
+        self.arbitrary_command(&["config", "--get", &partial_clone_filter_key()])
+            .is_ok()
+    }
+
+    /// Fetches the history and objects a shallow or filtered clone (see `clone_with_credentials`) left out, so that
+    /// the repository is complete. Returns `None` if nothing was left out.
+    pub fn unshallow(&mut self) -> Result<Option<Output>> {
+        let (shallow, partial) = (self.is_shallow(), self.is_partial());
+
+        if !shallow && !partial {
+            return Ok(None);
+        }
+
+        let mut args = vec!["fetch"];
+
+        if shallow {
+            args.push("--unshallow");
+        }
+
+        if !partial {
+            args.push(REMOTE_NAME);
+
+            return self.arbitrary_command(&args).map(Some);
+        }
+
+        // Fetching again without the filter downloads everything. The filter is only removed from the remote for
+        // good once that worked, as the objects it left out can't be downloaded without it.
+        let promisor = format!("remote.{}.promisor", REMOTE_NAME);
+        let filter_key = partial_clone_filter_key();
+        let filter = self.arbitrary_command(&["config", "--get", &filter_key])?;
+        let filter = String::from_utf8_lossy(&filter.stdout).trim().to_string();
+
+        self.arbitrary_command(&["config", "--unset", &filter_key])?;
+        args.extend(["--refetch", REMOTE_NAME]);
+
+        match self.arbitrary_command(&args) {
+            Ok(output) => {
+                self.arbitrary_command(&["config", "--unset", &promisor])?;
+                Ok(Some(output))
+            }
+            Err(err) => {
+                self.arbitrary_command(&["config", &filter_key, &filter])?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Checks if the remote repository has the branch `branch`. Returns `false` if there is no remote.
+    pub fn has_remote_branch(&mut self, branch: &str) -> bool {
+        self.backend
+            .has_remote_branch(&self.context(), REMOTE_NAME, branch)
+    }
+
+    /// Checks if the revision `ancestor` is an ancestor of (or the same as) the revision `rev`, i.e. if `rev` can
+    /// be fast-forwarded from `ancestor`.
+    pub fn is_ancestor(&mut self, ancestor: &str, rev: &str) -> bool {
+        self.arbitrary_command(&["merge-base", "--is-ancestor", ancestor, rev])
+            .is_ok()
+    }
+
+    /// Pushes the revision `rev` to the branch `branch` of the remote repository. This fails if the push isn't a
+    /// fast-forward.
+    pub fn push_rev(&mut self, rev: &str, branch: &str) -> Result<Output> {
+        let refspec = format!("{}:refs/heads/{}", rev, branch);
+
+        self.arbitrary_command(&["push", REMOTE_NAME, &refspec])
+    }
+
+    /// Gets the commits that are in the revision `to`, but not in the revision `from`, newest commit first.
+    pub fn commits_between(&mut self, from: &str, to: &str) -> Result<Vec<Commit>> {
+        let range = format!("{}..{}", from, to);
+        let output = self.arbitrary_command(&[
+            "log",
+            "--date=format:%Y-%m-%d %H:%M",
+            COMMIT_FORMAT,
+            &range,
+        ])?;
+
+        Ok(parse_commits(&output.stdout))
+    }
+
+    /// Gets the pulled commits (on the remote's branch) that aren't signed or whose signature is bad, newest commit
+    /// first. `from` is the commit the branch was at before pulling, or `None` if it had no commits, in which case
+    /// all commits are checked.
+    pub fn unsigned_commits(&mut self, from: Option<&str>) -> Result<Vec<Commit>> {
+        let remote = format!("{}/{}", REMOTE_NAME, self.branch);
+
+        if !self.has_rev(&remote) {
+            return Ok(vec![]);
+        }
+
+        let range = match from {
+            Some(from) => format!("{}..{}", from, remote),
+            None => remote,
+        };
+
+        // Git can't check SSH signatures without an allowed signers file, and then says they're missing (`N` for
+        // `%G?`), so whether commits are signed at all is told by their `gpgsig` header instead.
+        let raw = self.arbitrary_command(&["log", "--pretty=raw", &range])?;
+        let mut signed = HashSet::new();
+        let mut hash = None;
+
+        for line in String::from_utf8_lossy(&raw.stdout).lines() {
+            if let Some(commit) = line.strip_prefix("commit ") {
+                hash = commit.split(' ').next().map(str::to_string);
+            } else if line.starts_with("gpgsig") {
+                signed.extend(hash.take());
+            }
+        }
+
+        // `%G?` is `B` for bad signatures.
+        let format = format!("{}%x1f%G?", COMMIT_FORMAT);
+        let output =
+            self.arbitrary_command(&["log", "--date=format:%Y-%m-%d %H:%M", &format, &range])?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.rsplit_once('\x1f'))
+            .flat_map(|(commit, status)| {
+                parse_commits(commit.as_bytes())
+                    .into_iter()
+                    .map(move |commit| (commit, status))
+            })
+            .filter(|(commit, status)| *status == "B" || !signed.contains(&commit.hash))
+            .map(|(commit, _)| commit)
+            .collect())
+    }
+
+    /// Gets the machines (hosts) that synced to the repository, on any local or remote-tracking branch, and when
+    /// they last synced. Only commits with a `Dotbak-Host` trailer (see `set_host`) count. The host that synced
+    /// most recently comes first.
+    pub fn hosts(&mut self) -> Result<Vec<Host>> {
+        let format = format!(
+            "--format=%H%x1f%ct%x1f%cd%x1f%(trailers:key={},valueonly,separator=%x1f)",
+            HOST_TRAILER
+        );
+        let output = self.arbitrary_command(&[
+            "log",
+            "--branches",
+            "--remotes",
+            "--date=format:%Y-%m-%d %H:%M",
+            &format,
+        ])?;
+        let retired = self.retired_hosts()?;
+        let mut hosts: Vec<Host> = vec![];
+
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields = line.split('\x1f');
+            let (Some(commit), Some(Ok(last_sync)), Some(date)) = (
+                fields.next(),
+                fields.next().map(str::parse::<i64>),
+                fields.next(),
+            ) else {
+                continue;
+            };
+
+            for name in fields.map(str::trim).filter(|name| !name.is_empty()) {
+                match hosts.iter_mut().find(|host| host.name == name) {
+                    Some(host) if host.last_sync >= last_sync => (),
+                    Some(host) => {
+                        host.commit = commit.to_string();
+                        host.last_sync = last_sync;
+                        host.date = date.to_string();
+                    }
+                    None => hosts.push(Host {
+                        name: name.to_string(),
+                        commit: commit.to_string(),
+                        last_sync,
+                        date: date.to_string(),
+                        retired: retired.iter().any(|retired| retired == name),
+                    }),
+                }
+            }
+        }
+
+        hosts.sort_by(|a, b| b.last_sync.cmp(&a.last_sync).then(a.name.cmp(&b.name)));
+
+        Ok(hosts)
+    }
+
+    /// Archives the branch of the host `host` (`hosts/<name>`) as `archive/hosts/<name>`, which marks the host as
+    /// retired. If there is no local branch, the archive branch is created from the remote's branch, or, if the
+    /// host never had a branch, from its newest commit. Returns the name of the archive branch.
+    pub fn archive_host(&mut self, host: &Host) -> Result<String> {
+        let branch = format!("{}{}", HOST_BRANCH_PREFIX, host.name);
+        let archive = format!("{}{}", ARCHIVED_HOST_BRANCH_PREFIX, host.name);
+        let remote_branch = format!("{}/{}", REMOTE_NAME, branch);
+
+        if self.has_rev(&format!("refs/heads/{}", branch)) {
+            self.arbitrary_command(&["branch", "-m", &branch, &archive])?;
+        } else if self.has_rev(&format!("refs/remotes/{}", remote_branch)) {
+            self.arbitrary_command(&["branch", "--no-track", &archive, &remote_branch])?;
+        } else {
+            self.arbitrary_command(&["branch", &archive, &host.commit])?;
+        }
+
+        Ok(archive)
+    }
+
+    /// Fetches the branch `dotbak` works on, and the branches of hosts and retired hosts, from the remote
+    /// repository, without merging them. It will return an error if there is no remote. A shallow clone stays
+    /// shallow (see `shallow_options`), which takes fetching the branch `dotbak` works on first.
+    pub fn fetch_hosts(&mut self) -> Result<Output> {
+        let refspec =
+            |branch: &str| format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, REMOTE_NAME);
+        let hosts = [
+            refspec(&format!("{}*", HOST_BRANCH_PREFIX)),
+            refspec(&format!("{}*", ARCHIVED_HOST_BRANCH_PREFIX)),
+        ];
+        let mut args = vec!["fetch".to_string(), "--no-tags".to_string()];
+
+        // Hosts' branches are never the branch `dotbak` works on.
+        let shallow = self.shallow_options(HOST_BRANCH_PREFIX);
+
+        if shallow.is_empty() {
+            args.push(REMOTE_NAME.to_string());
+            args.push(refspec(&self.branch));
+            args.extend(hosts);
+
+            return self.arbitrary_command(&args);
+        }
+
+        let branch = self.branch.clone();
+        self.arbitrary_command(&["fetch", "--no-tags", REMOTE_NAME, &refspec(&branch)])?;
+
+        args.extend(shallow);
+        args.push(REMOTE_NAME.to_string());
+        args.extend(hosts);
+
+        self.arbitrary_command(&args)
+    }
+
+    /// Deletes the branch `branch` of the remote repository.
+    pub fn delete_remote_branch(&mut self, branch: &str) -> Result<Output> {
+        self.arbitrary_command(&["push", REMOTE_NAME, "--delete", branch])
+    }
+
+    /// Shows the uncommitted changes to `paths` (or to everything, if it's empty) as a unified diff: the ones that
+    /// aren't staged yet, or the staged ones if `staged` is set. The output is colored if colors are enabled for the
+    /// terminal. Returns an empty string if there are no such changes.
+    ///
+    /// `paths` are the paths to the files in the repository. These paths must be relative to the repository.
+    pub fn diff<P>(&mut self, paths: &[P], staged: bool) -> Result<String>
+    where
+        P: AsRef<Path>,
+    {
+        let mut args = vec![OsStr::new("diff"), OsStr::new(color_flag())];
+
+        if staged {
+            args.push(OsStr::new("--cached"));
+        }
+
+        args.push(OsStr::new("--"));
+        args.extend(paths.iter().map(|path| path.as_ref().as_os_str()));
+
+        let output = self.arbitrary_command(&args)?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Shows the changes made in the revision `to` since it diverged from the revision `from`: a summary of the
+    /// changed files, followed by the changes themselves. The output is colored if colors are enabled for the
+    /// terminal.
+    pub fn diff_revs(&mut self, from: &str, to: &str) -> Result<String> {
+        let range = format!("{}...{}", from, to);
+        let output =
+            self.arbitrary_command(&["diff", color_flag(), "--stat", "--patch", &range])?;
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Checks if `path` differs between the revisions `rev` and `other`, or between `rev` and the working tree if
+    /// `other` is `None`. Returns `false` if either revision doesn't exist (e.g. the remote was never fetched).
+    pub fn path_differs<P>(&mut self, rev: &str, other: Option<&str>, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        let revs = [Some(rev), other].into_iter().flatten().collect_vec();
+
+        let exists = revs.iter().all(|rev| {
+            self.arbitrary_command(&[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{}^{{commit}}", rev),
+            ])
+            .is_ok()
+        });
+
+        // `git diff --quiet` exits with 1 (which is an error here) if there are differences.
+        exists
+            && self
+                .arbitrary_command(
+                    &[
+                        &[OsStr::new("diff"), OsStr::new("--quiet")],
+                        revs.iter().map(OsStr::new).collect_vec().as_slice(),
+                        &[OsStr::new("--"), path.as_ref().as_os_str()],
+                    ]
+                    .concat(),
+                )
+                .is_err()
+    }
+
+    /// Counts the files with uncommitted changes in the working tree, including untracked files.
+    pub fn uncommitted_changes(&mut self) -> Result<usize> {
+        self.backend.uncommitted_changes(&self.context())
+    }
+
+    /// Lists the files that changed since the last commit (including untracked ones), as they'd be committed.
+    /// Deleted files are listed too, and renamed files by their new path.
+    pub fn changed_files(&mut self) -> Result<Vec<PathBuf>> {
+        self.backend.status(&self.context())
+    }
+
+    /// Counts the commits that haven't been pushed to the remote's main branch yet, as of the last fetch. If the
+    /// remote branch is unknown, all commits are counted. See `ahead_behind`.
+    pub fn unpushed_commits(&mut self) -> Result<usize> {
+        Ok(self.ahead_behind()?.0)
+    }
+
+    /// Counts the commits that haven't been pushed to the remote's main branch yet (ahead), and the commits on it
+    /// that haven't been pulled yet (behind), as of the last fetch. If the remote branch is unknown, all commits are
+    /// ahead.
+    pub fn ahead_behind(&mut self) -> Result<(usize, usize)> {
+        if !self.has_commits() {
+            return Ok((0, 0));
+        }
+
+        let remote_branch = format!("{}/{}", REMOTE_NAME, self.branch);
+        let upstream = self
+            .has_commit(&remote_branch)
+            .then_some(remote_branch.as_str());
+
+        self.backend.ahead_behind(&self.context(), "HEAD", upstream)
+    }
+
+    /// Gets the size in bytes of the largest file changed by the commits that haven't been pushed to the remote
+    /// yet. If the remote branch is unknown, all files are considered unpushed. Returns 0 if there are no
+    /// commits.
+    pub fn largest_unpushed_file(&mut self) -> Result<u64> {
+        // There's nothing to push if there are no commits.
+        if !self.has_commits() {
+            return Ok(0);
+        }
+
+        let remote_branch = format!("{}/{}", REMOTE_NAME, self.branch);
+        let changed = match self.has_rev(&remote_branch) {
+            true => Some(self.arbitrary_command(&[
+                "diff",
+                "--name-only",
+                "-z",
+                &remote_branch,
+                "HEAD",
+            ])?),
+            false => None,
+        };
+        let changed = changed.map(|output| {
+            output
+                .stdout
+                .split(|b| *b == 0)
+                .filter(|path| !path.is_empty())
+                .map(|path| path.to_vec())
+                .collect_vec()
+        });
+
+        // Each entry looks like `<mode> blob <hash> <size>\t<path>`.
+        let tree = self.arbitrary_command(&["ls-tree", "-r", "-l", "-z", "HEAD"])?;
+
+        Ok(tree
+            .stdout
+            .split(|b| *b == 0)
+            .filter_map(|entry| {
+                let tab = entry.iter().position(|b| *b == b'\t')?;
+                let (info, path) = (&entry[..tab], &entry[tab + 1..]);
+
+                if let Some(changed) = &changed {
+                    if !changed.iter().any(|changed| changed == path) {
+                        return None;
+                    }
+                }
+
+                String::from_utf8_lossy(info)
+                    .split_whitespace()
+                    .nth(3)?
+                    .parse::<u64>()
+                    .ok()
+            })
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// Gets the hash of the current commit, or `None` if there are no commits yet.
+    pub fn head(&mut self) -> Option<String> {
+        self.resolve("HEAD")
+    }
+
+    /// Gets the hash of the revision `rev` (e.g. `origin/main`), or `None` if there is no such revision.
+    pub fn resolve(&mut self, rev: &str) -> Option<String> {
+        self.backend.resolve(&self.context(), rev)
+    }
+
+    /// Checks if the revision `rev` (e.g. a hash, or `HEAD~2`) exists, and is a commit.
+    pub fn has_commit(&mut self, rev: &str) -> bool {
+        let commit = format!("{}^{{commit}}", rev);
+
+        self.has_rev(&commit)
+    }
+
+    /// Finds the commit `rev` (e.g. a hash), along with the files it touched. Returns `None` if there is no such
+    /// commit.
+    pub fn find_commit(&mut self, rev: &str) -> Result<Option<LogEntry>> {
+        if !self.has_commit(rev) {
+            return Ok(None);
+        }
+
+        let output = self.arbitrary_command(&[
+            "log",
+            "--date=format:%Y-%m-%d %H:%M",
+            "--name-only",
+            "-z",
+            "--max-count=1",
+            LOG_FORMAT,
+            rev,
+            "--",
+        ])?;
+
+        Ok(parse_log(&output.stdout).into_iter().next())
+    }
+
+    /// Checks if the revision `rev` was pushed to the remote's main branch, as of the last fetch. Returns `false`
+    /// if the remote branch is unknown.
+    pub fn is_pushed(&mut self, rev: &str) -> bool {
+        let remote_branch = format!("{}/{}", REMOTE_NAME, self.branch);
+
+        self.has_commit(&remote_branch) && self.is_ancestor(rev, &remote_branch)
+    }
+
+    /// Checks if the revision `rev` is on any remote-tracking branch, as of the last fetch. Unlike `is_pushed`, this
+    /// also counts commits pushed to other branches (e.g. `STAGING_BRANCH_NAME`) and other remotes, which can't be
+    /// rewritten without force-pushing either.
+    pub fn is_on_remote(&mut self, rev: &str) -> bool {
+        self.arbitrary_command(&["branch", "--remotes", "--contains", rev])
+            .is_ok_and(|output| !output.stdout.trim_ascii().is_empty())
+    }
+
+    /// Checks if the revision `rev` was committed by this machine (see `set_host`) with the summary `summary`.
+    pub fn is_own_commit(&mut self, rev: &str, summary: &str) -> Result<bool> {
+        if !self.has_commit(rev) {
+            return Ok(false);
+        }
+
+        Ok(!self
+            .own_commits(summary, &["--max-count=1", rev])?
+            .is_empty())
+    }
+
+    /// Counts the commits on the current branch committed by this machine (see `set_host`) with the summary `summary`
+    /// since `since`, which is anything `git log --since` understands (e.g. `midnight`).
+    pub fn count_own_commits_since(&mut self, summary: &str, since: &str) -> Result<usize> {
+        if !self.has_commits() {
+            return Ok(0);
+        }
+
+        let since = format!("--since={}", since);
+
+        Ok(self.own_commits(summary, &[&since, "HEAD"])?.len())
+    }
+
+    /// Removes the newest `count` commits from the current branch, keeping their changes staged. It will return an
+    /// error if there aren't more than `count` commits.
+    pub fn uncommit(&mut self, count: usize) -> Result<Output> {
+        let rev = format!("HEAD~{}", count);
+
+        self.arbitrary_command(&["reset", "--soft", &rev])
+    }
+
+    /// Moves the current branch back (or forward) to the commit `rev`, updating the files that changed in between.
+    /// Uncommitted changes are kept, and it will return an error if they would be overwritten.
+    pub fn reset_to(&mut self, rev: &str) -> Result<Output> {
+        self.arbitrary_command(&["reset", "--keep", rev])
+    }
+
+    /// Reverts the commits `revs` with new commits, in the given order (i.e. newest first, so that they apply
+    /// cleanly). Merges (e.g. made by pulling) are reverted against their first parent, which undoes the commits they
+    /// brought in, so those are skipped if they're in `revs` too. If any of them can't be reverted (e.g. because
+    /// newer commits conflict with it), none of them are.
+    pub fn revert<S>(&mut self, revs: &[S]) -> Result<Output>
+    where
+        S: AsRef<str>,
+    {
+        let merges = revs
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|rev| self.is_merge(rev))
+            .collect_vec();
+        let mut merged_in = HashSet::new();
+
+        for merge in &merges {
+            let output = self.arbitrary_command(&[
+                "rev-list".to_string(),
+                format!("{}^@", merge),
+                format!("^{}^1", merge),
+            ])?;
+
+            merged_in.extend(
+                String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .map(str::to_string),
+            );
+        }
+
+        let mut args = vec!["revert", "--no-edit"];
+
+        // Git accepts a mainline for commits that aren't merges too, so it's the same for all of them.
+        if !merges.is_empty() {
+            args.extend(["-m", "1"]);
+        }
+
+        for rev in revs.iter().map(AsRef::as_ref) {
+            if !self
+                .resolve(rev)
+                .is_some_and(|hash| merged_in.contains(&hash))
+            {
+                args.push(rev);
+            }
+        }
+
+        let result = self.signed_command(&args);
+
+        if result.is_err() {
+            // Nothing might be in progress, if `git revert` failed before starting.
+            let _ = self.arbitrary_command(&["revert", "--abort"]);
+        }
+
+        result
+    }
+
+    /// Lists the files renamed between the revisions `from` and `to`, as `(old, new)` pairs of paths relative to
+    /// the repository.
+    pub fn renames(&mut self, from: &str, to: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+        self.backend.renames(&self.context(), from, to)
+    }
+
+    /// Fetches the `HEAD` of `source` into `FETCH_HEAD`. `source` is either a remote URL or a path to another
+    /// repository. It will return an error if the repository is not initialized.
+    pub fn fetch_head(&mut self, source: &str) -> Result<Output> {
+        self.arbitrary_command(&["fetch", source, "HEAD"])
+    }
+
+    /// Reads the file at `path` (relative to the repository) as of the revision `rev`. Returns `None` if the file
+    /// does not exist in that revision.
+    pub fn show_file<P>(&mut self, rev: &str, path: P) -> Result<Option<String>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut object = OsString::from(format!("{}:", rev));
+        object.push(path.as_ref());
+
+        if self
+            .arbitrary_command(&[OsStr::new("cat-file"), OsStr::new("-e"), &object])
+            .is_err()
+        {
+            return Ok(None);
+        }
+
+        let output = self.arbitrary_command(&[OsStr::new("show"), &object])?;
+
+        Ok(Some(String::from_utf8_lossy(&output.stdout).to_string()))
+    }
+
+    /// Gets the size in bytes of the file at `path` (relative to the repository) as of the revision `rev`, without
+    /// reading it. Returns `None` if the file does not exist in that revision.
+    pub fn file_size<P>(&mut self, rev: &str, path: P) -> Result<Option<u64>>
+    where
+        P: AsRef<Path>,
+    {
+        let mut object = OsString::from(format!("{}:", rev));
+        object.push(path.as_ref());
+
+        match self.arbitrary_command(&[OsStr::new("cat-file"), OsStr::new("-s"), &object]) {
+            Ok(output) => Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok()),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Restores the file/folder at `path` (relative to the repository) from the newest commit that has it, e.g.
+    /// after it was deleted. Returns whether it was found in the history.
+    pub fn restore_from_history<P>(&mut self, path: P) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        if !self.has_commits() {
+            return Ok(false);
+        }
+
+        let path = path.as_ref().as_os_str();
+        let output = self.arbitrary_command(&[
+            OsStr::new("log"),
+            OsStr::new("--max-count=1"),
+            OsStr::new("--format=%H"),
+            OsStr::new("--"),
+            path,
+        ])?;
+        let hash = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if hash.is_empty() {
+            return Ok(false);
+        }
+
+        // The newest commit touching the path either still has it, or deleted it, in which case its parent has it.
+        for rev in [hash.clone(), format!("{}^", hash)] {
+            if self
+                .arbitrary_command(&[
+                    OsStr::new("checkout"),
+                    OsStr::new(&rev),
+                    OsStr::new("--"),
+                    path,
+                ])
+                .is_ok()
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Marks the files at `paths` (relative to the repository) as binary in the repository's `.gitattributes`, so
+    /// that git never diffs or merges them line by line. Returns whether any files were newly marked.
+    pub fn mark_binary<P>(&mut self, paths: &[P]) -> Result<bool>
+    where
+        P: AsRef<Path>,
+    {
+        let attributes_path = self.path.join(ATTRIBUTES_FILE);
+        let mut attributes = match fs::read(&attributes_path) {
+            Ok(attributes) => attributes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                return Err(IoError::Read {
+                    source: err,
+                    path: attributes_path,
+                }
+                .into())
+            }
+        };
+        let lines = attributes
+            .split(|b| *b == b'\n')
+            .map(|line| line.to_vec())
+            .collect_vec();
+        let missing = paths
+            .iter()
+            .map(|path| binary_attribute(path.as_ref()))
+            .filter(|line| !lines.contains(line))
+            .unique()
+            .collect_vec();
+
+        if missing.is_empty() {
+            return Ok(false);
+        }
+
+        if !attributes.is_empty() && !attributes.ends_with(b"\n") {
+            attributes.push(b'\n');
+        }
+
+        for line in missing {
+            attributes.extend(line);
+            attributes.push(b'\n');
+        }
+
+        fs::write(&attributes_path, attributes).map_err(|err| IoError::Write {
+            source: err,
+            path: attributes_path,
+        })?;
+
+        Ok(true)
+    }
+
+    /// Sets the patterns of the files git ignores (see `ignore.patterns`) in the part of the repository's
+    /// `.gitignore` that `dotbak` manages, leaving the rest of it alone. Returns whether `.gitignore` changed.
+    pub fn set_ignored<S>(&mut self, patterns: &[S]) -> Result<bool>
+    where
+        S: AsRef<str>,
+    {
+        let lines = patterns
+            .iter()
+            .map(|pattern| pattern.as_ref())
+            .collect_vec();
+
+        write_block(
+            &self.path.join(IGNORE_FILE),
+            IGNORE_BEGIN,
+            IGNORE_END,
+            &lines,
+        )
+    }
+
+    /// Sets the patterns of the files stored with git-lfs (see `git.lfs.track`) in the part of the repository's
+    /// `.gitattributes` that `dotbak` manages, leaving the rest of it alone. Returns whether `.gitattributes`
+    /// changed.
+    pub fn set_lfs_tracked<S>(&mut self, patterns: &[S]) -> Result<bool>
+    where
+        S: AsRef<str>,
+    {
+        let lines = patterns
+            .iter()
+            .map(|pattern| format!("{} {}", pattern.as_ref(), LFS_ATTRIBUTES))
+            .collect_vec();
+
+        write_block(&self.path.join(ATTRIBUTES_FILE), LFS_BEGIN, LFS_END, &lines)
+    }
+
+    /// Sets up git-lfs for the repository, so that the files `set_lfs_tracked` marks are stored with it. This only
+    /// installs its hooks in the repository, not globally.
+    pub fn lfs_install(&mut self) -> Result<Output> {
+        self.arbitrary_command(&["lfs", "install", "--local"])
+    }
+
+    /// Stages the committed files again with the current `.gitattributes`, e.g. so that files committed before they
+    /// were marked to be stored with git-lfs are stored with it from the next commit on.
+    pub fn renormalize(&mut self) -> Result<Output> {
+        self.arbitrary_command(&["add", "--renormalize", "--", "."])
+    }
+
+    /// Downloads the git-lfs content of the checked out files, replacing their pointers (e.g. after cloning before
+    /// git-lfs was set up for the repository).
+    pub fn lfs_pull(&mut self) -> Result<Output> {
+        self.arbitrary_command(&["lfs", "pull", REMOTE_NAME])
+    }
+
+    /// Stops tracking the files that are ignored (e.g. after adding patterns to `.gitignore`), without deleting them,
+    /// so that they're removed from the repository with the next commit. Returns the files (relative to the
+    /// repository).
+    pub fn untrack_ignored(&mut self) -> Result<Vec<PathBuf>> {
+        self.backend.untrack_ignored(&self.context())
+    }
+
+    /// Checks out only `paths` (files or folders, relative to the repository) with git's sparse checkout, so that
+    /// files other machines need (like large fonts or wallpapers) aren't in the working tree. The files of the
+    /// repository itself (like `.gitattributes`) are always checked out. If `paths` is `None`, every file is checked
+    /// out again. This needs git 2.35 or newer.
+    pub fn sparse_checkout<P>(&mut self, paths: Option<&[P]>) -> Result<Output>
+    where
+        P: AsRef<Path>,
+    {
+        let Some(paths) = paths else {
+            return self.arbitrary_command(&["sparse-checkout", "disable"]);
+        };
+
+        let mut args = ["sparse-checkout", "set", "--no-cone", "/.git*"]
+            .map(OsString::from)
+            .to_vec();
+        args.extend(paths.iter().map(|path| sparse_pattern(path.as_ref())));
+
+        self.arbitrary_command(&args)
+    }
+
+    /// Whether only some of the files are checked out, see `sparse_checkout`.
+    pub fn is_sparse(&mut self) -> bool {
+        self.backend
+            .config(&self.context(), "core.sparseCheckout")
+            .is_some_and(|value| {
+                ["true", "yes", "on", "1"].contains(&value.to_lowercase().as_str())
+            })
+    }
+
+    /// Lists all the files tracked in the current commit. Returns nothing if there are no commits yet.
+    pub fn tracked_files(&mut self) -> Result<Vec<PathBuf>> {
+        if !self.has_commits() {
+            return Ok(vec![]);
+        }
+
+        let output = self.arbitrary_command(&["ls-tree", "-r", "-z", "--name-only", "HEAD"])?;
+
+        Ok(split_paths(&output.stdout))
+    }
+
+    /// Counts the files that differ between the commits `from` and `to`. If there's no `from` (e.g. before the
+    /// first commit), all the files in `to` are counted.
+    pub fn count_changed_files(&mut self, from: Option<&str>, to: &str) -> Result<usize> {
+        self.backend.count_changed_files(&self.context(), from, to)
+    }
+
+    /// Lists all the files in the history of the current branch, with the size in bytes of their largest version.
+    /// Returns nothing if there are no commits yet.
+    pub fn history_files(&mut self) -> Result<Vec<(PathBuf, u64)>> {
+        if !self.has_commits() {
+            return Ok(vec![]);
+        }
+
+        // Each object is listed as `<hash> <type> <size>`, and each object in the history as `<hash> <path>`.
+        let objects = self.arbitrary_command(&[
+            "cat-file",
+            "--batch-all-objects",
+            "--batch-check=%(objectname) %(objecttype) %(objectsize)",
+        ])?;
+        let sizes: HashMap<String, u64> = String::from_utf8_lossy(&objects.stdout)
+            .lines()
+            .filter_map(|line| match line.split(' ').collect_vec()[..] {
+                [hash, "blob", size] => Some((hash.to_string(), size.parse().ok()?)),
+                _ => None,
+            })
+            .collect();
+
+        let history = self.arbitrary_command(&["rev-list", "--objects", "HEAD"])?;
+        let mut files: BTreeMap<PathBuf, u64> = BTreeMap::new();
+
+        for line in history.stdout.split(|b| *b == b'\n') {
+            let Some(space) = line.iter().position(|b| *b == b' ') else {
+                continue;
+            };
+            let Some(size) = sizes.get(String::from_utf8_lossy(&line[..space]).as_ref()) else {
+                continue;
+            };
+
+            let path = PathBuf::from(platform::os_string_from_bytes(&line[space + 1..]));
+            let largest = files.entry(path).or_default();
+            *largest = (*largest).max(*size);
+        }
+
+        Ok(files.into_iter().collect())
+    }
+
+    /// Rewrites the history of the current branch without the files/folders `paths` (relative to the repository),
+    /// leaving out the commits that end up empty. The rewritten commits aren't signed anymore. Other branches and the
+    /// remote's history aren't rewritten (see `force_push_to`), and the old history is only deleted by `gc`.
+    pub fn drop_from_history(&mut self, paths: &[PathBuf]) -> Result<Output> {
+        let git_dir = self.arbitrary_command(&["rev-parse", "--absolute-git-dir"])?;
+        let list = PathBuf::from(platform::os_string_from_bytes(git_dir.stdout.trim_ascii()))
+            .join(DROPPED_PATHS_FILE);
+
+        let contents = paths
+            .iter()
+            .flat_map(|path| [&platform::os_str_bytes(path.as_os_str())[..], b"\0"].concat())
+            .collect_vec();
+        fs::write(&list, contents).map_err(|err| IoError::Write {
+            source: err,
+            path: list.clone(),
+        })?;
+
+        // The filter unstages the listed paths in each commit, taking them literally rather than as patterns.
+        let filter = format!(
+            r#"git --literal-pathspecs rm -r --cached --ignore-unmatch --quiet --pathspec-from-file="$GIT_DIR/{}" --pathspec-file-nul"#,
+            DROPPED_PATHS_FILE
+        );
+        let branch = format!("refs/heads/{}", self.branch);
+        let result = self.arbitrary_command(&[
+            "filter-branch",
+            "--force",
+            "--index-filter",
+            &filter,
+            "--prune-empty",
+            "--",
+            &branch,
+        ]);
+        let _ = fs::remove_file(&list);
+        let output = result?;
+
+        // `filter-branch` keeps the old history around under `refs/original`.
+        let original = format!("refs/original/{}", branch);
+
+        if self.has_rev(&original) {
+            self.arbitrary_command(&["update-ref", "-d", &original])?;
+        }
+
+        Ok(output)
+    }
+
+    /// Deletes what the history doesn't need anymore with `git gc`. If `rewritten` is set (after `drop_from_history`),
+    /// the reflogs of the current branch are emptied first, so that the old history is deleted too.
+    pub fn gc(&mut self, rewritten: bool) -> Result<Output> {
+        if rewritten {
+            let refs = [
+                "HEAD".to_string(),
+                format!("refs/heads/{}", self.branch),
+                format!("refs/remotes/{}/{}", REMOTE_NAME, self.branch),
+            ];
+            let mut args = vec!["reflog", "expire", "--expire=now"];
+            args.extend(
+                refs.iter()
+                    .map(String::as_str)
+                    .filter(|rev| self.has_rev(rev)),
+            );
+
+            self.arbitrary_command(&args)?;
+        }
+
+        self.arbitrary_command(&["gc", "--prune=now", "--quiet"])
+    }
+
+    /// Gets the size in bytes of the objects in the repository, as `git count-objects` counts them.
+    pub fn objects_size(&mut self) -> Result<u64> {
+        let output = self.arbitrary_command(&["count-objects", "-v"])?;
+
+        // The sizes are in KiB.
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .filter(|(key, _)| matches!(*key, "size" | "size-pack" | "size-garbage"))
+            .filter_map(|(_, size)| size.parse::<u64>().ok())
+            .sum::<u64>()
+            * 1024)
+    }
+
+    /// Gets the history of the repository, newest commit first. If `path` is given, only the commits changing it
+    /// are returned (following renames). Returns nothing if there are no commits yet.
+    pub fn history(&mut self, path: Option<&Path>) -> Result<Vec<Commit>> {
+        if !self.has_commits() {
+            return Ok(vec![]);
+        }
+
+        let mut args = ["log", "--date=format:%Y-%m-%d %H:%M", COMMIT_FORMAT]
+            .map(OsStr::new)
+            .to_vec();
+
+        if let Some(path) = path {
+            args.extend([OsStr::new("--follow"), OsStr::new("--"), path.as_os_str()]);
+        }
+
+        let output = self.arbitrary_command(&args)?;
+
+        Ok(parse_commits(&output.stdout))
+    }
+
+    /// Gets the history of the repository like `history`, along with the files each commit touched, newest commit
+    /// first. Only the newest `limit` commits are returned, if it's given.
+    pub fn log(&mut self, path: Option<&Path>, limit: Option<usize>) -> Result<Vec<LogEntry>> {
+        self.backend.log(&self.context(), path, limit)
+    }
+
+    /// Merges the revision `rev` into the current branch, even if the two don't share any history. If the merge
+    /// succeeds, it is committed with `message`. Otherwise, the merge is left in progress and the conflicting
+    /// paths are returned, so they can be resolved with `resolve_conflict` and committed with `commit_merge`.
+    pub fn merge_unrelated(&mut self, rev: &str, message: &str) -> Result<Vec<PathBuf>> {
+        let result = self.signed_command(&[
+            "merge",
+            "--allow-unrelated-histories",
+            "--no-edit",
+            "-m",
+            message,
+            rev,
+        ]);
+
+        match result {
+            Ok(_) => Ok(vec![]),
+
+            // If the merge failed because of conflicts, return them. Otherwise, it's a "real" error.
+            Err(err) => {
+                let conflicts = self.conflicts()?;
+
+                if conflicts.is_empty() {
+                    Err(err)
+                } else {
+                    Ok(conflicts)
+                }
+            }
+        }
+    }
+
+    /// Lists the paths (relative to the repository) that currently have merge conflicts.
+    pub fn conflicts(&mut self) -> Result<Vec<PathBuf>> {
+        let output = self.arbitrary_command(&["diff", "--name-only", "--diff-filter=U", "-z"])?;
+
+        Ok(split_paths(&output.stdout))
+    }
+
+    /// Resolves the merge conflict at `path` (relative to the repository) by keeping `side`'s version. If that
+    /// side deleted the file, the file is deleted.
+    pub fn resolve_conflict<P>(&mut self, path: P, side: MergeSide) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().as_os_str();
+        let side_flag = match side {
+            MergeSide::Ours => "--ours",
+            MergeSide::Theirs => "--theirs",
+        };
+
+        match self.arbitrary_command(&[
+            OsStr::new("checkout"),
+            OsStr::new(side_flag),
+            OsStr::new("--"),
+            path,
+        ]) {
+            Ok(_) => self.arbitrary_command(&[OsStr::new("add"), OsStr::new("--"), path])?,
+            Err(_) => self.arbitrary_command(&[
+                OsStr::new("rm"),
+                OsStr::new("--quiet"),
+                OsStr::new("--"),
+                path,
+            ])?,
+        };
+
+        Ok(())
+    }
+
+    /// Commits a merge after all of its conflicts have been resolved, using the merge's default message.
+    pub fn commit_merge(&mut self) -> Result<Output> {
+        self.signed_command(&["commit", "--no-edit"])
+    }
+
+    /// Deletes the git repository, moving it to `trash` if there is one, or permanently otherwise. It will return an
+    /// error if the repository is not initialized or is not there. Will not return an error if the repository is not
+    /// empty.
+    /// TODO: implement logging and such.
+    /// TODO: Move symlinked files to their original location.
+    pub fn delete(self, trash: Option<&dyn Trash>) -> Result<()> {
+        platform::delete(&self.path, trash).map_err(|err| IoError::Delete {
+            source: err,
+            path: self.path,
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Private git API for `Repository`.
+impl Repository {
+    /// Checks if the commit `rev` is a merge, i.e. has more than one parent.
+    fn is_merge(&mut self, rev: &str) -> bool {
+        self.arbitrary_command(&["rev-list", "--parents", "-n1", rev])
+            .is_ok_and(|output| {
+                String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .count()
+                    > 2
+            })
+    }
+
+    /// Gets the names of the retired hosts, i.e. the ones with a local or remote-tracking archive branch.
+    fn retired_hosts(&mut self) -> Result<Vec<String>> {
+        let output = self.arbitrary_command(&[
+            "for-each-ref",
+            "--format=%(refname)",
+            &format!("refs/heads/{}", ARCHIVED_HOST_BRANCH_PREFIX),
+            &format!(
+                "refs/remotes/{}/{}",
+                REMOTE_NAME, ARCHIVED_HOST_BRANCH_PREFIX
+            ),
+        ])?;
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|refname| refname.split_once(ARCHIVED_HOST_BRANCH_PREFIX))
+            .map(|(_, name)| name.to_string())
+            .collect())
+    }
+
+    /// Checks if the revision `rev` exists.
+    fn has_rev(&mut self, rev: &str) -> bool {
+        self.resolve(rev).is_some()
+    }
+
+    /// Checks if the current branch has any commits yet.
+    fn has_commits(&mut self) -> bool {
+        self.has_rev("HEAD")
+    }
+
+    /// Lists the hashes of the commits `git log` lists with the arguments `args` that were committed by this machine
+    /// (see `set_host`) with the summary `summary`. If no host is set, the host isn't checked.
+    fn own_commits(&mut self, summary: &str, args: &[&str]) -> Result<Vec<String>> {
+        let format = format!(
+            "--format=%H%x1f%s%x1f%(trailers:key={},valueonly,separator=%x1f)",
+            HOST_TRAILER
+        );
+        let output = self.arbitrary_command(&[&["log", &format], args, &["--"]].concat())?;
+        let host = self.host.clone();
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\x1f');
+                let (hash, subject) = (fields.next()?, fields.next()?);
+                let own = match &host {
+                    Some(host) => fields.any(|name| name.trim() == host),
+                    None => true,
+                };
+
+                (subject == summary && own).then(|| hash.to_string())
+            })
+            .collect())
+    }
+
+    /// Pulls all commits from the branch `branch` of the remote repository into the current branch, reconciling them
+    /// with the local commits according to `strategy`.
+    #[tracing::instrument(level = "debug", skip(self))]
+    fn pull_with(&mut self, branch: &str, strategy: PullStrategy) -> Result<Output> {
+        self.backend
+            .pull(&self.context(), REMOTE_NAME, branch, strategy)
+    }
+
+    /// Gets what the backend needs to know about the repository. See `GitBackend`.
+    fn context(&self) -> Context<'_> {
+        Context {
+            path: &self.path,
+            low_bandwidth: self.low_bandwidth,
+            credentials: self.credentials.as_ref(),
+            timeouts: &self.timeouts,
+            signing: self.signing.as_ref(),
+            progress: self.progress.as_ref(),
+        }
+    }
+}
+
+/// These are helper functions for tests on `Repository`.
+#[cfg(test)]
+impl Repository {
+    /// Get the path to the repository.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Gets the version of git, as printed by `git --version` (e.g. `git version 2.39.5`).
+pub fn version() -> Result<String> {
+    let output = git_command()
+        .arg("--version")
+        .output()
+        .map_err(|err| IoError::CommandIO {
+            source: err,
+            command: "git".to_string(),
+            args: vec!["--version".to_string()],
+        })?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Shows the differences between two files/folders `old` and `new`, which don't need to be inside a repository.
+/// The output is colored if colors are enabled for the terminal. Returns an empty string if they are the same.
+pub fn diff_paths<P1, P2>(old: P1, new: P2) -> Result<String>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let args = [
+        OsStr::new("diff"),
+        OsStr::new("--no-index"),
+        OsStr::new(color_flag()),
+        OsStr::new("--"),
+        old.as_ref().as_os_str(),
+        new.as_ref().as_os_str(),
+    ];
+
+    let output = git_command()
+        .args(args)
+        .output()
+        .map_err(|err| IoError::CommandIO {
+            source: err,
+            command: "git".to_string(),
+            args: display_args(&args),
+        })?;
+
+    // `git diff --no-index` exits with 1 if there are differences, which isn't an error.
+    match output.status.code() {
+        Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+        _ => Err(classify_failure(
+            display_args(&args),
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Run a command in the repository.
+///
+/// `path` is the path to the repository.
+///
+/// `args` is the arguments to pass to the command.
+///
+/// `credentials` are the credentials to authenticate with HTTPS remotes, if there are any.
+///
+/// `timeouts` are how long the command may run, see `Repository::set_timeouts` and `timeout_for`.
+///
+/// Returns the output of the command. Failures are told apart by `classify_failure`, and commands that run for too
+/// long are killed with a `CommandTimeout` error.
+fn run_arbitrary_git_command<P, S>(
+    path: P,
+    args: &[S],
+    credentials: Option<&Credentials>,
+    timeouts: &BTreeMap<String, u64>,
+) -> Result<Output>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    run_streamed_git_command(path, args, credentials, timeouts, None)
+}
+
+/// Runs git like `run_arbitrary_git_command`, but reports what git prints to stderr (e.g. its progress, with
+/// `--progress`) to `progress` line by line while it runs. Lines git overwrites (ending with a carriage return) are
+/// only reported, and left out of the returned output.
+#[tracing::instrument(level = "trace", name = "git", skip_all, fields(args = %display_args(args).join(" ")))]
+fn run_streamed_git_command<P, S>(
+    path: P,
+    args: &[S],
+    credentials: Option<&Credentials>,
+    timeouts: &BTreeMap<String, u64>,
+    progress: Option<&ProgressHandler>,
+) -> Result<Output>
+where
+    P: AsRef<Path>,
+    S: AsRef<OsStr>,
+{
+    let mut command = git_command();
+
+    if let Some(credentials) = credentials {
+        credentials.apply(&mut command);
+    }
+
+    command.args(args).current_dir(path);
+
+    let io_err = |err: std::io::Error| -> DotbakError {
+        match err.kind() {
+            std::io::ErrorKind::NotFound => GitError::NotInstalled {
+                args: display_args(args),
+            }
+            .into(),
+            _ => IoError::CommandIO {
+                source: err,
+                command: "git".to_string(),
+                args: display_args(args),
+            }
+            .into(),
+        }
+    };
+
+    // Run the command.
+    let timeout = timeout_for(args, timeouts);
+    let output = match (timeout, progress) {
+        (None, None) => command.output().map_err(io_err)?,
+        _ => match watch_output(&mut command, timeout, progress).map_err(io_err)? {
+            Some(output) => output,
+            None => {
+                return Err(GitError::CommandTimeout {
+                    args: display_args(args),
+                    timeout: timeout.unwrap_or_default().as_secs(),
+                }
+                .into())
+            }
+        },
+    };
+
+    // If the command succeeded, return.
+    if output.status.success() {
+        return Ok(output);
+    }
+
+    Err(classify_failure(
+        display_args(args),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+    .into())
+}
+
+/// Gets how long the git command run with `args` may run with `timeouts` (see `Repository::set_timeouts`), or `None`
+/// if it has no timeout.
+fn timeout_for<S>(args: &[S], timeouts: &BTreeMap<String, u64>) -> Option<Duration>
+where
+    S: AsRef<OsStr>,
+{
+    // The subcommand is the first argument that isn't an option, skipping the values of `-c` and `-C`.
+    let mut args = args.iter().map(|arg| arg.as_ref().to_string_lossy());
+    let mut subcommand = String::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "-c" | "-C" => {
+                args.next();
+            }
+            arg if arg.starts_with('-') => {}
+            arg => {
+                subcommand = arg.to_string();
+                break;
+            }
+        }
+    }
+
+    let secs = timeouts
+        .get(&subcommand)
+        .or_else(|| timeouts.get(DEFAULT_TIMEOUT_KEY))
+        .copied()
+        .unwrap_or(match REMOTE_COMMANDS.contains(&subcommand.as_str()) {
+            true => REMOTE_TIMEOUT,
+            false => 0,
+        });
+
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Runs `command` like `Command::output`, but kills it once it ran for `timeout` (if there is one), and reports the
+/// lines it prints to stderr to `progress` while it runs (see `run_streamed_git_command`). Returns `None` if it was
+/// killed. It runs in its own process group, so that what it started (e.g. `ssh`, or a credential helper) is killed
+/// with it, instead of keeping its output open.
+fn watch_output(
+    command: &mut Command,
+    timeout: Option<Duration>,
+    progress: Option<&ProgressHandler>,
+) -> std::io::Result<Option<Output>> {
+    platform::own_process_group(command);
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Read the output while waiting, so that the command doesn't block on a full pipe.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = match progress {
+        Some(progress) => stream_in_background(child.stderr.take(), progress.clone()),
+        None => read_in_background(child.stderr.take()),
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            platform::kill_group(&mut child)?;
+
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(20));
+    };
+
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+/// Helper function to read all of `pipe` (e.g. the output of a command) in another thread.
+fn read_in_background<R>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut buf = vec![];
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+
+        buf
+    })
+}
+
+/// Helper function to read all of `pipe` in another thread like `read_in_background`, reporting every line to
+/// `progress` as soon as it's read. Lines ending with a carriage return (which git overwrites with the next one, e.g.
+/// for percentages) are left out of what's returned.
+fn stream_in_background<R>(
+    pipe: Option<R>,
+    progress: ProgressHandler,
+) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let (mut buf, mut line) = (vec![], vec![]);
+        let (mut chunk, mut overwritten) = ([0; 1024], false);
+        let report = |line: &[u8]| {
+            let line = String::from_utf8_lossy(line);
+
+            if !line.trim().is_empty() {
+                progress.report(line.trim());
+            }
+        };
+
+        let Some(mut pipe) = pipe else {
+            return buf;
+        };
+
+        while let Ok(read) = pipe.read(&mut chunk) {
+            if read == 0 {
+                break;
+            }
+
+            for &byte in &chunk[..read] {
+                match byte {
+                    b'\n' => {
+                        if !overwritten {
+                            report(&line);
+                        }
+
+                        buf.append(&mut line);
+                        buf.push(b'\n');
+                        overwritten = false;
+                    }
+                    b'\r' => {
+                        report(&line);
+                        overwritten = true;
+                    }
+                    byte => {
+                        if overwritten {
+                            line.clear();
+                            overwritten = false;
+                        }
+
+                        line.push(byte);
+                    }
+                }
+            }
+        }
+
+        if !overwritten {
+            report(&line);
+        }
+
+        buf.append(&mut line);
+        buf
+    })
+}
+
+/// Tells what kind of failure a git command run with `args` had from its output `stdout` and `stderr`, so it gets
+/// the matching `GitError` (with help on fixing it).
+fn classify_failure(args: Vec<String>, stdout: String, stderr: String) -> GitError {
+    let says = |failures: &[&str]| {
+        failures
+            .iter()
+            .any(|failure| stdout.contains(failure) || stderr.contains(failure))
+    };
+
+    if let Some(transport) = AuthFailure::detect(&stderr) {
+        GitError::AuthFailed {
+            transport,
+            args,
+            stderr,
+            help: transport.help(),
+        }
+    } else if says(IDENTITY_FAILURES) {
+        GitError::MissingIdentity { args, stderr }
+    } else if says(CONFLICT_FAILURES) {
+        GitError::MergeConflict {
+            args,
+            stdout,
+            stderr,
+        }
+    } else if says(DIRTY_FAILURES) {
+        GitError::DirtyWorktree { args, stderr }
+    } else if says(DETACHED_FAILURES) {
+        GitError::DetachedHead { args, stderr }
+    } else if says(NO_UPSTREAM_FAILURES) {
+        GitError::NoUpstream { args, stderr }
+    } else if says(UNREACHABLE_FAILURES) {
+        GitError::RemoteUnreachable { args, stderr }
+    } else if says(NOTHING_TO_COMMIT_FAILURES) {
+        GitError::NothingToCommit { args, stdout }
+    } else {
+        GitError::Command {
+            args,
+            stdout,
+            stderr,
+        }
+    }
+}
+
+/// Builds the `.gitattributes` line marking the file at `path` (relative to the repository) as binary. The path is
+/// anchored to the repository, and the characters that are special in patterns are escaped.
+fn binary_attribute(path: &Path) -> Vec<u8> {
+    let mut line = vec![b'/'];
+
+    for byte in platform::os_str_bytes(path.as_os_str()).iter() {
+        match byte {
+            b' ' => line.extend(b"[[:space:]]"),
+            b'*' | b'?' | b'[' | b'\\' => line.extend([b'\\', *byte]),
+            _ => line.push(*byte),
+        }
+    }
+
+    line.extend(b" binary");
+    line
+}
+
+/// The git configuration key holding the object filter of a filtered clone.
+fn partial_clone_filter_key() -> String {
+    format!("remote.{}.partialclonefilter", REMOTE_NAME)
+}
+
+/// Builds the sparse checkout pattern checking out the file or folder at `path` (relative to the repository). Like
+/// in `binary_attribute`, the path is anchored to the repository, and the characters that are special in patterns
+/// are escaped.
+fn sparse_pattern(path: &Path) -> OsString {
+    let mut pattern = vec![b'/'];
+
+    for byte in platform::os_str_bytes(path.as_os_str()).iter() {
+        match byte {
+            b'*' | b'?' | b'[' | b'\\' => pattern.extend([b'\\', *byte]),
+            _ => pattern.push(*byte),
+        }
+    }
+
+    platform::os_string_from_bytes(&pattern)
+}
+
+/// Parses the output of `git log` with `COMMIT_FORMAT` into `Commit`s.
+fn parse_commits(output: &[u8]) -> Vec<Commit> {
+    String::from_utf8_lossy(output)
+        .lines()
+        .filter_map(|line| {
+            let (hash, author, email, date, summary) = line.splitn(5, '\x1f').collect_tuple()?;
+
+            Some(Commit {
+                hash: hash.to_string(),
+                author: author.to_string(),
+                email: email.to_string(),
+                date: date.to_string(),
+                summary: summary.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses the output of `git log --name-only -z` with `LOG_FORMAT` into `LogEntry`s. Each commit starts with a
+/// record separator, and its fields end with a NUL, followed by the NUL-separated files it touched.
+fn parse_log(output: &[u8]) -> Vec<LogEntry> {
+    output
+        .split(|b| *b == 0x1e)
+        .filter_map(|record| {
+            let end = record.iter().position(|b| *b == 0).unwrap_or(record.len());
+            let commit = parse_commits(&record[..end]).pop()?;
+            let files = &record[end..];
+            let files = files.strip_prefix(b"\0").unwrap_or(files);
+            let files = split_paths(files.strip_prefix(b"\n").unwrap_or(files));
+
+            Some(LogEntry { commit, files })
+        })
+        .collect()
+}
+
+/// The flag for `git diff` to color its output if colors are enabled for the terminal.
+fn color_flag() -> &'static str {
+    if console::colors_enabled() {
+        "--color=always"
+    } else {
+        "--color=never"
+    }
+}
+
+/// Creates a `git` command that runs in the C locale, so that its output doesn't depend on the user's language
+/// settings.
+fn git_command() -> Command {
+    let mut command = Command::new("git");
+    command.env("LC_ALL", "C").env("LANGUAGE", "C");
+
+    // Fail instead of asking for credentials on the terminal, see `Repository::set_credentials`.
+    command.env("GIT_TERMINAL_PROMPT", "0");
+
+    // `git filter-branch` waits a few seconds after warning that it's deprecated. Its uses here are safe.
+    command.env("FILTER_BRANCH_SQUELCH_WARNING", "1");
+
+    // Date commits with the scripted time, so that tests control the history's timeline.
+    if let Some(now) = clock::scripted() {
+        let date = format!("@{} +0000", now);
+        command
+            .env("GIT_AUTHOR_DATE", &date)
+            .env("GIT_COMMITTER_DATE", &date);
+    }
+
+    command
+}
+
+/// Checks if git-lfs is installed, i.e. if `git lfs` can be run.
+pub fn lfs_available() -> bool {
+    git_command()
+        .args(["lfs", "version"])
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Replaces the part of the file at `path` between the lines `begin` and `end` with `lines`, or appends it if there
+/// is none, leaving the rest of the file alone. The part is left out if `lines` is empty, and the file is removed if
+/// nothing is left. Returns whether the file changed.
+fn write_block<S>(path: &Path, begin: &str, end: &str, lines: &[S]) -> Result<bool>
+where
+    S: AsRef<str>,
+{
+    let existing = match fs::read_to_string(path) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => {
+            return Err(IoError::Read {
+                source: err,
+                path: path.to_path_buf(),
+            }
+            .into())
+        }
+    };
+
+    // Keep everything outside of the managed part, which stays where it is.
+    let (mut kept, mut after) = (vec![], vec![]);
+    let mut block = None;
+
+    for line in existing.lines() {
+        match block {
+            None if line == begin => block = Some(false),
+            Some(false) if line == end => block = Some(true),
+            Some(false) => (),
+            Some(true) => after.push(line),
+            None => kept.push(line),
+        }
+    }
+
+    while kept.last().is_some_and(|line| line.is_empty()) {
+        kept.pop();
+    }
+
+    if !lines.is_empty() {
+        if !kept.is_empty() {
+            kept.push("");
+        }
+
+        kept.push(begin);
+        kept.extend(lines.iter().map(|line| line.as_ref()));
+        kept.push(end);
+    } else {
+        while after.first().is_some_and(|line| line.is_empty()) {
+            after.remove(0);
+        }
+
+        if !kept.is_empty() && !after.is_empty() {
+            kept.push("");
+        }
+    }
+
+    kept.extend(after);
+
+    let contents = kept.iter().map(|line| format!("{}\n", line)).join("");
+
+    if contents == existing {
+        return Ok(false);
+    }
+
+    let written = match contents.is_empty() {
+        true => fs::remove_file(path),
+        false => fs::write(path, contents),
+    };
+
+    written.map_err(|err| IoError::Write {
+        source: err,
+        path: path.to_path_buf(),
+    })?;
+
+    Ok(true)
+}
+
+/// Splits NUL-separated paths (as printed by git with `-z`) into paths, keeping their bytes as-is even if they
+/// aren't valid UTF-8.
+fn split_paths(output: &[u8]) -> Vec<PathBuf> {
+    output
+        .split(|b| *b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| PathBuf::from(platform::os_string_from_bytes(path)))
+        .collect()
+}
+
+/// Converts command arguments to strings for error messages and logs. Arguments that aren't valid UTF-8 are
+/// decoded lossily, as they are only displayed, and the credentials in URLs are redacted (see
+/// `diagnostics::redact_url`).
+fn display_args<S>(args: &[S]) -> Vec<String>
+where
+    S: AsRef<OsStr>,
+{
+    args.iter()
+        .map(|arg| diagnostics::redact_url(&arg.as_ref().to_string_lossy()))
+        .collect()
 }
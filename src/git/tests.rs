@@ -1,14 +1,27 @@
 #![cfg(test)]
 
 use crate::{
-    errors::{io::IoError, DotbakError},
-    git::Repository,
+    config::git::{Backend, PullStrategy, SigningFormat},
+    errors::{git::GitError, io::IoError, DotbakError},
+    git::{
+        auth::{AuthFailure, Credentials},
+        display_args, git_command, stream_in_background, CloneOptions, MergeSide, ProgressHandler,
+        Remote, Repository, Signing, HOST_BRANCH_PREFIX, IGNORE_BEGIN, IGNORE_END, LFS_ATTRIBUTES,
+        LFS_BEGIN, LFS_END,
+    },
     repo_exists, repo_not_exists,
+    test_util::{self, TestEnv, SEED_FILE},
 };
+use age::secrecy::SecretString;
 use assert_fs::{prelude::*, TempDir};
-
-/// The repository URL for the test repository.
-const TEST_GIT_REPO_URL: &str = "https://github.com/cogsandsquigs/dotbak";
+use itertools::Itertools;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
 
 /// Test if we can create a new repository at a given path.
 #[test]
@@ -121,31 +134,34 @@ fn test_load_path_nonexistent() {
 /// Test if we can clone a remote repository into a given path.
 #[test]
 fn test_clone_path_exists() {
-    // Create a temporary directory.
+    // Create a temporary directory and the remote.
     let tmp_dir = TempDir::new().unwrap();
+    let env = TestEnv::new();
 
     // Get the path to the repo directory.
     let repo_dir = tmp_dir.path();
 
     // Initialize the repository.
-    let repo = Repository::clone(repo_dir, TEST_GIT_REPO_URL).unwrap();
+    let repo = Repository::clone(repo_dir, env.remote_url(), false).unwrap();
 
     // Check if the repository exists.
     repo_exists!(repo_dir);
     assert_eq!(repo.path, repo_dir);
+    assert!(repo_dir.join(SEED_FILE).exists());
 }
 
 /// Test if we can clone a remote repository into a given path that doesn't exist.
 #[test]
 fn test_clone_path_nonexistent() {
-    // Create a temporary directory.
+    // Create a temporary directory and the remote.
     let tmp_dir = TempDir::new().unwrap();
+    let env = TestEnv::new();
 
     // Get the path to the repo directory.
     let repo_dir = tmp_dir.path().join("some/sub/folders");
 
     // Initialize the repository.
-    let repo = Repository::clone(&repo_dir, TEST_GIT_REPO_URL).unwrap();
+    let repo = Repository::clone(&repo_dir, env.remote_url(), false).unwrap();
 
     // Check if the repository exists.
     repo_exists!(&repo_dir);
@@ -170,7 +186,7 @@ fn test_clone_exists_already() {
 
     // Try to clone the repository again.
     // THIS SHOULD PANIC
-    let result = Repository::clone(repo_dir, TEST_GIT_REPO_URL);
+    let result = Repository::clone(repo_dir, TestEnv::new().remote_url(), false);
 
     // Check if the result is an error.
     assert!(result.is_err());
@@ -178,7 +194,7 @@ fn test_clone_exists_already() {
     // Check that it is a git clone error.
     assert!(matches!(
         result,
-        Err(DotbakError::Io ( IoError::CommandRun { stderr, .. })) if stderr.contains("already exists and is not an empty directory")
+        Err(DotbakError::Git(GitError::Command { stderr, .. })) if stderr.contains("already exists and is not an empty directory")
     ));
 }
 
@@ -220,6 +236,191 @@ fn test_arbitrary_command() {
     assert!(tmp_dir.child("test.txt").path().exists());
 }
 
+/// Test that failing git commands get the error matching what went wrong.
+#[test]
+fn test_classify_failure() {
+    let tmp_dir = TempDir::new().unwrap();
+    let remote_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(tmp_dir.path(), None).unwrap();
+
+    tmp_dir.child("a.txt").write_str("a").unwrap();
+    repo.arbitrary_command(&["add", "."]).unwrap();
+    repo.arbitrary_command(&["commit", "-m", "a"]).unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["commit", "-m", "nothing"]),
+        Err(DotbakError::Git(GitError::NothingToCommit { .. }))
+    ));
+
+    assert!(matches!(
+        repo.arbitrary_command(&[
+            "-c",
+            "user.name=",
+            "-c",
+            "user.email=",
+            "commit",
+            "--allow-empty",
+            "-m",
+            "nobody",
+        ]),
+        Err(DotbakError::Git(GitError::MissingIdentity { .. }))
+    ));
+
+    repo.arbitrary_command(&["checkout", "-b", "other"])
+        .unwrap();
+    tmp_dir.child("a.txt").write_str("other").unwrap();
+    repo.arbitrary_command(&["commit", "-am", "other"]).unwrap();
+    repo.arbitrary_command(&["checkout", "-"]).unwrap();
+    tmp_dir.child("a.txt").write_str("main").unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["checkout", "other"]),
+        Err(DotbakError::Git(GitError::DirtyWorktree { .. }))
+    ));
+
+    repo.arbitrary_command(&["commit", "-am", "main"]).unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["merge", "other"]),
+        Err(DotbakError::Git(GitError::MergeConflict { .. }))
+    ));
+
+    repo.arbitrary_command(&["merge", "--abort"]).unwrap();
+    repo.arbitrary_command(&["checkout", "--detach"]).unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["pull"]),
+        Err(DotbakError::Git(GitError::DetachedHead { .. }))
+    ));
+
+    repo.arbitrary_command(&["checkout", "-"]).unwrap();
+    repo.arbitrary_command(&["init", "--bare", &remote_dir.path().to_string_lossy()])
+        .unwrap();
+    repo.arbitrary_command(&[
+        "remote",
+        "add",
+        "bare",
+        &remote_dir.path().to_string_lossy(),
+    ])
+    .unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["push"]),
+        Err(DotbakError::Git(GitError::NoUpstream { .. }))
+    ));
+
+    repo.arbitrary_command(&[
+        "remote",
+        "add",
+        "gone",
+        &remote_dir.path().join("gone").to_string_lossy(),
+    ])
+    .unwrap();
+
+    assert!(matches!(
+        repo.arbitrary_command(&["fetch", "gone"]),
+        Err(DotbakError::Git(GitError::RemoteUnreachable { .. }))
+    ));
+    assert!(matches!(
+        repo.arbitrary_command(&["frobnicate"]),
+        Err(DotbakError::Git(GitError::Command { .. }))
+    ));
+}
+
+/// Test that git commands get the timeout for their subcommand, and are stopped once they run for longer.
+#[test]
+fn test_timeouts() {
+    use crate::git::timeout_for;
+    use std::time::Duration;
+
+    let mut timeouts = BTreeMap::new();
+
+    assert_eq!(
+        timeout_for(&["-c", "core.compression=9", "push"], &timeouts),
+        Some(Duration::from_secs(600))
+    );
+    assert_eq!(timeout_for(&["commit", "-m", "push"], &timeouts), None);
+
+    timeouts.insert("push".to_string(), 0);
+    timeouts.insert("default".to_string(), 30);
+
+    assert_eq!(timeout_for(&["push"], &timeouts), None);
+    assert_eq!(
+        timeout_for(&["commit"], &timeouts),
+        Some(Duration::from_secs(30))
+    );
+
+    let tmp_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(tmp_dir.path(), None).unwrap();
+    repo.set_timeouts(BTreeMap::from([("hang".to_string(), 1)]));
+
+    assert!(matches!(
+        repo.arbitrary_command(&["-c", "alias.hang=!sleep 10", "hang"]),
+        Err(DotbakError::Git(GitError::CommandTimeout {
+            timeout: 1,
+            ..
+        }))
+    ));
+    assert!(repo.arbitrary_command(&["status"]).is_ok());
+}
+
+/// Test if a timeout also kills what git started, like a hanging `ssh`.
+#[cfg(unix)]
+#[test]
+fn test_timeout_kills_ssh() {
+    use crate::git::watch_output;
+    use std::{
+        os::unix::fs::PermissionsExt,
+        process::Command,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    let tmp_dir = TempDir::new().unwrap();
+    let pid_file = tmp_dir.child("ssh.pid");
+    let ssh = tmp_dir.child("ssh");
+    ssh.write_str(&format!(
+        "#!/bin/sh\necho $$ > '{}'\nexec sleep 30\n",
+        pid_file.path().display()
+    ))
+    .unwrap();
+    std::fs::set_permissions(ssh.path(), std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let start = Instant::now();
+    let mut command = git_command();
+    command
+        .args(["ls-remote", "ssh://example.invalid/repo.git"])
+        .env("GIT_SSH_COMMAND", ssh.path());
+
+    assert!(
+        watch_output(&mut command, Some(Duration::from_secs(1)), None)
+            .unwrap()
+            .is_none()
+    );
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // The fake `ssh` is gone (or a zombie, if nothing reaps orphans here).
+    let pid = std::fs::read_to_string(pid_file.path()).unwrap();
+    let alive = || {
+        let output = Command::new("ps")
+            .args(["-o", "stat=", "-p", pid.trim()])
+            .output()
+            .unwrap();
+        let stat = String::from_utf8_lossy(&output.stdout);
+        !stat.trim().is_empty() && !stat.trim().starts_with('Z')
+    };
+
+    for _ in 0..50 {
+        if !alive() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert!(!alive());
+}
+
 /// Test if we can commit changes to a repository.
 #[test]
 fn test_commit() {
@@ -269,8 +470,9 @@ fn test_commit() {
 /// Test setting the remote of a repository.
 #[test]
 fn test_set_remote() {
-    // Create a temporary directory.
+    // Create a temporary directory and the remote.
     let tmp_dir = TempDir::new().unwrap();
+    let env = TestEnv::new();
 
     // Get the path to the repo directory.
     let mut repo_dir = tmp_dir.path().to_path_buf();
@@ -283,7 +485,7 @@ fn test_set_remote() {
     assert_eq!(repo.path, repo_dir);
 
     // Set the remote.
-    repo.set_remote(TEST_GIT_REPO_URL).unwrap();
+    repo.set_remote(env.remote_url()).unwrap();
 
     // Check if the repository exists.
     repo_exists!(&repo_dir);
@@ -291,14 +493,14 @@ fn test_set_remote() {
 
     // Clone the repository.
     repo_dir = tmp_dir.path().join("clone");
-    let mut repo = Repository::clone(&repo_dir, TEST_GIT_REPO_URL).unwrap();
+    let mut repo = Repository::clone(&repo_dir, env.remote_url(), false).unwrap();
 
     // Check if the repository exists.
     repo_exists!(&repo_dir);
     assert_eq!(repo.path, repo_dir);
 
     // Set the remote.
-    repo.set_remote(TEST_GIT_REPO_URL).unwrap();
+    repo.set_remote(env.remote_url()).unwrap();
 
     // Check if the repository exists.
     repo_exists!(&repo_dir);
@@ -308,58 +510,128 @@ fn test_set_remote() {
 /// Test pushing data to a remote repository.
 #[test]
 fn test_push() {
-    // Check if we are in a CI environment. If we are, skip the test.
-    // This is because the environment doesn't have the correct credentials.
-    // If we are not in a CI environment, run the test.
-    if !is_ci::uncached() {
-        // Create a temporary directory.
-        let tmp_dir = TempDir::new().unwrap();
+    // Create a temporary directory and the remote.
+    let tmp_dir = TempDir::new().unwrap();
+    let env = TestEnv::new();
 
-        // Get the path to the repo directory.
-        let repo_dir = tmp_dir.path();
+    // Get the path to the repo directory.
+    let repo_dir = tmp_dir.path();
 
-        // Initialize the repository.
-        let mut repo = Repository::clone(repo_dir, TEST_GIT_REPO_URL).unwrap();
+    // Initialize the repository.
+    let mut repo = Repository::clone(repo_dir, env.remote_url(), false).unwrap();
 
-        // Check if the repository exists.
-        repo_exists!(repo_dir);
-        assert_eq!(repo.path, repo_dir);
+    // Check if the repository exists.
+    repo_exists!(repo_dir);
+    assert_eq!(repo.path, repo_dir);
 
-        // Push the changes.
-        repo.push().unwrap();
+    // Commit and push the changes.
+    tmp_dir.child("test.txt").write_str("test").unwrap();
+    repo.commit("Test commit").unwrap();
+    repo.push().unwrap();
+
+    // Check that the remote has the commit, and its files.
+    assert_eq!(env.remote_log(), vec!["Test commit", "Initial commit"]);
+    assert_eq!(
+        env.remote_files(),
+        BTreeMap::from([
+            (PathBuf::from(SEED_FILE), "# dotfiles\n".to_string()),
+            (PathBuf::from("test.txt"), "test".to_string()),
+        ])
+    );
+}
 
-        // Check if the repository exists.
-        repo_exists!(repo_dir);
-        assert_eq!(repo.path(), repo_dir);
-    }
-    // Otherwise, skip the test.
-    else {
-        println!("Skipping test_push because we are in a CI environment.");
-    }
+/// Test adding, listing and removing remotes.
+#[test]
+fn test_remotes() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut repo =
+        Repository::init(tmp_dir.path(), Some("https://example.com/a.git".into())).unwrap();
+
+    repo.add_remote("backup", "https://example.com/b.git")
+        .unwrap();
+    repo.add_remote("backup", "https://example.com/c.git")
+        .unwrap();
+
+    assert_eq!(
+        repo.list_remotes().unwrap(),
+        vec![
+            Remote {
+                name: "backup".into(),
+                url: "https://example.com/c.git".into()
+            },
+            Remote {
+                name: "origin".into(),
+                url: "https://example.com/a.git".into()
+            },
+        ]
+    );
+
+    repo.remove_remote("backup").unwrap();
+
+    assert_eq!(repo.list_remotes().unwrap().len(), 1);
+    assert!(repo.remove_remote("backup").is_err());
+}
+
+/// Test switching branches, and that clones work on the remote's default branch.
+#[test]
+fn test_branches() {
+    let tmp_dir = TempDir::new().unwrap();
+    let (seed_dir, clone_dir) = (tmp_dir.path().join("seed"), tmp_dir.path().join("clone"));
+
+    let mut seed = Repository::init(&seed_dir, None).unwrap();
+    assert_eq!(seed.current_branch().as_deref(), Some("main"));
+
+    seed.switch_branch("trunk").unwrap();
+    tmp_dir.child("seed/test.txt").write_str("test").unwrap();
+    seed.commit("Test commit").unwrap();
+    seed.arbitrary_command(&["branch", "laptop"]).unwrap();
+
+    assert_eq!(seed.branch(), "trunk");
+
+    let mut clone = Repository::clone(&clone_dir, seed_dir.display(), false).unwrap();
+    assert_eq!(clone.branch(), "trunk");
+
+    // Branches on the remote are tracked, others are created from the current commit.
+    clone.switch_branch("laptop").unwrap();
+    assert_eq!(clone.current_branch().as_deref(), Some("laptop"));
+    assert_eq!(clone.branch(), "laptop");
+    assert!(clone
+        .arbitrary_command(&["rev-parse", "--verify", "--quiet", "laptop@{upstream}"])
+        .is_ok());
+
+    clone.switch_branch("desktop").unwrap();
+    assert_eq!(clone.current_branch().as_deref(), Some("desktop"));
+    assert_eq!(clone.history(None).unwrap().len(), 1);
 }
 
 /// Test pulling data from a remote repository.
 #[test]
 fn test_pull() {
-    // Create a temporary directory.
+    // Create a temporary directory and the remote.
     let tmp_dir = TempDir::new().unwrap();
+    let env = TestEnv::new();
 
-    // Get the path to the repo directory.
-    let repo_dir = tmp_dir.path();
+    // Get the path to the repo directories.
+    let repo_dir = tmp_dir.child("repo");
+    let other_dir = tmp_dir.child("other");
 
-    // Initialize the repository.
-    let mut repo = Repository::clone(repo_dir, TEST_GIT_REPO_URL).unwrap();
+    // Initialize the repositories.
+    let mut repo = Repository::clone(repo_dir.path(), env.remote_url(), false).unwrap();
+    let mut other = Repository::clone(other_dir.path(), env.remote_url(), false).unwrap();
 
     // Check if the repository exists.
-    repo_exists!(repo_dir);
-    assert_eq!(repo.path, repo_dir);
+    repo_exists!(repo_dir.path());
+    assert_eq!(repo.path, repo_dir.path());
+
+    // Push changes from the other repository, and pull them.
+    other_dir.child("test.txt").write_str("test").unwrap();
+    other.commit("Test commit").unwrap();
+    other.push().unwrap();
 
-    // Pull the changes.
     repo.pull().unwrap();
 
-    // Check if the repository exists.
-    repo_exists!(repo_dir);
-    assert_eq!(repo.path, repo_dir);
+    // Check that the changes arrived.
+    repo_dir.child("test.txt").assert("test");
 }
 
 /// Test the deletion of a repository.
@@ -379,8 +651,1219 @@ fn test_delete() {
     assert_eq!(repo.path, repo_dir);
 
     // Delete the repository.
-    repo.delete().unwrap();
+    repo.delete(None).unwrap();
 
     // Check if the repository exists.
     repo_not_exists!(repo_dir);
 }
+
+/// Test merging an unrelated repository, and resolving the conflicts.
+#[test]
+fn test_merge_unrelated() {
+    let tmp_dir = TempDir::new().unwrap();
+    let ours_dir = tmp_dir.child("ours");
+    let theirs_dir = tmp_dir.child("theirs");
+
+    let mut ours = Repository::init(ours_dir.path(), None).unwrap();
+    let mut theirs = Repository::init(theirs_dir.path(), None).unwrap();
+
+    ours_dir.child("shared.txt").write_str("ours").unwrap();
+    ours_dir.child("ours.txt").touch().unwrap();
+    ours.commit("Ours").unwrap();
+
+    theirs_dir.child("shared.txt").write_str("theirs").unwrap();
+    theirs_dir.child("theirs.txt").touch().unwrap();
+    theirs.commit("Theirs").unwrap();
+
+    ours.fetch_head(&theirs_dir.path().to_string_lossy())
+        .unwrap();
+
+    assert_eq!(
+        ours.show_file("FETCH_HEAD", "shared.txt").unwrap(),
+        Some("theirs".to_string())
+    );
+    assert_eq!(ours.show_file("FETCH_HEAD", "ours.txt").unwrap(), None);
+
+    let conflicts = ours.merge_unrelated("FETCH_HEAD", "Merge").unwrap();
+
+    assert_eq!(conflicts, vec![std::path::PathBuf::from("shared.txt")]);
+
+    ours.resolve_conflict("shared.txt", MergeSide::Theirs)
+        .unwrap();
+    ours.commit_merge().unwrap();
+
+    assert!(ours.conflicts().unwrap().is_empty());
+    assert!(ours_dir.child("ours.txt").exists());
+    assert!(ours_dir.child("theirs.txt").exists());
+    ours_dir.child("shared.txt").assert("theirs");
+
+    // Both histories should be kept.
+    let log = ours.arbitrary_command(&["log", "--format=%s"]).unwrap();
+    let log = String::from_utf8_lossy(&log.stdout);
+
+    assert!(log.contains("Ours"));
+    assert!(log.contains("Theirs"));
+}
+
+/// Test diffing two files outside of a repository.
+#[test]
+fn test_diff_paths() {
+    let tmp_dir = TempDir::new().unwrap();
+    let old = tmp_dir.child("old");
+    let new = tmp_dir.child("new");
+
+    old.write_str("same\nold\n").unwrap();
+    new.write_str("same\nnew\n").unwrap();
+
+    let diff = crate::git::diff_paths(old.path(), new.path()).unwrap();
+
+    assert!(diff.contains("-old"));
+    assert!(diff.contains("+new"));
+    assert!(crate::git::diff_paths(old.path(), old.path())
+        .unwrap()
+        .is_empty());
+}
+
+/// Test finding the largest unpushed file, using a local bare repository as the remote.
+#[test]
+fn test_largest_unpushed_file() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    // No commits, so nothing to push.
+    assert_eq!(repo.largest_unpushed_file().unwrap(), 0);
+
+    repo_dir.child("small").write_str("small").unwrap();
+    repo_dir
+        .child("large")
+        .write_str(&"large".repeat(100))
+        .unwrap();
+    repo.commit("First commit").unwrap();
+
+    // No remote branch, so everything is unpushed.
+    assert_eq!(repo.largest_unpushed_file().unwrap(), 500);
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo.push().unwrap();
+
+    assert_eq!(repo.largest_unpushed_file().unwrap(), 0);
+
+    repo_dir
+        .child("medium")
+        .write_str(&"medium".repeat(10))
+        .unwrap();
+    repo.commit("Second commit").unwrap();
+
+    assert_eq!(repo.largest_unpushed_file().unwrap(), 60);
+}
+
+/// Test counting the commits that are ahead of and behind the remote, using a local bare repository as the remote.
+#[test]
+fn test_ahead_behind() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    assert_eq!(repo.ahead_behind().unwrap(), (0, 0));
+
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("First commit").unwrap();
+
+    // No remote branch, so everything is ahead.
+    assert_eq!(repo.ahead_behind().unwrap(), (1, 0));
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo.push().unwrap();
+    assert_eq!(repo.ahead_behind().unwrap(), (0, 0));
+
+    let other_dir = tmp_dir.child("other");
+    let mut other =
+        Repository::clone(other_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+    other_dir.child("other").write_str("other").unwrap();
+    other.commit("Other commit").unwrap();
+    other.push().unwrap();
+
+    repo_dir.child("file").write_str("second").unwrap();
+    repo.commit("Second commit").unwrap();
+    repo.fetch().unwrap();
+    assert_eq!(repo.ahead_behind().unwrap(), (1, 1));
+    assert_eq!(repo.unpushed_commits().unwrap(), 1);
+}
+
+/// Test that pulling reconciles diverged commits according to the pull strategy, and that uncommitted changes can
+/// be stashed around it.
+#[test]
+fn test_pull_strategy() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+    let other_dir = tmp_dir.child("other");
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("First commit").unwrap();
+    repo.push().unwrap();
+
+    let mut other =
+        Repository::clone(other_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+    other_dir.child("other").write_str("other").unwrap();
+    other.commit("Other commit").unwrap();
+    other.push().unwrap();
+
+    repo_dir.child("local").write_str("local").unwrap();
+    repo.commit("Local commit").unwrap();
+
+    // The branches diverged, so they can't be fast-forwarded.
+    repo.set_pull_strategy(PullStrategy::FfOnly);
+    assert!(repo.pull().is_err());
+
+    repo_dir.child("file").write_str("uncommitted").unwrap();
+    assert_eq!(repo.stash_push().unwrap(), 1);
+    assert_eq!(repo.uncommitted_changes().unwrap(), 0);
+
+    repo.set_pull_strategy(PullStrategy::Rebase);
+    repo.pull().unwrap();
+    assert!(repo.stash_pop().unwrap().is_empty());
+
+    let merges = repo
+        .arbitrary_command(&["rev-list", "--merges", "--count", "HEAD"])
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&merges.stdout).trim(), "0");
+    assert_eq!(repo.history(None).unwrap().len(), 3);
+    assert_eq!(repo.history(None).unwrap()[0].summary, "Local commit");
+    repo_dir.child("file").assert("uncommitted");
+}
+
+/// Test that commits (and merges when pulling) are signed, and that pulled commits without a signature are found.
+#[test]
+fn test_signing() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+    let other_dir = tmp_dir.child("other");
+    let key = tmp_dir.child("key");
+
+    std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(key.path())
+        .status()
+        .unwrap();
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo.set_signing(Some(Signing {
+        format: Some(SigningFormat::Ssh),
+        key: Some(format!("{}.pub", key.path().display())),
+    }));
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("Signed commit").unwrap();
+    repo.push().unwrap();
+
+    let is_signed = |repo: &mut Repository| {
+        let raw = repo
+            .arbitrary_command(&["log", "-1", "--pretty=raw"])
+            .unwrap();
+        String::from_utf8_lossy(&raw.stdout).contains("gpgsig")
+    };
+    assert!(is_signed(&mut repo));
+
+    let mut other =
+        Repository::clone(other_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+    other_dir.child("other").write_str("other").unwrap();
+    other.commit("Unsigned commit").unwrap();
+    other.push().unwrap();
+
+    // The merge is signed, and only the other machine's commit isn't.
+    repo_dir.child("local").write_str("local").unwrap();
+    repo.commit("Local commit").unwrap();
+    let before = repo.head();
+    repo.pull().unwrap();
+
+    assert!(is_signed(&mut repo));
+    let unsigned = repo.unsigned_commits(before.as_deref()).unwrap();
+    assert_eq!(unsigned.len(), 1);
+    assert_eq!(unsigned[0].summary, "Unsigned commit");
+    assert_eq!(repo.unsigned_commits(None).unwrap().len(), 1);
+
+    // Pulling the signed commits doesn't find any unsigned ones.
+    repo.push().unwrap();
+    let before = other.head();
+    other.pull().unwrap();
+
+    assert!(other
+        .unsigned_commits(before.as_deref())
+        .unwrap()
+        .is_empty());
+}
+
+/// Test that the commits made besides `commit` and `pull` (reverts, merges of unrelated histories, and merges whose
+/// conflicts were resolved) are signed too.
+#[test]
+fn test_signing_other_commits() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let other_dir = tmp_dir.child("other");
+    let key = tmp_dir.child("key");
+
+    std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(key.path())
+        .status()
+        .unwrap();
+
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo.set_signing(Some(Signing {
+        format: Some(SigningFormat::Ssh),
+        key: Some(format!("{}.pub", key.path().display())),
+    }));
+    let is_signed = |repo: &mut Repository| {
+        let raw = repo
+            .arbitrary_command(&["log", "-1", "--pretty=raw"])
+            .unwrap();
+        String::from_utf8_lossy(&raw.stdout).contains("gpgsig")
+    };
+
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("First commit").unwrap();
+    repo_dir.child("file").write_str("second").unwrap();
+    repo.commit("Second commit").unwrap();
+
+    repo.revert(&["HEAD"]).unwrap();
+    assert!(is_signed(&mut repo));
+    repo_dir.child("file").assert("first");
+
+    // Another history, which conflicts with this one.
+    let mut other = Repository::init(other_dir.path(), None).unwrap();
+    other_dir.child("unrelated").write_str("unrelated").unwrap();
+    other.commit("Unrelated commit").unwrap();
+    repo.arbitrary_command(&[
+        "fetch",
+        other_dir.path().to_str().unwrap(),
+        "HEAD:unrelated",
+    ])
+    .unwrap();
+
+    assert!(repo
+        .merge_unrelated("unrelated", "Merge unrelated")
+        .unwrap()
+        .is_empty());
+    assert!(is_signed(&mut repo));
+
+    other_dir.child("file").write_str("other").unwrap();
+    other.commit("Conflicting commit").unwrap();
+    repo.arbitrary_command(&[
+        "fetch",
+        other_dir.path().to_str().unwrap(),
+        "HEAD:conflicting",
+    ])
+    .unwrap();
+
+    let conflicts = repo
+        .merge_unrelated("conflicting", "Merge conflicting")
+        .unwrap();
+    assert_eq!(conflicts, [PathBuf::from("file")]);
+    repo.resolve_conflict("file", MergeSide::Ours).unwrap();
+    repo.commit_merge().unwrap();
+    assert!(is_signed(&mut repo));
+}
+
+/// Test that stashed changes conflicting with pulled ones are reported, and kept in the stash.
+#[test]
+fn test_stash_conflict() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+    let other_dir = tmp_dir.child("other");
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("First commit").unwrap();
+    repo.push().unwrap();
+
+    let mut other =
+        Repository::clone(other_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+    other_dir.child("file").write_str("other").unwrap();
+    other.commit("Other commit").unwrap();
+    other.push().unwrap();
+
+    // Nothing to stash.
+    assert_eq!(repo.stash_push().unwrap(), 0);
+
+    repo_dir.child("file").write_str("uncommitted").unwrap();
+    assert_eq!(repo.stash_push().unwrap(), 1);
+    repo.pull().unwrap();
+
+    assert_eq!(repo.stash_pop().unwrap(), vec![PathBuf::from("file")]);
+
+    let stashes = repo.arbitrary_command(&["stash", "list"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&stashes.stdout).lines().count(), 1);
+}
+
+/// Test that submodules are cloned along with the repository, and checked out when a pull adds them.
+#[test]
+fn test_submodules() {
+    test_util::allow_local_submodules();
+
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let remote_dir = tmp_dir.child("remote");
+    let module_dir = tmp_dir.child("module");
+    let other_dir = tmp_dir.child("other");
+    let clone_dir = tmp_dir.child("clone");
+
+    crate::git::run_arbitrary_git_command(
+        tmp_dir.path(),
+        &["init", "--bare", "--initial-branch=main", "remote"],
+        None,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    let mut module = Repository::init(module_dir.path(), None).unwrap();
+    module_dir.child("init.lua").write_str("shared").unwrap();
+    module.commit("Shared commit").unwrap();
+
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    repo.set_remote(remote_dir.path().to_string_lossy())
+        .unwrap();
+    repo_dir.child("file").write_str("first").unwrap();
+    repo.commit("First commit").unwrap();
+    repo.push().unwrap();
+    assert!(!repo.has_submodules());
+
+    let mut other =
+        Repository::clone(other_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+
+    repo.add_submodule(module_dir.path(), ".config/nvim")
+        .unwrap();
+    repo.commit("Add module").unwrap();
+    repo.push().unwrap();
+    assert!(repo.has_submodules());
+
+    Repository::clone(clone_dir.path(), remote_dir.path().to_string_lossy(), false).unwrap();
+    clone_dir.child(".config/nvim/init.lua").assert("shared");
+
+    other.pull().unwrap();
+    other_dir.child(".config/nvim/init.lua").assert("shared");
+}
+
+/// Test that git commands don't get configuration of the tests' own, e.g. allowing submodules from local paths.
+#[test]
+fn test_git_command_config() {
+    assert!(git_command()
+        .get_envs()
+        .all(|(key, _)| !key.to_string_lossy().starts_with("GIT_CONFIG")));
+}
+
+/// Test that low bandwidth clones are shallow.
+#[test]
+fn test_clone_low_bandwidth() {
+    let tmp_dir = TempDir::new().unwrap();
+    let origin_dir = tmp_dir.child("origin");
+    let clone_dir = tmp_dir.child("clone");
+
+    let mut origin = Repository::init(origin_dir.path(), None).unwrap();
+
+    for i in 0..3 {
+        origin_dir.child("file").write_str(&i.to_string()).unwrap();
+        origin.commit(&format!("Commit {}", i)).unwrap();
+    }
+
+    // Local clones ignore `--depth` unless they go through `file://`.
+    let url = format!("file://{}", origin_dir.path().display());
+    let mut clone = Repository::clone(clone_dir.path(), url, true).unwrap();
+
+    assert!(clone.low_bandwidth());
+
+    let count = clone
+        .arbitrary_command(&["rev-list", "--count", "HEAD"])
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&count.stdout).trim(), "1");
+    clone_dir.child("file").assert("2");
+
+    // Pulling new commits should still work on the shallow clone.
+    origin_dir.child("file").write_str("3").unwrap();
+    origin.commit("Commit 3").unwrap();
+    clone.pull().unwrap();
+
+    clone_dir.child("file").assert("3");
+}
+
+/// Test that fetching hosts' branches on a shallow clone doesn't download the history it left out, and that pulling
+/// still works afterwards.
+#[test]
+fn test_fetch_hosts_shallow() {
+    let tmp_dir = TempDir::new().unwrap();
+    let origin_dir = tmp_dir.child("origin");
+    let clone_dir = tmp_dir.child("clone");
+
+    let mut origin = Repository::init(origin_dir.path(), None).unwrap();
+
+    for i in 0..5 {
+        origin_dir.child("file").write_str(&i.to_string()).unwrap();
+        origin.commit(&format!("Commit {}", i)).unwrap();
+    }
+
+    // Local clones ignore `--depth` unless they go through `file://`.
+    let url = format!("file://{}", origin_dir.path().display());
+    let mut clone = Repository::clone(clone_dir.path(), url, true).unwrap();
+
+    // A host's branch forked off the first commit, so fetching all of it would download the whole history.
+    origin
+        .arbitrary_command(&["branch", &format!("{}laptop", HOST_BRANCH_PREFIX), "HEAD~4"])
+        .unwrap();
+    origin
+        .arbitrary_command(&[
+            "checkout",
+            "--quiet",
+            &format!("{}laptop", HOST_BRANCH_PREFIX),
+        ])
+        .unwrap();
+    origin_dir.child("host").write_str("laptop").unwrap();
+    origin.commit("Host commit").unwrap();
+    origin
+        .arbitrary_command(&["checkout", "--quiet", clone.branch()])
+        .unwrap();
+
+    clone.fetch_hosts().unwrap();
+
+    assert!(clone.is_shallow());
+
+    let count = clone
+        .arbitrary_command(&["rev-list", "--count", "--all"])
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&count.stdout).trim(), "2");
+
+    // Pulling new commits (on top of a local one) should still work after that.
+    origin_dir.child("file").write_str("5").unwrap();
+    origin.commit("Commit 5").unwrap();
+    clone_dir.child("other").write_str("local").unwrap();
+    clone.commit("Local commit").unwrap();
+    clone.pull().unwrap();
+
+    clone_dir.child("file").assert("5");
+    clone_dir.child("other").assert("local");
+}
+
+/// Test that commits get increasing sequence number trailers, continuing from the highest one in the history.
+#[test]
+fn test_sequence_trailer() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    // Commits without the trailer don't count.
+    repo_dir.child("a").write_str("a").unwrap();
+    repo.commit("Untracked sequence").unwrap();
+
+    repo.set_sequence_trailer(true);
+
+    for content in ["b", "c"] {
+        repo_dir.child("a").write_str(content).unwrap();
+        repo.commit("Tracked sequence").unwrap();
+    }
+
+    let output = repo
+        .arbitrary_command(&[
+            "log",
+            "--format=%(trailers:key=Dotbak-Sequence,valueonly)%x00",
+        ])
+        .unwrap();
+    let sequence = String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .map(|trailer| trailer.trim().to_string())
+        .filter(|trailer| !trailer.is_empty())
+        .collect::<Vec<_>>();
+
+    assert_eq!(sequence, vec!["2", "1"]);
+}
+
+/// Test getting the time of the newest commit, even if it is in the future.
+#[test]
+fn test_newest_commit_time() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    assert_eq!(repo.newest_commit_time().unwrap(), None);
+
+    repo_dir.child("a").write_str("a").unwrap();
+    repo.commit("Now").unwrap();
+    assert!(repo.newest_commit_time().unwrap().is_some());
+
+    // Make a commit from the "future", as a machine with a wrong clock would.
+    let status = std::process::Command::new("git")
+        .args(["commit", "--allow-empty", "-m", "Future"])
+        .env("GIT_COMMITTER_DATE", "@4102444800 +0000") // 2100-01-01
+        .current_dir(repo_dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert_eq!(repo.newest_commit_time().unwrap(), Some(4102444800));
+}
+
+/// Test checking if a path differs between revisions and the working tree.
+#[test]
+fn test_path_differs() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    // There are no revisions yet.
+    assert!(!repo.path_differs("HEAD", None, "a"));
+
+    repo_dir.child("a").write_str("a").unwrap();
+    repo_dir.child("b").write_str("b").unwrap();
+    repo.commit("First").unwrap();
+    repo_dir.child("a").write_str("changed").unwrap();
+
+    assert!(repo.path_differs("HEAD", None, "a"));
+    assert!(!repo.path_differs("HEAD", None, "b"));
+
+    repo.commit("Second").unwrap();
+
+    assert!(repo.path_differs("HEAD~1", Some("HEAD"), "a"));
+    assert!(!repo.path_differs("HEAD~1", Some("HEAD"), "b"));
+    assert!(!repo.path_differs("HEAD", Some("origin/main"), "a"));
+}
+
+/// Test that committing without any changes succeeds without creating a commit.
+#[test]
+fn test_commit_nothing_staged() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    // There is nothing to commit yet, not even a first commit.
+    repo.commit("Empty").unwrap();
+    assert!(repo.history(None).unwrap().is_empty());
+
+    repo_dir.child("a").write_str("a").unwrap();
+    repo.commit("First").unwrap();
+    repo.commit("Nothing").unwrap();
+
+    let history = repo.history(None).unwrap();
+
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].summary, "First");
+}
+
+/// Test that setting the remote works whether or not it exists already.
+#[test]
+fn test_set_remote_existing() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(tmp_dir.child("repo").path(), None).unwrap();
+
+    repo.set_remote("/first").unwrap();
+    repo.set_remote("/second").unwrap();
+
+    let output = repo
+        .arbitrary_command(&["remote", "get-url", "origin"])
+        .unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "/second");
+}
+
+/// Test that paths which aren't valid UTF-8 are kept byte-for-byte.
+#[test]
+fn test_non_utf8_paths() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+    let name = Path::new(OsStr::from_bytes(b"caf\xe9.txt"));
+
+    repo_dir.child(name).write_str("latin-1").unwrap();
+    repo.commit("Add file").unwrap();
+
+    assert_eq!(repo.tracked_files().unwrap(), vec![name.to_path_buf()]);
+    assert_eq!(
+        repo.show_file("HEAD", name).unwrap().as_deref(),
+        Some("latin-1")
+    );
+    assert_eq!(repo.history(Some(name)).unwrap().len(), 1);
+
+    repo_dir.child(name).write_str("changed").unwrap();
+    assert!(repo.path_differs("HEAD", None, name));
+}
+
+/// Test listing the files renamed between two revisions.
+#[test]
+fn test_renames() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir
+        .child(".zshrc")
+        .write_str("export EDITOR=nvim")
+        .unwrap();
+    repo_dir.child(".vimrc").write_str("set number").unwrap();
+    repo.commit("First").unwrap();
+    let before = repo.head().unwrap();
+
+    repo.arbitrary_command(&["mv", ".zshrc", ".zshenv"])
+        .unwrap();
+    repo_dir.child(".vimrc").write_str("set nonumber").unwrap();
+    repo.commit("Second").unwrap();
+
+    assert_eq!(
+        repo.renames(&before, "HEAD").unwrap(),
+        vec![(
+            std::path::PathBuf::from(".zshrc"),
+            std::path::PathBuf::from(".zshenv")
+        )]
+    );
+    assert!(repo.renames("HEAD", "HEAD").unwrap().is_empty());
+}
+
+/// Test marking files as binary in `.gitattributes`.
+#[test]
+fn test_mark_binary() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir
+        .child(".gitattributes")
+        .write_str("*.lua text")
+        .unwrap();
+
+    assert!(repo
+        .mark_binary(&["wallpaper.png", "my fonts/a*.ttf"])
+        .unwrap());
+    assert!(!repo.mark_binary(&["wallpaper.png"]).unwrap());
+
+    repo_dir
+        .child(".gitattributes")
+        .assert("*.lua text\n/wallpaper.png binary\n/my[[:space:]]fonts/a\\*.ttf binary\n");
+
+    let output = repo
+        .arbitrary_command(&[
+            "check-attr",
+            "diff",
+            "--",
+            "my fonts/a*.ttf",
+            "my fonts/ab.ttf",
+        ])
+        .unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "my fonts/a*.ttf: diff: unset\nmy fonts/ab.ttf: diff: unspecified\n"
+    );
+}
+
+/// Test keeping the managed part of `.gitignore` in sync, and removing ignored files from the repository.
+#[test]
+fn test_set_ignored() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir.child(".gitignore").write_str("*.log\n").unwrap();
+    repo_dir.child("nvim/init.lua").write_str("init").unwrap();
+    repo_dir
+        .child("nvim/plugin/packer_compiled.lua")
+        .write_str("packer")
+        .unwrap();
+    repo.commit("Add nvim").unwrap();
+
+    assert!(repo.set_ignored(&["nvim/plugin/", "*.swp"]).unwrap());
+    assert!(!repo.set_ignored(&["nvim/plugin/", "*.swp"]).unwrap());
+    repo_dir.child(".gitignore").assert(format!(
+        "*.log\n\n{}\nnvim/plugin/\n*.swp\n{}\n",
+        IGNORE_BEGIN, IGNORE_END
+    ));
+
+    assert_eq!(
+        repo.untrack_ignored().unwrap(),
+        vec![PathBuf::from("nvim/plugin/packer_compiled.lua")]
+    );
+    repo.commit("Ignore plugins").unwrap();
+    repo_dir
+        .child("nvim/plugin/packer_compiled.lua")
+        .assert("packer");
+    assert_eq!(
+        repo.tracked_files().unwrap(),
+        vec![PathBuf::from(".gitignore"), PathBuf::from("nvim/init.lua")]
+    );
+
+    assert!(repo.set_ignored::<&str>(&[]).unwrap());
+    repo_dir.child(".gitignore").assert("*.log\n");
+}
+
+/// Test keeping the git-lfs part of `.gitattributes` in sync, next to files marked as binary.
+#[test]
+fn test_set_lfs_tracked() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    assert!(!repo.set_lfs_tracked::<&str>(&[]).unwrap());
+    assert!(!repo_dir.child(".gitattributes").exists());
+
+    assert!(repo.set_lfs_tracked(&["*.ttf", "wallpapers/**"]).unwrap());
+    assert!(repo.mark_binary(&["image.png"]).unwrap());
+    assert!(!repo.set_lfs_tracked(&["*.ttf", "wallpapers/**"]).unwrap());
+    repo_dir.child(".gitattributes").assert(format!(
+        "{0}\n*.ttf {2}\nwallpapers/** {2}\n{1}\n/image.png binary\n",
+        LFS_BEGIN, LFS_END, LFS_ATTRIBUTES
+    ));
+
+    assert!(repo.set_lfs_tracked::<&str>(&[]).unwrap());
+    repo_dir
+        .child(".gitattributes")
+        .assert("/image.png binary\n");
+}
+
+/// Test finding the hosts from the trailers of their commits, and archiving them.
+#[test]
+fn test_hosts() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    // Commits without the trailer don't count.
+    repo_dir.child("a").write_str("a").unwrap();
+    repo.commit("Unknown host").unwrap();
+    assert!(repo.hosts().unwrap().is_empty());
+
+    for (host, content) in [("laptop", "b"), ("desktop", "c"), ("laptop", "d")] {
+        repo.set_host(Some(host.to_string()));
+        repo_dir.child("a").write_str(content).unwrap();
+        repo.commit("Sync").unwrap();
+    }
+
+    let hosts = repo.hosts().unwrap();
+    let head = repo.arbitrary_command(&["rev-parse", "HEAD"]).unwrap();
+
+    assert_eq!(
+        hosts
+            .iter()
+            .map(|host| host.name.as_str())
+            .sorted()
+            .collect_vec(),
+        vec!["desktop", "laptop"]
+    );
+    assert!(hosts.iter().all(|host| !host.retired));
+    assert_eq!(
+        hosts
+            .iter()
+            .find(|host| host.name == "laptop")
+            .unwrap()
+            .commit,
+        String::from_utf8_lossy(&head.stdout).trim()
+    );
+
+    // Hosts with a branch get it archived, the others get an archive branch at their newest commit.
+    repo.arbitrary_command(&["branch", "hosts/desktop"])
+        .unwrap();
+
+    for host in &hosts {
+        let archive = repo.archive_host(host).unwrap();
+        assert_eq!(archive, format!("archive/hosts/{}", host.name));
+    }
+
+    assert!(repo
+        .arbitrary_command(&[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            "refs/heads/hosts/desktop"
+        ])
+        .is_err());
+    assert!(repo.hosts().unwrap().iter().all(|host| host.retired));
+}
+
+/// Tests that authentication failures are detected in git's output, and that credentials are answered to git.
+#[test]
+fn test_auth() {
+    assert_eq!(
+        AuthFailure::detect(
+            "fatal: could not read Username for 'https://example.com': terminal prompts disabled"
+        ),
+        Some(AuthFailure::Https)
+    );
+    assert_eq!(
+        AuthFailure::detect("git@example.com: Permission denied (publickey).\nfatal: Could not read from remote repository."),
+        Some(AuthFailure::Ssh)
+    );
+    assert_eq!(
+        AuthFailure::detect("fatal: repository 'https://example.com/dotfiles.git' not found"),
+        None
+    );
+
+    let credentials = Credentials::new(None, SecretString::from("hunter2".to_string()));
+    let mut command = git_command();
+    credentials.apply(&mut command);
+
+    let mut child = command
+        .args(["credential", "fill"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"protocol=https\nhost=example.com\n\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.contains("username=x-access-token\n"));
+    assert!(output.contains("password=hunter2\n"));
+}
+
+/// Test that the log lists the files each commit touched, follows renames, and is limited.
+#[test]
+fn test_log() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir.child(".zshrc").write_str("zsh").unwrap();
+    repo_dir.child(".vimrc").write_str("vim").unwrap();
+    repo.commit("Add files").unwrap();
+    repo.arbitrary_command(&["mv", ".zshrc", ".zshenv"])
+        .unwrap();
+    repo.commit("Rename").unwrap();
+    repo_dir.child(".vimrc").write_str("vim!").unwrap();
+    repo.commit("Change").unwrap();
+
+    let log = repo.log(None, None).unwrap();
+
+    assert_eq!(
+        log.iter()
+            .map(|entry| (entry.commit.summary.as_str(), entry.files.clone()))
+            .collect_vec(),
+        vec![
+            ("Change", vec![PathBuf::from(".vimrc")]),
+            // Renames are listed under their new name.
+            ("Rename", vec![PathBuf::from(".zshenv")]),
+            (
+                "Add files",
+                vec![PathBuf::from(".vimrc"), PathBuf::from(".zshrc")]
+            ),
+        ]
+    );
+
+    let log = repo.log(Some(Path::new(".zshenv")), None).unwrap();
+
+    assert_eq!(
+        log.iter()
+            .map(|entry| entry.commit.summary.as_str())
+            .collect_vec(),
+        vec!["Rename", "Add files"]
+    );
+    assert_eq!(repo.log(None, Some(1)).unwrap().len(), 1);
+}
+
+/// Test that only the given files (and the repository's own files) are checked out in a sparse checkout, also when
+/// cloning, and that everything is checked out again once it's turned off.
+#[test]
+fn test_sparse_checkout() {
+    let tmp_dir = TempDir::new().unwrap();
+    let (repo_dir, clone_dir) = (tmp_dir.child("repo"), tmp_dir.child("clone"));
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    repo_dir.child(".gitattributes").write_str("").unwrap();
+    repo_dir.child(".zshrc").write_str("zsh").unwrap();
+    repo_dir
+        .child(".config/a*b/config")
+        .write_str("ab")
+        .unwrap();
+    repo_dir.child(".config/ab/config").write_str("ab").unwrap();
+    repo_dir.child("fonts/large.ttf").write_str("font").unwrap();
+    repo.commit("Add files").unwrap();
+
+    assert!(!repo.is_sparse());
+
+    repo.sparse_checkout(Some(&[".zshrc", ".config/a*b"]))
+        .unwrap();
+
+    assert!(repo.is_sparse());
+    assert!(repo_dir.child(".gitattributes").exists());
+    assert!(repo_dir.child(".zshrc").exists());
+    assert!(repo_dir.child(".config/a*b/config").exists());
+    assert!(!repo_dir.child(".config/ab").exists());
+    assert!(!repo_dir.child("fonts").exists());
+
+    repo.sparse_checkout(None::<&[&str]>).unwrap();
+
+    assert!(!repo.is_sparse());
+    assert!(repo_dir.child("fonts/large.ttf").exists());
+
+    // A sparse clone only has the files at the top until the files to check out are set.
+    let mut clone = Repository::clone_with_credentials(
+        clone_dir.path(),
+        repo_dir.path().display(),
+        false,
+        &CloneOptions {
+            sparse: true,
+            ..Default::default()
+        },
+        None,
+        BTreeMap::new(),
+        Backend::Git,
+    )
+    .unwrap();
+
+    assert!(clone_dir.child(".zshrc").exists());
+    assert!(!clone_dir.child("fonts").exists());
+
+    clone.sparse_checkout(Some(&["fonts"])).unwrap();
+
+    assert!(!clone_dir.child(".zshrc").exists());
+    assert!(clone_dir.child("fonts/large.ttf").exists());
+}
+
+/// Test that the identity to make commits as is written to the repository's git config.
+#[test]
+fn test_identity() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(tmp_dir.path(), None).unwrap();
+
+    repo.set_identity(Some("Dot Bak"), Some("dot@bak.test"))
+        .unwrap();
+    // Values that aren't given are left alone.
+    repo.set_identity(None, Some("bak@dot.test")).unwrap();
+
+    let name = repo
+        .arbitrary_command(&["config", "--local", "user.name"])
+        .unwrap();
+    let email = repo
+        .arbitrary_command(&["config", "--local", "user.email"])
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&name.stdout).trim(), "Dot Bak");
+    assert_eq!(
+        String::from_utf8_lossy(&email.stdout).trim(),
+        "bak@dot.test"
+    );
+
+    tmp_dir.child("a.txt").write_str("a").unwrap();
+    repo.commit("a").unwrap();
+    let author = repo
+        .arbitrary_command(&["log", "-1", "--format=%an <%ae>"])
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&author.stdout).trim(),
+        "Dot Bak <bak@dot.test>"
+    );
+}
+
+/// Test that shallow and filtered clones leave out the older commits and the file contents, and that unshallowing
+/// fetches them.
+#[test]
+fn test_unshallow() {
+    let tmp_dir = TempDir::new().unwrap();
+    let repo_dir = tmp_dir.child("repo");
+    let clone_dir = tmp_dir.child("clone");
+    let mut repo = Repository::init(repo_dir.path(), None).unwrap();
+
+    for content in ["first", "second", "third"] {
+        repo_dir.child("file").write_str(content).unwrap();
+        repo.commit(content).unwrap();
+    }
+
+    // Local clones ignore depths and filters, unless they go through a `file://` URL.
+    repo.arbitrary_command(&["config", "uploadpack.allowFilter", "true"])
+        .unwrap();
+    let url = format!("file://{}", repo_dir.path().display());
+
+    let mut clone = Repository::clone_with_credentials(
+        clone_dir.path(),
+        url,
+        false,
+        &CloneOptions {
+            depth: Some(1),
+            filter: Some("blob:none".to_string()),
+            ..Default::default()
+        },
+        None,
+        BTreeMap::new(),
+        Backend::Git,
+    )
+    .unwrap();
+
+    clone_dir.child("file").assert("third");
+    assert!(clone.is_shallow());
+    assert!(clone.is_partial());
+    assert_eq!(clone.log(None, None).unwrap().len(), 1);
+
+    assert!(clone.unshallow().unwrap().is_some());
+    assert!(!clone.is_shallow());
+    assert!(!clone.is_partial());
+    assert_eq!(clone.log(None, None).unwrap().len(), 3);
+
+    // All the file contents are there, even without the remote.
+    std::fs::remove_dir_all(repo_dir.path()).unwrap();
+    let first = clone.arbitrary_command(&["show", "HEAD~2:file"]).unwrap();
+    assert_eq!(String::from_utf8_lossy(&first.stdout), "first");
+
+    assert!(clone.unshallow().unwrap().is_none());
+}
+
+/// Test that commands passed through to git exit like git did, without that being an error.
+#[test]
+fn test_passthrough() {
+    let tmp_dir = TempDir::new().unwrap();
+    let mut repo = Repository::init(tmp_dir.path(), None).unwrap();
+
+    let status = repo.passthrough(&["diff", "--quiet"]).unwrap();
+    assert!(status.success());
+
+    let status = repo
+        .passthrough(&["rev-parse", "--verify", "--quiet", "nope"])
+        .unwrap();
+    assert_eq!(status.code(), Some(1));
+}
+
+/// Test that the progress git reports is passed on line by line while it's read, and that the lines git overwrites
+/// are left out of the output.
+#[test]
+fn test_stream_progress() {
+    let lines = Arc::new(Mutex::new(vec![]));
+    let reported = lines.clone();
+    let progress =
+        ProgressHandler::new(move |line| reported.lock().unwrap().push(line.to_string()));
+
+    let stderr = stream_in_background(
+        Some(&b"Counting: 50% (1/2)\rCounting: 100% (2/2), done.\nremote: hi\r\n\nlast"[..]),
+        progress,
+    )
+    .join()
+    .unwrap();
+
+    assert_eq!(
+        *lines.lock().unwrap(),
+        [
+            "Counting: 50% (1/2)",
+            "Counting: 100% (2/2), done.",
+            "remote: hi",
+            "last"
+        ]
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&stderr),
+        "Counting: 100% (2/2), done.\nremote: hi\n\nlast"
+    );
+}
+
+/// Test that clones, pushes and pulls report their progress.
+#[test]
+fn test_progress() {
+    let tmp_dir = TempDir::new().unwrap();
+    let remote_dir = tmp_dir.child("remote");
+    let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+    let reported = lines.clone();
+    let progress =
+        ProgressHandler::new(move |line| reported.lock().unwrap().push(line.to_string()));
+
+    let mut remote = Repository::init(remote_dir.path(), None).unwrap();
+    remote_dir.child("file").write_str("file").unwrap();
+    remote.commit("First commit").unwrap();
+    remote
+        .arbitrary_command(&["config", "receive.denyCurrentBranch", "updateInstead"])
+        .unwrap();
+
+    let mut repo = Repository::clone_with_credentials(
+        tmp_dir.child("repo").path(),
+        remote_dir.path().display(),
+        false,
+        &CloneOptions {
+            progress: Some(progress),
+            ..Default::default()
+        },
+        None,
+        BTreeMap::new(),
+        Backend::Git,
+    )
+    .unwrap();
+    assert!(lines
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|line| line.starts_with("Cloning into")));
+
+    tmp_dir.child("repo/file").write_str("changed").unwrap();
+    repo.commit("Second commit").unwrap();
+    lines.lock().unwrap().clear();
+    let output = repo.push().unwrap();
+
+    assert!(lines
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|line| line.starts_with("Writing objects: 100%")));
+    assert!(!output.stderr.contains(&b'\r'));
+}
+
+/// Test that credentials in URLs are kept out of the arguments shown in errors and logs.
+#[test]
+fn test_display_args() {
+    assert_eq!(
+        display_args(&[
+            "remote",
+            "add",
+            "backup",
+            "https://ghp_token@github.com/user/dotfiles"
+        ]),
+        [
+            "remote",
+            "add",
+            "backup",
+            "https://<redacted>@github.com/user/dotfiles"
+        ]
+    );
+    assert_eq!(display_args(&["-c", "a=b", "push"]), ["-c", "a=b", "push"]);
+}
@@ -0,0 +1,103 @@
+mod tests;
+
+use crate::errors::{io::IoError, Result};
+use std::{
+    path::Path,
+    process::{Command, Output},
+};
+
+/// The environment variable telling hook commands which hook they're run for, e.g. `post_pull`.
+pub const HOOK_VAR: &str = "DOTBAK_HOOK";
+
+/// The environment variable telling hook commands where the repository is.
+pub const REPO_VAR: &str = "DOTBAK_REPO";
+
+/// The points around `dotbak`'s operations where hooks are run. See `HooksConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    /// Before files are added with `dotbak add`, before anything is moved into the repository. If it fails, nothing
+    /// is added.
+    PreAdd,
+
+    /// After files were added with `dotbak add` and committed.
+    PostAdd,
+
+    /// Before a sync, before anything is committed or pulled. If it fails, the sync is stopped.
+    PreSync,
+
+    /// After a sync, once the files are linked again and the commits are pushed (or queued).
+    PostSync,
+
+    /// Before pulling, on its own or as part of a sync. If it fails, nothing is pulled.
+    PrePull,
+
+    /// After a pull succeeded, once the renames it brought in are followed.
+    PostPull,
+
+    /// Before pushing, on its own, as part of a sync, or right after committing with `git.auto_push`. If it fails,
+    /// nothing is pushed.
+    PrePush,
+
+    /// After a push succeeded. Pushes that are deferred or queued don't run it.
+    PostPush,
+
+    /// Once a new machine is set up with `dotbak bootstrap`, after its host branch (if any) is checked out.
+    Bootstrap,
+}
+
+impl Hook {
+    /// The name of the hook, as in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::PreAdd => "pre_add",
+            Hook::PostAdd => "post_add",
+            Hook::PreSync => "pre_sync",
+            Hook::PostSync => "post_sync",
+            Hook::PrePull => "pre_pull",
+            Hook::PostPull => "post_pull",
+            Hook::PrePush => "pre_push",
+            Hook::PostPush => "post_push",
+            Hook::Bootstrap => "bootstrap",
+        }
+    }
+}
+
+/// Runs the `commands` of `hook` one after another with `sh -c` in the folder `dir`, and returns their outputs. The
+/// commands get the hook's name in `DOTBAK_HOOK` and the repository folder `repo_dir` in `DOTBAK_REPO`. Stops at the
+/// first command that fails, and returns an error with its output.
+pub fn run<P1, P2>(hook: Hook, commands: &[String], dir: P1, repo_dir: P2) -> Result<Vec<Output>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut outputs = vec![];
+
+    for command in commands {
+        let args = vec!["-c".to_string(), command.clone()];
+        let output = Command::new("sh")
+            .args(&args)
+            .current_dir(dir.as_ref())
+            .env(HOOK_VAR, hook.name())
+            .env(REPO_VAR, repo_dir.as_ref())
+            .output()
+            .map_err(|err| IoError::CommandIO {
+                source: err,
+                command: "sh".to_string(),
+                args: args.clone(),
+            })?;
+
+        if !output.status.success() {
+            return Err(IoError::CommandRun {
+                command: "sh".to_string(),
+                args,
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            }
+            .into());
+        }
+
+        outputs.push(output);
+    }
+
+    Ok(outputs)
+}
@@ -0,0 +1,39 @@
+#![cfg(test)]
+
+use super::*;
+use crate::errors::DotbakError;
+use assert_fs::{prelude::*, TempDir};
+
+/// Test that the commands run in order in the given folder, and know the hook and the repository.
+#[test]
+fn test_run() {
+    let dir = TempDir::new().unwrap();
+    let commands = vec![
+        "echo \"$DOTBAK_HOOK $DOTBAK_REPO\" > hook.txt".to_string(),
+        "cat hook.txt".to_string(),
+    ];
+
+    let outputs = run(Hook::PostPull, &commands, dir.path(), "/repo").unwrap();
+
+    dir.child("hook.txt").assert("post_pull /repo\n");
+    assert_eq!(outputs.len(), 2);
+    assert_eq!(outputs[1].stdout, b"post_pull /repo\n");
+}
+
+/// Test that a failing command stops the remaining ones, and returns its output.
+#[test]
+fn test_run_failure() {
+    let dir = TempDir::new().unwrap();
+    let commands = vec![
+        "echo oops >&2; exit 1".to_string(),
+        "touch ran.txt".to_string(),
+    ];
+
+    let result = run(Hook::PreSync, &commands, dir.path(), "/repo");
+
+    assert!(matches!(
+        result,
+        Err(DotbakError::Io(IoError::CommandRun { ref stderr, .. })) if stderr == "oops\n"
+    ));
+    assert!(!dir.child("ran.txt").exists());
+}
@@ -0,0 +1,70 @@
+//! The library behind the `dotbak` CLI, for embedding `dotbak` in other tools (GUIs, scripts, etc.).
+//!
+//! `Dotbak` manages the dotfiles: it's set up or loaded with a `DotbakBuilder`, which defaults to the user's home
+//! directory and the XDG base directories (`~/.config/dotbak`, `~/.local/share/dotbak` and `~/.local/state/dotbak`).
+//! Its parts are available on their own, too: the configuration (`Config`), the managed files and their symlinks
+//! (`Files`), and the git repository (`Repository`). The other types they use (e.g. `Report` or `Coverage`) are
+//! re-exported here, while the modules implementing them are internal.
+//!
+//! ```no_run
+//! use dotbak_core::DotbakBuilder;
+//!
+//! let mut dotbak = DotbakBuilder::new()
+//!     .home_dir("/home/alice")
+//!     .config_path("/home/alice/.config/dotbak/config.toml")
+//!     .load()?;
+//!
+//! dotbak.sync()?;
+//! # Ok::<(), dotbak_core::DotbakError>(())
+//! ```
+
+pub mod alias;
+pub(crate) mod bundle;
+pub(crate) mod clock;
+pub mod config;
+pub(crate) mod diagnostics;
+pub mod dotbak;
+pub(crate) mod eject;
+pub mod errors;
+pub(crate) mod export;
+pub mod files;
+pub mod git;
+pub(crate) mod hooks;
+pub(crate) mod lock;
+pub(crate) mod network;
+pub(crate) mod notifications;
+pub(crate) mod packages;
+pub(crate) mod paths;
+pub(crate) mod platform;
+pub(crate) mod plugins;
+pub(crate) mod rewrite;
+pub(crate) mod schedule;
+pub(crate) mod secrets;
+pub mod shell;
+pub(crate) mod stow;
+pub(crate) mod suggest;
+#[cfg(any(test, feature = "testing"))]
+pub mod test_util;
+pub mod ui;
+pub(crate) mod xdg;
+
+pub use self::{
+    clock::format_utc,
+    config::Config,
+    diagnostics::{
+        doctor::{Check, Finding, Severity},
+        Report,
+    },
+    dotbak::{builder::DotbakBuilder, Dotbak},
+    eject::InstallStyle,
+    errors::{DotbakError, Result},
+    files::Files,
+    git::Repository,
+    hooks::Hook,
+    lock::{Lock, LockMode},
+    packages::Manager,
+    plugins::{LockFile, LockStatus},
+    rewrite::HardcodedPath,
+    schedule::{Scheduler, UnitFile},
+    suggest::{Candidate, Coverage},
+};
@@ -0,0 +1,124 @@
+mod tests;
+
+use crate::errors::{io::IoError, Result};
+use std::{
+    fs::{self, File, OpenOptions, TryLockError},
+    path::{Path, PathBuf},
+};
+
+/// The name of the lock file, next to the configuration file.
+pub const LOCK_FILE_NAME: &str = "dotbak.lock";
+
+/// How a command accesses `dotbak`'s state (the configuration, the repository, the managed files, etc.).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockMode {
+    /// The command only reads the state, so it never waits for other `dotbak` processes (like a slow daemon
+    /// sync), and doesn't make them wait either. It reads the state as of the last completed write, as the state
+    /// files are always replaced as a whole (see `write_atomic`).
+    Shared,
+
+    /// The command changes the state, so it waits for other `dotbak` processes changing it to finish first, and
+    /// makes them wait in turn.
+    Exclusive,
+}
+
+/// The exclusive lock on `dotbak`'s state, which is held until this is dropped. See `LockMode`.
+#[derive(Debug)]
+pub struct Lock {
+    /// The locked file. Closing it releases the lock.
+    _file: File,
+}
+
+impl Lock {
+    /// Takes the exclusive lock in the folder `dir` (the folder containing the configuration file), which is
+    /// created if it doesn't exist yet. If another process holds the lock, `on_wait` is called, and this waits
+    /// until the lock is released.
+    pub fn exclusive<P, F>(dir: P, on_wait: F) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(),
+    {
+        let path = dir.as_ref().join(LOCK_FILE_NAME);
+
+        fs::create_dir_all(dir.as_ref()).map_err(|err| IoError::Create {
+            source: err,
+            path: dir.as_ref().to_path_buf(),
+        })?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .map_err(|err| IoError::Create {
+                source: err,
+                path: path.clone(),
+            })?;
+
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => {
+                on_wait();
+                file.lock().map_err(|err| lock_err(err, &path))?;
+            }
+            Err(TryLockError::Error(err)) => return Err(lock_err(err, &path).into()),
+        }
+
+        Ok(Lock { _file: file })
+    }
+
+    /// Checks if another process holds the exclusive lock in the folder `dir`, i.e. is changing the state right
+    /// now. This never waits.
+    pub fn is_held<P>(dir: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        File::open(dir.as_ref().join(LOCK_FILE_NAME))
+            .is_ok_and(|file| matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)))
+    }
+}
+
+/// Replaces the file at `path` with `contents` as a whole: the contents are written to a temporary file next to
+/// it first, which is then renamed over it. So processes reading the file without the lock never see it half
+/// written. If `path` is a symlink (e.g. to a managed file in the repository), the file it points to is replaced.
+pub fn write_atomic<P, C>(path: P, contents: C) -> Result<()>
+where
+    P: AsRef<Path>,
+    C: AsRef<[u8]>,
+{
+    let path = match fs::symlink_metadata(path.as_ref()) {
+        Ok(meta) if meta.is_symlink() => {
+            fs::canonicalize(path.as_ref()).map_err(|err| IoError::Read {
+                source: err,
+                path: path.as_ref().to_path_buf(),
+            })?
+        }
+        _ => path.as_ref().to_path_buf(),
+    };
+    let path = path.as_path();
+    let mut temp = path.as_os_str().to_os_string();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    fs::write(&temp, contents).map_err(|err| IoError::Write {
+        source: err,
+        path: temp.clone(),
+    })?;
+
+    fs::rename(&temp, path).map_err(|err| {
+        IoError::Move {
+            source: err,
+            from: temp,
+            to: path.to_path_buf(),
+        }
+        .into()
+    })
+}
+
+/// Helper function to create the error for failing to lock the file at `path`.
+fn lock_err(err: std::io::Error, path: &Path) -> IoError {
+    IoError::Lock {
+        source: err,
+        path: path.to_path_buf(),
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::{prelude::*, TempDir};
+use std::{
+    os::unix::fs::symlink,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Test that the exclusive lock is only held until it's dropped, and that taking it waits for other holders.
+#[test]
+fn test_exclusive() {
+    let dir = TempDir::new().unwrap();
+    let state_dir = dir.child("state");
+
+    assert!(!Lock::is_held(&state_dir));
+
+    let lock = Lock::exclusive(&state_dir, || panic!("Nothing holds the lock yet!")).unwrap();
+    assert!(Lock::is_held(&state_dir));
+
+    // Release the lock from another thread in a bit, while this one waits for it.
+    let released = Arc::new(AtomicBool::new(false));
+    let releaser = {
+        let released = released.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            released.store(true, Ordering::SeqCst);
+            drop(lock);
+        })
+    };
+
+    let mut waited = false;
+    let _lock = Lock::exclusive(&state_dir, || waited = true).unwrap();
+
+    assert!(waited);
+    assert!(released.load(Ordering::SeqCst));
+    releaser.join().unwrap();
+}
+
+/// Test that files are replaced as a whole, through symlinks.
+#[test]
+fn test_write_atomic() {
+    let dir = TempDir::new().unwrap();
+    let (file, link) = (dir.child("file.toml"), dir.child("link.toml"));
+
+    file.write_str("old").unwrap();
+    symlink(file.path(), link.path()).unwrap();
+
+    write_atomic(&link, "new").unwrap();
+
+    assert!(link.is_symlink());
+    file.assert("new");
+    assert!(!dir.child("file.toml.tmp").exists());
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+}
@@ -1,24 +1,15 @@
 mod cli;
-mod config;
-mod dotbak;
-mod errors;
-mod files;
-mod git;
-mod test_util;
-mod ui;
-
-use clap::Parser;
+
 use cli::Cli;
 use miette::Result;
+use std::process::ExitCode;
 
-fn main() -> Result<()> {
+fn main() -> Result<ExitCode> {
     amend_panic_with_issue_msg();
 
-    let cli = Cli::parse();
-
-    cli.run()?;
+    let cli = Cli::parse_with_aliases()?;
 
-    Ok(())
+    Ok(cli.run()?)
 }
 
 /// OVerride panic messages with a message to submit an issue at the git repo.
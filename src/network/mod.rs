@@ -0,0 +1,89 @@
+mod tests;
+
+use crate::errors::{DotbakError, Result};
+use std::{process::Command, time::Duration};
+
+/// Whether the current network connection is metered (i.e. data usage is limited or costs money).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metered {
+    /// The connection is (or is guessed to be) metered.
+    Yes,
+
+    /// The connection is (or is guessed to be) not metered.
+    No,
+
+    /// Whether the connection is metered can't be detected on this system.
+    Unknown,
+}
+
+/// Detects whether the current network connection is metered. On Linux, this asks NetworkManager. On other
+/// systems (or without NetworkManager), this returns `Metered::Unknown`.
+pub fn detect_metered() -> Metered {
+    if !cfg!(target_os = "linux") {
+        return Metered::Unknown;
+    }
+
+    let output = Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {
+            parse_network_manager_metered(&String::from_utf8_lossy(&output.stdout))
+        }
+        _ => Metered::Unknown,
+    }
+}
+
+/// Parses NetworkManager's `Metered` property, as printed by `busctl` (e.g. `u 4`). The values are documented
+/// as `NMMetered`: 0 is unknown, 1 is yes, 2 is no, 3 is guess-yes, and 4 is guess-no.
+fn parse_network_manager_metered(output: &str) -> Metered {
+    match output.trim().strip_prefix("u ").map(str::trim) {
+        Some("1") | Some("3") => Metered::Yes,
+        Some("2") | Some("4") => Metered::No,
+        _ => Metered::Unknown,
+    }
+}
+
+/// Runs `run` (e.g. a push, pull or clone) again while it fails to reach the remote (see `GitError::is_transient`),
+/// up to `attempts` times in total. It waits `backoff` milliseconds before the first retry, and twice as long before
+/// every next one, by calling `sleep` (e.g. `thread::sleep`) with the delay. `on_retry` is called with the error and
+/// the delay before each retry, e.g. to tell the user.
+pub fn with_retries<T, S, F, R>(
+    attempts: u32,
+    backoff: u64,
+    mut sleep: S,
+    mut run: F,
+    mut on_retry: R,
+) -> Result<T>
+where
+    S: FnMut(Duration),
+    F: FnMut() -> Result<T>,
+    R: FnMut(&DotbakError, Duration),
+{
+    let mut delay = Duration::from_millis(backoff);
+
+    for _ in 1..attempts.max(1) {
+        match run() {
+            Err(err) if is_transient(&err) => {
+                on_retry(&err, delay);
+                sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    run()
+}
+
+/// Helper function to check whether `err` is a failure that may go away by itself, like the network being down.
+fn is_transient(err: &DotbakError) -> bool {
+    matches!(err, DotbakError::Git(err) if err.is_transient())
+}
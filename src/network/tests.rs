@@ -0,0 +1,87 @@
+#![cfg(test)]
+
+use super::*;
+
+/// Test parsing NetworkManager's metered state.
+#[test]
+fn test_parse_network_manager_metered() {
+    assert_eq!(parse_network_manager_metered("u 1\n"), Metered::Yes);
+    assert_eq!(parse_network_manager_metered("u 3\n"), Metered::Yes);
+    assert_eq!(parse_network_manager_metered("u 2\n"), Metered::No);
+    assert_eq!(parse_network_manager_metered("u 4"), Metered::No);
+    assert_eq!(parse_network_manager_metered("u 0"), Metered::Unknown);
+    assert_eq!(parse_network_manager_metered(""), Metered::Unknown);
+}
+
+/// Test that only failures to reach the remote are retried, up to the given number of attempts, waiting twice as
+/// long before every retry.
+#[test]
+fn test_with_retries() {
+    use crate::errors::{git::GitError, DotbakError};
+
+    let unreachable = || {
+        Err::<(), _>(DotbakError::Git(GitError::RemoteUnreachable {
+            args: vec!["push".to_string()],
+            stderr: "fatal: Could not resolve host: example.com".to_string(),
+        }))
+    };
+    let (mut runs, mut delays, mut sleeps) = (0, vec![], vec![]);
+
+    assert!(with_retries(
+        4,
+        1000,
+        |delay| sleeps.push(delay),
+        || {
+            runs += 1;
+            unreachable()
+        },
+        |_, delay| delays.push(delay)
+    )
+    .is_err());
+    assert_eq!(runs, 4);
+    assert_eq!(
+        sleeps,
+        vec![
+            Duration::from_secs(1),
+            Duration::from_secs(2),
+            Duration::from_secs(4)
+        ]
+    );
+    assert_eq!(delays, sleeps);
+
+    (runs, sleeps) = (0, vec![]);
+    assert!(with_retries(
+        3,
+        100,
+        |delay| sleeps.push(delay),
+        || {
+            runs += 1;
+            match runs {
+                1 => unreachable(),
+                _ => Ok(()),
+            }
+        },
+        |_, _| {}
+    )
+    .is_ok());
+    assert_eq!(runs, 2);
+    assert_eq!(sleeps, vec![Duration::from_millis(100)]);
+
+    (runs, sleeps) = (0, vec![]);
+    assert!(with_retries(
+        3,
+        100,
+        |delay| sleeps.push(delay),
+        || {
+            runs += 1;
+            Err::<(), _>(DotbakError::Git(GitError::NoUpstream {
+                args: vec!["push".to_string()],
+                stderr: "fatal: The current branch main has no upstream branch.".to_string(),
+            }))
+        },
+        |_, _| {}
+    )
+    .is_err());
+    assert_eq!(runs, 1);
+    assert!(sleeps.is_empty());
+}
@@ -0,0 +1,300 @@
+mod tests;
+
+use crate::{
+    config::notifications::NotificationsConfig,
+    errors::{io::IoError, Result},
+};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// How long in seconds posting to a webhook may take, so that an unreachable one doesn't hold up the daemon.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+/// The environment variable the title of a notification is passed to PowerShell in on Windows, so that it doesn't
+/// need to be escaped for it. See `TOAST_SCRIPT`.
+const TITLE_VAR: &str = "DOTBAK_NOTIFICATION_TITLE";
+
+/// The environment variable the body of a notification is passed to PowerShell in on Windows, like `TITLE_VAR`.
+const BODY_VAR: &str = "DOTBAK_NOTIFICATION_BODY";
+
+/// The PowerShell script showing a notification as a toast on Windows, through the WinRT API. Toasts have to come
+/// from an installed app, so it's shown as one of PowerShell's.
+const TOAST_SCRIPT: &str = r#"$ErrorActionPreference = 'Stop'
+$manager = [Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime]
+$xml = $manager::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$text = $xml.GetElementsByTagName('text')
+[void]$text.Item(0).AppendChild($xml.CreateTextNode($env:DOTBAK_NOTIFICATION_TITLE))
+[void]$text.Item(1).AppendChild($xml.CreateTextNode($env:DOTBAK_NOTIFICATION_BODY))
+$app = '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\WindowsPowerShell\v1.0\powershell.exe'
+$manager::CreateToastNotifier($app).Show([Windows.UI.Notifications.ToastNotification]::new($xml))
+"#;
+
+/// How severe a failure is. See `config::notifications::Level`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// Something went wrong, but the sync still did what it could, e.g. it only committed locally, as the remote
+    /// couldn't be reached.
+    Warning,
+
+    /// The sync failed, e.g. because of a merge conflict, or because authenticating with the remote failed.
+    Error,
+}
+
+impl Severity {
+    /// Gets the name of the severity, as it's posted to webhooks.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// A failure the user is notified of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// How severe the failure is.
+    pub severity: Severity,
+
+    /// A short summary, e.g. `dotbak couldn't sync`.
+    pub title: String,
+
+    /// What went wrong, e.g. the error.
+    pub body: String,
+}
+
+/// Sends `notification` in all the ways `config` says to notify of failures of its severity. Every way is tried,
+/// even if another one fails, and the first failure is returned.
+pub fn send(config: &NotificationsConfig, notification: &Notification) -> Result<()> {
+    let severity = notification.severity;
+    let mut results = vec![];
+
+    if config.desktop.includes(severity) {
+        results.push(match desktop_command(notification) {
+            Some(command) => run("to the desktop", command, None),
+            None => Err(IoError::Notification {
+                channel: "to the desktop".to_string(),
+                reason: format!(
+                    "desktop notifications aren't supported on {}",
+                    std::env::consts::OS
+                ),
+            }),
+        });
+    }
+
+    if let Some(url) = config
+        .webhook
+        .as_ref()
+        .filter(|_| config.webhook_level.includes(severity))
+    {
+        results.push(run(
+            "to the webhook",
+            webhook_command(),
+            Some(&webhook_config(url, &json(notification))),
+        ));
+    }
+
+    if let Some(to) = config
+        .email
+        .as_ref()
+        .filter(|_| config.email_level.includes(severity))
+    {
+        results.push(run(
+            "by email",
+            email_command(),
+            Some(&email(to, notification)),
+        ));
+    }
+
+    match results.into_iter().find_map(|result| result.err()) {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
+}
+
+/// Creates the command showing `notification` as a desktop notification, or `None` if the platform has no command
+/// for that: `notify-send` on Linux and the BSDs, `osascript` on macOS, and PowerShell on Windows (see
+/// `TOAST_SCRIPT`).
+fn desktop_command(notification: &Notification) -> Option<Command> {
+    if cfg!(target_os = "macos") {
+        // The title and body are passed as arguments, so that they don't need to be escaped for AppleScript.
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            "on run argv",
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+            "-e",
+            "end run",
+            &notification.title,
+            &notification.body,
+        ]);
+
+        Some(command)
+    } else if cfg!(unix) {
+        let urgency = match notification.severity {
+            Severity::Warning => "--urgency=normal",
+            Severity::Error => "--urgency=critical",
+        };
+
+        let mut command = Command::new("notify-send");
+        command.args([
+            "--app-name=dotbak",
+            urgency,
+            "--",
+            &notification.title,
+            &notification.body,
+        ]);
+
+        Some(command)
+    } else if cfg!(windows) {
+        let mut command = Command::new("powershell");
+        command
+            .args(["-NoProfile", "-NonInteractive", "-Command", TOAST_SCRIPT])
+            .env(TITLE_VAR, &notification.title)
+            .env(BODY_VAR, &notification.body);
+
+        Some(command)
+    } else {
+        None
+    }
+}
+
+/// Creates the command posting to a webhook with `curl`, which reads the URL and the JSON from its stdin (see
+/// `webhook_config`). They aren't passed as arguments, since other users can see those (e.g. with `ps`), and the URL
+/// of a webhook is a secret.
+fn webhook_command() -> Command {
+    let mut command = Command::new("curl");
+    command.args([
+        "--silent",
+        "--show-error",
+        "--fail",
+        "--max-time",
+        &WEBHOOK_TIMEOUT.to_string(),
+        "--header",
+        "Content-Type: application/json",
+        "--config",
+        "-",
+    ]);
+
+    command
+}
+
+/// Formats the `curl` configuration posting `json` to the webhook `url`, for `webhook_command`.
+fn webhook_config(url: &str, json: &str) -> String {
+    format!(
+        "url = {}\ndata-binary = {}\n",
+        curl_string(url),
+        curl_string(json)
+    )
+}
+
+/// Creates the command sending the email on its stdin (with its headers) with `sendmail`. Lines with just a `.` in
+/// the body would end the email early, unless `-i` is passed.
+fn email_command() -> Command {
+    let mut command = Command::new("sendmail");
+    command.args(["-t", "-i"]);
+
+    command
+}
+
+/// Formats `notification` as the JSON object posted to webhooks, with its `title`, `body` and `severity`.
+fn json(notification: &Notification) -> String {
+    format!(
+        r#"{{"title":{},"body":{},"severity":{}}}"#,
+        json_string(&notification.title),
+        json_string(&notification.body),
+        json_string(notification.severity.as_str())
+    )
+}
+
+/// Formats `notification` as an email to `to`, with the headers `sendmail -t` reads the recipient from.
+fn email(to: &str, notification: &Notification) -> String {
+    // Line breaks would end the headers early, or add headers of their own.
+    let header = |value: &str| value.replace(['\r', '\n'], " ");
+
+    format!(
+        "To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n",
+        header(to),
+        header(&notification.title),
+        notification.body
+    )
+}
+
+/// Quotes `value` as a string in a `curl` configuration.
+fn curl_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str(r#"\""#),
+            '\\' => quoted.push_str(r"\\"),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes `value` as a JSON string.
+fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str(r#"\""#),
+            '\\' => quoted.push_str(r"\\"),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            c if c.is_control() => quoted.push_str(&format!(r"\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+/// Runs `command` to send a notification `channel` (e.g. `by email`), writing `input` to its stdin if given.
+fn run(
+    channel: &str,
+    mut command: Command,
+    input: Option<&str>,
+) -> std::result::Result<(), IoError> {
+    let fail = |reason: String| IoError::Notification {
+        channel: channel.to_string(),
+        reason,
+    };
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| fail(err.to_string()))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|err| fail(err.to_string()))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|err| fail(err.to_string()))?;
+
+    match String::from_utf8_lossy(&output.stderr).trim() {
+        _ if output.status.success() => Ok(()),
+        "" => Err(fail(output.status.to_string())),
+        stderr => Err(fail(stderr.to_string())),
+    }
+}
@@ -0,0 +1,134 @@
+#![cfg(test)]
+
+use super::*;
+use crate::config::notifications::Level;
+
+/// Helper function to create a notification of `severity`.
+fn notification(severity: Severity) -> Notification {
+    Notification {
+        severity,
+        title: "dotbak couldn't sync".to_string(),
+        body: "Merge conflict in \"x\"\n\tand\u{1} more".to_string(),
+    }
+}
+
+/// Test which failures each level notifies of.
+#[test]
+fn test_level_includes() {
+    assert!(!Level::Off.includes(Severity::Error));
+    assert!(Level::Errors.includes(Severity::Error));
+    assert!(!Level::Errors.includes(Severity::Warning));
+    assert!(Level::Warnings.includes(Severity::Warning));
+    assert!(Level::Warnings.includes(Severity::Error));
+}
+
+/// Test that notifications are posted to webhooks as JSON, escaping what needs to be.
+#[test]
+fn test_json() {
+    assert_eq!(
+        json(&notification(Severity::Error)),
+        r#"{"title":"dotbak couldn't sync","body":"Merge conflict in \"x\"\n\tand\u0001 more","severity":"error"}"#
+    );
+}
+
+/// Test that the webhook's URL and JSON are given to `curl` on its stdin, quoted, and not as arguments.
+#[test]
+fn test_webhook_config() {
+    assert!(!webhook_command()
+        .get_args()
+        .any(|arg| arg.to_string_lossy().contains("://")));
+    assert_eq!(
+        webhook_config(
+            "https://hooks.example.com/T0/secret",
+            r#"{"body":"a \"b\"\n"}"#
+        ),
+        concat!(
+            r#"url = "https://hooks.example.com/T0/secret""#,
+            "\n",
+            r#"data-binary = "{\"body\":\"a \\\"b\\\"\\n\"}""#,
+            "\n"
+        )
+    );
+}
+
+/// Test that emails can't get extra headers through line breaks.
+#[test]
+fn test_email() {
+    let mut notification = notification(Severity::Warning);
+    notification.title = "a\nBcc: b@example.com".to_string();
+
+    assert_eq!(
+        email("me@example.com", &notification),
+        "To: me@example.com\nSubject: a Bcc: b@example.com\nContent-Type: text/plain; charset=utf-8\n\n\
+         Merge conflict in \"x\"\n\tand\u{1} more\n"
+    );
+}
+
+/// Test that `sendmail` doesn't end emails at lines with just a `.` in their body.
+#[test]
+fn test_email_command() {
+    let command = email_command();
+
+    assert_eq!(command.get_program(), "sendmail");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["-t", "-i"]);
+}
+
+/// Test that nothing is sent for failures no way of notifying is used for, and that failing to send is an error.
+#[test]
+fn test_send() {
+    let mut config = NotificationsConfig {
+        desktop: Level::Off,
+        webhook: Some("http://localhost:1".to_string()),
+        webhook_level: Level::Errors,
+        email: None,
+        email_level: Level::Warnings,
+    };
+
+    assert!(send(&config, &notification(Severity::Warning)).is_ok());
+
+    config.webhook_level = Level::Off;
+    assert!(send(&config, &notification(Severity::Error)).is_ok());
+
+    // Either `curl` isn't there, or nothing listens on the port.
+    config.webhook_level = Level::Warnings;
+    assert!(send(&config, &notification(Severity::Warning)).is_err());
+}
+
+/// Test the command showing desktop notifications.
+#[test]
+#[cfg(all(unix, not(target_os = "macos")))]
+fn test_desktop_command() {
+    let command = desktop_command(&notification(Severity::Error)).unwrap();
+
+    assert_eq!(command.get_program(), "notify-send");
+    assert_eq!(
+        command.get_args().collect::<Vec<_>>(),
+        [
+            "--app-name=dotbak",
+            "--urgency=critical",
+            "--",
+            "dotbak couldn't sync",
+            "Merge conflict in \"x\"\n\tand\u{1} more"
+        ]
+    );
+}
+
+/// Test the command showing desktop notifications on Windows, which gets the title and body in its environment.
+#[test]
+#[cfg(windows)]
+fn test_desktop_command_windows() {
+    use std::ffi::OsStr;
+
+    let command = desktop_command(&notification(Severity::Error)).unwrap();
+    let envs = command.get_envs().collect::<Vec<_>>();
+
+    assert_eq!(command.get_program(), "powershell");
+    assert!(envs.contains(&(
+        OsStr::new(TITLE_VAR),
+        Some(OsStr::new("dotbak couldn't sync"))
+    )));
+    assert!(envs.contains(&(
+        OsStr::new(BODY_VAR),
+        Some(OsStr::new("Merge conflict in \"x\"\n\tand\u{1} more"))
+    )));
+}
@@ -0,0 +1,167 @@
+mod tests;
+
+use crate::{
+    errors::{io::IoError, Result},
+    lock,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+/// The folder at the top of the repository that the package lists are exported to, see `export`.
+pub const PACKAGES_DIR: &str = ".dotbak-packages";
+
+/// A package manager whose installed packages can be exported to the repository, and installed again from there on
+/// another machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    /// Homebrew, exported as a `Brewfile` with `brew bundle dump`.
+    Brew,
+
+    /// apt, exporting the manually installed packages with `apt-mark showmanual`.
+    Apt,
+
+    /// pacman, exporting the explicitly installed packages with `pacman -Qqe`.
+    Pacman,
+}
+
+/// Public API for `Manager`.
+impl Manager {
+    /// The name of the package manager, as in the configuration.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Manager::Brew => "brew",
+            Manager::Apt => "apt",
+            Manager::Pacman => "pacman",
+        }
+    }
+
+    /// The name of the file the package list is exported to, in `PACKAGES_DIR`.
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Manager::Brew => "Brewfile",
+            Manager::Apt => "apt.txt",
+            Manager::Pacman => "pacman.txt",
+        }
+    }
+
+    /// The command printing the list of installed packages.
+    pub fn export_command(&self) -> Command {
+        let (program, args): (&str, &[&str]) = match self {
+            Manager::Brew => ("brew", &["bundle", "dump", "--file=-", "--force"]),
+            Manager::Apt => ("apt-mark", &["showmanual"]),
+            Manager::Pacman => ("pacman", &["-Qqe"]),
+        };
+
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    }
+
+    /// The command installing the packages in the exported package list `list` (the contents of `file_name`),
+    /// which is at `path`. Packages that are installed already are left alone.
+    pub fn install_command<P>(&self, list: &str, path: P) -> Command
+    where
+        P: AsRef<Path>,
+    {
+        let packages = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let mut command = match self {
+            Manager::Brew => {
+                let mut command = Command::new("brew");
+                command
+                    .args(["bundle", "install", "--file"])
+                    .arg(path.as_ref());
+                command
+            }
+            Manager::Apt => {
+                let mut command = Command::new("sudo");
+                command.args(["apt-get", "install", "--yes"]);
+                command
+            }
+            Manager::Pacman => {
+                let mut command = Command::new("sudo");
+                command.args(["pacman", "--sync", "--needed", "--noconfirm"]);
+                command
+            }
+        };
+
+        if *self != Manager::Brew {
+            command.args(packages);
+        }
+
+        command
+    }
+}
+
+/// Exports the installed packages of `manager` to its file in the folder `dir` (usually `PACKAGES_DIR` in the
+/// repository). Returns whether the package list changed.
+pub fn export<P>(manager: Manager, dir: P) -> Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let output = run(manager.export_command())?;
+    let list = String::from_utf8_lossy(&output.stdout);
+    let path = dir.as_ref().join(manager.file_name());
+
+    if fs::read_to_string(&path).is_ok_and(|old| old == list) {
+        return Ok(false);
+    }
+
+    fs::create_dir_all(dir.as_ref()).map_err(|err| IoError::Create {
+        source: err,
+        path: dir.as_ref().to_path_buf(),
+    })?;
+    lock::write_atomic(&path, list.as_bytes())?;
+
+    Ok(true)
+}
+
+/// Installs the packages of `manager` from its file in the folder `dir`, see `Manager::install_command`. Returns
+/// `None` if no package list was exported for `manager`.
+pub fn apply<P>(manager: Manager, dir: P) -> Result<Option<Output>>
+where
+    P: AsRef<Path>,
+{
+    let path = dir.as_ref().join(manager.file_name());
+
+    let Ok(list) = fs::read_to_string(&path) else {
+        return Ok(None);
+    };
+
+    run(manager.install_command(&list, &path)).map(Some)
+}
+
+/// Helper function to run `command`, returning an error if it can't be run or fails.
+fn run(mut command: Command) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let args = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+
+    let output = command.output().map_err(|err| IoError::CommandIO {
+        source: err,
+        command: program.clone(),
+        args: args.clone(),
+    })?;
+
+    if !output.status.success() {
+        return Err(IoError::CommandRun {
+            command: program,
+            args,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+        .into());
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::TempDir;
+use std::process::Command;
+
+/// Helper function to get the program and arguments of `command`.
+fn words(command: &Command) -> Vec<String> {
+    [command.get_program()]
+        .into_iter()
+        .chain(command.get_args())
+        .map(|word| word.to_string_lossy().to_string())
+        .collect()
+}
+
+/// Test that the installed packages are exported with each package manager's own command.
+#[test]
+fn test_export_command() {
+    assert_eq!(
+        words(&Manager::Brew.export_command()),
+        vec!["brew", "bundle", "dump", "--file=-", "--force"]
+    );
+    assert_eq!(
+        words(&Manager::Apt.export_command()),
+        vec!["apt-mark", "showmanual"]
+    );
+    assert_eq!(
+        words(&Manager::Pacman.export_command()),
+        vec!["pacman", "-Qqe"]
+    );
+}
+
+/// Test that the packages are installed from the exported lists, leaving out blank lines and comments.
+#[test]
+fn test_install_command() {
+    let list = "git\n\n# editors\nneovim\n";
+
+    assert_eq!(
+        words(&Manager::Apt.install_command(list, "apt.txt")),
+        vec!["sudo", "apt-get", "install", "--yes", "git", "neovim"]
+    );
+    assert_eq!(
+        words(&Manager::Pacman.install_command(list, "pacman.txt")),
+        vec![
+            "sudo",
+            "pacman",
+            "--sync",
+            "--needed",
+            "--noconfirm",
+            "git",
+            "neovim"
+        ]
+    );
+
+    // The Brewfile is read by `brew bundle` itself.
+    assert_eq!(
+        words(&Manager::Brew.install_command("brew \"git\"", "Brewfile")),
+        vec!["brew", "bundle", "install", "--file", "Brewfile"]
+    );
+}
+
+/// Test that nothing is installed for package managers without an exported package list.
+#[test]
+fn test_apply_without_list() {
+    let dir = TempDir::new().unwrap();
+
+    assert!(apply(Manager::Pacman, dir.path()).unwrap().is_none());
+}
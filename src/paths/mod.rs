@@ -0,0 +1,394 @@
+mod tests;
+
+use crate::errors::{config::ConfigError, Result};
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::{
+    fs,
+    ops::Deref,
+    path::{Component, Path, PathBuf},
+};
+
+/// The prefixes standing for the home directory in paths: `~`, and `%USERPROFILE%` as on Windows.
+const HOME_PREFIXES: &[&str] = &["~", "%USERPROFILE%"];
+
+/// Resolves `path` against the home directory `home`. Absolute paths are kept as-is, paths starting with `~` (or
+/// `%USERPROFILE%`) have it replaced with `home`, and all other paths are taken to be relative to `home`.
+pub fn resolve_home<P1, P2>(path: P1, home: P2) -> PathBuf
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    match HOME_PREFIXES
+        .iter()
+        .find_map(|prefix| path.strip_prefix(prefix).ok())
+    {
+        Some(rest) => home.as_ref().join(rest),
+        None => home.as_ref().join(path), // `join` keeps absolute paths as-is.
+    }
+}
+
+/// Lexically normalizes `path`, removing `.` and resolving `..` components. Unlike `fs::canonicalize`, this doesn't
+/// touch the filesystem, so it works for paths that don't exist (yet), and doesn't follow the symlinks `dotbak`
+/// creates.
+pub fn normalize<P>(path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let mut normalized = PathBuf::new();
+
+    for component in path.as_ref().components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Gets `path` relative to the home directory `home`, which is how paths are stored in `files.include`. `path` is
+/// resolved with `resolve_home` and normalized first, so `~/.zshrc`, `/home/user/.zshrc`, `.zshrc` and
+/// `.config/../.zshrc` all become `.zshrc`. If `home` is a symlink (e.g. `/home/user` pointing to `/data/user`),
+/// paths through where it points to are inside of it too.
+///
+/// Returns an error if `path` is not inside of `home` (or is `home` itself), e.g. if it escapes it with `..`.
+pub fn relative_to_home<P1, P2>(path: P1, home: P2) -> Result<PathBuf>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let home = normalize(home);
+    let resolved = normalize(resolve_home(&path, &home));
+
+    // The real home directory is only looked up if it's needed, as it touches the filesystem.
+    let relative = strip_dir(&resolved, &home).or_else(|| {
+        fs::canonicalize(&home)
+            .ok()
+            .filter(|real| *real != home)
+            .and_then(|real| strip_dir(&resolved, &real))
+    });
+
+    relative.ok_or_else(|| {
+        ConfigError::OutsideHome {
+            path: path.as_ref().to_path_buf(),
+            home,
+        }
+        .into()
+    })
+}
+
+/// A path to manage, the way it's stored in `files.include`: relative to the home directory if it's inside of it
+/// (see `relative_to_home`), and as a normalized absolute path if it's inside of one of the folders outside of the
+/// home directory in `files.roots` instead, e.g. `/etc/hosts`. It's normalized (see `normalize`), so it has no `.`
+/// or `..` components or trailing slashes, and it never escapes the home directory or its root, nor is either of
+/// them itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ManagedPath(PathBuf);
+
+impl ManagedPath {
+    /// Validates `path` (given by the user, e.g. on the command line, or in the configuration) against the home
+    /// directory `home` and the folders `roots` outside of it (see `FilesConfig::roots`).
+    ///
+    /// Returns an error if `path` is inside of neither (or is `home` or one of `roots` itself).
+    pub fn new<P1, P2>(path: P1, home: P2, roots: &[PathBuf]) -> Result<Self>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let resolved = normalize(resolve_home(&path, &home));
+
+        match relative_to_home(&path, &home) {
+            Ok(relative) => Ok(Self(relative)),
+            Err(_)
+                if roots
+                    .iter()
+                    .map(normalize)
+                    .any(|root| strip_dir(&resolved, &root).is_some()) =>
+            {
+                Ok(Self(resolved))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether the path is inside of one of the roots outside of the home directory, i.e. absolute.
+    pub fn is_in_root(&self) -> bool {
+        self.0.is_absolute()
+    }
+
+    /// Gets the path.
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Turns this into the path.
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+}
+
+impl Deref for ManagedPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for ManagedPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl From<ManagedPath> for PathBuf {
+    fn from(path: ManagedPath) -> Self {
+        path.0
+    }
+}
+
+/// Helper function to get `path` relative to the folder `dir`, or `None` if it isn't inside of it (or is `dir`
+/// itself). Both must be normalized.
+fn strip_dir(path: &Path, dir: &Path) -> Option<PathBuf> {
+    path.strip_prefix(dir)
+        .ok()
+        .filter(|relative| *relative != Path::new(""))
+        .map(Path::to_path_buf)
+}
+
+/// Checks that `path` (relative to the home directory `home`) can be managed, i.e. that it is neither (inside
+/// of) the repository directory `repo`, nor an ancestor of the repository or the configuration file `config`.
+/// Managing any of these would make `dotbak` move its own files into the repository and symlink them into
+/// themselves. Note that the configuration file itself *can* be managed (and is by default). Neither can the
+/// encryption key `key`, nor a folder containing it, be managed, as that would commit the key in plaintext.
+pub fn check_manageable<P1, P2, P3, P4, P5>(
+    path: P1,
+    home: P2,
+    repo: P3,
+    config: P4,
+    key: P5,
+) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+    P4: AsRef<Path>,
+    P5: AsRef<Path>,
+{
+    let resolved = normalize(home.as_ref().join(path.as_ref()));
+    let repo = normalize(repo);
+
+    if resolved.starts_with(&repo) {
+        return Err(ConfigError::InsideRepo {
+            path: path.as_ref().to_path_buf(),
+            repo_path: repo,
+        }
+        .into());
+    }
+
+    for dotbak_path in [repo, normalize(config)] {
+        if dotbak_path.starts_with(&resolved) && dotbak_path != resolved {
+            return Err(ConfigError::ContainsDotbakPath {
+                path: path.as_ref().to_path_buf(),
+                dotbak_path,
+            }
+            .into());
+        }
+    }
+
+    let key_path = normalize(key);
+
+    if key_path.starts_with(&resolved) {
+        return Err(ConfigError::ContainsKey {
+            path: path.as_ref().to_path_buf(),
+            key_path,
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Prepares `paths` given by the user (e.g. on the command line) to be managed: validates them as `ManagedPath`s
+/// against the home directory `home` and `roots`, checks them with `check_manageable` (against the repository
+/// `repo`, the configuration file `config` and the encryption key `key`), and removes duplicates.
+pub fn preprocess<P1, P2, P3, P4, P5>(
+    paths: &[P1],
+    home: P2,
+    roots: &[PathBuf],
+    repo: P3,
+    config: P4,
+    key: P5,
+) -> Result<Vec<ManagedPath>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+    P4: AsRef<Path>,
+    P5: AsRef<Path>,
+{
+    let mut preprocessed: Vec<ManagedPath> = vec![];
+
+    for path in paths {
+        let path = ManagedPath::new(path, &home, roots)?;
+
+        check_manageable(&path, &home, &repo, &config, &key)?;
+
+        if !preprocessed.contains(&path) {
+            preprocessed.push(path);
+        }
+    }
+
+    Ok(preprocessed)
+}
+
+/// Checks if `path` is a glob pattern, rather than a plain path.
+pub fn is_pattern<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    path.as_ref()
+        .to_string_lossy()
+        .contains(['*', '?', '[', '{'])
+}
+
+/// Selects the entries of `include` that `paths` (given by the user, e.g. on the command line) refer to. Plain paths
+/// are validated as `ManagedPath`s against the home directory `home` and `roots`, and are selected as-is. Glob patterns
+/// (see `is_pattern`) are resolved the same way, and select all entries they match, where `*` doesn't match `/`
+/// (but `**` does). The selected entries are in the order of `include`, followed by the plain paths that aren't in
+/// `include`.
+pub fn select<P1, P2>(
+    paths: &[P1],
+    include: &[PathBuf],
+    home: P2,
+    roots: &[PathBuf],
+) -> Result<Vec<PathBuf>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut patterns = GlobSetBuilder::new();
+    let mut plain = vec![];
+
+    for path in paths {
+        let path = ManagedPath::new(path, &home, roots)?.into_path_buf();
+
+        match is_pattern(&path) {
+            true => {
+                patterns.add(glob(&path.to_string_lossy())?);
+            }
+            false => plain.push(path),
+        }
+    }
+
+    let patterns = build(patterns)?;
+    let mut selected = include
+        .iter()
+        .filter(|entry| patterns.is_match(entry) || plain.contains(entry))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for path in plain {
+        if !selected.contains(&path) {
+            selected.push(path);
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Selects the entries of `include` that have a component (i.e. a file or folder name) matching the glob pattern
+/// `pattern`, e.g. `slack*` selects both `.config/slack` and `.local/share/slack-desktop`. This is for finding
+/// everything an application scattered across the home directory. Matching is case-sensitive.
+pub fn select_all_matching(pattern: &str, include: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut patterns = GlobSetBuilder::new();
+    patterns.add(glob(pattern)?);
+    let patterns = build(patterns)?;
+
+    Ok(include
+        .iter()
+        .filter(|entry| {
+            entry
+                .components()
+                .any(|component| patterns.is_match(component.as_os_str()))
+        })
+        .cloned()
+        .collect())
+}
+
+/// Compiles the glob `patterns` (e.g. from the configuration) into a set, where `*` doesn't match `/` (but `**`
+/// does).
+pub fn glob_set<S>(patterns: &[S]) -> Result<GlobSet>
+where
+    S: AsRef<str>,
+{
+    let mut set = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        set.add(glob(pattern.as_ref())?);
+    }
+
+    build(set)
+}
+
+/// Helper function to compile the glob `pattern`, where `*` doesn't match `/`.
+fn glob(pattern: &str) -> Result<Glob> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|err| {
+            ConfigError::InvalidPattern {
+                pattern: pattern.to_string(),
+                source: err,
+            }
+            .into()
+        })
+}
+
+/// Helper function to build the set of glob patterns `patterns`.
+fn build(patterns: GlobSetBuilder) -> Result<GlobSet> {
+    patterns.build().map_err(|err| {
+        ConfigError::InvalidPattern {
+            pattern: err.glob().unwrap_or_default().to_string(),
+            source: err,
+        }
+        .into()
+    })
+}
+
+/// Finds the new paths of the entries of `include` that were renamed by `renames`, which are `(old, new)` pairs of
+/// file paths (e.g. from `Repository::renames`). A file entry follows its own rename, and a folder entry follows
+/// the renames of the files inside of it, as long as they all agree on the folder's new path.
+///
+/// Returns the renamed entries as `(old, new)` pairs.
+pub fn follow_renames(
+    include: &[PathBuf],
+    renames: &[(PathBuf, PathBuf)],
+) -> Vec<(PathBuf, PathBuf)> {
+    include
+        .iter()
+        .filter_map(|entry| {
+            // The new path of the entry according to each rename inside of it. A file must keep its place inside
+            // of the folder, otherwise the folder wasn't just renamed.
+            let mut targets = renames.iter().filter_map(|(old, new)| {
+                let suffix = old.strip_prefix(entry).ok()?;
+
+                Some(match new.ends_with(suffix) {
+                    true => new.ancestors().nth(suffix.components().count()),
+                    false => None,
+                })
+            });
+
+            let target = targets.next()??;
+
+            targets
+                .all(|other| other == Some(target))
+                .then(|| (entry.clone(), target.to_path_buf()))
+        })
+        .collect()
+}
@@ -0,0 +1,447 @@
+#![cfg(test)]
+
+use super::*;
+use crate::errors::DotbakError;
+use assert_fs::TempDir;
+use proptest::prelude::*;
+
+/// The home directory used in the tests.
+const HOME: &str = "/home/user";
+
+/// Test that paths are resolved against the home directory.
+#[test]
+fn test_resolve_home() {
+    assert_eq!(
+        resolve_home(".zshrc", HOME),
+        PathBuf::from("/home/user/.zshrc")
+    );
+    assert_eq!(
+        resolve_home("~/.zshrc", HOME),
+        PathBuf::from("/home/user/.zshrc")
+    );
+    assert_eq!(resolve_home("~", HOME), PathBuf::from("/home/user"));
+    assert_eq!(
+        resolve_home("%USERPROFILE%/.gitconfig", HOME),
+        PathBuf::from("/home/user/.gitconfig")
+    );
+    assert_eq!(
+        resolve_home("/etc/hosts", HOME),
+        PathBuf::from("/etc/hosts")
+    );
+
+    // Only a leading `~` component is replaced.
+    assert_eq!(
+        resolve_home("~user", HOME),
+        PathBuf::from("/home/user/~user")
+    );
+}
+
+/// Test that `.` and `..` are normalized away.
+#[test]
+fn test_normalize() {
+    assert_eq!(
+        normalize("/home/user/./.zshrc"),
+        PathBuf::from("/home/user/.zshrc")
+    );
+    assert_eq!(
+        normalize("/home/user/.config/../.zshrc"),
+        PathBuf::from("/home/user/.zshrc")
+    );
+    assert_eq!(
+        normalize("/home/user/.config/"),
+        PathBuf::from("/home/user/.config")
+    );
+    assert_eq!(normalize("/../.."), PathBuf::from("/"));
+    assert_eq!(normalize("a/./b/../c"), PathBuf::from("a/c"));
+    assert_eq!(normalize(""), PathBuf::from(""));
+}
+
+/// Test that all the ways of writing a path in the home directory end up the same.
+#[test]
+fn test_relative_to_home() {
+    for path in [
+        ".zshrc",
+        "./.zshrc",
+        "~/.zshrc",
+        "/home/user/.zshrc",
+        "/home/user/./.zshrc",
+        ".config/../.zshrc",
+        "/home/other/../user/.zshrc",
+    ] {
+        assert_eq!(
+            relative_to_home(path, HOME).unwrap(),
+            PathBuf::from(".zshrc"),
+            "{}",
+            path
+        );
+    }
+
+    assert_eq!(
+        relative_to_home("~/.config/nvim/", HOME).unwrap(),
+        PathBuf::from(".config/nvim")
+    );
+
+    // The home directory doesn't need to be normalized either.
+    assert_eq!(
+        relative_to_home(".zshrc", "/home/./user/").unwrap(),
+        PathBuf::from(".zshrc")
+    );
+}
+
+/// Test that paths outside of the home directory (or the home directory itself) are rejected.
+#[test]
+fn test_relative_to_home_outside() {
+    for path in [
+        "/etc/hosts",
+        "../other/.zshrc",
+        "~/../other",
+        "/home/username",
+        "",
+        ".",
+        "~",
+        HOME,
+    ] {
+        assert!(
+            matches!(
+                relative_to_home(path, HOME),
+                Err(DotbakError::Config(ConfigError::OutsideHome { .. }))
+            ),
+            "{} should be rejected",
+            path
+        );
+    }
+}
+
+/// Tests that paths inside of the roots outside of the home directory are kept absolute, and paths inside of the
+/// home directory are still made relative to it.
+#[test]
+fn test_managed_path() {
+    let roots = [PathBuf::from("/etc"), PathBuf::from("/srv/www/")];
+    let managed = |path: &str| ManagedPath::new(path, HOME, &roots).map(PathBuf::from);
+
+    assert_eq!(managed("/etc/hosts").unwrap(), PathBuf::from("/etc/hosts"));
+    assert_eq!(
+        managed("/etc/nixos/../hosts").unwrap(),
+        PathBuf::from("/etc/hosts")
+    );
+    assert_eq!(
+        managed("/srv/www/index.html").unwrap(),
+        PathBuf::from("/srv/www/index.html")
+    );
+    assert_eq!(managed("~/.zshrc").unwrap(), PathBuf::from(".zshrc"));
+    assert!(ManagedPath::new("/etc/hosts", HOME, &roots)
+        .unwrap()
+        .is_in_root());
+    assert!(!ManagedPath::new(".zshrc", HOME, &roots)
+        .unwrap()
+        .is_in_root());
+
+    for path in ["/etc", "/etcetera/hosts", "/srv/other", "../other/.zshrc"] {
+        assert!(
+            matches!(
+                managed(path),
+                Err(DotbakError::Config(ConfigError::OutsideHome { .. }))
+            ),
+            "{} should be rejected",
+            path
+        );
+    }
+}
+
+/// Tests that paths through where a symlinked home directory points to are inside of it too.
+#[cfg(unix)]
+#[test]
+fn test_managed_path_symlinked_home() {
+    let dir = TempDir::new().unwrap();
+    let real = dir.path().join("data/user");
+    let home = dir.path().join("home/user");
+    fs::create_dir_all(&real).unwrap();
+    fs::create_dir_all(home.parent().unwrap()).unwrap();
+    std::os::unix::fs::symlink(&real, &home).unwrap();
+
+    // Temporary folders may be behind symlinks themselves (e.g. on macOS).
+    let real = fs::canonicalize(&real).unwrap();
+
+    assert_eq!(
+        ManagedPath::new(real.join(".zshrc"), &home, &[])
+            .unwrap()
+            .as_path(),
+        Path::new(".zshrc")
+    );
+    assert_eq!(
+        ManagedPath::new(home.join(".config/nvim/"), &home, &[])
+            .unwrap()
+            .as_path(),
+        Path::new(".config/nvim")
+    );
+    assert!(ManagedPath::new(&real, &home, &[]).is_err());
+    assert!(ManagedPath::new(real.join("../other"), &home, &[]).is_err());
+}
+
+/// Generates a relative path from the components of user input, including `.`, `..`, unicode and spaces, and
+/// sometimes a trailing slash.
+fn arb_relative_path() -> impl Strategy<Value = String> {
+    let component = prop_oneof![
+        1 => Just(".".to_string()),
+        2 => Just("..".to_string()),
+        6 => "[a-zA-Z0-9_. -]{1,8}",
+        2 => "[äöüßé漢字🦀]{1,4}",
+    ];
+
+    (prop::collection::vec(component, 1..6), any::<bool>()).prop_map(|(components, slash)| {
+        let mut path = components.join("/");
+
+        if slash {
+            path.push('/');
+        }
+
+        path
+    })
+}
+
+proptest! {
+    /// Any accepted path is normalized, inside of the home directory, and stands for the same file as the input.
+    #[test]
+    fn prop_managed_path_is_normalized(path in arb_relative_path()) {
+        if let Ok(managed) = ManagedPath::new(&path, HOME, &[]) {
+            prop_assert!(managed.is_relative());
+            prop_assert!(managed
+                .components()
+                .all(|component| matches!(component, Component::Normal(_))));
+            prop_assert_eq!(
+                Path::new(HOME).join(&managed),
+                normalize(Path::new(HOME).join(&path))
+            );
+
+            // Validating it again changes nothing.
+            prop_assert_eq!(&ManagedPath::new(&managed, HOME, &[]).unwrap(), &managed);
+        }
+    }
+
+    /// Paths are rejected exactly when they escape the home directory, or are the home directory itself.
+    #[test]
+    fn prop_managed_path_rejects_escapes(path in arb_relative_path()) {
+        let resolved = normalize(Path::new(HOME).join(&path));
+        let inside = resolved.starts_with(HOME) && resolved != Path::new(HOME);
+
+        prop_assert_eq!(ManagedPath::new(&path, HOME, &[]).is_ok(), inside, "{}", path);
+    }
+
+    /// The ways of writing the same path all give the same managed path.
+    #[test]
+    fn prop_managed_path_spellings(path in arb_relative_path()) {
+        let managed = ManagedPath::new(&path, HOME, &[]).ok();
+
+        for spelling in [
+            format!("~/{}", path),
+            format!("{}/{}", HOME, path),
+            format!("./{}", path),
+            format!("{}/", path),
+        ] {
+            let respelled = ManagedPath::new(&spelling, HOME, &[]).ok();
+
+            prop_assert_eq!(respelled, managed.clone(), "{}", spelling);
+        }
+    }
+
+    /// Paths inside of roots outside of the home directory stay absolute inside of their root.
+    #[test]
+    fn prop_managed_path_in_root(path in arb_relative_path()) {
+        let resolved = normalize(Path::new("/etc").join(&path));
+        let inside = resolved.starts_with("/etc") && resolved != Path::new("/etc");
+        let managed = ManagedPath::new(format!("/etc/{}", path), HOME, &[PathBuf::from("/etc")]);
+
+        prop_assert!(managed.as_ref().map_or(true, ManagedPath::is_in_root));
+        prop_assert_eq!(managed.ok().map(PathBuf::from), inside.then_some(resolved));
+    }
+}
+
+/// Tests that dotbak's repository, paths inside of it, ancestors of it or the configuration file, and the encryption
+/// key and its ancestors can't be managed.
+#[test]
+fn test_check_manageable() {
+    let repo = PathBuf::from(HOME).join(".dotbak/dotfiles");
+    let config = PathBuf::from(HOME).join(".dotbak/config.toml");
+    let key = PathBuf::from(HOME).join(".config/age/key.txt");
+    let check = |path: &str| check_manageable(path, HOME, &repo, &config, &key);
+
+    for inside in [
+        ".dotbak/dotfiles",
+        ".dotbak/dotfiles/.zshrc",
+        ".config/../.dotbak/dotfiles",
+    ] {
+        assert!(
+            matches!(
+                check(inside),
+                Err(DotbakError::Config(ConfigError::InsideRepo { .. }))
+            ),
+            "{} should be rejected",
+            inside
+        );
+    }
+
+    for ancestor in [".dotbak", ".", "", "../user", "/home"] {
+        assert!(
+            matches!(
+                check(ancestor),
+                Err(DotbakError::Config(ConfigError::ContainsDotbakPath { .. }))
+            ),
+            "{} should be rejected",
+            ancestor
+        );
+    }
+
+    for key in [".config/age/key.txt", ".config/age", ".config"] {
+        assert!(
+            matches!(
+                check(key),
+                Err(DotbakError::Config(ConfigError::ContainsKey { .. }))
+            ),
+            "{} should be rejected",
+            key
+        );
+    }
+
+    assert!(check(".dotbak/config.toml").is_ok());
+    assert!(check(".config/age/keys.txt").is_ok());
+    assert!(check(".zshrc").is_ok());
+    assert!(check(".dotbakrc").is_ok());
+    assert!(check(".config/nvim").is_ok());
+}
+
+/// Test that user-given paths are made relative to the home directory, checked, and deduplicated.
+#[test]
+fn test_preprocess() {
+    let repo = PathBuf::from(HOME).join(".dotbak/dotfiles");
+    let config = PathBuf::from(HOME).join(".dotbak/config.toml");
+    let key = PathBuf::from(HOME).join(".dotbak/key.txt");
+
+    assert_eq!(
+        preprocess(
+            &[
+                "~/.zshrc",
+                ".config/nvim",
+                "/home/user/.zshrc",
+                "./.dotbak/config.toml"
+            ],
+            HOME,
+            &[],
+            &repo,
+            &config,
+            &key
+        )
+        .unwrap()
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>(),
+        vec![
+            PathBuf::from(".zshrc"),
+            PathBuf::from(".config/nvim"),
+            PathBuf::from(".dotbak/config.toml"),
+        ]
+    );
+
+    assert!(matches!(
+        preprocess(&[".zshrc", "/etc/hosts"], HOME, &[], &repo, &config, &key),
+        Err(DotbakError::Config(ConfigError::OutsideHome { .. }))
+    ));
+    assert!(matches!(
+        preprocess(&[".zshrc", "~/.dotbak"], HOME, &[], &repo, &config, &key),
+        Err(DotbakError::Config(ConfigError::ContainsDotbakPath { .. }))
+    ));
+}
+
+/// Test that included files and folders follow renames of the files in them.
+#[test]
+fn test_follow_renames() {
+    let include = [".zshrc", ".config/nvim", ".config/fish", ".vimrc"].map(PathBuf::from);
+    let renames = [
+        (".zshrc", ".config/zsh/.zshrc"),
+        (".config/nvim/init.lua", ".config/neovim/init.lua"),
+        (
+            ".config/nvim/lua/plugins.lua",
+            ".config/neovim/lua/plugins.lua",
+        ),
+        // Files moving out of a folder in different ways don't rename the folder.
+        (".config/fish/config.fish", ".config/fish2/config.fish"),
+        (".config/fish/fish_plugins", ".fish_plugins"),
+    ]
+    .map(|(old, new)| (PathBuf::from(old), PathBuf::from(new)));
+
+    assert_eq!(
+        follow_renames(&include, &renames),
+        vec![
+            (PathBuf::from(".zshrc"), PathBuf::from(".config/zsh/.zshrc")),
+            (
+                PathBuf::from(".config/nvim"),
+                PathBuf::from(".config/neovim")
+            ),
+        ]
+    );
+}
+
+/// Test that patterns select the entries they match, and plain paths select themselves.
+#[test]
+fn test_select() {
+    let include = [
+        ".zshrc",
+        ".config/slack",
+        ".config/slack-theme.json",
+        ".config/nvim",
+        ".config/nvim/lua",
+    ]
+    .map(PathBuf::from);
+
+    assert_eq!(
+        select(
+            &["~/.config/slack*", ".zshrc", ".bashrc"],
+            &include,
+            HOME,
+            &[]
+        )
+        .unwrap(),
+        [
+            ".zshrc",
+            ".config/slack",
+            ".config/slack-theme.json",
+            ".bashrc"
+        ]
+        .map(PathBuf::from)
+    );
+
+    // `*` doesn't cross folders, but `**` does.
+    assert_eq!(
+        select(&[".config/*"], &include, HOME, &[]).unwrap(),
+        [".config/slack", ".config/slack-theme.json", ".config/nvim"].map(PathBuf::from)
+    );
+    assert_eq!(
+        select(&["**/lua"], &include, HOME, &[]).unwrap(),
+        [".config/nvim/lua"].map(PathBuf::from)
+    );
+
+    assert!(matches!(
+        select(&[".config/[slack"], &include, HOME, &[]),
+        Err(DotbakError::Config(ConfigError::InvalidPattern { .. }))
+    ));
+}
+
+/// Test that `--all-matching` patterns match any component of the entries.
+#[test]
+fn test_select_all_matching() {
+    let include = [
+        ".config/slack",
+        ".local/share/slack-desktop",
+        ".slackrc",
+        ".zshrc",
+    ]
+    .map(PathBuf::from);
+
+    assert_eq!(
+        select_all_matching("slack*", &include).unwrap(),
+        [".config/slack", ".local/share/slack-desktop"].map(PathBuf::from)
+    );
+    assert_eq!(
+        select_all_matching("*slack*", &include).unwrap(),
+        [".config/slack", ".local/share/slack-desktop", ".slackrc"].map(PathBuf::from)
+    );
+}
@@ -0,0 +1,464 @@
+mod tests;
+
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    fs::{self, Metadata, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+#[cfg(any(test, feature = "testing"))]
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(unix)]
+use std::os::unix::{
+    ffi::{OsStrExt, OsStringExt},
+    fs::{MetadataExt, OpenOptionsExt, PermissionsExt},
+};
+
+/// The name of the file created when probing whether symlinks can be created. See `can_symlink_files`.
+const PROBE_FILE_NAME: &str = ".dotbak-symlink-probe";
+
+/// Creates a symlink at `link` pointing to `target`. On Windows, symlinks to folders fall back to junctions when
+/// creating symlinks isn't allowed (it needs Developer Mode or admin rights), as junctions don't need them. There is
+/// no such fallback for files, see `can_symlink_files`.
+pub fn symlink<P1, P2>(target: P1, link: P2) -> io::Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::{symlink_dir, symlink_file};
+
+        let (target, link) = (target.as_ref(), link.as_ref());
+
+        if !target.is_dir() {
+            return symlink_file(target, link);
+        }
+
+        match symlink_dir(target, link) {
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => junction(target, link),
+            result => result,
+        }
+    }
+}
+
+/// Checks if symlinks to files can be created in `dir`. This is always the case on Unix, but on Windows it needs
+/// Developer Mode or admin rights. Without them, `dotbak` copies managed files into the home directory instead, and
+/// copies changes between the two when syncing.
+pub fn can_symlink_files<P>(dir: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    if cfg!(unix) {
+        return true;
+    }
+
+    let (target, link) = (
+        dir.as_ref().join(PROBE_FILE_NAME),
+        dir.as_ref().join(format!("{}-link", PROBE_FILE_NAME)),
+    );
+
+    let can_symlink = fs::write(&target, "").is_ok() && symlink(&target, &link).is_ok();
+
+    let _ = fs::remove_file(&link);
+    let _ = fs::remove_file(&target);
+
+    can_symlink
+}
+
+/// Removes the symlink (or junction) at `link`, leaving what it points to alone. On Windows, links to folders are
+/// removed like folders.
+pub fn remove_link<P>(link: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let link = link.as_ref();
+
+    if cfg!(windows) && link.is_dir() {
+        fs::remove_dir(link)
+    } else {
+        fs::remove_file(link)
+    }
+}
+
+/// Where `delete` moves files/folders to instead of deleting them permanently. `SystemTrash` is the system trash, and
+/// `RecordingTrash` (with the `testing` feature) one for tests.
+pub trait Trash: Send + Sync {
+    /// Moves the file/folder at `path` to the trash.
+    fn trash(&self, path: &Path) -> io::Result<()>;
+}
+
+/// The system trash (the Recycle Bin on Windows), which what's moved to it can be restored from. This is where
+/// `dotbak` deletes files/folders to unless it's given another trash.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemTrash;
+
+impl Trash for SystemTrash {
+    fn trash(&self, path: &Path) -> io::Result<()> {
+        trash::delete(path).map_err(io::Error::other)
+    }
+}
+
+/// A trash for tests, which records the paths moved to it and deletes them permanently, so that tests never touch the
+/// system trash.
+#[cfg(any(test, feature = "testing"))]
+#[derive(Debug, Default)]
+pub struct RecordingTrash {
+    /// The paths moved to the trash, in order.
+    paths: Mutex<Vec<PathBuf>>,
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl RecordingTrash {
+    /// Creates an empty trash.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the paths moved to the trash so far, in order.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.paths
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Trash for RecordingTrash {
+    fn trash(&self, path: &Path) -> io::Result<()> {
+        self.paths
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(path.to_path_buf());
+
+        delete(path, None)
+    }
+}
+
+/// Deletes the file/folder at `path` by moving it to `trash`, so that it can be restored from there, or deletes it
+/// permanently if there is no `trash`.
+pub fn delete<P>(path: P, trash: Option<&dyn Trash>) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+
+    if let Some(trash) = trash {
+        return trash.trash(path);
+    }
+
+    if path.is_symlink() {
+        remove_link(path)
+    } else if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Gets the number of hard links to the file with the metadata `meta`. This is always 1 on Windows, where it can't be
+/// read without opening the file.
+pub fn hard_links(meta: &Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        meta.nlink()
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = meta;
+        1
+    }
+}
+
+/// Checks if the file with the metadata `meta` is executable. On Windows, every file is.
+pub fn is_executable(meta: &Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        meta.is_file() && meta.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(windows)]
+    {
+        meta.is_file()
+    }
+}
+
+/// Gets the Unix permission bits (e.g. `0o644`) of the file with the metadata `meta`. This is `None` on Windows,
+/// which has no such bits.
+pub fn permission_bits(meta: &Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        Some(meta.permissions().mode() & 0o777)
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// Sets the Unix permission bits (e.g. `0o600`) of the file/folder at `path`. This does nothing on Windows, which has
+/// no such bits.
+pub fn set_permission_bits<P>(path: P, mode: u32) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// Makes the file at `path` executable by everyone. This does nothing on Windows, where every file is executable.
+pub fn set_executable<P>(path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// Gives the file/folder at `path` (and everything inside of it) that is owned by root back to the user running
+/// `dotbak` with `sudo` (see `sudo_user`), so that what `dotbak` writes into their repository or state folder while
+/// managing files outside of the home directory stays theirs. Symlinks themselves are given back, but not followed.
+/// This does nothing without `sudo`, and on Windows.
+pub fn give_back_to_sudo_user<P>(path: P) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        let Some((uid, gid)) = sudo_user() else {
+            return Ok(());
+        };
+
+        let path = path.as_ref();
+        let meta = match fs::symlink_metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            meta => meta?,
+        };
+
+        if meta.uid() == 0 {
+            std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+        }
+
+        if meta.is_dir() {
+            for entry in fs::read_dir(path)? {
+                give_back_to_sudo_user(entry?.path())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// Gets the user and group ID of the owner of the file/folder at `path`, or `None` if it can't be read, and on
+/// Windows.
+pub fn owner<P>(path: P) -> Option<(u32, u32)>
+where
+    P: AsRef<Path>,
+{
+    #[cfg(unix)]
+    {
+        fs::metadata(path).ok().map(|meta| (meta.uid(), meta.gid()))
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Gets the user and group ID of the user running `dotbak` with `sudo`, from the `SUDO_UID` and `SUDO_GID`
+/// environment variables `sudo` sets, or `None` if `dotbak` isn't run with `sudo` (or by root itself).
+pub fn sudo_user() -> Option<(u32, u32)> {
+    let id = |var| std::env::var(var).ok()?.parse::<u32>().ok();
+
+    Some((id("SUDO_UID")?, id("SUDO_GID")?)).filter(|(uid, _)| *uid != 0)
+}
+
+/// Makes `options` create files readable only by the user. On Windows, files get the permissions of their folder,
+/// which is only readable by the user inside of the home directory.
+pub fn private(options: &mut OpenOptions) -> &mut OpenOptions {
+    #[cfg(unix)]
+    {
+        options.mode(0o600)
+    }
+
+    #[cfg(windows)]
+    {
+        options
+    }
+}
+
+/// Converts `bytes` (e.g. a path in the output of git) to an `OsString`. On Windows, they have to be UTF-8, as git
+/// writes paths as UTF-8 there, so invalid bytes are replaced.
+pub fn os_string_from_bytes(bytes: &[u8]) -> OsString {
+    #[cfg(unix)]
+    {
+        OsString::from_vec(bytes.to_vec())
+    }
+
+    #[cfg(windows)]
+    {
+        OsString::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+}
+
+/// Gets the bytes of `string` (e.g. a path passed to git). On Windows, these are its UTF-8 bytes.
+pub fn os_str_bytes(string: &OsStr) -> Cow<'_, [u8]> {
+    #[cfg(unix)]
+    {
+        Cow::Borrowed(string.as_bytes())
+    }
+
+    #[cfg(windows)]
+    {
+        match string.to_string_lossy() {
+            Cow::Borrowed(string) => Cow::Borrowed(string.as_bytes()),
+            Cow::Owned(string) => Cow::Owned(string.into_bytes()),
+        }
+    }
+}
+
+/// Gets the folder for files which don't outlive the machine's uptime, like the daemon's PID file. This is `/tmp` on
+/// Unix, so that every `dotbak` finds the daemon regardless of `TMPDIR`.
+pub fn temp_dir() -> PathBuf {
+    if cfg!(unix) {
+        PathBuf::from("/tmp")
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Creates the command checking if the process `pid` is running. It succeeds if it is.
+pub fn process_exists_command(pid: u32) -> Command {
+    #[cfg(unix)]
+    {
+        // `kill -0` doesn't send a signal, it only checks if the process exists.
+        let mut command = Command::new("kill");
+        command.args(["-0", &pid.to_string()]);
+        command
+    }
+
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            &format!("Get-Process -Id {}", pid),
+        ]);
+        command
+    }
+}
+
+/// Creates the command stopping the process `pid`.
+pub fn terminate_command(pid: &str) -> Command {
+    #[cfg(unix)]
+    {
+        let mut command = Command::new("kill");
+        command.arg(pid);
+        command
+    }
+
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("taskkill");
+        command.args(["/PID", pid, "/F"]);
+        command
+    }
+}
+
+/// Makes the process `command` starts lead a process group of its own on Unix, so that `kill_group` kills the
+/// processes it starts in turn (like `ssh` for git) with it. They can't read from the terminal then, as they're in the
+/// background. On Windows, `kill_group` finds them in the process tree instead.
+pub fn own_process_group(command: &mut Command) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+
+        command.process_group(0);
+    }
+
+    #[cfg(windows)]
+    {
+        let _ = command;
+    }
+}
+
+/// Kills the process `child` and the processes it started, and waits for it to exit. On Unix, `child` has to lead its
+/// own process group (see `own_process_group`).
+pub fn kill_group(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    let status = Command::new("kill")
+        .args(["-KILL", "--", &format!("-{}", child.id())])
+        .stderr(Stdio::null())
+        .status();
+
+    #[cfg(windows)]
+    let status = Command::new("taskkill")
+        .args(["/PID", &child.id().to_string(), "/T", "/F"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    // Without `kill` (or if the group is gone already), at least `child` itself is killed.
+    if !status.is_ok_and(|status| status.success()) {
+        let _ = child.kill();
+    }
+
+    child.wait().map(|_| ())
+}
+
+/// Creates a junction at `link` pointing to the folder `target`, with `mklink`.
+#[cfg(windows)]
+fn junction(target: &Path, link: &Path) -> io::Result<()> {
+    let output = Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(target)
+        .output()?;
+
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
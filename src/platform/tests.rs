@@ -0,0 +1,77 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::{prelude::*, TempDir};
+
+/// Test that symlinks to files and folders can be created and removed, leaving what they point to alone.
+#[test]
+fn test_symlink() {
+    let dir = TempDir::new().unwrap();
+    dir.child("file").write_str("file").unwrap();
+    dir.child("folder/file").write_str("file").unwrap();
+
+    symlink(dir.child("file"), dir.child("file-link")).unwrap();
+    symlink(dir.child("folder"), dir.child("folder-link")).unwrap();
+
+    dir.child("file-link").assert("file");
+    dir.child("folder-link/file").assert("file");
+
+    remove_link(dir.child("file-link")).unwrap();
+    remove_link(dir.child("folder-link")).unwrap();
+
+    assert!(!dir.child("file-link").exists());
+    assert!(!dir.child("folder-link").exists());
+    dir.child("file").assert("file");
+    dir.child("folder/file").assert("file");
+    assert!(can_symlink_files(dir.path()));
+}
+
+/// Test that files and folders are deleted, but not what symlinks point to.
+#[test]
+fn test_delete() {
+    let dir = TempDir::new().unwrap();
+    dir.child("file").write_str("file").unwrap();
+    dir.child("folder/file").write_str("file").unwrap();
+    symlink(dir.child("folder"), dir.child("folder-link")).unwrap();
+
+    delete(dir.child("folder-link"), None).unwrap();
+
+    assert!(!dir.child("folder-link").exists());
+    dir.child("folder/file").assert("file");
+
+    delete(dir.child("file"), None).unwrap();
+    delete(dir.child("folder"), None).unwrap();
+
+    assert!(!dir.child("file").exists());
+    assert!(!dir.child("folder").exists());
+}
+
+/// Test that deleting moves files/folders to the trash, unless they're deleted permanently.
+#[test]
+fn test_delete_to_trash() {
+    let dir = TempDir::new().unwrap();
+    let trash = RecordingTrash::new();
+    dir.child("file").write_str("file").unwrap();
+    dir.child("folder/file").write_str("file").unwrap();
+
+    delete(dir.child("file"), Some(&trash)).unwrap();
+
+    assert!(!dir.child("file").exists());
+    assert_eq!(trash.paths(), vec![dir.child("file").to_path_buf()]);
+
+    delete(dir.child("folder"), None).unwrap();
+
+    assert!(!dir.child("folder").exists());
+    assert_eq!(trash.paths(), vec![dir.child("file").to_path_buf()]);
+}
+
+/// Test that the bytes of paths survive the round trip through `OsString`s.
+#[test]
+fn test_os_str_bytes() {
+    let bytes = b"some/path with spaces/\xc3\xa9";
+
+    assert_eq!(
+        os_str_bytes(&os_string_from_bytes(bytes)).as_ref(),
+        bytes.as_slice()
+    );
+}
@@ -0,0 +1,106 @@
+mod tests;
+
+use crate::git::Commit;
+use std::path::{Path, PathBuf};
+
+/// A lock file written by a plugin manager, pinning the versions of the installed plugins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LockFile {
+    /// The name of the plugin manager.
+    pub manager: &'static str,
+
+    /// The path to the lock file, relative to the home directory.
+    pub path: &'static str,
+}
+
+/// The lock files of well-known plugin managers. Only these are managed in `track_locks` mode, not the plugins
+/// themselves, as plugin managers can install those again from the lock files.
+pub const REGISTRY: &[LockFile] = &[
+    // Neovim
+    LockFile {
+        manager: "lazy.nvim",
+        path: ".config/nvim/lazy-lock.json",
+    },
+    LockFile {
+        manager: "mini.deps",
+        path: ".config/nvim/mini-deps-snap",
+    },
+    LockFile {
+        manager: "rocks.nvim",
+        path: ".config/nvim/rocks.toml",
+    },
+    // Shells
+    LockFile {
+        manager: "sheldon",
+        path: ".local/share/sheldon/plugins.lock",
+    },
+    LockFile {
+        manager: "fisher",
+        path: ".config/fish/fish_plugins",
+    },
+    // Emacs
+    LockFile {
+        manager: "straight.el",
+        path: ".emacs.d/straight/versions/default.el",
+    },
+    LockFile {
+        manager: "straight.el",
+        path: ".config/emacs/straight/versions/default.el",
+    },
+];
+
+/// Finds the lock files in the registry that exist in the home directory `home`.
+pub fn scan<P>(home: P) -> Vec<LockFile>
+where
+    P: AsRef<Path>,
+{
+    REGISTRY
+        .iter()
+        .filter(|lock| home.as_ref().join(lock.path).is_file())
+        .copied()
+        .collect()
+}
+
+/// Finds the lock files in `found` that aren't covered by the managed paths in `include` yet, either directly or
+/// through a managed folder containing them.
+pub fn untracked(found: &[LockFile], include: &[PathBuf]) -> Vec<LockFile> {
+    found
+        .iter()
+        .filter(|lock| {
+            !include
+                .iter()
+                .any(|managed| Path::new(lock.path).starts_with(managed))
+        })
+        .copied()
+        .collect()
+}
+
+/// How a managed lock file compares across machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LockStatus {
+    /// The lock file.
+    pub lock: LockFile,
+
+    /// Whether the lock file changed on this machine since the last commit.
+    pub changed_locally: bool,
+
+    /// Whether the lock file differs between this machine's last commit and the remote, i.e. whether another
+    /// machine updated its plugins (or this machine hasn't pushed its updates yet).
+    pub differs_from_remote: bool,
+
+    /// The last commit that changed the lock file, if any.
+    pub last_change: Option<Commit>,
+}
+
+/// Public API for `LockStatus`.
+impl LockStatus {
+    /// Describes the status in a few words.
+    pub fn describe(&self) -> &'static str {
+        match (self.changed_locally, self.differs_from_remote) {
+            (false, false) => "in sync",
+            (true, false) => "changed on this machine",
+            (false, true) => "differs from the remote",
+            (true, true) => "changed on this machine, and differs from the remote",
+        }
+    }
+}
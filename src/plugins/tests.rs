@@ -0,0 +1,56 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::{prelude::*, TempDir};
+
+/// Test that only existing lock files are found.
+#[test]
+fn test_scan() {
+    let home = TempDir::new().unwrap();
+
+    home.child(".config/nvim/lazy-lock.json")
+        .write_str("{}")
+        .unwrap();
+    home.child(".config/fish/fish_plugins")
+        .write_str("jorgebucaran/fisher")
+        .unwrap();
+    home.child(".config/nvim/rocks.toml")
+        .create_dir_all()
+        .unwrap(); // Not a file.
+
+    assert_eq!(
+        scan(home.path())
+            .iter()
+            .map(|lock| lock.manager)
+            .collect::<Vec<_>>(),
+        vec!["lazy.nvim", "fisher"]
+    );
+}
+
+/// Test that lock files in managed folders are already tracked.
+#[test]
+fn test_untracked() {
+    let found = [REGISTRY[0], REGISTRY[4]];
+
+    assert_eq!(untracked(&found, &[]), found.to_vec());
+    assert_eq!(
+        untracked(&found, &[".config/nvim".into()]),
+        vec![REGISTRY[4]]
+    );
+    assert_eq!(
+        untracked(
+            &found,
+            &[".config/fish/fish_plugins".into(), ".config".into()]
+        ),
+        vec![]
+    );
+}
+
+/// Test that every lock file in the registry is relative to the home directory, and listed once.
+#[test]
+fn test_registry() {
+    for (i, lock) in REGISTRY.iter().enumerate() {
+        assert!(Path::new(lock.path).is_relative());
+        assert!(!REGISTRY[..i].iter().any(|other| other.path == lock.path));
+    }
+}
@@ -0,0 +1,292 @@
+mod tests;
+
+use crate::{
+    errors::{io::IoError, Result},
+    files::compare,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File names of shell configuration files, where the home directory is written as `$HOME`, as `~` isn't expanded
+/// inside of quotes.
+const SHELL_FILES: &[&str] = &[
+    ".bashrc",
+    ".bash_profile",
+    ".bash_login",
+    ".bash_logout",
+    ".bash_aliases",
+    ".profile",
+    ".zshrc",
+    ".zshenv",
+    ".zprofile",
+    ".zlogin",
+    ".zlogout",
+    ".xinitrc",
+    ".xprofile",
+];
+
+/// File extensions of shell scripts. See `SHELL_FILES`.
+const SHELL_EXTENSIONS: &[&str] = &["sh", "bash", "zsh", "fish", "ksh"];
+
+/// File extensions of PowerShell scripts (like the `$PROFILE`), where the home directory is written as
+/// `$env:USERPROFILE`.
+const POWERSHELL_EXTENSIONS: &[&str] = &["ps1", "psm1"];
+
+/// File extensions of Windows batch files, where the home directory is written as `%USERPROFILE%`.
+const BATCH_EXTENSIONS: &[&str] = &["bat", "cmd"];
+
+/// How a hard-coded path to the home directory is rewritten, so that it works on machines with a different home
+/// directory (e.g. a different user name).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HomeForm {
+    /// `$HOME/...`, for shell configuration files.
+    Env,
+
+    /// `$env:USERPROFILE/...`, for PowerShell scripts.
+    PowerShell,
+
+    /// `%USERPROFILE%/...`, for Windows batch files.
+    Batch,
+
+    /// `~/...`, for everything else.
+    Tilde,
+}
+
+impl HomeForm {
+    /// Picks the form for the file at `path`, based on its name.
+    pub fn for_file<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let name = path.file_name().and_then(|name| name.to_str());
+        let ext = path.extension().and_then(|ext| ext.to_str());
+
+        match ext {
+            Some(ext) if POWERSHELL_EXTENSIONS.contains(&ext) => HomeForm::PowerShell,
+            Some(ext) if BATCH_EXTENSIONS.contains(&ext) => HomeForm::Batch,
+            Some(ext) if SHELL_EXTENSIONS.contains(&ext) => HomeForm::Env,
+            _ if name.is_some_and(|name| SHELL_FILES.contains(&name)) => HomeForm::Env,
+            _ => HomeForm::Tilde,
+        }
+    }
+
+    /// What the home directory is replaced with.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HomeForm::Env => "$HOME",
+            HomeForm::PowerShell => "$env:USERPROFILE",
+            HomeForm::Batch => "%USERPROFILE%",
+            HomeForm::Tilde => "~",
+        }
+    }
+}
+
+/// A line in a managed file containing a hard-coded path to the home directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HardcodedPath {
+    /// The path to the file, relative to the home directory.
+    pub path: PathBuf,
+
+    /// The (1-based) line number.
+    pub line: usize,
+
+    /// The contents of the line.
+    pub text: String,
+}
+
+/// Finds the lines of `contents` containing hard-coded paths to the home directory `home`. Returns the (1-based)
+/// line numbers with the lines.
+pub fn find_home_paths<P>(contents: &str, home: P) -> Vec<(usize, &str)>
+where
+    P: AsRef<Path>,
+{
+    let Some(home) = home_str(home.as_ref()) else {
+        return vec![];
+    };
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !home_matches(line, home).is_empty())
+        .map(|(index, line)| (index + 1, line))
+        .collect()
+}
+
+/// Replaces the hard-coded paths to the home directory `home` in `contents` with `form`. Returns the new contents
+/// and how many paths were replaced.
+pub fn rewrite_home<P>(contents: &str, home: P, form: HomeForm) -> (String, usize)
+where
+    P: AsRef<Path>,
+{
+    let Some(home) = home_str(home.as_ref()) else {
+        return (contents.to_string(), 0);
+    };
+
+    let matches = home_matches(contents, home);
+    let mut rewritten = String::with_capacity(contents.len());
+    let mut last = 0;
+
+    for &start in &matches {
+        rewritten.push_str(&contents[last..start]);
+        rewritten.push_str(form.as_str());
+        last = start + home.len();
+    }
+
+    rewritten.push_str(&contents[last..]);
+
+    (rewritten, matches.len())
+}
+
+/// Finds the hard-coded paths to the home directory `home` in the managed `files` (and the files inside of managed
+/// folders). Files larger than `limit` bytes and binary files are skipped, as are files that aren't UTF-8.
+///
+/// `files` are the paths to the files/folders in `dir`. These paths must be relative to `dir`, and so are the
+/// returned paths.
+pub fn scan<P1, P2, P3>(files: &[P1], dir: P2, home: P3, limit: u64) -> Result<Vec<HardcodedPath>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let mut found = vec![];
+
+    for file in text_files(files, dir.as_ref(), limit)? {
+        let Some(contents) = read_text(&dir.as_ref().join(&file))? else {
+            continue;
+        };
+
+        found.extend(
+            find_home_paths(&contents, home.as_ref())
+                .into_iter()
+                .map(|(line, text)| HardcodedPath {
+                    path: file.clone(),
+                    line,
+                    text: text.to_string(),
+                }),
+        );
+    }
+
+    Ok(found)
+}
+
+/// Rewrites the hard-coded paths to the home directory `home` in the managed `files` (and the files inside of
+/// managed folders), picking the form for each file with `HomeForm::for_file`. Skips the same files as `scan`.
+/// Returns the rewritten files, and how many paths were rewritten in each.
+///
+/// `files` are the paths to the files/folders in `dir`. These paths must be relative to `dir`, and so are the
+/// returned paths.
+pub fn rewrite_files<P1, P2, P3>(
+    files: &[P1],
+    dir: P2,
+    home: P3,
+    limit: u64,
+) -> Result<Vec<(PathBuf, usize)>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    P3: AsRef<Path>,
+{
+    let mut rewritten_files = vec![];
+
+    for file in text_files(files, dir.as_ref(), limit)? {
+        let path = dir.as_ref().join(&file);
+        let Some(contents) = read_text(&path)? else {
+            continue;
+        };
+
+        let (rewritten, count) = rewrite_home(&contents, home.as_ref(), HomeForm::for_file(&file));
+
+        if count == 0 {
+            continue;
+        }
+
+        fs::write(&path, rewritten).map_err(|err| IoError::Write {
+            source: err,
+            path: path.clone(),
+        })?;
+
+        rewritten_files.push((file, count));
+    }
+
+    Ok(rewritten_files)
+}
+
+/// Helper function to get the home directory as a string to search for. Returns `None` if it can't be searched
+/// for, i.e. it isn't UTF-8 or it's the root directory, which every absolute path starts with.
+fn home_str(home: &Path) -> Option<&str> {
+    home.to_str()
+        .map(|home| home.trim_end_matches(['/', '\\']))
+        .filter(|home| !home.is_empty())
+}
+
+/// Helper function to find where the hard-coded paths to `home` start in `contents`. Paths that merely contain
+/// `home` (like `/mnt/home/user`) or start with it (like `/home/username` for `/home/user`) aren't hard-coded
+/// paths to the home directory.
+fn home_matches(contents: &str, home: &str) -> Vec<usize> {
+    let is_path_char = |c: char| c.is_alphanumeric() || matches!(c, '_' | '-' | '.');
+
+    contents
+        .match_indices(home)
+        .map(|(start, _)| start)
+        .filter(|&start| {
+            let before = contents[..start].chars().next_back();
+            let after = contents[start + home.len()..].chars().next();
+
+            !before.is_some_and(|c| is_path_char(c) || matches!(c, '/' | '\\' | '~' | '$'))
+                && !after.is_some_and(is_path_char)
+        })
+        .collect()
+}
+
+/// Helper function to list the regular files among `files`, and inside of the folders among them, which aren't
+/// large or binary (see `compare::is_large_or_binary`). Symlinks and nested git repositories are skipped.
+pub(crate) fn text_files<P>(files: &[P], dir: &Path, limit: u64) -> Result<Vec<PathBuf>>
+where
+    P: AsRef<Path>,
+{
+    let mut found = vec![];
+
+    for file in files {
+        let path = dir.join(file);
+
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if meta.is_dir() => {
+                let entries = fs::read_dir(&path)
+                    .map_err(|err| IoError::Read {
+                        source: err,
+                        path: path.clone(),
+                    })?
+                    .flatten()
+                    .filter(|entry| entry.file_name() != ".git")
+                    .map(|entry| file.as_ref().join(entry.file_name()))
+                    .collect::<Vec<_>>();
+
+                found.extend(text_files(&entries, dir, limit)?);
+            }
+            Ok(meta) if meta.is_file() && !compare::is_large_or_binary(&path, limit)? => {
+                found.push(file.as_ref().to_path_buf());
+            }
+            _ => (),
+        }
+    }
+
+    found.sort();
+
+    Ok(found)
+}
+
+/// Helper function to read the file at `path` as text. Returns `None` if it isn't UTF-8.
+pub(crate) fn read_text(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(err) if err.kind() == std::io::ErrorKind::InvalidData => Ok(None),
+        Err(err) => Err(IoError::Read {
+            source: err,
+            path: path.to_path_buf(),
+        }
+        .into()),
+    }
+}
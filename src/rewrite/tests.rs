@@ -0,0 +1,130 @@
+#![cfg(test)]
+
+use super::*;
+
+/// Test that only paths to the home directory itself are found, not paths that merely look like them.
+#[test]
+fn test_find_home_paths() {
+    let contents = "source /home/alice/.zsh/aliases.zsh\n\
+                    export PATH=/home/alice2/bin:$PATH\n\
+                    ls /mnt/home/alice\n\
+                    cd \"/home/alice\"\n\
+                    echo ~/.zshrc\n";
+
+    assert_eq!(
+        find_home_paths(contents, "/home/alice"),
+        vec![
+            (1, "source /home/alice/.zsh/aliases.zsh"),
+            (4, "cd \"/home/alice\""),
+        ]
+    );
+    assert!(find_home_paths(contents, "/").is_empty());
+}
+
+/// Test that hard-coded paths are rewritten in the form for the file.
+#[test]
+fn test_rewrite_home() {
+    let contents = "source /home/alice/.zsh/aliases.zsh # not /home/alice2\nPATH=/home/alice/bin:/home/alice/.cargo/bin";
+
+    assert_eq!(
+        rewrite_home(contents, "/home/alice", HomeForm::for_file(".zshrc")),
+        (
+            "source $HOME/.zsh/aliases.zsh # not /home/alice2\nPATH=$HOME/bin:$HOME/.cargo/bin"
+                .to_string(),
+            3
+        )
+    );
+    assert_eq!(
+        rewrite_home(
+            "path = /home/alice/",
+            "/home/alice/",
+            HomeForm::for_file(".gitconfig")
+        ),
+        ("path = ~/".to_string(), 1)
+    );
+}
+
+/// Test that Windows home directories are rewritten to the variable of the script's language.
+#[test]
+fn test_rewrite_userprofile() {
+    let home = "C:\\Users\\alice";
+
+    assert_eq!(
+        rewrite_home(
+            "cd C:\\Users\\alice\\src",
+            home,
+            HomeForm::for_file("Documents/PowerShell/profile.ps1")
+        ),
+        ("cd $env:USERPROFILE\\src".to_string(), 1)
+    );
+    assert_eq!(
+        rewrite_home(
+            "set PATH=C:\\Users\\alice\\bin;D:\\C:\\Users\\alice",
+            home,
+            HomeForm::for_file("init.cmd")
+        ),
+        (
+            "set PATH=%USERPROFILE%\\bin;D:\\C:\\Users\\alice".to_string(),
+            1
+        )
+    );
+}
+
+/// Test that rewriting managed files looks inside of folders, but leaves binary files alone.
+#[test]
+fn test_rewrite_files() {
+    let dir = assert_fs::TempDir::new().unwrap();
+    let home = Path::new("/home/alice");
+
+    fs::create_dir_all(dir.join(".config/fish")).unwrap();
+    fs::write(
+        dir.join(".config/fish/config.fish"),
+        "source /home/alice/.aliases",
+    )
+    .unwrap();
+    fs::write(dir.join(".config/fish/blob"), b"/home/alice\0").unwrap();
+    fs::write(
+        dir.join(".gitconfig"),
+        "[core]\n\texcludesfile = /home/alice/.gitignore",
+    )
+    .unwrap();
+
+    let files = [".config/fish", ".gitconfig"];
+
+    assert_eq!(
+        scan(&files, &dir, home, u64::MAX).unwrap(),
+        vec![
+            HardcodedPath {
+                path: PathBuf::from(".config/fish/config.fish"),
+                line: 1,
+                text: "source /home/alice/.aliases".to_string(),
+            },
+            HardcodedPath {
+                path: PathBuf::from(".gitconfig"),
+                line: 2,
+                text: "\texcludesfile = /home/alice/.gitignore".to_string(),
+            },
+        ]
+    );
+
+    assert_eq!(
+        rewrite_files(&files, &dir, home, u64::MAX).unwrap(),
+        vec![
+            (PathBuf::from(".config/fish/config.fish"), 1),
+            (PathBuf::from(".gitconfig"), 1),
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join(".config/fish/config.fish")).unwrap(),
+        "source $HOME/.aliases"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join(".gitconfig")).unwrap(),
+        "[core]\n\texcludesfile = ~/.gitignore"
+    );
+    assert_eq!(
+        fs::read(dir.join(".config/fish/blob")).unwrap(),
+        b"/home/alice\0"
+    );
+    assert!(scan(&files, &dir, home, u64::MAX).unwrap().is_empty());
+}
@@ -0,0 +1,300 @@
+mod tests;
+
+use crate::errors::{io::IoError, DotbakError, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// The name of the systemd service and timer running `dotbak sync`.
+pub const SYSTEMD_UNIT_NAME: &str = "dotbak-sync";
+
+/// The label of the launchd agent running `dotbak sync`, which is also the name of its plist.
+pub const LAUNCHD_LABEL: &str = "io.github.cogsandsquigs.dotbak.sync";
+
+/// The services that can run `dotbak sync` on a schedule, which (unlike the daemon) keeps running across reboots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scheduler {
+    /// A systemd user service and timer, on Linux.
+    Systemd,
+
+    /// A launchd agent, on macOS.
+    Launchd,
+}
+
+/// A file the scheduler is set up with, like a systemd unit or a launchd plist.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnitFile {
+    /// The path of the file.
+    pub path: PathBuf,
+
+    /// The contents of the file.
+    pub contents: String,
+}
+
+impl Scheduler {
+    /// Gets the scheduler of this platform, or `None` if there is none `dotbak` can set up.
+    pub fn current() -> Option<Scheduler> {
+        if cfg!(target_os = "linux") {
+            Some(Scheduler::Systemd)
+        } else if cfg!(target_os = "macos") {
+            Some(Scheduler::Launchd)
+        } else {
+            None
+        }
+    }
+
+    /// Gets the scheduler of this platform, or an error if there is none.
+    pub fn current_or_err() -> Result<Scheduler> {
+        Scheduler::current().ok_or_else(|| DotbakError::SchedulerUnsupported {
+            os: std::env::consts::OS.to_string(),
+        })
+    }
+
+    /// Generates the files that run `binary --home <home> sync` every `interval` seconds, and after logging in (or
+    /// booting). They're put into the home directory `home`.
+    pub fn unit_files<P1, P2>(&self, binary: P1, home: P2, interval: u64) -> Vec<UnitFile>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let (binary, home) = (binary.as_ref(), home.as_ref());
+        let args = [
+            binary.to_string_lossy(),
+            "--home".into(),
+            home.to_string_lossy(),
+            "sync".into(),
+        ];
+        let paths = self.unit_paths(home);
+
+        match self {
+            Scheduler::Systemd => vec![
+                UnitFile {
+                    path: paths[0].clone(),
+                    contents: format!(
+                        "[Unit]\n\
+                         Description=Synchronize dotfiles with dotbak\n\
+                         \n\
+                         [Service]\n\
+                         Type=oneshot\n\
+                         ExecStart={}\n",
+                        args.iter().map(|arg| systemd_quote(arg)).collect::<Vec<_>>().join(" ")
+                    ),
+                },
+                UnitFile {
+                    path: paths[1].clone(),
+                    contents: format!(
+                        "[Unit]\n\
+                         Description=Synchronize dotfiles with dotbak every {interval} seconds\n\
+                         \n\
+                         [Timer]\n\
+                         OnBootSec=1min\n\
+                         OnUnitActiveSec={interval}s\n\
+                         \n\
+                         [Install]\n\
+                         WantedBy=timers.target\n"
+                    ),
+                },
+            ],
+
+            Scheduler::Launchd => vec![UnitFile {
+                path: paths[0].clone(),
+                contents: format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                     <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+                     <plist version=\"1.0\">\n\
+                     <dict>\n\
+                     \t<key>Label</key>\n\
+                     \t<string>{}</string>\n\
+                     \t<key>ProgramArguments</key>\n\
+                     \t<array>\n\
+                     {}\
+                     \t</array>\n\
+                     \t<key>StartInterval</key>\n\
+                     \t<integer>{}</integer>\n\
+                     \t<key>RunAtLoad</key>\n\
+                     \t<true/>\n\
+                     \t<key>StandardOutPath</key>\n\
+                     \t<string>/tmp/{}.out</string>\n\
+                     \t<key>StandardErrorPath</key>\n\
+                     \t<string>/tmp/{}.err</string>\n\
+                     </dict>\n\
+                     </plist>\n",
+                    LAUNCHD_LABEL,
+                    args.iter()
+                        .map(|arg| format!("\t\t<string>{}</string>\n", xml_escape(arg)))
+                        .collect::<String>(),
+                    interval,
+                    SYSTEMD_UNIT_NAME,
+                    SYSTEMD_UNIT_NAME,
+                ),
+            }],
+        }
+    }
+
+    /// Gets the paths of the files the scheduler is set up with, in the home directory `home`.
+    pub fn unit_paths<P>(&self, home: P) -> Vec<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        match self {
+            Scheduler::Systemd => {
+                let dir = home.as_ref().join(".config/systemd/user");
+
+                vec![
+                    dir.join(format!("{}.service", SYSTEMD_UNIT_NAME)),
+                    dir.join(format!("{}.timer", SYSTEMD_UNIT_NAME)),
+                ]
+            }
+            Scheduler::Launchd => vec![home
+                .as_ref()
+                .join("Library/LaunchAgents")
+                .join(format!("{}.plist", LAUNCHD_LABEL))],
+        }
+    }
+
+    /// Writes the files that run `binary --home <home> sync` every `interval` seconds (see `unit_files`), and
+    /// starts the schedule. If it's already installed, it's replaced. Returns the paths of the files.
+    pub fn install<P1, P2>(&self, binary: P1, home: P2, interval: u64) -> Result<Vec<PathBuf>>
+    where
+        P1: AsRef<Path>,
+        P2: AsRef<Path>,
+    {
+        let files = self.unit_files(binary, home, interval);
+
+        // launchd keeps running an agent that is loaded until it's unloaded, even if its plist changed.
+        if *self == Scheduler::Launchd && files[0].path.exists() {
+            let _ = run("launchctl", &["unload", &files[0].path.to_string_lossy()]);
+        }
+
+        for file in &files {
+            if let Some(parent) = file.path.parent() {
+                fs::create_dir_all(parent).map_err(|err| IoError::Create {
+                    source: err,
+                    path: parent.to_path_buf(),
+                })?;
+            }
+
+            fs::write(&file.path, &file.contents).map_err(|err| IoError::Write {
+                source: err,
+                path: file.path.clone(),
+            })?;
+        }
+
+        match self {
+            Scheduler::Systemd => {
+                run("systemctl", &["--user", "daemon-reload"])?;
+                run(
+                    "systemctl",
+                    &[
+                        "--user",
+                        "enable",
+                        "--now",
+                        &format!("{}.timer", SYSTEMD_UNIT_NAME),
+                    ],
+                )?;
+            }
+            Scheduler::Launchd => {
+                run(
+                    "launchctl",
+                    &["load", "-w", &files[0].path.to_string_lossy()],
+                )?;
+            }
+        }
+
+        Ok(files.into_iter().map(|file| file.path).collect())
+    }
+
+    /// Stops the schedule, and removes the files it was set up with from the home directory `home`. Returns the
+    /// paths of the files that were removed, which is nothing if it wasn't installed.
+    pub fn uninstall<P>(&self, home: P) -> Result<Vec<PathBuf>>
+    where
+        P: AsRef<Path>,
+    {
+        let paths = self
+            .unit_paths(home)
+            .into_iter()
+            .filter(|path| path.exists())
+            .collect::<Vec<_>>();
+
+        if paths.is_empty() {
+            return Ok(paths);
+        }
+
+        match self {
+            Scheduler::Systemd => {
+                run(
+                    "systemctl",
+                    &[
+                        "--user",
+                        "disable",
+                        "--now",
+                        &format!("{}.timer", SYSTEMD_UNIT_NAME),
+                    ],
+                )?;
+            }
+            Scheduler::Launchd => {
+                run("launchctl", &["unload", "-w", &paths[0].to_string_lossy()])?;
+            }
+        }
+
+        for path in &paths {
+            fs::remove_file(path).map_err(|err| IoError::Delete {
+                source: err,
+                path: path.clone(),
+            })?;
+        }
+
+        if *self == Scheduler::Systemd {
+            run("systemctl", &["--user", "daemon-reload"])?;
+        }
+
+        Ok(paths)
+    }
+}
+
+/// Helper function to run `command` with `args`, returning an error with its output if it fails.
+fn run(command: &str, args: &[&str]) -> Result<Output> {
+    let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+    let output = Command::new(command)
+        .args(&args)
+        .output()
+        .map_err(|err| IoError::CommandIO {
+            source: err,
+            command: command.to_string(),
+            args: args.clone(),
+        })?;
+
+    if !output.status.success() {
+        return Err(IoError::CommandRun {
+            command: command.to_string(),
+            args,
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }
+        .into());
+    }
+
+    Ok(output)
+}
+
+/// Helper function to quote `arg` for the command line of a systemd unit, where `%` starts a specifier and `$`
+/// starts a variable.
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+
+    format!("\"{}\"", escaped)
+}
+
+/// Helper function to escape `text` for XML.
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
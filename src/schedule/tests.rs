@@ -0,0 +1,58 @@
+#![cfg(test)]
+
+use super::*;
+
+/// Test that the systemd units run `dotbak sync` on the interval, with the arguments quoted.
+#[test]
+fn test_systemd_units() {
+    let files = Scheduler::Systemd.unit_files("/usr/bin/dotbak", "/home/a 100%", 600);
+
+    assert_eq!(
+        files.iter().map(|file| &file.path).collect::<Vec<_>>(),
+        vec![
+            Path::new("/home/a 100%/.config/systemd/user/dotbak-sync.service"),
+            Path::new("/home/a 100%/.config/systemd/user/dotbak-sync.timer")
+        ]
+    );
+    assert!(files[0]
+        .contents
+        .contains("ExecStart=\"/usr/bin/dotbak\" \"--home\" \"/home/a 100%%\" \"sync\"\n"));
+    assert!(files[1].contents.contains("OnUnitActiveSec=600s\n"));
+    assert!(files[1].contents.contains("WantedBy=timers.target\n"));
+}
+
+/// Test that the launchd plist runs `dotbak sync` on the interval, with the arguments escaped.
+#[test]
+fn test_launchd_plist() {
+    let files = Scheduler::Launchd.unit_files("/usr/local/bin/dotbak", "/Users/a&b", 900);
+
+    assert_eq!(files.len(), 1);
+    assert_eq!(
+        files[0].path,
+        Path::new("/Users/a&b/Library/LaunchAgents/io.github.cogsandsquigs.dotbak.sync.plist")
+    );
+    assert!(files[0].contents.contains(
+        "\t\t<string>/usr/local/bin/dotbak</string>\n\
+         \t\t<string>--home</string>\n\
+         \t\t<string>/Users/a&amp;b</string>\n\
+         \t\t<string>sync</string>\n"
+    ));
+    assert!(files[0]
+        .contents
+        .contains("<key>StartInterval</key>\n\t<integer>900</integer>"));
+}
+
+/// Test that uninstalling does nothing if the schedule isn't installed.
+#[test]
+fn test_uninstall_absent() {
+    let home = assert_fs::TempDir::new().unwrap();
+
+    assert!(Scheduler::Systemd
+        .uninstall(home.path())
+        .unwrap()
+        .is_empty());
+    assert!(Scheduler::Launchd
+        .uninstall(home.path())
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,138 @@
+mod tests;
+
+use crate::{
+    config::secrets::SecretsConfig,
+    errors::{config::ConfigError, Result},
+    paths, rewrite,
+};
+use globset::GlobSet;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// The built-in patterns to find secrets with, by name. They only match things that are (almost) certainly secrets,
+/// like keys with a well-known prefix, as every false positive stops a commit.
+pub const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("AWS access key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+    (
+        "AWS secret key",
+        r#"(?i)aws_?secret_?(?:access_?)?key\s*[:=]\s*["']?[0-9A-Za-z/+]{40}\b"#,
+    ),
+    (
+        "GitHub token",
+        r"\b(?:gh[pousr]_[0-9A-Za-z]{36}|github_pat_[0-9A-Za-z_]{82})\b",
+    ),
+    ("GitLab token", r"\bglpat-[0-9A-Za-z_-]{20}\b"),
+    ("Slack token", r"\bxox[abposr]-[0-9A-Za-z-]{10,}\b"),
+    ("Google API key", r"\bAIza[0-9A-Za-z_-]{35}\b"),
+    ("Stripe key", r"\b[rs]k_live_[0-9A-Za-z]{24,}\b"),
+    (
+        "private key",
+        r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----",
+    ),
+];
+
+/// Finds secrets (like access keys or private keys) in files, so that they aren't committed by accident.
+#[derive(Clone, Debug)]
+pub struct Scanner {
+    /// The patterns to find secrets with, by name.
+    patterns: Vec<(String, Regex)>,
+
+    /// The paths that are never scanned.
+    allow: GlobSet,
+}
+
+/// A possible secret found in a file. The secret itself isn't kept, so that it can't end up in logs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Secret {
+    /// The path of the file, relative to the folder that was scanned.
+    pub path: PathBuf,
+
+    /// The line it was found on, starting at 1.
+    pub line: usize,
+
+    /// The name of the pattern that found it, e.g. `AWS access key`.
+    pub kind: String,
+}
+
+impl Scanner {
+    /// Creates a scanner with the built-in patterns, and the patterns and allowlist of `config`.
+    pub fn new(config: &SecretsConfig) -> Result<Scanner> {
+        let builtin = BUILTIN_PATTERNS
+            .iter()
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()));
+        let custom = config
+            .patterns
+            .iter()
+            .map(|(name, pattern)| (name.clone(), pattern.clone()));
+
+        let patterns = builtin
+            .chain(custom)
+            .map(|(name, pattern)| match Regex::new(&pattern) {
+                Ok(regex) => Ok((name, regex)),
+                Err(err) => Err(ConfigError::InvalidSecretPattern { name, source: err }.into()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Scanner {
+            patterns,
+            allow: paths::glob_set(&config.allow)?,
+        })
+    }
+
+    /// Finds the secrets in `contents`, as the line they're on (starting at 1) and the name of the pattern that
+    /// found them. Every line is only reported once.
+    pub fn find<'a>(&'a self, contents: &str) -> Vec<(usize, &'a str)> {
+        contents
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                self.patterns
+                    .iter()
+                    .find(|(_, regex)| regex.is_match(line))
+                    .map(|(name, _)| (i + 1, name.as_str()))
+            })
+            .collect()
+    }
+
+    /// Whether the path `path` (or a folder it's in) is allowed to contain secrets.
+    pub fn is_allowed<P>(&self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        path.as_ref()
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| self.allow.is_match(ancestor))
+    }
+
+    /// Scans the files/folders `files` (relative to `dir`) for secrets. Files which are allowed, too large (see
+    /// `files.diff_memory_limit`) or binary are skipped.
+    pub fn scan<P>(&self, files: &[P], dir: &Path, limit: u64) -> Result<Vec<Secret>>
+    where
+        P: AsRef<Path>,
+    {
+        let files = files
+            .iter()
+            .filter(|file| !self.is_allowed(file))
+            .collect::<Vec<_>>();
+        let mut found = vec![];
+
+        for file in rewrite::text_files(&files, dir, limit)? {
+            if self.is_allowed(&file) {
+                continue;
+            }
+
+            let Some(contents) = rewrite::read_text(&dir.join(&file))? else {
+                continue;
+            };
+
+            found.extend(self.find(&contents).into_iter().map(|(line, kind)| Secret {
+                path: file.clone(),
+                line,
+                kind: kind.to_string(),
+            }));
+        }
+
+        Ok(found)
+    }
+}
@@ -0,0 +1,110 @@
+mod tests;
+
+use clap::ValueEnum;
+use std::path::Path;
+
+/// The environment variable with the path to the `dotbak` binary the shell helpers call, like `--binary`.
+pub const BINARY_VAR: &str = "DOTBAK_BIN";
+
+/// The shells that `dotbak` can generate integration code for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Generates shell code that defines convenience wrappers around `dotbak`, meant to be `eval`-ed in the user's
+/// shell configuration (e.g. `eval "$(dotbak shell-init bash)"`). The following are defined:
+///
+/// - `dot`: a short alias for `dotbak` (e.g. `dot add .zshrc`).
+/// - `dots`: prints a compact count of uncommitted changes in the repository (the `uncommitted` line of
+///   `dotbak status --porcelain`), for use in prompts. It prints nothing if there are no changes.
+/// - If `sync_on_exit` is set, `dotbak sync` is run in the background when the shell exits.
+///
+/// `binary` is the path to the `dotbak` binary to call.
+pub fn init_script<P>(shell: Shell, binary: P, sync_on_exit: bool) -> String
+where
+    P: AsRef<Path>,
+{
+    let binary = quote(shell, &binary.as_ref().to_string_lossy());
+
+    match shell {
+        Shell::Bash | Shell::Zsh => {
+            let mut script = format!(
+                r#"# dotbak shell integration.
+dot() {{
+    {binary} "$@"
+}}
+
+dots() {{
+    local changes
+    changes=$({binary} status --porcelain 2>/dev/null | sed -n 's/^uncommitted //p')
+    if [ "${{changes:-0}}" -gt 0 ]; then
+        printf '±%s' "$changes"
+    fi
+}}
+"#
+            );
+
+            if sync_on_exit {
+                script += &format!(
+                    r#"
+_dotbak_sync_on_exit() {{
+    ({binary} sync >/dev/null 2>&1 &)
+}}
+"#
+                );
+
+                // Bash only has one `EXIT` trap, so chain onto whatever was there before. Zsh has a hook for it.
+                script += match shell {
+                    Shell::Zsh => "autoload -Uz add-zsh-hook\nadd-zsh-hook zshexit _dotbak_sync_on_exit\n",
+                    _ => concat!(
+                        "_dotbak_old_exit_trap=$(trap -p EXIT | sed \"s/^trap -- '\\(.*\\)' EXIT$/\\1/\")\n",
+                        "trap '_dotbak_sync_on_exit; eval \"$_dotbak_old_exit_trap\"' EXIT\n"
+                    ),
+                };
+            }
+
+            script
+        }
+
+        Shell::Fish => {
+            let mut script = format!(
+                r#"# dotbak shell integration.
+function dot --wraps dotbak
+    {binary} $argv
+end
+
+function dots
+    set -l changes ({binary} status --porcelain 2>/dev/null | string replace -rf '^uncommitted ' '')
+    if test -n "$changes"; and test $changes -gt 0
+        printf '±%s' $changes
+    end
+end
+"#
+            );
+
+            if sync_on_exit {
+                script += &format!(
+                    r#"
+function _dotbak_sync_on_exit --on-event fish_exit
+    {binary} sync >/dev/null 2>&1 &
+    disown
+end
+"#
+                );
+            }
+
+            script
+        }
+    }
+}
+
+/// Quotes `value` so that it is passed literally as a single argument in `shell`.
+pub fn quote(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => format!("'{}'", value.replace('\'', r"'\''")),
+        Shell::Fish => format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'")),
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(test)]
+
+use super::*;
+use std::process::Command;
+
+/// Test that the generated scripts call the given binary, for the status too.
+#[test]
+fn test_init_script_paths() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        let script = init_script(shell, "/opt/bin/dotbak", false);
+
+        assert!(script.contains("'/opt/bin/dotbak' status --porcelain"));
+        assert!(!script.contains("git "));
+        assert!(!script.contains("_dotbak_sync_on_exit"));
+    }
+}
+
+/// Test that the sync-on-exit hook is only generated when asked for.
+#[test]
+fn test_init_script_sync_on_exit() {
+    let bash = init_script(Shell::Bash, "dotbak", true);
+    let zsh = init_script(Shell::Zsh, "dotbak", true);
+    let fish = init_script(Shell::Fish, "dotbak", true);
+
+    assert!(bash.contains("trap '_dotbak_sync_on_exit"));
+    assert!(zsh.contains("add-zsh-hook zshexit _dotbak_sync_on_exit"));
+    assert!(fish.contains("--on-event fish_exit"));
+}
+
+/// Test that quoting handles quotes and backslashes.
+#[test]
+fn test_quote() {
+    assert_eq!(quote(Shell::Bash, "it's"), r"'it'\''s'");
+    assert_eq!(quote(Shell::Fish, r"it's \o/"), r"'it\'s \\o/'");
+}
+
+/// Test that the generated bash script is valid, and that `dots` reports the uncommitted changes `dotbak status`
+/// reports.
+#[test]
+fn test_init_script_bash_runs() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp_dir = assert_fs::TempDir::new().unwrap();
+    let binary = tmp_dir.path().join("it's dotbak");
+    let dots = |uncommitted: usize| {
+        std::fs::write(
+            &binary,
+            format!(
+                "#!/bin/sh\n[ \"$*\" = 'status --porcelain' ] || exit 1\n\
+                 printf 'file intact .zshrc\\nuncommitted {}\\nunpushed 2\\n'\n",
+                uncommitted
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let script = init_script(Shell::Bash, &binary, false);
+        let output = Command::new("bash")
+            .args(["-c", &format!("{script}\ndots")])
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+
+    assert_eq!(dots(3), "±3");
+    assert_eq!(dots(0), "");
+}
@@ -0,0 +1,181 @@
+mod tests;
+
+use crate::errors::{io::IoError, Result};
+use std::{
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The prefix stow's `--dotfiles` option replaces with a dot, e.g. `dot-bashrc` is installed as `.bashrc`.
+const DOT_PREFIX: &str = "dot-";
+
+/// Files stow ignores by default, which are about the package rather than part of it.
+const IGNORED: &[&str] = &[
+    ".git",
+    ".gitignore",
+    ".gitmodules",
+    ".stow-local-ignore",
+    "CVS",
+    "RCS",
+];
+
+/// Files starting with these are ignored as well, like `README.md`.
+const IGNORED_PREFIXES: &[&str] = &["README", "LICENSE", "COPYING"];
+
+/// A file or folder in a stow package, and where it's installed in the home directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StowedPath {
+    /// The name of the package.
+    pub package: String,
+
+    /// The full path to the file or folder in the package.
+    pub source: PathBuf,
+
+    /// The path it's installed at, relative to the home directory.
+    pub path: PathBuf,
+}
+
+/// Lists the packages in the stow directory `dir`, i.e. the folders in it, sorted by name.
+pub fn packages<P>(dir: P) -> Result<Vec<String>>
+where
+    P: AsRef<Path>,
+{
+    let mut packages = read_dir(dir.as_ref())?
+        .into_iter()
+        .filter(|name| !is_ignored(name) && dir.as_ref().join(name).is_dir())
+        .map(|name| name.to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    packages.sort();
+
+    Ok(packages)
+}
+
+/// Lists the paths of `packages` in the stow directory `dir` to manage, with `home` as the target directory. Like
+/// stow's tree folding, folders that are a real folder in `home` (e.g. `.config`, shared by several packages) are
+/// descended into, and every other file or folder is managed as a whole.
+pub fn paths<P1, P2>(dir: P1, packages: &[String], home: P2) -> Result<Vec<StowedPath>>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let mut found = vec![];
+
+    for package in packages {
+        collect(
+            package,
+            &dir.as_ref().join(package),
+            Path::new(""),
+            home.as_ref(),
+            &mut found,
+        )?;
+    }
+
+    Ok(found)
+}
+
+/// Copies the file or folder `source` in a package to `target`, the way stow would install it (i.e. with `dot-`
+/// prefixes replaced). Anything at `target` is replaced.
+pub fn copy<P1, P2>(source: P1, target: P2) -> Result<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+{
+    let (source, target) = (source.as_ref(), target.as_ref());
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|err| IoError::Create {
+            source: err,
+            path: parent.to_path_buf(),
+        })?;
+    }
+
+    if !source.is_dir() {
+        fs::copy(source, target).map_err(|err| IoError::Copy {
+            source: err,
+            from: source.to_path_buf(),
+            to: target.to_path_buf(),
+        })?;
+
+        return Ok(());
+    }
+
+    fs::create_dir_all(target).map_err(|err| IoError::Create {
+        source: err,
+        path: target.to_path_buf(),
+    })?;
+
+    for name in read_dir(source)? {
+        if !is_ignored(&name) {
+            copy(source.join(&name), target.join(target_name(&name)))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the name a file in a package is installed as, replacing the `dot-` prefix of stow's `--dotfiles` option.
+pub fn target_name(name: &OsStr) -> OsString {
+    match name.to_str().and_then(|name| name.strip_prefix(DOT_PREFIX)) {
+        Some(rest) if !rest.is_empty() => OsString::from(format!(".{}", rest)),
+        _ => name.to_os_string(),
+    }
+}
+
+/// Helper function to list the paths to manage in the folder `rel` (relative to the package) of the package at
+/// `package_dir` into `found`.
+fn collect(
+    package: &str,
+    package_dir: &Path,
+    rel: &Path,
+    home: &Path,
+    found: &mut Vec<StowedPath>,
+) -> Result<()> {
+    let mut names = read_dir(&package_dir.join(rel))?;
+    names.sort();
+
+    for name in names.into_iter().filter(|name| !is_ignored(name)) {
+        let source = package_dir.join(rel).join(&name);
+        let path = installed_path(rel).join(target_name(&name));
+        let is_real_dir = fs::symlink_metadata(home.join(&path)).is_ok_and(|meta| meta.is_dir());
+
+        if source.is_dir() && is_real_dir {
+            collect(package, package_dir, &rel.join(&name), home, found)?;
+        } else {
+            found.push(StowedPath {
+                package: package.to_string(),
+                source,
+                path,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Helper function to get the path `rel` in a package is installed at, relative to the home directory.
+fn installed_path(rel: &Path) -> PathBuf {
+    rel.iter().map(target_name).collect()
+}
+
+/// Helper function to check if stow ignores the file `name`.
+fn is_ignored(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+
+    IGNORED.contains(&name.as_ref())
+        || IGNORED_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Helper function to list the names of the files in the folder `dir`.
+fn read_dir(dir: &Path) -> Result<Vec<OsString>> {
+    Ok(fs::read_dir(dir)
+        .map_err(|err| IoError::Read {
+            source: err,
+            path: dir.to_path_buf(),
+        })?
+        .flatten()
+        .map(|entry| entry.file_name())
+        .collect())
+}
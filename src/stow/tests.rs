@@ -0,0 +1,64 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::TempDir;
+
+/// Test that packages are the folders of the stow directory, and that their paths are found like stow folds them.
+#[test]
+fn test_paths() {
+    let dir = TempDir::new().unwrap();
+    let (stow, home) = (dir.path().join("stow"), dir.path().join("home"));
+
+    fs::create_dir_all(stow.join("zsh")).unwrap();
+    fs::write(stow.join("zsh/.zshrc"), "").unwrap();
+    fs::write(stow.join("zsh/README.md"), "").unwrap();
+    fs::create_dir_all(stow.join("nvim/.config/nvim")).unwrap();
+    fs::write(stow.join("nvim/.config/nvim/init.lua"), "").unwrap();
+    fs::create_dir_all(stow.join("git/dot-config/git")).unwrap();
+    fs::write(stow.join("git/dot-gitconfig"), "").unwrap();
+    fs::write(stow.join("git/dot-config/git/ignore"), "").unwrap();
+    fs::write(stow.join("notes.txt"), "").unwrap();
+    fs::create_dir_all(home.join(".config")).unwrap();
+
+    let packages = packages(&stow).unwrap();
+
+    assert_eq!(packages, vec!["git", "nvim", "zsh"]);
+
+    let paths = paths(&stow, &packages, &home)
+        .unwrap()
+        .into_iter()
+        .map(|stowed| (stowed.package, stowed.path))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        paths,
+        vec![
+            ("git".to_string(), PathBuf::from(".config/git")),
+            ("git".to_string(), PathBuf::from(".gitconfig")),
+            ("nvim".to_string(), PathBuf::from(".config/nvim")),
+            ("zsh".to_string(), PathBuf::from(".zshrc")),
+        ]
+    );
+}
+
+/// Test that copies are installed like stow would, with `dot-` prefixes replaced.
+#[test]
+fn test_copy() {
+    let dir = TempDir::new().unwrap();
+    let (source, target) = (
+        dir.path().join("dot-config"),
+        dir.path().join("home/.config"),
+    );
+
+    fs::create_dir_all(source.join("dot-app")).unwrap();
+    fs::write(source.join("dot-app/config"), "contents").unwrap();
+    fs::write(source.join("dot-"), "").unwrap();
+
+    copy(&source, &target).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(target.join(".app/config")).unwrap(),
+        "contents"
+    );
+    assert!(target.join("dot-").exists());
+}
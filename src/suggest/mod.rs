@@ -0,0 +1,149 @@
+mod tests;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Well-known configuration files and folders, relative to the home directory. These are the locations that
+/// are worth suggesting for management when they exist.
+pub const CATALOG: &[&str] = &[
+    // Shells
+    ".bashrc",
+    ".bash_profile",
+    ".bash_aliases",
+    ".profile",
+    ".zshrc",
+    ".zprofile",
+    ".zshenv",
+    ".config/fish",
+    ".inputrc",
+    ".config/starship.toml",
+    // Editors
+    ".vimrc",
+    ".vim",
+    ".config/nvim",
+    ".emacs",
+    ".emacs.d",
+    ".config/helix",
+    ".config/zed",
+    ".config/Code/User/settings.json",
+    // Terminals and multiplexers
+    ".tmux.conf",
+    ".config/tmux",
+    ".config/alacritty",
+    ".config/kitty",
+    ".config/wezterm",
+    ".wezterm.lua",
+    ".config/zellij",
+    // Version control
+    ".gitconfig",
+    ".gitignore_global",
+    ".config/git",
+    ".hgrc",
+    // Tools
+    ".ssh/config",
+    ".config/gh/config.yml",
+    ".config/bat",
+    ".config/htop",
+    ".config/btop",
+    ".npmrc",
+    ".cargo/config.toml",
+    ".config/lazygit",
+    ".config/yazi",
+    // Desktop environments and window managers
+    ".config/i3",
+    ".config/sway",
+    ".config/hypr",
+    ".config/waybar",
+    ".config/rofi",
+    ".config/dunst",
+    ".config/picom",
+    ".Xresources",
+    ".xinitrc",
+    ".config/karabiner",
+    ".config/aerospace",
+    ".yabairc",
+    ".skhdrc",
+];
+
+/// A configuration file/folder that was found in the home directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    /// The path to the file/folder, relative to the home directory.
+    pub path: PathBuf,
+
+    /// When the file/folder (or, for folders, anything directly inside of it) was last modified.
+    pub modified: Option<SystemTime>,
+}
+
+/// Scans `home` for all the entries in `CATALOG` that exist. Symlinks are not followed, so that entries that
+/// are already symlinked somewhere else are still found.
+pub fn scan<P>(home: P) -> Vec<Candidate>
+where
+    P: AsRef<Path>,
+{
+    CATALOG
+        .iter()
+        .map(PathBuf::from)
+        .filter_map(|path| {
+            let full_path = home.as_ref().join(&path);
+            let meta = fs::symlink_metadata(&full_path).ok()?;
+
+            let mut modified = meta.modified().ok();
+
+            // Folders are only as fresh as their most recently modified child.
+            if meta.is_dir() {
+                let children = fs::read_dir(&full_path).into_iter().flatten().flatten();
+
+                modified = children
+                    .filter_map(|child| child.metadata().ok()?.modified().ok())
+                    .chain(modified)
+                    .max();
+            }
+
+            Some(Candidate { path, modified })
+        })
+        .collect()
+}
+
+/// How much of the configuration in the home directory is managed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    /// The configuration files/folders that are already managed.
+    pub managed: Vec<Candidate>,
+
+    /// The configuration files/folders that aren't managed yet, most recently modified first.
+    pub unmanaged: Vec<Candidate>,
+}
+
+impl Coverage {
+    /// Splits `candidates` into managed and unmanaged ones. A candidate is managed if it, or any of its parent
+    /// folders, is in `include`.
+    pub fn new<P>(candidates: Vec<Candidate>, include: &[P]) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let (managed, mut unmanaged): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|candidate| include.iter().any(|p| candidate.path.starts_with(p)));
+
+        // `None` sorts before `Some`, so reversing puts unknown modification times last.
+        unmanaged.sort_by_key(|candidate| std::cmp::Reverse(candidate.modified));
+
+        Coverage { managed, unmanaged }
+    }
+
+    /// The fraction of found configuration files/folders that are managed, between `0.0` and `1.0`. If nothing
+    /// was found, everything is considered covered.
+    pub fn fraction(&self) -> f64 {
+        let total = self.managed.len() + self.unmanaged.len();
+
+        if total == 0 {
+            1.0
+        } else {
+            self.managed.len() as f64 / total as f64
+        }
+    }
+}
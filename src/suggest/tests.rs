@@ -0,0 +1,71 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::{prelude::*, TempDir};
+use std::time::Duration;
+
+/// Test that scanning only finds the catalog entries that exist.
+#[test]
+fn test_scan() {
+    let home = TempDir::new().unwrap();
+
+    home.child(".zshrc").touch().unwrap();
+    home.child(".config/nvim/init.lua").touch().unwrap();
+    home.child("not-a-config").touch().unwrap();
+
+    let found = scan(home.path())
+        .into_iter()
+        .map(|candidate| candidate.path)
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        found,
+        vec![PathBuf::from(".zshrc"), PathBuf::from(".config/nvim")]
+    );
+}
+
+/// Test that coverage is split by the include list, and sorted by modification time.
+#[test]
+fn test_coverage() {
+    let now = SystemTime::now();
+    let candidate = |path: &str, age: Option<u64>| Candidate {
+        path: path.into(),
+        modified: age.map(|age| now - Duration::from_secs(age)),
+    };
+
+    let coverage = Coverage::new(
+        vec![
+            candidate(".zshrc", Some(10)),
+            candidate(".config/nvim", Some(100)),
+            candidate(".gitconfig", None),
+            candidate(".tmux.conf", Some(1)),
+        ],
+        &[".config", ".zshrc"],
+    );
+
+    assert_eq!(
+        coverage
+            .managed
+            .iter()
+            .map(|c| c.path.clone())
+            .collect::<Vec<_>>(),
+        vec![PathBuf::from(".zshrc"), PathBuf::from(".config/nvim")]
+    );
+    assert_eq!(
+        coverage
+            .unmanaged
+            .iter()
+            .map(|c| c.path.clone())
+            .collect::<Vec<_>>(),
+        vec![PathBuf::from(".tmux.conf"), PathBuf::from(".gitconfig")]
+    );
+    assert_eq!(coverage.fraction(), 0.5);
+}
+
+/// Test that nothing to cover counts as fully covered.
+#[test]
+fn test_coverage_empty() {
+    let coverage = Coverage::new(vec![], &[".zshrc"]);
+
+    assert_eq!(coverage.fraction(), 1.0);
+}
@@ -1,4 +1,23 @@
-#![cfg(test)]
+//! The harness for end-to-end tests: `TestEnv` stands in for a git host with local bare repositories, and for
+//! machines with fake home directories, so that tests of cloning, pushing and pulling run offline and always the
+//! same way. It's available to tests outside of this crate with the `testing` feature.
+//!
+//! ```
+//! use dotbak_core::{test_util::TestEnv, Dotbak};
+//! use std::path::Path;
+//!
+//! let env = TestEnv::new();
+//! let laptop = env.machine("laptop");
+//!
+//! laptop.write(".zshrc", "export EDITOR=vim");
+//! let mut dotbak = Dotbak::clone_machine(&laptop, &env.remote_url())?;
+//! dotbak.add(&[".zshrc"], false)?;
+//! dotbak.push()?;
+//!
+//! assert!(laptop.is_managed(".zshrc"));
+//! assert_eq!(env.remote_files()[Path::new(".zshrc")], "export EDITOR=vim");
+//! # Ok::<(), dotbak_core::DotbakError>(())
+//! ```
 
 /// Helper function to check if a repository exists at a path.
 #[macro_export]
@@ -17,3 +36,219 @@ macro_rules! repo_not_exists {
         assert!(!$path.join(".git").exists());
     };
 }
+
+use crate::{
+    git::{Repository, MAIN_BRANCH_NAME, REMOTE_NAME},
+    platform::RecordingTrash,
+    xdg::BaseDirs,
+};
+use assert_fs::TempDir;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Once},
+};
+
+/// The file the remote of a `TestEnv` is seeded with, so that it can be cloned and pulled from like a freshly
+/// created repository on a git host.
+pub const SEED_FILE: &str = "README.md";
+
+/// A hermetic environment for end-to-end tests: a local bare repository standing in for the remote, and fake
+/// home directories for any number of machines. Nothing touches the network or the real home directory.
+pub struct TestEnv {
+    /// The folder everything lives in. It's removed once the environment is dropped.
+    dir: TempDir,
+
+    /// The path to the bare repository.
+    remote: PathBuf,
+}
+
+/// A machine in a `TestEnv`, laid out like a real home directory.
+pub struct Machine {
+    /// The name of the machine, which is recorded as its hostname in the commits it makes.
+    pub name: String,
+
+    /// The fake home directory.
+    pub home: PathBuf,
+
+    /// The configuration file, at `<home>/.config/dotbak/config.toml`.
+    pub config: PathBuf,
+
+    /// The repository, at `<home>/.local/share/dotbak/dotfiles`.
+    pub repo: PathBuf,
+
+    /// The trash what `dotbak` deletes on the machine goes to, instead of the system trash.
+    pub trash: Arc<RecordingTrash>,
+}
+
+impl TestEnv {
+    /// Creates the environment, with a remote containing a single commit (see `SEED_FILE`).
+    pub fn new() -> Self {
+        allow_local_submodules();
+
+        let dir = TempDir::new().unwrap();
+        let remote = dir.path().join("remote.git");
+        let seed = dir.path().join("seed");
+
+        fs::create_dir_all(&remote).unwrap();
+        git(
+            &remote,
+            &["init", "--bare", "--initial-branch", MAIN_BRANCH_NAME],
+        );
+
+        let mut repo = Repository::init(&seed, None).unwrap();
+        fs::write(seed.join(SEED_FILE), "# dotfiles\n").unwrap();
+        repo.commit("Initial commit").unwrap();
+        git(&seed, &["push", remote.to_str().unwrap(), MAIN_BRANCH_NAME]);
+        fs::remove_dir_all(&seed).unwrap();
+
+        TestEnv { dir, remote }
+    }
+
+    /// The URL of the remote, to clone it or set it as a repository's remote.
+    pub fn remote_url(&self) -> String {
+        format!("file://{}", self.remote.display())
+    }
+
+    /// Creates the machine `name`, with an empty home directory.
+    pub fn machine(&self, name: &str) -> Machine {
+        let home = self.dir.path().join(name).join("home");
+        let base = BaseDirs::new(&home);
+
+        fs::create_dir_all(&home).unwrap();
+
+        Machine {
+            name: name.to_string(),
+            config: base.config.join("config.toml"),
+            repo: base.data.join("dotfiles"),
+            home,
+            trash: Arc::new(RecordingTrash::new()),
+        }
+    }
+
+    /// The summaries of the commits on the remote's main branch, newest first.
+    pub fn remote_log(&self) -> Vec<String> {
+        log(&self.remote)
+    }
+
+    /// A snapshot of the files on the remote's main branch (by their path in the repository) with their contents, to
+    /// compare what was pushed against.
+    pub fn remote_files(&self) -> BTreeMap<PathBuf, String> {
+        let paths = git(
+            &self.remote,
+            &["ls-tree", "-r", "-z", "--name-only", MAIN_BRANCH_NAME],
+        );
+
+        paths
+            .split(|&byte| byte == 0)
+            .filter(|path| !path.is_empty())
+            .map(|path| {
+                let path = String::from_utf8_lossy(path).to_string();
+                let rev = format!("{}:{}", MAIN_BRANCH_NAME, path);
+                let contents = git(&self.remote, &["show", &rev]);
+
+                (
+                    PathBuf::from(path),
+                    String::from_utf8_lossy(&contents).to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// Creates another, empty remote `name` (e.g. a backup mirror), and returns its URL.
+    pub fn extra_remote(&self, name: &str) -> String {
+        let remote = self.dir.path().join(format!("{}.git", name));
+
+        fs::create_dir_all(&remote).unwrap();
+        git(
+            &remote,
+            &["init", "--bare", "--initial-branch", MAIN_BRANCH_NAME],
+        );
+
+        format!("file://{}", remote.display())
+    }
+
+    /// The summaries of the commits on the main branch of the extra remote `name`, newest first.
+    pub fn extra_remote_log(&self, name: &str) -> Vec<String> {
+        log(&self.dir.path().join(format!("{}.git", name)))
+    }
+}
+
+impl Default for TestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    /// Writes `contents` to the file at `path` (relative to the home directory), creating its parent folders.
+    pub fn write<P>(&self, path: P, contents: &str)
+    where
+        P: AsRef<Path>,
+    {
+        let path = self.home.join(path);
+
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    /// Reads the file at `path` (relative to the home directory).
+    pub fn read<P>(&self, path: P) -> String
+    where
+        P: AsRef<Path>,
+    {
+        fs::read_to_string(self.home.join(path)).unwrap()
+    }
+
+    /// Checks if the file at `path` (relative to the home directory) is a symlink into the repository.
+    pub fn is_managed<P>(&self, path: P) -> bool
+    where
+        P: AsRef<Path>,
+    {
+        fs::read_link(self.home.join(&path)).is_ok_and(|target| target == self.repo.join(&path))
+    }
+
+    /// Checks if the repository's remote is the remote of the environment `env`.
+    pub fn uses_remote(&self, env: &TestEnv) -> bool {
+        let url = git(&self.repo, &["remote", "get-url", REMOTE_NAME]);
+
+        String::from_utf8_lossy(&url).trim() == env.remote_url()
+    }
+}
+
+/// Lets git clone submodules from local paths, as the ones in tests are, which it refuses by default. This is set in
+/// the environment of the whole test process, since git clones submodules in processes of its own, which don't get
+/// the repository's configuration.
+pub fn allow_local_submodules() {
+    static ALLOW: Once = Once::new();
+
+    ALLOW.call_once(|| env::set_var("GIT_CONFIG_PARAMETERS", "'protocol.file.allow'='always'"));
+}
+
+/// Helper function to get the summaries of the commits on the main branch of the bare repository `remote`, newest
+/// first.
+fn log(remote: &Path) -> Vec<String> {
+    String::from_utf8_lossy(&git(remote, &["log", "--format=%s", MAIN_BRANCH_NAME]))
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Helper function to run `git` with `args` in the folder `dir`, panicking if it fails. Returns its output.
+fn git(dir: &Path, args: &[&str]) -> Vec<u8> {
+    let output = std::process::Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "git {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    output.stdout
+}
@@ -3,11 +3,38 @@ pub const MAX_MSG_LEN: usize = 49;
 pub const COMMIT_MSG: &str = "📦 Committing changes";
 pub const PUSH_MSG: &str = "📤 Pushing changes";
 pub const PULL_MSG: &str = "📥 Pulling changes";
+pub const CLONE_MSG: &str = "📥 Cloning repository";
 pub const SYNC_MSG: &str = "🔄 Syncing state";
-pub const UNDO_MSG: &str = "⏪ Undoing last commit";
+pub const UNDO_MSG: &str = "⏪ Undoing commits";
+pub const PIN_MSG: &str = "📌 Moving back to the pinned commit";
 pub const UPDATE_CONF_MSG: &str = "💾 Updating configuration";
 pub const RM_FILES_MSG: &str = "🗑️ Removing files";
 pub const RESTORE_FILES_MSG: &str = "⏪ Restoring files";
 pub const RM_CONFG_MSG: &str = "🗑️ Removing configuration";
 pub const RM_REPO_MSG: &str = "🗑️ Removing repository";
-pub const ARBITRARY_GIT_CMD_MSG: &str = "🏃 Running arbitrary git command";
+pub const MOVE_REPO_MSG: &str = "🚚 Moving repository";
+pub const RELINK_MSG: &str = "🔗 Relinking files";
+pub const FETCH_MSG: &str = "📥 Fetching other history";
+pub const MERGE_MSG: &str = "🔀 Merging histories";
+pub const CREATE_BUNDLE_MSG: &str = "🔐 Creating bundle";
+pub const RESTORE_BUNDLE_MSG: &str = "🔓 Restoring bundle";
+pub const EXPORT_MSG: &str = "🌐 Exporting site";
+pub const FETCH_REMOTE_MSG: &str = "📥 Fetching remote";
+pub const UNSHALLOW_MSG: &str = "📥 Fetching full history";
+pub const TRACK_LOCKS_MSG: &str = "🔒 Tracking plugin lock files";
+pub const PROMOTE_MSG: &str = "🚀 Promoting staged changes";
+pub const EJECT_MSG: &str = "⏏️ Replacing symlinks with files";
+pub const INSTALL_SCRIPT_MSG: &str = "📝 Writing install script";
+pub const ARCHIVE_HOST_MSG: &str = "🗄️ Archiving host";
+pub const HOOKS_MSG: &str = "🪝 Running hooks";
+pub const IMPORT_MSG: &str = "📥 Importing files";
+pub const ADOPT_MSG: &str = "🔗 Adopting symlinks";
+pub const PRUNE_MSG: &str = "🧹 Pruning orphaned files";
+pub const SWITCH_BRANCH_MSG: &str = "🌿 Switching branch";
+pub const ADD_MODULE_MSG: &str = "🧩 Adding module";
+pub const EXPORT_PACKAGES_MSG: &str = "📋 Exporting package lists";
+pub const APPLY_PACKAGES_MSG: &str = "📦 Installing packages";
+pub const EXPORT_ARCHIVE_MSG: &str = "🗜️ Packing archive";
+pub const IMPORT_ARCHIVE_MSG: &str = "📥 Unpacking archive";
+pub const REWRITE_HISTORY_MSG: &str = "✂️ Rewriting history";
+pub const GC_MSG: &str = "🧹 Cleaning up repository";
@@ -1,8 +1,17 @@
 pub mod messages;
 
+use crate::{
+    errors::{io::IoError, Result},
+    files::Progress,
+};
 use console::{style, Term};
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
-use std::time::Duration;
+use std::{
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 const SPINNER_FRAMES: &[&str] = &[
     "⠁", "⠂", "⠄", "⡀", "⡈", "⡐", "⡠", "⣀", "⣁", "⣂", "⣄", "⣌", "⣔", "⣤", "⣥", "⣦", "⣮", "⣶", "⣷",
@@ -11,6 +20,42 @@ const SPINNER_FRAMES: &[&str] = &[
 
 const SPINNER_FRAME_DURATION: Duration = Duration::from_millis(80);
 
+/// The longest detail shown next to a spinner (see `Interface::show_detail`), in characters.
+const MAX_DETAIL_LEN: usize = 50;
+
+/// The environment variable that CI services set to `true`, which makes `dotbak` run non-interactively.
+pub const CI_VAR: &str = "CI";
+
+/// How much `dotbak` tells about what it's doing on the terminal. Everything is written to the log file either way
+/// (see `dotbak::logger::Logger`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only the results and errors, without spinners (`-q`).
+    Quiet,
+
+    /// The spinners and warnings as well.
+    #[default]
+    Normal,
+
+    /// What's being done as well, e.g. which files were synced (`-v`).
+    Verbose,
+
+    /// The output of the git commands that were run as well (`-vv`).
+    Debug,
+}
+
+impl Verbosity {
+    /// Gets the verbosity from the number of times `-v` was given, and whether `-q` was given.
+    pub fn from_flags(verbose: u8, quiet: bool) -> Verbosity {
+        match (verbose, quiet) {
+            (_, true) => Verbosity::Quiet,
+            (0, false) => Verbosity::Normal,
+            (1, false) => Verbosity::Verbose,
+            (_, false) => Verbosity::Debug,
+        }
+    }
+}
+
 /// An interface to the terminal, for spinners. This is a wrapper around `indicatif::MultiProgress`, and also is
 /// `Clone`-able (as it uses Rc internally).
 #[derive(Clone, Debug)]
@@ -26,27 +71,65 @@ pub struct Interface {
 
     /// The current spinner "depth"
     current_depth: usize,
+
+    /// Whether the user can be asked for things, and spinners can be drawn. Otherwise, prompts are answered with
+    /// their default, and closed spinners are printed as plain lines instead.
+    interactive: bool,
+
+    /// Whether nothing is printed for spinners, see `silence`.
+    silent: bool,
+
+    /// The newest spinner, which `show_detail` shows details next to. It's shared between clones of the interface.
+    newest: Arc<Mutex<Option<ProgressBar>>>,
 }
 
 impl Interface {
-    /// Creates a new interface.
-    pub fn new(max_msg_len: usize) -> Interface {
+    /// Creates a new interface. It's silent (see `silence`) if `verbosity` is `Verbosity::Quiet`, and
+    /// non-interactive (see `set_non_interactive`) if `is_interactive` says so.
+    pub fn new(max_msg_len: usize, verbosity: Verbosity) -> Interface {
         let term = Term::stdout();
         let draw_target = ProgressDrawTarget::term(term.clone(), 30);
 
-        Interface {
+        let mut interface = Interface {
             mp: MultiProgress::with_draw_target(draw_target),
             term,
             max_msg_len,
             current_depth: 0,
+            interactive: true,
+            silent: false,
+            newest: Arc::default(),
+        };
+
+        if verbosity == Verbosity::Quiet {
+            interface.silence();
+        }
+
+        if !is_interactive() {
+            interface.set_non_interactive();
         }
+
+        interface
     }
 
     /// Makes interface "silent" by removing output.
     pub fn silence(&mut self) {
+        self.silent = true;
+        self.mp.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    /// Makes the interface non-interactive, e.g. for provisioning scripts and cron jobs: spinners are printed as
+    /// plain lines once they're closed, prompts are answered with their default choice, and asking for secrets or
+    /// opening an editor fails with `IoError::NonInteractive`.
+    pub fn set_non_interactive(&mut self) {
+        self.interactive = false;
         self.mp.set_draw_target(ProgressDrawTarget::hidden());
     }
 
+    /// Whether the user can be asked for things, see `set_non_interactive`.
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
     /// Draw a message to the terminal.
     pub fn println<S>(&self, message: S)
     where
@@ -69,6 +152,168 @@ impl Interface {
             .unwrap();
     }
 
+    /// Asks the user to pick one of `choices`, and returns the index of the chosen one. The first choice is the
+    /// default, and is picked when the user just hits enter (or when there is no input at all, or the interface is
+    /// non-interactive).
+    pub fn choose<S>(&self, question: S, choices: &[&str]) -> Result<usize>
+    where
+        S: ToString,
+    {
+        if !self.interactive {
+            self.println(format!(
+                "❓ {} {}",
+                question.to_string(),
+                style(format!("[{}] (non-interactive)", choices[0])).dim()
+            ));
+
+            return Ok(0);
+        }
+
+        let options = choices
+            .iter()
+            .enumerate()
+            .map(|(i, choice)| format!("[{}] {}", i + 1, choice))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        // Hide the spinners while asking, so they don't draw over the question.
+        self.mp.suspend(|| loop {
+            self.term
+                .write_str(&format!(
+                    "❓ {} {} ",
+                    question.to_string(),
+                    style(&options).dim()
+                ))
+                .map_err(|err| IoError::Prompt { source: err })?;
+
+            let answer = self
+                .term
+                .read_line()
+                .map_err(|err| IoError::Prompt { source: err })?;
+
+            match answer.trim() {
+                "" => return Ok(0),
+                answer => match answer.parse::<usize>() {
+                    Ok(i) if (1..=choices.len()).contains(&i) => return Ok(i - 1),
+                    _ => self.warn(format!(
+                        "Please answer with a number between 1 and {}.",
+                        choices.len()
+                    )),
+                },
+            }
+        })
+    }
+
+    /// Runs `run` with the spinners hidden, e.g. for commands that may ask the user for things themselves.
+    pub fn suspend<F, R>(&self, run: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        self.mp.suspend(run)
+    }
+
+    /// Asks the user for a line of text (like their name), and returns it without surrounding whitespace.
+    pub fn ask<S>(&self, prompt: S) -> Result<String>
+    where
+        S: ToString,
+    {
+        if !self.interactive {
+            return Err(IoError::NonInteractive {
+                prompt: prompt.to_string(),
+            }
+            .into());
+        }
+
+        // Hide the spinners while asking, so they don't draw over the prompt.
+        self.mp.suspend(|| {
+            self.term
+                .write_str(&format!("❓ {} ", prompt.to_string()))
+                .and_then(|_| self.term.read_line())
+                .map(|answer| answer.trim().to_string())
+                .map_err(|err| IoError::Prompt { source: err }.into())
+        })
+    }
+
+    /// Reads a secret (like an access token) from the terminal, without echoing it. See `read_secret`.
+    pub fn ask_secret<S>(&self, prompt: S) -> Result<String>
+    where
+        S: ToString,
+    {
+        if !self.interactive {
+            return Err(IoError::NonInteractive {
+                prompt: prompt.to_string(),
+            }
+            .into());
+        }
+
+        // Hide the spinners while asking, so they don't draw over the prompt.
+        self.mp.suspend(|| read_secret(prompt))
+    }
+
+    /// Opens `path` in the user's editor (`$VISUAL`, then `$EDITOR`, falling back to `vi`), and waits for the
+    /// editor to exit.
+    pub fn open_editor<P>(&self, path: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        if !self.interactive {
+            return Err(IoError::NonInteractive {
+                prompt: format!("edit '{}'", path.as_ref().display()),
+            }
+            .into());
+        }
+
+        let editor = std::env::var("VISUAL")
+            .or_else(|_| std::env::var("EDITOR"))
+            .unwrap_or_else(|_| "vi".to_string());
+
+        // Editors are often configured with arguments, e.g. `code --wait`.
+        let mut words = editor.split_whitespace().map(str::to_string);
+        let editor = words.next().unwrap_or_else(|| "vi".to_string());
+        let args = words
+            .chain([path.as_ref().to_string_lossy().to_string()])
+            .collect::<Vec<_>>();
+
+        // Hide the spinners while editing, so they don't draw over the editor.
+        let status = self
+            .mp
+            .suspend(|| Command::new(&editor).args(&args).status())
+            .map_err(|err| IoError::CommandIO {
+                source: err,
+                command: editor.clone(),
+                args: args.clone(),
+            })?;
+
+        if !status.success() {
+            return Err(IoError::CommandRun {
+                command: editor,
+                args,
+                stdout: String::new(),
+                stderr: format!("The editor exited with {}", status),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Shows `detail` (e.g. the progress git reports while pushing) next to the newest spinner while it runs,
+    /// replacing the detail shown before. Details are left out once the spinner is closed.
+    pub fn show_detail<S>(&self, detail: S)
+    where
+        S: ToString,
+    {
+        let Ok(newest) = self.newest.lock() else {
+            return;
+        };
+
+        if let Some(spinner) = newest.as_ref().filter(|spinner| !spinner.is_finished()) {
+            spinner.set_prefix(
+                console::truncate_str(&detail.to_string(), MAX_DETAIL_LEN, "…").to_string(),
+            );
+        }
+    }
+
     /// Spawns a new spinner. Returns a handle to the spinner, which can be used to update the spinner.
     pub fn spawn_spinner<S>(&mut self, message: S, depth: usize) -> Spinner
     where
@@ -82,17 +327,79 @@ impl Interface {
 
         let pb = ProgressBar::new_spinner().with_message(message).with_style(
             ProgressStyle::default_spinner()
-                .template(&get_template("{spinner:.blue}", num_dots, depth, new_depth))
+                .template(&format!(
+                    "{} {{prefix:.dim}}",
+                    get_template("{spinner:.blue}", num_dots, depth, new_depth)
+                ))
                 .expect("This should not fail!")
                 .tick_strings(SPINNER_FRAMES),
         );
+        let pb = self.mp.add(pb);
+
+        if let Ok(mut newest) = self.newest.lock() {
+            *newest = Some(pb.clone());
+        }
 
-        let mut spinner = Spinner::new(self.mp.add(pb), num_dots, self.current_depth, new_depth);
+        let mut spinner =
+            Spinner::new(pb, num_dots, self.current_depth, new_depth).with_plain(self.plain());
 
         spinner.start();
 
         spinner
     }
+
+    /// Spawns a new progress bar for an operation over `total` files/folders, showing the file/folder it's at, how
+    /// many are done, and how many are done per second. Returns a handle to the progress bar, which is updated
+    /// through `files::Progress`.
+    pub fn spawn_progress<S>(&mut self, message: S, depth: usize, total: usize) -> FileProgress
+    where
+        S: ToString,
+    {
+        let message = message.to_string();
+        let num_dots = self.max_msg_len.saturating_sub(message.len());
+
+        let new_depth = depth > self.current_depth;
+        self.current_depth = depth;
+
+        let pb = ProgressBar::new(total as u64)
+            .with_prefix(message.clone())
+            .with_style(
+                ProgressStyle::default_bar()
+                    .template(&get_progress_template(depth, new_depth))
+                    .expect("This should not fail!")
+                    .progress_chars("━╸ "),
+            );
+
+        FileProgress {
+            spinner: Spinner::new(self.mp.add(pb), num_dots, self.current_depth, new_depth)
+                .with_plain(self.plain()),
+            message,
+        }
+    }
+
+    /// Helper function to get the terminal to print closed spinners to as plain lines, if they aren't drawn.
+    fn plain(&self) -> Option<Term> {
+        (!self.interactive && !self.silent).then(|| self.term.clone())
+    }
+}
+
+/// Whether `dotbak` can ask the user for things: stdout is a terminal, and `CI` isn't `true`.
+pub fn is_interactive() -> bool {
+    Term::stdout().is_term()
+        && !std::env::var(CI_VAR).is_ok_and(|ci| ci.eq_ignore_ascii_case("true"))
+}
+
+/// Reads a secret (like a passphrase) from the terminal, without echoing it. This is a free function, as secrets
+/// may be needed before there is an `Interface` to ask with.
+pub fn read_secret<S>(prompt: S) -> Result<String>
+where
+    S: ToString,
+{
+    let term = Term::stderr();
+
+    term.write_str(&format!("🔑 {} ", prompt.to_string()))
+        .and_then(|_| term.read_secure_line())
+        .map_err(|err| IoError::Prompt { source: err }.into())
 }
 
 /// A wrapper around a progress bar.
@@ -109,6 +416,10 @@ pub struct Spinner {
 
     /// Whether the spinner was created with a new depth.
     new_depth: bool,
+
+    /// The terminal to print the spinner to as a plain line once it's closed, if it isn't drawn (see
+    /// `Interface::set_non_interactive`).
+    plain: Option<Term>,
 }
 
 impl Spinner {
@@ -118,9 +429,15 @@ impl Spinner {
             num_dots,
             depth,
             new_depth,
+            plain: None,
         }
     }
 
+    /// Prints the spinner to `plain` as a plain line once it's closed, instead of drawing it.
+    pub fn with_plain(self, plain: Option<Term>) -> Spinner {
+        Spinner { plain, ..self }
+    }
+
     /// Starts the spinner. Note that the spinner does not appear until the first tick.
     pub fn start(&mut self) {
         self.spinner.enable_steady_tick(SPINNER_FRAME_DURATION);
@@ -128,6 +445,14 @@ impl Spinner {
 
     /// Closes the spinner.
     pub fn close(self) {
+        if let Some(term) = &self.plain {
+            let _ = term.write_line(&format!(
+                "{}{} ✓",
+                "   ".repeat(self.depth + 1),
+                self.spinner.message()
+            ));
+        }
+
         let raw_spinner = self.spinner;
 
         raw_spinner.set_style(
@@ -146,14 +471,36 @@ impl Spinner {
     }
 }
 
+/// A progress bar for an operation over many files/folders, see `Interface::spawn_progress`.
+#[derive(Clone, Debug)]
+pub struct FileProgress {
+    /// The progress bar, which is closed like a spinner.
+    spinner: Spinner,
+
+    /// The message of the progress bar.
+    message: String,
+}
+
+impl FileProgress {
+    /// Closes the progress bar, leaving only its message, like a closed spinner.
+    pub fn close(self) {
+        self.spinner.spinner.set_message(self.message);
+        self.spinner.close();
+    }
+}
+
+impl Progress for FileProgress {
+    fn start(&self, file: &Path) {
+        self.spinner.spinner.set_message(file.display().to_string());
+    }
+
+    fn finish(&self, _file: &Path) {
+        self.spinner.spinner.inc(1);
+    }
+}
+
 fn get_template(ending: &str, num_dots: usize, depth: usize, new_depth: bool) -> String {
-    let depth_string = if new_depth {
-        "   ".repeat(depth) + "╰─→ "
-    } else if depth > 0 {
-        "   ".repeat(depth) + "    "
-    } else {
-        "   ".into()
-    };
+    let depth_string = get_indent(depth, new_depth);
 
     let num_dots = if depth > 0 {
         num_dots.saturating_sub(3 * depth + 1)
@@ -167,3 +514,21 @@ fn get_template(ending: &str, num_dots: usize, depth: usize, new_depth: bool) ->
         dots = console::style("·".repeat(num_dots)).dim(),
     )
 }
+
+fn get_progress_template(depth: usize, new_depth: bool) -> String {
+    format!(
+        "{tabs}{{prefix}} {{bar:20.blue}} {{pos}}/{{len}} {rate} {{wide_msg:.dim}}",
+        tabs = console::style(get_indent(depth, new_depth)).dim(),
+        rate = console::style("({per_sec})").dim(),
+    )
+}
+
+fn get_indent(depth: usize, new_depth: bool) -> String {
+    if new_depth {
+        "   ".repeat(depth) + "╰─→ "
+    } else if depth > 0 {
+        "   ".repeat(depth) + "    "
+    } else {
+        "   ".into()
+    }
+}
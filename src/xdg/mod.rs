@@ -0,0 +1,84 @@
+mod tests;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable with the folder for the user's configuration files.
+pub const CONFIG_HOME_VAR: &str = "XDG_CONFIG_HOME";
+
+/// The environment variable with the folder for the user's data files.
+pub const DATA_HOME_VAR: &str = "XDG_DATA_HOME";
+
+/// The environment variable with the folder for the user's state files, which outlive restarts but aren't worth
+/// backing up.
+pub const STATE_HOME_VAR: &str = "XDG_STATE_HOME";
+
+/// The name of `dotbak`'s folder in each of the base directories.
+pub const APP_FOLDER_NAME: &str = "dotbak";
+
+/// The folder in the home directory that `dotbak` kept everything in before it followed the XDG base directories.
+/// See `BaseDirs::legacy`.
+pub const LEGACY_FOLDER_NAME: &str = ".dotbak";
+
+/// The folders `dotbak` keeps its files in for a home directory, following the XDG base directory specification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BaseDirs {
+    /// The folder with the configuration file and the encryption key: `$XDG_CONFIG_HOME/dotbak`, which is
+    /// `~/.config/dotbak` by default.
+    pub config: PathBuf,
+
+    /// The folder with the repository: `$XDG_DATA_HOME/dotbak`, which is `~/.local/share/dotbak` by default.
+    pub data: PathBuf,
+
+    /// The folder with the state: the lock, the quarantine, the pin, the queued push, the recorded metadata, the log
+    /// files, and the daemon's PID file and output. It's `$XDG_STATE_HOME/dotbak`, which is `~/.local/state/dotbak`
+    /// by default.
+    pub state: PathBuf,
+}
+
+impl BaseDirs {
+    /// Gets the base directories for the home directory `home`. The `XDG_*` environment variables are only used if
+    /// `home` is the user's home directory, as that's the one they're set for. Relative paths in them are ignored,
+    /// as the specification says.
+    pub fn new<P>(home: P) -> BaseDirs
+    where
+        P: AsRef<Path>,
+    {
+        let home = home.as_ref();
+        let own_home = dirs::home_dir().is_some_and(|user| user == home);
+        let dir = |var: &str, default: &str| {
+            std::env::var_os(var)
+                .map(PathBuf::from)
+                .filter(|path| own_home && path.is_absolute())
+                .unwrap_or_else(|| home.join(default))
+                .join(APP_FOLDER_NAME)
+        };
+
+        BaseDirs {
+            config: dir(CONFIG_HOME_VAR, ".config"),
+            data: dir(DATA_HOME_VAR, ".local/share"),
+            state: dir(STATE_HOME_VAR, ".local/state"),
+        }
+    }
+
+    /// Gets the base directories for the user's home directory, or `None` if it can't be found.
+    pub fn user() -> Option<BaseDirs> {
+        dirs::home_dir().map(BaseDirs::new)
+    }
+
+    /// Gets the legacy folder in the home directory `home` if `dotbak` was set up there before it followed the XDG
+    /// base directories, and its files haven't been moved yet. This is the case if the legacy folder has a
+    /// configuration file named `config_file_name`, and these base directories don't.
+    pub fn legacy<P>(&self, home: P, config_file_name: &str) -> Option<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let legacy = home.as_ref().join(LEGACY_FOLDER_NAME);
+        let exists = |path: PathBuf| fs::symlink_metadata(path).is_ok();
+
+        (exists(legacy.join(config_file_name)) && !exists(self.config.join(config_file_name)))
+            .then_some(legacy)
+    }
+}
@@ -0,0 +1,37 @@
+#![cfg(test)]
+
+use super::*;
+use assert_fs::TempDir;
+
+/// Tests that homes other than the user's get the default base directories in them.
+#[test]
+fn test_base_dirs() {
+    let home = PathBuf::from("/home/someone-else");
+
+    assert_eq!(
+        BaseDirs::new(&home),
+        BaseDirs {
+            config: home.join(".config/dotbak"),
+            data: home.join(".local/share/dotbak"),
+            state: home.join(".local/state/dotbak"),
+        }
+    );
+}
+
+/// Tests that the legacy folder is only found until it's been moved to the base directories.
+#[test]
+fn test_legacy() {
+    let dir = TempDir::new().unwrap();
+    let home = dir.path();
+    let base = BaseDirs::new(home);
+
+    assert_eq!(base.legacy(home, "config.toml"), None);
+
+    fs::create_dir_all(home.join(".dotbak")).unwrap();
+    fs::write(home.join(".dotbak/config.toml"), "").unwrap();
+    assert_eq!(base.legacy(home, "config.toml"), Some(home.join(".dotbak")));
+
+    fs::create_dir_all(&base.config).unwrap();
+    fs::write(base.config.join("config.toml"), "").unwrap();
+    assert_eq!(base.legacy(home, "config.toml"), None);
+}